@@ -0,0 +1,65 @@
+//! Authorization of realtime subscriptions and the events pushed to them
+//!
+//! Realtime clients only ever receive events from their own feed. A host application can narrow
+//! that further with a [`RealtimeAuthorizer`], which is asked once when a client subscribes and
+//! again for every event before it is pushed.
+
+use async_trait::async_trait;
+
+use crate::model::ActivityEvent;
+
+/// A realtime client and what it subscribed to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscriber {
+    /// Authenticated user the stream belongs to
+    pub user_id: i64,
+    /// Subject table the client subscribed to, e.g. `files`; `None` for every table
+    pub table: Option<String>,
+}
+
+impl Subscriber {
+    /// Whether an event belongs on this subscriber's stream
+    /// Events from other users' feeds, or about other tables, are never delivered
+    pub fn wants(&self, event: &ActivityEvent) -> bool {
+        event.user_id == self.user_id
+            && self.table.as_deref().is_none_or(|table| table == event.subject_table)
+    }
+}
+
+/// Host-provided checks on realtime subscriptions
+///
+/// Both checks run on top of [`Subscriber::wants`], so an authorizer can only withhold events,
+/// never expose another user's. Implementations should deny when they cannot decide.
+#[async_trait]
+pub trait RealtimeAuthorizer: Send + Sync {
+    /// Whether the subscriber may open its stream at all
+    async fn authorize_subscribe(&self, _subscriber: &Subscriber) -> bool {
+        true
+    }
+
+    /// Whether an event may be pushed to the subscriber
+    async fn authorize_event(&self, _subscriber: &Subscriber, _event: &ActivityEvent) -> bool {
+        true
+    }
+}
+
+/// Authorizer that adds no checks of its own
+pub struct AllowOwnEvents;
+
+impl RealtimeAuthorizer for AllowOwnEvents {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ActivityKind;
+
+    #[test]
+    fn test_subscriber_wants() {
+        let event = ActivityEvent::new(1, ActivityKind::FileUploaded, "files", "abc");
+
+        assert!(Subscriber { user_id: 1, table: None }.wants(&event));
+        assert!(Subscriber { user_id: 1, table: Some("files".to_string()) }.wants(&event));
+        assert!(!Subscriber { user_id: 1, table: Some("notes".to_string()) }.wants(&event));
+        assert!(!Subscriber { user_id: 2, table: None }.wants(&event));
+    }
+}
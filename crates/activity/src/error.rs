@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ActivityError {
+    #[error("Invalid cursor: {0}")]
+    InvalidCursor(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+
+    #[error("Fanout error: {0}")]
+    FanoutError(String),
+}
+
+pub type Result<T> = std::result::Result<T, ActivityError>;
@@ -0,0 +1,179 @@
+//! Delivery of recorded events to realtime subscribers
+//!
+//! Events are always persisted first; a fanout only pushes them to connected clients. With
+//! the default [`LocalFanout`] a client only hears about events recorded by the instance it
+//! is connected to, which is fine for a single instance. Behind a load balancer, use
+//! [`RedisFanout`] so every instance republishes every event to its own subscribers.
+
+use crate::{ActivityEvent, Result};
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+
+/// Events buffered for slow realtime subscribers before they start missing items
+pub const BROADCAST_CAPACITY: usize = 1024;
+
+/// Carries recorded events to realtime subscribers
+#[async_trait]
+pub trait EventFanout: Send + Sync {
+    /// Push an event to subscribers
+    async fn publish(&self, event: &ActivityEvent) -> Result<()>;
+
+    /// Receive every event published from now on
+    fn subscribe(&self) -> broadcast::Receiver<ActivityEvent>;
+
+    /// Short name for status reports, e.g. `in-memory`
+    fn name(&self) -> &'static str;
+
+    /// Whether subscribers on other instances receive the events published here
+    fn is_shared(&self) -> bool;
+}
+
+/// In-process fanout over a broadcast channel
+pub struct LocalFanout {
+    sender: broadcast::Sender<ActivityEvent>,
+}
+
+impl LocalFanout {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BROADCAST_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl Default for LocalFanout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl EventFanout for LocalFanout {
+    async fn publish(&self, event: &ActivityEvent) -> Result<()> {
+        // Sending only fails when nobody is subscribed
+        let _ = self.sender.send(event.clone());
+        Ok(())
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<ActivityEvent> {
+        self.sender.subscribe()
+    }
+
+    fn name(&self) -> &'static str {
+        "in-memory"
+    }
+
+    fn is_shared(&self) -> bool {
+        false
+    }
+}
+
+/// Fanout over Redis pub/sub, shared by every instance subscribed to the same channel
+///
+/// Published events go to Redis only; each instance, this one included, hands them to its
+/// subscribers as they come back on the channel, so no subscriber sees an event twice.
+#[cfg(feature = "redis")]
+pub struct RedisFanout {
+    connection: redis::aio::MultiplexedConnection,
+    channel: String,
+    sender: broadcast::Sender<ActivityEvent>,
+    listener: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "redis")]
+impl RedisFanout {
+    /// Connect to Redis and start relaying `channel` to local subscribers
+    /// The subscription is re-established if the connection drops
+    pub async fn connect(url: &str, channel: &str) -> Result<Self> {
+        let redis_error = |e: redis::RedisError| crate::ActivityError::FanoutError(e.to_string());
+
+        let client = redis::Client::open(url).map_err(redis_error)?;
+        let connection = client.get_multiplexed_async_connection().await.map_err(redis_error)?;
+        // Fail now rather than in the background when the channel can't be subscribed to
+        let mut pubsub = client.get_async_pubsub().await.map_err(redis_error)?;
+        pubsub.subscribe(channel).await.map_err(redis_error)?;
+
+        let (sender, _) = broadcast::channel(BROADCAST_CAPACITY);
+        let listener = tokio::spawn(relay(client, pubsub, channel.to_string(), sender.clone()));
+
+        Ok(Self {
+            connection,
+            channel: channel.to_string(),
+            sender,
+            listener,
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl Drop for RedisFanout {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+/// Forward events arriving on `channel` to local subscribers, resubscribing after disconnects
+#[cfg(feature = "redis")]
+async fn relay(
+    client: redis::Client,
+    mut pubsub: redis::aio::PubSub,
+    channel: String,
+    sender: broadcast::Sender<ActivityEvent>,
+) {
+    use futures_util::StreamExt;
+
+    loop {
+        {
+            let mut messages = pubsub.on_message();
+            while let Some(message) = messages.next().await {
+                let event = message.get_payload::<String>().ok()
+                    .and_then(|payload| serde_json::from_str::<ActivityEvent>(&payload).ok());
+                match event {
+                    Some(event) => {
+                        let _ = sender.send(event);
+                    }
+                    None => eprintln!("⚠️  Ignoring malformed activity event on {}", channel),
+                }
+            }
+        }
+
+        eprintln!("⚠️  Lost the Redis subscription to {}, reconnecting", channel);
+        pubsub = loop {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            let Ok(mut pubsub) = client.get_async_pubsub().await else {
+                continue;
+            };
+            if pubsub.subscribe(&channel).await.is_ok() {
+                break pubsub;
+            }
+        };
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl EventFanout for RedisFanout {
+    async fn publish(&self, event: &ActivityEvent) -> Result<()> {
+        let payload = serde_json::to_string(event)
+            .map_err(|e| crate::ActivityError::FanoutError(e.to_string()))?;
+        let mut connection = self.connection.clone();
+        redis::cmd("PUBLISH")
+            .arg(&self.channel)
+            .arg(payload)
+            .query_async::<i64>(&mut connection)
+            .await
+            .map_err(|e| crate::ActivityError::FanoutError(e.to_string()))?;
+        Ok(())
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<ActivityEvent> {
+        self.sender.subscribe()
+    }
+
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn is_shared(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,21 @@
+//! Per-user activity feed
+//!
+//! Events relevant to a user (their uploads, comments on their records, admin actions
+//! affecting them) are appended to an event log table and broadcast in-process, so
+//! clients can page through their history and receive new items as they happen.
+//! With the `redis` feature, new items reach clients connected to any instance.
+
+mod error;
+
+pub mod authorize;
+pub mod fanout;
+pub mod model;
+pub mod service;
+
+pub use authorize::{AllowOwnEvents, RealtimeAuthorizer, Subscriber};
+pub use error::{ActivityError, Result};
+#[cfg(feature = "redis")]
+pub use fanout::RedisFanout;
+pub use fanout::{EventFanout, LocalFanout, BROADCAST_CAPACITY};
+pub use model::{ActivityEvent, ActivityKind};
+pub use service::{ActivityPage, ActivityService, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
@@ -0,0 +1,203 @@
+use chrono::{DateTime, Utc};
+use orm::prelude::*;
+use orm::model::Row;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActivityKind {
+    /// The user uploaded a file
+    #[serde(rename = "file.uploaded")]
+    FileUploaded,
+    /// Someone commented on a record the user owns
+    #[serde(rename = "comment.created")]
+    CommentCreated,
+    /// An admin acted on something the user owns
+    #[serde(rename = "admin.action")]
+    AdminAction,
+    /// The user logged in; data carries where from
+    #[serde(rename = "auth.login")]
+    LoggedIn,
+}
+
+impl ActivityKind {
+    /// Convert kind to string for database storage
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityKind::FileUploaded => "file.uploaded",
+            ActivityKind::CommentCreated => "comment.created",
+            ActivityKind::AdminAction => "admin.action",
+            ActivityKind::LoggedIn => "auth.login",
+        }
+    }
+
+    /// Parse kind from database string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "file.uploaded" => Some(ActivityKind::FileUploaded),
+            "comment.created" => Some(ActivityKind::CommentCreated),
+            "admin.action" => Some(ActivityKind::AdminAction),
+            "auth.login" => Some(ActivityKind::LoggedIn),
+            _ => None,
+        }
+    }
+}
+
+/// One entry in a user's activity feed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityEvent {
+    /// Position in the event log; also the pagination cursor
+    pub id: Option<i64>,
+    /// User whose feed the event belongs to
+    pub user_id: i64,
+    /// User who caused the event, if it wasn't the feed owner
+    pub actor_id: Option<i64>,
+    pub kind: ActivityKind,
+    /// Table of the record the event is about, e.g. `files`
+    pub subject_table: String,
+    /// Primary key of the record the event is about
+    pub subject_id: String,
+    /// Event-specific details
+    pub data: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ActivityEvent {
+    /// Create an event for a user's feed
+    pub fn new(user_id: i64, kind: ActivityKind, subject_table: &str, subject_id: &str) -> Self {
+        Self {
+            id: None,
+            user_id,
+            actor_id: None,
+            kind,
+            subject_table: subject_table.to_string(),
+            subject_id: subject_id.to_string(),
+            data: serde_json::Value::Null,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Set the user who caused the event
+    pub fn with_actor(mut self, actor_id: i64) -> Self {
+        self.actor_id = Some(actor_id);
+        self
+    }
+
+    /// Attach event-specific details
+    pub fn with_data(mut self, data: serde_json::Value) -> Self {
+        self.data = data;
+        self
+    }
+}
+
+impl Model for ActivityEvent {
+    fn table_name() -> &'static str {
+        "activity_events"
+    }
+
+    fn primary_key() -> &'static str {
+        "id"
+    }
+
+    fn primary_key_value(&self) -> Option<Value> {
+        self.id.map(Value::I64)
+    }
+
+    fn to_values(&self) -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        if let Some(id) = self.id {
+            map.insert("id".to_string(), Value::I64(id));
+        }
+        map.insert("user_id".to_string(), Value::I64(self.user_id));
+        if let Some(actor_id) = self.actor_id {
+            map.insert("actor_id".to_string(), Value::I64(actor_id));
+        }
+        map.insert("kind".to_string(), Value::String(self.kind.as_str().to_string()));
+        map.insert("subject_table".to_string(), Value::String(self.subject_table.clone()));
+        map.insert("subject_id".to_string(), Value::String(self.subject_id.clone()));
+        map.insert("data".to_string(), Value::String(self.data.to_string()));
+        map.insert("created_at".to_string(), Value::String(self.created_at.to_rfc3339()));
+        map
+    }
+
+    fn columns() -> Vec<&'static str> {
+        vec!["user_id", "actor_id", "kind", "subject_table", "subject_id", "data", "created_at"]
+    }
+}
+
+impl FromRow for ActivityEvent {
+    fn from_row(row: &Row) -> Result<Self> {
+        let integer = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::I64(i) => Some(*i),
+                    Value::I32(i) => Some(*i as i64),
+                    _ => None,
+                })
+        };
+
+        let string = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::String(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .ok_or_else(|| Error::SerializationError(format!("Missing {}", column)))
+        };
+
+        let kind = ActivityKind::from_str(&string("kind")?)
+            .ok_or_else(|| Error::SerializationError("Invalid kind".to_string()))?;
+
+        let data = string("data")
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or(serde_json::Value::Null);
+
+        let created_at = row.get("created_at")
+            .and_then(|v| match v {
+                Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                _ => None,
+            })
+            .unwrap_or_else(Utc::now);
+
+        Ok(ActivityEvent {
+            id: integer("id"),
+            user_id: integer("user_id")
+                .ok_or_else(|| Error::SerializationError("Missing user_id".to_string()))?,
+            actor_id: integer("actor_id"),
+            kind,
+            subject_table: string("subject_table")?,
+            subject_id: string("subject_id")?,
+            data,
+            created_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_roundtrip() {
+        for kind in [ActivityKind::FileUploaded, ActivityKind::CommentCreated, ActivityKind::AdminAction, ActivityKind::LoggedIn] {
+            assert_eq!(ActivityKind::from_str(kind.as_str()), Some(kind));
+            assert_eq!(serde_json::to_value(kind).unwrap(), kind.as_str());
+        }
+        assert_eq!(ActivityKind::from_str("file.deleted"), None);
+    }
+
+    #[test]
+    fn test_event_values() {
+        let event = ActivityEvent::new(1, ActivityKind::CommentCreated, "posts", "7")
+            .with_actor(2)
+            .with_data(serde_json::json!({"comment_id": 12}));
+
+        let values = event.to_values();
+        assert!(!values.contains_key("id"));
+        assert!(matches!(values.get("kind"), Some(Value::String(kind)) if kind == "comment.created"));
+        assert!(matches!(values.get("actor_id"), Some(Value::I64(2))));
+        assert!(matches!(values.get("data"), Some(Value::String(data)) if data == r#"{"comment_id":12}"#));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    error::{ActivityError, Result},
+    fanout::{EventFanout, LocalFanout},
+    model::ActivityEvent,
+};
+use orm::prelude::*;
+use orm::query::QueryValue;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Page size when the client doesn't ask for one
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+
+/// Largest page a client may request
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// One page of a user's activity feed, newest first
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityPage {
+    pub items: Vec<ActivityEvent>,
+    /// Pass as `before` to fetch the next (older) page; `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Service recording activity events and serving per-user feeds
+pub struct ActivityService {
+    db: Database,
+    fanout: Arc<dyn EventFanout>,
+}
+
+impl ActivityService {
+    /// Create a new ActivityService
+    ///
+    /// # Arguments
+    /// * `db` - Database connection from ORM
+    pub fn new(db: Database) -> Self {
+        Self {
+            db,
+            fanout: Arc::new(LocalFanout::new()),
+        }
+    }
+
+    /// Push recorded events to realtime subscribers through `fanout` instead of in-process
+    pub fn with_fanout(mut self, fanout: Arc<dyn EventFanout>) -> Self {
+        self.fanout = fanout;
+        self
+    }
+
+    /// How recorded events reach realtime subscribers
+    pub fn fanout(&self) -> &dyn EventFanout {
+        self.fanout.as_ref()
+    }
+
+    /// Append an event to its user's feed and push it to realtime subscribers
+    pub async fn record(&self, mut event: ActivityEvent) -> Result<ActivityEvent> {
+        let backend = self.db.backend();
+        let mut query_builder = backend.query_builder();
+
+        let values = event.to_values();
+        let columns: Vec<&str> = values.keys().map(|s| s.as_str()).collect();
+        let query_values: Vec<_> = values.values().map(|v| v.to_query_value()).collect();
+
+        query_builder.insert_into(ActivityEvent::table_name(), &columns);
+        query_builder.values_params(&query_values);
+
+        // Use RETURNING clause for SQLite or LAST_INSERT_ID() for MySQL
+        if backend.supports_feature(orm::backend::BackendFeature::Returning) {
+            query_builder.returning(&["id"]);
+            let sql = query_builder.build()
+                .map_err(|e| ActivityError::DatabaseError(format!("Query build error: {}", e)))?;
+
+            let result = backend.fetch_one_params(&sql, query_builder.params()).await
+                .map_err(|e| ActivityError::DatabaseError(e.to_string()))?;
+            event.id = result.and_then(|json| json.get("id").and_then(|v| v.as_i64()));
+        } else {
+            let sql = query_builder.build()
+                .map_err(|e| ActivityError::DatabaseError(format!("Query build error: {}", e)))?;
+
+            backend.execute(&sql, query_builder.params()).await
+                .map_err(|e| ActivityError::DatabaseError(e.to_string()))?;
+
+            #[allow(deprecated)]
+            let result = backend.fetch_one("SELECT LAST_INSERT_ID() as id").await
+                .map_err(|e| ActivityError::DatabaseError(format!("Failed to get last insert ID: {}", e)))?;
+            event.id = result.and_then(|json| json.get("id").and_then(|v| v.as_i64()));
+        }
+
+        // The event is already in the log, where reconnecting clients catch up on it
+        if let Err(e) = self.fanout.publish(&event).await {
+            eprintln!("⚠️  Failed to publish activity event: {}", e);
+        }
+
+        Ok(event)
+    }
+
+    /// Record an event, logging instead of failing when the event log is unavailable
+    /// Used from request paths where the activity feed must never break the main operation
+    pub async fn record_best_effort(&self, event: ActivityEvent) {
+        if let Err(e) = self.record(event).await {
+            eprintln!("⚠️  Failed to record activity event: {}", e);
+        }
+    }
+
+    /// Fetch a page of a user's feed, newest first
+    ///
+    /// # Arguments
+    /// * `user_id` - Feed owner
+    /// * `before` - Cursor from a previous page's `next_cursor`; `None` for the newest items
+    /// * `limit` - Page size, clamped to `1..=MAX_PAGE_SIZE`
+    pub async fn feed(&self, user_id: i64, before: Option<&str>, limit: i64) -> Result<ActivityPage> {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let before = before.map(decode_cursor).transpose()?;
+
+        let backend = self.db.backend();
+        let mut params = vec![QueryValue::I64(user_id)];
+        let mut sql = format!("SELECT * FROM {} WHERE user_id = ?1", ActivityEvent::table_name());
+        if let Some(before) = before {
+            sql.push_str(" AND id < ?2");
+            params.push(QueryValue::I64(before));
+        }
+        // Fetch one extra row to learn whether another page follows
+        sql.push_str(&format!(" ORDER BY id DESC LIMIT {}", limit + 1));
+
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| ActivityError::DatabaseError(e.to_string()))?;
+
+        let mut items = events_from_rows(&json_rows)?;
+        let has_more = items.len() as i64 > limit;
+        items.truncate(limit as usize);
+
+        let next_cursor = if has_more {
+            items.last().and_then(|event| event.id).map(encode_cursor)
+        } else {
+            None
+        };
+
+        Ok(ActivityPage { items, next_cursor })
+    }
+
+    /// Fetch a user's events newer than `after_id`, oldest first
+    /// Lets realtime clients catch up on what they missed while disconnected
+    pub async fn events_after(&self, user_id: i64, after_id: i64) -> Result<Vec<ActivityEvent>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE user_id = ?1 AND id > ?2 ORDER BY id ASC LIMIT {}",
+            ActivityEvent::table_name(),
+            MAX_PAGE_SIZE
+        );
+        let params = [QueryValue::I64(user_id), QueryValue::I64(after_id)];
+
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| ActivityError::DatabaseError(e.to_string()))?;
+
+        events_from_rows(&json_rows)
+    }
+
+    /// Receive every event recorded from now on
+    /// Subscribers must filter by `user_id`; the channel carries all users' events
+    pub fn subscribe(&self) -> broadcast::Receiver<ActivityEvent> {
+        self.fanout.subscribe()
+    }
+}
+
+/// Opaque cursor for an event id
+fn encode_cursor(id: i64) -> String {
+    id.to_string()
+}
+
+fn decode_cursor(cursor: &str) -> Result<i64> {
+    cursor.parse::<i64>()
+        .ok()
+        .filter(|id| *id > 0)
+        .ok_or_else(|| ActivityError::InvalidCursor(cursor.to_string()))
+}
+
+/// Deserialize a set of JSON rows into `ActivityEvent` records
+fn events_from_rows(json_rows: &[serde_json::Value]) -> Result<Vec<ActivityEvent>> {
+    json_rows.iter()
+        .map(|json| ActivityEvent::from_json(json)
+            .map_err(|e| ActivityError::DatabaseError(format!("Deserialization error: {}", e))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        assert_eq!(decode_cursor(&encode_cursor(42)).unwrap(), 42);
+        assert!(matches!(decode_cursor("abc"), Err(ActivityError::InvalidCursor(_))));
+        assert!(matches!(decode_cursor("0"), Err(ActivityError::InvalidCursor(_))));
+    }
+}
@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AnnouncementError {
+    #[error("Announcement not found: {0}")]
+    NotFound(i64),
+
+    #[error("Invalid announcement: {0}")]
+    Invalid(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+}
+
+pub type Result<T> = std::result::Result<T, AnnouncementError>;
@@ -0,0 +1,13 @@
+//! In-app announcements
+//!
+//! Admins publish system messages (maintenance notices, new features) that
+//! authenticated clients fetch and dismiss per user.
+
+mod error;
+
+pub mod model;
+pub mod service;
+
+pub use error::{AnnouncementError, Result};
+pub use model::{Announcement, AnnouncementLevel, Dismissal, NewAnnouncement};
+pub use service::{AnnouncementService, UserAnnouncement};
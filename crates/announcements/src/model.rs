@@ -0,0 +1,282 @@
+use chrono::{DateTime, Utc};
+use orm::prelude::*;
+use orm::model::Row;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How prominently clients should display an announcement
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncementLevel {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+impl AnnouncementLevel {
+    /// Convert level to string for database storage
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnnouncementLevel::Info => "info",
+            AnnouncementLevel::Warning => "warning",
+            AnnouncementLevel::Critical => "critical",
+        }
+    }
+
+    /// Parse level from database string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "info" => Some(AnnouncementLevel::Info),
+            "warning" => Some(AnnouncementLevel::Warning),
+            "critical" => Some(AnnouncementLevel::Critical),
+            _ => None,
+        }
+    }
+}
+
+/// Fields an admin provides when publishing an announcement
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewAnnouncement {
+    pub title: String,
+    pub body: String,
+    #[serde(default)]
+    pub level: AnnouncementLevel,
+    /// Hidden from clients before this time; defaults to immediately
+    pub starts_at: Option<DateTime<Utc>>,
+    /// Hidden from clients from this time on; defaults to never
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+/// A published system message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Announcement {
+    pub id: Option<i64>,
+    pub title: String,
+    pub body: String,
+    pub level: AnnouncementLevel,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: Option<DateTime<Utc>>,
+    /// Admin who published it
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Announcement {
+    /// Create an announcement from an admin's request
+    pub fn new(new: NewAnnouncement, created_by: Option<i64>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: None,
+            title: new.title,
+            body: new.body,
+            level: new.level,
+            starts_at: new.starts_at.unwrap_or(now),
+            ends_at: new.ends_at,
+            created_by,
+            created_at: now,
+        }
+    }
+
+    /// Whether clients should see the announcement at `at`
+    pub fn is_active(&self, at: DateTime<Utc>) -> bool {
+        self.starts_at <= at && self.ends_at.is_none_or(|ends_at| at < ends_at)
+    }
+}
+
+impl Model for Announcement {
+    fn table_name() -> &'static str {
+        "announcements"
+    }
+
+    fn primary_key() -> &'static str {
+        "id"
+    }
+
+    fn primary_key_value(&self) -> Option<Value> {
+        self.id.map(Value::I64)
+    }
+
+    fn to_values(&self) -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        if let Some(id) = self.id {
+            map.insert("id".to_string(), Value::I64(id));
+        }
+        map.insert("title".to_string(), Value::String(self.title.clone()));
+        map.insert("body".to_string(), Value::String(self.body.clone()));
+        map.insert("level".to_string(), Value::String(self.level.as_str().to_string()));
+        map.insert("starts_at".to_string(), Value::String(self.starts_at.to_rfc3339()));
+        if let Some(ends_at) = &self.ends_at {
+            map.insert("ends_at".to_string(), Value::String(ends_at.to_rfc3339()));
+        }
+        if let Some(created_by) = self.created_by {
+            map.insert("created_by".to_string(), Value::I64(created_by));
+        }
+        map.insert("created_at".to_string(), Value::String(self.created_at.to_rfc3339()));
+        map
+    }
+
+    fn columns() -> Vec<&'static str> {
+        vec!["title", "body", "level", "starts_at", "ends_at", "created_by", "created_at"]
+    }
+}
+
+impl FromRow for Announcement {
+    fn from_row(row: &Row) -> Result<Self> {
+        let integer = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::I64(i) => Some(*i),
+                    Value::I32(i) => Some(*i as i64),
+                    _ => None,
+                })
+        };
+
+        let string = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::String(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .ok_or_else(|| Error::SerializationError(format!("Missing {}", column)))
+        };
+
+        let timestamp = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                    _ => None,
+                })
+        };
+
+        let level = AnnouncementLevel::from_str(&string("level")?)
+            .ok_or_else(|| Error::SerializationError("Invalid level".to_string()))?;
+
+        let created_at = timestamp("created_at").unwrap_or_else(Utc::now);
+
+        Ok(Announcement {
+            id: integer("id"),
+            title: string("title")?,
+            body: string("body")?,
+            level,
+            starts_at: timestamp("starts_at").unwrap_or(created_at),
+            ends_at: timestamp("ends_at"),
+            created_by: integer("created_by"),
+            created_at,
+        })
+    }
+}
+
+/// Record that a user dismissed an announcement
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dismissal {
+    pub id: Option<i64>,
+    pub announcement_id: i64,
+    pub user_id: i64,
+    pub dismissed_at: DateTime<Utc>,
+}
+
+impl Dismissal {
+    pub fn new(announcement_id: i64, user_id: i64) -> Self {
+        Self {
+            id: None,
+            announcement_id,
+            user_id,
+            dismissed_at: Utc::now(),
+        }
+    }
+}
+
+impl Model for Dismissal {
+    fn table_name() -> &'static str {
+        "announcement_dismissals"
+    }
+
+    fn primary_key() -> &'static str {
+        "id"
+    }
+
+    fn primary_key_value(&self) -> Option<Value> {
+        self.id.map(Value::I64)
+    }
+
+    fn to_values(&self) -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        if let Some(id) = self.id {
+            map.insert("id".to_string(), Value::I64(id));
+        }
+        map.insert("announcement_id".to_string(), Value::I64(self.announcement_id));
+        map.insert("user_id".to_string(), Value::I64(self.user_id));
+        map.insert("dismissed_at".to_string(), Value::String(self.dismissed_at.to_rfc3339()));
+        map
+    }
+
+    fn columns() -> Vec<&'static str> {
+        vec!["announcement_id", "user_id", "dismissed_at"]
+    }
+}
+
+impl FromRow for Dismissal {
+    fn from_row(row: &Row) -> Result<Self> {
+        let integer = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::I64(i) => Some(*i),
+                    Value::I32(i) => Some(*i as i64),
+                    _ => None,
+                })
+        };
+
+        let dismissed_at = row.get("dismissed_at")
+            .and_then(|v| match v {
+                Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                _ => None,
+            })
+            .unwrap_or_else(Utc::now);
+
+        Ok(Dismissal {
+            id: integer("id"),
+            announcement_id: integer("announcement_id")
+                .ok_or_else(|| Error::SerializationError("Missing announcement_id".to_string()))?,
+            user_id: integer("user_id")
+                .ok_or_else(|| Error::SerializationError("Missing user_id".to_string()))?,
+            dismissed_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_is_active() {
+        let now = Utc::now();
+        let mut announcement = Announcement::new(
+            NewAnnouncement {
+                title: "Maintenance".to_string(),
+                body: "Down for an hour tonight".to_string(),
+                level: AnnouncementLevel::Warning,
+                starts_at: Some(now + Duration::hours(1)),
+                ends_at: Some(now + Duration::hours(2)),
+            },
+            Some(1),
+        );
+
+        assert!(!announcement.is_active(now));
+        assert!(announcement.is_active(now + Duration::minutes(90)));
+        assert!(!announcement.is_active(now + Duration::hours(2)));
+
+        announcement.ends_at = None;
+        assert!(announcement.is_active(now + Duration::days(365)));
+    }
+
+    #[test]
+    fn test_level_roundtrip() {
+        for level in [AnnouncementLevel::Info, AnnouncementLevel::Warning, AnnouncementLevel::Critical] {
+            assert_eq!(AnnouncementLevel::from_str(level.as_str()), Some(level));
+        }
+        assert_eq!(AnnouncementLevel::from_str("urgent"), None);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{
+    error::{AnnouncementError, Result},
+    model::{Announcement, Dismissal, NewAnnouncement},
+};
+use chrono::Utc;
+use orm::prelude::*;
+use orm::query::QueryValue;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// An announcement as seen by one user
+#[derive(Debug, Clone, Serialize)]
+pub struct UserAnnouncement {
+    #[serde(flatten)]
+    pub announcement: Announcement,
+    pub dismissed: bool,
+}
+
+/// Service for publishing announcements and tracking who dismissed them
+pub struct AnnouncementService {
+    db: Database,
+}
+
+impl AnnouncementService {
+    /// Create a new AnnouncementService
+    ///
+    /// # Arguments
+    /// * `db` - Database connection from ORM
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Publish an announcement
+    ///
+    /// # Arguments
+    /// * `new` - Title, body, level and display window
+    /// * `created_by` - Admin publishing it
+    pub async fn publish(&self, new: NewAnnouncement, created_by: Option<i64>) -> Result<Announcement> {
+        if new.title.trim().is_empty() {
+            return Err(AnnouncementError::Invalid("title cannot be empty".to_string()));
+        }
+        let mut announcement = Announcement::new(new, created_by);
+        if announcement.ends_at.is_some_and(|ends_at| ends_at <= announcement.starts_at) {
+            return Err(AnnouncementError::Invalid("ends_at must be after starts_at".to_string()));
+        }
+
+        let backend = self.db.backend();
+        let mut query_builder = backend.query_builder();
+
+        let values = announcement.to_values();
+        let columns: Vec<&str> = values.keys().map(|s| s.as_str()).collect();
+        let query_values: Vec<_> = values.values().map(|v| v.to_query_value()).collect();
+
+        query_builder.insert_into(Announcement::table_name(), &columns);
+        query_builder.values_params(&query_values);
+
+        // Use RETURNING clause for SQLite or LAST_INSERT_ID() for MySQL
+        if backend.supports_feature(orm::backend::BackendFeature::Returning) {
+            query_builder.returning(&["id"]);
+            let sql = query_builder.build()
+                .map_err(|e| AnnouncementError::DatabaseError(format!("Query build error: {}", e)))?;
+
+            let result = backend.fetch_one_params(&sql, query_builder.params()).await
+                .map_err(|e| AnnouncementError::DatabaseError(e.to_string()))?;
+            announcement.id = result.and_then(|json| json.get("id").and_then(|v| v.as_i64()));
+        } else {
+            let sql = query_builder.build()
+                .map_err(|e| AnnouncementError::DatabaseError(format!("Query build error: {}", e)))?;
+
+            backend.execute(&sql, query_builder.params()).await
+                .map_err(|e| AnnouncementError::DatabaseError(e.to_string()))?;
+
+            #[allow(deprecated)]
+            let result = backend.fetch_one("SELECT LAST_INSERT_ID() as id").await
+                .map_err(|e| AnnouncementError::DatabaseError(format!("Failed to get last insert ID: {}", e)))?;
+            announcement.id = result.and_then(|json| json.get("id").and_then(|v| v.as_i64()));
+        }
+
+        if announcement.id.is_none() {
+            return Err(AnnouncementError::DatabaseError("Failed to get announcement id".to_string()));
+        }
+
+        Ok(announcement)
+    }
+
+    /// Get every announcement, including scheduled and expired ones, newest first
+    pub async fn list_all(&self) -> Result<Vec<Announcement>> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT * FROM {} ORDER BY starts_at DESC", Announcement::table_name());
+
+        let json_rows = backend.fetch_all_params(&sql, &[]).await
+            .map_err(|e| AnnouncementError::DatabaseError(e.to_string()))?;
+
+        json_rows.iter()
+            .map(|json| Announcement::from_json(json)
+                .map_err(|e| AnnouncementError::DatabaseError(format!("Deserialization error: {}", e))))
+            .collect()
+    }
+
+    /// Get a page of every announcement, newest first, along with how many there are
+    pub async fn list_all_page(&self, limit: i64, offset: i64) -> Result<(Vec<Announcement>, i64)> {
+        self.fetch_page("", Vec::new(), limit, offset).await
+    }
+
+    /// Get an announcement by id
+    pub async fn find(&self, id: i64) -> Result<Announcement> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT * FROM {} WHERE id = ?1", Announcement::table_name());
+
+        let json = backend.fetch_one_params(&sql, &[QueryValue::I64(id)]).await
+            .map_err(|e| AnnouncementError::DatabaseError(e.to_string()))?
+            .ok_or(AnnouncementError::NotFound(id))?;
+
+        Announcement::from_json(&json)
+            .map_err(|e| AnnouncementError::DatabaseError(format!("Deserialization error: {}", e)))
+    }
+
+    /// Delete an announcement along with its dismissals
+    pub async fn delete(&self, id: i64) -> Result<()> {
+        let backend = self.db.backend();
+
+        let sql = format!("DELETE FROM {} WHERE announcement_id = ?1", Dismissal::table_name());
+        backend.execute(&sql, &[QueryValue::I64(id)]).await
+            .map_err(|e| AnnouncementError::DatabaseError(e.to_string()))?;
+
+        let sql = format!("DELETE FROM {} WHERE id = ?1", Announcement::table_name());
+        let rows_affected = backend.execute(&sql, &[QueryValue::I64(id)]).await
+            .map_err(|e| AnnouncementError::DatabaseError(e.to_string()))?;
+
+        if rows_affected == 0 {
+            return Err(AnnouncementError::NotFound(id));
+        }
+        Ok(())
+    }
+
+    /// Get the announcements currently shown to clients, with the user's dismissed state
+    ///
+    /// # Arguments
+    /// * `user_id` - User fetching announcements
+    /// * `include_dismissed` - Whether to keep announcements the user already dismissed
+    pub async fn list_for_user(&self, user_id: i64, include_dismissed: bool) -> Result<Vec<UserAnnouncement>> {
+        let now = Utc::now();
+        let dismissed = self.dismissed_ids(user_id).await?;
+
+        let announcements = self.list_all().await?
+            .into_iter()
+            .filter(|announcement| announcement.is_active(now))
+            .map(|announcement| {
+                let dismissed = announcement.id.is_some_and(|id| dismissed.contains(&id));
+                UserAnnouncement { announcement, dismissed }
+            })
+            .filter(|entry| include_dismissed || !entry.dismissed)
+            .collect();
+
+        Ok(announcements)
+    }
+
+    /// Get a page of the announcements currently shown to clients, with the user's dismissed
+    /// state, along with how many there are
+    pub async fn list_for_user_page(
+        &self,
+        user_id: i64,
+        include_dismissed: bool,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<UserAnnouncement>, i64)> {
+        let dismissed = self.dismissed_ids(user_id).await?;
+
+        // Timestamps are stored as RFC 3339 in UTC, so they compare as text
+        let mut where_clause = " WHERE starts_at <= ?1 AND (ends_at IS NULL OR ends_at > ?1)".to_string();
+        let mut params = vec![QueryValue::String(Utc::now().to_rfc3339())];
+        if !include_dismissed {
+            where_clause.push_str(&format!(
+                " AND id NOT IN (SELECT announcement_id FROM {} WHERE user_id = ?2)",
+                Dismissal::table_name()
+            ));
+            params.push(QueryValue::I64(user_id));
+        }
+
+        let (announcements, total) = self.fetch_page(&where_clause, params, limit, offset).await?;
+        let announcements = announcements.into_iter()
+            .map(|announcement| {
+                let dismissed = announcement.id.is_some_and(|id| dismissed.contains(&id));
+                UserAnnouncement { announcement, dismissed }
+            })
+            .collect();
+        Ok((announcements, total))
+    }
+
+    /// Mark an announcement as dismissed for a user
+    /// Dismissing it again is a no-op
+    pub async fn dismiss(&self, id: i64, user_id: i64) -> Result<()> {
+        self.find(id).await?;
+        if self.dismissed_ids(user_id).await?.contains(&id) {
+            return Ok(());
+        }
+
+        let dismissal = Dismissal::new(id, user_id);
+        let backend = self.db.backend();
+        let mut query_builder = backend.query_builder();
+
+        let values = dismissal.to_values();
+        let columns: Vec<&str> = values.keys().map(|s| s.as_str()).collect();
+        let query_values: Vec<_> = values.values().map(|v| v.to_query_value()).collect();
+
+        query_builder.insert_into(Dismissal::table_name(), &columns);
+        query_builder.values_params(&query_values);
+
+        let sql = query_builder.build()
+            .map_err(|e| AnnouncementError::DatabaseError(format!("Query build error: {}", e)))?;
+        backend.execute(&sql, query_builder.params()).await
+            .map_err(|e| AnnouncementError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Count the announcements `where_clause` matches, and fetch `limit` of them after skipping
+    /// `offset`, newest first
+    async fn fetch_page(&self, where_clause: &str, mut params: Vec<QueryValue>, limit: i64, offset: i64) -> Result<(Vec<Announcement>, i64)> {
+        let backend = self.db.backend();
+
+        let count_sql = format!("SELECT COUNT(*) as total FROM {}{}", Announcement::table_name(), where_clause);
+        let total = backend.fetch_one_params(&count_sql, &params).await
+            .map_err(|e| AnnouncementError::DatabaseError(e.to_string()))?
+            .and_then(|json| json.get("total").and_then(|v| v.as_i64()))
+            .unwrap_or(0);
+
+        let sql = format!(
+            "SELECT * FROM {}{} ORDER BY starts_at DESC, id DESC LIMIT ?{} OFFSET ?{}",
+            Announcement::table_name(),
+            where_clause,
+            params.len() + 1,
+            params.len() + 2
+        );
+        params.push(QueryValue::I64(limit));
+        params.push(QueryValue::I64(offset));
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| AnnouncementError::DatabaseError(e.to_string()))?;
+
+        let announcements = json_rows.iter()
+            .map(|json| Announcement::from_json(json)
+                .map_err(|e| AnnouncementError::DatabaseError(format!("Deserialization error: {}", e))))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((announcements, total))
+    }
+
+    async fn dismissed_ids(&self, user_id: i64) -> Result<HashSet<i64>> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT announcement_id FROM {} WHERE user_id = ?1", Dismissal::table_name());
+
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(user_id)]).await
+            .map_err(|e| AnnouncementError::DatabaseError(e.to_string()))?;
+
+        Ok(json_rows.iter()
+            .filter_map(|json| json.get("announcement_id").and_then(|v| v.as_i64()))
+            .collect())
+    }
+}
@@ -0,0 +1,37 @@
+//! `/db` INSERT building for payloads of increasing width
+//!
+//! Run with `cargo bench -p api`.
+
+use api::db_handlers::build_insert;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use serde_json::{json, Map, Value};
+use std::hint::black_box;
+
+fn payload(columns: usize) -> Map<String, Value> {
+    (0..columns)
+        .map(|i| {
+            let value = match i % 3 {
+                0 => json!(i),
+                1 => json!(format!("value {}", i)),
+                _ => json!(i % 2 == 0),
+            };
+            (format!("column_{}", i), value)
+        })
+        .collect()
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("db/build_insert");
+
+    for columns in [1, 8, 64] {
+        let obj = payload(columns);
+        group.bench_with_input(BenchmarkId::from_parameter(columns), &obj, |b, obj| {
+            b.iter(|| build_insert(black_box("notes"), obj).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, insert);
+criterion_main!(benches);
@@ -0,0 +1,144 @@
+use activity::{ActivityError, ActivityEvent, Subscriber, DEFAULT_PAGE_SIZE};
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Json,
+};
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::db_handlers::is_valid_table_name;
+use crate::file_handlers::ErrorResponse;
+use crate::middleware::AuthUser;
+use crate::pagination::{Page, PageParams};
+use crate::AppState;
+
+/// GET /activity - The authenticated user's activity feed, newest first
+pub async fn list_activity(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state
+        .activity_service
+        .feed(user_id, page.cursor.as_deref(), page.limit_or(DEFAULT_PAGE_SIZE))
+        .await
+    {
+        Ok(feed) => Page::from_keyset(feed.items, feed.next_cursor).into_response(),
+        Err(e) => {
+            let status = match e {
+                ActivityError::InvalidCursor(_) => StatusCode::BAD_REQUEST,
+                ActivityError::DatabaseError(_) | ActivityError::FanoutError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let error = ErrorResponse {
+                error: format!("Failed to load activity: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// Query parameters of `GET /activity/stream`
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// Only stream events about records in this table
+    pub table: Option<String>,
+}
+
+/// GET /activity/stream - Server-sent events for new items in the user's feed
+/// Reconnecting clients send `Last-Event-ID` and first receive the items they missed.
+/// The realtime authorizer is consulted on subscribe and again for every event.
+pub async fn stream_activity(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Some(table) = &query.table {
+        if !is_valid_table_name(table) {
+            let error = ErrorResponse {
+                error: format!("Invalid table name: '{}'", table),
+            };
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+    }
+
+    let subscriber = Subscriber {
+        user_id: user.id.unwrap(),
+        table: query.table,
+    };
+    let authorizer = state.realtime_authorizer.clone();
+    if !authorizer.authorize_subscribe(&subscriber).await {
+        let error = ErrorResponse {
+            error: "Not allowed to subscribe to this stream".to_string(),
+        };
+        return (StatusCode::FORBIDDEN, Json(error)).into_response();
+    }
+
+    // Subscribe before catching up so nothing recorded in between is lost
+    let receiver = state.activity_service.subscribe();
+
+    let last_event_id = headers
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<i64>().ok());
+    let missed = match last_event_id {
+        Some(after_id) => state
+            .activity_service
+            .events_after(subscriber.user_id, after_id)
+            .await
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let last_sent = missed.last().and_then(|event| event.id).or(last_event_id).unwrap_or(0);
+
+    // Missed events go through the same checks as live ones
+    let mut allowed = Vec::with_capacity(missed.len());
+    for event in missed {
+        if subscriber.wants(&event) && authorizer.authorize_event(&subscriber, &event).await {
+            allowed.push(event);
+        }
+    }
+
+    // Counted as open until the client disconnects and the stream is dropped
+    let connection = state.metrics.realtime_connection();
+
+    let live = stream::unfold((receiver, connection), move |(mut receiver, connection)| {
+        let subscriber = subscriber.clone();
+        let authorizer = authorizer.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if subscriber.wants(&event) && event.id.unwrap_or(0) > last_sent => {
+                        if authorizer.authorize_event(&subscriber, &event).await {
+                            return Some((event, (receiver, connection)));
+                        }
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+
+    let events = stream::iter(allowed).chain(live).map(|event| sse_event(&event));
+
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+fn sse_event(event: &ActivityEvent) -> Result<Event, axum::Error> {
+    let sse = Event::default().event(event.kind.as_str());
+    let sse = match event.id {
+        Some(id) => sse.id(id.to_string()),
+        None => sse,
+    };
+    sse.json_data(event)
+}
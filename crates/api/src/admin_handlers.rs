@@ -0,0 +1,773 @@
+use activity::{ActivityEvent, ActivityKind};
+use auth::AuthError;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::cluster;
+use crate::db_handlers::is_valid_table_name;
+use crate::file_handlers::{storage_error_status, ErrorResponse};
+use crate::jobs::{Job, JobError, JobState, JobTypeStatus};
+use crate::metrics::OPENMETRICS_CONTENT_TYPE;
+use crate::middleware::{AuthUser, ClientIp};
+use crate::AppState;
+use storage::{DeliveryStatus, LegalHold, ReportPeriod, UsageReport, DEFAULT_GC_GRACE_SECONDS};
+
+#[derive(Debug, Deserialize)]
+pub struct StorageReportQuery {
+    /// First day included, as `YYYY-MM-DD`
+    pub from: NaiveDate,
+    /// Day after the last day included, as `YYYY-MM-DD`
+    pub to: NaiveDate,
+    #[serde(default = "default_report_period")]
+    pub period: ReportPeriod,
+    pub user_id: Option<i64>,
+}
+
+fn default_report_period() -> ReportPeriod {
+    ReportPeriod::Daily
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StorageGcQuery {
+    /// Only report what would be removed
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Minimum age of an unreferenced blob before it is removed
+    #[serde(default = "default_gc_grace_seconds")]
+    pub grace_seconds: u64,
+}
+
+fn default_gc_grace_seconds() -> u64 {
+    DEFAULT_GC_GRACE_SECONDS
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StorageFsckQuery {
+    /// Fix the problems found instead of only reporting them
+    #[serde(default)]
+    pub repair: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IntegrityAuditQuery {
+    /// Files to check, least recently checked first; every file when unset
+    pub sample: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LegalHoldQuery {
+    /// Only holds on this table
+    pub table: Option<String>,
+    /// Include released holds
+    #[serde(default)]
+    pub include_released: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginHistoryQuery {
+    /// Most recent logins returned
+    #[serde(default = "default_login_history_limit")]
+    pub limit: u64,
+}
+
+fn default_login_history_limit() -> u64 {
+    50
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookDeliveryQuery {
+    /// Only deliveries in this state: `pending`, `delivered`, or `failed`
+    pub status: Option<DeliveryStatus>,
+    #[serde(default = "default_webhook_delivery_limit")]
+    pub limit: u64,
+}
+
+fn default_webhook_delivery_limit() -> u64 {
+    100
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobQuery {
+    /// Only jobs in this state: `queued`, `running`, or `dead_lettered`
+    pub state: Option<JobState>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaceLegalHoldRequest {
+    /// Table of the held row; `files` for files
+    pub table: String,
+    pub record_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminActionResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobsResponse {
+    pub types: Vec<JobTypeStatus>,
+    pub jobs: Vec<Job>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageReportResponse {
+    pub period: ReportPeriod,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub series: Vec<UsageReport>,
+}
+
+/// Compare secrets in time that depends only on their length, so timing can't reveal how
+/// much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// GET /metrics - Job, webhook, and realtime metrics in the OpenMetrics text format
+/// Requires `server.metrics_token` as a bearer token when one is configured
+pub async fn metrics(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Some(expected) = &state.metrics_token {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if !token.is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes())) {
+            let error = ErrorResponse {
+                error: "Missing or invalid metrics token".to_string(),
+            };
+            return (StatusCode::UNAUTHORIZED, Json(error)).into_response();
+        }
+    }
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)],
+        state.metrics.render(),
+    )
+        .into_response()
+}
+
+/// GET /admin/cluster - Whether this instance can run alongside others, component by component
+pub async fn cluster_report(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (StatusCode::OK, Json(cluster::report(&state))).into_response()
+}
+
+/// GET /admin/reports/storage?from=&to= - Per-user bandwidth and storage usage over time
+pub async fn storage_reports(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<StorageReportQuery>,
+) -> impl IntoResponse {
+    if params.from >= params.to {
+        let error = ErrorResponse {
+            error: "`from` must be before `to`".to_string(),
+        };
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    }
+
+    let from = Utc.from_utc_datetime(&params.from.and_hms_opt(0, 0, 0).unwrap_or_default());
+    let to = Utc.from_utc_datetime(&params.to.and_hms_opt(0, 0, 0).unwrap_or_default());
+
+    match state
+        .storage_service
+        .list_usage_reports(params.period, from, to, params.user_id)
+        .await
+    {
+        Ok(series) => {
+            let response = StorageReportResponse {
+                period: params.period,
+                from: params.from,
+                to: params.to,
+                series,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to load storage reports: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /admin/storage/gc - Remove blobs no file refers to and report files whose blob is missing
+pub async fn storage_gc(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<StorageGcQuery>,
+) -> impl IntoResponse {
+    let grace = std::time::Duration::from_secs(params.grace_seconds);
+
+    match state.storage_service.gc(grace, params.dry_run).await {
+        Ok(report) => {
+            if report.removed_blobs > 0 || !report.missing_blobs.is_empty() {
+                println!(
+                    "🧹 Storage GC: removed {} orphaned blob(s), {} file(s) missing their blob",
+                    report.removed_blobs,
+                    report.missing_blobs.len()
+                );
+            }
+            (StatusCode::OK, Json(report)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Storage GC failed: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /admin/storage/fsck - Check the files table against storage, optionally repairing it
+pub async fn storage_fsck(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<StorageFsckQuery>,
+) -> impl IntoResponse {
+    match state.storage_service.verify_consistency(params.repair).await {
+        Ok(report) => {
+            println!(
+                "🩺 Storage fsck: {} missing blob(s), {} size mismatch(es), {} unknown blob(s), {} repaired",
+                report.missing_blobs.len(),
+                report.size_mismatches.len(),
+                report.unknown_blobs.len(),
+                report.repaired
+            );
+            (StatusCode::OK, Json(report)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Storage consistency check failed: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /admin/storage/integrity - Integrity of stored files as found by past audits
+pub async fn storage_integrity(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    match state.storage_service.integrity_report().await {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to read integrity report: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /admin/storage/integrity - Audit stored files against their checksums now
+pub async fn audit_storage_integrity(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<IntegrityAuditQuery>,
+) -> impl IntoResponse {
+    match state.storage_service.audit_integrity(params.sample).await {
+        Ok(audit) => {
+            println!(
+                "🔍 Integrity audit: {} file(s) checked, {} corrupted, {} missing",
+                audit.checked,
+                audit.corrupted.len(),
+                audit.missing.len()
+            );
+            (StatusCode::OK, Json(audit)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Integrity audit failed: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// Response to deleting an account that can still be restored
+#[derive(Debug, Serialize)]
+pub struct UserDeletedResponse {
+    pub success: bool,
+    pub message: String,
+    /// When the account and its files will be purged
+    pub purge_at: String,
+    /// Token the user can send to `POST /auth/recover` to restore their account until then
+    pub recovery_token: String,
+}
+
+/// DELETE /admin/users/:id - Delete a user account (requires sudo)
+/// The account and its files are kept for the configured grace period and can be restored until
+/// then; with no grace period they are erased straight away.
+pub async fn delete_user(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    if user.id == Some(id) {
+        let error = ErrorResponse {
+            error: "Service accounts cannot delete themselves".to_string(),
+        };
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    }
+
+    let grace_seconds = state.auth_service.deletion_grace_seconds();
+    if grace_seconds == 0 {
+        return purge_user(&state, id).await;
+    }
+
+    match state.auth_service.soft_delete_user(id).await {
+        Ok(recovery_token) => {
+            let purge_at = Utc::now() + chrono::Duration::seconds(grace_seconds);
+            let response = UserDeletedResponse {
+                success: true,
+                message: format!("User {} deleted; the account can be restored until it is purged", id),
+                purge_at: purge_at.to_rfc3339(),
+                recovery_token,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let status = match e {
+                AuthError::UserNotFound(_) => StatusCode::NOT_FOUND,
+                AuthError::AccountDeleted => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let error = ErrorResponse {
+                error: format!("Failed to delete user: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// Erase an account and all of its files immediately
+async fn purge_user(state: &AppState, id: i64) -> axum::response::Response {
+    match state.storage_service.user_has_legal_holds(id).await {
+        Ok(false) => {}
+        Ok(true) => {
+            let error = ErrorResponse {
+                error: format!("User {} owns files or records under a legal hold", id),
+            };
+            return (StatusCode::LOCKED, Json(error)).into_response();
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to check legal holds: {}", e),
+            };
+            return (status, Json(error)).into_response();
+        }
+    }
+
+    // Blobs aren't removed by the cascade, so purge them while the metadata still exists
+    let purged = match state.storage_service.purge_user_files(id).await {
+        Ok(purged) => purged,
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to delete user's files: {}", e),
+            };
+            return (status, Json(error)).into_response();
+        }
+    };
+
+    match state.auth_service.delete_user(id).await {
+        Ok(_) => {
+            let response = AdminActionResponse {
+                success: true,
+                message: format!("User {} deleted with {} file(s)", id, purged),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let status = match e {
+                AuthError::UserNotFound(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let error = ErrorResponse {
+                error: format!("Failed to delete user: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /admin/users/:id/restore - Restore a deleted account before it is purged
+pub async fn restore_user(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> impl IntoResponse {
+    match state.auth_service.restore_user(id).await {
+        Ok(_) => {
+            let response = AdminActionResponse {
+                success: true,
+                message: format!("User {} restored", id),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let status = match e {
+                AuthError::UserNotFound(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let error = ErrorResponse {
+                error: format!("Failed to restore user: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /admin/users/:id/logins - A user's most recent logins and where they came from
+pub async fn login_history(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    Query(params): Query<LoginHistoryQuery>,
+) -> impl IntoResponse {
+    match state.auth_service.login_history(id, params.limit.min(1000)).await {
+        Ok(logins) => (StatusCode::OK, Json(logins)).into_response(),
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to load login history: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutRoleRequest {
+    pub description: Option<String>,
+    #[serde(default)]
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserRolesResponse {
+    pub user_id: i64,
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+}
+
+fn role_error(context: &str, e: AuthError) -> axum::response::Response {
+    let status = match e {
+        AuthError::RoleNotFound(_) | AuthError::UserNotFound(_) => StatusCode::NOT_FOUND,
+        AuthError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let error = ErrorResponse {
+        error: format!("{}: {}", context, e),
+    };
+    (status, Json(error)).into_response()
+}
+
+/// GET /admin/roles - The custom roles and the permissions they grant
+pub async fn list_roles(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    match state.auth_service.list_roles().await {
+        Ok(roles) => (StatusCode::OK, Json(roles)).into_response(),
+        Err(e) => role_error("Failed to list roles", e),
+    }
+}
+
+/// PUT /admin/roles/:name - Define a role, or replace an existing role's permissions
+pub async fn put_role(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(payload): Json<PutRoleRequest>,
+) -> impl IntoResponse {
+    match state.auth_service.put_role(&name, payload.description.as_deref(), &payload.permissions).await {
+        Ok(role) => (StatusCode::OK, Json(role)).into_response(),
+        Err(e) => role_error("Failed to save role", e),
+    }
+}
+
+/// DELETE /admin/roles/:name - Delete a role, taking it away from everyone who holds it
+pub async fn delete_role(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match state.auth_service.delete_role(&name).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => role_error("Failed to delete role", e),
+    }
+}
+
+/// GET /admin/users/:id/roles - The custom roles a user holds and the permissions they grant
+pub async fn user_roles(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> impl IntoResponse {
+    let roles = match state.auth_service.roles_of(id).await {
+        Ok(roles) => roles,
+        Err(e) => return role_error("Failed to load roles", e),
+    };
+    match state.auth_service.permissions_of(id).await {
+        Ok(permissions) => {
+            let response = UserRolesResponse { user_id: id, roles, permissions };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => role_error("Failed to load permissions", e),
+    }
+}
+
+/// PUT /admin/users/:id/roles/:name - Give a user a role
+pub async fn assign_role(
+    State(state): State<Arc<AppState>>,
+    Path((id, name)): Path<(i64, String)>,
+) -> impl IntoResponse {
+    match state.auth_service.assign_role(id, &name).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => role_error("Failed to assign role", e),
+    }
+}
+
+/// DELETE /admin/users/:id/roles/:name - Take a role away from a user
+pub async fn unassign_role(
+    State(state): State<Arc<AppState>>,
+    Path((id, name)): Path<(i64, String)>,
+) -> impl IntoResponse {
+    match state.auth_service.unassign_role(id, &name).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => role_error("Failed to remove role", e),
+    }
+}
+
+/// DELETE /admin/files/:id - Permanently delete any user's file, skipping the trash (requires sudo)
+pub async fn purge_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.storage_service.force_purge_file(&file_id).await {
+        Ok(file) => {
+            let admin_id = user.id.unwrap();
+            if file.user_id != admin_id {
+                let event = ActivityEvent::new(file.user_id, ActivityKind::AdminAction, "files", &file_id)
+                    .with_actor(admin_id)
+                    .with_data(serde_json::json!({ "action": "file.purged", "name": file.original_name }));
+                state.activity_service.record_best_effort(event).await;
+            }
+
+            let response = AdminActionResponse {
+                success: true,
+                message: "File permanently deleted".to_string(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to purge file: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// Record a hold change in the acting admin's activity feed
+async fn audit_legal_hold(state: &AppState, admin_id: i64, client: ClientIp, action: &str, hold: &LegalHold) {
+    let event = ActivityEvent::new(admin_id, ActivityKind::AdminAction, "legal_holds", &hold.id.to_string())
+        .with_data(serde_json::json!({
+            "action": action,
+            "table": hold.table_name,
+            "record_id": hold.record_id,
+            "reason": hold.reason,
+            "client": client.describe(state),
+        }));
+    state.activity_service.record_best_effort(event).await;
+}
+
+/// GET /admin/holds - List legal holds, active ones only unless `include_released` is set
+pub async fn list_legal_holds(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<LegalHoldQuery>,
+) -> impl IntoResponse {
+    match state
+        .storage_service
+        .list_legal_holds(params.table.as_deref(), !params.include_released)
+        .await
+    {
+        Ok(holds) => (StatusCode::OK, Json(holds)).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to list legal holds: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /admin/holds - Place a legal hold on a file or a table row
+pub async fn place_legal_hold(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    client: ClientIp,
+    Json(payload): Json<PlaceLegalHoldRequest>,
+) -> impl IntoResponse {
+    if !is_valid_table_name(&payload.table) {
+        let error = ErrorResponse {
+            error: "Invalid table name".to_string(),
+        };
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    }
+    let admin_id = user.id.unwrap();
+
+    match state
+        .storage_service
+        .place_legal_hold(&payload.table, &payload.record_id, &payload.reason, admin_id)
+        .await
+    {
+        Ok(hold) => {
+            println!("⚖️  Legal hold {} placed on {} {} by user {}", hold.id, hold.table_name, hold.record_id, admin_id);
+            audit_legal_hold(&state, admin_id, client, "legal_hold.placed", &hold).await;
+            (StatusCode::CREATED, Json(hold)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to place legal hold: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// DELETE /admin/holds/:id - Release a legal hold (requires sudo)
+pub async fn release_legal_hold(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    AuthUser(user): AuthUser,
+    client: ClientIp,
+) -> impl IntoResponse {
+    let admin_id = user.id.unwrap();
+
+    match state.storage_service.release_legal_hold(id, admin_id).await {
+        Ok(hold) => {
+            println!("⚖️  Legal hold {} on {} {} released by user {}", hold.id, hold.table_name, hold.record_id, admin_id);
+            audit_legal_hold(&state, admin_id, client, "legal_hold.released", &hold).await;
+            (StatusCode::OK, Json(hold)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to release legal hold: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// Response for webhook routes when webhooks are not configured
+fn webhooks_disabled() -> axum::response::Response {
+    let error = ErrorResponse {
+        error: "Webhooks are not enabled".to_string(),
+    };
+    (StatusCode::NOT_FOUND, Json(error)).into_response()
+}
+
+/// GET /admin/webhooks/deliveries - The webhook delivery log, most recent first
+pub async fn list_webhook_deliveries(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<WebhookDeliveryQuery>,
+) -> impl IntoResponse {
+    let Some(webhooks) = &state.webhooks else {
+        return webhooks_disabled();
+    };
+
+    match webhooks.list_deliveries(params.status, params.limit.min(1000)).await {
+        Ok(deliveries) => (StatusCode::OK, Json(deliveries)).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to list webhook deliveries: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /admin/webhooks/deliveries/:id/redeliver - Send a failed delivery again
+pub async fn redeliver_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> impl IntoResponse {
+    let Some(webhooks) = &state.webhooks else {
+        return webhooks_disabled();
+    };
+
+    match webhooks.redeliver(id).await {
+        Ok(delivery) => {
+            let webhooks = webhooks.clone();
+            tokio::spawn(async move {
+                let _ = webhooks.deliver_due().await;
+            });
+            (StatusCode::ACCEPTED, Json(delivery)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to redeliver webhook: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+fn job_error(context: &str, error: JobError) -> axum::response::Response {
+    let status = match error {
+        JobError::UnknownType(_) | JobError::NotFound(_) => StatusCode::NOT_FOUND,
+        JobError::Running(_) => StatusCode::PRECONDITION_FAILED,
+    };
+    let error = ErrorResponse {
+        error: format!("{}: {}", context, error),
+    };
+    (status, Json(error)).into_response()
+}
+
+/// GET /admin/jobs - Background job types with their limits, and the jobs in this instance's queue
+pub async fn list_jobs(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<JobQuery>,
+) -> impl IntoResponse {
+    let response = JobsResponse {
+        types: state.jobs.job_types(),
+        jobs: state.jobs.jobs(params.state),
+    };
+    (StatusCode::OK, Json(response))
+}
+
+/// POST /admin/jobs/:id/retry - Run a dead-lettered or backed-off job as soon as a slot is free
+pub async fn retry_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.jobs.retry(id) {
+        Ok(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+        Err(e) => job_error("Failed to retry job", e),
+    }
+}
+
+/// POST /admin/jobs/:id/cancel - Remove a queued or dead-lettered job
+pub async fn cancel_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.jobs.cancel(id) {
+        Ok(job) => (StatusCode::OK, Json(job)).into_response(),
+        Err(e) => job_error("Failed to cancel job", e),
+    }
+}
@@ -0,0 +1,132 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use announcements::{AnnouncementError, NewAnnouncement};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::db_handlers::check_not_held;
+use crate::file_handlers::ErrorResponse;
+use crate::middleware::AuthUser;
+use crate::pagination::{Page, PageParams};
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct AnnouncementQuery {
+    /// Also return announcements the user has dismissed
+    #[serde(default)]
+    pub include_dismissed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnnouncementActionResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+fn announcement_error_status(error: &AnnouncementError) -> StatusCode {
+    match error {
+        AnnouncementError::NotFound(_) => StatusCode::NOT_FOUND,
+        AnnouncementError::Invalid(_) => StatusCode::BAD_REQUEST,
+        AnnouncementError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn announcement_error(context: &str, error: AnnouncementError) -> axum::response::Response {
+    let status = announcement_error_status(&error);
+    let error = ErrorResponse {
+        error: format!("{}: {}", context, error),
+    };
+    (status, Json(error)).into_response()
+}
+
+/// GET /announcements - Active announcements with the user's dismissed state
+pub async fn list_announcements(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(params): Query<AnnouncementQuery>,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.announcement_service.list_for_user_page(user_id, params.include_dismissed, page.limit(), offset).await {
+        Ok((announcements, total)) => Page::from_offset(announcements, offset, page.limit(), total).into_response(),
+        Err(e) => announcement_error("Failed to load announcements", e),
+    }
+}
+
+/// POST /announcements/:id/dismiss - Hide an announcement for the authenticated user
+pub async fn dismiss_announcement(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<i64>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.announcement_service.dismiss(id, user_id).await {
+        Ok(_) => {
+            let response = AnnouncementActionResponse {
+                success: true,
+                message: "Announcement dismissed".to_string(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => announcement_error("Failed to dismiss announcement", e),
+    }
+}
+
+/// GET /admin/announcements - Every announcement, including scheduled and expired ones
+pub async fn list_all_announcements(
+    State(state): State<Arc<AppState>>,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.announcement_service.list_all_page(page.limit(), offset).await {
+        Ok((announcements, total)) => Page::from_offset(announcements, offset, page.limit(), total).into_response(),
+        Err(e) => announcement_error("Failed to load announcements", e),
+    }
+}
+
+/// POST /admin/announcements - Publish an announcement
+pub async fn create_announcement(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<NewAnnouncement>,
+) -> impl IntoResponse {
+    match state.announcement_service.publish(payload, user.id).await {
+        Ok(announcement) => (StatusCode::CREATED, Json(announcement)).into_response(),
+        Err(e) => announcement_error("Failed to publish announcement", e),
+    }
+}
+
+/// DELETE /admin/announcements/:id - Withdraw an announcement
+pub async fn delete_announcement(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> impl IntoResponse {
+    if let Err(response) = check_not_held(&state, "announcements", &id.to_string()).await {
+        return response;
+    }
+
+    match state.announcement_service.delete(id).await {
+        Ok(_) => {
+            let response = AnnouncementActionResponse {
+                success: true,
+                message: "Announcement deleted".to_string(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => announcement_error("Failed to delete announcement", e),
+    }
+}
@@ -0,0 +1,147 @@
+use axum::{
+    extract::{Multipart, Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::file_handlers::{storage_error_status, ErrorResponse};
+use crate::middleware::AuthUser;
+use crate::AppState;
+use storage::StorageError;
+
+/// Assets never change under a given URL, so caches may keep them forever
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+#[derive(Debug, Serialize)]
+pub struct AssetResponse {
+    pub hash: String,
+    pub url: String,
+    pub size: u64,
+}
+
+/// POST /assets - Upload a public, content-addressed asset
+pub async fn upload_asset(
+    State(state): State<Arc<AppState>>,
+    AuthUser(_user): AuthUser,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    while let Ok(Some(field)) = multipart.next_field().await {
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let original_name = field.file_name().unwrap_or("asset").to_string();
+        let data = match field.bytes().await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let error = ErrorResponse {
+                    error: format!("Failed to read file data: {}", e),
+                };
+                return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+            }
+        };
+
+        return match state.storage_service.store_asset(&data, &original_name).await {
+            Ok(metadata) => {
+                let response = AssetResponse {
+                    url: state.storage_service.public_url(&format!("/assets/{}", metadata.stored_name)),
+                    hash: metadata.id,
+                    size: metadata.size,
+                };
+                (StatusCode::CREATED, Json(response)).into_response()
+            }
+            Err(e) => {
+                let status = storage_error_status(&e);
+                let error = ErrorResponse {
+                    error: format!("Failed to store asset: {}", e),
+                };
+                (status, Json(error)).into_response()
+            }
+        };
+    }
+
+    let error = ErrorResponse {
+        error: "No file provided in request".to_string(),
+    };
+    (StatusCode::BAD_REQUEST, Json(error)).into_response()
+}
+
+/// GET /assets/:name - Serve a public asset with immutable cache headers
+pub async fn get_asset(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    request_headers: HeaderMap,
+) -> impl IntoResponse {
+    // The content hash is a strong validator for the asset's bytes
+    let hash = name.split('.').next().unwrap_or(&name);
+    let etag = format!("\"{}\"", hash);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL));
+    if let Ok(value) = etag.parse() {
+        headers.insert(header::ETAG, value);
+    }
+
+    let not_modified = request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    match state.storage_service.retrieve_asset(&name).await {
+        Ok(data) => {
+            let extension = name.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("");
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(asset_content_type(extension)));
+            // Assets are user-supplied; never let a browser treat one as an active document
+            headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+            headers.insert(header::CONTENT_SECURITY_POLICY, HeaderValue::from_static("sandbox"));
+            (StatusCode::OK, headers, data).into_response()
+        }
+        Err(StorageError::FileNotFound(_)) | Err(StorageError::InvalidPath(_)) => {
+            let error = ErrorResponse {
+                error: "Asset not found".to_string(),
+            };
+            (StatusCode::NOT_FOUND, Json(error)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to retrieve asset: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// Content type served for an asset, from its extension
+/// HTML is deliberately served as an opaque download
+fn asset_content_type(extension: &str) -> &'static str {
+    match extension.to_ascii_lowercase().as_str() {
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "wasm" => "application/wasm",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "txt" => "text/plain; charset=utf-8",
+        "pdf" => "application/pdf",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mp3" => "audio/mpeg",
+        _ => "application/octet-stream",
+    }
+}
@@ -1,15 +1,19 @@
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     Json,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
 };
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::Instant;
 
 use crate::AppState;
-use crate::middleware::AuthUser;
-use auth::Role;
+use crate::middleware::{AuthUser, ClientIp};
+use activity::{ActivityEvent, ActivityKind};
+use auth::{AuthError, Email, EmailTokenPurpose, LoginRecord, NotificationPreferencesUpdate, Profile, ProfileUpdate, Role, User};
+use core::SignupMode;
 
 #[derive(Debug, Deserialize)]
 pub struct SignupRequest {
@@ -29,9 +33,27 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SudoRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SudoResponse {
+    pub sudo_token: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
     pub token: String,
+    /// Traded for the next access token at `POST /auth/refresh`; each works once
+    pub refresh_token: String,
     pub user: UserResponse,
 }
 
@@ -41,6 +63,43 @@ pub struct UserResponse {
     pub email: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RecoverRequest {
+    /// Recovery token from the link issued when the account was deleted
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailTokenRequest {
+    /// Token from the link in the email
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailChangeRequest {
+    /// The account's current password
+    pub password: String,
+    pub new_email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    /// Token from the link in the password reset email
+    pub token: String,
+    pub password: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error: String,
@@ -48,19 +107,38 @@ pub struct ErrorResponse {
 
 pub async fn signup(
     State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
     Json(payload): Json<SignupRequest>,
 ) -> impl IntoResponse {
+    if let Err(response) = check_login_limit(&state, ip, &payload.email) {
+        return response;
+    }
+    if let Err((status, error)) = check_signup_allowed(&state, &payload.email).await {
+        return (status, Json(ErrorResponse { error })).into_response();
+    }
+
     match state.auth_service.signup(&payload.email, &payload.password).await {
-        Ok(_user) => {
+        Ok(user) => {
+            if let Err(e) = send_email_link(&state, &user, EmailTokenPurpose::EmailVerification).await {
+                eprintln!("⚠️  Failed to send a verification email to {}: {}", user.email, e);
+            }
+
             // After signup, automatically log them in
             match state.auth_service.login(&payload.email, &payload.password).await {
-                Ok((token, user)) => {
-                    let response = AuthResponse {
-                        token,
-                        user: UserResponse {
-                            id: user.id,
-                            email: user.email,
-                        },
+                Ok((token, user)) => match auth_response(&state, token, user).await {
+                    Ok(response) => (StatusCode::CREATED, Json(response)).into_response(),
+                    Err(e) => {
+                        let error = ErrorResponse {
+                            error: format!("Failed to issue refresh token: {}", e),
+                        };
+                        (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+                    }
+                },
+                // The account can't be used until the address is verified
+                Err(AuthError::EmailNotVerified) => {
+                    let response = UserResponse {
+                        id: user.id,
+                        email: user.email,
                     };
                     (StatusCode::CREATED, Json(response)).into_response()
                 }
@@ -83,12 +161,109 @@ pub async fn signup(
 
 pub async fn login(
     State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
     Json(payload): Json<LoginRequest>,
 ) -> impl IntoResponse {
-    match state.auth_service.login(&payload.email, &payload.password).await {
-        Ok((token, user)) => {
+    if let Err(response) = check_login_limit(&state, ip, &payload.email) {
+        return response;
+    }
+    let result = state.auth_service.login_from(&payload.email, &payload.password, ip).await;
+    record_password_attempt(&state, ip, &payload.email, &result);
+    match result {
+        Ok((token, user, login)) => {
+            record_login(&state, &login).await;
+            match auth_response(&state, token, user).await {
+                Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+                Err(e) => {
+                    let error = ErrorResponse {
+                        error: format!("Failed to issue refresh token: {}", e),
+                    };
+                    (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+                }
+            }
+        }
+        Err(AuthError::EmailNotVerified) => {
+            // The password was right, so send a fresh link in case the first one was lost
+            let state = state.clone();
+            tokio::spawn(async move {
+                send_email_link_to(&state, &payload.email, EmailTokenPurpose::EmailVerification).await;
+            });
+            let error = ErrorResponse {
+                error: "Verify your email address before logging in; a new link is on its way".to_string(),
+            };
+            (StatusCode::FORBIDDEN, Json(error)).into_response()
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Login failed: {}", e),
+            };
+            (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+        }
+    }
+}
+
+/// Refuse an attempt to log in or sign up as `email` from `ip` with 429 once either has made
+/// too many, before the password is hashed
+fn check_login_limit(state: &AppState, ip: Option<IpAddr>, email: &str) -> Result<(), Response> {
+    state.login_limiter.check(ip, email, Instant::now()).map_err(too_many_attempts)
+}
+
+/// Refuse a request from `ip` to email `email` a link with 429 once either has asked too often,
+/// before anything is looked up or sent
+fn check_email_link_limit(state: &AppState, ip: Option<IpAddr>, email: &str) -> Result<(), Response> {
+    state.login_limiter.check_email_link(ip, email, Instant::now()).map_err(too_many_attempts)
+}
+
+/// 429 asking the client to wait `retry_after` seconds
+pub(crate) fn too_many_attempts(retry_after: u64) -> Response {
+    let error = ErrorResponse {
+        error: "Too many attempts, try again later".to_string(),
+    };
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(error)).into_response();
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+/// Count a wrong password from `ip` against the account it was tried on, or clear the count
+/// once the right one is given
+fn record_password_attempt<T>(state: &AppState, ip: Option<IpAddr>, email: &str, result: &auth::Result<T>) {
+    match result {
+        Err(AuthError::InvalidPassword) => state.login_limiter.record_failure(ip, email, Instant::now()),
+        Err(_) => {}
+        Ok(_) => state.login_limiter.record_success(ip, email),
+    }
+}
+
+/// Log a login from a new country and add the login to the user's activity feed
+async fn record_login(state: &AppState, login: &LoginRecord) {
+    if login.new_country {
+        eprintln!(
+            "⚠️  User {} logged in from a new country: {} ({})",
+            login.user_id,
+            login.country.as_deref().unwrap_or("unknown"),
+            login.ip_address.as_deref().unwrap_or("unknown address"),
+        );
+    }
+    let event = ActivityEvent::new(login.user_id, ActivityKind::LoggedIn, "users", &login.user_id.to_string())
+        .with_data(serde_json::json!({
+            "ip": login.ip_address,
+            "country": login.country,
+            "city": login.city,
+            "new_country": login.new_country,
+        }));
+    state.activity_service.record_best_effort(event).await;
+}
+
+/// POST /auth/refresh - Trade a refresh token for a new access token and refresh token
+pub async fn refresh(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RefreshRequest>,
+) -> impl IntoResponse {
+    match state.auth_service.refresh(&payload.refresh_token).await {
+        Ok((token, refresh_token, user)) => {
             let response = AuthResponse {
                 token,
+                refresh_token,
                 user: UserResponse {
                     id: user.id,
                     email: user.email,
@@ -98,13 +273,45 @@ pub async fn login(
         }
         Err(e) => {
             let error = ErrorResponse {
-                error: format!("Login failed: {}", e),
+                error: format!("Refresh failed: {}", e),
             };
             (StatusCode::UNAUTHORIZED, Json(error)).into_response()
         }
     }
 }
 
+/// The response to a login: its access token, a new refresh token, and the user
+async fn auth_response(state: &AppState, token: String, user: User) -> Result<AuthResponse, AuthError> {
+    let refresh_token = state.auth_service.issue_refresh_token(&user).await?;
+    Ok(AuthResponse {
+        token,
+        refresh_token,
+        user: UserResponse {
+            id: user.id,
+            email: user.email,
+        },
+    })
+}
+
+/// Refuse signups the configured mode or domain list doesn't allow
+async fn check_signup_allowed(state: &AppState, email: &str) -> Result<(), (StatusCode, String)> {
+    let signup = &state.signup;
+    let forbidden = |reason: &str| Err((StatusCode::FORBIDDEN, reason.to_string()));
+    match signup.mode {
+        SignupMode::Open => {}
+        SignupMode::Closed => return forbidden("Signups are closed"),
+        SignupMode::InviteOnly => match state.storage_service.has_pending_invitation(email).await {
+            Ok(true) => {}
+            Ok(false) => return forbidden("Signups require an invitation"),
+            Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to check invitations: {}", e))),
+        },
+    }
+    if !signup.allows_domain(email) {
+        return forbidden("Signups are not allowed for this email domain");
+    }
+    Ok(())
+}
+
 /// Create a service account - requires existing service account authentication
 pub async fn create_service_account(
     State(state): State<Arc<AppState>>,
@@ -124,16 +331,15 @@ pub async fn create_service_account(
         Ok(_user) => {
             // After creation, automatically log them in
             match state.auth_service.login(&payload.email, &payload.password).await {
-                Ok((token, user)) => {
-                    let response = AuthResponse {
-                        token,
-                        user: UserResponse {
-                            id: user.id,
-                            email: user.email,
-                        },
-                    };
-                    (StatusCode::CREATED, Json(response)).into_response()
-                }
+                Ok((token, user)) => match auth_response(&state, token, user).await {
+                    Ok(response) => (StatusCode::CREATED, Json(response)).into_response(),
+                    Err(e) => {
+                        let error = ErrorResponse {
+                            error: format!("Failed to issue refresh token: {}", e),
+                        };
+                        (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+                    }
+                },
                 Err(e) => {
                     let error = ErrorResponse {
                         error: format!("Service account created but login failed: {}", e),
@@ -150,3 +356,488 @@ pub async fn create_service_account(
         }
     }
 }
+
+/// Exchange the password for a short-lived sudo token - requires service account authentication
+/// Destructive admin endpoints require the sudo token in the `X-Sudo-Token` header
+pub async fn sudo(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    ClientIp(ip): ClientIp,
+    Json(payload): Json<SudoRequest>,
+) -> impl IntoResponse {
+    // A stolen access token mustn't make guessing the password any faster than logging in
+    if let Err(response) = check_login_limit(&state, ip, &user.email) {
+        return response;
+    }
+    let result = state.auth_service.elevate(&user, &payload.password).await;
+    record_password_attempt(&state, ip, &user.email, &result);
+    match result {
+        Ok((sudo_token, expires_at)) => {
+            let response = SudoResponse {
+                sudo_token,
+                expires_at: expires_at.to_rfc3339(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Sudo failed: {}", e),
+            };
+            (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /auth/change-password - Change the password with the current one
+/// Every session of the account ends, so the response carries a new token and refresh token
+pub async fn change_password(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    ClientIp(ip): ClientIp,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> impl IntoResponse {
+    if let Err(response) = check_login_limit(&state, ip, &user.email) {
+        return response;
+    }
+    let result = state.auth_service
+        .change_password(&user, &payload.current_password, &payload.new_password)
+        .await;
+    record_password_attempt(&state, ip, &user.email, &result);
+    let response = match result {
+        Ok((token, user)) => auth_response(&state, token, user).await,
+        Err(e) => Err(e),
+    };
+    match response {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(AuthError::InvalidPassword) => {
+            let error = ErrorResponse {
+                error: "Current password is incorrect".to_string(),
+            };
+            (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Password change failed: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /auth/recover - Restore a deleted account with its recovery token
+pub async fn recover(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RecoverRequest>,
+) -> impl IntoResponse {
+    match state.auth_service.recover_account(&payload.token).await {
+        Ok(user) => {
+            let response = UserResponse {
+                id: user.id,
+                email: user.email,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Account recovery failed: {}", e),
+            };
+            (StatusCode::BAD_REQUEST, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /auth/me/preferences - The authenticated user's notification preferences
+pub async fn get_preferences(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.auth_service.notification_preferences(user.id.unwrap()).await {
+        Ok(preferences) => (StatusCode::OK, Json(preferences)).into_response(),
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to load preferences: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+/// PATCH /auth/me/preferences - Turn notification kinds on or off
+pub async fn update_preferences(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(update): Json<NotificationPreferencesUpdate>,
+) -> impl IntoResponse {
+    match state.auth_service.update_notification_preferences(user.id.unwrap(), &update).await {
+        Ok(preferences) => (StatusCode::OK, Json(preferences)).into_response(),
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to update preferences: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+/// Email a user a link carrying a new token for `purpose`
+async fn send_email_link(state: &AppState, user: &User, purpose: EmailTokenPurpose) -> Result<(), AuthError> {
+    let token = state.auth_service.issue_email_token(user, purpose).await?;
+    state.mailer.send(&link_email(state, &user.email, purpose, &token)).await
+}
+
+fn link_email(state: &AppState, to: &str, purpose: EmailTokenPurpose, token: &str) -> Email {
+    let (subject, path, text) = match purpose {
+        EmailTokenPurpose::PasswordReset => (
+            "Reset your password",
+            "/reset-password",
+            "Someone asked to reset the password of your account. To choose a new one, open this link within the hour:",
+        ),
+        EmailTokenPurpose::EmailVerification => (
+            "Confirm your email address",
+            "/verify-email",
+            "Confirm that this is your email address by opening this link within 3 days:",
+        ),
+        EmailTokenPurpose::MagicLink => (
+            "Your sign-in link",
+            "/magic-link",
+            "To sign in, open this link within 15 minutes:",
+        ),
+        EmailTokenPurpose::EmailChange => (
+            "Confirm your new email address",
+            "/confirm-email-change",
+            "To use this address for your account, open this link within a day:",
+        ),
+    };
+    let url = state.storage_service.public_url(&format!("{}?token={}", path, token));
+    Email {
+        to: to.to_string(),
+        subject: subject.to_string(),
+        body: format!("{}\n\n{}\n\nIf you didn't ask for this email, you can ignore it.", text, url),
+    }
+}
+
+/// Email a link for `purpose` to the account with this address, if there is one
+/// Callers answer the same either way, and without waiting for the email, so the response
+/// doesn't reveal who has an account.
+async fn send_email_link_to(state: &AppState, email: &str, purpose: EmailTokenPurpose) {
+    let user = match state.auth_service.active_user_by_email(email.trim()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("⚠️  Failed to look up {} for a {} email: {}", email, purpose.as_str(), e);
+            return;
+        }
+    };
+    if let Err(e) = send_email_link(state, &user, purpose).await {
+        eprintln!("⚠️  Failed to send a {} email to {}: {}", purpose.as_str(), user.email, e);
+    }
+}
+
+/// POST /auth/password-reset - Email a password reset link
+pub async fn request_password_reset(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    Json(payload): Json<EmailRequest>,
+) -> impl IntoResponse {
+    if let Err(response) = check_email_link_limit(&state, ip, &payload.email) {
+        return response;
+    }
+    tokio::spawn(async move {
+        send_email_link_to(&state, &payload.email, EmailTokenPurpose::PasswordReset).await;
+    });
+    StatusCode::ACCEPTED.into_response()
+}
+
+/// POST /auth/password-reset/confirm - Choose a new password with the token from the email
+pub async fn reset_password(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> impl IntoResponse {
+    match state.auth_service.reset_password(&payload.token, &payload.password).await {
+        Ok(user) => {
+            let response = UserResponse {
+                id: user.id,
+                email: user.email,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Password reset failed: {}", e),
+            };
+            (StatusCode::BAD_REQUEST, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /auth/profile - The authenticated user's profile
+pub async fn get_profile(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    match state.auth_service.profile(user_id).await {
+        Ok(profile) => (StatusCode::OK, Json(with_live_avatar(&state, user_id, profile).await)).into_response(),
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to load profile: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+/// PATCH /auth/profile - Change the authenticated user's display name, avatar, bio, or locale
+pub async fn update_profile(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(update): Json<ProfileUpdate>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let new_avatar = update.avatar_file_id.as_deref().map(str::trim).filter(|id| !id.is_empty());
+    if let Some(file_id) = new_avatar {
+        if !is_usable_avatar(&state, user_id, file_id).await {
+            let error = ErrorResponse {
+                error: "Avatar must be an image you uploaded".to_string(),
+            };
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+    }
+
+    match state.auth_service.update_profile(user_id, &update).await {
+        Ok(profile) => (StatusCode::OK, Json(with_live_avatar(&state, user_id, profile).await)).into_response(),
+        Err(e) => {
+            let status = match e {
+                AuthError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let error = ErrorResponse {
+                error: format!("Failed to update profile: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// Whether a stored file can be a user's avatar: an image they own that isn't in the trash
+async fn is_usable_avatar(state: &AppState, user_id: i64, file_id: &str) -> bool {
+    match state.storage_service.get_file_by_id(file_id).await {
+        Ok(Some(file)) => {
+            file.user_id == user_id
+                && !file.is_trashed()
+                && file.content_type().is_some_and(|mime| mime.starts_with("image/"))
+        }
+        _ => false,
+    }
+}
+
+/// A profile as shown to clients, without an avatar that was trashed or deleted since it was set
+async fn with_live_avatar(state: &AppState, user_id: i64, mut profile: Profile) -> Profile {
+    if let Some(file_id) = profile.avatar_file_id.as_deref() {
+        if !is_usable_avatar(state, user_id, file_id).await {
+            profile.avatar_file_id = None;
+        }
+    }
+    profile
+}
+
+/// POST /auth/me/email - Email a link to the new address that moves the account there
+/// Requires the current password; the account keeps its address until the link is opened
+pub async fn request_email_change(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    ClientIp(ip): ClientIp,
+    Json(payload): Json<EmailChangeRequest>,
+) -> impl IntoResponse {
+    if let Err(response) = check_login_limit(&state, ip, &user.email) {
+        return response;
+    }
+    let new_email = payload.new_email.trim();
+    if !state.signup.allows_domain(new_email) {
+        let error = ErrorResponse {
+            error: "Accounts are not allowed for this email domain".to_string(),
+        };
+        return (StatusCode::FORBIDDEN, Json(error)).into_response();
+    }
+
+    let result = state.auth_service.request_email_change(&user, &payload.password, new_email).await;
+    record_password_attempt(&state, ip, &user.email, &result);
+    let token = match result {
+        Ok(token) => token,
+        Err(e) => {
+            let status = match e {
+                AuthError::InvalidPassword => StatusCode::UNAUTHORIZED,
+                AuthError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let error = ErrorResponse {
+                error: format!("Email change failed: {}", e),
+            };
+            return (status, Json(error)).into_response();
+        }
+    };
+    if let Err(e) = state.mailer.send(&email_change_notice(&user.email, new_email, false)).await {
+        eprintln!("⚠️  Failed to tell {} about an email change: {}", user.email, e);
+    }
+    // Another account has the address: answer as if the link went out
+    let Some(token) = token else {
+        return StatusCode::ACCEPTED.into_response();
+    };
+    let email = link_email(&state, new_email, EmailTokenPurpose::EmailChange, &token);
+    match state.mailer.send(&email).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to send confirmation email: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+/// The email telling the account's old address that it is being, or has been, replaced
+fn email_change_notice(old_email: &str, new_email: &str, confirmed: bool) -> Email {
+    let (subject, what) = if confirmed {
+        ("Your email address was changed", "was changed")
+    } else {
+        ("Your email address is being changed", "is being changed")
+    };
+    Email {
+        to: old_email.to_string(),
+        subject: subject.to_string(),
+        body: format!(
+            "The email address of your account {} to {}.\n\nIf you didn't ask for this, reset your password and contact support.",
+            what, new_email
+        ),
+    }
+}
+
+/// POST /auth/email-change/confirm - Move the account to its new address with the token from the email
+pub async fn confirm_email_change(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<EmailTokenRequest>,
+) -> impl IntoResponse {
+    match state.auth_service.confirm_email_change(&payload.token).await {
+        Ok((user, old_email)) => {
+            if let Err(e) = state.mailer.send(&email_change_notice(&old_email, &user.email, true)).await {
+                eprintln!("⚠️  Failed to tell {} about an email change: {}", old_email, e);
+            }
+            accept_share_invitations(&state, &user).await;
+            let response = UserResponse {
+                id: user.id,
+                email: user.email,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Email change failed: {}", e),
+            };
+            (StatusCode::BAD_REQUEST, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /auth/me/verify-email - Email the authenticated user a link to verify their address
+pub async fn send_verification_email(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    if user.is_email_verified() {
+        let error = ErrorResponse {
+            error: "Email address is already verified".to_string(),
+        };
+        return (StatusCode::CONFLICT, Json(error)).into_response();
+    }
+    match send_email_link(&state, &user, EmailTokenPurpose::EmailVerification).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to send verification email: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /auth/verify-email - Verify an email address with the token from the email
+pub async fn verify_email(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<EmailTokenRequest>,
+) -> impl IntoResponse {
+    match state.auth_service.verify_email(&payload.token).await {
+        Ok(user) => {
+            accept_share_invitations(&state, &user).await;
+            let response = UserResponse {
+                id: user.id,
+                email: user.email,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Email verification failed: {}", e),
+            };
+            (StatusCode::BAD_REQUEST, Json(error)).into_response()
+        }
+    }
+}
+
+/// Give a user the files shared with their address before they had an account
+/// Only called once the user has shown they own the address, so signing up with someone
+/// else's address doesn't hand over what was shared with them
+async fn accept_share_invitations(state: &AppState, user: &User) {
+    let Some(user_id) = user.id else {
+        return;
+    };
+    match state.storage_service.accept_invitations(&user.email, user_id).await {
+        Ok(accepted) if !accepted.is_empty() => {
+            println!("📨 Accepted {} share invitation(s) for user {}", accepted.len(), user_id);
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("⚠️  Failed to accept share invitations for user {}: {}", user_id, e),
+    }
+}
+
+/// POST /auth/magic-link - Email a link that logs in without the password
+pub async fn request_magic_link(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    Json(payload): Json<EmailRequest>,
+) -> impl IntoResponse {
+    if let Err(response) = check_email_link_limit(&state, ip, &payload.email) {
+        return response;
+    }
+    tokio::spawn(async move {
+        send_email_link_to(&state, &payload.email, EmailTokenPurpose::MagicLink).await;
+    });
+    StatusCode::ACCEPTED.into_response()
+}
+
+/// POST /auth/magic-link/login - Log in with the token from a magic link email
+pub async fn magic_link_login(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    Json(payload): Json<EmailTokenRequest>,
+) -> impl IntoResponse {
+    match state.auth_service.login_with_magic_link(&payload.token, ip).await {
+        Ok((token, user, login)) => {
+            record_login(&state, &login).await;
+            match auth_response(&state, token, user).await {
+                Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+                Err(e) => {
+                    let error = ErrorResponse {
+                        error: format!("Failed to issue refresh token: {}", e),
+                    };
+                    (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+                }
+            }
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Login failed: {}", e),
+            };
+            (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use billing::{usage_period, BillingError, Plan, Subscription, Tenant};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::file_handlers::{storage_error_status, ErrorResponse};
+use crate::middleware::AuthUser;
+use crate::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct SubscriptionResponse {
+    pub plan: Plan,
+    pub subscription: Option<Subscription>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageResponse {
+    /// User or organization the requests count against
+    pub tenant: Tenant,
+    pub plan: Plan,
+    /// Month the count covers, as `YYYY-MM`
+    pub period: String,
+    pub requests_this_month: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetOrganizationPlanRequest {
+    /// Plan to put the organization on; `null` falls back to its owner's plan
+    pub plan: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookResponse {
+    pub received: bool,
+}
+
+/// Response for billing routes when billing is not configured
+fn billing_disabled() -> axum::response::Response {
+    let error = ErrorResponse {
+        error: "Billing is not enabled".to_string(),
+    };
+    (StatusCode::NOT_FOUND, Json(error)).into_response()
+}
+
+/// POST /billing/stripe/webhook - Receive subscription updates from Stripe
+pub async fn stripe_webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    let Some(billing) = &state.billing_service else {
+        return billing_disabled();
+    };
+
+    let signature = headers
+        .get("Stripe-Signature")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    let started = std::time::Instant::now();
+    let result = billing.handle_stripe_webhook(&body, signature).await;
+    state.metrics.record_webhook("stripe", result.is_ok(), started.elapsed());
+
+    match result {
+        Ok(_) => (StatusCode::OK, Json(WebhookResponse { received: true })).into_response(),
+        Err(e) => {
+            let status = match e {
+                BillingError::InvalidSignature(_) => StatusCode::UNAUTHORIZED,
+                BillingError::InvalidPayload(_) | BillingError::UnknownPlan(_) => StatusCode::BAD_REQUEST,
+                BillingError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let error = ErrorResponse {
+                error: format!("Failed to process webhook: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /billing/subscription - The authenticated user's plan and subscription
+pub async fn get_subscription(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let Some(billing) = &state.billing_service else {
+        return billing_disabled();
+    };
+    let user_id = user.id.unwrap();
+
+    let result = async {
+        let plan = billing.plan_for_user(user_id).await?.clone();
+        let subscription = billing.find_subscription(user_id).await?;
+        Ok::<_, BillingError>(SubscriptionResponse { plan, subscription })
+    }.await;
+
+    match result {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to load subscription: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /billing/usage - Requests made this month by the user, or by the organization an
+/// organization token acts for
+pub async fn get_usage(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    tenant: Option<Extension<Tenant>>,
+) -> impl IntoResponse {
+    let Some(billing) = &state.billing_service else {
+        return billing_disabled();
+    };
+    let user_id = user.id.unwrap();
+    let tenant = tenant.map_or(Tenant::User(user_id), |Extension(tenant)| tenant);
+
+    let plan = match tenant {
+        Tenant::User(user_id) => billing.plan_for_user(user_id).await,
+        Tenant::Organization(organization_id) => {
+            match state.storage_service.get_organization(organization_id, user_id).await {
+                Ok(organization) => billing.plan_for_organization(&organization).await,
+                Err(e) => {
+                    let error = ErrorResponse {
+                        error: format!("Failed to load organization: {}", e),
+                    };
+                    return (storage_error_status(&e), Json(error)).into_response();
+                }
+            }
+        }
+    };
+
+    let result = async {
+        let plan = plan?.clone();
+        let requests_this_month = billing.requests_this_month(tenant).await?;
+        Ok::<_, BillingError>(UsageResponse {
+            tenant,
+            plan,
+            period: usage_period(Utc::now()),
+            requests_this_month,
+        })
+    }.await;
+
+    match result {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to load usage: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+/// PUT /admin/organizations/:id/plan - Put an organization on a plan of its own (service role)
+pub async fn set_organization_plan(
+    State(state): State<Arc<AppState>>,
+    Path(organization_id): Path<i64>,
+    Json(payload): Json<SetOrganizationPlanRequest>,
+) -> impl IntoResponse {
+    let Some(billing) = &state.billing_service else {
+        return billing_disabled();
+    };
+
+    if let Some(name) = &payload.plan {
+        if billing.plan(name).is_none() {
+            let error = ErrorResponse {
+                error: format!("Unknown plan: {}", name),
+            };
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+    }
+
+    match state.storage_service.set_organization_plan(organization_id, payload.plan.as_deref()).await {
+        Ok(organization) => (StatusCode::OK, Json(organization)).into_response(),
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to set plan: {}", e),
+            };
+            (storage_error_status(&e), Json(error)).into_response()
+        }
+    }
+}
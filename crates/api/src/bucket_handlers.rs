@@ -0,0 +1,129 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use storage::BucketCredentials;
+
+use crate::file_handlers::{storage_error_status, ErrorResponse};
+use crate::middleware::AuthUser;
+use crate::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct BucketRemovedResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+/// Response for bucket routes when user buckets are not configured
+fn buckets_disabled() -> axum::response::Response {
+    let error = ErrorResponse {
+        error: "User buckets are not enabled".to_string(),
+    };
+    (StatusCode::NOT_FOUND, Json(error)).into_response()
+}
+
+fn no_bucket() -> axum::response::Response {
+    let error = ErrorResponse {
+        error: "No bucket is registered".to_string(),
+    };
+    (StatusCode::NOT_FOUND, Json(error)).into_response()
+}
+
+/// GET /storage/bucket - The authenticated user's own bucket and its health
+pub async fn get_bucket(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let Some(buckets) = &state.user_buckets else {
+        return buckets_disabled();
+    };
+
+    match buckets.get(user.id.unwrap()).await {
+        Ok(Some(bucket)) => (StatusCode::OK, Json(bucket)).into_response(),
+        Ok(None) => no_bucket(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to get bucket: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// PUT /storage/bucket - Store the authenticated user's files in their own bucket
+/// The bucket must pass a health check, and the user must have no files yet
+pub async fn put_bucket(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(credentials): Json<BucketCredentials>,
+) -> impl IntoResponse {
+    let Some(buckets) = &state.user_buckets else {
+        return buckets_disabled();
+    };
+
+    match buckets.configure(user.id.unwrap(), credentials).await {
+        Ok(bucket) => (StatusCode::OK, Json(bucket)).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to register bucket: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// DELETE /storage/bucket - Go back to storing the authenticated user's files on the server
+pub async fn delete_bucket(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let Some(buckets) = &state.user_buckets else {
+        return buckets_disabled();
+    };
+
+    match buckets.remove(user.id.unwrap()).await {
+        Ok(true) => {
+            let response = BucketRemovedResponse {
+                success: true,
+                message: "Bucket removed".to_string(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Ok(false) => no_bucket(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to remove bucket: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /storage/bucket/check - Health-check the authenticated user's bucket now
+pub async fn check_bucket(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let Some(buckets) = &state.user_buckets else {
+        return buckets_disabled();
+    };
+
+    match buckets.check_health(user.id.unwrap()).await {
+        Ok(Some(bucket)) => (StatusCode::OK, Json(bucket)).into_response(),
+        Ok(None) => no_bucket(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to check bucket: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Whether this instance can run alongside others behind a load balancer
+//!
+//! `GET /admin/cluster` lists every piece of state the server keeps and where it lives. State
+//! in the database or object storage is shared by all instances; state in this process is
+//! either harmless to keep per instance (metrics) or breaks when requests land on different
+//! instances (SQLite, local blobs, in-memory realtime fanout). Ingestion from a drop directory
+//! or SFTP must run on only one instance.
+
+use core::StorageBackendKind;
+use serde::Serialize;
+
+use crate::AppState;
+
+/// Facts about how this instance was deployed that handlers can't see on their own
+#[derive(Debug, Clone, Default)]
+pub struct Deployment {
+    /// Scheme of the database URL: `sqlite`, `mysql`, or `postgres`
+    pub database: String,
+    pub storage_backend: StorageBackendKind,
+    /// `storage.path` is on a filesystem every instance mounts
+    pub shared_local_storage: bool,
+    /// `[storage.watch]` is enabled
+    pub drop_directory_watcher: bool,
+    /// `[storage.sftp]` is configured
+    pub sftp_ingestion: bool,
+    /// `[storage.cache]` is enabled
+    pub blob_cache: bool,
+}
+
+/// Where a piece of state lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// In a store every instance uses
+    Shared,
+    /// In this process or on this machine
+    Instance,
+    /// Work that must only run on one instance
+    Singleton,
+}
+
+/// One piece of state and whether it holds up with several instances
+#[derive(Debug, Clone, Serialize)]
+pub struct Component {
+    pub name: &'static str,
+    pub scope: Scope,
+    pub multi_node_safe: bool,
+    pub detail: String,
+}
+
+impl Component {
+    fn new(name: &'static str, scope: Scope, multi_node_safe: bool, detail: impl Into<String>) -> Self {
+        Self { name, scope, multi_node_safe, detail: detail.into() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClusterReport {
+    /// Every component is safe, so more instances with this configuration can be added
+    pub multi_node_safe: bool,
+    pub components: Vec<Component>,
+}
+
+/// Report on the state of a running instance
+pub fn report(state: &AppState) -> ClusterReport {
+    let deployment = &state.deployment;
+    let mut components = Vec::new();
+
+    components.push(if deployment.database == "sqlite" {
+        Component::new("database", Scope::Instance, false, "SQLite is a file on this instance; use MySQL or PostgreSQL")
+    } else {
+        Component::new("database", Scope::Shared, true, format!("{} server", deployment.database))
+    });
+
+    components.push(match deployment.storage_backend {
+        StorageBackendKind::S3 => Component::new("file_storage", Scope::Shared, true, "S3-compatible object storage"),
+        StorageBackendKind::Gcs => Component::new("file_storage", Scope::Shared, true, "Google Cloud Storage"),
+        StorageBackendKind::Local if deployment.shared_local_storage => {
+            Component::new("file_storage", Scope::Shared, true, "local directory on a shared filesystem")
+        }
+        StorageBackendKind::Local => Component::new(
+            "file_storage",
+            Scope::Instance,
+            false,
+            "blobs are on this instance's disk; use S3 or GCS, or mount storage.path on every instance and set cluster.shared_local_storage",
+        ),
+    });
+
+    components.push(Component::new(
+        "sessions_and_tokens",
+        Scope::Shared,
+        true,
+        "sessions, share links, download tokens, legal holds, and resumable uploads are stored in the database; nothing is cached in memory",
+    ));
+
+    let fanout = state.activity_service.fanout();
+    components.push(if fanout.is_shared() {
+        Component::new("realtime_activity", Scope::Shared, true, format!("events are fanned out through {}", fanout.name()))
+    } else {
+        Component::new(
+            "realtime_activity",
+            Scope::Instance,
+            false,
+            "in-memory: realtime clients only receive events recorded by the instance they are connected to; set cluster.redis_url",
+        )
+    });
+
+    components.push(Component::new(
+        "storage_events",
+        Scope::Instance,
+        true,
+        "in-process by design: subscribers only see files stored or deleted through this instance",
+    ));
+
+    if state.webhooks.is_some() {
+        components.push(Component::new(
+            "webhook_delivery",
+            Scope::Shared,
+            true,
+            "deliveries are claimed with a lease, so every instance can run the delivery job",
+        ));
+    }
+
+    if state.billing_service.is_some() {
+        components.push(Component::new(
+            "rate_limits",
+            Scope::Instance,
+            true,
+            "per-minute plan limits are counted by each instance on its own; monthly request caps are counted in the database",
+        ));
+    }
+
+    if deployment.blob_cache {
+        components.push(Component::new(
+            "blob_cache",
+            Scope::Instance,
+            true,
+            "each instance caches the small files it reads; files rewritten through another instance are served from the old copy until storage.cache.ttl_seconds passes",
+        ));
+    }
+
+    if state.download_throttle.is_limited() {
+        components.push(Component::new(
+            "download_limits",
+            Scope::Instance,
+            true,
+            "download bandwidth is paced by each instance on its own, so a user downloading through several instances gets each instance's rate",
+        ));
+    }
+
+    components.push(Component::new(
+        "background_jobs",
+        Scope::Instance,
+        true,
+        "each instance queues and runs its own maintenance jobs; /admin/jobs shows the queue of the instance that answers",
+    ));
+
+    components.push(Component::new("metrics", Scope::Instance, true, "counters are per instance; scrape every instance"));
+
+    if deployment.drop_directory_watcher {
+        components.push(Component::new(
+            "drop_directory_watcher",
+            Scope::Singleton,
+            false,
+            "enable [storage.watch] on one instance only, or dropped files are ingested twice",
+        ));
+    }
+    if deployment.sftp_ingestion {
+        components.push(Component::new(
+            "sftp_ingestion",
+            Scope::Singleton,
+            false,
+            "configure [storage.sftp] on one instance only, or remote files are ingested twice",
+        ));
+    }
+
+    ClusterReport {
+        multi_node_safe: components.iter().all(|component| component.multi_node_safe),
+        components,
+    }
+}
@@ -0,0 +1,207 @@
+use activity::{ActivityEvent, ActivityKind};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use auth::User;
+use comments::CommentError;
+use orm::query::QueryValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::sync::Arc;
+
+use crate::db_handlers::{check_not_held, is_protected_table, is_valid_table_name};
+use crate::file_handlers::ErrorResponse;
+use crate::middleware::AuthUser;
+use crate::pagination::{Page, PageParams};
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct CommentRequest {
+    pub body: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommentActionResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+fn comment_error(context: &str, error: CommentError) -> Response {
+    let status = match error {
+        CommentError::NotFound(_) => StatusCode::NOT_FOUND,
+        CommentError::Forbidden(_) => StatusCode::FORBIDDEN,
+        CommentError::Invalid(_) => StatusCode::BAD_REQUEST,
+        CommentError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let error = ErrorResponse {
+        error: format!("{}: {}", context, error),
+    };
+    (status, Json(error)).into_response()
+}
+
+/// Check that the user may read the commented record, applying the same rules as `/db/:table`
+/// Comments are only visible to users who can see the record they are attached to
+/// 
+/// # Returns
+/// The record, so callers can find its owner
+async fn check_record_access(state: &AppState, user: &User, table: &str, row_id: &str) -> Result<JsonValue, Response> {
+    if !is_valid_table_name(table) {
+        let error = ErrorResponse {
+            error: format!("Invalid table name: '{}'", table),
+        };
+        return Err((StatusCode::BAD_REQUEST, Json(error)).into_response());
+    }
+
+    if is_protected_table(table) && !user.is_service() {
+        let error = ErrorResponse {
+            error: format!("Access denied to protected table '{}'. Service role required.", table),
+        };
+        return Err((StatusCode::FORBIDDEN, Json(error)).into_response());
+    }
+
+    let key = match row_id.parse::<i64>() {
+        Ok(id) => QueryValue::I64(id),
+        Err(_) => QueryValue::String(row_id.to_string()),
+    };
+    let mut params = vec![key];
+    // Records the select policy hides are reported missing, so their IDs can't be probed
+    let filter = match state.row_policies.select_filter(table, user, &mut params) {
+        Ok(filter) => filter,
+        Err(error) => return Err((StatusCode::FORBIDDEN, Json(ErrorResponse { error })).into_response()),
+    };
+    let sql = match filter {
+        Some(condition) => format!("SELECT * FROM {} WHERE id = ?1 AND {}", table, condition),
+        None => format!("SELECT * FROM {} WHERE id = ?1", table),
+    };
+
+    match state.db.backend().fetch_one_params(&sql, &params).await {
+        Ok(Some(record)) => Ok(record),
+        Ok(None) => {
+            let error = ErrorResponse {
+                error: format!("Record '{}' not found in table '{}'", row_id, table),
+            };
+            Err((StatusCode::NOT_FOUND, Json(error)).into_response())
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to look up record in table '{}': {}", table, e),
+            };
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response())
+        }
+    }
+}
+
+/// GET /db/:table/:id/comments - Comments on a record, oldest first
+pub async fn list_comments(
+    State(state): State<Arc<AppState>>,
+    Path((table, row_id)): Path<(String, String)>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+    if let Err(response) = check_record_access(&state, &user, &table, &row_id).await {
+        return response;
+    }
+
+    match state.comment_service.list_page(&table, &row_id, page.limit(), offset).await {
+        Ok((comments, total)) => Page::from_offset(comments, offset, page.limit(), total).into_response(),
+        Err(e) => comment_error("Failed to load comments", e),
+    }
+}
+
+/// POST /db/:table/:id/comments - Comment on a record
+pub async fn create_comment(
+    State(state): State<Arc<AppState>>,
+    Path((table, row_id)): Path<(String, String)>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<CommentRequest>,
+) -> impl IntoResponse {
+    let record = match check_record_access(&state, &user, &table, &row_id).await {
+        Ok(record) => record,
+        Err(response) => return response,
+    };
+    let user_id = user.id.unwrap();
+
+    match state.comment_service.create(&table, &row_id, user_id, &payload.body).await {
+        Ok(comment) => {
+            // Tell the record's owner, if it has one, unless they commented themselves
+            let owner_id = record.get("user_id").and_then(|v| v.as_i64());
+            if let (Some(owner_id), Some(comment_id)) = (owner_id, comment.id) {
+                if owner_id != user_id {
+                    let event = ActivityEvent::new(owner_id, ActivityKind::CommentCreated, &table, &row_id)
+                        .with_actor(user_id)
+                        .with_data(serde_json::json!({ "comment_id": comment_id }));
+                    state.activity_service.record_best_effort(event).await;
+                }
+            }
+
+            (StatusCode::CREATED, Json(comment)).into_response()
+        }
+        Err(e) => comment_error("Failed to post comment", e),
+    }
+}
+
+/// PATCH /comments/:id - Edit one of the user's own comments
+pub async fn update_comment(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<CommentRequest>,
+) -> impl IntoResponse {
+    let comment = match state.comment_service.find(id).await {
+        Ok(comment) => comment,
+        Err(e) => return comment_error("Failed to edit comment", e),
+    };
+    if let Err(response) = check_record_access(&state, &user, &comment.table_name, &comment.row_id).await {
+        return response;
+    }
+    let user_id = user.id.unwrap();
+
+    match state.comment_service.update(id, user_id, &payload.body).await {
+        Ok(comment) => (StatusCode::OK, Json(comment)).into_response(),
+        Err(e) => comment_error("Failed to edit comment", e),
+    }
+}
+
+/// DELETE /comments/:id - Delete a comment (the author's own, or any for service accounts)
+pub async fn delete_comment(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let comment = match state.comment_service.find(id).await {
+        Ok(comment) => comment,
+        Err(e) => return comment_error("Failed to delete comment", e),
+    };
+    if let Err(response) = check_record_access(&state, &user, &comment.table_name, &comment.row_id).await {
+        return response;
+    }
+    if let Err(response) = check_not_held(&state, "comments", &id.to_string()).await {
+        return response;
+    }
+    let user_id = user.id.unwrap();
+
+    match state.comment_service.delete(id, user_id, user.is_service()).await {
+        Ok(_) => {
+            if comment.user_id != user_id {
+                let event = ActivityEvent::new(comment.user_id, ActivityKind::AdminAction, &comment.table_name, &comment.row_id)
+                    .with_actor(user_id)
+                    .with_data(serde_json::json!({ "action": "comment.deleted", "comment_id": id }));
+                state.activity_service.record_best_effort(event).await;
+            }
+
+            let response = CommentActionResponse {
+                success: true,
+                message: "Comment deleted".to_string(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => comment_error("Failed to delete comment", e),
+    }
+}
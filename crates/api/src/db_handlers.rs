@@ -1,49 +1,477 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     Json,
     response::IntoResponse,
 };
 use serde::Serialize;
 use serde_json::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use streaming::ChangeEvent;
 
 use crate::AppState;
 use crate::middleware::AuthUser;
+use crate::pagination::{Page, PageParams, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use auth::Role;
+use core::{DbConfig, DbEncryptionConfig, DbReadConfig};
+use storage::CredentialCipher;
 
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error: String,
 }
+
+/// Body of a 422 response for a write that sets server-managed columns
+#[derive(Debug, Serialize)]
+pub struct RejectedFieldsResponse {
+    pub error: String,
+    pub rejected_fields: Vec<String>,
+}
+
+/// Columns that `/db` writes from anyone but the service role may not set
+/// Ownership, timestamps, and roles belong here, so users can't forge them on their own rows
+#[derive(Debug, Clone, Default)]
+pub struct ServerManagedColumns {
+    /// Protected in every table
+    everywhere: HashSet<String>,
+    /// Protected in one table
+    by_table: HashMap<String, HashSet<String>>,
+}
+
+impl ServerManagedColumns {
+    /// Parse `[db] server_managed_columns`: `column` for every table, or `table.column`
+    pub fn from_config(entries: &[String]) -> Result<Self, String> {
+        let mut columns = Self::default();
+        for entry in entries {
+            let (table, column) = match entry.split_once('.') {
+                Some((table, column)) => (Some(table), column),
+                None => (None, entry.as_str()),
+            };
+            if !is_valid_table_name(column) || table.is_some_and(|table| !is_valid_table_name(table)) {
+                return Err(format!("invalid server-managed column '{}'", entry));
+            }
+            // Identifiers are case-insensitive in SQL, so `User_Id` must not slip through
+            let column = column.to_ascii_lowercase();
+            match table {
+                Some(table) => {
+                    columns.by_table.entry(table.to_ascii_lowercase()).or_default().insert(column);
+                }
+                None => {
+                    columns.everywhere.insert(column);
+                }
+            }
+        }
+        Ok(columns)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.everywhere.is_empty() && self.by_table.is_empty()
+    }
+
+    /// The fields of a write to `table` that are server-managed, as sent
+    pub fn rejected<'a>(&self, table: &str, fields: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+        let in_table = self.by_table.get(&table.to_ascii_lowercase());
+        fields
+            .into_iter()
+            .filter(|field| {
+                let field = field.to_ascii_lowercase();
+                self.everywhere.contains(&field) || in_table.is_some_and(|columns| columns.contains(&field))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Prefix of encrypted values, which are stored as `enc:<key id>:<hex nonce and ciphertext>`
+const ENCRYPTED_PREFIX: &str = "enc:";
+
+/// Columns of `/db` tables that are encrypted at rest
+///
+/// Writes encrypt these columns with the current key; reads decrypt them for the configured
+/// roles and return `null` to everyone else. Each value names the key it was encrypted with, so
+/// a new key can take over while values written under older ones stay readable.
+#[derive(Clone, Default)]
+pub struct EncryptedColumns {
+    by_table: HashMap<String, HashSet<String>>,
+    keys: HashMap<String, CredentialCipher>,
+    current_key: String,
+    readers: HashSet<Role>,
+}
+
+impl EncryptedColumns {
+    /// Parse `[db.encryption]`
+    pub fn from_config(config: &DbEncryptionConfig) -> Result<Self, String> {
+        let mut columns = Self::default();
+        for entry in &config.columns {
+            match entry.split_once('.') {
+                Some((table, column)) if is_valid_table_name(table) && is_valid_table_name(column) => {
+                    columns.by_table.entry(table.to_ascii_lowercase()).or_default().insert(column.to_ascii_lowercase());
+                }
+                _ => return Err(format!("invalid encrypted column '{}': expected table.column", entry)),
+            }
+        }
+
+        for (id, secret) in &config.keys {
+            // IDs are stored in front of a `:`, so they can't contain one
+            if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(format!("invalid key ID '{}'", id));
+            }
+            if secret.is_empty() {
+                return Err(format!("key '{}' is empty", id));
+            }
+            columns.keys.insert(id.clone(), CredentialCipher::new(secret));
+        }
+
+        for role in &config.readers {
+            columns.readers.insert(Role::from_str(role).ok_or_else(|| format!("unknown reader role '{}'", role))?);
+        }
+
+        if columns.by_table.is_empty() {
+            return Ok(columns);
+        }
+        match &config.current_key {
+            Some(id) if columns.keys.contains_key(id) => columns.current_key = id.clone(),
+            Some(id) => return Err(format!("current_key '{}' is not one of the keys", id)),
+            None => return Err("current_key is required when columns are encrypted".to_string()),
+        }
+        Ok(columns)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_table.is_empty()
+    }
+
+    fn columns(&self, table: &str) -> Option<&HashSet<String>> {
+        self.by_table.get(&table.to_ascii_lowercase())
+    }
+
+    /// Replace the encrypted columns of a write to `table` with their ciphertext
+    /// Nulls stay null, so they can still be told apart from values
+    pub fn encrypt(&self, table: &str, fields: &mut serde_json::Map<String, JsonValue>) -> Result<(), String> {
+        let Some(columns) = self.columns(table) else {
+            return Ok(());
+        };
+        let cipher = &self.keys[&self.current_key];
+
+        for (field, value) in fields.iter_mut() {
+            if value.is_null() || !columns.contains(&field.to_ascii_lowercase()) {
+                continue;
+            }
+            let plaintext = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+            let ciphertext = cipher.encrypt(&plaintext).map_err(|_| format!("Failed to encrypt '{}'", field))?;
+            *value = JsonValue::String(format!("{}{}:{}", ENCRYPTED_PREFIX, self.current_key, ciphertext));
+        }
+        Ok(())
+    }
+
+    /// Decrypt the encrypted columns of a row read from `table` for a reader with `role`
+    /// Values that can't be decrypted, and every value for other roles, are replaced by `null`;
+    /// values written before their column was encrypted are returned as they are to readers
+    pub fn decrypt(&self, table: &str, row: &mut JsonValue, role: Role) {
+        let (Some(columns), Some(row)) = (self.columns(table), row.as_object_mut()) else {
+            return;
+        };
+        let may_read = self.readers.contains(&role);
+
+        for (field, value) in row.iter_mut() {
+            if value.is_null() || !columns.contains(&field.to_ascii_lowercase()) {
+                continue;
+            }
+            *value = match value.as_str().and_then(|stored| stored.strip_prefix(ENCRYPTED_PREFIX)) {
+                Some(envelope) if may_read => self.open(envelope).unwrap_or(JsonValue::Null),
+                None if may_read => value.take(),
+                _ => JsonValue::Null,
+            };
+        }
+    }
+
+    /// Decrypt `<key id>:<ciphertext>` with the key it names
+    fn open(&self, envelope: &str) -> Option<JsonValue> {
+        let (key_id, ciphertext) = envelope.split_once(':')?;
+        let plaintext = self.keys.get(key_id)?.decrypt(ciphertext).ok()?;
+        serde_json::from_slice(&plaintext).ok()
+    }
+}
+
+/// How `GET /db/{table}` orders and pages a table when the client doesn't say
+/// Reads are ordered where the table allows it, so pages are stable, and always limited, so naive
+/// clients can't scan a whole table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadPolicy {
+    /// Column pages are ordered by; `id` when unset, if the table has one
+    pub order_by: Option<String>,
+    pub descending: bool,
+    /// Rows per page when the client passes no limit
+    pub default_limit: i64,
+    /// Rows per page at most
+    pub max_limit: i64,
+}
+
+impl Default for ReadPolicy {
+    fn default() -> Self {
+        Self {
+            order_by: None,
+            descending: false,
+            default_limit: DEFAULT_PAGE_LIMIT,
+            max_limit: MAX_PAGE_LIMIT,
+        }
+    }
+}
+
+impl ReadPolicy {
+    /// This policy with the fields `config` sets replaced
+    fn merged(&self, config: &DbReadConfig) -> Result<Self, String> {
+        let mut policy = self.clone();
+        if let Some(order_by) = &config.order_by {
+            let mut words = order_by.split_whitespace();
+            let column = words.next().unwrap_or_default();
+            if !is_valid_table_name(column) {
+                return Err(format!("invalid order_by column '{}'", order_by));
+            }
+            policy.order_by = Some(column.to_string());
+            policy.descending = match words.next().map(str::to_ascii_lowercase).as_deref() {
+                None | Some("asc") => false,
+                Some("desc") => true,
+                Some(_) => return Err(format!("order_by '{}' must be a column, optionally followed by asc or desc", order_by)),
+            };
+            if words.next().is_some() {
+                return Err(format!("order_by '{}' must be a column, optionally followed by asc or desc", order_by));
+            }
+        }
+        if let Some(max_limit) = config.max_limit {
+            if max_limit < 1 {
+                return Err(format!("max_limit must be at least 1, got {}", max_limit));
+            }
+            policy.max_limit = max_limit;
+            // An inherited default never exceeds a lowered maximum
+            policy.default_limit = policy.default_limit.min(max_limit);
+        }
+        if let Some(default_limit) = config.default_limit {
+            if default_limit < 1 || default_limit > policy.max_limit {
+                return Err(format!("default_limit must be between 1 and max_limit ({}), got {}", policy.max_limit, default_limit));
+            }
+            policy.default_limit = default_limit;
+        }
+        Ok(policy)
+    }
+
+    /// Page size for a request: the client's limit within bounds, or the default
+    pub fn limit(&self, requested: Option<i64>) -> i64 {
+        requested.unwrap_or(self.default_limit).clamp(1, self.max_limit)
+    }
+
+    /// The `ORDER BY` clause of a read, given whether the table has an `id` column
+    /// Without a configured column or an `id` to fall back on, pages are left unordered
+    fn order_clause(&self, has_id: bool) -> String {
+        let column = match &self.order_by {
+            Some(column) => column.as_str(),
+            None if has_id => "id",
+            None => return String::new(),
+        };
+        format!("ORDER BY {} {}", column, if self.descending { "DESC" } else { "ASC" })
+    }
+}
+
+/// The read policy of every table, from `[db.reads]` and `[db.tables.<name>]`
+#[derive(Debug, Clone, Default)]
+pub struct ReadPolicies {
+    defaults: ReadPolicy,
+    by_table: HashMap<String, ReadPolicy>,
+}
+
+impl ReadPolicies {
+    pub fn from_config(config: &DbConfig) -> Result<Self, String> {
+        let defaults = ReadPolicy::default()
+            .merged(&config.reads)
+            .map_err(|e| format!("[db.reads]: {}", e))?;
+
+        let mut by_table = HashMap::new();
+        for (table, overrides) in &config.tables {
+            if !is_valid_table_name(table) {
+                return Err(format!("[db.tables]: invalid table name '{}'", table));
+            }
+            let policy = defaults.merged(overrides).map_err(|e| format!("[db.tables.{}]: {}", table, e))?;
+            by_table.insert(table.to_ascii_lowercase(), policy);
+        }
+        Ok(Self { defaults, by_table })
+    }
+
+    /// The policy reads of `table` follow
+    pub fn for_table(&self, table: &str) -> &ReadPolicy {
+        self.by_table.get(&table.to_ascii_lowercase()).unwrap_or(&self.defaults)
+    }
+}
+
 /// Validate table name to prevent SQL injection
-/// Only allows alphanumeric characters and underscores
-fn is_valid_table_name(table: &str) -> bool {
+/// Only allows ASCII alphanumeric characters and underscores, since names are interpolated unquoted
+pub(crate) fn is_valid_table_name(table: &str) -> bool {
     if table.is_empty() || table.len() > 64 {
         return false;
     }
     
     // Must start with a letter or underscore
-    if !table.chars().next().unwrap().is_alphabetic() && !table.starts_with('_') {
+    if !table.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
         return false;
     }
     
     // Only allow alphanumeric and underscores
-    table.chars().all(|c| c.is_alphanumeric() || c == '_')
+    table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
 /// List of system tables that should not be directly accessible
-const PROTECTED_TABLES: &[&str] = &["users", "sessions", "migrations"];
+const PROTECTED_TABLES: &[&str] = &[
+    "users",
+    "sessions",
+    "migrations",
+    "schema_migration_steps",
+    "schema_migration_started",
+    "billing_subscriptions",
+    "announcements",
+    "announcement_dismissals",
+    "comments",
+    "file_shares",
+    "file_favorites",
+    "file_access_log",
+    "activity_events",
+    "download_tokens",
+    "storage_transfer_log",
+    "storage_usage_reports",
+    "notification_preferences",
+    "user_buckets",
+    "legal_holds",
+    "login_history",
+    "webhook_deliveries",
+    "upload_sessions",
+    "share_invitations",
+    "upload_journal",
+    "signing_keys",
+    "file_permissions",
+    "file_transfers",
+    "organizations",
+    "memberships",
+    "table_versions",
+    "api_usage",
+    "file_texts",
+    "presigned_uploads",
+    "refresh_tokens",
+    "email_tokens",
+    "roles",
+    "permissions",
+    "user_roles",
+    "profiles",
+];
 
-fn is_protected_table(table: &str) -> bool {
+pub(crate) fn is_protected_table(table: &str) -> bool {
     PROTECTED_TABLES.contains(&table)
 }
 
-/// GET /db/:table - Fetch all records from a table
-/// Requires authentication. Service accounts can access all tables, users can only access non-protected tables.
+/// Table of the write counters that `/db` list reads use as their ETag
+const TABLE_VERSIONS_TABLE: &str = "table_versions";
+
+/// Counter in `table_versions` bumped by writes that may touch any table
+const ALL_TABLES: &str = "*";
+
+/// Current version of a table: its own write counter plus the one shared by every table
+/// Both only grow, so the sum changes with every write; 0 until anything is written
+async fn table_version(state: &AppState, table: &str) -> Result<i64, String> {
+    let sql = format!("SELECT version FROM {} WHERE table_name IN (?1, ?2)", TABLE_VERSIONS_TABLE);
+    let params = [
+        orm::query::QueryValue::String(table.to_string()),
+        orm::query::QueryValue::String(ALL_TABLES.to_string()),
+    ];
+    let rows = state.db.backend()
+        .fetch_all_params(&sql, &params)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(rows.iter().filter_map(|row| row.get("version").and_then(|v| v.as_i64())).sum())
+}
+
+/// Record a write to a table, so its list reads stop matching the ETags handed out before
+async fn bump_table_version(state: &AppState, table: &str) {
+    let backend = state.db.backend();
+    let now = orm::query::QueryValue::String(chrono::Utc::now().to_rfc3339());
+    let name = orm::query::QueryValue::String(table.to_string());
+
+    let update_sql = format!(
+        "UPDATE {} SET version = version + 1, updated_at = ?1 WHERE table_name = ?2",
+        TABLE_VERSIONS_TABLE
+    );
+    let result = match backend.execute(&update_sql, &[now.clone(), name.clone()]).await {
+        Ok(0) => {
+            let insert_sql = format!(
+                "INSERT INTO {} (table_name, version, updated_at) VALUES (?1, 1, ?2)",
+                TABLE_VERSIONS_TABLE
+            );
+            backend.execute(&insert_sql, &[name, now]).await
+        }
+        result => result,
+    };
+    if let Err(e) = result {
+        eprintln!("⚠️  Failed to bump the version of table {}: {}", table, e);
+    }
+}
+
+/// Record a write that may have touched any table, such as one made through the SQL console
+pub(crate) async fn bump_all_table_versions(state: &AppState) {
+    bump_table_version(state, ALL_TABLES).await;
+}
+
+/// Weak ETag of a table's list reads at `version`, as seen by `viewer`
+/// Weak because only the rows are versioned, not how they are rendered
+fn table_etag(table: &str, version: i64, viewer: u64) -> String {
+    format!("W/\"{}-{}-{:016x}\"", table, version, viewer)
+}
+
+/// Fingerprint of who a list read is for and the filter their select policy applied, so one
+/// caller's ETag never validates the rows another caller was sent
+fn viewer_fingerprint(user_id: Option<i64>, role: Role, filter: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    (user_id, role, filter).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Whether `If-None-Match` names `etag`, comparing weakly as RFC 9110 requires
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag)))
+}
+
+/// Whether a table has an `id` column, probed with a query that reads no rows
+async fn has_id_column(state: &AppState, table: &str) -> bool {
+    let sql = format!("SELECT id FROM {} WHERE 1 = 0", table);
+    state.db.backend().fetch_all_params(&sql, &[]).await.is_ok()
+}
+
+/// Refuse to delete a row under a legal hold with 423 Locked
+pub(crate) async fn check_not_held(state: &AppState, table: &str, id: &str) -> Result<(), axum::response::Response> {
+    match state.storage_service.ensure_not_held(table, id).await {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let status = crate::file_handlers::storage_error_status(&e);
+            let error = ErrorResponse {
+                error: e.to_string(),
+            };
+            Err((status, Json(error)).into_response())
+        }
+    }
+}
+
+/// GET /db/:table - Fetch a page of records from a table
+/// Requires authentication. Service accounts can access all tables, users can only access non-protected tables,
+/// and only the rows the table's select policy lets them see.
+/// Reads of non-protected tables carry a weak ETag that changes with every write through the
+/// API; a matching `If-None-Match` gets 304 without the table being read.
 pub async fn get_table(
     State(state): State<Arc<AppState>>,
     Path(table): Path<String>,
     AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+    request_headers: HeaderMap,
 ) -> impl IntoResponse {
     // Validate table name
     if !is_valid_table_name(&table) {
@@ -61,14 +489,74 @@ pub async fn get_table(
         return (StatusCode::FORBIDDEN, Json(error)).into_response();
     }
     
+    let mut params = Vec::new();
+    let filter = match state.row_policies.select_filter(&table, &user, &mut params) {
+        Ok(filter) => filter,
+        Err(error) => return (StatusCode::FORBIDDEN, Json(ErrorResponse { error })).into_response(),
+    };
+    let where_clause = filter.map(|condition| format!(" WHERE {}", condition)).unwrap_or_default();
+    
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    // Rows depend on who is asking, so only the caller's own cache may keep them
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("private, no-cache"));
+    headers.insert(header::VARY, HeaderValue::from_static("Authorization"));
+
+    // Protected tables are also written outside `/db`, so only the others can be versioned
+    if !is_protected_table(&table) {
+        match table_version(&state, &table).await {
+            Ok(version) => {
+                let etag = table_etag(&table, version, viewer_fingerprint(user.id, user.role, &where_clause));
+                if let Ok(value) = HeaderValue::from_str(&etag) {
+                    headers.insert(header::ETAG, value);
+                }
+                if if_none_match(&request_headers, &etag) {
+                    return (StatusCode::NOT_MODIFIED, headers).into_response();
+                }
+            }
+            Err(e) => eprintln!("⚠️  Failed to read the version of table {}: {}", table, e),
+        }
+    }
+
+    let policy = state.read_policies.for_table(&table);
+    let limit = policy.limit(page.limit);
+    
     let backend = state.db.backend();
     
-    // Build a simple SELECT * query
-    let sql = format!("SELECT * FROM {}", table);
+    let count_sql = format!("SELECT COUNT(*) as total FROM {}{}", table, where_clause);
+    let total = match backend.fetch_one_params(&count_sql, &params).await {
+        Ok(row) => row.and_then(|json| json.get("total").and_then(|v| v.as_i64())).unwrap_or(0),
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to fetch from table '{}': {}", table, e),
+            };
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response();
+        }
+    };
+    
+    // Order every page, so offsets address the same rows from one request to the next
+    let has_id = policy.order_by.is_none() && has_id_column(&state, &table).await;
+    let sql = format!(
+        "SELECT * FROM {}{} {} LIMIT ?{} OFFSET ?{}",
+        table,
+        where_clause,
+        policy.order_clause(has_id),
+        params.len() + 1,
+        params.len() + 2
+    );
+    params.push(orm::query::QueryValue::I64(limit));
+    params.push(orm::query::QueryValue::I64(offset));
     
-    match backend.fetch_all_params(&sql, &[]).await {
-        Ok(rows) => {
-            (StatusCode::OK, Json(rows)).into_response()
+    match backend.fetch_all_params(&sql, &params).await {
+        Ok(mut rows) => {
+            for row in &mut rows {
+                state.encrypted_columns.decrypt(&table, row, user.role);
+            }
+            (headers, Page::from_offset(rows, offset, limit, total)).into_response()
         }
         Err(e) => {
             let error = ErrorResponse {
@@ -81,6 +569,8 @@ pub async fn get_table(
 
 /// POST /db/:table - Insert a new record into a table
 /// Requires authentication. Service accounts can access all tables, users can only access non-protected tables.
+/// Only service accounts may set server-managed columns; anyone else gets 422 listing them.
+/// Rows the table's insert policy doesn't allow get 403.
 pub async fn post_table(
     State(state): State<Arc<AppState>>,
     Path(table): Path<String>,
@@ -123,38 +613,40 @@ pub async fn post_table(
         return (StatusCode::BAD_REQUEST, Json(error)).into_response();
     }
     
-    // Validate column names to prevent SQL injection
-    for col in obj.keys() {
-        if !is_valid_table_name(col) {
-            let error = ErrorResponse {
-                error: format!("Invalid column name: '{}'", col),
+    if !user.is_service() {
+        let rejected_fields = state.server_managed_columns.rejected(&table, obj.keys());
+        if !rejected_fields.is_empty() {
+            let response = RejectedFieldsResponse {
+                error: format!("Server-managed fields can't be set: {}", rejected_fields.join(", ")),
+                rejected_fields,
             };
-            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(response)).into_response();
         }
     }
     
-    // Build column names and parameter placeholders
-    let columns: Vec<String> = obj.keys().cloned().collect();
-    let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+    let mut stored = obj.clone();
+    if let Err(error) = state.row_policies.check_insert(&table, &user, &mut stored) {
+        return (StatusCode::FORBIDDEN, Json(ErrorResponse { error })).into_response();
+    }
     
-    // Convert JSON values to ORM QueryValue
-    let mut params = Vec::new();
-    for col in &columns {
-        let json_val = &obj[col];
-        let query_val = json_to_query_value(json_val);
-        params.push(query_val);
+    // Encrypted columns are stored, and published, as ciphertext
+    if let Err(error) = state.encrypted_columns.encrypt(&table, &mut stored) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error })).into_response();
     }
     
-    // Build INSERT query
-    let sql = format!(
-        "INSERT INTO {} ({}) VALUES ({})",
-        table,
-        columns.join(", "),
-        placeholders.join(", ")
-    );
+    let (sql, params) = match build_insert(&table, &stored) {
+        Ok(query) => query,
+        Err(error) => {
+            return (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response();
+        }
+    };
     
     match backend.execute(&sql, &params).await {
         Ok(rows_affected) => {
+            bump_table_version(&state, &table).await;
+            if let Some(event_stream) = &state.event_stream {
+                event_stream.publish_in_background(ChangeEvent::record_created(&table, user.id.unwrap(), JsonValue::Object(stored)));
+            }
             let response = serde_json::json!({
                 "success": true,
                 "rows_affected": rows_affected,
@@ -170,8 +662,39 @@ pub async fn post_table(
     }
 }
 
+/// Build the parameterized INSERT for a `/db/{table}` payload
+/// Column names are validated here; the table name must already have been checked by the caller
+pub fn build_insert(
+    table: &str,
+    obj: &serde_json::Map<String, JsonValue>,
+) -> Result<(String, Vec<orm::query::QueryValue>), String> {
+    // Validate column names to prevent SQL injection
+    for col in obj.keys() {
+        if !is_valid_table_name(col) {
+            return Err(format!("Invalid column name: '{}'", col));
+        }
+    }
+    
+    // Build column names and parameter placeholders
+    let columns: Vec<&str> = obj.keys().map(|col| col.as_str()).collect();
+    let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+    
+    // Convert JSON values to ORM QueryValue
+    let params = obj.values().map(json_to_query_value).collect();
+    
+    // Build INSERT query
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table,
+        columns.join(", "),
+        placeholders.join(", ")
+    );
+    
+    Ok((sql, params))
+}
+
 /// Helper function to convert serde_json::Value to orm::query::QueryValue
-fn json_to_query_value(val: &JsonValue) -> orm::query::QueryValue {
+pub(crate) fn json_to_query_value(val: &JsonValue) -> orm::query::QueryValue {
     match val {
         JsonValue::Null => orm::query::QueryValue::Null,
         JsonValue::Bool(b) => orm::query::QueryValue::Bool(*b),
@@ -188,3 +711,184 @@ fn json_to_query_value(val: &JsonValue) -> orm::query::QueryValue {
         _ => orm::query::QueryValue::Null,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Reference definition of a safe unquoted identifier
+    fn is_plain_identifier(name: &str) -> bool {
+        let bytes = name.as_bytes();
+        !bytes.is_empty()
+            && bytes.len() <= 64
+            && (bytes[0].is_ascii_alphabetic() || bytes[0] == b'_')
+            && bytes.iter().all(|b| b.is_ascii_alphanumeric() || *b == b'_')
+    }
+
+    #[test]
+    fn test_table_names() {
+        assert!(is_valid_table_name("notes"));
+        assert!(is_valid_table_name("_drafts_2024"));
+        assert!(!is_valid_table_name(""));
+        assert!(!is_valid_table_name("2024_notes"));
+        assert!(!is_valid_table_name("notes; DROP TABLE users"));
+        assert!(!is_valid_table_name("notes--"));
+        assert!(!is_valid_table_name("\"notes\""));
+        assert!(!is_valid_table_name("café"));
+        assert!(!is_valid_table_name(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let etag = table_etag("notes", 3, 0xab);
+        assert_eq!(etag, "W/\"notes-3-00000000000000ab\"");
+        let mut headers = HeaderMap::new();
+        assert!(!if_none_match(&headers, &etag));
+
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("W/\"notes-2-00000000000000ab\", W/\"notes-3-00000000000000ab\""));
+        assert!(if_none_match(&headers, &etag));
+        // Weak comparison ignores the W/ prefix
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"notes-3-00000000000000ab\""));
+        assert!(if_none_match(&headers, &etag));
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(if_none_match(&headers, &etag));
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("W/\"notes-4-00000000000000ab\""));
+        assert!(!if_none_match(&headers, &etag));
+    }
+
+    #[test]
+    fn test_viewer_fingerprint() {
+        let own_rows = "user_id = ?1";
+        let fingerprint = viewer_fingerprint(Some(1), Role::User, own_rows);
+        assert_eq!(fingerprint, viewer_fingerprint(Some(1), Role::User, own_rows));
+        assert_ne!(fingerprint, viewer_fingerprint(Some(2), Role::User, own_rows));
+        assert_ne!(fingerprint, viewer_fingerprint(Some(1), Role::Service, own_rows));
+        assert_ne!(fingerprint, viewer_fingerprint(Some(1), Role::User, ""));
+    }
+
+    #[test]
+    fn test_server_managed_columns() {
+        let entries = ["user_id".to_string(), "notes.Pinned".to_string()];
+        let columns = ServerManagedColumns::from_config(&entries).unwrap();
+        let fields = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(columns.rejected("notes", &fields(&["title", "USER_ID", "pinned"])), ["USER_ID", "pinned"]);
+        assert_eq!(columns.rejected("tasks", &fields(&["title", "pinned"])), Vec::<String>::new());
+        assert_eq!(columns.rejected("tasks", &fields(&["user_id"])), ["user_id"]);
+        assert!(ServerManagedColumns::default().is_empty());
+
+        assert!(ServerManagedColumns::from_config(&["notes.".to_string()]).is_err());
+        assert!(ServerManagedColumns::from_config(&["user id".to_string()]).is_err());
+        assert!(ServerManagedColumns::from_config(&["a.b.c".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_read_policies() {
+        let mut config = DbConfig::default();
+        let policies = ReadPolicies::from_config(&config).unwrap();
+        let policy = policies.for_table("notes");
+        assert_eq!(policy.order_clause(true), "ORDER BY id ASC");
+        // Tables without an id column are left unordered rather than failing
+        assert_eq!(policy.order_clause(false), "");
+        assert_eq!(policy.limit(None), DEFAULT_PAGE_LIMIT);
+        assert_eq!(policy.limit(Some(10_000)), MAX_PAGE_LIMIT);
+
+        config.reads.max_limit = Some(20);
+        config.tables.insert("Events".to_string(), DbReadConfig {
+            order_by: Some("created_at DESC".to_string()),
+            default_limit: Some(5),
+            ..Default::default()
+        });
+        let policies = ReadPolicies::from_config(&config).unwrap();
+        // A lowered maximum caps the inherited default too
+        assert_eq!(policies.for_table("notes").limit(None), 20);
+        let events = policies.for_table("events");
+        assert_eq!(events.order_clause(false), "ORDER BY created_at DESC");
+        assert_eq!((events.limit(None), events.limit(Some(50))), (5, 20));
+
+        let invalid = |reads: DbReadConfig| ReadPolicies::from_config(&DbConfig { reads, ..Default::default() }).is_err();
+        assert!(invalid(DbReadConfig { order_by: Some("id; DROP TABLE users".to_string()), ..Default::default() }));
+        assert!(invalid(DbReadConfig { order_by: Some("id sideways".to_string()), ..Default::default() }));
+        assert!(invalid(DbReadConfig { max_limit: Some(0), ..Default::default() }));
+        assert!(invalid(DbReadConfig { default_limit: Some(200), max_limit: Some(100), ..Default::default() }));
+    }
+
+    #[test]
+    fn test_encrypted_columns() {
+        let mut config = DbEncryptionConfig {
+            columns: vec!["patients.SSN".to_string()],
+            keys: HashMap::from([("2024-01".to_string(), "first secret".to_string())]),
+            current_key: Some("2024-01".to_string()),
+            ..Default::default()
+        };
+        let columns = EncryptedColumns::from_config(&config).unwrap();
+
+        let mut fields = serde_json::json!({"name": "Ada", "ssn": "123-45-6789", "notes": null});
+        let fields = fields.as_object_mut().unwrap();
+        columns.encrypt("patients", fields).unwrap();
+        let stored = fields["ssn"].as_str().unwrap().to_string();
+        assert!(stored.starts_with("enc:2024-01:"));
+        assert!(!stored.contains("123-45-6789"));
+        assert_eq!(fields["name"], "Ada");
+
+        let row = serde_json::json!({"id": 1, "name": "Ada", "ssn": stored, "legacy": "plain"});
+        let mut read = row.clone();
+        columns.decrypt("patients", &mut read, Role::Service);
+        assert_eq!(read["ssn"], "123-45-6789");
+        let mut read = row.clone();
+        columns.decrypt("patients", &mut read, Role::User);
+        assert!(read["ssn"].is_null());
+        assert_eq!(read["name"], "Ada");
+
+        // Values written under a retired key stay readable while the key is configured
+        config.keys.insert("k2".to_string(), "second secret".to_string());
+        config.current_key = Some("k2".to_string());
+        let rotated = EncryptedColumns::from_config(&config).unwrap();
+        let mut read = row.clone();
+        rotated.decrypt("patients", &mut read, Role::Service);
+        assert_eq!(read["ssn"], "123-45-6789");
+        config.keys.remove("2024-01");
+        let mut read = row;
+        EncryptedColumns::from_config(&config).unwrap().decrypt("patients", &mut read, Role::Service);
+        assert!(read["ssn"].is_null());
+
+        let invalid = |config: DbEncryptionConfig| EncryptedColumns::from_config(&config).is_err();
+        assert!(invalid(DbEncryptionConfig { columns: vec!["ssn".to_string()], ..config.clone() }));
+        assert!(invalid(DbEncryptionConfig { current_key: Some("2024-01".to_string()), ..config.clone() }));
+        assert!(invalid(DbEncryptionConfig { current_key: None, ..config.clone() }));
+        assert!(invalid(DbEncryptionConfig { keys: HashMap::from([("k:2".to_string(), "secret".to_string())]), ..config.clone() }));
+        assert!(invalid(DbEncryptionConfig { readers: vec!["admin".to_string()], ..config }));
+        assert!(EncryptedColumns::from_config(&DbEncryptionConfig::default()).unwrap().is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_accepts_plain_identifiers(name in "[A-Za-z_][A-Za-z0-9_]{0,63}") {
+            prop_assert!(is_valid_table_name(&name));
+        }
+
+        #[test]
+        fn prop_accepts_nothing_else(name in any::<String>()) {
+            prop_assert_eq!(is_valid_table_name(&name), is_plain_identifier(&name));
+        }
+
+        #[test]
+        fn prop_rejects_sql_metacharacters(
+            prefix in "[A-Za-z_][A-Za-z0-9_]{0,20}",
+            meta in prop::sample::select(vec![" ", ";", "'", "\"", "`", "-", "/", "*", "(", ")", ",", ".", "=", "\0", "\n"]),
+            suffix in "[A-Za-z0-9_ ]{0,20}",
+        ) {
+            let name = format!("{}{}{}", prefix, meta, suffix);
+            prop_assert!(!is_valid_table_name(&name));
+        }
+
+        #[test]
+        fn prop_json_values_convert_without_panicking(value in any::<i64>(), text in any::<String>(), float in any::<f64>()) {
+            let payload = serde_json::json!({ "n": value, "s": text, "f": float, "a": [1, 2] });
+            for value in payload.as_object().unwrap().values() {
+                let _ = json_to_query_value(value);
+            }
+        }
+    }
+}
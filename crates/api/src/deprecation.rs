@@ -0,0 +1,307 @@
+//! Deprecated routes and response fields
+//!
+//! Deprecations come from `[[deprecations]]` in the config. A deprecated route answers with
+//! `Deprecation` (RFC 9745), `Sunset` (RFC 8594) and `Link` headers and, once its sunset has
+//! passed, with `410 Gone`. JSON object responses also get a `warnings` array naming every
+//! deprecation that applies; a deprecated field is dropped from responses after its sunset.
+
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use core::DeprecationConfig;
+use serde_json::Value as JsonValue;
+use std::sync::Arc;
+
+use crate::middleware::ErrorResponse;
+use crate::AppState;
+
+/// A deprecated route, or a deprecated field of its JSON responses
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    pub route: String,
+    /// Every method when unset
+    pub method: Option<Method>,
+    pub field: Option<String>,
+    pub since: DateTime<Utc>,
+    pub sunset: Option<DateTime<Utc>>,
+    pub link: Option<String>,
+    pub message: String,
+}
+
+impl Deprecation {
+    /// Validate a `[[deprecations]]` entry
+    pub fn from_config(config: &DeprecationConfig) -> Result<Self, String> {
+        let parse_date = |value: &str| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|dt| dt.with_timezone(&Utc))
+                .map_err(|e| format!("invalid date {:?} for {}: {}", value, config.route, e))
+        };
+
+        if !config.route.starts_with('/') {
+            return Err(format!("route {:?} must start with /", config.route));
+        }
+        let method = match &config.method {
+            Some(method) => Some(
+                Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .map_err(|_| format!("invalid method {:?} for {}", method, config.route))?,
+            ),
+            None => None,
+        };
+        let since = parse_date(&config.since)?;
+        let sunset = config.sunset.as_deref().map(parse_date).transpose()?;
+        if sunset.is_some_and(|sunset| sunset < since) {
+            return Err(format!("sunset of {} is before its deprecation", config.route));
+        }
+
+        let mut deprecation = Self {
+            route: config.route.clone(),
+            method,
+            field: config.field.clone(),
+            since,
+            sunset,
+            link: config.link.clone(),
+            message: String::new(),
+        };
+        deprecation.message = config.message.clone().unwrap_or_else(|| deprecation.default_message());
+        Ok(deprecation)
+    }
+
+    fn default_message(&self) -> String {
+        let subject = match (&self.field, &self.method) {
+            (Some(field), _) => format!("Field `{}` of {}", field, self.route),
+            (None, Some(method)) => format!("{} {}", method, self.route),
+            (None, None) => self.route.clone(),
+        };
+        let mut message = format!("{} is deprecated", subject);
+        if let Some(sunset) = self.sunset {
+            message.push_str(&format!(" and will be removed on {}", sunset.format("%Y-%m-%d")));
+        }
+        if let Some(link) = &self.link {
+            message.push_str(&format!("; see {}", link));
+        }
+        message
+    }
+
+    fn applies_to(&self, route: &str, method: &Method) -> bool {
+        self.route == route && self.method.as_ref().is_none_or(|m| m == method)
+    }
+
+    fn is_sunset(&self, now: DateTime<Utc>) -> bool {
+        self.sunset.is_some_and(|sunset| sunset <= now)
+    }
+}
+
+/// All configured deprecations
+#[derive(Debug, Clone, Default)]
+pub struct Deprecations(Vec<Deprecation>);
+
+impl Deprecations {
+    pub fn from_config(configs: &[DeprecationConfig]) -> Result<Self, String> {
+        configs.iter().map(Deprecation::from_config).collect::<Result<_, _>>().map(Self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn matching(&self, route: &str, method: &Method) -> Vec<&Deprecation> {
+        self.0.iter().filter(|d| d.applies_to(route, method)).collect()
+    }
+}
+
+/// Format a timestamp as an HTTP date (`Sun, 06 Nov 1994 08:49:37 GMT`)
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Add `warnings` to a JSON object response, and drop deprecated fields past their sunset
+/// Returns whether the body changed; bodies that aren't objects are left alone
+fn annotate(body: &mut JsonValue, deprecations: &[&Deprecation], now: DateTime<Utc>) -> bool {
+    let Some(object) = body.as_object_mut() else {
+        return false;
+    };
+
+    let mut changed = false;
+    let mut warnings = Vec::new();
+    for deprecation in deprecations {
+        match &deprecation.field {
+            Some(field) if deprecation.is_sunset(now) => {
+                changed |= object.remove(field).is_some();
+            }
+            Some(field) if object.contains_key(field) => warnings.push(deprecation.message.clone()),
+            Some(_) => {}
+            None => warnings.push(deprecation.message.clone()),
+        }
+    }
+
+    if !warnings.is_empty() {
+        match object.get_mut("warnings").and_then(|w| w.as_array_mut()) {
+            Some(existing) => {
+                existing.extend(warnings.into_iter().map(JsonValue::String));
+                changed = true;
+            }
+            None if !object.contains_key("warnings") => {
+                object.insert("warnings".to_string(), JsonValue::from(warnings));
+                changed = true;
+            }
+            None => {}
+        }
+    }
+    changed
+}
+
+/// Middleware applying the configured deprecations to matched routes
+pub async fn emit_deprecations(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.deprecations.is_empty() {
+        return next.run(request).await;
+    }
+    let Some(route) = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string()) else {
+        return next.run(request).await;
+    };
+    let deprecations = state.deprecations.matching(&route, request.method());
+    if deprecations.is_empty() {
+        return next.run(request).await;
+    }
+
+    let now = Utc::now();
+    let route_deprecation = deprecations.iter().find(|d| d.field.is_none()).copied();
+
+    let mut response = match route_deprecation {
+        Some(deprecation) if deprecation.is_sunset(now) => {
+            let error = ErrorResponse {
+                error: format!("{} was removed on {}", route, deprecation.sunset.unwrap().format("%Y-%m-%d")),
+            };
+            (StatusCode::GONE, Json(error)).into_response()
+        }
+        _ => next.run(request).await,
+    };
+
+    if let Some(deprecation) = route_deprecation {
+        let headers = response.headers_mut();
+        if let Ok(value) = HeaderValue::from_str(&format!("@{}", deprecation.since.timestamp())) {
+            headers.insert("Deprecation", value);
+        }
+        if let Some(value) = deprecation.sunset.and_then(|sunset| HeaderValue::from_str(&http_date(sunset)).ok()) {
+            headers.insert("Sunset", value);
+        }
+        if let Some(value) = deprecation.link.as_ref().and_then(|link| {
+            HeaderValue::from_str(&format!("<{}>; rel=\"deprecation\"; type=\"text/html\"", link)).ok()
+        }) {
+            headers.append(header::LINK, value);
+        }
+    }
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !is_json || response.status() == StatusCode::GONE {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to read response body".to_string(),
+        })).into_response();
+    };
+    let mut json = match serde_json::from_slice::<JsonValue>(&bytes) {
+        Ok(json) => json,
+        Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+    };
+    if !annotate(&mut json, &deprecations, now) {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = serde_json::to_vec(&json).map(Body::from).unwrap_or_else(|_| Body::from(bytes));
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deprecation(field: Option<&str>, sunset: Option<&str>) -> Deprecation {
+        Deprecation::from_config(&DeprecationConfig {
+            route: "/files/stats".to_string(),
+            method: Some("get".to_string()),
+            field: field.map(str::to_string),
+            since: "2024-11-01T00:00:00Z".to_string(),
+            sunset: sunset.map(str::to_string),
+            link: None,
+            message: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_from_config() {
+        let route = deprecation(None, Some("2025-05-01T00:00:00Z"));
+        assert_eq!(route.method, Some(Method::GET));
+        assert!(route.applies_to("/files/stats", &Method::GET));
+        assert!(!route.applies_to("/files/stats", &Method::POST));
+        assert!(!route.applies_to("/files/{id}", &Method::GET));
+        assert_eq!(route.message, "GET /files/stats is deprecated and will be removed on 2025-05-01");
+
+        let field = deprecation(Some("total_size"), None);
+        assert_eq!(field.message, "Field `total_size` of /files/stats is deprecated");
+
+        let mut config = DeprecationConfig {
+            route: "/files".to_string(),
+            method: None,
+            field: None,
+            since: "yesterday".to_string(),
+            sunset: None,
+            link: None,
+            message: None,
+        };
+        assert!(Deprecation::from_config(&config).is_err());
+        config.since = "2025-05-01T00:00:00Z".to_string();
+        config.sunset = Some("2024-11-01T00:00:00Z".to_string());
+        assert!(Deprecation::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_http_date() {
+        let date = DateTime::parse_from_rfc3339("1994-11-06T08:49:37Z").unwrap().with_timezone(&Utc);
+        assert_eq!(http_date(date), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+
+    #[test]
+    fn test_annotate() {
+        let now = DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        let route = deprecation(None, None);
+        let field = deprecation(Some("total_size"), None);
+        let removed = deprecation(Some("file_count"), Some("2024-12-01T00:00:00Z"));
+
+        let mut body = serde_json::json!({ "file_count": 5, "total_size": 512 });
+        assert!(annotate(&mut body, &[&route, &field, &removed], now));
+        assert!(body.get("file_count").is_none());
+        assert_eq!(body["total_size"], 512);
+        assert_eq!(body["warnings"], serde_json::json!([route.message, field.message]));
+
+        // Fields that aren't in the response don't warn
+        let mut body = serde_json::json!({ "file_count": 5 });
+        assert!(!annotate(&mut body, &[&field], now));
+        assert!(body.get("warnings").is_none());
+
+        let mut body = serde_json::json!([1, 2]);
+        assert!(!annotate(&mut body, &[&route], now));
+    }
+}
@@ -1,20 +1,42 @@
+use activity::{ActivityEvent, ActivityKind};
 use axum::{
-    extract::{Path, State, Multipart},
-    http::{header, StatusCode},
+    body::Bytes,
+    extract::{multipart::Field, Path, Query, State, Multipart},
+    http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
 };
-use serde::Serialize;
+use futures_util::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tokio_util::io::StreamReader;
 
 use crate::middleware::AuthUser;
+use crate::pagination::{Page, PageParams, MAX_PAGE_LIMIT};
+use crate::range::ByteRange;
 use crate::AppState;
+use storage::{ExportFormat, FileListOptions, FileSort, MetadataUpdate, PolicyViolation, StorageError, DEFAULT_DOWNLOAD_TOKEN_TTL_SECONDS, MAX_DOWNLOAD_TOKEN_TTL_SECONDS};
 
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error: String,
 }
 
+/// Body of a 422 response for an upload rejected by the upload policy
+#[derive(Debug, Serialize)]
+pub struct PolicyViolationResponse {
+    pub error: String,
+    pub violation: PolicyViolation,
+}
+
+pub(crate) fn policy_violation_response(violation: PolicyViolation) -> axum::response::Response {
+    let response = PolicyViolationResponse {
+        error: format!("Upload rejected: {}", violation),
+        violation,
+    };
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(response)).into_response()
+}
+
 #[derive(Debug, Serialize)]
 pub struct FileResponse {
     pub id: String,
@@ -22,7 +44,52 @@ pub struct FileResponse {
     pub stored_name: String,
     pub size: i64,
     pub mime_type: Option<String>,
+    pub detected_mime_type: Option<String>,
+    pub checksum: Option<String>,
+    pub folder: Option<String>,
     pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
+    pub download_count: i64,
+    pub last_accessed_at: Option<String>,
+    /// Organization the file belongs to, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_id: Option<i64>,
+    /// Width in pixels of an image or video, when it could be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<i64>,
+    /// Height in pixels of an image or video, when it could be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<i64>,
+    /// Playing time in milliseconds of a video or audio file, when it could be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<i64>,
+    pub etag: String,
+}
+
+impl From<storage::File> for FileResponse {
+    fn from(file: storage::File) -> Self {
+        let etag = file.etag();
+        Self {
+            etag,
+            id: file.id.unwrap_or_default(),
+            original_name: file.original_name,
+            stored_name: file.stored_name,
+            size: file.size,
+            mime_type: file.mime_type,
+            detected_mime_type: file.detected_mime_type,
+            checksum: file.checksum,
+            folder: file.folder,
+            created_at: file.created_at.to_rfc3339(),
+            deleted_at: file.deleted_at.map(|deleted_at| deleted_at.to_rfc3339()),
+            download_count: file.download_count,
+            last_accessed_at: file.last_accessed_at.map(|accessed_at| accessed_at.to_rfc3339()),
+            organization_id: file.organization_id,
+            width: file.width,
+            height: file.height,
+            duration_ms: file.duration_ms,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -31,210 +98,1003 @@ pub struct UploadResponse {
     pub file: FileResponse,
 }
 
+#[derive(Debug, Serialize)]
+pub struct BatchUploadResponse {
+    pub success: bool,
+    pub files: Vec<FileResponse>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct DeleteResponse {
     pub success: bool,
     pub message: String,
 }
 
-/// POST /files/upload - Upload a file
+#[derive(Debug, Serialize)]
+pub struct EmptyTrashResponse {
+    pub success: bool,
+    pub purged: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+/// Page size of search results and recent files when the client doesn't ask for one
+const DEFAULT_SEARCH_LIMIT: i64 = 20;
+
+/// Which files `GET /files` lists
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileListView {
+    /// The user's own files
+    #[default]
+    Owned,
+    /// Files other users shared with the user
+    Shared,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListFilesQuery {
+    #[serde(default)]
+    pub view: FileListView,
+    /// `created_at`, `name`, or `size`
+    pub sort: Option<String>,
+    /// `asc` or `desc`; defaults to the sort's natural direction
+    pub order: Option<String>,
+    /// Only files of this content type, or of a family such as `image/*`
+    pub mime_type: Option<String>,
+    /// Only files directly in this folder; empty for the root
+    pub folder: Option<String>,
+}
+
+impl ListFilesQuery {
+    /// Listing options for one page, or the message of the response for an invalid parameter
+    fn options(&self, limit: i64, offset: i64) -> Result<FileListOptions, String> {
+        let sort = match self.sort.as_deref() {
+            None => None,
+            Some(sort) => Some(FileSort::from_str(sort)
+                .ok_or_else(|| format!("Unknown sort '{}'; use created_at, name, or size", sort))?),
+        };
+        let descending = match self.order.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            None => None,
+            Some("asc") => Some(false),
+            Some("desc") => Some(true),
+            Some(order) => return Err(format!("Unknown order '{}'; use asc or desc", order)),
+        };
+        Ok(FileListOptions {
+            sort,
+            descending,
+            mime_type: self.mime_type.clone().filter(|mime_type| !mime_type.trim().is_empty()),
+            folder: self.folder.clone(),
+            ..FileListOptions::new(limit, offset)
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// `csv` (the default) or `json`
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UploadOptions {
+    /// Unpack zip archives into their files instead of storing the archive
+    #[serde(default)]
+    pub extract: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContentPatchQuery {
+    /// Byte offset to write at; the body is appended when omitted
+    pub offset: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CopyFileRequest {
+    /// Folder to place the copy in; the original's folder when omitted
+    pub folder: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportFileRequest {
+    /// `http` or `https` URL the server downloads the file from
+    pub url: String,
+    /// Name to store the file under; the last segment of the URL's path when omitted
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferRequest {
+    /// User to offer the file to
+    pub user_id: i64,
+}
+
+/// Files for `POST /files/archive`: either `file_ids` or `folder`
+#[derive(Debug, Deserialize)]
+pub struct ArchiveRequest {
+    pub file_ids: Option<Vec<String>>,
+    /// Folder to archive with its subfolders; `""` archives every file
+    pub folder: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadTokenRequest {
+    /// Seconds until the token expires if unused
+    pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DownloadTokenResponse {
+    pub token: String,
+    pub file_id: String,
+    pub url: String,
+    pub expires_at: String,
+}
+
+/// Map a storage error to the HTTP status the client should see
+/// Transient backend failures become 503 so clients know to retry later
+pub(crate) fn storage_error_status(e: &StorageError) -> StatusCode {
+    match e {
+        StorageError::FileNotFound(_) => StatusCode::NOT_FOUND,
+        StorageError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+        StorageError::QuotaExceeded(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        StorageError::InvalidInput(_) | StorageError::InvalidPath(_) => StatusCode::BAD_REQUEST,
+        StorageError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+        StorageError::LegalHold(_) => StatusCode::LOCKED,
+        StorageError::MimeTypeMismatch(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        StorageError::PolicyViolation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        StorageError::Transient(_) | StorageError::CircuitOpen(_) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Read an archive part into memory, refusing one larger than it could unpack to
+async fn read_archive_field(mut field: Field<'_>) -> Result<Vec<u8>, axum::response::Response> {
+    let mut data = Vec::new();
+    loop {
+        match field.chunk().await {
+            Ok(Some(chunk)) => {
+                if (data.len() + chunk.len()) as u64 > storage::MAX_EXTRACTED_SIZE {
+                    let error = ErrorResponse {
+                        error: format!("Archive is larger than {} bytes", storage::MAX_EXTRACTED_SIZE),
+                    };
+                    return Err((StatusCode::PAYLOAD_TOO_LARGE, Json(error)).into_response());
+                }
+                data.extend_from_slice(&chunk);
+            }
+            Ok(None) => return Ok(data),
+            Err(e) => {
+                let error = ErrorResponse {
+                    error: format!("Invalid multipart body: {}", e),
+                };
+                return Err((StatusCode::BAD_REQUEST, Json(error)).into_response());
+            }
+        }
+    }
+}
+
+/// POST /files/upload - Upload one or more files
+/// Every `file` part is stored; if any of them fails, the ones already stored are removed again.
+/// With `?extract=true`, zip archives are unpacked into their files.
 pub async fn upload_file(
     State(state): State<Arc<AppState>>,
     AuthUser(user): AuthUser,
+    Query(options): Query<UploadOptions>,
     mut multipart: Multipart,
 ) -> impl IntoResponse {
-    // Extract file from multipart form data
-    let mut file_data: Option<Vec<u8>> = None;
-    let mut file_name: Option<String> = None;
-    let mut mime_type: Option<String> = None;
+    let user_id = user.id.unwrap();
+
+    // Stream each file field straight into storage instead of buffering it
+    let mut stored = Vec::new();
+    let mut failure = None;
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                let error = ErrorResponse {
+                    error: format!("Invalid multipart body: {}", e),
+                };
+                failure = Some((StatusCode::BAD_REQUEST, Json(error)).into_response());
+                break;
+            }
+        };
 
-    while let Ok(Some(field)) = multipart.next_field().await {
-        let field_name = field.name().unwrap_or("").to_string();
+        if field.name() != Some("file") {
+            continue;
+        }
 
-        if field_name == "file" {
-            file_name = field.file_name().map(|s| s.to_string());
-            mime_type = field.content_type().map(|s| s.to_string());
+        let original_name = field.file_name().unwrap_or("unnamed").to_string();
+        let mime_type = field.content_type().map(|s| s.to_string());
 
-            match field.bytes().await {
-                Ok(bytes) => {
-                    file_data = Some(bytes.to_vec());
+        if options.extract && storage::archive::is_zip(&original_name, mime_type.as_deref()) {
+            // The central directory is at the end, so the archive is read whole before unpacking
+            let data = match read_archive_field(field).await {
+                Ok(data) => data,
+                Err(response) => {
+                    failure = Some(response);
+                    break;
+                }
+            };
+            match storage::archive::extract_zip(&state.storage_service, user_id, data).await {
+                Ok(files) => stored.extend(files),
+                Err(StorageError::PolicyViolation(violation)) => {
+                    failure = Some(policy_violation_response(violation));
+                    break;
                 }
                 Err(e) => {
+                    let status = storage_error_status(&e);
                     let error = ErrorResponse {
-                        error: format!("Failed to read file data: {}", e),
+                        error: format!("Failed to extract archive '{}': {}", original_name, e),
                     };
-                    return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+                    failure = Some((status, Json(error)).into_response());
+                    break;
                 }
             }
+            continue;
+        }
+
+        // Refuse what the policy rules out before reading any of the body
+        // Size and content are checked again once the file is stored
+        let policy = state.storage_service.upload_policy();
+        if let Err(violation) = policy.check_declared(&original_name, mime_type.as_deref(), None) {
+            failure = Some(policy_violation_response(violation));
+            break;
+        }
+
+        let reader = StreamReader::new(Box::pin(field.map_err(std::io::Error::other)));
+
+        match state
+            .storage_service
+            .store_stream_with_metadata(reader, &original_name, user_id, mime_type)
+            .await
+        {
+            Ok(file) => stored.push(file),
+            Err(StorageError::PolicyViolation(violation)) => {
+                failure = Some(policy_violation_response(violation));
+                break;
+            }
+            Err(e) => {
+                let status = storage_error_status(&e);
+                let error = ErrorResponse {
+                    error: format!("Failed to upload file '{}': {}", original_name, e),
+                };
+                failure = Some((status, Json(error)).into_response());
+                break;
+            }
         }
     }
 
-    // Validate we got file data
-    let data = match file_data {
-        Some(d) => d,
-        None => {
-            let error = ErrorResponse {
-                error: "No file provided in request".to_string(),
-            };
-            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    if let Some(response) = failure {
+        // All or nothing: undo the files this request already stored
+        for file in &stored {
+            if let Some(file_id) = &file.id {
+                if let Err(e) = state.storage_service.force_purge_file(file_id).await {
+                    eprintln!("⚠️  Failed to roll back upload of file {}: {}", file_id, e);
+                }
+            }
+        }
+        return response;
+    }
+
+    if stored.is_empty() {
+        let error = ErrorResponse {
+            error: "No file provided in request".to_string(),
+        };
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    }
+
+    for file in &stored {
+        if let Some(file_id) = &file.id {
+            let event = ActivityEvent::new(user_id, ActivityKind::FileUploaded, "files", file_id)
+                .with_data(serde_json::json!({
+                    "original_name": file.original_name,
+                    "size": file.size,
+                }));
+            state.activity_service.record_best_effort(event).await;
         }
+    }
+
+    // A single file keeps the original response shape; extracted archives always list their files
+    if stored.len() == 1 && !options.extract {
+        let response = UploadResponse {
+            success: true,
+            file: FileResponse::from(stored.remove(0)),
+        };
+        return (StatusCode::CREATED, Json(response)).into_response();
+    }
+
+    let response = BatchUploadResponse {
+        success: true,
+        files: stored.into_iter().map(FileResponse::from).collect(),
     };
+    (StatusCode::CREATED, Json(response)).into_response()
+}
 
-    let original_name = file_name.unwrap_or_else(|| "unnamed".to_string());
+/// POST /files/import - Store a file the server downloads from a remote URL
+/// The download is bounded by `[storage.url_import]`, and the file is then checked like an upload
+pub async fn import_file(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<ImportFileRequest>,
+) -> impl IntoResponse {
     let user_id = user.id.unwrap();
+    let Some(url_importer) = &state.url_importer else {
+        let error = ErrorResponse {
+            error: "Importing files from URLs is not enabled".to_string(),
+        };
+        return (StatusCode::NOT_FOUND, Json(error)).into_response();
+    };
+
+    let remote = match url_importer.fetch(&payload.url).await {
+        Ok(remote) => remote,
+        Err(StorageError::PolicyViolation(violation)) => return policy_violation_response(violation),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to import file: {}", e),
+            };
+            return (status, Json(error)).into_response();
+        }
+    };
+    let original_name = payload.name
+        .or(remote.file_name)
+        .unwrap_or_else(|| "unnamed".to_string());
 
-    // Store file with metadata
     match state
         .storage_service
-        .store_with_metadata(&data, &original_name, user_id, mime_type)
+        .store_with_metadata(&remote.data, &original_name, user_id, remote.mime_type)
         .await
     {
         Ok(file) => {
+            if let Some(file_id) = &file.id {
+                let event = ActivityEvent::new(user_id, ActivityKind::FileUploaded, "files", file_id)
+                    .with_data(serde_json::json!({
+                        "original_name": file.original_name,
+                        "size": file.size,
+                        "source_url": payload.url,
+                    }));
+                state.activity_service.record_best_effort(event).await;
+            }
             let response = UploadResponse {
                 success: true,
-                file: FileResponse {
-                    id: file.id.unwrap_or_default(),
-                    original_name: file.original_name,
-                    stored_name: file.stored_name,
-                    size: file.size,
-                    mime_type: file.mime_type,
-                    created_at: file.created_at.to_rfc3339(),
-                },
+                file: FileResponse::from(file),
             };
             (StatusCode::CREATED, Json(response)).into_response()
         }
+        Err(StorageError::PolicyViolation(violation)) => policy_violation_response(violation),
         Err(e) => {
+            let status = storage_error_status(&e);
             let error = ErrorResponse {
-                error: format!("Failed to upload file: {}", e),
+                error: format!("Failed to import file '{}': {}", original_name, e),
             };
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+            (status, Json(error)).into_response()
         }
     }
 }
 
 /// GET /files/:id - Download a file
+/// A single-range `Range` header is answered with `206 Partial Content`; other downloads stream
+/// the whole file. `If-None-Match` with the file's current ETag is answered with `304 Not Modified`.
 pub async fn download_file(
     State(state): State<Arc<AppState>>,
     Path(file_id): Path<String>,
     AuthUser(user): AuthUser,
+    request_headers: HeaderMap,
 ) -> impl IntoResponse {
     let user_id = user.id.unwrap();
 
-    // Get file metadata first to check permissions and get original name
-    let file = match state.storage_service.get_file_by_id(&file_id).await {
-        Ok(Some(f)) => f,
-        Ok(None) => {
+    // A client whose cached copy is current gets 304 without the download being counted
+    let if_none_match = request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok());
+    if let Some(if_none_match) = if_none_match {
+        match state.storage_service.get_file_with_permission(&file_id, user_id).await {
+            Ok(file) if file.matches_if_none_match(if_none_match) => {
+                let mut headers = HeaderMap::new();
+                if let Ok(header_value) = file.etag().parse() {
+                    headers.insert(header::ETAG, header_value);
+                }
+                return (StatusCode::NOT_MODIFIED, headers).into_response();
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let status = storage_error_status(&e);
+                let error = ErrorResponse {
+                    error: format!("Failed to download file: {}", e),
+                };
+                return (status, Json(error)).into_response();
+            }
+        }
+    }
+
+    let range = request_headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(ByteRange::parse);
+    if let Some(range) = range {
+        return download_range(&state, &file_id, user_id, range).await;
+    }
+
+    // One lookup checks permissions and provides the headers; the body streams from storage
+    match state
+        .storage_service
+        .retrieve_with_metadata(&file_id, user_id)
+        .await
+    {
+        Ok((file, reader)) => {
+            let mut headers = download_headers(&file);
+            headers.insert(header::CONTENT_LENGTH, file.size.into());
+
+            let body = download_body(&state, user_id, &file, reader);
+            (StatusCode::OK, headers, body).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
             let error = ErrorResponse {
-                error: "File not found".to_string(),
+                error: format!("Failed to download file: {}", e),
             };
-            return (StatusCode::NOT_FOUND, Json(error)).into_response();
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// Body streaming a file's data, paced against `user_id`'s download rate
+/// A large file found corrupted at the end of the stream is cut off and recorded
+pub(crate) fn download_body(state: &Arc<AppState>, user_id: i64, file: &storage::File, reader: storage::BlobReader) -> axum::body::Body {
+    let recorder = state.clone();
+    let file_id = file.id.clone();
+    let stream = tokio_util::io::ReaderStream::new(reader).inspect(move |chunk| {
+        if let (Err(e), Some(file_id)) = (chunk, &file_id) {
+            if e.kind() == std::io::ErrorKind::InvalidData {
+                let (recorder, file_id) = (recorder.clone(), file_id.clone());
+                tokio::spawn(async move { recorder.storage_service.record_corrupted(&file_id).await });
+            }
+        }
+    });
+    axum::body::Body::from_stream(state.download_throttle.throttle(user_id, stream))
+}
+
+/// Answer a `Range` request for part of a file
+async fn download_range(state: &AppState, file_id: &str, user_id: i64, range: ByteRange) -> axum::response::Response {
+    let result = async {
+        let file = state.storage_service.get_file_with_permission(file_id, user_id).await?;
+        let Some((offset, len)) = range.resolve(file.size) else {
+            return Ok((file, None));
+        };
+        let data = state.storage_service.retrieve_range_with_permission(file_id, user_id, offset, len).await?;
+        Ok::<_, StorageError>((file, Some((offset, data))))
+    }.await;
+
+    match result {
+        Ok((file, Some((offset, data)))) => {
+            let mut headers = download_headers(&file);
+            headers.insert(header::CONTENT_LENGTH, (data.len() as u64).into());
+            let last = (offset + data.len() as u64).saturating_sub(1);
+            let content_range = format!("bytes {}-{}/{}", offset, last, file.size);
+            if let Ok(header_value) = content_range.parse() {
+                headers.insert(header::CONTENT_RANGE, header_value);
+            }
+            let body = axum::body::Body::from_stream(state.download_throttle.throttle_bytes(user_id, data.into()));
+            (StatusCode::PARTIAL_CONTENT, headers, body).into_response()
+        }
+        Ok((file, None)) => {
+            let mut headers = HeaderMap::new();
+            if let Ok(header_value) = format!("bytes */{}", file.size).parse() {
+                headers.insert(header::CONTENT_RANGE, header_value);
+            }
+            let error = ErrorResponse {
+                error: format!("Range is outside the file's {} bytes", file.size),
+            };
+            (StatusCode::RANGE_NOT_SATISFIABLE, headers, Json(error)).into_response()
         }
         Err(e) => {
+            let status = storage_error_status(&e);
             let error = ErrorResponse {
-                error: format!("Failed to fetch file metadata: {}", e),
+                error: format!("Failed to download file: {}", e),
             };
-            return (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response();
+            (status, Json(error)).into_response()
         }
-    };
+    }
+}
 
-    // Check permission
-    if file.user_id != user_id {
-        let error = ErrorResponse {
-            error: "Access denied: file belongs to another user".to_string(),
-        };
-        return (StatusCode::FORBIDDEN, Json(error)).into_response();
+/// `Content-Disposition` value that downloads a file as `name`
+/// Clients that support RFC 5987 read the UTF-8 name from `filename*`; others get `filename`
+/// with anything that isn't printable ASCII, quotes, and backslashes replaced by `_`.
+pub(crate) fn attachment_disposition(name: &str) -> String {
+    let fallback: String = name
+        .chars()
+        .map(|c| if c == ' ' || (c.is_ascii_graphic() && c != '"' && c != '\\') { c } else { '_' })
+        .collect();
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
     }
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
 
-    // Retrieve file data
-    match state
+/// Content type, disposition, ETag, and range support headers of a download
+fn download_headers(file: &storage::File) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    // Set content type
+    if let Some(mime) = file.content_type() {
+        if let Ok(header_value) = mime.parse() {
+            headers.insert(header::CONTENT_TYPE, header_value);
+        }
+    }
+
+    // Set content disposition with original filename
+    let disposition = attachment_disposition(&file.original_name);
+    if let Ok(header_value) = disposition.parse() {
+        headers.insert(header::CONTENT_DISPOSITION, header_value);
+    }
+    headers.insert(header::ACCEPT_RANGES, header::HeaderValue::from_static("bytes"));
+    if let Ok(header_value) = file.etag().parse() {
+        headers.insert(header::ETAG, header_value);
+    }
+    // Caches may keep the file but must check the ETag before reusing it
+    headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static("private, no-cache"));
+    headers
+}
+
+/// POST /files/archive - Download several files as one zip archive
+/// The files are checked up front; the archive is then streamed while it is being built, so a
+/// blob that fails to read midway aborts the response instead of returning an error status.
+pub async fn download_archive(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<ArchiveRequest>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    let (file_ids, folder) = match (payload.file_ids, payload.folder) {
+        (Some(file_ids), None) => (file_ids, None),
+        (None, Some(folder)) => (Vec::new(), Some(folder)),
+        _ => {
+            let error = ErrorResponse {
+                error: "Send either file_ids or folder".to_string(),
+            };
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+    };
+
+    let entries = match state
         .storage_service
-        .retrieve_with_permission(&file_id, user_id)
+        .archive_entries(user_id, &file_ids, folder.as_deref())
         .await
     {
-        Ok(data) => {
-            let mut headers = axum::http::HeaderMap::new();
-            
-            // Set content type
-            if let Some(mime) = &file.mime_type {
-                if let Ok(header_value) = mime.parse() {
-                    headers.insert(header::CONTENT_TYPE, header_value);
-                }
-            }
-            
-            // Set content disposition with original filename
-            let disposition = format!("attachment; filename=\"{}\"", file.original_name);
-            if let Ok(header_value) = disposition.parse() {
-                headers.insert(header::CONTENT_DISPOSITION, header_value);
-            }
-
-            (StatusCode::OK, headers, data).into_response()
-        }
+        Ok(entries) => entries,
         Err(e) => {
+            let status = storage_error_status(&e);
             let error = ErrorResponse {
-                error: format!("Failed to download file: {}", e),
+                error: format!("Failed to create archive: {}", e),
             };
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+            return (status, Json(error)).into_response();
         }
+    };
+
+    let archive_name = folder
+        .as_deref()
+        .and_then(|folder| folder.rsplit('/').find(|segment| !segment.trim().is_empty()))
+        .unwrap_or("files");
+
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+    let task_state = state.clone();
+    let task = tokio::spawn(async move {
+        storage::archive::write_zip(&task_state.storage_service, user_id, &entries, writer).await
+    });
+    // Surface a failure after the last byte as a stream error, so the client sees a broken
+    // download rather than a truncated archive
+    let outcome = futures_util::stream::once(async move {
+        match task.await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(Err(std::io::Error::other(e.to_string()))),
+            Err(e) => Some(Err(std::io::Error::other(e.to_string()))),
+        }
+    })
+    .filter_map(std::future::ready);
+    let stream = tokio_util::io::ReaderStream::new(reader).chain(outcome);
+    let body = axum::body::Body::from_stream(state.download_throttle.throttle(user_id, stream));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/zip"));
+    let disposition = attachment_disposition(&format!("{}.zip", archive_name.trim()));
+    if let Ok(header_value) = disposition.parse() {
+        headers.insert(header::CONTENT_DISPOSITION, header_value);
     }
+
+    (StatusCode::OK, headers, body).into_response()
 }
 
-/// DELETE /files/:id - Delete a file
+/// DELETE /files/:id - Move a file to the trash
+/// Honors `If-Match` so concurrent editors get 412 instead of deleting a newer version.
+/// Deleting a file that is already gone returns 204 so retries are safe.
 pub async fn delete_file(
     State(state): State<Arc<AppState>>,
     Path(file_id): Path<String>,
     AuthUser(user): AuthUser,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let user_id = user.id.unwrap();
+    let if_match = headers
+        .get(header::IF_MATCH)
+        .and_then(|v| v.to_str().ok());
 
     match state
         .storage_service
-        .delete_with_metadata(&file_id, user_id)
+        .delete_with_metadata_if_match(&file_id, user_id, if_match)
         .await
     {
         Ok(_) => {
             let response = DeleteResponse {
                 success: true,
-                message: format!("File {} deleted successfully", file_id),
+                message: format!("File {} moved to trash", file_id),
             };
             (StatusCode::OK, Json(response)).into_response()
         }
+        Err(StorageError::FileNotFound(_)) => StatusCode::NO_CONTENT.into_response(),
+        Err(StorageError::PreconditionFailed(msg)) => {
+            let error = ErrorResponse { error: msg };
+            (StatusCode::PRECONDITION_FAILED, Json(error)).into_response()
+        }
         Err(e) => {
+            let status = storage_error_status(&e);
             let error = ErrorResponse {
                 error: format!("Failed to delete file: {}", e),
             };
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+            (status, Json(error)).into_response()
         }
     }
 }
 
-/// GET /files - List all files for the authenticated user
-pub async fn list_files(
+/// GET /files/trash - List the authenticated user's deleted files
+pub async fn list_trash(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.storage_service.list_trashed_files_page(user_id, page.limit(), offset).await {
+        Ok(results) => Page::from_offset(results.files, offset, page.limit(), results.total)
+            .map(FileResponse::from)
+            .into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to list trash: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /files/:id/restore - Move a file out of the trash
+pub async fn restore_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.storage_service.restore_file(&file_id, user_id).await {
+        Ok(file) => (StatusCode::OK, Json(FileResponse::from(file))).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to restore file: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// DELETE /files/trash/:id - Permanently delete a file in the trash
+pub async fn purge_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.storage_service.purge_file(&file_id, user_id).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to purge file: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// DELETE /files/trash - Permanently delete every file in the trash
+pub async fn empty_trash(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.storage_service.empty_trash(user_id).await {
+        Ok(purged) => {
+            let response = EmptyTrashResponse {
+                success: true,
+                purged,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to empty trash: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /files/:id/append - Append the request body to the end of a file, for log-style files
+pub async fn append_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    let if_match = headers
+        .get(header::IF_MATCH)
+        .and_then(|v| v.to_str().ok());
+
+    match state
+        .storage_service
+        .append_with_metadata(&file_id, user.id.unwrap(), &body, if_match)
+        .await
+    {
+        Ok(file) => (StatusCode::OK, Json(FileResponse::from(file))).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to append to file: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// PATCH /files/:id/content - Append to a file, or overwrite a byte range with `?offset=N`
+pub async fn patch_file_content(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<ContentPatchQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let if_match = headers
+        .get(header::IF_MATCH)
+        .and_then(|v| v.to_str().ok());
+
+    let result = match query.offset {
+        Some(offset) => {
+            state
+                .storage_service
+                .write_range_with_metadata(&file_id, user_id, offset, &body, if_match)
+                .await
+        }
+        None => {
+            state
+                .storage_service
+                .append_with_metadata(&file_id, user_id, &body, if_match)
+                .await
+        }
+    };
+
+    match result {
+        Ok(file) => (StatusCode::OK, Json(FileResponse::from(file))).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to update file content: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// PATCH /files/:id - Rename a file, change its MIME type, or move it to another folder
+pub async fn update_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    headers: HeaderMap,
+    Json(update): Json<MetadataUpdate>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let if_match = headers
+        .get(header::IF_MATCH)
+        .and_then(|v| v.to_str().ok());
+
+    match state.storage_service.update_metadata(&file_id, user_id, update, if_match).await {
+        Ok(file) => (StatusCode::OK, Json(FileResponse::from(file))).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to update file: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /files/:id/copy - Duplicate a file, optionally into another folder
+pub async fn copy_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    payload: Option<Json<CopyFileRequest>>,
+) -> impl IntoResponse {
+    let folder = payload.and_then(|Json(payload)| payload.folder);
+
+    match state
+        .storage_service
+        .copy_with_metadata(&file_id, user.id.unwrap(), folder.as_deref())
+        .await
+    {
+        Ok(file) => (StatusCode::CREATED, Json(FileResponse::from(file))).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to copy file: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /files/:id/transfer - Offer a file to another user, who becomes its owner on accepting
+pub async fn transfer_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<TransferRequest>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.auth_service.active_user_by_id(payload.user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            let error = ErrorResponse {
+                error: format!("User {} not found", payload.user_id),
+            };
+            return (StatusCode::NOT_FOUND, Json(error)).into_response();
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to look up user: {}", e),
+            };
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response();
+        }
+    }
+
+    match state.storage_service.request_transfer(&file_id, user_id, payload.user_id).await {
+        Ok(transfer) => (StatusCode::CREATED, Json(transfer)).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to transfer file: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /files/transfers - List the transfers the authenticated user offered or was offered
+pub async fn list_transfers(
     State(state): State<Arc<AppState>>,
     AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
 ) -> impl IntoResponse {
     let user_id = user.id.unwrap();
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
 
-    match state.storage_service.list_user_files(user_id).await {
-        Ok(files) => {
-            let file_responses: Vec<FileResponse> = files
-                .into_iter()
-                .map(|f| FileResponse {
-                    id: f.id.unwrap_or_default(),
-                    original_name: f.original_name,
-                    stored_name: f.stored_name,
-                    size: f.size,
-                    mime_type: f.mime_type,
-                    created_at: f.created_at.to_rfc3339(),
-                })
-                .collect();
-
-            (StatusCode::OK, Json(file_responses)).into_response()
+    match state.storage_service.list_transfers_page(user_id, page.limit(), offset).await {
+        Ok((transfers, total)) => Page::from_offset(transfers, offset, page.limit(), total).into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to list transfers: {}", e),
+            };
+            (status, Json(error)).into_response()
         }
+    }
+}
+
+/// POST /files/transfers/:id/accept - Become the owner of a file offered to the authenticated user
+pub async fn accept_transfer(
+    State(state): State<Arc<AppState>>,
+    Path(transfer_id): Path<i64>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.storage_service.accept_transfer(transfer_id, user.id.unwrap()).await {
+        Ok(file) => (StatusCode::OK, Json(FileResponse::from(file))).into_response(),
         Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to accept transfer: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// DELETE /files/transfers/:id - Withdraw a transfer, or decline one offered to the authenticated user
+pub async fn cancel_transfer(
+    State(state): State<Arc<AppState>>,
+    Path(transfer_id): Path<i64>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.storage_service.cancel_transfer(transfer_id, user.id.unwrap()).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to cancel transfer: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /files?view=owned|shared&sort=&order=&mime_type=&folder= - List the authenticated user's
+/// files, or the files shared with them, a page at a time
+pub async fn list_files(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+    Query(query): Query<ListFilesQuery>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+    let options = match query.options(page.limit(), offset) {
+        Ok(options) => options,
+        Err(error) => return (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response(),
+    };
+
+    let results = match query.view {
+        FileListView::Owned => state.storage_service.list_user_files_page(user_id, &options).await,
+        FileListView::Shared => state.storage_service.files_shared_with_page(user_id, &options).await,
+    };
+    match results {
+        Ok(results) => Page::from_offset(results.files, offset, page.limit(), results.total)
+            .map(FileResponse::from)
+            .into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
             let error = ErrorResponse {
                 error: format!("Failed to list files: {}", e),
             };
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+            (status, Json(error)).into_response()
         }
     }
 }
@@ -253,10 +1113,262 @@ pub async fn get_storage_stats(
     {
         Ok(stats) => (StatusCode::OK, Json(stats)).into_response(),
         Err(e) => {
+            let status = storage_error_status(&e);
             let error = ErrorResponse {
                 error: format!("Failed to get storage stats: {}", e),
             };
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+            (status, Json(error)).into_response()
         }
     }
-}
\ No newline at end of file
+}
+
+/// GET /files/export?format=csv|json - Download the authenticated user's whole file inventory
+/// The export is streamed while it is read from the database, like an archive download
+pub async fn export_files(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(params): Query<ExportQuery>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let format = match params.format.as_deref().map(ExportFormat::from_str) {
+        None => ExportFormat::default(),
+        Some(Some(format)) => format,
+        Some(None) => {
+            let error = ErrorResponse {
+                error: "format must be csv or json".to_string(),
+            };
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+    };
+
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+    let task_state = state.clone();
+    let task = tokio::spawn(async move {
+        task_state.storage_service.export_files(user_id, format, writer).await
+    });
+    let outcome = futures_util::stream::once(async move {
+        match task.await {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => Some(Err(std::io::Error::other(e.to_string()))),
+            Err(e) => Some(Err(std::io::Error::other(e.to_string()))),
+        }
+    })
+    .filter_map(std::future::ready);
+    let stream = tokio_util::io::ReaderStream::new(reader).chain(outcome);
+    let body = axum::body::Body::from_stream(state.download_throttle.throttle(user_id, stream));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static(format.content_type()));
+    let disposition = format!("attachment; filename=\"files.{}\"", format.extension());
+    if let Ok(header_value) = disposition.parse() {
+        headers.insert(header::CONTENT_DISPOSITION, header_value);
+    }
+
+    (StatusCode::OK, headers, body).into_response()
+}
+
+/// GET /files/search?q= - Search the authenticated user's files by name, MIME type, and extracted text
+pub async fn search_files(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(params): Query<SearchQuery>,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let limit = page.limit_or(DEFAULT_SEARCH_LIMIT);
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state
+        .storage_service
+        .search_user_files(user_id, &params.q, limit, offset)
+        .await
+    {
+        Ok(results) => Page::from_offset(results.files, offset, limit, results.total)
+            .map(FileResponse::from)
+            .into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to search files: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /files/:id/favorite - Mark a file as a favorite
+pub async fn favorite_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.storage_service.favorite_file(&file_id, user_id).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to favorite file: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// DELETE /files/:id/favorite - Remove a file from favorites
+pub async fn unfavorite_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.storage_service.unfavorite_file(&file_id, user_id).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to unfavorite file: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /files/favorites - List the authenticated user's favorite files
+pub async fn list_favorites(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.storage_service.list_favorite_files_page(user_id, page.limit(), offset).await {
+        Ok(results) => Page::from_offset(results.files, offset, page.limit(), results.total)
+            .map(FileResponse::from)
+            .into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to list favorite files: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /files/recent - List the files the authenticated user accessed most recently
+/// Only the `MAX_PAGE_LIMIT` most recent files are kept, so the pages never go further back
+pub async fn list_recent(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.storage_service.list_recent_files(user_id, MAX_PAGE_LIMIT).await {
+        Ok(files) => Page::from_vec(files, offset, page.limit_or(DEFAULT_SEARCH_LIMIT))
+            .map(FileResponse::from)
+            .into_response(),
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to list recent files: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// POST /files/:id/token - Issue a single-use download token (service role)
+/// The token can be handed to a third-party processor, which downloads the file once
+/// from `GET /download/:token` without any credentials
+pub async fn create_download_token(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    payload: Option<Json<DownloadTokenRequest>>,
+) -> impl IntoResponse {
+    let ttl_seconds = payload
+        .and_then(|Json(payload)| payload.ttl_seconds)
+        .unwrap_or(DEFAULT_DOWNLOAD_TOKEN_TTL_SECONDS);
+
+    if !(1..=MAX_DOWNLOAD_TOKEN_TTL_SECONDS).contains(&ttl_seconds) {
+        let error = ErrorResponse {
+            error: format!("ttl_seconds must be between 1 and {}", MAX_DOWNLOAD_TOKEN_TTL_SECONDS),
+        };
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    }
+
+    match state
+        .storage_service
+        .issue_download_token(&file_id, user.id.unwrap(), chrono::Duration::seconds(ttl_seconds))
+        .await
+    {
+        Ok(token) => {
+            let response = DownloadTokenResponse {
+                url: state.storage_service.public_url(&format!("/download/{}", token.token)),
+                token: token.token,
+                file_id: token.file_id,
+                expires_at: token.expires_at.to_rfc3339(),
+            };
+            (StatusCode::CREATED, Json(response)).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to issue download token: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /download/:token - Download a file with a single-use token (no authentication)
+pub async fn redeem_download_token(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+) -> impl IntoResponse {
+    match state.storage_service.redeem_download_token(&token).await {
+        Ok((file, reader)) => {
+            let mut headers = axum::http::HeaderMap::new();
+
+            if let Some(mime) = file.content_type() {
+                if let Ok(header_value) = mime.parse() {
+                    headers.insert(header::CONTENT_TYPE, header_value);
+                }
+            }
+
+            let disposition = attachment_disposition(&file.original_name);
+            if let Ok(header_value) = disposition.parse() {
+                headers.insert(header::CONTENT_DISPOSITION, header_value);
+            }
+
+            headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static("private, no-store"));
+            headers.insert(header::CONTENT_LENGTH, file.size.into());
+
+            // Streamed, and paced against the owner's download rate like their own downloads
+            let body = download_body(&state, file.user_id, &file, reader);
+            (StatusCode::OK, headers, body).into_response()
+        }
+        Err(e) => {
+            let status = storage_error_status(&e);
+            let error = ErrorResponse {
+                error: format!("Failed to download file: {}", e),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+}
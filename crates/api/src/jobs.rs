@@ -0,0 +1,563 @@
+//! Background job scheduler
+//!
+//! Maintenance work such as journal recovery, integrity audits, and webhook retries is queued
+//! here and run by one dispatcher instead of each task looping on its own. Every job type has a
+//! concurrency limit, a priority, and a timeout: the highest-priority queued job whose type has
+//! a free slot starts first, and a run outlasting its timeout is cancelled and counts as failed.
+//! Types with a reserved slot run outside the shared pool, so they never wait behind long jobs.
+//! Failed jobs are retried with exponential backoff until `max_attempts` runs in a row have
+//! failed, then dead-lettered and kept for an admin to inspect, retry, or cancel.
+//!
+//! The queue is in memory, so each instance schedules and runs its own jobs.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use core::{JobTypeConfig, JobsConfig};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::metrics::Metrics;
+
+/// Dead-lettered jobs kept for inspection; the oldest are dropped first
+pub const MAX_DEAD_LETTERS: usize = 100;
+
+/// Failed runs in a row before a job is dead-lettered, unless its type says otherwise
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Longest wait between retries of a failed job
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+/// How runs of one job type are scheduled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobLimits {
+    /// Runs of the type at once
+    pub concurrency: usize,
+    /// Queued jobs with a higher priority start first
+    pub priority: i32,
+    /// Runs taking longer are cancelled and count as failed
+    pub timeout: Duration,
+    /// Failed runs in a row before the job is dead-lettered
+    pub max_attempts: u32,
+    /// Runs in a slot of its own outside `max_concurrency`, so long jobs filling the pool can't
+    /// hold it up
+    pub reserved: bool,
+}
+
+impl JobLimits {
+    /// One run at a time, dead-lettered after [`DEFAULT_MAX_ATTEMPTS`] failures
+    pub fn new(priority: i32, timeout: Duration) -> Self {
+        Self {
+            concurrency: 1,
+            priority,
+            timeout,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            reserved: false,
+        }
+    }
+
+    /// Run outside the shared pool, for jobs that must not wait behind others
+    pub fn with_reserved_slot(mut self) -> Self {
+        self.reserved = true;
+        self
+    }
+
+    /// Replace the limits `config` sets
+    pub fn with_overrides(mut self, config: &JobTypeConfig) -> Self {
+        if let Some(concurrency) = config.concurrency {
+            self.concurrency = concurrency.max(1);
+        }
+        if let Some(priority) = config.priority {
+            self.priority = priority;
+        }
+        if let Some(timeout_seconds) = config.timeout_seconds {
+            self.timeout = Duration::from_secs(timeout_seconds.max(1));
+        }
+        if let Some(max_attempts) = config.max_attempts {
+            self.max_attempts = max_attempts.max(1);
+        }
+        self
+    }
+}
+
+/// Where a job is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for a free slot, or for its retry backoff to pass
+    Queued,
+    Running,
+    /// Failed too many times in a row; only runs again when retried
+    DeadLettered,
+}
+
+/// One queued, running, or dead-lettered run of a job type
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: u64,
+    pub job_type: &'static str,
+    pub priority: i32,
+    pub state: JobState,
+    /// Runs that failed in a row
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub enqueued_at: DateTime<Utc>,
+    /// Earliest time a queued job may start
+    pub run_after: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+}
+
+/// A job type with its limits and how much of it is in the queue
+#[derive(Debug, Clone, Serialize)]
+pub struct JobTypeStatus {
+    pub name: &'static str,
+    pub concurrency: usize,
+    pub priority: i32,
+    pub timeout_seconds: u64,
+    pub max_attempts: u32,
+    pub queued: usize,
+    pub running: usize,
+    pub dead_lettered: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobError {
+    UnknownType(String),
+    NotFound(u64),
+    /// The job is running and can't be changed until it finishes
+    Running(u64),
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::UnknownType(name) => write!(f, "no job type named {}", name),
+            JobError::NotFound(id) => write!(f, "job {} not found", id),
+            JobError::Running(id) => write!(f, "job {} is running", id),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+type JobHandler = Arc<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+struct JobType {
+    limits: JobLimits,
+    handler: JobHandler,
+    running: usize,
+}
+
+#[derive(Default)]
+struct Queue {
+    next_id: u64,
+    types: BTreeMap<&'static str, JobType>,
+    jobs: BTreeMap<u64, Job>,
+    running: usize,
+}
+
+impl Queue {
+    /// The queued job to start next, if a slot is free for it
+    /// With the shared pool full, only job types with a reserved slot can start.
+    fn next_runnable(&self, now: DateTime<Utc>, pool_full: bool) -> Option<u64> {
+        self.jobs
+            .values()
+            .filter(|job| job.state == JobState::Queued && job.run_after <= now)
+            .filter(|job| {
+                self.types.get(job.job_type).is_some_and(|job_type| {
+                    job_type.running < job_type.limits.concurrency && (job_type.limits.reserved || !pool_full)
+                })
+            })
+            .max_by_key(|job| (job.priority, Reverse(job.id)))
+            .map(|job| job.id)
+    }
+
+    /// Drop the oldest dead-lettered jobs beyond [`MAX_DEAD_LETTERS`]
+    fn trim_dead_letters(&mut self) {
+        let dead: Vec<u64> = self.jobs
+            .values()
+            .filter(|job| job.state == JobState::DeadLettered)
+            .map(|job| job.id)
+            .collect();
+        for id in dead.iter().take(dead.len().saturating_sub(MAX_DEAD_LETTERS)) {
+            self.jobs.remove(id);
+        }
+    }
+}
+
+/// Queues background jobs and runs them within their limits
+pub struct JobScheduler {
+    queue: Mutex<Queue>,
+    wake: Notify,
+    metrics: Arc<Metrics>,
+    max_concurrency: usize,
+    retry_base: Duration,
+    overrides: HashMap<String, JobTypeConfig>,
+}
+
+impl JobScheduler {
+    /// Runs are reported to `metrics`
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self::with_config(metrics, &JobsConfig::default())
+    }
+
+    /// Apply `config`'s overall limits, and its overrides to job types registered later
+    pub fn with_config(metrics: Arc<Metrics>, config: &JobsConfig) -> Self {
+        Self {
+            queue: Mutex::new(Queue::default()),
+            wake: Notify::new(),
+            metrics,
+            max_concurrency: config.max_concurrency.max(1),
+            retry_base: Duration::from_secs(config.retry_base_seconds),
+            overrides: config.types.clone(),
+        }
+    }
+
+    /// Add a job type, run by calling `handler`
+    /// `limits` are the built-in ones; `[jobs.types.<name>]` overrides them.
+    pub fn register<F, Fut>(&self, name: &'static str, limits: JobLimits, handler: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let limits = match self.overrides.get(name) {
+            Some(config) => limits.with_overrides(config),
+            None => limits,
+        };
+        let handler: JobHandler = Arc::new(move || Box::pin(handler()));
+        self.queue.lock().unwrap().types.insert(name, JobType { limits, handler, running: 0 });
+    }
+
+    /// Job types named in `[jobs.types]` that were never registered
+    pub fn unknown_overrides(&self) -> Vec<String> {
+        let queue = self.queue.lock().unwrap();
+        let mut names: Vec<String> = self.overrides
+            .keys()
+            .filter(|name| !queue.types.contains_key(name.as_str()))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Queue a run of `name`
+    /// A job of the type that is already queued is returned instead, so a slow or failing
+    /// job doesn't pile up runs behind it.
+    pub fn enqueue(&self, name: &str) -> Result<Job, JobError> {
+        let mut queue = self.queue.lock().unwrap();
+        let (&job_type, entry) = queue.types
+            .get_key_value(name)
+            .ok_or_else(|| JobError::UnknownType(name.to_string()))?;
+        let priority = entry.limits.priority;
+
+        if let Some(job) = queue.jobs.values().find(|job| job.job_type == job_type && job.state == JobState::Queued) {
+            return Ok(job.clone());
+        }
+
+        queue.next_id += 1;
+        let now = Utc::now();
+        let job = Job {
+            id: queue.next_id,
+            job_type,
+            priority,
+            state: JobState::Queued,
+            attempts: 0,
+            last_error: None,
+            enqueued_at: now,
+            run_after: now,
+            started_at: None,
+        };
+        queue.jobs.insert(job.id, job.clone());
+        drop(queue);
+
+        self.wake.notify_one();
+        Ok(job)
+    }
+
+    /// Every job, oldest first, optionally only those in `state`
+    pub fn jobs(&self, state: Option<JobState>) -> Vec<Job> {
+        self.queue
+            .lock()
+            .unwrap()
+            .jobs
+            .values()
+            .filter(|job| state.is_none_or(|state| job.state == state))
+            .cloned()
+            .collect()
+    }
+
+    /// Every registered job type with its limits and queue
+    pub fn job_types(&self) -> Vec<JobTypeStatus> {
+        let queue = self.queue.lock().unwrap();
+        let count = |name: &str, state: JobState| {
+            queue.jobs.values().filter(|job| job.job_type == name && job.state == state).count()
+        };
+        queue.types
+            .iter()
+            .map(|(&name, job_type)| JobTypeStatus {
+                name,
+                concurrency: job_type.limits.concurrency,
+                priority: job_type.limits.priority,
+                timeout_seconds: job_type.limits.timeout.as_secs(),
+                max_attempts: job_type.limits.max_attempts,
+                queued: count(name, JobState::Queued),
+                running: job_type.running,
+                dead_lettered: count(name, JobState::DeadLettered),
+            })
+            .collect()
+    }
+
+    /// Run a job as soon as a slot is free
+    /// A dead-lettered job is queued again with a fresh set of attempts; a queued one skips
+    /// the rest of its backoff.
+    pub fn retry(&self, id: u64) -> Result<Job, JobError> {
+        let mut queue = self.queue.lock().unwrap();
+        let job = queue.jobs.get_mut(&id).ok_or(JobError::NotFound(id))?;
+        match job.state {
+            JobState::Running => return Err(JobError::Running(id)),
+            JobState::DeadLettered => {
+                job.state = JobState::Queued;
+                job.attempts = 0;
+            }
+            JobState::Queued => {}
+        }
+        job.run_after = Utc::now();
+        let job = job.clone();
+        drop(queue);
+
+        self.wake.notify_one();
+        Ok(job)
+    }
+
+    /// Remove a queued or dead-lettered job; running jobs can't be cancelled
+    pub fn cancel(&self, id: u64) -> Result<Job, JobError> {
+        let mut queue = self.queue.lock().unwrap();
+        let state = queue.jobs.get(&id).map(|job| job.state).ok_or(JobError::NotFound(id))?;
+        if state == JobState::Running {
+            return Err(JobError::Running(id));
+        }
+        Ok(queue.jobs.remove(&id).unwrap())
+    }
+
+    /// Start dispatching queued jobs in the background
+    pub fn start(self: &Arc<Self>) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let wait = scheduler.dispatch();
+                tokio::select! {
+                    _ = scheduler.wake.notified() => {}
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+        });
+    }
+
+    /// Start every job a slot is free for, and return how long until a backoff passes
+    fn dispatch(self: &Arc<Self>) -> Duration {
+        let now = Utc::now();
+        let mut guard = self.queue.lock().unwrap();
+        let queue = &mut *guard;
+
+        loop {
+            let pool_full = queue.running >= self.max_concurrency;
+            let Some(id) = queue.next_runnable(now, pool_full) else {
+                break;
+            };
+            let job = queue.jobs.get_mut(&id).unwrap();
+            job.state = JobState::Running;
+            job.started_at = Some(now);
+            let job_type = queue.types.get_mut(job.job_type).unwrap();
+            job_type.running += 1;
+            let handler = job_type.handler.clone();
+            let timeout = job_type.limits.timeout;
+            if !job_type.limits.reserved {
+                queue.running += 1;
+            }
+
+            let scheduler = self.clone();
+            tokio::spawn(async move {
+                // The run gets its own task, so a panic fails the job instead of leaking its slot
+                let mut run = tokio::spawn(handler());
+                let result = match tokio::time::timeout(timeout, &mut run).await {
+                    Ok(Ok(result)) => result,
+                    Ok(Err(e)) => Err(format!("job panicked: {}", e)),
+                    Err(_) => {
+                        run.abort();
+                        Err(format!("timed out after {:?}", timeout))
+                    }
+                };
+                scheduler.finish(id, result);
+            });
+        }
+
+        queue.jobs
+            .values()
+            .filter(|job| job.state == JobState::Queued && job.run_after > now)
+            .map(|job| (job.run_after - now).to_std().unwrap_or_default())
+            .min()
+            .unwrap_or(Duration::from_secs(60))
+    }
+
+    /// Record the outcome of a run, and retry or dead-letter it if it failed
+    fn finish(&self, id: u64, result: Result<(), String>) {
+        let mut guard = self.queue.lock().unwrap();
+        let queue = &mut *guard;
+        let Some(job) = queue.jobs.get(&id) else {
+            return;
+        };
+        let job_type = job.job_type;
+        let entry = queue.types.get_mut(job_type).unwrap();
+        entry.running -= 1;
+        if !entry.limits.reserved {
+            queue.running -= 1;
+        }
+        let max_attempts = entry.limits.max_attempts;
+        self.metrics.record_job_run(job_type, result.is_ok());
+
+        match result {
+            Ok(()) => {
+                queue.jobs.remove(&id);
+            }
+            Err(error) => {
+                let job = queue.jobs.get_mut(&id).unwrap();
+                job.attempts += 1;
+                job.last_error = Some(error);
+                job.started_at = None;
+                if job.attempts >= max_attempts {
+                    job.state = JobState::DeadLettered;
+                    self.metrics.record_job_dead_letter(job_type);
+                    queue.trim_dead_letters();
+                } else {
+                    let delay = self.retry_base
+                        .saturating_mul(2u32.saturating_pow(job.attempts - 1))
+                        .min(MAX_RETRY_DELAY);
+                    job.state = JobState::Queued;
+                    job.run_after = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+                }
+            }
+        }
+        drop(guard);
+
+        self.wake.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn scheduler(max_concurrency: usize) -> Arc<JobScheduler> {
+        let config = JobsConfig {
+            max_concurrency,
+            retry_base_seconds: 0,
+            ..JobsConfig::default()
+        };
+        Arc::new(JobScheduler::with_config(Arc::new(Metrics::default()), &config))
+    }
+
+    async fn settle(scheduler: &JobScheduler) {
+        for _ in 0..100 {
+            if scheduler.jobs(None).iter().all(|job| job.state == JobState::DeadLettered) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("jobs never settled: {:?}", scheduler.jobs(None));
+    }
+
+    #[tokio::test]
+    async fn test_priority_and_coalescing() {
+        let scheduler = scheduler(1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for (name, priority) in [("low", -1), ("high", 10)] {
+            let order = order.clone();
+            scheduler.register(name, JobLimits::new(priority, Duration::from_secs(5)), move || {
+                let order = order.clone();
+                async move {
+                    order.lock().unwrap().push(name);
+                    Ok(())
+                }
+            });
+        }
+
+        let low = scheduler.enqueue("low").unwrap();
+        assert_eq!(scheduler.enqueue("low").unwrap().id, low.id);
+        scheduler.enqueue("high").unwrap();
+        assert_eq!(scheduler.enqueue("missing").unwrap_err(), JobError::UnknownType("missing".to_string()));
+
+        scheduler.start();
+        settle(&scheduler).await;
+        assert_eq!(*order.lock().unwrap(), vec!["high", "low"]);
+    }
+
+    #[tokio::test]
+    async fn test_reserved_slot_runs_while_pool_is_full() {
+        let scheduler = scheduler(1);
+        scheduler.register("slow", JobLimits::new(0, Duration::from_secs(5)), || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        });
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        scheduler.register("keys", JobLimits::new(0, Duration::from_secs(5)).with_reserved_slot(), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }
+        });
+        scheduler.start();
+
+        scheduler.enqueue("slow").unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        scheduler.enqueue("keys").unwrap();
+        for _ in 0..100 {
+            if runs.load(Ordering::SeqCst) == 1 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("reserved job never ran: {:?}", scheduler.jobs(None));
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_are_dead_lettered_and_retried() {
+        let scheduler = scheduler(4);
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        let limits = JobLimits::new(0, Duration::from_millis(20)).with_overrides(&JobTypeConfig {
+            max_attempts: Some(2),
+            ..JobTypeConfig::default()
+        });
+        scheduler.register("slow", limits, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            }
+        });
+        scheduler.start();
+
+        let job = scheduler.enqueue("slow").unwrap();
+        settle(&scheduler).await;
+        let dead = scheduler.jobs(Some(JobState::DeadLettered));
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].attempts, 2);
+        assert_eq!(dead[0].last_error.as_deref(), Some("timed out after 20ms"));
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(scheduler.job_types()[0].dead_lettered, 1);
+
+        assert_eq!(scheduler.retry(job.id).unwrap().state, JobState::Queued);
+        settle(&scheduler).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+
+        assert_eq!(scheduler.cancel(job.id).unwrap().id, job.id);
+        assert_eq!(scheduler.cancel(job.id).unwrap_err(), JobError::NotFound(job.id));
+        assert!(scheduler.jobs(None).is_empty());
+    }
+}
@@ -1,8 +1,29 @@
 pub mod router;
 pub mod state;
+pub mod activity_handlers;
+pub mod admin_handlers;
+pub mod announcement_handlers;
+pub mod asset_handlers;
 pub mod auth_handlers;
+pub mod billing_handlers;
+pub mod bucket_handlers;
+pub mod cluster;
+pub mod comment_handlers;
 pub mod db_handlers;
+pub mod deprecation;
 pub mod file_handlers;
+pub mod jobs;
+pub mod login_limit;
+pub mod metrics;
 pub mod middleware;
+pub mod organization_handlers;
+pub mod pagination;
+pub mod range;
+pub mod rate_limit;
+pub mod row_policies;
+pub mod share_handlers;
+pub mod sql_handlers;
+pub mod throttle;
+pub mod upload_handlers;
 
 pub use state::AppState;
\ No newline at end of file
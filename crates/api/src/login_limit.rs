@@ -0,0 +1,361 @@
+//! Throttling of logins and signups
+//!
+//! Token buckets hold attempts: an attempt takes a token, and tokens come back at a steady rate
+//! up to the bucket's size. Each client IP address has a bucket that every attempt draws from,
+//! which slows down one client trying many accounts. Each email address has a bucket per IP
+//! address that only failed attempts draw from and a success empties, so guessing one account's
+//! password is slow while nobody else can lock its owner out. Failed attempts also draw from a
+//! larger bucket of the address shared by every client, which slows down guessing from many IP
+//! addresses at once without running out before a single client would. Requests to email an address a
+//! link, such as a password reset, also draw from a bucket of the address shared by every
+//! client, so nobody can flood one inbox. Buckets are kept in memory, so each server instance
+//! throttles its own share of the traffic.
+
+use core::LoginRateLimitConfig;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Buckets kept per key type; the oldest are dropped beyond this
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// Size and refill rate of a token bucket
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BucketLimit {
+    capacity: f64,
+    per_second: f64,
+}
+
+impl BucketLimit {
+    /// `None` when either setting is 0, which leaves attempts unlimited
+    fn new(burst: u32, per_minute: u32) -> Option<Self> {
+        if burst == 0 || per_minute == 0 {
+            return None;
+        }
+        Some(Self {
+            capacity: burst as f64,
+            per_second: per_minute as f64 / 60.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets of one kind of key
+struct Buckets<K> {
+    limit: Option<BucketLimit>,
+    buckets: HashMap<K, Bucket>,
+}
+
+impl<K: Eq + Hash + Clone> Buckets<K> {
+    fn new(limit: Option<BucketLimit>) -> Self {
+        Self {
+            limit,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Tokens in `key`'s bucket at `now`
+    fn tokens(&self, key: &K, now: Instant) -> f64 {
+        let Some(limit) = self.limit else {
+            return f64::INFINITY;
+        };
+        match self.buckets.get(key) {
+            Some(bucket) => {
+                let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+                (bucket.tokens + elapsed * limit.per_second).min(limit.capacity)
+            }
+            None => limit.capacity,
+        }
+    }
+
+    /// Seconds until `key`'s bucket holds a whole token again
+    fn wait(&self, key: &K, now: Instant) -> u64 {
+        let Some(limit) = self.limit else {
+            return 0;
+        };
+        ((1.0 - self.tokens(key, now)) / limit.per_second).ceil().max(1.0) as u64
+    }
+
+    fn take(&mut self, key: K, now: Instant) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        let tokens = self.tokens(&key, now) - 1.0;
+        if self.buckets.len() >= MAX_TRACKED_KEYS && !self.buckets.contains_key(&key) {
+            self.prune(limit, now);
+        }
+        self.buckets.insert(key, Bucket { tokens, updated: now });
+    }
+
+    /// Refill `key`'s bucket
+    fn reset(&mut self, key: &K) {
+        self.buckets.remove(key);
+    }
+
+    /// Make room for new buckets: drop those that have refilled, since a full bucket is the
+    /// same as no bucket, then the least recently used down to three quarters of the maximum,
+    /// so pruning runs once per many new keys rather than on every one
+    fn prune(&mut self, limit: BucketLimit, now: Instant) {
+        self.buckets.retain(|_, bucket| {
+            bucket.tokens + now.saturating_duration_since(bucket.updated).as_secs_f64() * limit.per_second < limit.capacity
+        });
+        let keep = MAX_TRACKED_KEYS * 3 / 4;
+        if self.buckets.len() <= keep {
+            return;
+        }
+        let mut by_age: Vec<(Instant, K)> = self.buckets.iter().map(|(key, bucket)| (bucket.updated, key.clone())).collect();
+        by_age.sort_unstable_by_key(|(updated, _)| *updated);
+        let excess = self.buckets.len() - keep;
+        for (_, key) in by_age.into_iter().take(excess) {
+            self.buckets.remove(&key);
+        }
+    }
+}
+
+/// Key of the bucket of failed attempts on an account from one client
+type AccountKey = (String, Option<IpAddr>);
+
+/// Addresses differing only in case or surrounding spaces belong to the same account
+fn account_key(ip: Option<IpAddr>, email: &str) -> AccountKey {
+    (email.trim().to_lowercase(), ip)
+}
+
+/// Limits login and signup attempts per IP address, and failed logins per email address
+pub struct LoginLimiter {
+    ips: Mutex<Buckets<IpAddr>>,
+    emails: Mutex<Buckets<AccountKey>>,
+    accounts: Mutex<Buckets<String>>,
+    links: Mutex<Buckets<String>>,
+}
+
+impl Default for LoginLimiter {
+    /// A limiter that lets every attempt through
+    fn default() -> Self {
+        Self {
+            ips: Mutex::new(Buckets::new(None)),
+            emails: Mutex::new(Buckets::new(None)),
+            accounts: Mutex::new(Buckets::new(None)),
+            links: Mutex::new(Buckets::new(None)),
+        }
+    }
+}
+
+impl LoginLimiter {
+    pub fn new(config: &LoginRateLimitConfig) -> Self {
+        if !config.enabled {
+            return Self::default();
+        }
+        Self {
+            ips: Mutex::new(Buckets::new(BucketLimit::new(config.ip_burst, config.ip_per_minute))),
+            emails: Mutex::new(Buckets::new(BucketLimit::new(config.email_burst, config.email_per_minute))),
+            accounts: Mutex::new(Buckets::new(BucketLimit::new(config.account_burst, config.account_per_minute))),
+            links: Mutex::new(Buckets::new(BucketLimit::new(config.email_burst, config.email_per_minute))),
+        }
+    }
+
+    /// Count a request from `ip` to email `email` a link, such as a password reset
+    /// Every request draws from the IP bucket and from the address's own bucket, whether or not
+    /// an account has the address, so the answer doesn't tell which addresses are registered.
+    ///
+    /// # Returns
+    /// The seconds until another request can be made when either bucket is empty
+    pub fn check_email_link(&self, ip: Option<IpAddr>, email: &str, now: Instant) -> Result<(), u64> {
+        let key = email.trim().to_lowercase();
+        let mut ips = self.ips.lock().unwrap();
+        let mut links = self.links.lock().unwrap();
+
+        let mut wait = 0;
+        if let Some(ip) = ip {
+            if ips.tokens(&ip, now) < 1.0 {
+                wait = wait.max(ips.wait(&ip, now));
+            }
+        }
+        if links.tokens(&key, now) < 1.0 {
+            wait = wait.max(links.wait(&key, now));
+        }
+        if wait > 0 {
+            return Err(wait);
+        }
+
+        if let Some(ip) = ip {
+            ips.take(ip, now);
+        }
+        links.take(key, now);
+        Ok(())
+    }
+
+    /// Count an attempt from `ip` on `email` made at `now`, before the password is checked
+    /// Only the IP bucket is drawn from; report the outcome with [`Self::record_failure`] or
+    /// [`Self::record_success`]. Refused attempts cost nothing.
+    ///
+    /// # Returns
+    /// The seconds until another attempt can be made when any bucket is empty
+    pub fn check(&self, ip: Option<IpAddr>, email: &str, now: Instant) -> Result<(), u64> {
+        let key = account_key(ip, email);
+        let mut ips = self.ips.lock().unwrap();
+        let emails = self.emails.lock().unwrap();
+        let accounts = self.accounts.lock().unwrap();
+
+        let mut wait = 0;
+        if let Some(ip) = ip {
+            if ips.tokens(&ip, now) < 1.0 {
+                wait = wait.max(ips.wait(&ip, now));
+            }
+        }
+        if emails.tokens(&key, now) < 1.0 {
+            wait = wait.max(emails.wait(&key, now));
+        }
+        if accounts.tokens(&key.0, now) < 1.0 {
+            wait = wait.max(accounts.wait(&key.0, now));
+        }
+        if wait > 0 {
+            return Err(wait);
+        }
+
+        if let Some(ip) = ip {
+            ips.take(ip, now);
+        }
+        Ok(())
+    }
+
+    /// Count a wrong password from `ip` on `email` against the account's bucket for that client,
+    /// and its bucket shared by every client
+    pub fn record_failure(&self, ip: Option<IpAddr>, email: &str, now: Instant) {
+        let key = account_key(ip, email);
+        self.accounts.lock().unwrap().take(key.0.clone(), now);
+        self.emails.lock().unwrap().take(key, now);
+    }
+
+    /// Forget the failed attempts on `email` after the right password was given from `ip`
+    pub fn record_success(&self, ip: Option<IpAddr>, email: &str) {
+        let key = account_key(ip, email);
+        self.accounts.lock().unwrap().reset(&key.0);
+        self.emails.lock().unwrap().reset(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn config(ip_burst: u32, email_burst: u32) -> LoginRateLimitConfig {
+        LoginRateLimitConfig {
+            enabled: true,
+            ip_burst,
+            ip_per_minute: 6,
+            email_burst,
+            email_per_minute: 6,
+            account_burst: 20,
+            account_per_minute: 6,
+        }
+    }
+
+    #[test]
+    fn test_login_buckets() {
+        let limiter = LoginLimiter::new(&config(10, 2));
+        let now = Instant::now();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let other_ip: IpAddr = "198.51.100.1".parse().unwrap();
+
+        // Wrong passwords on one account run out its bucket for that client only
+        for _ in 0..2 {
+            assert_eq!(limiter.check(Some(ip), "ada@example.com", now), Ok(()));
+            limiter.record_failure(Some(ip), " ADA@example.com", now);
+        }
+        assert_eq!(limiter.check(Some(ip), "ada@example.com", now), Err(10));
+        assert_eq!(limiter.check(Some(other_ip), "ada@example.com", now), Ok(()));
+
+        // Tokens come back at 6 a minute, and the right password refills the bucket
+        let later = now + Duration::from_secs(10);
+        assert_eq!(limiter.check(Some(ip), "ada@example.com", later), Ok(()));
+        limiter.record_failure(Some(ip), "ada@example.com", later);
+        assert_eq!(limiter.check(Some(ip), "ada@example.com", later), Err(10));
+        limiter.record_success(Some(ip), "ada@example.com");
+        assert_eq!(limiter.check(Some(ip), "ada@example.com", later), Ok(()));
+
+        // One address trying several accounts runs out of IP tokens, right or wrong
+        let limiter = LoginLimiter::new(&config(3, 2));
+        for email in ["ada@example.com", "bob@example.com", "eve@example.com"] {
+            assert_eq!(limiter.check(Some(ip), email, now), Ok(()));
+        }
+        assert_eq!(limiter.check(Some(ip), "mallory@example.com", now), Err(10));
+    }
+
+    #[test]
+    fn test_account_bucket() {
+        let limiter = LoginLimiter::new(&config(10, 2));
+        let now = Instant::now();
+
+        // Wrong passwords from many addresses together run out the account's shared bucket
+        for i in 0..20u32 {
+            let ip = IpAddr::from((0xcb00_7100 + i).to_be_bytes());
+            assert_eq!(limiter.check(Some(ip), "ada@example.com", now), Ok(()));
+            limiter.record_failure(Some(ip), "ADA@example.com", now);
+        }
+        let fresh_ip: IpAddr = "198.51.100.1".parse().unwrap();
+        assert_eq!(limiter.check(Some(fresh_ip), "ada@example.com", now), Err(10));
+        assert_eq!(limiter.check(Some(fresh_ip), "bob@example.com", now), Ok(()));
+
+        // The right password refills it
+        limiter.record_success(Some(fresh_ip), "ada@example.com");
+        assert_eq!(limiter.check(Some(fresh_ip), "ada@example.com", now), Ok(()));
+    }
+
+    #[test]
+    fn test_email_link_buckets() {
+        let limiter = LoginLimiter::new(&config(10, 2));
+        let now = Instant::now();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let other_ip: IpAddr = "198.51.100.1".parse().unwrap();
+
+        // Every request draws from the address's bucket, whichever client sends it
+        assert_eq!(limiter.check_email_link(Some(ip), "ada@example.com", now), Ok(()));
+        assert_eq!(limiter.check_email_link(Some(other_ip), " ADA@example.com", now), Ok(()));
+        assert_eq!(limiter.check_email_link(None, "ada@example.com", now), Err(10));
+        assert_eq!(limiter.check_email_link(Some(ip), "bob@example.com", now), Ok(()));
+
+        // Logins on the address are counted separately
+        assert_eq!(limiter.check(Some(ip), "ada@example.com", now), Ok(()));
+    }
+
+    #[test]
+    fn test_disabled_limits() {
+        let now = Instant::now();
+        let unlimited = LoginLimiter::new(&LoginRateLimitConfig { enabled: false, ..config(1, 1) });
+        for _ in 0..100 {
+            assert_eq!(unlimited.check(None, "ada@example.com", now), Ok(()));
+            unlimited.record_failure(None, "ada@example.com", now);
+        }
+
+        // A zero setting turns one bucket off
+        let accounts_only = LoginLimiter::new(&config(0, 1));
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        assert_eq!(accounts_only.check(Some(ip), "ada@example.com", now), Ok(()));
+        accounts_only.record_failure(Some(ip), "ada@example.com", now);
+        assert_eq!(accounts_only.check(Some(ip), "bob@example.com", now), Ok(()));
+        assert!(accounts_only.check(Some(ip), "ada@example.com", now).is_err());
+    }
+
+    #[test]
+    fn test_bounded_buckets() {
+        let limiter = LoginLimiter::new(&config(1, 1));
+        let start = Instant::now();
+        for i in 0..(MAX_TRACKED_KEYS as u32 + 10) {
+            let ip = IpAddr::from(i.to_be_bytes());
+            let at = start + Duration::from_micros(u64::from(i));
+            assert_eq!(limiter.check(Some(ip), "ada@example.com", at), Ok(()));
+        }
+        let ips = limiter.ips.lock().unwrap();
+        assert!(ips.buckets.len() <= MAX_TRACKED_KEYS);
+        // The oldest buckets went first
+        assert!(!ips.buckets.contains_key(&IpAddr::from(0u32.to_be_bytes())));
+    }
+}
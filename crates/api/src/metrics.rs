@@ -0,0 +1,227 @@
+//! Operational metrics in the OpenMetrics text format
+//!
+//! Background jobs, webhooks, realtime streams, and authentication report here as they run; `GET /metrics`
+//! renders the current values for Prometheus-compatible scrapers.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+
+/// Content type of the rendered metrics
+pub const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Upper bounds, in seconds, of the webhook latency histogram buckets
+const LATENCY_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+#[derive(Debug, Default)]
+struct JobStats {
+    succeeded: u64,
+    failed: u64,
+    /// Jobs given up on after failing too many times in a row
+    dead_lettered: u64,
+    /// Items the job found waiting on its last run
+    backlog: Option<u64>,
+    last_success: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+struct WebhookStats {
+    succeeded: u64,
+    failed: u64,
+    latency: Histogram,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Non-cumulative counts per bucket of `LATENCY_BUCKETS`, plus the `+Inf` bucket
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Process-wide metrics registry
+#[derive(Debug, Default)]
+pub struct Metrics {
+    jobs: Mutex<BTreeMap<&'static str, JobStats>>,
+    webhooks: Mutex<BTreeMap<&'static str, WebhookStats>>,
+    /// Authenticated requests by whether their token's signing key is current or retiring
+    token_validations: Mutex<BTreeMap<&'static str, u64>>,
+    realtime_connections: AtomicI64,
+}
+
+impl Metrics {
+    /// Record one run of a background job
+    pub fn record_job_run(&self, job: &'static str, success: bool) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let stats = jobs.entry(job).or_default();
+        if success {
+            stats.succeeded += 1;
+            stats.last_success = Some(Utc::now().timestamp());
+        } else {
+            stats.failed += 1;
+        }
+    }
+
+    /// Record a background job given up on after repeated failures
+    pub fn record_job_dead_letter(&self, job: &'static str) {
+        self.jobs.lock().unwrap().entry(job).or_default().dead_lettered += 1;
+    }
+
+    /// Record how many items a background job found waiting
+    pub fn set_job_backlog(&self, job: &'static str, backlog: u64) {
+        self.jobs.lock().unwrap().entry(job).or_default().backlog = Some(backlog);
+    }
+
+    /// Record one webhook delivery and how long it took to process
+    pub fn record_webhook(&self, source: &'static str, success: bool, latency: Duration) {
+        let mut webhooks = self.webhooks.lock().unwrap();
+        let stats = webhooks.entry(source).or_default();
+        if success {
+            stats.succeeded += 1;
+        } else {
+            stats.failed += 1;
+        }
+        stats.latency.observe(latency.as_secs_f64());
+    }
+
+    /// Count an authenticated request by the state of the key that signed its token
+    pub fn record_token_validation(&self, key: &'static str) {
+        *self.token_validations.lock().unwrap().entry(key).or_default() += 1;
+    }
+
+    /// Count a realtime connection until the returned guard is dropped
+    pub fn realtime_connection(self: &Arc<Self>) -> RealtimeConnectionGuard {
+        self.realtime_connections.fetch_add(1, Ordering::Relaxed);
+        RealtimeConnectionGuard(self.clone())
+    }
+
+    /// Render every metric in the OpenMetrics text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        {
+            let jobs = self.jobs.lock().unwrap();
+
+            out.push_str("# TYPE projectkit_job_runs counter\n");
+            out.push_str("# HELP projectkit_job_runs Background job runs by outcome.\n");
+            for (job, stats) in jobs.iter() {
+                let _ = writeln!(out, "projectkit_job_runs_total{{job=\"{}\",outcome=\"success\"}} {}", job, stats.succeeded);
+                let _ = writeln!(out, "projectkit_job_runs_total{{job=\"{}\",outcome=\"failure\"}} {}", job, stats.failed);
+            }
+
+            out.push_str("# TYPE projectkit_job_dead_letters counter\n");
+            out.push_str("# HELP projectkit_job_dead_letters Background jobs dead-lettered after failing too many times in a row.\n");
+            for (job, stats) in jobs.iter() {
+                let _ = writeln!(out, "projectkit_job_dead_letters_total{{job=\"{}\"}} {}", job, stats.dead_lettered);
+            }
+
+            out.push_str("# TYPE projectkit_job_backlog gauge\n");
+            out.push_str("# HELP projectkit_job_backlog Items a background job found waiting on its last run.\n");
+            for (job, stats) in jobs.iter() {
+                if let Some(backlog) = stats.backlog {
+                    let _ = writeln!(out, "projectkit_job_backlog{{job=\"{}\"}} {}", job, backlog);
+                }
+            }
+
+            out.push_str("# TYPE projectkit_job_last_success_timestamp_seconds gauge\n");
+            out.push_str("# HELP projectkit_job_last_success_timestamp_seconds When a background job last succeeded.\n");
+            for (job, stats) in jobs.iter() {
+                if let Some(last_success) = stats.last_success {
+                    let _ = writeln!(out, "projectkit_job_last_success_timestamp_seconds{{job=\"{}\"}} {}", job, last_success);
+                }
+            }
+        }
+
+        {
+            let webhooks = self.webhooks.lock().unwrap();
+
+            out.push_str("# TYPE projectkit_webhook_deliveries counter\n");
+            out.push_str("# HELP projectkit_webhook_deliveries Webhook deliveries by outcome.\n");
+            for (source, stats) in webhooks.iter() {
+                let _ = writeln!(out, "projectkit_webhook_deliveries_total{{source=\"{}\",outcome=\"success\"}} {}", source, stats.succeeded);
+                let _ = writeln!(out, "projectkit_webhook_deliveries_total{{source=\"{}\",outcome=\"failure\"}} {}", source, stats.failed);
+            }
+
+            out.push_str("# TYPE projectkit_webhook_duration_seconds histogram\n");
+            out.push_str("# HELP projectkit_webhook_duration_seconds Time taken to process a webhook delivery.\n");
+            for (source, stats) in webhooks.iter() {
+                let mut cumulative = 0;
+                for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.latency.buckets.iter()) {
+                    cumulative += count;
+                    let _ = writeln!(out, "projectkit_webhook_duration_seconds_bucket{{source=\"{}\",le=\"{}\"}} {}", source, bound, cumulative);
+                }
+                let _ = writeln!(out, "projectkit_webhook_duration_seconds_bucket{{source=\"{}\",le=\"+Inf\"}} {}", source, stats.latency.count);
+                let _ = writeln!(out, "projectkit_webhook_duration_seconds_sum{{source=\"{}\"}} {}", source, stats.latency.sum);
+                let _ = writeln!(out, "projectkit_webhook_duration_seconds_count{{source=\"{}\"}} {}", source, stats.latency.count);
+            }
+        }
+
+        out.push_str("# TYPE projectkit_auth_token_validations counter\n");
+        out.push_str("# HELP projectkit_auth_token_validations Authenticated requests by whether their token's signing key is current or retiring.\n");
+        for (key, count) in self.token_validations.lock().unwrap().iter() {
+            let _ = writeln!(out, "projectkit_auth_token_validations_total{{key=\"{}\"}} {}", key, count);
+        }
+
+        out.push_str("# TYPE projectkit_realtime_connections gauge\n");
+        out.push_str("# HELP projectkit_realtime_connections Open realtime (server-sent events) connections.\n");
+        let _ = writeln!(out, "projectkit_realtime_connections {}", self.realtime_connections.load(Ordering::Relaxed));
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+/// Keeps a realtime connection counted while it is open
+pub struct RealtimeConnectionGuard(Arc<Metrics>);
+
+impl Drop for RealtimeConnectionGuard {
+    fn drop(&mut self) {
+        self.0.realtime_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.record_job_run("pending_upload_gc", true);
+        metrics.record_job_run("pending_upload_gc", false);
+        metrics.set_job_backlog("pending_upload_gc", 7);
+        metrics.record_job_dead_letter("pending_upload_gc");
+        metrics.record_webhook("stripe", true, Duration::from_millis(30));
+        metrics.record_token_validation("retiring");
+        let guard = metrics.realtime_connection();
+
+        let text = metrics.render();
+        assert!(text.contains("projectkit_job_runs_total{job=\"pending_upload_gc\",outcome=\"failure\"} 1"));
+        assert!(text.contains("projectkit_job_backlog{job=\"pending_upload_gc\"} 7"));
+        assert!(text.contains("projectkit_job_dead_letters_total{job=\"pending_upload_gc\"} 1"));
+        assert!(text.contains("projectkit_webhook_duration_seconds_bucket{source=\"stripe\",le=\"0.025\"} 0"));
+        assert!(text.contains("projectkit_webhook_duration_seconds_bucket{source=\"stripe\",le=\"0.05\"} 1"));
+        assert!(text.contains("projectkit_auth_token_validations_total{key=\"retiring\"} 1"));
+        assert!(text.contains("projectkit_realtime_connections 1"));
+        assert!(text.ends_with("# EOF\n"));
+
+        drop(guard);
+        assert!(metrics.render().contains("projectkit_realtime_connections 0"));
+    }
+}
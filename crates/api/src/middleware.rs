@@ -1,15 +1,19 @@
 use axum::{
-    extract::{Request, State},
-    http::{HeaderMap, StatusCode},
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
     Json,
 };
 use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
+use crate::file_handlers::storage_error_status;
 use crate::AppState;
 use auth::{Role, User};
+use billing::Tenant;
+use chrono::Utc;
 
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
@@ -21,11 +25,16 @@ pub async fn extract_user_from_token(
     state: &AppState,
     headers: &HeaderMap,
 ) -> Result<User, Response> {
+    extract_user_and_organization(state, headers).await.map(|(user, _)| user)
+}
+
+/// Extract and validate JWT token from Authorization header, along with the organization it acts for
+async fn extract_user_and_organization(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<(User, Option<i64>), Response> {
     // Extract token from Authorization header
-    let token = headers
-        .get("Authorization")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|v| v.strip_prefix("Bearer "))
+    let token = bearer_token(headers)
         .ok_or_else(|| {
             let error = ErrorResponse {
                 error: "Missing or invalid Authorization header".to_string(),
@@ -36,8 +45,9 @@ pub async fn extract_user_from_token(
     // Validate token and get user
     state
         .auth_service
-        .validate(token)
+        .validate_with_claims(token)
         .await
+        .map(|(user, claims)| (user, claims.org))
         .map_err(|e| {
             let error = ErrorResponse {
                 error: format!("Invalid token: {}", e),
@@ -46,18 +56,47 @@ pub async fn extract_user_from_token(
         })
 }
 
+/// Response header carrying a token re-signed with the current key
+pub const RENEWED_TOKEN_HEADER: &str = "x-renewed-token";
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Run an authenticated request, counting which key signed its token
+/// While that key is being retired, the response carries the token re-signed with the current key
+async fn run_authenticated(state: &AppState, request: Request, next: Next) -> Response {
+    let renewed = match bearer_token(request.headers()) {
+        Some(token) => state.auth_service.renew_token(token).await,
+        None => None,
+    };
+    state.metrics.record_token_validation(if renewed.is_some() { "retiring" } else { "current" });
+
+    let mut response = next.run(request).await;
+    if let Some(value) = renewed.and_then(|token| HeaderValue::from_str(&token).ok()) {
+        response.headers_mut().insert(RENEWED_TOKEN_HEADER, value);
+    }
+    response
+}
+
 /// Middleware to require authentication
 pub async fn require_auth(
     State(state): State<Arc<AppState>>,
     mut request: Request,
     next: Next,
 ) -> Result<Response, Response> {
-    let user = extract_user_from_token(&state, request.headers()).await?;
+    let (user, organization) = extract_user_and_organization(&state, request.headers()).await?;
     
     // Store user in request extensions for handlers to access
     request.extensions_mut().insert(user);
+    if let Some(organization_id) = organization {
+        request.extensions_mut().insert(TokenOrganization(organization_id));
+    }
     
-    Ok(next.run(request).await)
+    Ok(run_authenticated(&state, request, next).await)
 }
 
 /// Middleware to require a specific role
@@ -78,7 +117,31 @@ pub fn require_role(required_role: Role) -> impl Fn(State<Arc<AppState>>, Reques
             // Store user in request extensions for handlers to access
             request.extensions_mut().insert(user);
             
-            Ok(next.run(request).await)
+            Ok(run_authenticated(&state, request, next).await)
+        })
+    }
+}
+
+/// Middleware to require a permission, e.g. `require_permission("files:delete")`
+/// Service accounts hold every permission; other users get them from the custom roles they hold
+pub fn require_permission(permission: &'static str) -> impl Fn(State<Arc<AppState>>, Request, Next) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Response, Response>> + Send>> + Clone {
+    move |State(state): State<Arc<AppState>>, mut request: Request, next: Next| {
+        Box::pin(async move {
+            let (user, organization) = extract_user_and_organization(&state, request.headers()).await?;
+
+            if !user.has_permission(permission) {
+                let error = ErrorResponse {
+                    error: format!("Access denied. Required permission: {}", permission),
+                };
+                return Err((StatusCode::FORBIDDEN, Json(error)).into_response());
+            }
+
+            request.extensions_mut().insert(user);
+            if let Some(organization_id) = organization {
+                request.extensions_mut().insert(TokenOrganization(organization_id));
+            }
+
+            Ok(run_authenticated(&state, request, next).await)
         })
     }
 }
@@ -99,7 +162,7 @@ pub async fn require_user_role(
     }
     
     request.extensions_mut().insert(user);
-    Ok(next.run(request).await)
+    Ok(run_authenticated(&state, request, next).await)
 }
 
 /// Middleware to require service role (service accounts only)
@@ -118,9 +181,153 @@ pub async fn require_service_role(
     }
     
     request.extensions_mut().insert(user);
+    Ok(run_authenticated(&state, request, next).await)
+}
+
+/// Header carrying the sudo token from `POST /auth/sudo`
+pub const SUDO_TOKEN_HEADER: &str = "X-Sudo-Token";
+
+/// Middleware requiring a sudo token for destructive operations
+/// Runs after an authentication middleware; the sudo token must belong to the same user
+pub async fn require_sudo(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Response> {
+    let Some(user) = request.user() else {
+        let error = ErrorResponse {
+            error: "User not authenticated".to_string(),
+        };
+        return Err((StatusCode::UNAUTHORIZED, Json(error)).into_response());
+    };
+
+    let sudo_token = request
+        .headers()
+        .get(SUDO_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok());
+
+    let verified = sudo_token
+        .map(|token| state.auth_service.validate_sudo(token, user).is_ok())
+        .unwrap_or(false);
+    if !verified {
+        let error = ErrorResponse {
+            error: format!("This operation requires a valid {} header. Obtain one from POST /auth/sudo.", SUDO_TOKEN_HEADER),
+        };
+        return Err((StatusCode::FORBIDDEN, Json(error)).into_response());
+    }
+
     Ok(next.run(request).await)
 }
 
+/// Organization an organization token from `POST /organizations/:id/token` acts for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenOrganization(pub i64);
+
+/// Response header with the requests per minute the tenant's plan allows
+pub const RATE_LIMIT_LIMIT_HEADER: &str = "x-ratelimit-limit";
+
+/// Response header with the requests left in the current minute
+pub const RATE_LIMIT_REMAINING_HEADER: &str = "x-ratelimit-remaining";
+
+/// Middleware enforcing the request limits of the tenant's plan
+/// Runs after `require_auth`. Requests made with an organization token count against the
+/// organization's plan, and others against the user's. Uploads are checked against the plan
+/// by their declared `Content-Length` only, so oversized bodies are refused before they are
+/// read; storage enforces the limits on actual bytes.
+pub async fn enforce_plan_limits(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, Response> {
+    let (Some(billing), Some(user)) = (&state.billing_service, request.user().cloned()) else {
+        return Ok(next.run(request).await);
+    };
+    // Service accounts act for the whole instance and aren't on a plan
+    let (Some(user_id), false) = (user.id, user.is_service()) else {
+        return Ok(next.run(request).await);
+    };
+
+    let (tenant, plan) = match request.extensions().get::<TokenOrganization>().copied() {
+        Some(TokenOrganization(organization_id)) => {
+            // Membership is checked on every request, so removed members lose access at once
+            let organization = state
+                .storage_service
+                .get_organization(organization_id, user_id)
+                .await
+                .map_err(|e| {
+                    let error = ErrorResponse {
+                        error: format!("Organization token is no longer valid: {}", e),
+                    };
+                    (storage_error_status(&e), Json(error)).into_response()
+                })?;
+            (billing.tenant_for_organization(&organization), billing.plan_for_organization(&organization).await)
+        }
+        None => (Tenant::User(user_id), billing.plan_for_user(user_id).await),
+    };
+    let plan = plan.map_err(|e| {
+        let error = ErrorResponse {
+            error: format!("Failed to load billing plan: {}", e),
+        };
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+    })?;
+
+    let mut rate_limit = None;
+    if let Some(limit) = plan.api.requests_per_minute {
+        match state.rate_limiter.check(tenant, limit, Utc::now()) {
+            Ok(remaining) => rate_limit = Some((limit, remaining)),
+            Err(retry_after) => {
+                let error = ErrorResponse {
+                    error: format!("The {} plan allows {} requests per minute", plan.name, limit),
+                };
+                let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(error)).into_response();
+                let headers = response.headers_mut();
+                headers.insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                headers.insert(RATE_LIMIT_LIMIT_HEADER, HeaderValue::from(limit));
+                headers.insert(RATE_LIMIT_REMAINING_HEADER, HeaderValue::from(0));
+                return Err(response);
+            }
+        }
+    }
+
+    if let Some(cap) = plan.api.requests_per_month {
+        let used = billing.record_request(tenant).await.map_err(|e| {
+            let error = ErrorResponse {
+                error: format!("Failed to record API usage: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        })?;
+        if used > cap {
+            let error = ErrorResponse {
+                error: format!("The {} plan's limit of {} requests this month has been reached", plan.name, cap),
+            };
+            return Err((StatusCode::TOO_MANY_REQUESTS, Json(error)).into_response());
+        }
+    }
+
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+
+    if let (Some(content_length), Some(max)) = (content_length, plan.limits.max_file_size) {
+        if content_length > max {
+            let error = ErrorResponse {
+                error: format!("Request body exceeds the {} plan's limit of {} bytes", plan.name, max),
+            };
+            return Err((StatusCode::PAYLOAD_TOO_LARGE, Json(error)).into_response());
+        }
+    }
+
+    request.extensions_mut().insert(tenant);
+    let mut response = next.run(request).await;
+    if let Some((limit, remaining)) = rate_limit {
+        response.headers_mut().insert(RATE_LIMIT_LIMIT_HEADER, HeaderValue::from(limit));
+        response.headers_mut().insert(RATE_LIMIT_REMAINING_HEADER, HeaderValue::from(remaining));
+    }
+    Ok(response)
+}
+
 /// Extension trait to extract authenticated user from request
 pub trait AuthenticatedUser {
     fn user(&self) -> Option<&User>;
@@ -159,4 +366,75 @@ where
                 (StatusCode::UNAUTHORIZED, Json(error))
             })
     }
-}
\ No newline at end of file
+}
+
+/// Extractor for the client's IP address
+/// The `X-Forwarded-For` entry added by the outermost trusted proxy when the state trusts the
+/// header, otherwise the connection's peer; `None` when neither is known (the server was not
+/// started with connect info, e.g. in tests)
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl ClientIp {
+    /// The address and where it is located, for audit event data
+    pub fn describe(&self, state: &AppState) -> serde_json::Value {
+        let location = self.0.and_then(|ip| state.auth_service.locate(ip)).unwrap_or_default();
+        serde_json::json!({
+            "ip": self.0.map(|ip| ip.to_string()),
+            "country": location.country,
+            "city": location.city,
+        })
+    }
+}
+
+impl axum::extract::FromRequestParts<Arc<AppState>> for ClientIp {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        if state.trusted_proxy_hops > 0 {
+            let forwarded = parts
+                .headers
+                .get("X-Forwarded-For")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| forwarded_client(v, state.trusted_proxy_hops));
+            if forwarded.is_some() {
+                return Ok(ClientIp(forwarded));
+            }
+        }
+
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(ClientIp(peer))
+    }
+}
+
+/// The client address in an `X-Forwarded-For` value appended to by `hops` trusted proxies
+/// Each proxy appends the address it received the request from, so the client is `hops`
+/// entries from the right; anything further left is whatever the client sent. `None` when the
+/// request passed fewer proxies than that.
+fn forwarded_client(header: &str, hops: usize) -> Option<IpAddr> {
+    let entries: Vec<&str> = header.split(',').map(str::trim).collect();
+    let index = entries.len().checked_sub(hops)?;
+    entries[index].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwarded_client() {
+        let ip = |s: &str| s.parse::<IpAddr>().ok();
+        assert_eq!(forwarded_client("203.0.113.7", 1), ip("203.0.113.7"));
+        // A client can put anything in front of what the proxies append
+        assert_eq!(forwarded_client("10.0.0.1, 203.0.113.7", 1), ip("203.0.113.7"));
+        assert_eq!(forwarded_client("10.0.0.1, 203.0.113.7, 198.51.100.2", 2), ip("203.0.113.7"));
+        assert_eq!(forwarded_client("203.0.113.7", 2), None);
+        assert_eq!(forwarded_client("not-an-ip", 1), None);
+    }
+}
@@ -0,0 +1,214 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use storage::{OrganizationRole, StorageError};
+
+use crate::file_handlers::{storage_error_status, ErrorResponse, FileResponse};
+use crate::middleware::AuthUser;
+use crate::pagination::{Page, PageParams};
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddMemberRequest {
+    pub role: OrganizationRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrganizationTokenResponse {
+    pub token: String,
+    pub organization_id: i64,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetFileOrganizationRequest {
+    /// Organization to move the file into; `null` moves it back to its uploader
+    pub organization_id: Option<i64>,
+}
+
+fn organization_error(context: &str, error: StorageError) -> Response {
+    let status = storage_error_status(&error);
+    let error = ErrorResponse {
+        error: format!("{}: {}", context, error),
+    };
+    (status, Json(error)).into_response()
+}
+
+/// POST /organizations - Create an organization owned by the authenticated user
+pub async fn create_organization(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<CreateOrganizationRequest>,
+) -> impl IntoResponse {
+    match state.storage_service.create_organization(&payload.name, user.id.unwrap()).await {
+        Ok(organization) => (StatusCode::CREATED, Json(organization)).into_response(),
+        Err(e) => organization_error("Failed to create organization", e),
+    }
+}
+
+/// GET /organizations - List the organizations the authenticated user is a member of
+pub async fn list_organizations(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.storage_service.organizations_for_page(user.id.unwrap(), page.limit(), offset).await {
+        Ok((organizations, total)) => Page::from_offset(organizations, offset, page.limit(), total).into_response(),
+        Err(e) => organization_error("Failed to list organizations", e),
+    }
+}
+
+/// GET /organizations/:id - Get an organization the authenticated user is a member of
+pub async fn get_organization(
+    State(state): State<Arc<AppState>>,
+    Path(organization_id): Path<i64>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.storage_service.get_organization(organization_id, user.id.unwrap()).await {
+        Ok(organization) => (StatusCode::OK, Json(organization)).into_response(),
+        Err(e) => organization_error("Failed to get organization", e),
+    }
+}
+
+/// POST /organizations/:id/token - Issue a token whose requests count against the organization's plan
+/// Any member may act for the organization; the token stops working once they leave it
+pub async fn create_organization_token(
+    State(state): State<Arc<AppState>>,
+    Path(organization_id): Path<i64>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    if let Err(e) = state.storage_service.get_organization(organization_id, user.id.unwrap()).await {
+        return organization_error("Failed to issue organization token", e);
+    }
+
+    match state.auth_service.organization_token(&user, organization_id) {
+        Ok((token, expires_at)) => {
+            let response = OrganizationTokenResponse { token, organization_id, expires_at };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to issue organization token: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+/// GET /organizations/:id/members - List the members of an organization
+pub async fn list_members(
+    State(state): State<Arc<AppState>>,
+    Path(organization_id): Path<i64>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.storage_service.list_members_page(organization_id, user.id.unwrap(), page.limit(), offset).await {
+        Ok((members, total)) => Page::from_offset(members, offset, page.limit(), total).into_response(),
+        Err(e) => organization_error("Failed to list members", e),
+    }
+}
+
+/// PUT /organizations/:id/members/:user_id - Add a user to an organization or change their role
+/// Only admins manage members
+pub async fn add_member(
+    State(state): State<Arc<AppState>>,
+    Path((organization_id, member_id)): Path<(i64, i64)>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<AddMemberRequest>,
+) -> impl IntoResponse {
+    match state.auth_service.active_user_by_id(member_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            let error = ErrorResponse {
+                error: format!("User {} not found", member_id),
+            };
+            return (StatusCode::NOT_FOUND, Json(error)).into_response();
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to look up user: {}", e),
+            };
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response();
+        }
+    }
+
+    match state
+        .storage_service
+        .add_member(organization_id, user.id.unwrap(), member_id, payload.role)
+        .await
+    {
+        Ok(membership) => (StatusCode::OK, Json(membership)).into_response(),
+        Err(e) => organization_error("Failed to add member", e),
+    }
+}
+
+/// DELETE /organizations/:id/members/:user_id - Remove a member, or leave the organization
+pub async fn remove_member(
+    State(state): State<Arc<AppState>>,
+    Path((organization_id, member_id)): Path<(i64, i64)>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.storage_service.remove_member(organization_id, user.id.unwrap(), member_id).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => organization_error("Failed to remove member", e),
+    }
+}
+
+/// GET /organizations/:id/files - List the files of an organization
+/// They are downloaded and changed through `/files/:id` like the user's own files
+pub async fn list_organization_files(
+    State(state): State<Arc<AppState>>,
+    Path(organization_id): Path<i64>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.storage_service.list_organization_files_page(organization_id, user.id.unwrap(), page.limit(), offset).await {
+        Ok(results) => Page::from_offset(results.files, offset, page.limit(), results.total)
+            .map(FileResponse::from)
+            .into_response(),
+        Err(e) => organization_error("Failed to list files", e),
+    }
+}
+
+/// PUT /files/:id/organization - Move a file into an organization, or back out of it
+pub async fn set_file_organization(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SetFileOrganizationRequest>,
+) -> impl IntoResponse {
+    match state
+        .storage_service
+        .set_file_organization(&file_id, user.id.unwrap(), payload.organization_id)
+        .await
+    {
+        Ok(file) => (StatusCode::OK, Json(FileResponse::from(file))).into_response(),
+        Err(e) => organization_error("Failed to move file", e),
+    }
+}
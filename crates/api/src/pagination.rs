@@ -0,0 +1,162 @@
+//! Common envelope for list responses
+//!
+//! Every list endpoint returns `{ data, page: { next_cursor, prev_cursor, total? } }` and
+//! accepts `?cursor=&limit=`, so clients paginate every resource the same way. Cursors are
+//! opaque: clients pass back `next_cursor`/`prev_cursor` and never build them.
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::file_handlers::ErrorResponse;
+
+/// Page size when the client doesn't ask for one
+pub const DEFAULT_PAGE_LIMIT: i64 = 50;
+
+/// Largest page a client may request
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+/// `?cursor=&limit=` query parameters shared by all list endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct PageParams {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl PageParams {
+    /// Requested page size, clamped to `1..=MAX_PAGE_LIMIT`
+    pub fn limit(&self) -> i64 {
+        self.limit_or(DEFAULT_PAGE_LIMIT)
+    }
+
+    /// Requested page size with an endpoint-specific default
+    pub fn limit_or(&self, default: i64) -> i64 {
+        self.limit.unwrap_or(default).clamp(1, MAX_PAGE_LIMIT)
+    }
+
+    /// Offset encoded in an offset-based cursor; 0 when no cursor was given
+    pub fn offset(&self) -> Result<i64, Response> {
+        match self.cursor.as_deref() {
+            None | Some("") => Ok(0),
+            Some(cursor) => decode_offset(cursor).ok_or_else(|| invalid_cursor(cursor)),
+        }
+    }
+}
+
+/// Response for a cursor the server can't decode
+pub fn invalid_cursor(cursor: &str) -> Response {
+    let error = ErrorResponse {
+        error: format!("Invalid cursor: '{}'", cursor),
+    };
+    (StatusCode::BAD_REQUEST, Json(error)).into_response()
+}
+
+/// Pagination links for one page
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PageInfo {
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+    /// Total number of items across all pages, when it is cheap to know
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
+}
+
+/// A page of results in the common list envelope
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub page: PageInfo,
+}
+
+impl<T> Page<T> {
+    /// A page of an offset-paginated query whose total is known
+    pub fn from_offset(data: Vec<T>, offset: i64, limit: i64, total: i64) -> Self {
+        let next_offset = offset + data.len() as i64;
+        let page = PageInfo {
+            next_cursor: (next_offset < total && !data.is_empty()).then(|| encode_offset(next_offset)),
+            prev_cursor: (offset > 0).then(|| encode_offset((offset - limit).max(0))),
+            total: Some(total),
+        };
+        Self { data, page }
+    }
+
+    /// Paginate a list already held in memory
+    pub fn from_vec(all: Vec<T>, offset: i64, limit: i64) -> Self {
+        let total = all.len() as i64;
+        let data = all
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .collect();
+        Self::from_offset(data, offset, limit, total)
+    }
+
+    /// A page of a keyset-paginated query, which can only move forward
+    pub fn from_keyset(data: Vec<T>, next_cursor: Option<String>) -> Self {
+        let page = PageInfo {
+            next_cursor,
+            ..PageInfo::default()
+        };
+        Self { data, page }
+    }
+
+    /// Convert the items of a page, keeping its cursors
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            data: self.data.into_iter().map(f).collect(),
+            page: self.page,
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Page<T> {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+fn encode_offset(offset: i64) -> String {
+    format!("o{}", offset)
+}
+
+fn decode_offset(cursor: &str) -> Option<i64> {
+    cursor.strip_prefix('o')?.parse::<i64>().ok().filter(|offset| *offset >= 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_vec() {
+        let first = Page::from_vec((1..=5).collect::<Vec<_>>(), 0, 2);
+        assert_eq!(first.data, vec![1, 2]);
+        assert_eq!(first.page.total, Some(5));
+        assert!(first.page.prev_cursor.is_none());
+
+        let params = PageParams {
+            cursor: first.page.next_cursor.clone(),
+            limit: Some(2),
+        };
+        let offset = params.offset().unwrap();
+        let second = Page::from_vec((1..=5).collect::<Vec<_>>(), offset, 2);
+        assert_eq!(second.data, vec![3, 4]);
+        assert_eq!(second.page.prev_cursor.as_deref(), Some("o0"));
+
+        let last = Page::from_vec((1..=5).collect::<Vec<_>>(), 4, 2);
+        assert_eq!(last.data, vec![5]);
+        assert!(last.page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_cursor_decoding() {
+        assert_eq!(decode_offset("o40"), Some(40));
+        assert_eq!(decode_offset("40"), None);
+        assert_eq!(decode_offset("o-1"), None);
+        assert!(PageParams { cursor: Some("bogus".to_string()), limit: None }.offset().is_err());
+        assert_eq!(PageParams { cursor: None, limit: Some(1000) }.limit(), MAX_PAGE_LIMIT);
+    }
+}
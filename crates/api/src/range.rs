@@ -0,0 +1,76 @@
+//! `Range` requests on file downloads
+//!
+//! Only single byte ranges are served. A header asking for several ranges, or one that doesn't
+//! parse, is ignored and the whole file is sent, as HTTP allows.
+
+/// Most bytes sent in one partial response; longer ranges are cut short and clients ask for the rest
+pub const MAX_RANGE_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// A byte range from a `Range: bytes=...` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `bytes=first-` or `bytes=first-last`
+    From { first: u64, last: Option<u64> },
+    /// `bytes=-length`: the last `length` bytes
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Parse a `Range` header, or `None` if it should be ignored
+    pub fn parse(header: &str) -> Option<Self> {
+        let spec = header.trim().strip_prefix("bytes=")?.trim();
+        if spec.contains(',') {
+            return None;
+        }
+        let (first, last) = spec.split_once('-')?;
+        let (first, last) = (first.trim(), last.trim());
+
+        if first.is_empty() {
+            return last.parse().ok().map(ByteRange::Suffix);
+        }
+        let first = first.parse().ok()?;
+        let last = match last {
+            "" => None,
+            last => Some(last.parse().ok().filter(|last| *last >= first)?),
+        };
+        Some(ByteRange::From { first, last })
+    }
+
+    /// Offset and length of the range in a file of `size` bytes, or `None` if no byte of it is
+    /// in the file; the length is capped at [`MAX_RANGE_LENGTH`]
+    pub fn resolve(self, size: u64) -> Option<(u64, u64)> {
+        let (offset, end) = match self {
+            ByteRange::From { first, .. } if first >= size => return None,
+            ByteRange::From { first, last } => (first, last.map_or(size, |last| (last + 1).min(size))),
+            ByteRange::Suffix(length) if length == 0 || size == 0 => return None,
+            ByteRange::Suffix(length) =>(size.saturating_sub(length), size),
+        };
+        Some((offset, (end - offset).min(MAX_RANGE_LENGTH)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(ByteRange::parse("bytes=0-99"), Some(ByteRange::From { first: 0, last: Some(99) }));
+        assert_eq!(ByteRange::parse("bytes=100-"), Some(ByteRange::From { first: 100, last: None }));
+        assert_eq!(ByteRange::parse("bytes=-500"), Some(ByteRange::Suffix(500)));
+        for ignored in ["bytes=0-1,5-9", "items=0-9", "bytes=9-0", "bytes=a-b", "bytes=-"] {
+            assert_eq!(ByteRange::parse(ignored), None, "{}", ignored);
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(ByteRange::From { first: 2, last: Some(4) }.resolve(10), Some((2, 3)));
+        assert_eq!(ByteRange::From { first: 8, last: Some(40) }.resolve(10), Some((8, 2)));
+        assert_eq!(ByteRange::From { first: 10, last: None }.resolve(10), None);
+        assert_eq!(ByteRange::Suffix(3).resolve(10), Some((7, 3)));
+        assert_eq!(ByteRange::Suffix(30).resolve(10), Some((0, 10)));
+        assert_eq!(ByteRange::Suffix(0).resolve(10), None);
+        assert_eq!(ByteRange::From { first: 0, last: None }.resolve(MAX_RANGE_LENGTH * 2), Some((0, MAX_RANGE_LENGTH)));
+    }
+}
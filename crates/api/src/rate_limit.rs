@@ -0,0 +1,76 @@
+//! Per-minute request limits of billing plans
+//!
+//! Requests are counted in fixed one-minute windows per tenant, in memory, so each server
+//! instance enforces the limit on its own share of the traffic.
+
+use billing::Tenant;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Length of a rate limit window in seconds
+pub const RATE_LIMIT_WINDOW_SECONDS: i64 = 60;
+
+/// Windows kept before those that have ended are pruned
+const MAX_TRACKED_TENANTS: usize = 10_000;
+
+/// Counts requests per tenant in the current minute
+#[derive(Default)]
+pub struct RateLimiter {
+    windows: Mutex<HashMap<Tenant, Window>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    started: i64,
+    requests: u64,
+}
+
+impl RateLimiter {
+    /// Count a request made at `now` against a limit of `limit` requests per minute
+    ///
+    /// # Returns
+    /// The requests left in the window, or the seconds until the next window when the limit is reached
+    pub fn check(&self, tenant: Tenant, limit: u64, now: DateTime<Utc>) -> Result<u64, u64> {
+        let started = now.timestamp() - now.timestamp().rem_euclid(RATE_LIMIT_WINDOW_SECONDS);
+        let mut windows = self.windows.lock().unwrap();
+
+        if windows.len() >= MAX_TRACKED_TENANTS {
+            windows.retain(|_, window| window.started == started);
+        }
+
+        let window = windows.entry(tenant).or_insert(Window { started, requests: 0 });
+        if window.started != started {
+            *window = Window { started, requests: 0 };
+        }
+
+        if window.requests >= limit {
+            return Err((started + RATE_LIMIT_WINDOW_SECONDS - now.timestamp()) as u64);
+        }
+        window.requests += 1;
+        Ok(limit - window.requests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_rate_limit_windows() {
+        let limiter = RateLimiter::default();
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 45).unwrap();
+
+        assert_eq!(limiter.check(Tenant::Organization(1), 2, now), Ok(1));
+        assert_eq!(limiter.check(Tenant::Organization(1), 2, now), Ok(0));
+        assert_eq!(limiter.check(Tenant::Organization(1), 2, now), Err(15));
+
+        // Each tenant has its own window
+        assert_eq!(limiter.check(Tenant::User(1), 2, now), Ok(1));
+
+        // The next minute starts over
+        let next = Utc.with_ymd_and_hms(2025, 3, 1, 12, 1, 0).unwrap();
+        assert_eq!(limiter.check(Tenant::Organization(1), 2, next), Ok(1));
+    }
+}
@@ -1,39 +1,265 @@
-use axum::{Router, routing::{get, post, delete}, middleware};
+use axum::{Router, extract::DefaultBodyLimit, routing::{get, post, put, patch, delete}, middleware};
 use std::sync::Arc;
 
-use crate::{auth_handlers, db_handlers, file_handlers, middleware as auth_middleware, AppState};
+use crate::{activity_handlers, admin_handlers, announcement_handlers, asset_handlers, auth_handlers, billing_handlers, bucket_handlers, comment_handlers, db_handlers, deprecation, file_handlers, middleware as auth_middleware, organization_handlers, share_handlers, sql_handlers, upload_handlers, AppState};
+
+/// Mount `routes` only when their feature is enabled
+fn gated(enabled: bool, routes: Router<Arc<AppState>>) -> Router<Arc<AppState>> {
+    if enabled { routes } else { Router::new() }
+}
+
+/// Require `permission` on every route of `routes`
+fn permitted(state: &Arc<AppState>, permission: &'static str, routes: Router<Arc<AppState>>) -> Router<Arc<AppState>> {
+    routes.route_layer(middleware::from_fn_with_state(
+        state.clone(),
+        auth_middleware::require_permission(permission),
+    ))
+}
+
+/// Require `permission` and a sudo token on every route of `routes`
+fn sudo(state: &Arc<AppState>, permission: &'static str, routes: Router<Arc<AppState>>) -> Router<Arc<AppState>> {
+    // Layers run outermost first, so the permission check authenticates the user before `require_sudo`
+    permitted(state, permission, routes.route_layer(middleware::from_fn_with_state(
+        state.clone(),
+        auth_middleware::require_sudo,
+    )))
+}
 
 pub fn router(state: Arc<AppState>) -> Router {
+    let features = state.features.clone();
+
     // Public routes (no authentication required)
-    let public_routes = Router::new()
+    let mut public_routes = Router::new()
         .route("/", get(|| async { "Project Kit API running" }))
         .route("/auth/signup", post(auth_handlers::signup))
-        .route("/auth/login", post(auth_handlers::login));
+        .route("/auth/login", post(auth_handlers::login))
+        .route("/auth/refresh", post(auth_handlers::refresh))
+        .route("/auth/recover", post(auth_handlers::recover))
+        .route("/auth/password-reset", post(auth_handlers::request_password_reset))
+        .route("/auth/password-reset/confirm", post(auth_handlers::reset_password))
+        .route("/auth/email-change/confirm", post(auth_handlers::confirm_email_change))
+        .route("/auth/verify-email", post(auth_handlers::verify_email))
+        .route("/auth/magic-link", post(auth_handlers::request_magic_link))
+        .route("/auth/magic-link/login", post(auth_handlers::magic_link_login))
+        .route("/assets/{name}", get(asset_handlers::get_asset))
+        .route("/download/{token}", get(file_handlers::redeem_download_token))
+        // Streamed to storage and bounded by the URL's size limit, not the request body limit
+        .route("/uploads/{id}", put(upload_handlers::put_presigned_upload).layer(DefaultBodyLimit::disable()))
+        .route("/billing/stripe/webhook", post(billing_handlers::stripe_webhook));
+    if features.metrics {
+        public_routes = public_routes.route("/metrics", get(admin_handlers::metrics));
+    }
+    if features.shares {
+        public_routes = public_routes.route("/share/{token}", get(share_handlers::open_share));
+    }
 
-    // Protected auth routes (require service role)
+    // Service account routes (require service role)
     let service_routes = Router::new()
         .route("/auth/service", post(auth_handlers::create_service_account))
+        .route("/files/{id}/token", post(file_handlers::create_download_token))
         .route_layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware::require_service_role,
         ));
 
+    // Admin routes (each group requires a permission; service accounts hold them all)
+    let mut admin_routes = Router::new()
+        .merge(permitted(&state, "storage:manage", Router::new()
+            .route("/admin/reports/storage", get(admin_handlers::storage_reports))
+            .route("/admin/storage/gc", post(admin_handlers::storage_gc))
+            .route("/admin/storage/fsck", post(admin_handlers::storage_fsck))
+            .route("/admin/storage/integrity", get(admin_handlers::storage_integrity))
+            .route("/admin/storage/integrity", post(admin_handlers::audit_storage_integrity))))
+        // Raw SQL reaches password hashes and can change roles, so no grantable permission opens it
+        .merge(Router::new()
+            .route("/admin/sql", post(sql_handlers::run_sql))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware::require_service_role,
+            )))
+        .merge(permitted(&state, "users:manage", Router::new()
+            .route("/admin/users/{id}/restore", post(admin_handlers::restore_user))
+            .route("/admin/users/{id}/logins", get(admin_handlers::login_history))))
+        .merge(permitted(&state, "roles:manage", Router::new()
+            .route("/admin/users/{id}/roles", get(admin_handlers::user_roles))
+            .route("/admin/users/{id}/roles/{name}", put(admin_handlers::assign_role))
+            .route("/admin/users/{id}/roles/{name}", delete(admin_handlers::unassign_role))
+            .route("/admin/roles", get(admin_handlers::list_roles))
+            .route("/admin/roles/{name}", put(admin_handlers::put_role))
+            .route("/admin/roles/{name}", delete(admin_handlers::delete_role))))
+        .merge(permitted(&state, "holds:manage", Router::new()
+            .route("/admin/holds", get(admin_handlers::list_legal_holds))
+            .route("/admin/holds", post(admin_handlers::place_legal_hold))))
+        .merge(permitted(&state, "webhooks:manage", Router::new()
+            .route("/admin/webhooks/deliveries", get(admin_handlers::list_webhook_deliveries))
+            .route("/admin/webhooks/deliveries/{id}/redeliver", post(admin_handlers::redeliver_webhook))))
+        .merge(permitted(&state, "jobs:manage", Router::new()
+            .route("/admin/jobs", get(admin_handlers::list_jobs))
+            .route("/admin/jobs/{id}/retry", post(admin_handlers::retry_job))
+            .route("/admin/jobs/{id}/cancel", post(admin_handlers::cancel_job))))
+        .merge(permitted(&state, "billing:manage", Router::new()
+            .route("/admin/organizations/{id}/plan", put(billing_handlers::set_organization_plan))))
+        .merge(permitted(&state, "cluster:read", Router::new()
+            .route("/admin/cluster", get(admin_handlers::cluster_report))));
+    if features.announcements {
+        admin_routes = admin_routes.merge(permitted(&state, "announcements:manage", Router::new()
+            .route("/admin/announcements", get(announcement_handlers::list_all_announcements))
+            .route("/admin/announcements", post(announcement_handlers::create_announcement))
+            .route("/admin/announcements/{id}", delete(announcement_handlers::delete_announcement))));
+    }
+    let admin_routes = gated(features.admin, admin_routes);
+
+    // Destructive admin routes (require a permission and a sudo token)
+    let sudo_routes = gated(features.admin, Router::new()
+        .merge(sudo(&state, "users:delete", Router::new()
+            .route("/admin/users/{id}", delete(admin_handlers::delete_user))))
+        .merge(sudo(&state, "files:purge", Router::new()
+            .route("/admin/files/{id}", delete(admin_handlers::purge_file))))
+        .merge(sudo(&state, "holds:manage", Router::new()
+            .route("/admin/holds/{id}", delete(admin_handlers::release_legal_hold)))));
+
+    // Account routes (require authentication)
+    let account_routes = Router::new()
+        .route("/auth/me/preferences", get(auth_handlers::get_preferences))
+        .route("/auth/me/preferences", patch(auth_handlers::update_preferences))
+        .route("/auth/profile", get(auth_handlers::get_profile))
+        .route("/auth/profile", patch(auth_handlers::update_profile))
+        .route("/auth/me/verify-email", post(auth_handlers::send_verification_email))
+        .route("/auth/sudo", post(auth_handlers::sudo))
+        .route("/auth/change-password", post(auth_handlers::change_password))
+        .route("/auth/me/email", post(auth_handlers::request_email_change))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware::require_auth,
+        ));
+
     // Protected database routes (require authentication)
-    let db_routes = Router::new()
+    let db_routes = gated(features.database, Router::new()
         .route("/db/{table}", get(db_handlers::get_table))
         .route("/db/{table}", post(db_handlers::post_table))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware::enforce_plan_limits,
+        ))
         .route_layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware::require_auth,
-        ));
+        )));
+
+    // Comment routes (require authentication)
+    let comment_routes = gated(features.comments, Router::new()
+        .route("/db/{table}/{id}/comments", get(comment_handlers::list_comments))
+        .route("/db/{table}/{id}/comments", post(comment_handlers::create_comment))
+        .route("/comments/{id}", patch(comment_handlers::update_comment))
+        .route("/comments/{id}", delete(comment_handlers::delete_comment))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware::enforce_plan_limits,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware::require_auth,
+        )));
+
+    // Announcement routes (require authentication)
+    let announcement_routes = gated(features.announcements, Router::new()
+        .route("/announcements", get(announcement_handlers::list_announcements))
+        .route("/announcements/{id}/dismiss", post(announcement_handlers::dismiss_announcement))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware::require_auth,
+        )));
+
+    // Activity feed routes (require authentication)
+    let activity_routes = gated(features.activity, Router::new()
+        .route("/activity", get(activity_handlers::list_activity))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware::require_auth,
+        )));
+
+    // Realtime streams (require authentication)
+    let realtime_routes = gated(features.realtime, Router::new()
+        .route("/activity/stream", get(activity_handlers::stream_activity))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware::require_auth,
+        )));
+
+    // Share links and email invitations (require authentication)
+    let share_routes = gated(features.shares, Router::new()
+        .route("/files/{id}/shares", get(share_handlers::list_shares))
+        .route("/files/{id}/shares", post(share_handlers::create_share))
+        .route("/files/{id}/shares/{token}", delete(share_handlers::revoke_share))
+        .route("/files/{id}/invitations", get(share_handlers::list_invitations))
+        .route("/files/{id}/invitations", post(share_handlers::create_invitation))
+        .route("/files/{id}/invitations/{invitation_id}", delete(share_handlers::revoke_invitation))
+        .route("/files/{id}/permissions", get(share_handlers::list_permissions))
+        .route("/files/{id}/permissions/{user_id}", put(share_handlers::grant_permission))
+        .route("/files/{id}/permissions/{user_id}", delete(share_handlers::revoke_permission))
+        .route("/files/shared", get(share_handlers::list_shared_with_me))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware::enforce_plan_limits,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware::require_auth,
+        )));
 
     // Protected file routes (require authentication)
     let file_routes = Router::new()
         .route("/files", get(file_handlers::list_files))
-        .route("/files/upload", post(file_handlers::upload_file))
+        // Uploads are streamed to storage and bounded by the upload policy, not the request body limit
+        .route("/files/upload", post(file_handlers::upload_file).layer(DefaultBodyLimit::disable()))
+        .route("/files/import", post(file_handlers::import_file))
+        .route("/files/archive", post(file_handlers::download_archive))
+        .route("/files/uploads", post(upload_handlers::create_upload))
+        .route("/files/presign-upload", post(upload_handlers::presign_upload))
+        .route("/files/uploads/{id}", get(upload_handlers::get_upload))
+        .route("/files/uploads/{id}", patch(upload_handlers::upload_chunk))
+        .route("/files/uploads/{id}", delete(upload_handlers::cancel_upload))
         .route("/files/stats", get(file_handlers::get_storage_stats))
+        .route("/files/search", get(file_handlers::search_files))
+        .route("/files/export", get(file_handlers::export_files))
+        .route("/files/favorites", get(file_handlers::list_favorites))
+        .route("/files/recent", get(file_handlers::list_recent))
+        .route("/files/trash", get(file_handlers::list_trash))
+        .route("/files/trash", delete(file_handlers::empty_trash))
+        .route("/files/trash/{id}", delete(file_handlers::purge_file))
         .route("/files/{id}", get(file_handlers::download_file))
+        .route("/files/{id}", patch(file_handlers::update_file))
         .route("/files/{id}", delete(file_handlers::delete_file))
+        .route("/files/{id}/content", patch(file_handlers::patch_file_content))
+        .route("/files/{id}/append", post(file_handlers::append_file))
+        .route("/files/{id}/copy", post(file_handlers::copy_file))
+        .route("/files/{id}/transfer", post(file_handlers::transfer_file))
+        .route("/files/transfers", get(file_handlers::list_transfers))
+        .route("/files/transfers/{id}/accept", post(file_handlers::accept_transfer))
+        .route("/files/transfers/{id}", delete(file_handlers::cancel_transfer))
+        .route("/files/{id}/organization", put(organization_handlers::set_file_organization))
+        .route("/organizations", post(organization_handlers::create_organization))
+        .route("/organizations", get(organization_handlers::list_organizations))
+        .route("/organizations/{id}", get(organization_handlers::get_organization))
+        .route("/organizations/{id}/members", get(organization_handlers::list_members))
+        .route("/organizations/{id}/members/{user_id}", put(organization_handlers::add_member))
+        .route("/organizations/{id}/members/{user_id}", delete(organization_handlers::remove_member))
+        .route("/organizations/{id}/files", get(organization_handlers::list_organization_files))
+        .route("/organizations/{id}/token", post(organization_handlers::create_organization_token))
+        .route("/files/{id}/restore", post(file_handlers::restore_file))
+        .route("/files/{id}/favorite", post(file_handlers::favorite_file))
+        .route("/files/{id}/favorite", delete(file_handlers::unfavorite_file))
+        .route("/assets", post(asset_handlers::upload_asset))
+        .route("/storage/bucket", get(bucket_handlers::get_bucket))
+        .route("/storage/bucket", put(bucket_handlers::put_bucket))
+        .route("/storage/bucket", delete(bucket_handlers::delete_bucket))
+        .route("/storage/bucket/check", post(bucket_handlers::check_bucket))
+        .route("/billing/subscription", get(billing_handlers::get_subscription))
+        .route("/billing/usage", get(billing_handlers::get_usage))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware::enforce_plan_limits,
+        ))
         .route_layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware::require_auth,
@@ -43,7 +269,20 @@ pub fn router(state: Arc<AppState>) -> Router {
     Router::new()
         .merge(public_routes)
         .merge(service_routes)
+        .merge(admin_routes)
+        .merge(sudo_routes)
+        .merge(account_routes)
         .merge(db_routes)
+        .merge(comment_routes)
+        .merge(announcement_routes)
+        .merge(activity_routes)
+        .merge(realtime_routes)
+        .merge(share_routes)
         .merge(file_routes)
+        // Runs inside each route, so the matched route pattern is known
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            deprecation::emit_deprecations,
+        ))
         .with_state(state)
-}
\ No newline at end of file
+}
@@ -0,0 +1,709 @@
+//! Row-level security for `/db` tables
+//!
+//! Policies come from `[db.policies.<table>]` in the config. For each operation a policy gives a
+//! condition rows must meet, written like a SQL `WHERE` clause over the table's columns and the
+//! caller, e.g. `user_id = auth.uid() or published = true`. Reads are filtered by the condition
+//! in SQL, with the caller's values bound as parameters; inserts are checked against it before
+//! they are written. Once a table has a policy, operations it gives no condition for are denied.
+//! The service role is not bound by policies.
+//!
+//! A condition combines comparisons (`=`, `!=`, `<>`, `<`, `<=`, `>`, `>=`) of a column with
+//! `auth.uid()`, `auth.role()`, `auth.email()` or a literal; `column is [not] null`;
+//! `auth.has_permission('notes:read')`; `true` and `false`; and `and`, `or`, `not` and parentheses.
+
+use auth::User;
+use core::DbConfig;
+use orm::query::QueryValue;
+use serde_json::{Map, Value as JsonValue};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::db_handlers::{is_protected_table, is_valid_table_name, json_to_query_value};
+
+/// Words with a meaning of their own, which can't name columns
+const KEYWORDS: &[&str] = &["and", "or", "not", "is", "null", "true", "false"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn sql(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::Ne => "<>",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    /// The comparison with its sides swapped, so `5 < x` becomes `x > 5`
+    fn flipped(&self) -> Self {
+        match self {
+            Comparison::Lt => Comparison::Gt,
+            Comparison::Le => Comparison::Ge,
+            Comparison::Gt => Comparison::Lt,
+            Comparison::Ge => Comparison::Le,
+            other => *other,
+        }
+    }
+
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Eq => ordering == Ordering::Equal,
+            Comparison::Ne => ordering != Ordering::Equal,
+            Comparison::Lt => ordering == Ordering::Less,
+            Comparison::Le => ordering != Ordering::Greater,
+            Comparison::Gt => ordering == Ordering::Greater,
+            Comparison::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// What a column is compared with
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    /// `auth.uid()`, the caller's user ID
+    Uid,
+    /// `auth.role()`, the caller's built-in role
+    Role,
+    /// `auth.email()`, the caller's email address
+    Email,
+    Literal(JsonValue),
+}
+
+impl Operand {
+    fn value(&self, user: &User) -> JsonValue {
+        match self {
+            Operand::Uid => user.id.map(JsonValue::from).unwrap_or(JsonValue::Null),
+            Operand::Role => JsonValue::from(user.role.as_str()),
+            Operand::Email => JsonValue::from(user.email.as_str()),
+            Operand::Literal(value) => value.clone(),
+        }
+    }
+}
+
+/// A parsed policy condition
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Constant(bool),
+    HasPermission(String),
+    Compare {
+        column: String,
+        comparison: Comparison,
+        operand: Operand,
+    },
+    IsNull {
+        column: String,
+        negated: bool,
+    },
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// The condition in SQL, with the caller's values bound to parameters appended to `params`
+    fn to_sql(&self, user: &User, params: &mut Vec<QueryValue>) -> String {
+        match self {
+            Condition::Constant(value) => constant_sql(*value),
+            Condition::HasPermission(permission) => constant_sql(user.has_permission(permission)),
+            Condition::Compare { column, comparison, operand } => {
+                params.push(json_to_query_value(&operand.value(user)));
+                format!("{} {} ?{}", column, comparison.sql(), params.len())
+            }
+            Condition::IsNull { column, negated } => {
+                format!("{} IS {}NULL", column, if *negated { "NOT " } else { "" })
+            }
+            Condition::Not(inner) => format!("NOT ({})", inner.to_sql(user, params)),
+            Condition::And(left, right) => {
+                format!("({} AND {})", left.to_sql(user, params), right.to_sql(user, params))
+            }
+            Condition::Or(left, right) => {
+                format!("({} OR {})", left.to_sql(user, params), right.to_sql(user, params))
+            }
+        }
+    }
+
+    /// Whether `row` meets the condition, following SQL's three-valued logic: a comparison
+    /// involving null, or values of different types, is unknown (`None`)
+    fn evaluate(&self, row: &Map<String, JsonValue>, user: &User) -> Option<bool> {
+        match self {
+            Condition::Constant(value) => Some(*value),
+            Condition::HasPermission(permission) => Some(user.has_permission(permission)),
+            Condition::Compare { column, comparison, operand } => {
+                let ordering = compare_values(&column_value(row, column), &operand.value(user))?;
+                Some(comparison.holds(ordering))
+            }
+            Condition::IsNull { column, negated } => Some(column_value(row, column).is_null() != *negated),
+            Condition::Not(inner) => inner.evaluate(row, user).map(|value| !value),
+            Condition::And(left, right) => match (left.evaluate(row, user), right.evaluate(row, user)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Condition::Or(left, right) => match (left.evaluate(row, user), right.evaluate(row, user)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+        }
+    }
+
+    /// Columns the condition requires to equal one of the caller's values, through `=`
+    /// comparisons joined by `and`
+    fn pinned_columns<'a>(&'a self, pinned: &mut Vec<(&'a str, &'a Operand)>) {
+        match self {
+            Condition::Compare { column, comparison: Comparison::Eq, operand } if !matches!(operand, Operand::Literal(_)) => {
+                pinned.push((column.as_str(), operand));
+            }
+            Condition::And(left, right) => {
+                left.pinned_columns(pinned);
+                right.pinned_columns(pinned);
+            }
+            _ => {}
+        }
+    }
+
+    /// Every column the condition reads
+    fn columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Condition::Compare { column, .. } | Condition::IsNull { column, .. } => columns.push(column.as_str()),
+            Condition::Not(inner) => inner.columns(columns),
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                left.columns(columns);
+                right.columns(columns);
+            }
+            Condition::Constant(_) | Condition::HasPermission(_) => {}
+        }
+    }
+}
+
+/// A condition that always or never holds, in SQL every backend understands
+fn constant_sql(value: bool) -> String {
+    let sql = if value { "1 = 1" } else { "1 = 0" };
+    sql.to_string()
+}
+
+/// Value of a column in a written row; columns are matched case-insensitively and missing ones are null
+fn column_value(row: &Map<String, JsonValue>, column: &str) -> JsonValue {
+    row.iter()
+        .find(|(field, _)| field.eq_ignore_ascii_case(column))
+        .map(|(_, value)| value.clone())
+        .unwrap_or(JsonValue::Null)
+}
+
+/// Order of two values of the same type; `None` when either is null or their types differ
+fn compare_values(left: &JsonValue, right: &JsonValue) -> Option<Ordering> {
+    match (left, right) {
+        (JsonValue::Number(left), JsonValue::Number(right)) => match (left.as_i64(), right.as_i64()) {
+            (Some(left), Some(right)) => Some(left.cmp(&right)),
+            _ => left.as_f64()?.partial_cmp(&right.as_f64()?),
+        },
+        (JsonValue::String(left), JsonValue::String(right)) => Some(left.cmp(right)),
+        (JsonValue::Bool(left), JsonValue::Bool(right)) => Some(left.cmp(right)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(JsonValue),
+    Compare(Comparison),
+    Dot,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Text(text) => write!(f, "string '{}'", text),
+            Token::Number(number) => write!(f, "number {}", number),
+            Token::Compare(comparison) => write!(f, "'{}'", comparison.sql()),
+            Token::Dot => write!(f, "'.'"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            '.' => {
+                tokens.push(Token::Dot);
+                i += 1;
+            }
+            '=' | '!' | '<' | '>' => {
+                let (comparison, length) = match (c, next) {
+                    ('=', _) => (Comparison::Eq, 1),
+                    ('!', Some('=')) | ('<', Some('>')) => (Comparison::Ne, 2),
+                    ('<', Some('=')) => (Comparison::Le, 2),
+                    ('>', Some('=')) => (Comparison::Ge, 2),
+                    ('<', _) => (Comparison::Lt, 1),
+                    ('>', _) => (Comparison::Gt, 1),
+                    _ => return Err(format!("unexpected '{}'", c)),
+                };
+                tokens.push(Token::Compare(comparison));
+                i += length;
+            }
+            // Quotes inside strings are doubled, as in SQL
+            '\'' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err("unterminated string".to_string()),
+                        Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                            text.push('\'');
+                            i += 2;
+                        }
+                        Some('\'') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(c) => {
+                            text.push(*c);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(Token::Text(text));
+            }
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = match text.parse::<i64>() {
+                    Ok(number) => JsonValue::from(number),
+                    Err(_) => text
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|number| number.is_finite())
+                        .map(JsonValue::from)
+                        .ok_or_else(|| format!("invalid number '{}'", text))?,
+                };
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Word(chars[start..i].iter().collect()));
+            }
+            _ => return Err(format!("unexpected '{}'", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// One side of a comparison, or a condition on its own
+enum Term {
+    Column(String),
+    Operand(Operand),
+    Permission(String),
+}
+
+/// Recursive-descent parser; `or` binds loosest, then `and`, then `not`
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Consume `keyword` if it comes next
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.advance() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected {}, found {}", expected, token)),
+            None => Err(format!("expected {} at the end", expected)),
+        }
+    }
+
+    fn disjunction(&mut self) -> Result<Condition, String> {
+        let mut condition = self.conjunction()?;
+        while self.keyword("or") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.conjunction()?));
+        }
+        Ok(condition)
+    }
+
+    fn conjunction(&mut self) -> Result<Condition, String> {
+        let mut condition = self.negation()?;
+        while self.keyword("and") {
+            condition = Condition::And(Box::new(condition), Box::new(self.negation()?));
+        }
+        Ok(condition)
+    }
+
+    fn negation(&mut self) -> Result<Condition, String> {
+        if self.keyword("not") {
+            return Ok(Condition::Not(Box::new(self.negation()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Condition, String> {
+        if self.peek() == Some(&Token::Open) {
+            self.position += 1;
+            let condition = self.disjunction()?;
+            self.expect(Token::Close)?;
+            return Ok(condition);
+        }
+
+        let left = self.term()?;
+        if let Some(Token::Compare(comparison)) = self.peek().cloned() {
+            self.position += 1;
+            return match (left, self.term()?) {
+                (Term::Column(column), Term::Operand(operand)) => Ok(Condition::Compare { column, comparison, operand }),
+                (Term::Operand(operand), Term::Column(column)) => Ok(Condition::Compare {
+                    column,
+                    comparison: comparison.flipped(),
+                    operand,
+                }),
+                _ => Err("a comparison needs a column on one side, and auth.uid(), auth.role(), auth.email() or a literal on the other".to_string()),
+            };
+        }
+
+        match left {
+            Term::Column(column) if self.keyword("is") => {
+                let negated = self.keyword("not");
+                if !self.keyword("null") {
+                    return Err(format!("expected null after '{} is'", column));
+                }
+                Ok(Condition::IsNull { column, negated })
+            }
+            Term::Column(column) => Err(format!("expected a comparison after '{}'", column)),
+            Term::Operand(Operand::Literal(JsonValue::Bool(value))) => Ok(Condition::Constant(value)),
+            Term::Operand(_) => Err("expected a comparison".to_string()),
+            Term::Permission(permission) => Ok(Condition::HasPermission(permission)),
+        }
+    }
+
+    fn term(&mut self) -> Result<Term, String> {
+        match self.advance() {
+            Some(Token::Text(text)) => Ok(Term::Operand(Operand::Literal(JsonValue::String(text)))),
+            Some(Token::Number(number)) => Ok(Term::Operand(Operand::Literal(number))),
+            Some(Token::Word(word)) => {
+                let lower = word.to_ascii_lowercase();
+                match lower.as_str() {
+                    "true" | "false" => Ok(Term::Operand(Operand::Literal(JsonValue::Bool(lower == "true")))),
+                    "null" => Err("compare with null through 'is null'".to_string()),
+                    "auth" if self.peek() == Some(&Token::Dot) => self.auth_function(),
+                    _ if KEYWORDS.contains(&lower.as_str()) => Err(format!("unexpected '{}'", word)),
+                    _ if is_valid_table_name(&word) => Ok(Term::Column(lower)),
+                    _ => Err(format!("invalid column name '{}'", word)),
+                }
+            }
+            Some(token) => Err(format!("unexpected {}", token)),
+            None => Err("unexpected end of condition".to_string()),
+        }
+    }
+
+    /// `auth.<function>(...)`, once `auth` has been read
+    fn auth_function(&mut self) -> Result<Term, String> {
+        self.expect(Token::Dot)?;
+        let Some(Token::Word(name)) = self.advance() else {
+            return Err("expected a function after 'auth.'".to_string());
+        };
+        self.expect(Token::Open)?;
+        let term = match name.to_ascii_lowercase().as_str() {
+            "uid" => Term::Operand(Operand::Uid),
+            "role" => Term::Operand(Operand::Role),
+            "email" => Term::Operand(Operand::Email),
+            "has_permission" => match self.advance() {
+                Some(Token::Text(permission)) if auth::is_valid_permission(&permission) => Term::Permission(permission),
+                _ => return Err("auth.has_permission() takes a permission such as 'notes:read'".to_string()),
+            },
+            _ => return Err(format!("unknown function auth.{}()", name)),
+        };
+        self.expect(Token::Close)?;
+        Ok(term)
+    }
+}
+
+fn parse(source: &str) -> Result<Condition, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+    let condition = parser.disjunction()?;
+    match parser.peek() {
+        None => Ok(condition),
+        Some(token) => Err(format!("unexpected {}", token)),
+    }
+}
+
+/// The conditions of one table, by operation; an operation without one is denied
+#[derive(Debug, Clone, Default)]
+struct TablePolicy {
+    select: Option<Condition>,
+    insert: Option<Condition>,
+}
+
+/// The row-level security policies of `/db` tables, from `[db.policies.<table>]`
+/// Tables without a policy stay open to every authenticated user, as before
+#[derive(Debug, Clone, Default)]
+pub struct RowPolicies {
+    by_table: HashMap<String, TablePolicy>,
+}
+
+impl RowPolicies {
+    pub fn from_config(config: &DbConfig) -> Result<Self, String> {
+        let mut by_table = HashMap::new();
+        for (table, policy) in &config.policies {
+            if !is_valid_table_name(table) {
+                return Err(format!("[db.policies]: invalid table name '{}'", table));
+            }
+            let table = table.to_ascii_lowercase();
+            if is_protected_table(&table) {
+                return Err(format!("[db.policies.{}]: system tables are only open to the service role", table));
+            }
+
+            // Encrypted values are stored as ciphertext, so they can't be compared in SQL
+            let encrypted: Vec<String> = config.encryption.columns.iter()
+                .filter_map(|entry| entry.split_once('.'))
+                .filter(|(encrypted_table, _)| encrypted_table.eq_ignore_ascii_case(&table))
+                .map(|(_, column)| column.to_ascii_lowercase())
+                .collect();
+            let parse_action = |action: &str, source: &Option<String>| -> Result<Option<Condition>, String> {
+                let Some(source) = source else {
+                    return Ok(None);
+                };
+                let condition = parse(source).map_err(|e| format!("[db.policies.{}] {}: {}", table, action, e))?;
+                let mut columns = Vec::new();
+                condition.columns(&mut columns);
+                if let Some(column) = columns.into_iter().find(|column| encrypted.iter().any(|name| name == column)) {
+                    return Err(format!("[db.policies.{}] {}: column '{}' is encrypted and can't be compared", table, action, column));
+                }
+                Ok(Some(condition))
+            };
+
+            let policy = TablePolicy {
+                select: parse_action("select", &policy.select)?,
+                insert: parse_action("insert", &policy.insert)?,
+            };
+            by_table.insert(table, policy);
+        }
+        Ok(Self { by_table })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_table.is_empty()
+    }
+
+    fn for_table(&self, table: &str, user: &User) -> Option<&TablePolicy> {
+        if user.is_service() {
+            return None;
+        }
+        self.by_table.get(&table.to_ascii_lowercase())
+    }
+
+    /// The rows of `table` the caller may read, as a SQL condition and its parameters
+    /// `None` when every row is readable; an error when the table can't be read at all
+    /// Parameters are numbered after `params`, which the condition's own are appended to
+    pub fn select_filter(&self, table: &str, user: &User, params: &mut Vec<QueryValue>) -> Result<Option<String>, String> {
+        let Some(policy) = self.for_table(table, user) else {
+            return Ok(None);
+        };
+        match &policy.select {
+            Some(condition) => Ok(Some(condition.to_sql(user, params))),
+            None => Err(format!("No policy allows reading table '{}'", table)),
+        }
+    }
+
+    /// Check a row the caller inserts into `table`
+    /// Columns the policy requires to equal `auth.uid()`, `auth.role()` or `auth.email()` are
+    /// filled in when the row leaves them out, so clients needn't send their own user ID
+    pub fn check_insert(&self, table: &str, user: &User, row: &mut Map<String, JsonValue>) -> Result<(), String> {
+        let Some(policy) = self.for_table(table, user) else {
+            return Ok(());
+        };
+        let Some(condition) = &policy.insert else {
+            return Err(format!("No policy allows inserting into table '{}'", table));
+        };
+
+        let mut pinned = Vec::new();
+        condition.pinned_columns(&mut pinned);
+        for (column, operand) in pinned {
+            let value = operand.value(user);
+            if !value.is_null() && !row.keys().any(|field| field.eq_ignore_ascii_case(column)) {
+                row.insert(column.to_string(), value);
+            }
+        }
+
+        match condition.evaluate(row, user) {
+            Some(true) => Ok(()),
+            _ => Err(format!("Row violates the insert policy of table '{}'", table)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auth::Role;
+    use core::DbPolicyConfig;
+
+    fn user(id: i64) -> User {
+        let mut user = User::new(format!("user{}@example.com", id), String::new());
+        user.id = Some(id);
+        user
+    }
+
+    fn policies(table: &str, select: Option<&str>, insert: Option<&str>) -> Result<RowPolicies, String> {
+        let mut config = DbConfig::default();
+        config.policies.insert(table.to_string(), DbPolicyConfig {
+            select: select.map(str::to_string),
+            insert: insert.map(str::to_string),
+        });
+        RowPolicies::from_config(&config)
+    }
+
+    #[test]
+    fn test_parse() {
+        let sql = |source: &str| {
+            let mut params = Vec::new();
+            let sql = parse(source).unwrap().to_sql(&user(7), &mut params);
+            (sql, params.len())
+        };
+        assert_eq!(sql("user_id = auth.uid()"), ("user_id = ?1".to_string(), 1));
+        assert_eq!(sql("5 < Score"), ("score > ?1".to_string(), 1));
+        assert_eq!(
+            sql("owner_id = auth.uid() or (published = true and deleted_at is null)"),
+            ("(owner_id = ?1 OR (published = ?2 AND deleted_at IS NULL))".to_string(), 2)
+        );
+        assert_eq!(sql("not archived != 'it''s' or false"), ("(NOT (archived <> ?1) OR 1 = 0)".to_string(), 1));
+        assert_eq!(sql("auth.has_permission('notes:read')"), ("1 = 0".to_string(), 0));
+
+        for invalid in [
+            "",
+            "user_id",
+            "user_id = ",
+            "user_id = other_id",
+            "auth.uid() = 1",
+            "user_id = null",
+            "user_id = auth.sub()",
+            "auth.has_permission('Notes')",
+            "user_id = auth.uid() or",
+            "(user_id = 1",
+            "user_id = 1; DROP TABLE users",
+            "title = 'unterminated",
+            "and = 1",
+        ] {
+            assert!(parse(invalid).is_err(), "{:?} should not parse", invalid);
+        }
+    }
+
+    #[test]
+    fn test_select_filter() {
+        let policies = policies("notes", Some("user_id = auth.uid()"), None).unwrap();
+        let mut params = vec![QueryValue::I64(1)];
+        let filter = policies.select_filter("Notes", &user(7), &mut params).unwrap();
+        assert_eq!(filter.as_deref(), Some("user_id = ?2"));
+        assert_eq!(params.len(), 2);
+
+        assert_eq!(policies.select_filter("tasks", &user(7), &mut Vec::new()), Ok(None));
+        let mut service = user(1);
+        service.role = Role::Service;
+        assert_eq!(policies.select_filter("notes", &service, &mut Vec::new()), Ok(None));
+
+        let insert_only = self::policies("notes", None, Some("true")).unwrap();
+        assert!(insert_only.select_filter("notes", &user(7), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_check_insert() {
+        let policies = policies("notes", None, Some("user_id = auth.uid() and (priority < 5 or auth.role() = 'service')")).unwrap();
+        let row = |value: JsonValue| value.as_object().unwrap().clone();
+
+        // The owner is filled in when left out
+        let mut note = row(serde_json::json!({"title": "Hi", "priority": 1}));
+        policies.check_insert("notes", &user(7), &mut note).unwrap();
+        assert_eq!(note["user_id"], 7);
+
+        let mut note = row(serde_json::json!({"title": "Hi", "priority": 1, "USER_ID": 7}));
+        policies.check_insert("notes", &user(7), &mut note).unwrap();
+        assert!(!note.contains_key("user_id"));
+
+        let mut forged = row(serde_json::json!({"title": "Hi", "priority": 1, "user_id": 8}));
+        assert!(policies.check_insert("notes", &user(7), &mut forged).is_err());
+        let mut urgent = row(serde_json::json!({"title": "Hi", "priority": 9}));
+        assert!(policies.check_insert("notes", &user(7), &mut urgent).is_err());
+        // A missing column is null, so comparing it is unknown and the row is refused
+        let mut unranked = row(serde_json::json!({"title": "Hi"}));
+        assert!(policies.check_insert("notes", &user(7), &mut unranked).is_err());
+        let mut mistyped = row(serde_json::json!({"title": "Hi", "priority": "1"}));
+        assert!(policies.check_insert("notes", &user(7), &mut mistyped).is_err());
+
+        let read_only = self::policies("notes", Some("true"), None).unwrap();
+        assert!(read_only.check_insert("notes", &user(7), &mut row(serde_json::json!({"title": "Hi"}))).is_err());
+    }
+
+    #[test]
+    fn test_from_config() {
+        assert!(RowPolicies::from_config(&DbConfig::default()).unwrap().is_empty());
+        assert!(policies("notes", Some("user_id = auth.uid()"), Some("user_id = auth.uid()")).is_ok());
+        assert!(policies("users", Some("id = auth.uid()"), None).is_err());
+        assert!(policies("notes; --", Some("true"), None).is_err());
+        assert!(policies("notes", Some("user_id = "), None).is_err());
+
+        let mut config = DbConfig::default();
+        config.encryption.columns = vec!["patients.ssn".to_string()];
+        config.policies.insert("patients".to_string(), DbPolicyConfig {
+            select: Some("ssn = '123'".to_string()),
+            insert: None,
+        });
+        assert!(RowPolicies::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,489 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::auth_handlers::too_many_attempts;
+use crate::file_handlers::{attachment_disposition, download_body, storage_error_status, ErrorResponse, FileResponse};
+use crate::middleware::{AuthUser, ClientIp};
+use crate::pagination::{Page, PageParams};
+use crate::AppState;
+use auth::{Email, NotificationKind};
+use storage::{File, FileAccess, FileListOptions, FilePermission, FileShare, ShareInvitation, StorageError};
+
+/// Header carrying the password of a protected share link
+const SHARE_PASSWORD_HEADER: &str = "X-Share-Password";
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShareRequest {
+    /// Password required to open the link
+    pub password: Option<String>,
+    /// When the link stops working; never if omitted
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareResponse {
+    pub token: String,
+    pub file_id: String,
+    pub url: String,
+    pub has_password: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ShareResponse {
+    fn new(state: &AppState, share: FileShare) -> Self {
+        Self {
+            url: state.storage_service.public_url(&format!("/share/{}", share.token)),
+            has_password: share.has_password(),
+            token: share.token,
+            file_id: share.file_id,
+            expires_at: share.expires_at,
+            created_at: share.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevokeShareResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InviteRequest {
+    /// Address of the person to share the file with
+    pub email: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvitationResponse {
+    pub id: i64,
+    pub file_id: String,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ShareInvitation> for InvitationResponse {
+    fn from(invitation: ShareInvitation) -> Self {
+        Self {
+            id: invitation.id,
+            file_id: invitation.file_id,
+            email: invitation.email,
+            created_at: invitation.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InviteResponse {
+    #[serde(flatten)]
+    pub invitation: InvitationResponse,
+    /// Whether an email went out; the invitation stands either way
+    pub email_sent: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GrantPermissionRequest {
+    /// `read` or `read_write`
+    pub access: FileAccess,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PermissionResponse {
+    pub file_id: String,
+    pub user_id: i64,
+    pub access: FileAccess,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<FilePermission> for PermissionResponse {
+    fn from(permission: FilePermission) -> Self {
+        Self {
+            file_id: permission.file_id,
+            user_id: permission.user_id,
+            access: permission.access,
+            created_at: permission.created_at,
+        }
+    }
+}
+
+fn share_error(context: &str, error: StorageError) -> Response {
+    let status = storage_error_status(&error);
+    let error = ErrorResponse {
+        error: format!("{}: {}", context, error),
+    };
+    (status, Json(error)).into_response()
+}
+
+/// POST /files/:id/shares - Create a public share link for a file
+pub async fn create_share(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<CreateShareRequest>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    let password_hash = match payload.password.as_deref() {
+        Some("") => {
+            let error = ErrorResponse {
+                error: "Password cannot be empty".to_string(),
+            };
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+        Some(password) => match auth::hash_password(password) {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                let error = ErrorResponse {
+                    error: format!("Failed to hash password: {}", e),
+                };
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response();
+            }
+        },
+        None => None,
+    };
+
+    match state
+        .storage_service
+        .create_share(&file_id, user_id, password_hash, payload.expires_at)
+        .await
+    {
+        Ok(share) => (StatusCode::CREATED, Json(ShareResponse::new(&state, share))).into_response(),
+        Err(e) => share_error("Failed to create share link", e),
+    }
+}
+
+/// GET /files/:id/shares - List a file's share links
+pub async fn list_shares(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.storage_service.list_shares_page(&file_id, user_id, page.limit(), offset).await {
+        Ok((shares, total)) => Page::from_offset(shares, offset, page.limit(), total)
+            .map(|share| ShareResponse::new(&state, share))
+            .into_response(),
+        Err(e) => share_error("Failed to list share links", e),
+    }
+}
+
+/// DELETE /files/:id/shares/:token - Revoke a share link
+pub async fn revoke_share(
+    State(state): State<Arc<AppState>>,
+    Path((file_id, token)): Path<(String, String)>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.storage_service.revoke_share(&file_id, &token, user_id).await {
+        Ok(_) => {
+            let response = RevokeShareResponse {
+                success: true,
+                message: "Share link revoked".to_string(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => share_error("Failed to revoke share link", e),
+    }
+}
+
+/// The email telling a recipient about a file shared with them
+fn invitation_email(state: &AppState, sender: &str, file: &File, invitation: &ShareInvitation) -> Email {
+    let body = if invitation.is_pending() {
+        format!(
+            "{} shared \"{}\" with you.\n\nCreate an account with this email address to open it; the file will be waiting for you.",
+            sender, file.original_name
+        )
+    } else {
+        format!(
+            "{} shared \"{}\" with you.\n\nYou'll find it among the files shared with you: {}",
+            sender, file.original_name, state.storage_service.public_url("/files/shared")
+        )
+    };
+    Email {
+        to: invitation.email.clone(),
+        subject: format!("{} shared a file with you", sender),
+        body,
+    }
+}
+
+/// POST /files/:id/invitations - Share a file with someone by email
+/// Recipients with an account can open the file right away; anyone else gets an invitation
+/// that is accepted when they sign up with that address.
+pub async fn create_invitation(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<InviteRequest>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    let recipient = match state.auth_service.active_user_by_email(payload.email.trim()).await {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to look up recipient: {}", e),
+            };
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response();
+        }
+    };
+    // An unverified account may not belong to whoever owns the address; the invitation waits
+    // until the address is verified
+    let recipient_id = recipient
+        .filter(|recipient| recipient.is_email_verified())
+        .and_then(|recipient| recipient.id);
+
+    let invitation = match state
+        .storage_service
+        .invite_to_file(&file_id, user_id, &payload.email, recipient_id)
+        .await
+    {
+        Ok(invitation) => invitation,
+        Err(e) => return share_error("Failed to share file", e),
+    };
+
+    // Registered recipients are only emailed if they want to hear about shared files
+    let should_email = match recipient_id {
+        Some(recipient_id) => state
+            .auth_service
+            .should_notify(recipient_id, NotificationKind::ShareReceived)
+            .await
+            .unwrap_or(true),
+        None => true,
+    };
+    let mut email_sent = false;
+    if should_email {
+        if let Ok(Some(file)) = state.storage_service.get_file_by_id(&file_id).await {
+            let email = invitation_email(&state, &user.email, &file, &invitation);
+            match state.mailer.send(&email).await {
+                Ok(()) => email_sent = true,
+                Err(e) => eprintln!("⚠️  Failed to email invitation {} to {}: {}", invitation.id, invitation.email, e),
+            }
+        }
+    }
+
+    let response = InviteResponse {
+        invitation: InvitationResponse::from(invitation),
+        email_sent,
+    };
+    (StatusCode::CREATED, Json(response)).into_response()
+}
+
+/// GET /files/:id/invitations - List the people a file is shared with
+pub async fn list_invitations(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.storage_service.list_invitations_page(&file_id, user_id, page.limit(), offset).await {
+        Ok((invitations, total)) => Page::from_offset(invitations, offset, page.limit(), total)
+            .map(InvitationResponse::from)
+            .into_response(),
+        Err(e) => share_error("Failed to list invitations", e),
+    }
+}
+
+/// DELETE /files/:id/invitations/:invitation_id - Stop sharing a file with someone
+pub async fn revoke_invitation(
+    State(state): State<Arc<AppState>>,
+    Path((file_id, invitation_id)): Path<(String, i64)>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.storage_service.revoke_invitation(&file_id, invitation_id, user_id).await {
+        Ok(_) => {
+            let response = RevokeShareResponse {
+                success: true,
+                message: "Invitation revoked".to_string(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => share_error("Failed to revoke invitation", e),
+    }
+}
+
+/// PUT /files/:id/permissions/:user_id - Share a file with another user
+/// Replaces the access the user had to the file, if any
+pub async fn grant_permission(
+    State(state): State<Arc<AppState>>,
+    Path((file_id, grantee_id)): Path<(String, i64)>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<GrantPermissionRequest>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.auth_service.active_user_by_id(grantee_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            let error = ErrorResponse {
+                error: format!("User {} not found", grantee_id),
+            };
+            return (StatusCode::NOT_FOUND, Json(error)).into_response();
+        }
+        Err(e) => {
+            let error = ErrorResponse {
+                error: format!("Failed to look up user: {}", e),
+            };
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response();
+        }
+    }
+
+    match state
+        .storage_service
+        .grant_permission(&file_id, user_id, grantee_id, payload.access)
+        .await
+    {
+        Ok(permission) => (StatusCode::OK, Json(PermissionResponse::from(permission))).into_response(),
+        Err(e) => share_error("Failed to share file", e),
+    }
+}
+
+/// GET /files/:id/permissions - List the users a file is shared with
+pub async fn list_permissions(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    match state.storage_service.list_permissions_page(&file_id, user_id, page.limit(), offset).await {
+        Ok((permissions, total)) => Page::from_offset(permissions, offset, page.limit(), total)
+            .map(PermissionResponse::from)
+            .into_response(),
+        Err(e) => share_error("Failed to list permissions", e),
+    }
+}
+
+/// DELETE /files/:id/permissions/:user_id - Stop sharing a file with another user
+pub async fn revoke_permission(
+    State(state): State<Arc<AppState>>,
+    Path((file_id, grantee_id)): Path<(String, i64)>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    match state.storage_service.revoke_permission(&file_id, user_id, grantee_id).await {
+        Ok(_) => {
+            let response = RevokeShareResponse {
+                success: true,
+                message: "Permission revoked".to_string(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => share_error("Failed to revoke permission", e),
+    }
+}
+
+/// GET /files/shared - List the files others shared with the authenticated user
+/// They are downloaded with `GET /files/:id` like the user's own files
+pub async fn list_shared_with_me(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+    let offset = match page.offset() {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+
+    let options = FileListOptions::new(page.limit(), offset);
+    match state.storage_service.files_shared_with_page(user_id, &options).await {
+        Ok(results) => Page::from_offset(results.files, offset, page.limit(), results.total)
+            .map(FileResponse::from)
+            .into_response(),
+        Err(e) => share_error("Failed to list shared files", e),
+    }
+}
+
+/// GET /share/:token - Download a shared file without authentication
+/// Password-protected links take the password from `X-Share-Password`, never the URL, where it
+/// would end up in logs and `Referer` headers. Wrong passwords are throttled like logins, with
+/// the link standing in for the account.
+pub async fn open_share(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let (share, file) = match state.storage_service.resolve_share(&token).await {
+        Ok(resolved) => resolved,
+        Err(e) => return share_error("Failed to open share link", e),
+    };
+
+    if let Some(password_hash) = &share.password_hash {
+        // The token can't be mistaken for an email address, so links get buckets of their own
+        let limit_key = format!("share link {}", share.token);
+        if let Err(retry_after) = state.login_limiter.check(ip, &limit_key, Instant::now()) {
+            return too_many_attempts(retry_after);
+        }
+
+        let password = headers.get(SHARE_PASSWORD_HEADER).and_then(|v| v.to_str().ok());
+        let verified = password
+            .map(|password| auth::verify_password(password, password_hash).unwrap_or(false))
+            .unwrap_or(false);
+        if verified {
+            state.login_limiter.record_success(ip, &limit_key);
+        } else {
+            state.login_limiter.record_failure(ip, &limit_key, Instant::now());
+            let error = ErrorResponse {
+                error: "This share link requires a valid password".to_string(),
+            };
+            return (StatusCode::UNAUTHORIZED, Json(error)).into_response();
+        }
+    }
+
+    match state.storage_service.retrieve_shared(&share, &file).await {
+        Ok(reader) => {
+            let mut headers = HeaderMap::new();
+
+            if let Some(mime) = file.content_type() {
+                if let Ok(header_value) = mime.parse() {
+                    headers.insert(header::CONTENT_TYPE, header_value);
+                }
+            }
+
+            let disposition = attachment_disposition(&file.original_name);
+            if let Ok(header_value) = disposition.parse() {
+                headers.insert(header::CONTENT_DISPOSITION, header_value);
+            }
+
+            // Never let shared caches keep a copy of a link that can be revoked
+            headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static("private, no-store"));
+            headers.insert(header::CONTENT_LENGTH, file.size.into());
+
+            // Streamed, and paced against the owner's download rate like their own downloads
+            let body = download_body(&state, file.user_id, &file, reader);
+            (StatusCode::OK, headers, body).into_response()
+        }
+        Err(e) => share_error("Failed to download shared file", e),
+    }
+}
@@ -0,0 +1,373 @@
+//! Admin SQL console
+//!
+//! Service accounts can run one statement per request against the application database.
+//! Statements are read-only unless the request sets `allow_write` and carries a sudo token,
+//! queries return at most `[sql_console] max_rows` rows, anything running longer than
+//! `timeout_ms` is abandoned, and every statement, allowed or not, is written to the admin's
+//! activity feed. Reads run in a transaction that is rolled back, so a read that writes after
+//! all, such as through a function with side effects, changes nothing. Writes run in a
+//! transaction that is only committed when they finish in time.
+
+use activity::{ActivityEvent, ActivityKind};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::db_handlers::bump_all_table_versions;
+use crate::file_handlers::ErrorResponse;
+use crate::middleware::{AuthUser, ClientIp, SUDO_TOKEN_HEADER};
+use crate::AppState;
+
+/// Keywords that make a statement a write, wherever they appear outside literals
+const WRITE_KEYWORDS: &[&str] = &[
+    "INSERT", "UPDATE", "DELETE", "REPLACE", "MERGE", "UPSERT", "INTO",
+    "CREATE", "DROP", "ALTER", "TRUNCATE", "RENAME",
+    "ATTACH", "DETACH", "PRAGMA", "VACUUM", "REINDEX", "ANALYZE",
+    "GRANT", "REVOKE", "LOCK", "UNLOCK", "CALL", "SET", "LOAD", "HANDLER",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatementKind {
+    /// A query whose rows can be limited by wrapping it
+    Query,
+    /// A read that can't be wrapped; its rows are cut off after fetching
+    Explain,
+    /// Anything that may change data or schema
+    Write,
+}
+
+impl StatementKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StatementKind::Query | StatementKind::Explain => "read",
+            StatementKind::Write => "write",
+        }
+    }
+}
+
+/// Uppercased words of a statement outside string literals, quoted identifiers, and comments
+/// Fails when there is more than one statement
+fn statement_words(sql: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            word.push(c.to_ascii_uppercase());
+            continue;
+        }
+        if !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+
+        match c {
+            // Doubled quotes are escapes; skipping them as two literals is equivalent. Whether a
+            // backslash escapes the quote after it depends on the database, and guessing wrong
+            // would hide the end of the literal, so quoted text with one is refused.
+            '\'' | '"' | '`' => {
+                for next in chars.by_ref() {
+                    if next == '\\' {
+                        return Err("Backslashes in quoted text are not supported".to_string());
+                    }
+                    if next == c {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+            }
+            ';' => {
+                if chars.any(|next| !next.is_whitespace() && next != ';') {
+                    return Err("Only one statement can be run at a time".to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+/// Decide whether a statement only reads
+/// Errs on the side of `Write`: a read that mentions a write keyword needs the write flag too
+fn classify(sql: &str) -> Result<StatementKind, String> {
+    let words = statement_words(sql)?;
+    let Some(first) = words.first() else {
+        return Err("Empty statement".to_string());
+    };
+
+    if words.iter().any(|word| WRITE_KEYWORDS.contains(&word.as_str())) {
+        return Ok(StatementKind::Write);
+    }
+    Ok(match first.as_str() {
+        "SELECT" | "WITH" | "VALUES" => StatementKind::Query,
+        "EXPLAIN" => StatementKind::Explain,
+        _ => StatementKind::Write,
+    })
+}
+
+/// Whether a statement mentions `table` as a whole word anywhere, quoted or not
+/// Errs on the side of yes: a mention inside a literal counts too
+fn mentions_table(sql: &str, table: &str) -> bool {
+    let sql = sql.to_ascii_lowercase();
+    let table = table.to_ascii_lowercase();
+    let is_word_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    sql.match_indices(&table).any(|(start, _)| {
+        let before = sql[..start].chars().next_back();
+        let after = sql[start + table.len()..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SqlRequest {
+    pub sql: String,
+    /// Allow a statement that modifies data; also requires a sudo token
+    #[serde(default)]
+    pub allow_write: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SqlResponse {
+    /// Rows returned by a read
+    pub rows: Vec<JsonValue>,
+    /// Rows changed by a write
+    pub rows_affected: Option<u64>,
+    /// Whether rows past `max_rows` were cut off
+    pub truncated: bool,
+    pub duration_ms: u64,
+}
+
+/// Record a console statement on the admin's activity feed, and that it ran in the server log
+/// The statement itself only goes to the feed, since literals in it may hold secrets.
+async fn audit(state: &AppState, admin_id: i64, client: &JsonValue, sql: &str, kind: Option<StatementKind>, outcome: &str) {
+    let kind = kind.map(|kind| kind.as_str()).unwrap_or("invalid");
+    println!("🛠️  SQL console ({}) by user {} [{}]", kind, admin_id, outcome);
+
+    let event = ActivityEvent::new(admin_id, ActivityKind::AdminAction, "sql_console", kind)
+        .with_data(serde_json::json!({
+            "action": "sql.executed",
+            "sql": sql,
+            "outcome": outcome,
+            "client": client,
+        }));
+    state.activity_service.record_best_effort(event).await;
+}
+
+/// POST /admin/sql - Run one SQL statement (read-only unless `allow_write` and a sudo token are sent)
+pub async fn run_sql(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    client_ip: ClientIp,
+    headers: HeaderMap,
+    Json(request): Json<SqlRequest>,
+) -> impl IntoResponse {
+    let admin_id = user.id.unwrap();
+    let client = client_ip.describe(&state);
+    let sql = request.sql.trim().trim_end_matches(';').trim_end();
+
+    let kind = match classify(sql) {
+        Ok(kind) => kind,
+        Err(error) => {
+            audit(&state, admin_id, &client, sql, None, &format!("rejected: {}", error)).await;
+            return (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response();
+        }
+    };
+
+    if kind == StatementKind::Write {
+        if !request.allow_write {
+            audit(&state, admin_id, &client, sql, Some(kind), "rejected: allow_write not set").await;
+            let error = ErrorResponse {
+                error: format!(
+                    "This statement may modify data. Set \"allow_write\": true and send a {} header to run it.",
+                    SUDO_TOKEN_HEADER
+                ),
+            };
+            return (StatusCode::FORBIDDEN, Json(error)).into_response();
+        }
+
+        let sudo_verified = headers
+            .get(SUDO_TOKEN_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|token| state.auth_service.validate_sudo(token, &user).is_ok())
+            .unwrap_or(false);
+        if !sudo_verified {
+            audit(&state, admin_id, &client, sql, Some(kind), "rejected: no sudo token").await;
+            let error = ErrorResponse {
+                error: format!("Writes require a valid {} header. Obtain one from POST /auth/sudo.", SUDO_TOKEN_HEADER),
+            };
+            return (StatusCode::FORBIDDEN, Json(error)).into_response();
+        }
+
+        // Held rows can't be deleted, and holds are only changed through /admin/holds
+        let mut locked = match state.storage_service.tables_on_legal_hold().await {
+            Ok(tables) => tables,
+            Err(e) => {
+                let error = ErrorResponse {
+                    error: format!("Failed to check legal holds: {}", e),
+                };
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response();
+            }
+        };
+        locked.push(storage::LEGAL_HOLDS_TABLE.to_string());
+        if let Some(table) = locked.iter().find(|table| mentions_table(sql, table)) {
+            audit(&state, admin_id, &client, sql, Some(kind), &format!("rejected: {} is under a legal hold", table)).await;
+            let error = ErrorResponse {
+                error: format!("Writes to {} are blocked by legal holds", table),
+            };
+            return (StatusCode::LOCKED, Json(error)).into_response();
+        }
+    }
+
+    let limits = &state.sql_console;
+    let timeout = Duration::from_millis(limits.timeout_ms);
+    let db = &state.db;
+    let started = Instant::now();
+
+    let result = match kind {
+        // Committed only once the statement finished in time: one that runs over is rolled back
+        // with its transaction, rather than landing after the client was told it timed out
+        StatementKind::Write => match db.begin().await {
+            Ok(tx) => {
+                let executed = tokio::time::timeout(timeout, tx.execute(sql, &[])).await;
+                match executed {
+                    Ok(Ok(affected)) => Ok(tx.commit().await.map(|_| (Vec::new(), Some(affected)))),
+                    Ok(Err(e)) => Ok(Err(e)),
+                    Err(elapsed) => Err(elapsed),
+                }
+            }
+            Err(e) => Ok(Err(e)),
+        },
+        _ => tokio::time::timeout(timeout, async {
+            // Reads are never committed: dropping the transaction rolls back anything they did
+            let read = move |sql: String| async move {
+                let tx = db.begin().await?;
+                tx.fetch_all_params(&sql, &[]).await
+            };
+            if kind == StatementKind::Query {
+                // One row past the limit tells whether anything was cut off
+                let limited = format!("SELECT * FROM ({}) AS console_query LIMIT {}", sql, limits.max_rows + 1);
+                read(limited).await.map(|rows| (rows, None))
+            } else {
+                read(sql.to_string()).await.map(|rows| (rows, None))
+            }
+        })
+        .await,
+    };
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(Ok((mut rows, rows_affected))) => {
+            // Any table may have changed, so no cached `/db` list stays valid
+            if kind == StatementKind::Write {
+                bump_all_table_versions(&state).await;
+            }
+            let truncated = rows.len() > limits.max_rows;
+            rows.truncate(limits.max_rows);
+
+            let outcome = match rows_affected {
+                Some(affected) => format!("{} row(s) affected in {} ms", affected, duration_ms),
+                None => format!("{} row(s) returned in {} ms", rows.len(), duration_ms),
+            };
+            audit(&state, admin_id, &client, sql, Some(kind), &outcome).await;
+
+            let response = SqlResponse {
+                rows,
+                rows_affected,
+                truncated,
+                duration_ms,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Ok(Err(e)) => {
+            audit(&state, admin_id, &client, sql, Some(kind), &format!("failed: {}", e)).await;
+            let error = ErrorResponse {
+                error: format!("Statement failed: {}", e),
+            };
+            (StatusCode::BAD_REQUEST, Json(error)).into_response()
+        }
+        Err(_) => {
+            // The rollback can't be confirmed once the database stops answering in time
+            let outcome = if kind == StatementKind::Write {
+                bump_all_table_versions(&state).await;
+                "timed out, outcome unknown"
+            } else {
+                "timed out"
+            };
+            audit(&state, admin_id, &client, sql, Some(kind), outcome).await;
+            let error = ErrorResponse {
+                error: format!("Statement did not finish within {} ms", limits.timeout_ms),
+            };
+            (StatusCode::GATEWAY_TIMEOUT, Json(error)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads() {
+        assert_eq!(classify("SELECT * FROM users").unwrap(), StatementKind::Query);
+        assert_eq!(classify("with recent as (select 1) select * from recent;").unwrap(), StatementKind::Query);
+        assert_eq!(classify("EXPLAIN QUERY PLAN SELECT * FROM files").unwrap(), StatementKind::Explain);
+        // Keywords inside literals, quoted identifiers, and comments don't count
+        assert_eq!(classify("SELECT 'drop table users' AS \"update\" -- delete\nFROM files").unwrap(), StatementKind::Query);
+    }
+
+    #[test]
+    fn test_writes() {
+        assert_eq!(classify("DELETE FROM sessions").unwrap(), StatementKind::Write);
+        assert_eq!(classify("WITH doomed AS (SELECT id FROM files) DELETE FROM files").unwrap(), StatementKind::Write);
+        assert_eq!(classify("SELECT * INTO OUTFILE '/tmp/x' FROM users").unwrap(), StatementKind::Write);
+        assert_eq!(classify("PRAGMA journal_mode = DELETE").unwrap(), StatementKind::Write);
+        assert_eq!(classify("VACUUM").unwrap(), StatementKind::Write);
+    }
+
+    #[test]
+    fn test_mentions_table() {
+        assert!(mentions_table("DELETE FROM files WHERE id = 'x'", "files"));
+        assert!(mentions_table("delete from \"FILES\"", "files"));
+        assert!(!mentions_table("DELETE FROM file_favorites", "files"));
+        assert!(!mentions_table("UPDATE profiles SET x = 1", "files"));
+    }
+
+    #[test]
+    fn test_rejected() {
+        assert!(classify("   ").is_err());
+        assert!(classify("SELECT 1; DROP TABLE users").is_err());
+        assert!(classify("SELECT 1;;").is_ok());
+        // The second statement is only hidden from a scanner that gets quotes wrong
+        assert!(classify("SELECT '; DROP TABLE users; --'").is_ok());
+        // MySQL reads `\'` as an escaped quote, so this hides a second statement there
+        assert!(classify("SELECT 'a\\''; DROP TABLE x; '").is_err());
+        assert!(classify("SELECT 'C:\\temp'").is_err());
+    }
+}
@@ -1,20 +1,249 @@
-use auth::AuthService;
-use core::Database;
-use storage::TransactionalStorageService;
+use activity::{ActivityService, AllowOwnEvents, RealtimeAuthorizer};
+use announcements::AnnouncementService;
+use auth::{AuthService, LogMailer, Mailer};
+use billing::BillingService;
+use comments::CommentService;
+use core::{Database, DownloadLimitsConfig, FeaturesConfig, JobsConfig, LoginRateLimitConfig, SignupConfig, SqlConsoleConfig};
+use std::sync::Arc;
+use storage::{TransactionalStorageService, UrlImporter, UserBuckets, Webhooks};
+use streaming::EventStream;
+
+use crate::cluster::Deployment;
+use crate::db_handlers::{EncryptedColumns, ReadPolicies, ServerManagedColumns};
+use crate::deprecation::Deprecations;
+use crate::jobs::JobScheduler;
+use crate::login_limit::LoginLimiter;
+use crate::metrics::Metrics;
+use crate::rate_limit::RateLimiter;
+use crate::row_policies::RowPolicies;
+use crate::throttle::DownloadThrottle;
 
 /// Application state shared across all handlers
 pub struct AppState {
     pub db: Database,
     pub auth_service: AuthService,
     pub storage_service: TransactionalStorageService,
+    pub announcement_service: AnnouncementService,
+    pub comment_service: CommentService,
+    pub activity_service: ActivityService,
+    /// Checks realtime subscriptions and each event pushed to them
+    pub realtime_authorizer: Arc<dyn RealtimeAuthorizer>,
+    /// Sends share invitations; prints them to stdout unless a mail transport is configured
+    pub mailer: Arc<dyn Mailer>,
+    /// Present when billing is configured
+    pub billing_service: Option<Arc<BillingService>>,
+    /// Per-minute request counts checked against billing plans
+    pub rate_limiter: RateLimiter,
+    /// Bandwidth caps of file downloads; unlimited by default
+    pub download_throttle: DownloadThrottle,
+    /// Throttles logins and signups per IP address and per email address
+    pub login_limiter: LoginLimiter,
+    /// Present when users may store their files in their own bucket
+    pub user_buckets: Option<Arc<UserBuckets>>,
+    /// Present when webhooks are configured
+    pub webhooks: Option<Arc<Webhooks>>,
+    /// Present when files may be imported from remote URLs
+    pub url_importer: Option<Arc<UrlImporter>>,
+    /// Present when change events are published to message queues
+    pub event_stream: Option<Arc<EventStream>>,
+    pub metrics: Arc<Metrics>,
+    /// Queue of background jobs, inspected through `/admin/jobs`
+    pub jobs: Arc<JobScheduler>,
+    /// Bearer token required to scrape `/metrics`; open when unset
+    pub metrics_token: Option<String>,
+    /// Route groups mounted by `router()`
+    pub features: FeaturesConfig,
+    /// Row and time limits of `POST /admin/sql`
+    pub sql_console: SqlConsoleConfig,
+    /// Who may create an account
+    pub signup: SignupConfig,
+    /// Columns only the service role may write through `/db`
+    pub server_managed_columns: ServerManagedColumns,
+    /// Default ordering and page limits of `/db` reads
+    pub read_policies: ReadPolicies,
+    /// Columns of `/db` tables stored encrypted
+    pub encrypted_columns: EncryptedColumns,
+    /// Which rows of `/db` tables users may read and insert
+    pub row_policies: RowPolicies,
+    /// Proxies whose `X-Forwarded-For` entries are trusted for client addresses; the
+    /// connection's peer is used when 0
+    pub trusted_proxy_hops: usize,
+    /// Deprecated routes and response fields
+    pub deprecations: Deprecations,
+    /// How the instance was deployed, for the cluster report
+    pub deployment: Deployment,
 }
 
 impl AppState {
-    pub fn new(db: Database, auth_service: AuthService, storage_service: TransactionalStorageService) -> Self {
+    pub fn new(
+        db: Database,
+        auth_service: AuthService,
+        storage_service: TransactionalStorageService,
+        announcement_service: AnnouncementService,
+        comment_service: CommentService,
+        activity_service: ActivityService,
+    ) -> Self {
+        let metrics = Arc::new(Metrics::default());
         Self { 
             db, 
             auth_service,
             storage_service,
+            announcement_service,
+            comment_service,
+            activity_service,
+            realtime_authorizer: Arc::new(AllowOwnEvents),
+            mailer: Arc::new(LogMailer),
+            billing_service: None,
+            rate_limiter: RateLimiter::default(),
+            download_throttle: DownloadThrottle::default(),
+            login_limiter: LoginLimiter::default(),
+            user_buckets: None,
+            webhooks: None,
+            url_importer: None,
+            event_stream: None,
+            metrics: metrics.clone(),
+            jobs: Arc::new(JobScheduler::new(metrics)),
+            metrics_token: None,
+            features: FeaturesConfig::default(),
+            sql_console: SqlConsoleConfig::default(),
+            signup: SignupConfig::default(),
+            server_managed_columns: ServerManagedColumns::default(),
+            read_policies: ReadPolicies::default(),
+            encrypted_columns: EncryptedColumns::default(),
+            row_policies: RowPolicies::default(),
+            trusted_proxy_hops: 0,
+            deprecations: Deprecations::default(),
+            deployment: Deployment::default(),
         }
     }
+
+    /// Send email through this mailer instead of printing it
+    pub fn with_mailer(mut self, mailer: Arc<dyn Mailer>) -> Self {
+        self.mailer = mailer;
+        self
+    }
+
+    /// Enable billing: Stripe webhooks and plan limit enforcement
+    pub fn with_billing(mut self, billing_service: Arc<BillingService>) -> Self {
+        self.billing_service = Some(billing_service);
+        self
+    }
+
+    /// Let users register their own bucket; `storage_service` must route to the same registry
+    pub fn with_user_buckets(mut self, user_buckets: Arc<UserBuckets>) -> Self {
+        self.user_buckets = Some(user_buckets);
+        self
+    }
+
+    /// Expose the webhook delivery log; `storage_service` must send through the same instance
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Let users import files from remote URLs with `POST /files/import`
+    pub fn with_url_importer(mut self, url_importer: Arc<UrlImporter>) -> Self {
+        self.url_importer = Some(url_importer);
+        self
+    }
+
+    /// Publish records created through `/db` to message queues
+    /// Storage changes are relayed from `storage_service` by the caller
+    pub fn with_event_stream(mut self, event_stream: Arc<EventStream>) -> Self {
+        self.event_stream = Some(event_stream);
+        self
+    }
+
+    /// Check realtime subscriptions and events with `authorizer` on top of the feed-owner rule
+    pub fn with_realtime_authorizer(mut self, authorizer: Arc<dyn RealtimeAuthorizer>) -> Self {
+        self.realtime_authorizer = authorizer;
+        self
+    }
+
+    /// Require `token` as a bearer token to scrape `/metrics`
+    pub fn with_metrics_token(mut self, token: Option<String>) -> Self {
+        self.metrics_token = token;
+        self
+    }
+
+    /// Schedule background jobs with the concurrency, priorities, and retries in `config`
+    /// Job types are registered on `jobs` afterwards, which picks up `config`'s overrides.
+    pub fn with_jobs(mut self, config: &JobsConfig) -> Self {
+        self.jobs = Arc::new(JobScheduler::with_config(self.metrics.clone(), config));
+        self
+    }
+
+    /// Cap the bandwidth of each download and of each user's downloads together
+    pub fn with_download_limits(mut self, limits: &DownloadLimitsConfig) -> Self {
+        self.download_throttle =
+            DownloadThrottle::new(limits.connection_bytes_per_second, limits.user_bytes_per_second);
+        self
+    }
+
+    /// Throttle logins and signups to these limits; attempts are unlimited otherwise
+    pub fn with_login_limits(mut self, limits: &LoginRateLimitConfig) -> Self {
+        self.login_limiter = LoginLimiter::new(limits);
+        self
+    }
+
+    /// Only mount the route groups enabled in `features`
+    pub fn with_features(mut self, features: FeaturesConfig) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Limit what the admin SQL console returns and how long its statements may run
+    pub fn with_sql_console(mut self, sql_console: SqlConsoleConfig) -> Self {
+        self.sql_console = sql_console;
+        self
+    }
+
+    /// Only let the signups `signup` allows create accounts
+    pub fn with_signup(mut self, signup: SignupConfig) -> Self {
+        self.signup = signup;
+        self
+    }
+
+    /// Refuse `/db` writes that set these columns unless they come from the service role
+    pub fn with_server_managed_columns(mut self, columns: ServerManagedColumns) -> Self {
+        self.server_managed_columns = columns;
+        self
+    }
+
+    /// Order and limit `/db` reads by these policies when clients pass nothing
+    pub fn with_read_policies(mut self, policies: ReadPolicies) -> Self {
+        self.read_policies = policies;
+        self
+    }
+
+    /// Encrypt these columns on `/db` writes and decrypt them on reads for their readers
+    pub fn with_encrypted_columns(mut self, columns: EncryptedColumns) -> Self {
+        self.encrypted_columns = columns;
+        self
+    }
+
+    /// Scope `/db` reads and inserts of users by these row-level security policies
+    pub fn with_row_policies(mut self, policies: RowPolicies) -> Self {
+        self.row_policies = policies;
+        self
+    }
+
+    /// Take client addresses from `X-Forwarded-For`, as appended by `hops` proxies; only safe
+    /// behind that many proxies that each append to it
+    pub fn with_trusted_proxy_hops(mut self, hops: usize) -> Self {
+        self.trusted_proxy_hops = hops;
+        self
+    }
+
+    /// Announce `deprecations` on the routes they name, and retire them at their sunset
+    pub fn with_deprecations(mut self, deprecations: Deprecations) -> Self {
+        self.deprecations = deprecations;
+        self
+    }
+
+    /// Describe the database, storage, and ingestion this instance runs with
+    pub fn with_deployment(mut self, deployment: Deployment) -> Self {
+        self.deployment = deployment;
+        self
+    }
 }
@@ -0,0 +1,139 @@
+//! Bandwidth caps of file downloads
+//!
+//! Each download is paced so it stays under the per-connection rate, and all of a user's
+//! downloads together stay under the per-user rate. After an idle spell up to a second's worth
+//! of bytes goes out at once, so small files aren't slowed down. Pacing is per instance.
+
+use axum::body::Bytes;
+use futures_util::stream::{BoxStream, Stream, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+
+/// Size of the chunks a download held in memory is paced in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Users tracked before those no longer downloading are pruned
+const MAX_TRACKED_USERS: usize = 10_000;
+
+/// Paces downloads to the configured rates
+#[derive(Default)]
+pub struct DownloadThrottle {
+    connection_rate: Option<u64>,
+    user_rate: Option<u64>,
+    users: Mutex<HashMap<i64, Arc<Mutex<Pace>>>>,
+}
+
+impl DownloadThrottle {
+    /// Rates are in bytes per second; `None` leaves that rate unlimited
+    pub fn new(connection_rate: Option<u64>, user_rate: Option<u64>) -> Self {
+        Self {
+            connection_rate: connection_rate.filter(|rate| *rate > 0),
+            user_rate: user_rate.filter(|rate| *rate > 0),
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether any download rate is capped
+    pub fn is_limited(&self) -> bool {
+        self.connection_rate.is_some() || self.user_rate.is_some()
+    }
+
+    /// Send the chunks of a download for `user_id` no faster than the rates allow
+    pub fn throttle<S>(&self, user_id: i64, stream: S) -> BoxStream<'static, std::io::Result<Bytes>>
+    where
+        S: Stream<Item = std::io::Result<Bytes>> + Send + 'static,
+    {
+        if !self.is_limited() {
+            return stream.boxed();
+        }
+
+        let mut connection = self.connection_rate.map(Pace::new);
+        let user = self.user_rate.map(|rate| self.user_pace(user_id, rate));
+        stream
+            .then(move |chunk| {
+                let len = chunk.as_ref().map_or(0, Bytes::len) as u64;
+                let now = Instant::now();
+                let mut send_at = now;
+                if let Some(pace) = &mut connection {
+                    send_at = send_at.max(pace.reserve(len, now));
+                }
+                if let Some(pace) = &user {
+                    send_at = send_at.max(pace.lock().unwrap().reserve(len, now));
+                }
+                async move {
+                    tokio::time::sleep_until(send_at).await;
+                    chunk
+                }
+            })
+            .boxed()
+    }
+
+    /// Send `data`, already read into memory, in chunks no faster than the rates allow
+    pub fn throttle_bytes(&self, user_id: i64, data: Bytes) -> BoxStream<'static, std::io::Result<Bytes>> {
+        let chunks: Vec<_> = (0..data.len())
+            .step_by(CHUNK_SIZE)
+            .map(|start| Ok(data.slice(start..(start + CHUNK_SIZE).min(data.len()))))
+            .collect();
+        self.throttle(user_id, futures_util::stream::iter(chunks))
+    }
+
+    fn user_pace(&self, user_id: i64, rate: u64) -> Arc<Mutex<Pace>> {
+        let mut users = self.users.lock().unwrap();
+        if users.len() >= MAX_TRACKED_USERS {
+            let now = Instant::now();
+            users.retain(|_, pace| Arc::strong_count(pace) > 1 || pace.lock().unwrap().next > now);
+        }
+        users.entry(user_id).or_insert_with(|| Arc::new(Mutex::new(Pace::new(rate)))).clone()
+    }
+}
+
+/// When the next bytes may be sent at a fixed rate
+#[derive(Debug)]
+struct Pace {
+    rate: u64,
+    next: Instant,
+}
+
+impl Pace {
+    fn new(rate: u64) -> Self {
+        Self { rate, next: Instant::now() }
+    }
+
+    /// Reserve `len` bytes, returning when they may be sent
+    fn reserve(&mut self, len: u64, now: Instant) -> Instant {
+        let earliest = now.checked_sub(Duration::from_secs(1)).unwrap_or(now);
+        let start = self.next.max(earliest);
+        self.next = start + Duration::from_secs_f64(len as f64 / self.rate as f64);
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pace() {
+        let now = Instant::now() + Duration::from_secs(10);
+        let mut pace = Pace { rate: 1000, next: now };
+
+        // A second's worth goes out at once after an idle spell
+        let idle = now + Duration::from_secs(5);
+        assert!(pace.reserve(500, idle) <= idle);
+        assert!(pace.reserve(500, idle) <= idle);
+
+        // Then bytes are spaced out at the rate
+        assert_eq!(pace.reserve(1000, idle), idle);
+        assert_eq!(pace.reserve(1000, idle), idle + Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_passes_through() {
+        let throttle = DownloadThrottle::new(None, Some(0));
+        let chunks = vec![Ok(Bytes::from_static(b"ab")), Ok(Bytes::from_static(b"cd"))];
+        let sent: Vec<_> = throttle.throttle(1, futures_util::stream::iter(chunks)).collect().await;
+        assert_eq!(sent.len(), 2);
+        assert!(throttle.users.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+//! Resumable uploads: `POST /files/uploads` starts one, chunks follow with
+//! `PATCH /files/uploads/:id?offset=N`, and the chunk carrying the last byte turns the upload
+//! into a file. Progress is kept in the database, so an interrupted client asks
+//! `GET /files/uploads/:id` where to continue, on whichever instance it reaches.
+//!
+//! Presigned uploads: `POST /files/presign-upload` returns a signed URL, and whoever holds it
+//! sends the file with one `PUT`, without credentials, so a browser can upload directly.
+
+use activity::{ActivityEvent, ActivityKind};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_util::io::StreamReader;
+
+use crate::file_handlers::{policy_violation_response, storage_error_status, ErrorResponse, FileResponse, UploadResponse};
+use crate::middleware::AuthUser;
+use crate::AppState;
+use storage::{StorageError, UploadSession, DEFAULT_PRESIGNED_UPLOAD_TTL_SECONDS, MAX_PRESIGNED_UPLOAD_TTL_SECONDS};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUploadRequest {
+    pub original_name: String,
+    /// Size of the complete file in bytes
+    pub size: u64,
+    pub mime_type: Option<String>,
+    /// Hex SHA-256 of the complete file, verified when the last chunk arrives
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChunkQuery {
+    /// Byte offset of the chunk in the file
+    pub offset: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadSessionResponse {
+    pub upload_id: String,
+    pub original_name: String,
+    pub mime_type: Option<String>,
+    pub size: i64,
+    /// Bytes received so far; the next chunk starts here
+    pub offset: i64,
+    pub checksum: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl From<UploadSession> for UploadSessionResponse {
+    fn from(session: UploadSession) -> Self {
+        Self {
+            upload_id: session.upload_id,
+            original_name: session.original_name,
+            mime_type: session.mime_type,
+            size: session.total_size,
+            offset: session.received_bytes,
+            checksum: session.checksum,
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PresignUploadRequest {
+    pub original_name: String,
+    /// Most bytes the file may have
+    pub max_size: u64,
+    pub mime_type: Option<String>,
+    /// Seconds until the URL expires if unused
+    pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresignUploadResponse {
+    pub upload_id: String,
+    /// URL to `PUT` the file to
+    pub url: String,
+    pub original_name: String,
+    pub max_size: u64,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The signed parts of a presigned upload URL
+#[derive(Debug, Deserialize)]
+pub struct PresignedUploadQuery {
+    pub expires: i64,
+    pub max_size: u64,
+    pub signature: String,
+}
+
+fn upload_error_response(action: &str, e: StorageError) -> axum::response::Response {
+    if let StorageError::PolicyViolation(violation) = e {
+        return policy_violation_response(violation);
+    }
+    let status = match &e {
+        // The client is out of step with the bytes received; it should ask for the offset
+        StorageError::PreconditionFailed(_) => StatusCode::CONFLICT,
+        _ => storage_error_status(&e),
+    };
+    let error = ErrorResponse {
+        error: format!("Failed to {}: {}", action, e),
+    };
+    (status, Json(error)).into_response()
+}
+
+/// POST /files/uploads - Start a resumable upload
+pub async fn create_upload(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<CreateUploadRequest>,
+) -> impl IntoResponse {
+    match state
+        .storage_service
+        .create_upload_session(user.id.unwrap(), &payload.original_name, payload.mime_type, payload.size, payload.checksum)
+        .await
+    {
+        Ok(session) => (StatusCode::CREATED, Json(UploadSessionResponse::from(session))).into_response(),
+        Err(e) => upload_error_response("start upload", e),
+    }
+}
+
+/// GET /files/uploads/:id - How much of an upload has arrived
+pub async fn get_upload(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.storage_service.get_upload_session(&upload_id, user.id.unwrap()).await {
+        Ok(session) => (StatusCode::OK, Json(UploadSessionResponse::from(session))).into_response(),
+        Err(e) => upload_error_response("get upload", e),
+    }
+}
+
+/// PATCH /files/uploads/:id?offset=N - Send a chunk of an upload
+/// Returns the upload's progress, or 201 with the file once the last byte has arrived
+pub async fn upload_chunk(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<ChunkQuery>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let user_id = user.id.unwrap();
+
+    let session = match state
+        .storage_service
+        .write_upload_chunk(&upload_id, user_id, query.offset, &body)
+        .await
+    {
+        Ok(session) => session,
+        Err(e) => return upload_error_response("write chunk", e),
+    };
+    if !session.is_complete() {
+        return (StatusCode::OK, Json(UploadSessionResponse::from(session))).into_response();
+    }
+
+    match state.storage_service.complete_upload(&upload_id, user_id).await {
+        Ok(file) => {
+            let event = ActivityEvent::new(user_id, ActivityKind::FileUploaded, "files", &upload_id)
+                .with_data(serde_json::json!({
+                    "original_name": file.original_name,
+                    "size": file.size,
+                }));
+            state.activity_service.record_best_effort(event).await;
+
+            let response = UploadResponse {
+                success: true,
+                file: FileResponse::from(file),
+            };
+            (StatusCode::CREATED, Json(response)).into_response()
+        }
+        Err(e) => upload_error_response("complete upload", e),
+    }
+}
+
+/// DELETE /files/uploads/:id - Abandon an upload
+pub async fn cancel_upload(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.storage_service.cancel_upload(&upload_id, user.id.unwrap()).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => upload_error_response("cancel upload", e),
+    }
+}
+
+/// POST /files/presign-upload - Issue a one-time URL to upload a file to
+pub async fn presign_upload(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<PresignUploadRequest>,
+) -> impl IntoResponse {
+    let ttl_seconds = payload.ttl_seconds.unwrap_or(DEFAULT_PRESIGNED_UPLOAD_TTL_SECONDS);
+    if !(1..=MAX_PRESIGNED_UPLOAD_TTL_SECONDS).contains(&ttl_seconds) {
+        let error = ErrorResponse {
+            error: format!("ttl_seconds must be between 1 and {}", MAX_PRESIGNED_UPLOAD_TTL_SECONDS),
+        };
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    }
+
+    match state
+        .storage_service
+        .presign_upload(
+            user.id.unwrap(),
+            &payload.original_name,
+            payload.mime_type,
+            payload.max_size,
+            chrono::Duration::seconds(ttl_seconds),
+        )
+        .await
+    {
+        Ok((upload, url)) => {
+            let response = PresignUploadResponse {
+                upload_id: upload.id,
+                url,
+                original_name: upload.original_name,
+                max_size: upload.max_size,
+                expires_at: upload.expires_at,
+            };
+            (StatusCode::CREATED, Json(response)).into_response()
+        }
+        Err(e) => upload_error_response("presign upload", e),
+    }
+}
+
+/// PUT /uploads/:id?expires=..&max_size=..&signature=.. - Send the file of a presigned upload
+/// (no authentication; the signature stands in for it)
+pub async fn put_presigned_upload(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    Query(query): Query<PresignedUploadQuery>,
+    body: Body,
+) -> impl IntoResponse {
+    let reader = StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+
+    match state
+        .storage_service
+        .complete_presigned_upload(&upload_id, query.expires, query.max_size, &query.signature, reader)
+        .await
+    {
+        Ok(file) => {
+            if let Some(file_id) = &file.id {
+                let event = ActivityEvent::new(file.user_id, ActivityKind::FileUploaded, "files", file_id)
+                    .with_data(serde_json::json!({
+                        "original_name": file.original_name,
+                        "size": file.size,
+                        "via": "presigned_upload",
+                    }));
+                state.activity_service.record_best_effort(event).await;
+            }
+
+            let response = UploadResponse {
+                success: true,
+                file: FileResponse::from(file),
+            };
+            (StatusCode::CREATED, Json(response)).into_response()
+        }
+        Err(e) => upload_error_response("upload file", e),
+    }
+}
@@ -0,0 +1,56 @@
+//! Token and password hashing hot paths
+//!
+//! Run with `cargo bench -p auth`.
+
+use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+use auth::{generate_token, hash_password, validate_token, verify_password, Role};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand_core::OsRng;
+use std::hint::black_box;
+
+const SECRET: &str = "benchmark-secret-key";
+
+/// Argon2id cost presets an operator might pick: (name, memory KiB, iterations, lanes)
+const HASHING_PRESETS: [(&str, u32, u32, u32); 3] = [
+    ("low_memory", 12 * 1024, 3, 1),
+    ("default", Params::DEFAULT_M_COST, Params::DEFAULT_T_COST, Params::DEFAULT_P_COST),
+    ("hardened", 64 * 1024, 3, 4),
+];
+
+fn tokens(c: &mut Criterion) {
+    let token = generate_token("42", Role::User, SECRET, 3600).unwrap();
+
+    c.bench_function("token/generate", |b| {
+        b.iter(|| generate_token(black_box("42"), Role::User, SECRET, 3600).unwrap())
+    });
+    c.bench_function("token/validate", |b| {
+        b.iter(|| validate_token(black_box(&token), SECRET).unwrap())
+    });
+}
+
+fn passwords(c: &mut Criterion) {
+    let mut group = c.benchmark_group("password");
+    group.sample_size(10);
+
+    for (name, m_cost, t_cost, p_cost) in HASHING_PRESETS {
+        let params = Params::new(m_cost, t_cost, p_cost, None).unwrap();
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+        group.bench_with_input(BenchmarkId::new("hash", name), &argon2, |b, argon2| {
+            b.iter(|| {
+                let salt = SaltString::generate(&mut OsRng);
+                argon2.hash_password(black_box(b"correct-horse-battery"), &salt).unwrap().to_string()
+            })
+        });
+    }
+
+    // Login cost with the hashing parameters the server actually uses
+    let hash = hash_password("correct-horse-battery").unwrap();
+    group.bench_function("verify", |b| {
+        b.iter(|| verify_password(black_box("correct-horse-battery"), &hash).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, tokens, passwords);
+criterion_main!(benches);
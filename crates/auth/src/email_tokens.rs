@@ -0,0 +1,47 @@
+//! Single-use tokens emailed to users: password resets, address verification, magic links, and
+//! email changes
+//!
+//! Like refresh tokens, only a token's hash is stored. Each token serves the purpose it was
+//! issued for, only while the account still has the address it was sent to, and only once. An
+//! email change token is sent to the new address instead, which it switches the account to.
+//! Issuing a token replaces any unused one for the same user and purpose, so only the most
+//! recent email works.
+
+use chrono::Duration;
+
+/// Table of tokens sent by email
+pub const EMAIL_TOKENS_TABLE: &str = "email_tokens";
+
+/// What an emailed token lets its holder do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailTokenPurpose {
+    /// Choose a new password without the old one
+    PasswordReset,
+    /// Confirm the account's email address
+    EmailVerification,
+    /// Log in without the password
+    MagicLink,
+    /// Switch the account to the address the token was sent to
+    EmailChange,
+}
+
+impl EmailTokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailTokenPurpose::PasswordReset => "password_reset",
+            EmailTokenPurpose::EmailVerification => "email_verification",
+            EmailTokenPurpose::MagicLink => "magic_link",
+            EmailTokenPurpose::EmailChange => "email_change",
+        }
+    }
+
+    /// How long a token stays valid after it is sent
+    pub fn lifetime(&self) -> Duration {
+        match self {
+            EmailTokenPurpose::PasswordReset => Duration::hours(1),
+            EmailTokenPurpose::EmailVerification => Duration::days(3),
+            EmailTokenPurpose::MagicLink => Duration::minutes(15),
+            EmailTokenPurpose::EmailChange => Duration::days(1),
+        }
+    }
+}
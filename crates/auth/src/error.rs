@@ -22,6 +22,33 @@ pub enum AuthError {
     
     #[error("Invalid token")]
     InvalidToken,
+    
+    #[error("Sudo token required")]
+    SudoRequired,
+    
+    #[error("User not found: {0}")]
+    UserNotFound(i64),
+
+    #[error("Role not found: {0}")]
+    RoleNotFound(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("Account has been deleted")]
+    AccountDeleted,
+
+    #[error("Email address has not been verified")]
+    EmailNotVerified,
+
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+
+    #[error("GeoIP database error: {0}")]
+    GeoIpError(String),
+
+    #[error("Failed to send email: {0}")]
+    MailError(String),
 }
 
 pub type Result<T> = std::result::Result<T, AuthError>;
@@ -0,0 +1,113 @@
+//! IP geolocation of logins
+//!
+//! With a [`GeoLookup`] configured, `AuthService` records the country and city of every login
+//! on its session and in the login history, and flags logins from a country the user has not
+//! logged in from before.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::net::IpAddr;
+
+/// Table recording every successful login
+pub const LOGIN_HISTORY_TABLE: &str = "login_history";
+
+/// Where an IP address is located
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GeoLocation {
+    /// ISO 3166-1 alpha-2 country code, e.g. `DE`
+    pub country: Option<String>,
+    /// City name in English
+    pub city: Option<String>,
+}
+
+/// Resolves IP addresses to locations
+pub trait GeoLookup: Send + Sync {
+    /// Location of `ip`, or `None` if it is unknown (private ranges, for example)
+    fn lookup(&self, ip: IpAddr) -> Option<GeoLocation>;
+}
+
+/// Lookups against a MaxMind GeoIP2 or GeoLite2 City database
+#[cfg(feature = "geoip")]
+pub struct MaxMindLookup {
+    reader: maxminddb::Reader<Vec<u8>>,
+}
+
+#[cfg(feature = "geoip")]
+impl MaxMindLookup {
+    /// Load a `.mmdb` database into memory
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> crate::Result<Self> {
+        let reader = maxminddb::Reader::open_readfile(path.as_ref()).map_err(|e| {
+            crate::AuthError::GeoIpError(format!("failed to open {}: {}", path.as_ref().display(), e))
+        })?;
+        Ok(Self { reader })
+    }
+}
+
+#[cfg(feature = "geoip")]
+impl GeoLookup for MaxMindLookup {
+    fn lookup(&self, ip: IpAddr) -> Option<GeoLocation> {
+        let city: maxminddb::geoip2::City = self.reader.lookup(ip).ok()?;
+        let location = GeoLocation {
+            country: city.country.and_then(|country| country.iso_code).map(str::to_string),
+            city: city.city
+                .and_then(|city| city.names)
+                .and_then(|names| names.get("en").map(|name| name.to_string())),
+        };
+        (location != GeoLocation::default()).then_some(location)
+    }
+}
+
+/// One successful login, as shown in a user's login history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LoginRecord {
+    pub user_id: i64,
+    pub ip_address: Option<String>,
+    pub country: Option<String>,
+    pub city: Option<String>,
+    /// The user had logged in before, but never from this country
+    pub new_country: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl LoginRecord {
+    /// Read a login from a database row
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        let string = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+
+        Some(Self {
+            user_id: row.get("user_id").and_then(|v| v.as_i64())?,
+            ip_address: string("ip_address"),
+            country: string("country"),
+            city: string("city"),
+            new_country: row.get("new_country").and_then(|v| v.as_i64()).unwrap_or(0) != 0,
+            created_at: string("created_at")
+                .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(Utc::now),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login_record_from_json() {
+        let row = serde_json::json!({
+            "user_id": 7,
+            "ip_address": "203.0.113.9",
+            "country": "DE",
+            "city": null,
+            "new_country": 1,
+            "created_at": "2025-10-18T03:00:00+00:00",
+        });
+        let record = LoginRecord::from_json(&row).unwrap();
+        assert_eq!(record.user_id, 7);
+        assert_eq!(record.country.as_deref(), Some("DE"));
+        assert_eq!(record.city, None);
+        assert!(record.new_country);
+
+        assert!(LoginRecord::from_json(&serde_json::json!({ "country": "DE" })).is_none());
+    }
+}
@@ -1,8 +1,9 @@
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{decode, decode_header, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 
 use crate::error::{AuthError, Result};
+use crate::keys::{KeyRing, SigningKey};
 use crate::model::Role;
 
 /// JWT Claims structure
@@ -16,6 +17,15 @@ pub struct Claims {
     pub iat: i64,
     /// Expiration time (timestamp)
     pub exp: i64,
+    /// Whether this is a short-lived sudo token obtained by re-entering the password
+    #[serde(default)]
+    pub sudo: bool,
+    /// Whether this is an account recovery token, only good for restoring a deleted account
+    #[serde(default)]
+    pub recovery: bool,
+    /// Organization the token acts for; requests made with it count against the organization's plan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org: Option<i64>,
 }
 
 impl Claims {
@@ -29,6 +39,33 @@ impl Claims {
             role,
             iat: now.timestamp(),
             exp: expiration.timestamp(),
+            sudo: false,
+            recovery: false,
+            org: None,
+        }
+    }
+    
+    /// Claims of a sudo token, which elevates an existing session for destructive operations
+    pub fn sudo(subject: String, role: Role, expires_in_seconds: i64) -> Self {
+        Self {
+            sudo: true,
+            ..Self::new(subject, role, expires_in_seconds)
+        }
+    }
+
+    /// Claims of an account recovery token, which restores a deleted account during its grace period
+    pub fn recovery(subject: String, role: Role, expires_in_seconds: i64) -> Self {
+        Self {
+            recovery: true,
+            ..Self::new(subject, role, expires_in_seconds)
+        }
+    }
+
+    /// Claims of a session token that acts for an organization the user is a member of
+    pub fn organization(subject: String, role: Role, organization_id: i64, expires_in_seconds: i64) -> Self {
+        Self {
+            org: Some(organization_id),
+            ..Self::new(subject, role, expires_in_seconds)
         }
     }
     
@@ -47,15 +84,48 @@ impl Claims {
 /// * `expires_in_seconds` - Token expiration time in seconds (e.g., 3600 for 1 hour)
 pub fn generate_token(user_id: &str, role: Role, secret: &str, expires_in_seconds: i64) -> Result<String> {
     let claims = Claims::new(user_id.to_string(), role, expires_in_seconds);
-    
+    encode_claims(&claims, secret)
+}
+
+/// Generate a sudo token, which elevates an existing session for destructive operations
+/// 
+/// # Arguments
+/// * `user_id` - The user identifier
+/// * `role` - The user's role
+/// * `secret` - The secret key for signing the token
+/// * `expires_in_seconds` - Token expiration time in seconds; keep this short
+pub fn generate_sudo_token(user_id: &str, role: Role, secret: &str, expires_in_seconds: i64) -> Result<String> {
+    let claims = Claims::sudo(user_id.to_string(), role, expires_in_seconds);
+    encode_claims(&claims, secret)
+}
+
+fn encode_claims(claims: &Claims, secret: &str) -> Result<String> {
     encode(
         &Header::default(),
-        &claims,
+        claims,
         &EncodingKey::from_secret(secret.as_bytes()),
     )
     .map_err(|e| AuthError::TokenGenerationError(e.to_string()))
 }
 
+/// Sign claims with a key, naming it in the token's `kid` header
+pub(crate) fn sign_claims(claims: &Claims, key: &SigningKey) -> Result<String> {
+    let header = Header {
+        kid: Some(key.kid.clone()),
+        ..Header::default()
+    };
+    encode(&header, claims, &EncodingKey::from_secret(key.secret.as_bytes()))
+        .map_err(|e| AuthError::TokenGenerationError(e.to_string()))
+}
+
+/// Validate a token against the key its `kid` header names, returning the claims and the key
+pub(crate) fn validate_token_with_keys<'a>(token: &str, keys: &'a KeyRing) -> Result<(Claims, &'a SigningKey)> {
+    let header = decode_header(token).map_err(|e| AuthError::TokenValidationError(e.to_string()))?;
+    let key = keys.find(header.kid.as_deref(), Utc::now())
+        .ok_or_else(|| AuthError::TokenValidationError("Token was signed by an unknown or retired key".to_string()))?;
+    Ok((validate_token(token, &key.secret)?, key))
+}
+
 /// Validate a JWT token and return the claims
 /// 
 /// # Arguments
@@ -122,6 +192,19 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_sudo_token() {
+        let secret = "test_secret";
+        
+        let token = generate_sudo_token("user_123", Role::Service, secret, 300).unwrap();
+        let claims = validate_token(&token, secret).unwrap();
+        assert!(claims.sudo);
+        
+        let token = generate_token("user_123", Role::Service, secret, 300).unwrap();
+        let claims = validate_token(&token, secret).unwrap();
+        assert!(!claims.sudo);
+    }
+
     #[test]
     fn test_claims_creation() {
         let claims = Claims::new("user_456".to_string(), Role::Service, 3600);
@@ -131,4 +214,22 @@ mod tests {
         assert!(!claims.is_expired());
         assert!(claims.exp > claims.iat);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_validate_token_with_keys() {
+        let keys = KeyRing::new("configured_secret");
+        let key = keys.current(Utc::now());
+        let claims = Claims::new("user_123".to_string(), Role::User, 300);
+
+        let token = sign_claims(&claims, key).unwrap();
+        assert_eq!(decode_header(&token).unwrap().kid.as_deref(), Some(key.kid.as_str()));
+        let (validated, signer) = validate_token_with_keys(&token, &keys).unwrap();
+        assert_eq!(validated.sub, "user_123");
+        assert_eq!(signer.kid, key.kid);
+
+        // Tokens issued before keys were named are checked against the configured secret
+        let legacy = generate_token("user_123", Role::User, "configured_secret", 300).unwrap();
+        assert!(validate_token_with_keys(&legacy, &keys).is_ok());
+        assert!(validate_token_with_keys(&token, &KeyRing::new("other_secret")).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! JWT signing keys and their rotation
+//!
+//! Tokens are signed with the newest active key and name it in their `kid` header, so several
+//! keys can be valid at once. The configured `jwt_secret` is the first key. A rotation adds a
+//! random key to the database, which every instance picks up on its next reload; the new key
+//! starts signing once it has had time to reach them all, and the key it replaces keeps
+//! validating tokens until it retires at the end of the rotation window. Rotated keys are stored
+//! encrypted with a [`KeyCipher`], so reading the database alone isn't enough to forge tokens.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use chrono::{DateTime, Utc};
+use rand_core::{OsRng, RngCore};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Table of keys added by rotations, and of retirement dates for the configured secret
+pub const SIGNING_KEYS_TABLE: &str = "signing_keys";
+
+/// How long a rotated-out key keeps validating tokens by default (7 days)
+pub const DEFAULT_KEY_ROTATION_WINDOW_SECONDS: i64 = 7 * 24 * 3600;
+
+/// How often instances reload the keys from the database
+pub const KEY_RELOAD_INTERVAL_SECONDS: i64 = 30;
+
+/// Delay before a new key signs tokens, long enough for every instance to have loaded it
+pub const KEY_ACTIVATION_DELAY_SECONDS: i64 = 2 * KEY_RELOAD_INTERVAL_SECONDS;
+
+/// Prefix of stored secrets encrypted by a [`KeyCipher`]
+const ENCRYPTED_PREFIX: &str = "enc:";
+
+/// Bytes in an AES-GCM nonce
+const NONCE_LENGTH: usize = 12;
+
+/// A key tokens are signed and validated with
+#[derive(Clone, PartialEq, Eq, Serialize)]
+pub struct SigningKey {
+    /// Identifies the key in the `kid` header of the tokens it signs
+    pub kid: String,
+    #[serde(skip)]
+    pub(crate) secret: String,
+    /// Whether this is the configured `jwt_secret` rather than a key added by a rotation
+    pub configured: bool,
+    /// When the key starts signing tokens
+    pub activates_at: DateTime<Utc>,
+    /// When the key stops validating tokens, once a newer key has replaced it
+    pub retires_at: Option<DateTime<Utc>>,
+}
+
+impl SigningKey {
+    /// The key for the configured `jwt_secret`
+    pub fn configured(secret: &str) -> Self {
+        Self {
+            kid: key_id(secret),
+            secret: secret.to_string(),
+            configured: true,
+            activates_at: DateTime::UNIX_EPOCH,
+            retires_at: None,
+        }
+    }
+
+    /// A new random key
+    pub(crate) fn generate(activates_at: DateTime<Utc>) -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let secret: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        Self {
+            kid: key_id(&secret),
+            secret,
+            configured: false,
+            activates_at,
+            retires_at: None,
+        }
+    }
+
+    pub fn is_active(&self, at: DateTime<Utc>) -> bool {
+        self.activates_at <= at && !self.is_retired(at)
+    }
+
+    pub fn is_retired(&self, at: DateTime<Utc>) -> bool {
+        self.retires_at.is_some_and(|retires_at| retires_at <= at)
+    }
+}
+
+// The secret stays out of logs
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("kid", &self.kid)
+            .field("configured", &self.configured)
+            .field("activates_at", &self.activates_at)
+            .field("retires_at", &self.retires_at)
+            .finish()
+    }
+}
+
+/// Key ID of a secret; identifies the key without revealing it
+pub fn key_id(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))[..16].to_string()
+}
+
+/// Encrypts the secrets of rotated keys at rest with AES-256-GCM
+#[derive(Clone)]
+pub(crate) struct KeyCipher {
+    cipher: Aes256Gcm,
+}
+
+impl KeyCipher {
+    /// Derive the encryption key from a secret
+    /// The derivation is separate from [`key_id`], which tokens reveal in their `kid` header
+    pub(crate) fn new(secret: &str) -> Self {
+        let key = Sha256::digest(format!("projectkit signing key encryption:{}", secret).as_bytes());
+        Self {
+            cipher: Aes256Gcm::new_from_slice(&key).expect("SHA-256 output is a valid AES-256 key"),
+        }
+    }
+
+    /// Encrypt under a fresh random nonce, as `enc:` and hex of the nonce followed by the ciphertext
+    pub(crate) fn encrypt(&self, secret: &str) -> String {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self.cipher.encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+            .expect("AES-GCM encryption of a key secret can't fail");
+        let hex: String = nonce.iter().chain(&ciphertext).map(|b| format!("{:02x}", b)).collect();
+        format!("{}{}", ENCRYPTED_PREFIX, hex)
+    }
+
+    /// Decrypt a stored secret; secrets stored before encryption are returned as they are
+    /// Returns `None` for a secret encrypted with another key, or tampered with
+    pub(crate) fn decrypt(&self, stored: &str) -> Option<String> {
+        let Some(hex) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Some(stored.to_string());
+        };
+        let bytes = decode_hex(hex)?;
+        if bytes.len() < NONCE_LENGTH {
+            return None;
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+
+    /// Whether a stored secret predates encryption
+    pub(crate) fn is_plaintext(stored: &str) -> bool {
+        !stored.starts_with(ENCRYPTED_PREFIX)
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+/// Every key that may validate tokens
+#[derive(Debug, Clone)]
+pub struct KeyRing {
+    /// The configured key first, then rotated keys from oldest to newest
+    keys: Vec<SigningKey>,
+}
+
+impl KeyRing {
+    /// Only the configured secret, as before any rotation
+    pub fn new(secret: &str) -> Self {
+        Self { keys: vec![SigningKey::configured(secret)] }
+    }
+
+    /// The configured secret and the keys stored in the database
+    /// A row without a secret holds the retirement date of the configured secret it names.
+    /// Keys whose secret `cipher` can't decrypt are left out.
+    pub(crate) fn from_rows(secret: &str, cipher: &KeyCipher, rows: &[serde_json::Value]) -> Self {
+        let mut ring = Self::new(secret);
+        let timestamp = |row: &serde_json::Value, column: &str| {
+            row.get(column)
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+
+        for row in rows {
+            let Some(kid) = row.get("kid").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let retires_at = timestamp(row, "retires_at");
+            match row.get("secret").and_then(|v| v.as_str()) {
+                Some(stored) => match cipher.decrypt(stored) {
+                    Some(secret) => ring.keys.push(SigningKey {
+                        kid: kid.to_string(),
+                        secret,
+                        configured: false,
+                        activates_at: timestamp(row, "activates_at").unwrap_or(DateTime::UNIX_EPOCH),
+                        retires_at,
+                    }),
+                    None => eprintln!("⚠️  Signing key {} can't be decrypted with the configured key; ignoring it", kid),
+                },
+                None if kid == ring.keys[0].kid => ring.keys[0].retires_at = retires_at,
+                // A retirement date for a secret that is no longer configured
+                None => {}
+            }
+        }
+        ring.keys[1..].sort_by_key(|key| key.activates_at);
+        ring
+    }
+
+    /// The key new tokens are signed with: the newest active one
+    /// Falls back to the configured key when every key has retired, so tokens can still be issued
+    pub fn current(&self, at: DateTime<Utc>) -> &SigningKey {
+        self.keys.iter().rev().find(|key| key.is_active(at)).unwrap_or(&self.keys[0])
+    }
+
+    /// The key named by a token's `kid` header, if it may still validate tokens
+    /// Tokens without a `kid` were issued before keys were named, by the configured secret
+    pub fn find(&self, kid: Option<&str>, at: DateTime<Utc>) -> Option<&SigningKey> {
+        let kid = kid.unwrap_or(&self.keys[0].kid);
+        self.keys.iter().find(|key| key.kid == kid && !key.is_retired(at))
+    }
+
+    /// Every key, retired or not
+    pub fn keys(&self) -> &[SigningKey] {
+        &self.keys
+    }
+}
+
+/// Outcome of a key rotation
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyRotation {
+    /// The added key, which signs tokens from its activation on
+    pub key: SigningKey,
+    /// Keys that now retire at the end of the rotation window
+    pub retiring: Vec<SigningKey>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_key_ring() {
+        let now = Utc::now();
+        let configured = key_id("configured-secret");
+        let ring = KeyRing::new("configured-secret");
+        assert_eq!(ring.current(now).kid, configured);
+        assert_eq!(ring.find(None, now).unwrap().kid, configured);
+        assert!(ring.find(Some("unknown"), now).is_none());
+
+        let rows = vec![
+            serde_json::json!({"kid": configured, "secret": null, "retires_at": (now + Duration::days(7)).to_rfc3339()}),
+            serde_json::json!({"kid": "new", "secret": "s3cret", "activates_at": (now + Duration::seconds(60)).to_rfc3339(), "retires_at": null}),
+        ];
+        let ring = KeyRing::from_rows("configured-secret", &KeyCipher::new("configured-secret"), &rows);
+
+        // The new key validates straight away, but signs only once it is active
+        assert_eq!(ring.current(now).kid, configured);
+        assert_eq!(ring.find(Some("new"), now).unwrap().secret, "s3cret");
+        assert_eq!(ring.current(now + Duration::seconds(61)).kid, "new");
+
+        // The configured key retires at the end of the window
+        assert!(ring.find(None, now + Duration::days(6)).is_some());
+        assert!(ring.find(None, now + Duration::days(8)).is_none());
+        assert!(ring.find(Some("new"), now + Duration::days(8)).is_some());
+    }
+
+    #[test]
+    fn test_generated_keys() {
+        let key = SigningKey::generate(Utc::now());
+        assert_eq!(key.secret.len(), 64);
+        assert_eq!(key.kid, key_id(&key.secret));
+        assert_ne!(key.secret, SigningKey::generate(Utc::now()).secret);
+        assert!(!format!("{:?}", key).contains(&key.secret));
+    }
+
+    #[test]
+    fn test_key_cipher() {
+        let cipher = KeyCipher::new("encryption-key");
+        let stored = cipher.encrypt("s3cret");
+        assert!(!stored.contains("s3cret"));
+        assert_ne!(stored, cipher.encrypt("s3cret"));
+        assert_eq!(cipher.decrypt(&stored).as_deref(), Some("s3cret"));
+
+        // Another key can't read it, and secrets stored before encryption still load
+        assert!(KeyCipher::new("other-key").decrypt(&stored).is_none());
+        assert_eq!(cipher.decrypt("legacy").as_deref(), Some("legacy"));
+
+        let rows = vec![
+            serde_json::json!({"kid": "sealed", "secret": stored, "activates_at": Utc::now().to_rfc3339(), "retires_at": null}),
+        ];
+        let ring = KeyRing::from_rows("configured-secret", &cipher, &rows);
+        assert_eq!(ring.find(Some("sealed"), Utc::now()).unwrap().secret, "s3cret");
+        let ring = KeyRing::from_rows("configured-secret", &KeyCipher::new("other-key"), &rows);
+        assert!(ring.find(Some("sealed"), Utc::now()).is_none());
+    }
+}
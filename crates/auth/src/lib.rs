@@ -1,22 +1,43 @@
 // Core modules
+mod email_tokens;
 mod error;
+mod geo;
 mod password;
 mod jwt;
+mod keys;
+mod mailer;
+mod permissions;
+mod preferences;
+mod profile;
+mod refresh;
 
 // ORM-integrated modules
 pub mod model;
 pub mod service;
 
 // Re-export error types
+pub use email_tokens::{EmailTokenPurpose, EMAIL_TOKENS_TABLE};
 pub use error::{AuthError, Result};
 
 // Re-export crypto primitives (for standalone use without ORM)
 pub use password::{hash_password, verify_password};
-pub use jwt::{generate_token, validate_token, Claims};
+pub use jwt::{generate_sudo_token, generate_token, validate_token, Claims};
+pub use keys::{key_id, KeyRing, KeyRotation, SigningKey, DEFAULT_KEY_ROTATION_WINDOW_SECONDS, KEY_ACTIVATION_DELAY_SECONDS, KEY_RELOAD_INTERVAL_SECONDS, SIGNING_KEYS_TABLE};
+
+#[cfg(feature = "geoip")]
+pub use geo::MaxMindLookup;
+pub use geo::{GeoLocation, GeoLookup, LoginRecord, LOGIN_HISTORY_TABLE};
+#[cfg(feature = "smtp")]
+pub use mailer::SmtpMailer;
+pub use mailer::{Email, LogMailer, Mailer};
+pub use permissions::{is_valid_permission, is_valid_role_name, permission_matches, CustomRole, PERMISSIONS_TABLE, ROLES_TABLE, USER_ROLES_TABLE};
+pub use preferences::{NotificationKind, NotificationPreferences, NotificationPreferencesUpdate};
+pub use profile::{is_valid_locale, Profile, ProfileUpdate, MAX_BIO_CHARS, MAX_DISPLAY_NAME_CHARS, PROFILES_TABLE};
+pub use refresh::{DEFAULT_REFRESH_TOKEN_EXPIRY_SECONDS, REFRESH_TOKENS_TABLE};
 
 // Re-export ORM-integrated types
 pub use model::{User, Session, Role};
-pub use service::AuthService;
+pub use service::{AuthService, DEFAULT_DELETION_GRACE_SECONDS, SUDO_TOKEN_EXPIRY_SECONDS};
 
 /// Prelude module for convenient imports
 pub mod prelude {
@@ -0,0 +1,82 @@
+//! Outgoing email
+//!
+//! Messages go through a [`Mailer`]. [`LogMailer`] logs them instead of sending, which is
+//! what development servers use; with the `smtp` feature, [`SmtpMailer`] delivers them
+//! through an SMTP relay.
+
+use async_trait::async_trait;
+
+use crate::Result;
+
+/// A plain-text email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers email
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<()>;
+}
+
+/// Logs the recipient and subject of emails to stdout instead of sending them
+/// Bodies are left out, since the links they carry log in or reset passwords for whoever reads
+/// the log.
+#[derive(Debug, Clone, Default)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: &Email) -> Result<()> {
+        println!("📧 Email to {}: {} (body not logged)", email.to, email.subject);
+        Ok(())
+    }
+}
+
+/// Sends email through an SMTP relay over TLS
+#[cfg(feature = "smtp")]
+pub struct SmtpMailer {
+    transport: lettre::AsyncSmtpTransport<lettre::Tokio1Executor>,
+    from: lettre::message::Mailbox,
+}
+
+#[cfg(feature = "smtp")]
+impl SmtpMailer {
+    /// Connect to `host` on `port`, logging in when credentials are given
+    pub fn new(host: &str, port: u16, credentials: Option<(String, String)>, from: &str) -> Result<Self> {
+        let mail_error = |e: &dyn std::fmt::Display| crate::AuthError::MailError(e.to_string());
+
+        let mut builder = lettre::AsyncSmtpTransport::<lettre::Tokio1Executor>::relay(host)
+            .map_err(|e| mail_error(&e))?
+            .port(port);
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(lettre::transport::smtp::authentication::Credentials::new(username, password));
+        }
+        let from = from.parse().map_err(|e| mail_error(&format!("invalid sender '{}': {}", from, e)))?;
+
+        Ok(Self { transport: builder.build(), from })
+    }
+}
+
+#[cfg(feature = "smtp")]
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<()> {
+        use lettre::AsyncTransport;
+
+        let mail_error = |e: &dyn std::fmt::Display| crate::AuthError::MailError(e.to_string());
+        let to = email.to.parse().map_err(|e| mail_error(&format!("invalid recipient '{}': {}", email.to, e)))?;
+        let message = lettre::Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject.as_str())
+            .body(email.body.clone())
+            .map_err(|e| mail_error(&e))?;
+
+        self.transport.send(message).await.map_err(|e| mail_error(&e))?;
+        Ok(())
+    }
+}
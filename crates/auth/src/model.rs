@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// User role for authorization
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
     /// Regular user with basic permissions
@@ -48,6 +48,17 @@ pub struct User {
     pub role: Role,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// When the account was deleted; it can be restored until the grace period ends
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// When the user proved they receive mail at `email`
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// When the password was last changed
+    pub password_changed_at: Option<DateTime<Utc>>,
+    /// When every session of the account was last ended; tokens issued before then are refused
+    pub sessions_revoked_at: Option<DateTime<Utc>>,
+    /// Permissions granted by the custom roles the user holds; loaded when a token is validated
+    #[serde(skip)]
+    pub permissions: Vec<String>,
 }
 
 impl User {
@@ -66,6 +77,11 @@ impl User {
             role,
             created_at: now,
             updated_at: now,
+            deleted_at: None,
+            email_verified_at: None,
+            password_changed_at: None,
+            sessions_revoked_at: None,
+            permissions: Vec::new(),
         }
     }
 
@@ -79,10 +95,27 @@ impl User {
         self.role == Role::Service
     }
 
+    /// Check if user may do what `permission` covers, e.g. `files:delete`
+    /// Service accounts may do anything; see [`permission_matches`](crate::permission_matches)
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.is_service()
+            || self.permissions.iter().any(|granted| crate::permission_matches(granted, permission))
+    }
+
     /// Check if user is a regular user
     pub fn is_user(&self) -> bool {
         self.role == Role::User
     }
+
+    /// Check if the user has verified their email address
+    pub fn is_email_verified(&self) -> bool {
+        self.email_verified_at.is_some()
+    }
+
+    /// Check if the account is deleted and waiting to be purged
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
 }
 
 impl Model for User {
@@ -108,11 +141,23 @@ impl Model for User {
         map.insert("role".to_string(), Value::String(self.role.as_str().to_string()));
         map.insert("created_at".to_string(), Value::String(self.created_at.to_rfc3339()));
         map.insert("updated_at".to_string(), Value::String(self.updated_at.to_rfc3339()));
+        if let Some(deleted_at) = &self.deleted_at {
+            map.insert("deleted_at".to_string(), Value::String(deleted_at.to_rfc3339()));
+        }
+        if let Some(email_verified_at) = &self.email_verified_at {
+            map.insert("email_verified_at".to_string(), Value::String(email_verified_at.to_rfc3339()));
+        }
+        if let Some(password_changed_at) = &self.password_changed_at {
+            map.insert("password_changed_at".to_string(), Value::String(password_changed_at.to_rfc3339()));
+        }
+        if let Some(sessions_revoked_at) = &self.sessions_revoked_at {
+            map.insert("sessions_revoked_at".to_string(), Value::String(sessions_revoked_at.to_rfc3339()));
+        }
         map
     }
 
     fn columns() -> Vec<&'static str> {
-        vec!["email", "password_hash", "role", "created_at", "updated_at"]
+        vec!["email", "password_hash", "role", "created_at", "updated_at", "deleted_at", "email_verified_at", "password_changed_at", "sessions_revoked_at"]
     }
 }
 
@@ -160,6 +205,30 @@ impl FromRow for User {
             })
             .unwrap_or_else(Utc::now);
 
+        let deleted_at = row.get("deleted_at")
+            .and_then(|v| match v {
+                Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                _ => None,
+            });
+
+        let email_verified_at = row.get("email_verified_at")
+            .and_then(|v| match v {
+                Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                _ => None,
+            });
+
+        let password_changed_at = row.get("password_changed_at")
+            .and_then(|v| match v {
+                Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                _ => None,
+            });
+
+        let sessions_revoked_at = row.get("sessions_revoked_at")
+            .and_then(|v| match v {
+                Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                _ => None,
+            });
+
         Ok(User {
             id,
             email,
@@ -167,6 +236,11 @@ impl FromRow for User {
             role,
             created_at,
             updated_at,
+            deleted_at,
+            email_verified_at,
+            password_changed_at,
+            sessions_revoked_at,
+            permissions: Vec::new(),
         })
     }
 }
@@ -179,6 +253,11 @@ pub struct Session {
     pub token: String,
     pub expires_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
+    /// Address the session was opened from, when known
+    pub ip_address: Option<String>,
+    /// Country code and city of `ip_address`, when a GeoIP database is configured
+    pub country: Option<String>,
+    pub city: Option<String>,
 }
 
 impl Session {
@@ -189,6 +268,9 @@ impl Session {
             token,
             expires_at,
             created_at: Utc::now(),
+            ip_address: None,
+            country: None,
+            city: None,
         }
     }
 
@@ -219,11 +301,20 @@ impl Model for Session {
         map.insert("token".to_string(), Value::String(self.token.clone()));
         map.insert("expires_at".to_string(), Value::String(self.expires_at.to_rfc3339()));
         map.insert("created_at".to_string(), Value::String(self.created_at.to_rfc3339()));
+        if let Some(ip_address) = &self.ip_address {
+            map.insert("ip_address".to_string(), Value::String(ip_address.clone()));
+        }
+        if let Some(country) = &self.country {
+            map.insert("country".to_string(), Value::String(country.clone()));
+        }
+        if let Some(city) = &self.city {
+            map.insert("city".to_string(), Value::String(city.clone()));
+        }
         map
     }
 
     fn columns() -> Vec<&'static str> {
-        vec!["user_id", "token", "expires_at", "created_at"]
+        vec!["user_id", "token", "expires_at", "created_at", "ip_address", "country", "city"]
     }
 }
 
@@ -265,12 +356,20 @@ impl FromRow for Session {
             })
             .unwrap_or_else(Utc::now);
 
+        let string = |column: &str| row.get(column).and_then(|v| match v {
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        });
+
         Ok(Session {
             id,
             user_id,
             token,
             expires_at,
             created_at,
+            ip_address: string("ip_address"),
+            country: string("country"),
+            city: string("city"),
         })
     }
 }
\ No newline at end of file
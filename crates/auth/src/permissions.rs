@@ -0,0 +1,108 @@
+//! Custom roles and the permissions they grant
+//!
+//! Besides its built-in [`Role`](crate::Role), a user can hold any number of roles an app
+//! defines, each granting a set of permissions such as `files:delete`. A permission ending in
+//! `:*` grants everything under its prefix, and `*` grants everything. Service accounts hold
+//! every permission. Permissions are loaded when a token is validated, so changing a role takes
+//! effect on the next request.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Table of roles defined by the app
+pub const ROLES_TABLE: &str = "roles";
+
+/// Table of the permissions each role grants
+pub const PERMISSIONS_TABLE: &str = "permissions";
+
+/// Table of the roles held by each user
+pub const USER_ROLES_TABLE: &str = "user_roles";
+
+/// A role defined by the app
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CustomRole {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CustomRole {
+    pub(crate) fn from_json(row: &serde_json::Value, permissions: Vec<String>) -> Option<Self> {
+        Some(Self {
+            id: row.get("id")?.as_i64()?,
+            name: row.get("name")?.as_str()?.to_string(),
+            description: row.get("description").and_then(|v| v.as_str()).map(str::to_string),
+            permissions,
+            created_at: row
+                .get("created_at")
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))?,
+        })
+    }
+}
+
+/// Whether holding `granted` allows `required`
+pub fn permission_matches(granted: &str, required: &str) -> bool {
+    if granted == "*" || granted == required {
+        return true;
+    }
+    match granted.strip_suffix('*') {
+        Some(prefix) if prefix.ends_with(':') => required.starts_with(prefix),
+        _ => false,
+    }
+}
+
+/// Whether a permission is well formed: lowercase `resource:action` segments, where only the
+/// last segment may be `*`
+pub fn is_valid_permission(permission: &str) -> bool {
+    if permission == "*" {
+        return true;
+    }
+    let segments: Vec<&str> = permission.split(':').collect();
+    segments.len() >= 2
+        && segments.iter().enumerate().all(|(i, segment)| {
+            (*segment == "*" && i == segments.len() - 1) || is_valid_name(segment)
+        })
+}
+
+/// Whether a role name is well formed and not one of the built-in roles
+pub fn is_valid_role_name(name: &str) -> bool {
+    is_valid_name(name) && crate::Role::from_str(name).is_none()
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permission_matches() {
+        assert!(permission_matches("files:delete", "files:delete"));
+        assert!(permission_matches("files:*", "files:delete"));
+        assert!(permission_matches("*", "billing:refund"));
+        assert!(!permission_matches("files:read", "files:delete"));
+        assert!(!permission_matches("files:*", "filesystem:delete"));
+        assert!(!permission_matches("files*", "files:delete"));
+    }
+
+    #[test]
+    fn test_names() {
+        assert!(is_valid_permission("files:delete"));
+        assert!(is_valid_permission("reports:*"));
+        assert!(is_valid_permission("*"));
+        assert!(!is_valid_permission("files"));
+        assert!(!is_valid_permission("files:*:read"));
+        assert!(!is_valid_permission("Files:Delete"));
+        assert!(is_valid_role_name("moderator"));
+        assert!(!is_valid_role_name("service"));
+        assert!(!is_valid_role_name("team lead"));
+    }
+}
@@ -0,0 +1,107 @@
+//! Per-user notification preferences
+//!
+//! Every kind of notification is on until the user turns it off. Anything that notifies a user
+//! should check [`AuthService::should_notify`](crate::AuthService::should_notify) before delivering.
+
+use serde::{Deserialize, Serialize};
+
+/// Table holding each user's preferences, as one JSON document per user
+pub const PREFERENCES_TABLE: &str = "notification_preferences";
+
+/// Something a user can be notified about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// Email sent when someone logs in to the account
+    LoginAlert,
+    /// A file was shared with the user
+    ShareReceived,
+    /// The user is close to their storage quota
+    QuotaWarning,
+    /// An admin published an announcement
+    Announcements,
+}
+
+/// Which notifications a user wants to receive
+/// Stored as JSON, so kinds added later default to on for existing users
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationPreferences {
+    pub login_alert: bool,
+    pub share_received: bool,
+    pub quota_warning: bool,
+    pub announcements: bool,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        Self {
+            login_alert: true,
+            share_received: true,
+            quota_warning: true,
+            announcements: true,
+        }
+    }
+}
+
+impl NotificationPreferences {
+    /// Whether the user wants notifications of this kind
+    pub fn allows(&self, kind: NotificationKind) -> bool {
+        match kind {
+            NotificationKind::LoginAlert => self.login_alert,
+            NotificationKind::ShareReceived => self.share_received,
+            NotificationKind::QuotaWarning => self.quota_warning,
+            NotificationKind::Announcements => self.announcements,
+        }
+    }
+
+    /// Apply a partial update, leaving unset fields as they are
+    pub fn apply(&mut self, update: &NotificationPreferencesUpdate) {
+        if let Some(enabled) = update.login_alert {
+            self.login_alert = enabled;
+        }
+        if let Some(enabled) = update.share_received {
+            self.share_received = enabled;
+        }
+        if let Some(enabled) = update.quota_warning {
+            self.quota_warning = enabled;
+        }
+        if let Some(enabled) = update.announcements {
+            self.announcements = enabled;
+        }
+    }
+}
+
+/// Changes to a user's notification preferences; fields left out are unchanged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationPreferencesUpdate {
+    pub login_alert: Option<bool>,
+    pub share_received: Option<bool>,
+    pub quota_warning: Option<bool>,
+    pub announcements: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_and_allows() {
+        let mut preferences = NotificationPreferences::default();
+        assert!(preferences.allows(NotificationKind::QuotaWarning));
+
+        preferences.apply(&NotificationPreferencesUpdate {
+            quota_warning: Some(false),
+            ..Default::default()
+        });
+        assert!(!preferences.allows(NotificationKind::QuotaWarning));
+        assert!(preferences.allows(NotificationKind::LoginAlert));
+    }
+
+    #[test]
+    fn test_missing_kinds_default_to_on() {
+        let preferences: NotificationPreferences = serde_json::from_str(r#"{"login_alert": false}"#).unwrap();
+        assert!(!preferences.login_alert);
+        assert!(preferences.announcements);
+    }
+}
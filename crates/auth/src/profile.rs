@@ -0,0 +1,148 @@
+//! Public-facing user profiles
+//!
+//! A profile holds what a user chooses to show about themselves. Every field is optional, and a
+//! user without a profile row has an empty one. The avatar is a file the user uploaded through
+//! storage; checking that it is one is left to the caller, since this crate doesn't depend on
+//! storage.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Table holding each user's profile
+pub const PROFILES_TABLE: &str = "profiles";
+
+/// Longest display name, in characters
+pub const MAX_DISPLAY_NAME_CHARS: usize = 64;
+
+/// Longest bio, in characters
+pub const MAX_BIO_CHARS: usize = 500;
+
+/// What a user shows about themselves
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Profile {
+    pub display_name: Option<String>,
+    /// ID of the storage file used as the user's picture
+    pub avatar_file_id: Option<String>,
+    pub bio: Option<String>,
+    /// Language tag such as `en` or `pt-BR`
+    pub locale: Option<String>,
+    /// When the profile was last changed; `None` if it never was
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Profile {
+    pub(crate) fn from_json(row: &serde_json::Value) -> Self {
+        let text = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+        Self {
+            display_name: text("display_name"),
+            avatar_file_id: text("avatar_file_id"),
+            bio: text("bio"),
+            locale: text("locale"),
+            updated_at: row
+                .get("updated_at")
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc)),
+        }
+    }
+
+    /// Apply a partial update, leaving unset fields as they are
+    ///
+    /// # Returns
+    /// Why the update was refused, leaving the profile unchanged
+    pub fn apply(&mut self, update: &ProfileUpdate) -> std::result::Result<(), String> {
+        let display_name = updated(&self.display_name, &update.display_name);
+        if let Some(name) = &display_name {
+            if name.chars().count() > MAX_DISPLAY_NAME_CHARS || name.chars().any(char::is_control) {
+                return Err(format!(
+                    "Display name must be at most {} characters, without control characters",
+                    MAX_DISPLAY_NAME_CHARS
+                ));
+            }
+        }
+        let bio = updated(&self.bio, &update.bio);
+        if bio.as_ref().is_some_and(|bio| bio.chars().count() > MAX_BIO_CHARS) {
+            return Err(format!("Bio must be at most {} characters", MAX_BIO_CHARS));
+        }
+        let locale = updated(&self.locale, &update.locale);
+        if let Some(locale) = &locale {
+            if !is_valid_locale(locale) {
+                return Err(format!("'{}' is not a language tag", locale));
+            }
+        }
+
+        self.display_name = display_name;
+        self.avatar_file_id = updated(&self.avatar_file_id, &update.avatar_file_id);
+        self.bio = bio;
+        self.locale = locale;
+        Ok(())
+    }
+}
+
+/// Changes to a user's profile; fields left out are unchanged and `""` clears a field
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileUpdate {
+    pub display_name: Option<String>,
+    pub avatar_file_id: Option<String>,
+    pub bio: Option<String>,
+    pub locale: Option<String>,
+}
+
+/// A field's value after an update, trimmed, with blank values clearing it
+fn updated(current: &Option<String>, update: &Option<String>) -> Option<String> {
+    match update {
+        Some(value) => Some(value.trim()).filter(|value| !value.is_empty()).map(str::to_string),
+        None => current.clone(),
+    }
+}
+
+/// Whether a locale looks like a language tag: a 2-3 letter language, then optional
+/// alphanumeric subtags of up to 8 characters, separated by `-`
+pub fn is_valid_locale(locale: &str) -> bool {
+    let mut subtags = locale.split('-');
+    let language = subtags.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut profile = Profile::default();
+        profile.apply(&ProfileUpdate {
+            display_name: Some("  Ada  ".to_string()),
+            locale: Some("en-GB".to_string()),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(profile.display_name.as_deref(), Some("Ada"));
+        assert_eq!(profile.locale.as_deref(), Some("en-GB"));
+
+        // A refused update changes nothing
+        let refused = profile.apply(&ProfileUpdate {
+            display_name: Some(String::new()),
+            bio: Some("x".repeat(MAX_BIO_CHARS + 1)),
+            ..Default::default()
+        });
+        assert!(refused.is_err());
+        assert_eq!(profile.display_name.as_deref(), Some("Ada"));
+
+        profile.apply(&ProfileUpdate { display_name: Some(String::new()), ..Default::default() }).unwrap();
+        assert_eq!(profile.display_name, None);
+        assert_eq!(profile.locale.as_deref(), Some("en-GB"));
+    }
+
+    #[test]
+    fn test_locales() {
+        assert!(is_valid_locale("en"));
+        assert!(is_valid_locale("pt-BR"));
+        assert!(is_valid_locale("zh-Hant-TW"));
+        assert!(!is_valid_locale("english"));
+        assert!(!is_valid_locale("en_GB"));
+        assert!(!is_valid_locale("en-"));
+        assert!(!is_valid_locale(""));
+    }
+}
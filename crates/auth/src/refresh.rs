@@ -0,0 +1,41 @@
+//! Refresh tokens, which a client trades for a new access token without the password
+//!
+//! A refresh token is a random string handed out at login; only its SHA-256 hash is stored.
+//! Each token is used once: refreshing replaces it with a new token of the same family. A
+//! token that is presented after it was replaced must have been copied, so its whole family is
+//! revoked and the client has to log in again.
+
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Table of issued refresh tokens
+pub const REFRESH_TOKENS_TABLE: &str = "refresh_tokens";
+
+/// How long a refresh token stays valid by default (30 days)
+pub const DEFAULT_REFRESH_TOKEN_EXPIRY_SECONDS: i64 = 30 * 24 * 3600;
+
+/// A new random token, for refresh tokens and emailed links
+pub(crate) fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hash a token is stored and looked up by
+pub(crate) fn hash_opaque_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_tokens() {
+        let token = generate_opaque_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_opaque_token());
+        assert_eq!(hash_opaque_token(&token), hash_opaque_token(&token));
+        assert_ne!(hash_opaque_token(&token), token);
+    }
+}
@@ -1,17 +1,41 @@
 use crate::{
     error::{AuthError, Result},
-    jwt::{generate_token, validate_token},
+    geo::{GeoLocation, GeoLookup, LoginRecord, LOGIN_HISTORY_TABLE},
+    jwt::{sign_claims, validate_token_with_keys, Claims},
+    keys::{KeyCipher, KeyRing, KeyRotation, SigningKey, SIGNING_KEYS_TABLE},
     model::{Session, User, Role},
+    permissions::{is_valid_permission, is_valid_role_name, CustomRole, PERMISSIONS_TABLE, ROLES_TABLE, USER_ROLES_TABLE},
     password::{hash_password, verify_password},
+    email_tokens::{EmailTokenPurpose, EMAIL_TOKENS_TABLE},
+    preferences::{NotificationKind, NotificationPreferences, NotificationPreferencesUpdate, PREFERENCES_TABLE},
+    profile::{Profile, ProfileUpdate, PROFILES_TABLE},
+    refresh::{generate_opaque_token, hash_opaque_token, DEFAULT_REFRESH_TOKEN_EXPIRY_SECONDS, REFRESH_TOKENS_TABLE},
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use orm::prelude::*;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+/// Lifetime of a sudo token in seconds
+pub const SUDO_TOKEN_EXPIRY_SECONDS: i64 = 300;
+
+/// Default time a deleted account can be restored before it is purged (30 days)
+pub const DEFAULT_DELETION_GRACE_SECONDS: i64 = 30 * 24 * 3600;
 
 /// Authentication service that integrates ORM with auth logic
 pub struct AuthService {
     db: Database,
     jwt_secret: String,
+    /// The configured secret plus keys added by rotations; see [`AuthService::reload_signing_keys`]
+    keys: RwLock<KeyRing>,
+    /// Encrypts the secrets of rotated keys in the database
+    key_cipher: KeyCipher,
     token_expiry_seconds: i64,
+    refresh_token_expiry_seconds: i64,
+    deletion_grace_seconds: i64,
+    /// Refuse logins and tokens of users who haven't verified their address
+    require_verified_email: bool,
+    geo: Option<Arc<dyn GeoLookup>>,
 }
 
 impl AuthService {
@@ -24,11 +48,66 @@ impl AuthService {
     pub fn new(db: Database, jwt_secret: String, token_expiry_seconds: i64) -> Self {
         Self {
             db,
+            keys: RwLock::new(KeyRing::new(&jwt_secret)),
+            key_cipher: KeyCipher::new(&jwt_secret),
             jwt_secret,
             token_expiry_seconds,
+            refresh_token_expiry_seconds: DEFAULT_REFRESH_TOKEN_EXPIRY_SECONDS,
+            deletion_grace_seconds: DEFAULT_DELETION_GRACE_SECONDS,
+            require_verified_email: false,
+            geo: None,
         }
     }
 
+    /// Encrypt the secrets of rotated keys with a key derived from `secret` instead of the JWT secret
+    /// Every instance must use the same one; changing it makes the stored keys unreadable
+    pub fn with_signing_key_encryption_key(mut self, secret: &str) -> Self {
+        self.key_cipher = KeyCipher::new(secret);
+        self
+    }
+
+    /// Locate the client of every login with `geo`
+    pub fn with_geo_lookup(mut self, geo: Arc<dyn GeoLookup>) -> Self {
+        self.geo = Some(geo);
+        self
+    }
+
+    /// Where an IP address is located; `None` without a GeoIP lookup or for unknown addresses
+    pub fn locate(&self, ip: IpAddr) -> Option<GeoLocation> {
+        self.geo.as_ref().and_then(|geo| geo.lookup(ip))
+    }
+
+    /// Keep deleted accounts restorable for `seconds` before they are purged
+    /// With 0, deleted accounts are purged straight away
+    pub fn with_deletion_grace_seconds(mut self, seconds: i64) -> Self {
+        self.deletion_grace_seconds = seconds.max(0);
+        self
+    }
+
+    /// Keep refresh tokens valid for `seconds` after they are issued
+    pub fn with_refresh_token_expiry_seconds(mut self, seconds: i64) -> Self {
+        self.refresh_token_expiry_seconds = seconds.max(0);
+        self
+    }
+
+    /// Only let users who verified their email address log in or use their tokens
+    /// Service accounts, which have no mailbox, are exempt. A magic link login verifies the
+    /// address it was sent to.
+    pub fn with_verified_email_required(mut self, required: bool) -> Self {
+        self.require_verified_email = required;
+        self
+    }
+
+    /// Whether the user has to verify their address before the account can be used
+    fn is_unverified(&self, user: &User) -> bool {
+        self.require_verified_email && !user.is_email_verified() && !user.is_service()
+    }
+
+    /// How long deleted accounts stay restorable, in seconds
+    pub fn deletion_grace_seconds(&self) -> i64 {
+        self.deletion_grace_seconds
+    }
+
     /// Register a new user with default role (User)
     /// 
     /// # Arguments
@@ -123,6 +202,13 @@ impl AuthService {
     /// * `email` - User's email address
     /// * `password` - User's plain text password
     pub async fn login(&self, email: &str, password: &str) -> Result<(String, User)> {
+        let (token, user, _) = self.login_from(email, password, None).await?;
+        Ok((token, user))
+    }
+
+    /// Log in a client connecting from `ip`, if known
+    /// The address and its location are recorded on the session and in the login history
+    pub async fn login_from(&self, email: &str, password: &str, ip: Option<IpAddr>) -> Result<(String, User, LoginRecord)> {
         // Find user by email
         let user = self.find_user_by_email(email).await?
             .ok_or(AuthError::InvalidPassword)?;
@@ -132,17 +218,41 @@ impl AuthService {
             return Err(AuthError::InvalidPassword);
         }
 
+        if user.is_deleted() {
+            return Err(AuthError::AccountDeleted);
+        }
+
+        if self.is_unverified(&user) {
+            return Err(AuthError::EmailNotVerified);
+        }
+
+        self.start_session(user, ip).await
+    }
+
+    /// Issue an access token to a user who has proven who they are, and record the login
+    async fn start_session(&self, user: User, ip: Option<IpAddr>) -> Result<(String, User, LoginRecord)> {
         // Generate JWT token with user's role
         let user_id_str = user.id
             .ok_or(AuthError::TokenGenerationError("User has no ID".to_string()))?
             .to_string();
         
-        let token = generate_token(&user_id_str, user.role, &self.jwt_secret, self.token_expiry_seconds)?;
+        let token = self.sign(&Claims::new(user_id_str, user.role, self.token_expiry_seconds))?;
 
         // Optionally store session in database
         let expires_at = Utc::now() + Duration::seconds(self.token_expiry_seconds);
-        let session = Session::new(user.id.unwrap(), token.clone(), expires_at);
-        
+        let location = ip.and_then(|ip| self.locate(ip)).unwrap_or_default();
+        let mut session = Session::new(user.id.unwrap(), token.clone(), expires_at);
+        session.ip_address = ip.map(|ip| ip.to_string());
+        session.country = location.country.clone();
+        session.city = location.city.clone();
+        self.store_session(&session).await;
+
+        let login = self.record_login(user.id.unwrap(), session.ip_address, location).await;
+        Ok((token, user, login))
+    }
+
+    /// Store a session in the database; best effort, like the login history
+    async fn store_session(&self, session: &Session) {
         let backend = self.db.backend();
         let mut query_builder = backend.query_builder();
         
@@ -156,24 +266,447 @@ impl AuthService {
         if let Ok(sql) = query_builder.build() {
             let _ = backend.execute(&sql, query_builder.params()).await;
         }
+    }
+
+    /// Issue a refresh token for a user who has just logged in
+    /// The token starts a new family; see [`AuthService::refresh`]
+    pub async fn issue_refresh_token(&self, user: &User) -> Result<String> {
+        let user_id = user.id
+            .ok_or(AuthError::TokenGenerationError("User has no ID".to_string()))?;
+        let family = generate_opaque_token();
+        self.insert_refresh_token(user_id, &family).await
+    }
+
+    async fn insert_refresh_token(&self, user_id: i64, family: &str) -> Result<String> {
+        let token = generate_opaque_token();
+        let now = Utc::now();
+        let sql = format!(
+            "INSERT INTO {} (token_hash, family, user_id, expires_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            REFRESH_TOKENS_TABLE
+        );
+        self.db.backend().execute(&sql, &[
+            orm::query::QueryValue::String(hash_opaque_token(&token)),
+            orm::query::QueryValue::String(family.to_string()),
+            orm::query::QueryValue::I64(user_id),
+            orm::query::QueryValue::String((now + Duration::seconds(self.refresh_token_expiry_seconds)).to_rfc3339()),
+            orm::query::QueryValue::String(now.to_rfc3339()),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(token)
+    }
+
+    /// Trade a refresh token for a new access token and a new refresh token
+    ///
+    /// Each refresh token works once. Presenting one that was already traded in revokes every
+    /// token descended from the same login, since either the client or whoever copied the token
+    /// is using a stale one.
+    ///
+    /// # Returns
+    /// The access token, the refresh token that replaces the one presented, and the user
+    pub async fn refresh(&self, refresh_token: &str) -> Result<(String, String, User)> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT * FROM {} WHERE token_hash = ?1", REFRESH_TOKENS_TABLE);
+        let row = backend.fetch_one_params(&sql, &[orm::query::QueryValue::String(hash_opaque_token(refresh_token))]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?
+            .ok_or(AuthError::InvalidToken)?;
+        let text = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+        let integer = |column: &str| row.get(column).and_then(|v| v.as_i64());
+        let (Some(id), Some(user_id), Some(family)) = (integer("id"), integer("user_id"), text("family")) else {
+            return Err(AuthError::InvalidToken);
+        };
+        if text("revoked_at").is_some() {
+            return Err(AuthError::InvalidToken);
+        }
+        let now = Utc::now();
+        let expires_at = text("expires_at")
+            .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok_or(AuthError::InvalidToken)?;
+        if expires_at <= now {
+            return Err(AuthError::TokenExpired);
+        }
+
+        // Claim the token; losing the claim means it was already traded in
+        let claim_sql = format!("UPDATE {} SET used_at = ?1 WHERE id = ?2 AND used_at IS NULL", REFRESH_TOKENS_TABLE);
+        let claimed = backend.execute(&claim_sql, &[
+            orm::query::QueryValue::String(now.to_rfc3339()),
+            orm::query::QueryValue::I64(id),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        if claimed == 0 {
+            self.revoke_refresh_family(&family).await?;
+            return Err(AuthError::InvalidToken);
+        }
+
+        let user = self.find_user_by_id(user_id).await?
+            .ok_or(AuthError::InvalidToken)?;
+        if user.is_deleted() {
+            return Err(AuthError::AccountDeleted);
+        }
+
+        let token = self.sign(&Claims::new(user_id.to_string(), user.role, self.token_expiry_seconds))?;
+        let session = Session::new(user_id, token.clone(), now + Duration::seconds(self.token_expiry_seconds));
+        self.store_session(&session).await;
+        let refresh_token = self.insert_refresh_token(user_id, &family).await?;
+        Ok((token, refresh_token, user))
+    }
+
+    /// Issue a token to email to a user, replacing any unused one for the same purpose
+    pub async fn issue_email_token(&self, user: &User, purpose: EmailTokenPurpose) -> Result<String> {
+        let user_id = user.id
+            .ok_or(AuthError::TokenGenerationError("User has no ID".to_string()))?;
+        self.insert_email_token(user_id, purpose, &user.email).await
+    }
+
+    /// Store a token for `purpose` sent to `email`, replacing any unused one of the user
+    async fn insert_email_token(&self, user_id: i64, purpose: EmailTokenPurpose, email: &str) -> Result<String> {
+        let backend = self.db.backend();
+        let now = Utc::now();
+
+        let replace_sql = format!(
+            "UPDATE {} SET used_at = ?1 WHERE user_id = ?2 AND purpose = ?3 AND used_at IS NULL",
+            EMAIL_TOKENS_TABLE
+        );
+        backend.execute(&replace_sql, &[
+            orm::query::QueryValue::String(now.to_rfc3339()),
+            orm::query::QueryValue::I64(user_id),
+            orm::query::QueryValue::String(purpose.as_str().to_string()),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        let token = generate_opaque_token();
+        let insert_sql = format!(
+            "INSERT INTO {} (token_hash, user_id, purpose, email, expires_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            EMAIL_TOKENS_TABLE
+        );
+        backend.execute(&insert_sql, &[
+            orm::query::QueryValue::String(hash_opaque_token(&token)),
+            orm::query::QueryValue::I64(user_id),
+            orm::query::QueryValue::String(purpose.as_str().to_string()),
+            orm::query::QueryValue::String(email.to_string()),
+            orm::query::QueryValue::String((now + purpose.lifetime()).to_rfc3339()),
+            orm::query::QueryValue::String(now.to_rfc3339()),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(token)
+    }
+
+    /// Use up an emailed token, returning the user it was sent to and the address it was sent to
+    async fn redeem_email_token(&self, token: &str, purpose: EmailTokenPurpose) -> Result<(User, String)> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT * FROM {} WHERE token_hash = ?1 AND purpose = ?2", EMAIL_TOKENS_TABLE);
+        let row = backend.fetch_one_params(&sql, &[
+            orm::query::QueryValue::String(hash_opaque_token(token)),
+            orm::query::QueryValue::String(purpose.as_str().to_string()),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?
+            .ok_or(AuthError::InvalidToken)?;
+        let text = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+        let integer = |column: &str| row.get(column).and_then(|v| v.as_i64());
+        let (Some(id), Some(user_id), Some(email)) = (integer("id"), integer("user_id"), text("email")) else {
+            return Err(AuthError::InvalidToken);
+        };
+        let now = Utc::now();
+        let expires_at = text("expires_at")
+            .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok_or(AuthError::InvalidToken)?;
+        if expires_at <= now {
+            return Err(AuthError::TokenExpired);
+        }
+
+        let claim_sql = format!("UPDATE {} SET used_at = ?1 WHERE id = ?2 AND used_at IS NULL", EMAIL_TOKENS_TABLE);
+        let claimed = backend.execute(&claim_sql, &[
+            orm::query::QueryValue::String(now.to_rfc3339()),
+            orm::query::QueryValue::I64(id),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        if claimed == 0 {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let user = self.find_user_by_id(user_id).await?
+            .ok_or(AuthError::InvalidToken)?;
+        if user.is_deleted() {
+            return Err(AuthError::AccountDeleted);
+        }
+        // A token sent to an address the account no longer has proves nothing; email change
+        // tokens go to the address the account doesn't have yet
+        if purpose != EmailTokenPurpose::EmailChange && !user.email.eq_ignore_ascii_case(&email) {
+            return Err(AuthError::InvalidToken);
+        }
+        Ok((user, email))
+    }
+
+    /// Set a new password with a token from a password reset email
+    /// Like [`AuthService::change_password`], this ends every session of the account
+    pub async fn reset_password(&self, token: &str, new_password: &str) -> Result<User> {
+        let (user, _) = self.redeem_email_token(token, EmailTokenPurpose::PasswordReset).await?;
+        self.set_password(user, new_password).await
+    }
 
+    /// Change the password of a user who knows the current one
+    /// Every session of the account ends: access tokens issued before the change stop
+    /// validating and refresh tokens are revoked
+    ///
+    /// # Returns
+    /// A new access token, so the caller stays logged in, and the user
+    pub async fn change_password(&self, user: &User, current_password: &str, new_password: &str) -> Result<(String, User)> {
+        if !verify_password(current_password, &user.password_hash)? {
+            return Err(AuthError::InvalidPassword);
+        }
+        let user = self.set_password(user.clone(), new_password).await?;
+        let user_id = user.id.unwrap_or_default();
+
+        let token = self.sign(&Claims::new(user_id.to_string(), user.role, self.token_expiry_seconds))?;
+        let session = Session::new(user_id, token.clone(), Utc::now() + Duration::seconds(self.token_expiry_seconds));
+        self.store_session(&session).await;
         Ok((token, user))
     }
 
+    /// Store a new password and end every session of the account
+    async fn set_password(&self, mut user: User, new_password: &str) -> Result<User> {
+        let user_id = user.id
+            .ok_or(AuthError::InvalidInput("User has no ID".to_string()))?;
+        let now = Utc::now();
+        user.password_hash = hash_password(new_password)?;
+        user.updated_at = now;
+        user.password_changed_at = Some(now);
+
+        let sql = format!(
+            "UPDATE {} SET password_hash = ?1, updated_at = ?2, password_changed_at = ?2 WHERE id = ?3",
+            User::table_name()
+        );
+        self.db.backend().execute(&sql, &[
+            orm::query::QueryValue::String(user.password_hash.clone()),
+            orm::query::QueryValue::String(now.to_rfc3339()),
+            orm::query::QueryValue::I64(user_id),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        self.end_sessions(user_id, now).await?;
+        user.sessions_revoked_at = Some(now);
+        Ok(user)
+    }
+
+    /// End every session of a user at `at`: access tokens issued before then stop validating,
+    /// and every refresh token is revoked
+    async fn end_sessions(&self, user_id: i64, at: DateTime<Utc>) -> Result<()> {
+        let backend = self.db.backend();
+        let users_sql = format!("UPDATE {} SET sessions_revoked_at = ?1 WHERE id = ?2", User::table_name());
+        backend.execute(&users_sql, &[
+            orm::query::QueryValue::String(at.to_rfc3339()),
+            orm::query::QueryValue::I64(user_id),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        let sessions_sql = format!("DELETE FROM {} WHERE user_id = ?1", Session::table_name());
+        backend.execute(&sessions_sql, &[orm::query::QueryValue::I64(user_id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        let refresh_sql = format!(
+            "UPDATE {} SET revoked_at = ?1 WHERE user_id = ?2 AND revoked_at IS NULL",
+            REFRESH_TOKENS_TABLE
+        );
+        backend.execute(&refresh_sql, &[
+            orm::query::QueryValue::String(at.to_rfc3339()),
+            orm::query::QueryValue::I64(user_id),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Start moving a user to a new email address, once they confirm their password
+    /// Nothing changes until the token, which is meant for the new address, is confirmed with
+    /// [`AuthService::confirm_email_change`]
+    ///
+    /// # Returns
+    /// The token to email to the new address, or `None` when another account has the address.
+    /// Callers should answer the same either way, so the request can't be used to find out
+    /// which addresses have accounts.
+    pub async fn request_email_change(&self, user: &User, password: &str, new_email: &str) -> Result<Option<String>> {
+        let user_id = user.id
+            .ok_or(AuthError::InvalidInput("User has no ID".to_string()))?;
+        if !verify_password(password, &user.password_hash)? {
+            return Err(AuthError::InvalidPassword);
+        }
+        let new_email = new_email.trim();
+        if !new_email.contains('@') {
+            return Err(AuthError::InvalidInput(format!("'{}' is not an email address", new_email)));
+        }
+        if new_email.eq_ignore_ascii_case(&user.email) {
+            return Err(AuthError::InvalidInput("That is already the account's email address".to_string()));
+        }
+        if self.find_user_by_email(new_email).await?.is_some() {
+            return Ok(None);
+        }
+        self.insert_email_token(user_id, EmailTokenPurpose::EmailChange, new_email).await.map(Some)
+    }
+
+    /// Switch the account to the new address with a token from an email change
+    /// The address counts as verified, since the token was sent there, and every session of the
+    /// account ends
+    ///
+    /// # Returns
+    /// The updated user and the address it moved from
+    pub async fn confirm_email_change(&self, token: &str) -> Result<(User, String)> {
+        let (mut user, new_email) = self.redeem_email_token(token, EmailTokenPurpose::EmailChange).await?;
+        let user_id = user.id.unwrap_or_default();
+        // Someone may have signed up with the address since the change was asked for
+        if self.find_user_by_email(&new_email).await?.is_some_and(|other| other.id != user.id) {
+            return Err(AuthError::InvalidInput("Email address is already in use".to_string()));
+        }
+
+        let now = Utc::now();
+        let sql = format!(
+            "UPDATE {} SET email = ?1, email_verified_at = ?2, updated_at = ?2 WHERE id = ?3",
+            User::table_name()
+        );
+        self.db.backend().execute(&sql, &[
+            orm::query::QueryValue::String(new_email.clone()),
+            orm::query::QueryValue::String(now.to_rfc3339()),
+            orm::query::QueryValue::I64(user_id),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        self.end_sessions(user_id, now).await?;
+
+        let old_email = std::mem::replace(&mut user.email, new_email);
+        user.email_verified_at = Some(now);
+        user.updated_at = now;
+        user.sessions_revoked_at = Some(now);
+        Ok((user, old_email))
+    }
+
+    /// Mark the account's email address as verified with a token from a verification email
+    pub async fn verify_email(&self, token: &str) -> Result<User> {
+        let (mut user, _) = self.redeem_email_token(token, EmailTokenPurpose::EmailVerification).await?;
+        self.mark_email_verified(&mut user).await?;
+        Ok(user)
+    }
+
+    async fn mark_email_verified(&self, user: &mut User) -> Result<()> {
+        let verified_at = Utc::now();
+        let sql = format!("UPDATE {} SET email_verified_at = ?1 WHERE id = ?2", User::table_name());
+        self.db.backend().execute(&sql, &[
+            orm::query::QueryValue::String(verified_at.to_rfc3339()),
+            orm::query::QueryValue::I64(user.id.unwrap_or_default()),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        user.email_verified_at = Some(verified_at);
+        Ok(())
+    }
+
+    /// Log in with a token from a magic link email, from a client connecting from `ip`
+    /// Opening the link proves the user owns the address, so it counts as verified
+    pub async fn login_with_magic_link(&self, token: &str, ip: Option<IpAddr>) -> Result<(String, User, LoginRecord)> {
+        let (mut user, _) = self.redeem_email_token(token, EmailTokenPurpose::MagicLink).await?;
+        if !user.is_email_verified() {
+            self.mark_email_verified(&mut user).await?;
+        }
+        self.start_session(user, ip).await
+    }
+
+    /// Revoke every refresh token descended from the same login
+    async fn revoke_refresh_family(&self, family: &str) -> Result<()> {
+        let sql = format!("UPDATE {} SET revoked_at = ?1 WHERE family = ?2 AND revoked_at IS NULL", REFRESH_TOKENS_TABLE);
+        self.db.backend().execute(&sql, &[
+            orm::query::QueryValue::String(Utc::now().to_rfc3339()),
+            orm::query::QueryValue::String(family.to_string()),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Add a login to the user's history, flagging the first one from a new country
+    /// Like the session, the history is best effort and never fails a login
+    async fn record_login(&self, user_id: i64, ip_address: Option<String>, location: GeoLocation) -> LoginRecord {
+        let backend = self.db.backend();
+        let optional = |value: &Option<String>| value.clone().map(orm::query::QueryValue::String).unwrap_or(orm::query::QueryValue::Null);
+
+        let mut new_country = false;
+        if let Some(country) = &location.country {
+            let sql = format!(
+                "SELECT COUNT(*) as total, SUM(CASE WHEN country = ?2 THEN 1 ELSE 0 END) as same_country \
+                 FROM {} WHERE user_id = ?1",
+                LOGIN_HISTORY_TABLE
+            );
+            let params = [orm::query::QueryValue::I64(user_id), orm::query::QueryValue::String(country.clone())];
+            if let Ok(Some(row)) = backend.fetch_one_params(&sql, &params).await {
+                let count = |column: &str| row.get(column).and_then(|v| v.as_i64()).unwrap_or(0);
+                new_country = count("total") > 0 && count("same_country") == 0;
+            }
+        }
+
+        let record = LoginRecord {
+            user_id,
+            ip_address,
+            country: location.country,
+            city: location.city,
+            new_country,
+            created_at: Utc::now(),
+        };
+        let sql = format!(
+            "INSERT INTO {} (user_id, ip_address, country, city, new_country, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            LOGIN_HISTORY_TABLE
+        );
+        let _ = backend.execute(&sql, &[
+            orm::query::QueryValue::I64(user_id),
+            optional(&record.ip_address),
+            optional(&record.country),
+            optional(&record.city),
+            orm::query::QueryValue::I64(new_country as i64),
+            orm::query::QueryValue::String(record.created_at.to_rfc3339()),
+        ]).await;
+
+        record
+    }
+
+    /// A user's most recent logins, newest first
+    pub async fn login_history(&self, user_id: i64, limit: u64) -> Result<Vec<LoginRecord>> {
+        let sql = format!(
+            "SELECT * FROM {} WHERE user_id = ?1 ORDER BY id DESC LIMIT {}",
+            LOGIN_HISTORY_TABLE, limit
+        );
+        let rows = self.db.backend().fetch_all_params(&sql, &[orm::query::QueryValue::I64(user_id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().filter_map(LoginRecord::from_json).collect())
+    }
+
     /// Validate a JWT token and return the user
     /// Also verifies that the role in the token matches the user's current role
     pub async fn validate(&self, token: &str) -> Result<User> {
         // Validate JWT
-        let claims = validate_token(token, &self.jwt_secret)?;
+        let claims = self.verify(token)?;
 
         // Parse user ID from claims
         let user_id: i64 = claims.sub.parse()
             .map_err(|_| AuthError::InvalidToken)?;
 
+        // Sudo and recovery tokens only serve their own purpose; they can't stand in for a session
+        if claims.sudo || claims.recovery {
+            return Err(AuthError::InvalidToken);
+        }
+
         // Find user by ID
-        let user = self.find_user_by_id(user_id).await?
+        let mut user = self.find_user_by_id(user_id).await?
             .ok_or(AuthError::InvalidToken)?;
 
+        if user.is_deleted() {
+            return Err(AuthError::AccountDeleted);
+        }
+
+        // Covers tokens issued before verification was required
+        if self.is_unverified(&user) {
+            return Err(AuthError::EmailNotVerified);
+        }
+
+        // Tokens are only dated to the second, so one issued in the second of a change, like
+        // the one handed out with it, is still accepted
+        if user.sessions_revoked_at.is_some_and(|revoked| claims.iat < revoked.timestamp()) {
+            return Err(AuthError::TokenValidationError(
+                "Session has ended, please login again".to_string()
+            ));
+        }
+
         // Verify role hasn't changed
         if user.role != claims.role {
             return Err(AuthError::TokenValidationError(
@@ -181,16 +714,164 @@ impl AuthService {
             ));
         }
 
+        user.permissions = self.permissions_of(user_id).await?;
         Ok(user)
     }
 
     /// Validate a JWT token and return both the user and claims
     pub async fn validate_with_claims(&self, token: &str) -> Result<(User, crate::jwt::Claims)> {
-        let claims = validate_token(token, &self.jwt_secret)?;
+        let claims = self.verify(token)?;
         let user = self.validate(token).await?;
         Ok((user, claims))
     }
 
+    /// Issue a sudo token after the user re-enters their password
+    /// A stolen session token alone can't be used for operations that require sudo
+    /// 
+    /// # Returns
+    /// The sudo token and when it expires
+    pub async fn elevate(&self, user: &User, password: &str) -> Result<(String, DateTime<Utc>)> {
+        if !verify_password(password, &user.password_hash)? {
+            return Err(AuthError::InvalidPassword);
+        }
+
+        let user_id_str = user.id
+            .ok_or(AuthError::TokenGenerationError("User has no ID".to_string()))?
+            .to_string();
+
+        let token = self.sign(&Claims::sudo(user_id_str, user.role, SUDO_TOKEN_EXPIRY_SECONDS))?;
+        let expires_at = Utc::now() + Duration::seconds(SUDO_TOKEN_EXPIRY_SECONDS);
+
+        Ok((token, expires_at))
+    }
+
+    /// Issue a session token that acts for an organization
+    /// The caller checks that the user is a member of the organization.
+    ///
+    /// # Returns
+    /// The token and when it expires
+    pub fn organization_token(&self, user: &User, organization_id: i64) -> Result<(String, DateTime<Utc>)> {
+        let user_id_str = user.id
+            .ok_or(AuthError::TokenGenerationError("User has no ID".to_string()))?
+            .to_string();
+
+        let claims = Claims::organization(user_id_str, user.role, organization_id, self.token_expiry_seconds);
+        let token = self.sign(&claims)?;
+        let expires_at = Utc::now() + Duration::seconds(self.token_expiry_seconds);
+
+        Ok((token, expires_at))
+    }
+
+    /// Check that `token` is an unexpired sudo token for `user`
+    pub fn validate_sudo(&self, token: &str, user: &User) -> Result<()> {
+        let claims = self.verify(token)?;
+
+        let user_id = user.id.map(|id| id.to_string());
+        if !claims.sudo || user_id.as_deref() != Some(claims.sub.as_str()) || claims.role != user.role {
+            return Err(AuthError::SudoRequired);
+        }
+        if user.sessions_revoked_at.is_some_and(|revoked| claims.iat < revoked.timestamp()) {
+            return Err(AuthError::SudoRequired);
+        }
+
+        Ok(())
+    }
+
+    /// Delete a user account
+    /// Rows that reference the user (sessions, file metadata, ...) are removed by cascade
+    pub async fn delete_user(&self, id: i64) -> Result<()> {
+        let backend = self.db.backend();
+        let sql = format!("DELETE FROM {} WHERE id = ?1", User::table_name());
+
+        let rows_affected = backend.execute(&sql, &[orm::query::QueryValue::I64(id)]).await
+            .map_err(|e| AuthError::TokenValidationError(format!("Database error: {}", e)))?;
+
+        if rows_affected == 0 {
+            return Err(AuthError::UserNotFound(id));
+        }
+
+        Ok(())
+    }
+
+    /// Mark an account as deleted and end its sessions
+    /// The account and its data stay intact until the grace period ends, so it can still be restored
+    ///
+    /// # Returns
+    /// A recovery token that restores the account until it is purged
+    pub async fn soft_delete_user(&self, id: i64) -> Result<String> {
+        let user = self.find_user_by_id(id).await?
+            .ok_or(AuthError::UserNotFound(id))?;
+        if user.is_deleted() {
+            return Err(AuthError::AccountDeleted);
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let backend = self.db.backend();
+        let sql = format!("UPDATE {} SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2", User::table_name());
+        backend.execute(&sql, &[orm::query::QueryValue::String(now), orm::query::QueryValue::I64(id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        let sessions_sql = format!("DELETE FROM {} WHERE user_id = ?1", Session::table_name());
+        backend.execute(&sessions_sql, &[orm::query::QueryValue::I64(id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        self.sign(&Claims::recovery(id.to_string(), user.role, self.deletion_grace_seconds))
+    }
+
+    /// Restore a deleted account before it is purged
+    /// Restoring an account that isn't deleted does nothing
+    pub async fn restore_user(&self, id: i64) -> Result<User> {
+        let mut user = self.find_user_by_id(id).await?
+            .ok_or(AuthError::UserNotFound(id))?;
+        if !user.is_deleted() {
+            return Ok(user);
+        }
+
+        let now = Utc::now();
+        let backend = self.db.backend();
+        let sql = format!("UPDATE {} SET deleted_at = NULL, updated_at = ?1 WHERE id = ?2", User::table_name());
+        backend.execute(&sql, &[orm::query::QueryValue::String(now.to_rfc3339()), orm::query::QueryValue::I64(id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        user.deleted_at = None;
+        user.updated_at = now;
+        Ok(user)
+    }
+
+    /// Restore a deleted account with the recovery token issued when it was deleted
+    pub async fn recover_account(&self, token: &str) -> Result<User> {
+        let claims = self.verify(token)?;
+        if !claims.recovery {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let user_id: i64 = claims.sub.parse()
+            .map_err(|_| AuthError::InvalidToken)?;
+        let user = self.find_user_by_id(user_id).await?
+            .ok_or(AuthError::InvalidToken)?;
+
+        // Only the token from the current deletion counts, not one from an earlier delete and restore
+        match user.deleted_at {
+            Some(deleted_at) if claims.iat >= deleted_at.timestamp() => self.restore_user(user_id).await,
+            _ => Err(AuthError::InvalidToken),
+        }
+    }
+
+    /// IDs of deleted accounts whose grace period is over, ready to be purged
+    pub async fn users_to_purge(&self) -> Result<Vec<i64>> {
+        let cutoff = Utc::now() - Duration::seconds(self.deletion_grace_seconds);
+
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT id FROM {} WHERE deleted_at IS NOT NULL AND deleted_at <= ?1",
+            User::table_name()
+        );
+        let rows = backend.fetch_all_params(&sql, &[orm::query::QueryValue::String(cutoff.to_rfc3339())]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.iter().filter_map(|row| row.get("id").and_then(|v| v.as_i64())).collect())
+    }
+
     /// Logout a user by invalidating their session
     pub async fn logout(&self, token: &str) -> Result<()> {
         // Delete session from database
@@ -209,6 +890,135 @@ impl AuthService {
         Ok(())
     }
 
+    /// A user's notification preferences; everything is on until the user changes it
+    pub async fn notification_preferences(&self, user_id: i64) -> Result<NotificationPreferences> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT preferences FROM {} WHERE user_id = ?1", PREFERENCES_TABLE);
+
+        let row = backend.fetch_one_params(&sql, &[orm::query::QueryValue::I64(user_id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        match row.as_ref().and_then(|json| json.get("preferences")).and_then(|v| v.as_str()) {
+            Some(preferences) => serde_json::from_str(preferences)
+                .map_err(|e| AuthError::DatabaseError(format!("Deserialization error: {}", e))),
+            None => Ok(NotificationPreferences::default()),
+        }
+    }
+
+    /// Change some of a user's notification preferences, returning the result
+    pub async fn update_notification_preferences(
+        &self,
+        user_id: i64,
+        update: &NotificationPreferencesUpdate,
+    ) -> Result<NotificationPreferences> {
+        // Read and write in one transaction so concurrent changes can't lose each other's settings
+        let tx = self.db.begin().await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        let select_sql = format!("SELECT preferences FROM {} WHERE user_id = ?1", PREFERENCES_TABLE);
+        let row = tx.fetch_one_params(&select_sql, &[orm::query::QueryValue::I64(user_id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        let mut preferences = match row.as_ref().and_then(|json| json.get("preferences")).and_then(|v| v.as_str()) {
+            Some(preferences) => serde_json::from_str(preferences)
+                .map_err(|e| AuthError::DatabaseError(format!("Deserialization error: {}", e)))?,
+            None => NotificationPreferences::default(),
+        };
+        preferences.apply(update);
+
+        let json = serde_json::to_string(&preferences)
+            .map_err(|e| AuthError::DatabaseError(format!("Serialization error: {}", e)))?;
+        let values = [
+            orm::query::QueryValue::I64(user_id),
+            orm::query::QueryValue::String(json),
+            orm::query::QueryValue::String(Utc::now().to_rfc3339()),
+        ];
+
+        // One row per user: update it, or add it the first time a preference is changed
+        let update_sql = format!("UPDATE {} SET preferences = ?2, updated_at = ?3 WHERE user_id = ?1", PREFERENCES_TABLE);
+        let updated = tx.execute(&update_sql, &values).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        if updated == 0 {
+            let insert_sql = format!(
+                "INSERT INTO {} (user_id, preferences, updated_at) VALUES (?1, ?2, ?3)",
+                PREFERENCES_TABLE
+            );
+            tx.execute(&insert_sql, &values).await
+                .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        }
+        tx.commit().await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        Ok(preferences)
+    }
+
+    /// A user's profile; empty until the user fills it in
+    pub async fn profile(&self, user_id: i64) -> Result<Profile> {
+        let sql = format!("SELECT * FROM {} WHERE user_id = ?1", PROFILES_TABLE);
+        let row = self.db.backend().fetch_one_params(&sql, &[orm::query::QueryValue::I64(user_id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(row.as_ref().map(Profile::from_json).unwrap_or_default())
+    }
+
+    /// Change some of a user's profile, returning the result
+    /// The caller is responsible for checking that a new avatar is a file the user may use
+    pub async fn update_profile(&self, user_id: i64, update: &ProfileUpdate) -> Result<Profile> {
+        // Read and write in one transaction so concurrent changes can't lose each other's fields
+        let tx = self.db.begin().await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        let select_sql = format!("SELECT * FROM {} WHERE user_id = ?1", PROFILES_TABLE);
+        let row = tx.fetch_one_params(&select_sql, &[orm::query::QueryValue::I64(user_id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        let mut profile = row.as_ref().map(Profile::from_json).unwrap_or_default();
+        profile.apply(update).map_err(AuthError::InvalidInput)?;
+        let now = Utc::now();
+        profile.updated_at = Some(now);
+
+        let optional = |value: &Option<String>| value.clone().map(orm::query::QueryValue::String).unwrap_or(orm::query::QueryValue::Null);
+        let values = [
+            orm::query::QueryValue::I64(user_id),
+            optional(&profile.display_name),
+            optional(&profile.avatar_file_id),
+            optional(&profile.bio),
+            optional(&profile.locale),
+            orm::query::QueryValue::String(now.to_rfc3339()),
+        ];
+
+        // One row per user: update it, or add it the first time the profile is filled in
+        let update_sql = format!(
+            "UPDATE {} SET display_name = ?2, avatar_file_id = ?3, bio = ?4, locale = ?5, updated_at = ?6 WHERE user_id = ?1",
+            PROFILES_TABLE
+        );
+        let updated = tx.execute(&update_sql, &values).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        if updated == 0 {
+            let insert_sql = format!(
+                "INSERT INTO {} (user_id, display_name, avatar_file_id, bio, locale, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                PROFILES_TABLE
+            );
+            tx.execute(&insert_sql, &values).await
+                .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        }
+        tx.commit().await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        Ok(profile)
+    }
+
+    /// Whether a notification of `kind` may be delivered to the user
+    /// Notification senders must check this before delivering anything
+    pub async fn should_notify(&self, user_id: i64, kind: NotificationKind) -> Result<bool> {
+        Ok(self.notification_preferences(user_id).await?.allows(kind))
+    }
+
+    /// The account registered under an email address, unless it was deleted
+    pub async fn active_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        Ok(self.find_user_by_email(email).await?.filter(|user| !user.is_deleted()))
+    }
+
+    /// The account with an ID, unless it was deleted
+    pub async fn active_user_by_id(&self, id: i64) -> Result<Option<User>> {
+        Ok(self.find_user_by_id(id).await?.filter(|user| !user.is_deleted()))
+    }
+
     /// Find user by email
     async fn find_user_by_email(&self, email: &str) -> Result<Option<User>> {
         let backend = self.db.backend();
@@ -261,7 +1071,7 @@ impl AuthService {
         Ok(Some(user))
     }
 
-    /// Clean up expired sessions
+    /// Clean up expired sessions and refresh tokens
     pub async fn cleanup_expired_sessions(&self) -> Result<u64> {
         let now = Utc::now().to_rfc3339();
         
@@ -271,9 +1081,310 @@ impl AuthService {
         let rows_affected = backend.execute(&sql, &[]).await
             .map_err(|e| AuthError::TokenValidationError(format!("Database error: {}", e)))?;
 
-        Ok(rows_affected)
+        let refresh_sql = format!("DELETE FROM {} WHERE expires_at < ?1", REFRESH_TOKENS_TABLE);
+        let refresh_rows = backend.execute(&refresh_sql, &[orm::query::QueryValue::String(now)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        Ok(rows_affected + refresh_rows)
     }
     
+    /// Sign claims with the current key
+    fn sign(&self, claims: &Claims) -> Result<String> {
+        let keys = self.keys.read().unwrap();
+        sign_claims(claims, keys.current(Utc::now()))
+    }
+
+    /// Validate a token against the key that signed it
+    fn verify(&self, token: &str) -> Result<Claims> {
+        let keys = self.keys.read().unwrap();
+        validate_token_with_keys(token, &keys).map(|(claims, _)| claims)
+    }
+
+    /// The keys tokens are currently signed and validated with
+    pub fn signing_keys(&self) -> KeyRing {
+        self.keys.read().unwrap().clone()
+    }
+
+    /// Load the keys added by rotations, on this or any other instance
+    pub async fn reload_signing_keys(&self) -> Result<()> {
+        let sql = format!("SELECT * FROM {}", SIGNING_KEYS_TABLE);
+        let rows = self.db.backend().fetch_all_params(&sql, &[]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        *self.keys.write().unwrap() = KeyRing::from_rows(&self.jwt_secret, &self.key_cipher, &rows);
+
+        // Keys stored before secrets were encrypted are sealed on the way through
+        let seal_sql = format!("UPDATE {} SET secret = ?1 WHERE kid = ?2 AND secret = ?3", SIGNING_KEYS_TABLE);
+        for row in &rows {
+            let (Some(kid), Some(stored)) = (
+                row.get("kid").and_then(|v| v.as_str()),
+                row.get("secret").and_then(|v| v.as_str()),
+            ) else {
+                continue;
+            };
+            if KeyCipher::is_plaintext(stored) {
+                self.db.backend().execute(&seal_sql, &[
+                    orm::query::QueryValue::String(self.key_cipher.encrypt(stored)),
+                    orm::query::QueryValue::String(kid.to_string()),
+                    orm::query::QueryValue::String(stored.to_string()),
+                ]).await
+                    .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a new signing key and retire the keys it replaces at the end of `window`
+    ///
+    /// The new key validates tokens as soon as instances reload their keys, and signs them from
+    /// `activate_in` on. Replaced keys keep validating the tokens they signed until `window` after
+    /// that, so no session is cut off as long as the window outlasts the token lifetime.
+    pub async fn rotate_signing_key(&self, activate_in: Duration, window: Duration) -> Result<KeyRotation> {
+        // Keys another instance added must be retired too
+        self.reload_signing_keys().await?;
+
+        let now = Utc::now();
+        let key = SigningKey::generate(now + activate_in);
+        let retires_at = key.activates_at + window;
+        let backend = self.db.backend();
+        let insert_sql = format!(
+            "INSERT INTO {} (kid, secret, activates_at, retires_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            SIGNING_KEYS_TABLE
+        );
+        let update_sql = format!("UPDATE {} SET retires_at = ?1 WHERE kid = ?2", SIGNING_KEYS_TABLE);
+
+        let mut retiring = Vec::new();
+        for old in self.signing_keys().keys().iter().filter(|old| old.retires_at.is_none()) {
+            // The configured secret isn't stored; a row without a secret records when it retires
+            let result = if old.configured {
+                backend.execute(&insert_sql, &[
+                    orm::query::QueryValue::String(old.kid.clone()),
+                    orm::query::QueryValue::Null,
+                    orm::query::QueryValue::String(old.activates_at.to_rfc3339()),
+                    orm::query::QueryValue::String(retires_at.to_rfc3339()),
+                    orm::query::QueryValue::String(now.to_rfc3339()),
+                ]).await
+            } else {
+                backend.execute(&update_sql, &[
+                    orm::query::QueryValue::String(retires_at.to_rfc3339()),
+                    orm::query::QueryValue::String(old.kid.clone()),
+                ]).await
+            };
+            result.map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+            retiring.push(SigningKey {
+                retires_at: Some(retires_at),
+                ..old.clone()
+            });
+        }
+
+        backend.execute(&insert_sql, &[
+            orm::query::QueryValue::String(key.kid.clone()),
+            orm::query::QueryValue::String(self.key_cipher.encrypt(&key.secret)),
+            orm::query::QueryValue::String(key.activates_at.to_rfc3339()),
+            orm::query::QueryValue::Null,
+            orm::query::QueryValue::String(now.to_rfc3339()),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        self.reload_signing_keys().await?;
+        Ok(KeyRotation { key, retiring })
+    }
+
+    /// Delete rotated keys past their retirement, returning how many were deleted
+    /// Rows recording the retirement of a configured secret are kept, or it would validate again
+    pub async fn prune_signing_keys(&self) -> Result<u64> {
+        let sql = format!("DELETE FROM {} WHERE secret IS NOT NULL AND retires_at <= ?1", SIGNING_KEYS_TABLE);
+        self.db.backend().execute(&sql, &[orm::query::QueryValue::String(Utc::now().to_rfc3339())]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))
+    }
+
+    /// Re-sign a valid token whose key is being retired with the current key
+    ///
+    /// Returns `None` when the token is invalid or its key isn't retiring. The session row is
+    /// updated to the new token, so a client that switches over keeps its session.
+    pub async fn renew_token(&self, token: &str) -> Option<String> {
+        let renewed = {
+            let keys = self.keys.read().unwrap();
+            let (claims, key) = validate_token_with_keys(token, &keys).ok()?;
+            let current = keys.current(Utc::now());
+            if key.retires_at.is_none() || current.kid == key.kid {
+                return None;
+            }
+            sign_claims(&claims, current).ok()?
+        };
+
+        let sql = format!("UPDATE {} SET token = ?1 WHERE token = ?2", Session::table_name());
+        let _ = self.db.backend().execute(&sql, &[
+            orm::query::QueryValue::String(renewed.clone()),
+            orm::query::QueryValue::String(token.to_string()),
+        ]).await;
+        Some(renewed)
+    }
+
+    /// Define a role, or replace the description and permissions of an existing one
+    pub async fn put_role(&self, name: &str, description: Option<&str>, permissions: &[String]) -> Result<CustomRole> {
+        if !is_valid_role_name(name) {
+            return Err(AuthError::InvalidInput(format!("invalid role name '{}'", name)));
+        }
+        if let Some(invalid) = permissions.iter().find(|permission| !is_valid_permission(permission)) {
+            return Err(AuthError::InvalidInput(format!("invalid permission '{}'", invalid)));
+        }
+
+        // The role and its permissions are replaced together, so no request sees a half-written role
+        let tx = self.db.begin().await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        let description = description.map(|d| orm::query::QueryValue::String(d.to_string())).unwrap_or(orm::query::QueryValue::Null);
+        let id_sql = format!("SELECT id FROM {} WHERE name = ?1", ROLES_TABLE);
+        let existing = tx.fetch_one_params(&id_sql, &[orm::query::QueryValue::String(name.to_string())]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        if existing.is_some() {
+            let sql = format!("UPDATE {} SET description = ?1 WHERE name = ?2", ROLES_TABLE);
+            tx.execute(&sql, &[description, orm::query::QueryValue::String(name.to_string())]).await
+                .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        } else {
+            let sql = format!("INSERT INTO {} (name, description, created_at) VALUES (?1, ?2, ?3)", ROLES_TABLE);
+            tx.execute(&sql, &[
+                orm::query::QueryValue::String(name.to_string()),
+                description,
+                orm::query::QueryValue::String(Utc::now().to_rfc3339()),
+            ]).await
+                .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        }
+        let role_id = tx.fetch_one_params(&id_sql, &[orm::query::QueryValue::String(name.to_string())]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?
+            .and_then(|row| row.get("id").and_then(|v| v.as_i64()))
+            .ok_or_else(|| AuthError::RoleNotFound(name.to_string()))?;
+
+        let delete_sql = format!("DELETE FROM {} WHERE role_id = ?1", PERMISSIONS_TABLE);
+        tx.execute(&delete_sql, &[orm::query::QueryValue::I64(role_id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        let mut granted: Vec<&String> = permissions.iter().collect();
+        granted.sort();
+        granted.dedup();
+        let insert_sql = format!("INSERT INTO {} (role_id, permission) VALUES (?1, ?2)", PERMISSIONS_TABLE);
+        for permission in granted {
+            tx.execute(&insert_sql, &[
+                orm::query::QueryValue::I64(role_id),
+                orm::query::QueryValue::String(permission.clone()),
+            ]).await
+                .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        }
+        tx.commit().await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        self.get_role(name).await?
+            .ok_or_else(|| AuthError::RoleNotFound(name.to_string()))
+    }
+
+    /// A role the app defined, with its permissions
+    pub async fn get_role(&self, name: &str) -> Result<Option<CustomRole>> {
+        Ok(self.list_roles().await?.into_iter().find(|role| role.name == name))
+    }
+
+    /// Every role the app defined, by name
+    pub async fn list_roles(&self) -> Result<Vec<CustomRole>> {
+        let backend = self.db.backend();
+        let roles_sql = format!("SELECT * FROM {} ORDER BY name", ROLES_TABLE);
+        let roles = backend.fetch_all_params(&roles_sql, &[]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        let permissions_sql = format!("SELECT role_id, permission FROM {} ORDER BY permission", PERMISSIONS_TABLE);
+        let permissions = backend.fetch_all_params(&permissions_sql, &[]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+        Ok(roles
+            .iter()
+            .filter_map(|row| {
+                let id = row.get("id")?.as_i64()?;
+                let granted = permissions
+                    .iter()
+                    .filter(|p| p.get("role_id").and_then(|v| v.as_i64()) == Some(id))
+                    .filter_map(|p| p.get("permission").and_then(|v| v.as_str()).map(str::to_string))
+                    .collect();
+                CustomRole::from_json(row, granted)
+            })
+            .collect())
+    }
+
+    /// Delete a role, taking it and its permissions away from everyone who holds it
+    pub async fn delete_role(&self, name: &str) -> Result<()> {
+        let role_id = self.role_id(name).await?
+            .ok_or_else(|| AuthError::RoleNotFound(name.to_string()))?;
+        let tx = self.db.begin().await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        for table in [USER_ROLES_TABLE, PERMISSIONS_TABLE] {
+            let sql = format!("DELETE FROM {} WHERE role_id = ?1", table);
+            tx.execute(&sql, &[orm::query::QueryValue::I64(role_id)]).await
+                .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        }
+        let sql = format!("DELETE FROM {} WHERE id = ?1", ROLES_TABLE);
+        tx.execute(&sql, &[orm::query::QueryValue::I64(role_id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        tx.commit().await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Give a user a role; giving them one they already hold does nothing
+    pub async fn assign_role(&self, user_id: i64, name: &str) -> Result<()> {
+        if self.find_user_by_id(user_id).await?.is_none() {
+            return Err(AuthError::UserNotFound(user_id));
+        }
+        let role_id = self.role_id(name).await?
+            .ok_or_else(|| AuthError::RoleNotFound(name.to_string()))?;
+        if self.roles_of(user_id).await?.iter().any(|held| held == name) {
+            return Ok(());
+        }
+        let sql = format!("INSERT INTO {} (user_id, role_id, created_at) VALUES (?1, ?2, ?3)", USER_ROLES_TABLE);
+        self.db.backend().execute(&sql, &[
+            orm::query::QueryValue::I64(user_id),
+            orm::query::QueryValue::I64(role_id),
+            orm::query::QueryValue::String(Utc::now().to_rfc3339()),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Take a role away from a user, returning whether they held it
+    pub async fn unassign_role(&self, user_id: i64, name: &str) -> Result<bool> {
+        let role_id = self.role_id(name).await?
+            .ok_or_else(|| AuthError::RoleNotFound(name.to_string()))?;
+        let sql = format!("DELETE FROM {} WHERE user_id = ?1 AND role_id = ?2", USER_ROLES_TABLE);
+        let removed = self.db.backend().execute(&sql, &[
+            orm::query::QueryValue::I64(user_id),
+            orm::query::QueryValue::I64(role_id),
+        ]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(removed > 0)
+    }
+
+    /// Names of the custom roles a user holds
+    pub async fn roles_of(&self, user_id: i64) -> Result<Vec<String>> {
+        let sql = format!(
+            "SELECT r.name FROM {} ur JOIN {} r ON r.id = ur.role_id WHERE ur.user_id = ?1 ORDER BY r.name",
+            USER_ROLES_TABLE, ROLES_TABLE
+        );
+        let rows = self.db.backend().fetch_all_params(&sql, &[orm::query::QueryValue::I64(user_id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().filter_map(|row| row.get("name").and_then(|v| v.as_str()).map(str::to_string)).collect())
+    }
+
+    /// Permissions granted by the custom roles a user holds
+    pub async fn permissions_of(&self, user_id: i64) -> Result<Vec<String>> {
+        let sql = format!(
+            "SELECT DISTINCT p.permission FROM {} ur JOIN {} p ON p.role_id = ur.role_id WHERE ur.user_id = ?1 ORDER BY p.permission",
+            USER_ROLES_TABLE, PERMISSIONS_TABLE
+        );
+        let rows = self.db.backend().fetch_all_params(&sql, &[orm::query::QueryValue::I64(user_id)]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().filter_map(|row| row.get("permission").and_then(|v| v.as_str()).map(str::to_string)).collect())
+    }
+
+    async fn role_id(&self, name: &str) -> Result<Option<i64>> {
+        let sql = format!("SELECT id FROM {} WHERE name = ?1", ROLES_TABLE);
+        let row = self.db.backend().fetch_one_params(&sql, &[orm::query::QueryValue::String(name.to_string())]).await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        Ok(row.and_then(|row| row.get("id").and_then(|v| v.as_i64())))
+    }
+
     /// Get the database backend (for seeding and admin operations)
     pub fn db_backend(&self) -> &dyn orm::backend::Backend {
         self.db.backend()
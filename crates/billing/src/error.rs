@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum BillingError {
+    #[error("Invalid webhook signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Invalid webhook payload: {0}")]
+    InvalidPayload(String),
+
+    #[error("Unknown plan: {0}")]
+    UnknownPlan(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+}
+
+pub type Result<T> = std::result::Result<T, BillingError>;
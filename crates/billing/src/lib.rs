@@ -0,0 +1,20 @@
+//! Optional billing for SaaS deployments
+//!
+//! Maps Stripe subscriptions to plans, and plans to the storage limits enforced by
+//! `TransactionalStorageService` and the request limits enforced by the API's plan-limit
+//! middleware. Organizations may be assigned a plan of their own.
+
+mod error;
+
+pub mod model;
+pub mod plan;
+pub mod service;
+pub mod stripe;
+pub mod usage;
+
+pub use error::{BillingError, Result};
+pub use model::{Subscription, SubscriptionStatus};
+pub use plan::{ApiLimits, Plan};
+pub use service::BillingService;
+pub use stripe::{parse_subscription_event, verify_signature, SubscriptionEvent};
+pub use usage::{usage_period, Tenant, API_USAGE_TABLE};
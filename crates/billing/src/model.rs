@@ -0,0 +1,165 @@
+use chrono::{DateTime, Utc};
+use orm::prelude::*;
+use orm::model::Row;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Stripe subscription status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    Trialing,
+    Active,
+    PastDue,
+    Unpaid,
+    Canceled,
+    Incomplete,
+    IncompleteExpired,
+    Paused,
+}
+
+impl SubscriptionStatus {
+    /// Convert status to string for database storage (matches Stripe's names)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::Trialing => "trialing",
+            SubscriptionStatus::Active => "active",
+            SubscriptionStatus::PastDue => "past_due",
+            SubscriptionStatus::Unpaid => "unpaid",
+            SubscriptionStatus::Canceled => "canceled",
+            SubscriptionStatus::Incomplete => "incomplete",
+            SubscriptionStatus::IncompleteExpired => "incomplete_expired",
+            SubscriptionStatus::Paused => "paused",
+        }
+    }
+
+    /// Parse a Stripe subscription status
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "trialing" => Some(SubscriptionStatus::Trialing),
+            "active" => Some(SubscriptionStatus::Active),
+            "past_due" => Some(SubscriptionStatus::PastDue),
+            "unpaid" => Some(SubscriptionStatus::Unpaid),
+            "canceled" => Some(SubscriptionStatus::Canceled),
+            "incomplete" => Some(SubscriptionStatus::Incomplete),
+            "incomplete_expired" => Some(SubscriptionStatus::IncompleteExpired),
+            "paused" => Some(SubscriptionStatus::Paused),
+            _ => None,
+        }
+    }
+
+    /// Whether the subscriber keeps their plan's limits
+    /// Past-due subscriptions keep them while Stripe retries the payment
+    pub fn grants_plan(&self) -> bool {
+        matches!(
+            self,
+            SubscriptionStatus::Trialing | SubscriptionStatus::Active | SubscriptionStatus::PastDue
+        )
+    }
+}
+
+/// A user's Stripe subscription, as last reported by a webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: Option<i64>,
+    pub user_id: i64,
+    pub stripe_customer_id: String,
+    pub stripe_subscription_id: String,
+    pub plan: String,
+    pub status: SubscriptionStatus,
+    pub current_period_end: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Model for Subscription {
+    fn table_name() -> &'static str {
+        "billing_subscriptions"
+    }
+
+    fn primary_key() -> &'static str {
+        "id"
+    }
+
+    fn primary_key_value(&self) -> Option<Value> {
+        self.id.map(Value::I64)
+    }
+
+    fn to_values(&self) -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        if let Some(id) = self.id {
+            map.insert("id".to_string(), Value::I64(id));
+        }
+        map.insert("user_id".to_string(), Value::I64(self.user_id));
+        map.insert("stripe_customer_id".to_string(), Value::String(self.stripe_customer_id.clone()));
+        map.insert("stripe_subscription_id".to_string(), Value::String(self.stripe_subscription_id.clone()));
+        map.insert("plan".to_string(), Value::String(self.plan.clone()));
+        map.insert("status".to_string(), Value::String(self.status.as_str().to_string()));
+        if let Some(current_period_end) = &self.current_period_end {
+            map.insert("current_period_end".to_string(), Value::String(current_period_end.to_rfc3339()));
+        }
+        map.insert("updated_at".to_string(), Value::String(self.updated_at.to_rfc3339()));
+        map
+    }
+
+    fn columns() -> Vec<&'static str> {
+        vec![
+            "user_id",
+            "stripe_customer_id",
+            "stripe_subscription_id",
+            "plan",
+            "status",
+            "current_period_end",
+            "updated_at",
+        ]
+    }
+}
+
+impl FromRow for Subscription {
+    fn from_row(row: &Row) -> Result<Self> {
+        let id = row.get("id")
+            .and_then(|v| match v {
+                Value::I64(i) => Some(*i),
+                Value::I32(i) => Some(*i as i64),
+                _ => None,
+            });
+
+        let user_id = row.get("user_id")
+            .and_then(|v| match v {
+                Value::I64(i) => Some(*i),
+                Value::I32(i) => Some(*i as i64),
+                _ => None,
+            })
+            .ok_or_else(|| Error::SerializationError("Missing user_id".to_string()))?;
+
+        let string = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::String(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .ok_or_else(|| Error::SerializationError(format!("Missing {}", column)))
+        };
+
+        let status = SubscriptionStatus::from_str(&string("status")?)
+            .ok_or_else(|| Error::SerializationError("Invalid status".to_string()))?;
+
+        let timestamp = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                    _ => None,
+                })
+        };
+
+        Ok(Subscription {
+            id,
+            user_id,
+            stripe_customer_id: string("stripe_customer_id")?,
+            stripe_subscription_id: string("stripe_subscription_id")?,
+            plan: string("plan")?,
+            status,
+            current_period_end: timestamp("current_period_end"),
+            updated_at: timestamp("updated_at").unwrap_or_else(Utc::now),
+        })
+    }
+}
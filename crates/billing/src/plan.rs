@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use storage::StorageLimits;
+
+/// A billing plan and the limits it grants
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    /// Name the plan is referred to by in configuration and API responses
+    pub name: String,
+    /// Stripe price that subscribes a customer to this plan; `None` for free plans
+    pub stripe_price_id: Option<String>,
+    pub limits: StorageLimits,
+    /// Request limits of users and organizations on the plan
+    #[serde(default)]
+    pub api: ApiLimits,
+}
+
+/// Request limits a plan grants; `None` means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiLimits {
+    /// Requests per minute, counted by each server instance on its own
+    pub requests_per_minute: Option<u64>,
+    /// Requests per calendar month (UTC)
+    pub requests_per_month: Option<u64>,
+}
+
+impl Plan {
+    /// Create a plan with no Stripe price (e.g. a free tier)
+    pub fn new(name: impl Into<String>, limits: StorageLimits) -> Self {
+        Self {
+            name: name.into(),
+            stripe_price_id: None,
+            limits,
+            api: ApiLimits::default(),
+        }
+    }
+
+    /// Attach the Stripe price that subscribes a customer to this plan
+    pub fn with_stripe_price(mut self, price_id: impl Into<String>) -> Self {
+        self.stripe_price_id = Some(price_id.into());
+        self
+    }
+
+    /// Limit how many requests users and organizations on this plan may make
+    pub fn with_api_limits(mut self, api: ApiLimits) -> Self {
+        self.api = api;
+        self
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    error::{BillingError, Result},
+    model::Subscription,
+    plan::Plan,
+    stripe::{parse_subscription_event, verify_signature, SubscriptionEvent},
+    usage::{usage_period, Tenant, API_USAGE_TABLE},
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use orm::prelude::*;
+use orm::query::QueryValue;
+use storage::{LimitsProvider, Organization, StorageError, StorageLimits};
+
+/// Billing service mapping Stripe subscriptions to plans and their limits
+pub struct BillingService {
+    db: Database,
+    plans: Vec<Plan>,
+    default_plan: String,
+    webhook_secret: String,
+}
+
+impl BillingService {
+    /// Create a new BillingService
+    ///
+    /// # Arguments
+    /// * `db` - Database connection from ORM
+    /// * `plans` - Available plans
+    /// * `default_plan` - Plan for users without an active subscription
+    /// * `webhook_secret` - Signing secret of the Stripe webhook endpoint
+    pub fn new(db: Database, plans: Vec<Plan>, default_plan: String, webhook_secret: String) -> Result<Self> {
+        if !plans.iter().any(|plan| plan.name == default_plan) {
+            return Err(BillingError::UnknownPlan(default_plan));
+        }
+
+        Ok(Self {
+            db,
+            plans,
+            default_plan,
+            webhook_secret,
+        })
+    }
+
+    /// Look up a plan by name
+    pub fn plan(&self, name: &str) -> Option<&Plan> {
+        self.plans.iter().find(|plan| plan.name == name)
+    }
+
+    /// The plan currently in effect for a user
+    /// Users without a subscription, or whose subscription lapsed, get the default plan
+    pub async fn plan_for_user(&self, user_id: i64) -> Result<&Plan> {
+        let plan = self.find_subscription(user_id).await?
+            .filter(|subscription| subscription.status.grants_plan())
+            .and_then(|subscription| self.plan(&subscription.plan));
+
+        Ok(plan.unwrap_or_else(|| self.default_plan()))
+    }
+
+    /// The plan in effect for an organization: the one assigned to it, or else its owner's
+    pub async fn plan_for_organization(&self, organization: &Organization) -> Result<&Plan> {
+        match organization.plan.as_deref().and_then(|name| self.plan(name)) {
+            Some(plan) => Ok(plan),
+            None => self.plan_for_user(organization.owner_id).await,
+        }
+    }
+
+    /// Who an organization's requests are counted against
+    /// An organization without a plan of its own is on its owner's, and shares their rate limit
+    /// and monthly cap, so creating organizations doesn't add requests
+    pub fn tenant_for_organization(&self, organization: &Organization) -> Tenant {
+        match organization.plan.as_deref().and_then(|name| self.plan(name)) {
+            Some(_) => Tenant::Organization(organization.id),
+            None => Tenant::User(organization.owner_id),
+        }
+    }
+
+    fn default_plan(&self) -> &Plan {
+        self.plan(&self.default_plan).expect("default plan is validated in BillingService::new")
+    }
+
+    /// Verify and apply a Stripe webhook delivery
+    ///
+    /// # Returns
+    /// The updated subscription, or `None` if the event doesn't affect subscriptions
+    pub async fn handle_stripe_webhook(&self, payload: &[u8], signature_header: &str) -> Result<Option<Subscription>> {
+        verify_signature(payload, signature_header, &self.webhook_secret, Utc::now())?;
+
+        match parse_subscription_event(payload)? {
+            Some(event) => self.apply_subscription_event(event).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Record a subscription change reported by Stripe
+    pub async fn apply_subscription_event(&self, event: SubscriptionEvent) -> Result<Subscription> {
+        // Checkout sets metadata.user_id; later events may only identify the customer
+        let user_id = match event.user_id {
+            Some(user_id) => user_id,
+            None => self.find_subscription_by_customer(&event.stripe_customer_id).await?
+                .map(|subscription| subscription.user_id)
+                .ok_or_else(|| BillingError::InvalidPayload(format!(
+                    "no user is linked to customer {}",
+                    event.stripe_customer_id
+                )))?,
+        };
+
+        let plan = event.price_id.as_deref()
+            .and_then(|price_id| self.plans.iter().find(|plan| plan.stripe_price_id.as_deref() == Some(price_id)))
+            .ok_or_else(|| BillingError::UnknownPlan(event.price_id.clone().unwrap_or_default()))?;
+
+        let subscription = Subscription {
+            id: None,
+            user_id,
+            stripe_customer_id: event.stripe_customer_id,
+            stripe_subscription_id: event.stripe_subscription_id,
+            plan: plan.name.clone(),
+            status: event.status,
+            current_period_end: event.current_period_end,
+            updated_at: Utc::now(),
+        };
+
+        let values = [
+            QueryValue::I64(user_id),
+            QueryValue::String(subscription.stripe_customer_id.clone()),
+            QueryValue::String(subscription.stripe_subscription_id.clone()),
+            QueryValue::String(subscription.plan.clone()),
+            QueryValue::String(subscription.status.as_str().to_string()),
+            subscription.current_period_end
+                .map(|end| QueryValue::String(end.to_rfc3339()))
+                .unwrap_or(QueryValue::Null),
+            QueryValue::String(subscription.updated_at.to_rfc3339()),
+        ];
+
+        // One subscription per user: update it, or add it the first time the user subscribes
+        let tx = self.db.begin().await
+            .map_err(|e| BillingError::DatabaseError(e.to_string()))?;
+        let update_sql = format!(
+            "UPDATE {} SET stripe_customer_id = ?2, stripe_subscription_id = ?3, plan = ?4, status = ?5, current_period_end = ?6, updated_at = ?7 WHERE user_id = ?1",
+            Subscription::table_name()
+        );
+        let updated = tx.execute(&update_sql, &values).await
+            .map_err(|e| BillingError::DatabaseError(e.to_string()))?;
+        if updated == 0 {
+            let insert_sql = format!(
+                "INSERT INTO {} (user_id, stripe_customer_id, stripe_subscription_id, plan, status, current_period_end, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                Subscription::table_name()
+            );
+            tx.execute(&insert_sql, &values).await
+                .map_err(|e| BillingError::DatabaseError(e.to_string()))?;
+        }
+        tx.commit().await
+            .map_err(|e| BillingError::DatabaseError(e.to_string()))?;
+
+        Ok(subscription)
+    }
+
+    /// Get a user's recorded subscription
+    pub async fn find_subscription(&self, user_id: i64) -> Result<Option<Subscription>> {
+        self.find_one("user_id", QueryValue::I64(user_id)).await
+    }
+
+    /// Count a request toward the tenant's usage this month, returning the month's total so far
+    pub async fn record_request(&self, tenant: Tenant) -> Result<u64> {
+        let now = Utc::now();
+        let params = [
+            QueryValue::String(now.to_rfc3339()),
+            QueryValue::String(tenant.key()),
+            QueryValue::String(usage_period(now)),
+        ];
+        let backend = self.db.backend();
+        let update_sql = format!(
+            "UPDATE {} SET requests = requests + 1, updated_at = ?1 WHERE tenant = ?2 AND period = ?3",
+            API_USAGE_TABLE
+        );
+
+        let updated = backend.execute(&update_sql, &params).await
+            .map_err(|e| BillingError::DatabaseError(e.to_string()))?;
+        if updated == 0 {
+            let insert_sql = format!(
+                "INSERT INTO {} (tenant, period, requests, updated_at) VALUES (?2, ?3, 1, ?1)",
+                API_USAGE_TABLE
+            );
+            // Another request may have created the row first; count on top of it then
+            if backend.execute(&insert_sql, &params).await.is_err() {
+                backend.execute(&update_sql, &params).await
+                    .map_err(|e| BillingError::DatabaseError(e.to_string()))?;
+            }
+        }
+
+        self.requests_this_month(tenant).await
+    }
+
+    /// Requests counted toward the tenant's usage this month
+    pub async fn requests_this_month(&self, tenant: Tenant) -> Result<u64> {
+        let sql = format!("SELECT requests FROM {} WHERE tenant = ?1 AND period = ?2", API_USAGE_TABLE);
+        let params = [QueryValue::String(tenant.key()), QueryValue::String(usage_period(Utc::now()))];
+        let row = self.db.backend().fetch_one_params(&sql, &params).await
+            .map_err(|e| BillingError::DatabaseError(e.to_string()))?;
+        Ok(row.and_then(|json| json.get("requests").and_then(|v| v.as_i64())).unwrap_or(0) as u64)
+    }
+
+    async fn find_subscription_by_customer(&self, customer_id: &str) -> Result<Option<Subscription>> {
+        self.find_one("stripe_customer_id", QueryValue::String(customer_id.to_string())).await
+    }
+
+    async fn find_one(&self, column: &str, value: QueryValue) -> Result<Option<Subscription>> {
+        let backend = self.db.backend();
+        let mut query_builder = backend.query_builder();
+
+        query_builder.from(Subscription::table_name());
+        query_builder.select(&[]);
+        query_builder.where_eq(column, value);
+        query_builder.limit(1);
+
+        let sql = query_builder.build()
+            .map_err(|e| BillingError::DatabaseError(format!("Query build error: {}", e)))?;
+
+        let json_rows = backend.fetch_all_params(&sql, query_builder.params()).await
+            .map_err(|e| BillingError::DatabaseError(e.to_string()))?;
+
+        json_rows.first()
+            .map(|json| Subscription::from_json(json)
+                .map_err(|e| BillingError::DatabaseError(format!("Deserialization error: {}", e))))
+            .transpose()
+    }
+}
+
+#[async_trait]
+impl LimitsProvider for BillingService {
+    async fn limits_for(&self, user_id: i64) -> storage::Result<StorageLimits> {
+        self.plan_for_user(user_id).await
+            .map(|plan| plan.limits)
+            .map_err(|e| StorageError::StorageError(format!("Billing error: {}", e)))
+    }
+
+    async fn organization_limits(&self, organization: &Organization) -> storage::Result<StorageLimits> {
+        self.plan_for_organization(organization).await
+            .map(|plan| plan.limits)
+            .map_err(|e| StorageError::StorageError(format!("Billing error: {}", e)))
+    }
+}
@@ -0,0 +1,192 @@
+//! Stripe webhook verification and subscription event parsing
+
+use crate::error::{BillingError, Result};
+use crate::model::SubscriptionStatus;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+/// How far a webhook's timestamp may be from now before it is rejected as a replay
+pub const SIGNATURE_TOLERANCE_SECONDS: i64 = 300;
+
+/// Verify a `Stripe-Signature` header against the raw request body
+///
+/// The header looks like `t=<unix time>,v1=<hex hmac>[,v1=...]`; the signature is an
+/// HMAC-SHA256 of `<t>.<body>` keyed with the endpoint's webhook secret.
+pub fn verify_signature(payload: &[u8], header: &str, secret: &str, now: DateTime<Utc>) -> Result<()> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.push(value),
+            _ => {}
+        }
+    }
+
+    let timestamp = timestamp
+        .ok_or_else(|| BillingError::InvalidSignature("missing timestamp".to_string()))?;
+    if (now.timestamp() - timestamp).abs() > SIGNATURE_TOLERANCE_SECONDS {
+        return Err(BillingError::InvalidSignature("timestamp outside tolerance".to_string()));
+    }
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| BillingError::InvalidSignature(e.to_string()))?;
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+
+    let matches = signatures.iter()
+        .filter_map(|signature| decode_hex(signature))
+        .any(|signature| mac.clone().verify_slice(&signature).is_ok());
+
+    if matches {
+        Ok(())
+    } else {
+        Err(BillingError::InvalidSignature("no matching v1 signature".to_string()))
+    }
+}
+
+/// A Stripe event that changes a subscription
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriptionEvent {
+    pub stripe_subscription_id: String,
+    pub stripe_customer_id: String,
+    /// Price of the subscription's first item, which selects the plan
+    pub price_id: Option<String>,
+    pub status: SubscriptionStatus,
+    pub current_period_end: Option<DateTime<Utc>>,
+    /// `metadata.user_id` set on the subscription when checkout was started
+    pub user_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct Event {
+    #[serde(rename = "type")]
+    event_type: String,
+    data: EventData,
+}
+
+#[derive(Deserialize)]
+struct EventData {
+    object: serde_json::Value,
+}
+
+/// Parse a webhook body into a subscription change
+/// Returns `None` for event types that don't affect subscriptions
+pub fn parse_subscription_event(payload: &[u8]) -> Result<Option<SubscriptionEvent>> {
+    let event: Event = serde_json::from_slice(payload)
+        .map_err(|e| BillingError::InvalidPayload(e.to_string()))?;
+
+    let deleted = match event.event_type.as_str() {
+        "customer.subscription.created" | "customer.subscription.updated" => false,
+        "customer.subscription.deleted" => true,
+        _ => return Ok(None),
+    };
+
+    let object = &event.data.object;
+    let field = |name: &str| {
+        object.get(name)
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| BillingError::InvalidPayload(format!("subscription has no {}", name)))
+    };
+
+    let status = if deleted {
+        SubscriptionStatus::Canceled
+    } else {
+        let status = field("status")?;
+        SubscriptionStatus::from_str(&status)
+            .ok_or_else(|| BillingError::InvalidPayload(format!("unknown subscription status '{}'", status)))?
+    };
+
+    let price_id = object.pointer("/items/data/0/price/id")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    let current_period_end = object.get("current_period_end")
+        .and_then(|v| v.as_i64())
+        .and_then(|secs| DateTime::from_timestamp(secs, 0));
+    let user_id = object.pointer("/metadata/user_id")
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse().ok());
+
+    Ok(Some(SubscriptionEvent {
+        stripe_subscription_id: field("id")?,
+        stripe_customer_id: field("customer")?,
+        price_id,
+        status,
+        current_period_end,
+        user_id,
+    }))
+}
+
+/// Decode a hex string, returning `None` if it isn't valid hex
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(payload: &[u8], secret: &str, timestamp: i64) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}.", timestamp).as_bytes());
+        mac.update(payload);
+        let signature: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+        format!("t={},v1={}", timestamp, signature)
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let now = Utc::now();
+        let payload = br#"{"type":"ping"}"#;
+        let header = sign(payload, "whsec_test", now.timestamp());
+
+        assert!(verify_signature(payload, &header, "whsec_test", now).is_ok());
+        assert!(verify_signature(payload, &header, "whsec_other", now).is_err());
+        assert!(verify_signature(br#"{"type":"pong"}"#, &header, "whsec_test", now).is_err());
+
+        let stale = sign(payload, "whsec_test", now.timestamp() - SIGNATURE_TOLERANCE_SECONDS - 1);
+        assert!(verify_signature(payload, &stale, "whsec_test", now).is_err());
+        assert!(verify_signature(payload, "v1=abcd", "whsec_test", now).is_err());
+    }
+
+    #[test]
+    fn test_parse_subscription_event() {
+        let payload = br#"{
+            "type": "customer.subscription.updated",
+            "data": {"object": {
+                "id": "sub_123",
+                "customer": "cus_456",
+                "status": "past_due",
+                "current_period_end": 1735689600,
+                "metadata": {"user_id": "42"},
+                "items": {"data": [{"price": {"id": "price_pro"}}]}
+            }}
+        }"#;
+
+        let event = parse_subscription_event(payload).unwrap().unwrap();
+        assert_eq!(event.stripe_subscription_id, "sub_123");
+        assert_eq!(event.stripe_customer_id, "cus_456");
+        assert_eq!(event.price_id.as_deref(), Some("price_pro"));
+        assert_eq!(event.status, SubscriptionStatus::PastDue);
+        assert_eq!(event.user_id, Some(42));
+        assert!(event.current_period_end.is_some());
+
+        let deleted = payload.to_vec();
+        let deleted = String::from_utf8(deleted).unwrap().replace("customer.subscription.updated", "customer.subscription.deleted");
+        let event = parse_subscription_event(deleted.as_bytes()).unwrap().unwrap();
+        assert_eq!(event.status, SubscriptionStatus::Canceled);
+
+        let other = br#"{"type": "invoice.paid", "data": {"object": {}}}"#;
+        assert!(parse_subscription_event(other).unwrap().is_none());
+    }
+}
@@ -0,0 +1,48 @@
+//! Who requests are charged to, and the monthly request counts plan caps are checked against
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Table of request counts per tenant and month
+pub const API_USAGE_TABLE: &str = "api_usage";
+
+/// A user or organization whose plan limits a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "type", content = "id", rename_all = "lowercase")]
+pub enum Tenant {
+    User(i64),
+    /// Requests made with the token of an organization on a plan of its own
+    Organization(i64),
+}
+
+impl Tenant {
+    /// Key the tenant's usage is recorded under
+    pub fn key(&self) -> String {
+        match self {
+            Tenant::User(id) => format!("user:{}", id),
+            Tenant::Organization(id) => format!("organization:{}", id),
+        }
+    }
+}
+
+/// Month requests made at `at` count toward, as `YYYY-MM`
+pub fn usage_period(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_usage_keys() {
+        assert_eq!(Tenant::User(7).key(), "user:7");
+        assert_eq!(Tenant::Organization(3).key(), "organization:3");
+        assert_eq!(usage_period(Utc.with_ymd_and_hms(2025, 10, 31, 23, 59, 59).unwrap()), "2025-10");
+        assert_eq!(
+            serde_json::to_value(Tenant::Organization(3)).unwrap(),
+            serde_json::json!({"type": "organization", "id": 3})
+        );
+    }
+}
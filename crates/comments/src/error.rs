@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CommentError {
+    #[error("Comment not found: {0}")]
+    NotFound(i64),
+
+    #[error("Access denied: {0}")]
+    Forbidden(String),
+
+    #[error("Invalid comment: {0}")]
+    Invalid(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+}
+
+pub type Result<T> = std::result::Result<T, CommentError>;
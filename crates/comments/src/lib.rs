@@ -0,0 +1,14 @@
+//! Comment threads attachable to any record
+//!
+//! Comments are keyed by table name and row id, so apps can discuss rows of any
+//! table exposed through the `/db` layer. Deciding who may see a record is left
+//! to the caller; this crate only enforces that comments are edited by their author.
+
+mod error;
+
+pub mod model;
+pub mod service;
+
+pub use error::{CommentError, Result};
+pub use model::Comment;
+pub use service::{CommentService, MAX_COMMENT_LENGTH};
@@ -0,0 +1,134 @@
+use chrono::{DateTime, Utc};
+use orm::prelude::*;
+use orm::model::Row;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A comment on one row of a table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: Option<i64>,
+    /// Table holding the commented record
+    pub table_name: String,
+    /// Primary key of the commented record
+    pub row_id: String,
+    /// Author
+    pub user_id: i64,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Comment {
+    /// Create a new comment on a record
+    pub fn new(table_name: String, row_id: String, user_id: i64, body: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: None,
+            table_name,
+            row_id,
+            user_id,
+            body,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Whether the comment has been edited since it was posted
+    pub fn is_edited(&self) -> bool {
+        self.updated_at > self.created_at
+    }
+}
+
+impl Model for Comment {
+    fn table_name() -> &'static str {
+        "comments"
+    }
+
+    fn primary_key() -> &'static str {
+        "id"
+    }
+
+    fn primary_key_value(&self) -> Option<Value> {
+        self.id.map(Value::I64)
+    }
+
+    fn to_values(&self) -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        if let Some(id) = self.id {
+            map.insert("id".to_string(), Value::I64(id));
+        }
+        map.insert("table_name".to_string(), Value::String(self.table_name.clone()));
+        map.insert("row_id".to_string(), Value::String(self.row_id.clone()));
+        map.insert("user_id".to_string(), Value::I64(self.user_id));
+        map.insert("body".to_string(), Value::String(self.body.clone()));
+        map.insert("created_at".to_string(), Value::String(self.created_at.to_rfc3339()));
+        map.insert("updated_at".to_string(), Value::String(self.updated_at.to_rfc3339()));
+        map
+    }
+
+    fn columns() -> Vec<&'static str> {
+        vec!["table_name", "row_id", "user_id", "body", "created_at", "updated_at"]
+    }
+}
+
+impl FromRow for Comment {
+    fn from_row(row: &Row) -> Result<Self> {
+        let integer = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::I64(i) => Some(*i),
+                    Value::I32(i) => Some(*i as i64),
+                    _ => None,
+                })
+        };
+
+        let string = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::String(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .ok_or_else(|| Error::SerializationError(format!("Missing {}", column)))
+        };
+
+        let timestamp = |column: &str| {
+            row.get(column)
+                .and_then(|v| match v {
+                    Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                    _ => None,
+                })
+        };
+
+        let created_at = timestamp("created_at").unwrap_or_else(Utc::now);
+
+        Ok(Comment {
+            id: integer("id"),
+            table_name: string("table_name")?,
+            row_id: string("row_id")?,
+            user_id: integer("user_id")
+                .ok_or_else(|| Error::SerializationError("Missing user_id".to_string()))?,
+            body: string("body")?,
+            created_at,
+            updated_at: timestamp("updated_at").unwrap_or(created_at),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_comment_new() {
+        let mut comment = Comment::new("posts".to_string(), "7".to_string(), 1, "Nice post".to_string());
+        assert_eq!(comment.table_name, "posts");
+        assert_eq!(comment.row_id, "7");
+        assert!(comment.id.is_none());
+        assert!(!comment.is_edited());
+
+        comment.updated_at = comment.created_at + Duration::seconds(5);
+        assert!(comment.is_edited());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{
+    error::{CommentError, Result},
+    model::Comment,
+};
+use chrono::Utc;
+use orm::prelude::*;
+use orm::query::QueryValue;
+
+/// Longest comment body accepted, in characters
+pub const MAX_COMMENT_LENGTH: usize = 10_000;
+
+/// Service for comment threads on arbitrary records
+pub struct CommentService {
+    db: Database,
+}
+
+impl CommentService {
+    /// Create a new CommentService
+    ///
+    /// # Arguments
+    /// * `db` - Database connection from ORM
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Get the comments on a record, oldest first
+    pub async fn list(&self, table_name: &str, row_id: &str) -> Result<Vec<Comment>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE table_name = ?1 AND row_id = ?2 ORDER BY created_at ASC, id ASC",
+            Comment::table_name()
+        );
+        let params = [
+            QueryValue::String(table_name.to_string()),
+            QueryValue::String(row_id.to_string()),
+        ];
+
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| CommentError::DatabaseError(e.to_string()))?;
+
+        json_rows.iter()
+            .map(|json| Comment::from_json(json)
+                .map_err(|e| CommentError::DatabaseError(format!("Deserialization error: {}", e))))
+            .collect()
+    }
+
+    /// Get a page of the comments on a record, oldest first, along with how many there are
+    pub async fn list_page(&self, table_name: &str, row_id: &str, limit: i64, offset: i64) -> Result<(Vec<Comment>, i64)> {
+        let backend = self.db.backend();
+        let params = [
+            QueryValue::String(table_name.to_string()),
+            QueryValue::String(row_id.to_string()),
+            QueryValue::I64(limit),
+            QueryValue::I64(offset),
+        ];
+
+        let count_sql = format!(
+            "SELECT COUNT(*) as total FROM {} WHERE table_name = ?1 AND row_id = ?2",
+            Comment::table_name()
+        );
+        let total = backend.fetch_one_params(&count_sql, &params[..2]).await
+            .map_err(|e| CommentError::DatabaseError(e.to_string()))?
+            .and_then(|json| json.get("total").and_then(|v| v.as_i64()))
+            .unwrap_or(0);
+
+        let sql = format!(
+            "SELECT * FROM {} WHERE table_name = ?1 AND row_id = ?2 ORDER BY created_at ASC, id ASC LIMIT ?3 OFFSET ?4",
+            Comment::table_name()
+        );
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| CommentError::DatabaseError(e.to_string()))?;
+
+        let comments = json_rows.iter()
+            .map(|json| Comment::from_json(json)
+                .map_err(|e| CommentError::DatabaseError(format!("Deserialization error: {}", e))))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((comments, total))
+    }
+
+    /// Get a comment by id
+    pub async fn find(&self, id: i64) -> Result<Comment> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT * FROM {} WHERE id = ?1", Comment::table_name());
+
+        let json = backend.fetch_one_params(&sql, &[QueryValue::I64(id)]).await
+            .map_err(|e| CommentError::DatabaseError(e.to_string()))?
+            .ok_or(CommentError::NotFound(id))?;
+
+        Comment::from_json(&json)
+            .map_err(|e| CommentError::DatabaseError(format!("Deserialization error: {}", e)))
+    }
+
+    /// Post a comment on a record
+    /// The caller must have checked that the record exists and the author may see it
+    pub async fn create(&self, table_name: &str, row_id: &str, user_id: i64, body: &str) -> Result<Comment> {
+        let body = validate_body(body)?;
+        let mut comment = Comment::new(table_name.to_string(), row_id.to_string(), user_id, body);
+
+        let backend = self.db.backend();
+        let mut query_builder = backend.query_builder();
+
+        let values = comment.to_values();
+        let columns: Vec<&str> = values.keys().map(|s| s.as_str()).collect();
+        let query_values: Vec<_> = values.values().map(|v| v.to_query_value()).collect();
+
+        query_builder.insert_into(Comment::table_name(), &columns);
+        query_builder.values_params(&query_values);
+
+        // Use RETURNING clause for SQLite or LAST_INSERT_ID() for MySQL
+        if backend.supports_feature(orm::backend::BackendFeature::Returning) {
+            query_builder.returning(&["id"]);
+            let sql = query_builder.build()
+                .map_err(|e| CommentError::DatabaseError(format!("Query build error: {}", e)))?;
+
+            let result = backend.fetch_one_params(&sql, query_builder.params()).await
+                .map_err(|e| CommentError::DatabaseError(e.to_string()))?;
+            comment.id = result.and_then(|json| json.get("id").and_then(|v| v.as_i64()));
+        } else {
+            let sql = query_builder.build()
+                .map_err(|e| CommentError::DatabaseError(format!("Query build error: {}", e)))?;
+
+            backend.execute(&sql, query_builder.params()).await
+                .map_err(|e| CommentError::DatabaseError(e.to_string()))?;
+
+            #[allow(deprecated)]
+            let result = backend.fetch_one("SELECT LAST_INSERT_ID() as id").await
+                .map_err(|e| CommentError::DatabaseError(format!("Failed to get last insert ID: {}", e)))?;
+            comment.id = result.and_then(|json| json.get("id").and_then(|v| v.as_i64()));
+        }
+
+        if comment.id.is_none() {
+            return Err(CommentError::DatabaseError("Failed to get comment id".to_string()));
+        }
+
+        Ok(comment)
+    }
+
+    /// Edit a comment's body
+    /// Only the author may edit a comment
+    pub async fn update(&self, id: i64, user_id: i64, body: &str) -> Result<Comment> {
+        let body = validate_body(body)?;
+        let mut comment = self.find(id).await?;
+        if comment.user_id != user_id {
+            return Err(CommentError::Forbidden("only the author can edit a comment".to_string()));
+        }
+
+        comment.body = body;
+        comment.updated_at = Utc::now();
+
+        let backend = self.db.backend();
+        let sql = format!("UPDATE {} SET body = ?1, updated_at = ?2 WHERE id = ?3", Comment::table_name());
+        let params = [
+            QueryValue::String(comment.body.clone()),
+            QueryValue::String(comment.updated_at.to_rfc3339()),
+            QueryValue::I64(id),
+        ];
+        backend.execute(&sql, &params).await
+            .map_err(|e| CommentError::DatabaseError(e.to_string()))?;
+
+        Ok(comment)
+    }
+
+    /// Delete a comment
+    /// Authors can delete their own comments; moderators (service accounts) can delete any
+    pub async fn delete(&self, id: i64, user_id: i64, is_moderator: bool) -> Result<()> {
+        let comment = self.find(id).await?;
+        if comment.user_id != user_id && !is_moderator {
+            return Err(CommentError::Forbidden("only the author can delete a comment".to_string()));
+        }
+
+        let backend = self.db.backend();
+        let sql = format!("DELETE FROM {} WHERE id = ?1", Comment::table_name());
+        backend.execute(&sql, &[QueryValue::I64(id)]).await
+            .map_err(|e| CommentError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Trim a comment body and check it is neither empty nor too long
+fn validate_body(body: &str) -> Result<String> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(CommentError::Invalid("body cannot be empty".to_string()));
+    }
+    if body.chars().count() > MAX_COMMENT_LENGTH {
+        return Err(CommentError::Invalid(format!(
+            "body is longer than {} characters",
+            MAX_COMMENT_LENGTH
+        )));
+    }
+    Ok(body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_body() {
+        assert_eq!(validate_body("  hello \n").unwrap(), "hello");
+        assert!(matches!(validate_body("   "), Err(CommentError::Invalid(_))));
+        assert!(validate_body(&"a".repeat(MAX_COMMENT_LENGTH)).is_ok());
+        assert!(matches!(validate_body(&"a".repeat(MAX_COMMENT_LENGTH + 1)), Err(CommentError::Invalid(_))));
+    }
+}
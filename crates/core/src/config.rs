@@ -1,5 +1,6 @@
 use config::{Config, ConfigError, File};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::Path;
 
 #[derive(Debug, Deserialize, Clone)]
@@ -7,11 +8,380 @@ pub struct AppConfig {
     pub database: DatabaseConfig,
     pub auth: AuthConfig,
     pub server: ServerConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// Stripe billing; disabled when the section is absent
+    pub billing: Option<BillingConfig>,
+    #[serde(default)]
+    pub features: FeaturesConfig,
+    #[serde(default)]
+    pub sql_console: SqlConsoleConfig,
+    /// Rules for the generic `/db` routes
+    #[serde(default)]
+    pub db: DbConfig,
+    /// IP geolocation of logins; disabled when the section is absent
+    pub geoip: Option<GeoIpConfig>,
+    /// Routes and response fields being phased out
+    #[serde(default)]
+    pub deprecations: Vec<DeprecationConfig>,
+    /// Webhooks on file events; disabled when the section is absent
+    pub webhooks: Option<WebhooksConfig>,
+    /// State shared between instances when running more than one
+    #[serde(default)]
+    pub cluster: ClusterConfig,
+    /// Change events published to message queues; disabled when the section is absent
+    pub streaming: Option<StreamingConfig>,
+    /// Outgoing email; printed to stdout when the section is absent
+    pub mail: Option<MailConfig>,
+    /// Concurrency, priorities, and retries of background jobs
+    #[serde(default)]
+    pub jobs: JobsConfig,
+}
+
+/// How background jobs are queued, run, and retried
+#[derive(Debug, Deserialize, Clone)]
+pub struct JobsConfig {
+    /// Jobs running at once on this instance, across every type
+    #[serde(default = "default_jobs_max_concurrency")]
+    pub max_concurrency: usize,
+    /// Delay before a failed job is retried; doubled for each later attempt
+    #[serde(default = "default_jobs_retry_base_seconds")]
+    pub retry_base_seconds: u64,
+    /// Overrides of each job's built-in limits, keyed by job name, e.g. `[jobs.types.integrity_audit]`
+    #[serde(default)]
+    pub types: HashMap<String, JobTypeConfig>,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: default_jobs_max_concurrency(),
+            retry_base_seconds: default_jobs_retry_base_seconds(),
+            types: HashMap::new(),
+        }
+    }
+}
+
+/// Limits of one job type; unset values keep the job's built-in ones
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct JobTypeConfig {
+    /// Runs of this job at once
+    pub concurrency: Option<usize>,
+    /// Queued jobs with a higher priority start first
+    pub priority: Option<i32>,
+    /// Runs taking longer are cancelled and count as failed
+    pub timeout_seconds: Option<u64>,
+    /// Failed runs in a row before the job is dead-lettered
+    pub max_attempts: Option<u32>,
+}
+
+/// How outgoing email, such as share invitations, is sent
+#[derive(Debug, Deserialize, Clone)]
+pub struct MailConfig {
+    /// Sender address, e.g. `ProjectKit <noreply@example.com>`
+    pub from: String,
+    /// SMTP relay to send through; only the recipient and subject of emails are logged when unset
+    pub smtp_host: Option<String>,
+    /// Port of the relay, which must accept implicit TLS
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+}
+
+fn default_smtp_port() -> u16 {
+    465
+}
+
+/// Message queues storage and database changes are published to
+#[derive(Debug, Deserialize, Clone)]
+pub struct StreamingConfig {
+    /// Events are published under `<subject_prefix>.<event>`, e.g. `projectkit.file.uploaded`
+    #[serde(default = "default_streaming_subject_prefix")]
+    pub subject_prefix: String,
+    /// Events published (`file.uploaded`, `file.deleted`, `record.created`); every event when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub publishers: Vec<StreamPublisherConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StreamPublisherConfig {
+    pub kind: StreamPublisherKind,
+    /// Server URL; for Kafka, a comma-separated list of brokers
+    pub url: String,
+    /// RabbitMQ topic exchange events are published to
+    #[serde(default = "default_streaming_subject_prefix")]
+    pub exchange: String,
+}
+
+/// Which broker a publisher sends to
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamPublisherKind {
+    Nats,
+    Kafka,
+    Rabbitmq,
+}
+
+/// Running several instances behind a load balancer
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClusterConfig {
+    /// Redis server carrying realtime activity events between instances; in-process when unset
+    pub redis_url: Option<String>,
+    /// Redis pub/sub channel the events are published on
+    #[serde(default = "default_cluster_redis_channel")]
+    pub redis_channel: String,
+    /// `storage.path` is on a filesystem every instance mounts (NFS, EFS, ...)
+    #[serde(default)]
+    pub shared_local_storage: bool,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            redis_url: None,
+            redis_channel: default_cluster_redis_channel(),
+            shared_local_storage: false,
+        }
+    }
+}
+
+/// Endpoints notified of file events, and how failed deliveries are retried
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhooksConfig {
+    #[serde(default)]
+    pub endpoints: Vec<WebhookEndpointConfig>,
+    /// Attempts per delivery before it is marked failed
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled for each later one
+    #[serde(default = "default_webhook_retry_base_seconds")]
+    pub retry_base_seconds: u64,
+    /// Requests taking longer count as failed attempts
+    #[serde(default = "default_webhook_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookEndpointConfig {
+    pub url: String,
+    /// Key signing payloads sent to this endpoint
+    pub secret: String,
+    /// Events sent (`file.created`, `file.deleted`, `file.downloaded`); every event when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+/// A deprecated route, or a deprecated field of a route's JSON responses
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeprecationConfig {
+    /// Route as mounted, e.g. `/files/{id}/copy`
+    pub route: String,
+    /// Only requests with this method; every method when unset
+    pub method: Option<String>,
+    /// Deprecate this top-level field of the route's JSON responses instead of the route
+    pub field: Option<String>,
+    /// When the deprecation took effect, as an RFC 3339 date-time
+    pub since: String,
+    /// When the route starts answering 410 Gone, or the field is dropped, as an RFC 3339 date-time
+    pub sunset: Option<String>,
+    /// Migration guide, sent in a `Link` header with `rel="deprecation"`
+    pub link: Option<String>,
+    /// Warning shown to clients; generated from the route and dates when unset
+    pub message: Option<String>,
+}
+
+/// MaxMind database used to locate the IP addresses of logins
+#[derive(Debug, Deserialize, Clone)]
+pub struct GeoIpConfig {
+    /// Path of a GeoIP2 or GeoLite2 City database (`.mmdb`)
+    pub database_path: String,
+}
+
+/// Rules for reads and writes through `/db/{table}`
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct DbConfig {
+    /// Columns only the service role may set, as `column` (every table) or `table.column`
+    #[serde(default)]
+    pub server_managed_columns: Vec<String>,
+    /// How tables are read when the client passes no ordering or limit
+    #[serde(default)]
+    pub reads: DbReadConfig,
+    /// Per-table overrides of `reads`, keyed by table name
+    #[serde(default)]
+    pub tables: HashMap<String, DbReadConfig>,
+    /// Columns encrypted at rest
+    #[serde(default)]
+    pub encryption: DbEncryptionConfig,
+    /// Row-level security, keyed by table name
+    #[serde(default)]
+    pub policies: HashMap<String, DbPolicyConfig>,
+}
+
+/// Which rows of a `/db` table users may read and insert, as conditions such as
+/// `user_id = auth.uid()`; the service role is not bound by them
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct DbPolicyConfig {
+    /// Rows `GET /db/{table}` returns; the table can't be read when unset
+    pub select: Option<String>,
+    /// Rows `POST /db/{table}` accepts; nothing can be inserted when unset
+    pub insert: Option<String>,
+}
+
+/// Columns of `/db` tables that are stored encrypted
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct DbEncryptionConfig {
+    /// Encrypted columns, as `table.column`
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Secrets keys are derived from, by key ID; keep retired keys so older values stay readable
+    #[serde(default)]
+    pub keys: HashMap<String, String>,
+    /// ID of the key new values are encrypted with
+    pub current_key: Option<String>,
+    /// Roles that read decrypted values; everyone else reads `null`
+    #[serde(default = "default_db_encryption_readers")]
+    pub readers: Vec<String>,
+}
+
+impl Default for DbEncryptionConfig {
+    fn default() -> Self {
+        Self {
+            columns: Vec::new(),
+            keys: HashMap::new(),
+            current_key: None,
+            readers: default_db_encryption_readers(),
+        }
+    }
+}
+
+/// Ordering and limits of `GET /db/{table}`; unset fields fall back to the server defaults
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct DbReadConfig {
+    /// Column rows are ordered by, optionally followed by `asc` or `desc` (`id` when unset)
+    pub order_by: Option<String>,
+    /// Rows per page when the client passes no limit
+    pub default_limit: Option<i64>,
+    /// Rows per page at most, whatever limit the client asks for
+    pub max_limit: Option<i64>,
+}
+
+/// Limits of the admin SQL console (`POST /admin/sql`)
+#[derive(Debug, Deserialize, Clone)]
+pub struct SqlConsoleConfig {
+    /// Rows returned by a query at most; the rest are cut off
+    #[serde(default = "default_sql_console_max_rows")]
+    pub max_rows: usize,
+    /// Statements running longer than this are abandoned
+    #[serde(default = "default_sql_console_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for SqlConsoleConfig {
+    fn default() -> Self {
+        Self {
+            max_rows: default_sql_console_max_rows(),
+            timeout_ms: default_sql_console_timeout_ms(),
+        }
+    }
+}
+
+/// Route groups that can be switched off, evaluated once when the router is built
+/// Everything is enabled by default; disabled groups are not mounted at all and answer 404
+#[derive(Debug, Deserialize, Clone)]
+pub struct FeaturesConfig {
+    /// `/admin/*` reports, announcement management, and destructive sudo endpoints
+    #[serde(default = "default_true")]
+    pub admin: bool,
+    /// Generic table access under `/db/{table}`
+    #[serde(default = "default_true")]
+    pub database: bool,
+    /// Comment threads on records and `/comments/*`
+    #[serde(default = "default_true")]
+    pub comments: bool,
+    /// `/announcements` and their admin endpoints
+    #[serde(default = "default_true")]
+    pub announcements: bool,
+    /// The `/activity` feed
+    #[serde(default = "default_true")]
+    pub activity: bool,
+    /// Server-sent event streams (`/activity/stream`)
+    #[serde(default = "default_true")]
+    pub realtime: bool,
+    /// Public share links (`/files/{id}/shares` and `/share/{token}`)
+    #[serde(default = "default_true")]
+    pub shares: bool,
+    /// The OpenMetrics endpoint (`/metrics`)
+    #[serde(default = "default_true")]
+    pub metrics: bool,
+}
+
+impl FeaturesConfig {
+    /// Names of the route groups that are switched off
+    pub fn disabled(&self) -> Vec<&'static str> {
+        [
+            ("admin", self.admin),
+            ("database", self.database),
+            ("comments", self.comments),
+            ("announcements", self.announcements),
+            ("activity", self.activity),
+            ("realtime", self.realtime),
+            ("shares", self.shares),
+            ("metrics", self.metrics),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| !enabled)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            admin: true,
+            database: true,
+            comments: true,
+            announcements: true,
+            activity: true,
+            realtime: true,
+            shares: true,
+            metrics: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BillingConfig {
+    /// Signing secret of the Stripe webhook endpoint (`whsec_...`)
+    pub stripe_webhook_secret: String,
+    /// Plan for users without an active subscription
+    pub default_plan: String,
+    pub plans: Vec<PlanConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PlanConfig {
+    pub name: String,
+    /// Stripe price that subscribes a customer to this plan
+    pub stripe_price_id: Option<String>,
+    pub max_file_size_bytes: Option<u64>,
+    pub max_storage_bytes: Option<u64>,
+    pub max_files: Option<u64>,
+    /// Requests a user or organization on the plan may make per minute, per server instance
+    pub requests_per_minute: Option<u64>,
+    /// Requests a user or organization on the plan may make per calendar month
+    pub requests_per_month: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DatabaseConfig {
     pub url: String,
+    /// Declared schema applied by `server db push`
+    #[serde(default = "default_schema_path")]
+    pub schema_path: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -19,6 +389,110 @@ pub struct AuthConfig {
     pub jwt_secret: String,
     #[serde(default = "default_token_expiry")]
     pub token_expiry_seconds: i64,
+    /// How long a refresh token can be traded for a new access token
+    #[serde(default = "default_refresh_token_expiry")]
+    pub refresh_token_expiry_seconds: i64,
+    /// How long a deleted account can be restored before it is purged; 0 purges it straight away
+    #[serde(default = "default_account_deletion_grace")]
+    pub account_deletion_grace_seconds: i64,
+    /// How long a key replaced by `server auth rotate-secret` keeps validating tokens
+    #[serde(default = "default_key_rotation_window")]
+    pub key_rotation_window_seconds: i64,
+    /// Secret that keys added by `server auth rotate-secret` are encrypted with in the database;
+    /// `jwt_secret` is used when unset. Every instance must have the same one
+    pub signing_key_encryption_key: Option<String>,
+    /// Who may create an account through `POST /auth/signup`
+    #[serde(default)]
+    pub signup: SignupConfig,
+    /// Throttling of logins and signups
+    #[serde(default)]
+    pub login_rate_limit: LoginRateLimitConfig,
+}
+
+/// Token buckets throttling `POST /auth/login` and `POST /auth/signup` per client IP address,
+/// and wrong passwords per email address and client and per email address alone, so passwords
+/// can't be guessed at the speed Argon2 verifies them
+#[derive(Debug, Deserialize, Clone)]
+pub struct LoginRateLimitConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Attempts an IP address can make in a burst
+    #[serde(default = "default_login_ip_burst")]
+    pub ip_burst: u32,
+    /// Attempts an IP address regains per minute
+    #[serde(default = "default_login_ip_per_minute")]
+    pub ip_per_minute: u32,
+    /// Wrong passwords one IP address can give for one email address in a burst
+    #[serde(default = "default_login_email_burst")]
+    pub email_burst: u32,
+    /// Wrong passwords regained per minute
+    #[serde(default = "default_login_email_per_minute")]
+    pub email_per_minute: u32,
+    /// Wrong passwords all clients together can give for one email address in a burst
+    #[serde(default = "default_login_account_burst")]
+    pub account_burst: u32,
+    /// Wrong passwords regained per minute by all clients together
+    #[serde(default = "default_login_account_per_minute")]
+    pub account_per_minute: u32,
+}
+
+impl Default for LoginRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ip_burst: default_login_ip_burst(),
+            ip_per_minute: default_login_ip_per_minute(),
+            email_burst: default_login_email_burst(),
+            email_per_minute: default_login_email_per_minute(),
+            account_burst: default_login_account_burst(),
+            account_per_minute: default_login_account_per_minute(),
+        }
+    }
+}
+
+/// Who may create an account
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SignupMode {
+    /// Anyone
+    #[default]
+    Open,
+    /// Only addresses a file was shared with before they had an account
+    InviteOnly,
+    /// Nobody
+    Closed,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SignupConfig {
+    #[serde(default)]
+    pub mode: SignupMode,
+    /// Email domains accounts may be created for, in every mode; any domain when empty
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+}
+
+impl SignupConfig {
+    /// Whether the domain of `email` is allowed to sign up, ignoring case
+    /// Domains may be listed with a leading `@`
+    pub fn allows_domain(&self, email: &str) -> bool {
+        if self.allowed_domains.is_empty() {
+            return true;
+        }
+        let Some((_, domain)) = email.trim().rsplit_once('@') else {
+            return false;
+        };
+        self.allowed_domains
+            .iter()
+            .any(|allowed| allowed.trim().trim_start_matches('@').eq_ignore_ascii_case(domain))
+    }
+
+    /// Whether accounts must verify their address before they can be used
+    /// Signups are only restricted by the address typed in, so without verification anyone could
+    /// sign up with an invited or allowed address they don't own
+    pub fn requires_verified_email(&self) -> bool {
+        self.mode == SignupMode::InviteOnly || !self.allowed_domains.is_empty()
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -27,12 +501,445 @@ pub struct ServerConfig {
     pub host: String,
     #[serde(default = "default_port")]
     pub port: u16,
+    /// Bearer token required to scrape `/metrics`; the endpoint is open when unset
+    pub metrics_token: Option<String>,
+    /// Take client addresses from the `X-Forwarded-For` header (behind a reverse proxy only)
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+    /// Reverse proxies in front of the server that append to `X-Forwarded-For`
+    /// The client is the entry this many places from the right; entries further left were
+    /// sent by the client and can't be trusted
+    #[serde(default = "default_trusted_proxy_hops")]
+    pub trusted_proxy_hops: usize,
+}
+
+impl ServerConfig {
+    /// Trusted proxies whose `X-Forwarded-For` entries identify clients; 0 when the header
+    /// isn't trusted
+    pub fn forwarded_for_hops(&self) -> usize {
+        if self.trust_forwarded_for { self.trusted_proxy_hops } else { 0 }
+    }
+}
+
+/// How new file IDs are generated
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FileIdKind {
+    /// Random UUIDs
+    #[default]
+    UuidV4,
+    /// Time-ordered UUIDs
+    UuidV7,
+    /// Time-ordered 26-character ULIDs
+    Ulid,
+    /// Random URL-safe IDs of `storage.nanoid_length` characters
+    Nanoid,
+}
+
+/// Which backend file blobs are written to
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackendKind {
+    /// Local filesystem under `storage.path`
+    #[default]
+    Local,
+    /// S3-compatible object storage configured in `[storage.s3]`
+    S3,
+    /// Google Cloud Storage configured in `[storage.gcs]`
+    Gcs,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StorageConfig {
+    #[serde(default)]
+    pub backend: StorageBackendKind,
+    #[serde(default = "default_storage_path")]
+    pub path: String,
+    pub s3: Option<S3Config>,
+    pub gcs: Option<GcsConfig>,
+    #[serde(default)]
+    pub retry: StorageRetryConfig,
+    /// Namespace each owner's blobs under a per-tenant prefix
+    #[serde(default)]
+    pub tenant_isolation: bool,
+    /// Write uploads as pending blobs and commit them once their metadata is recorded
+    #[serde(default)]
+    pub two_phase_uploads: bool,
+    /// Reject uploads whose declared content type contradicts the type detected from their bytes
+    #[serde(default)]
+    pub strict_mime_types: bool,
+    /// Rules every upload must satisfy
+    #[serde(default)]
+    pub upload_policy: UploadPolicyConfig,
+    /// Scheme of new file IDs; existing files keep theirs
+    #[serde(default)]
+    pub file_ids: FileIdKind,
+    /// Length of nanoid file IDs
+    #[serde(default = "default_nanoid_length")]
+    pub nanoid_length: usize,
+    /// Age after which uncommitted pending blobs are garbage collected and unfinished journaled
+    /// uploads are recovered
+    #[serde(default = "default_pending_upload_grace_seconds")]
+    pub pending_upload_grace_seconds: u64,
+    #[serde(default)]
+    pub watch: StorageWatchConfig,
+    /// Scheduled re-hashing of stored files against their checksums
+    #[serde(default)]
+    pub integrity_audit: IntegrityAuditConfig,
+    /// Bandwidth caps of file downloads
+    #[serde(default)]
+    pub download_limits: DownloadLimitsConfig,
+    /// In-memory cache of small, frequently read blobs
+    #[serde(default)]
+    pub cache: BlobCacheConfig,
+    /// Background extraction of document text for file search
+    #[serde(default)]
+    pub text_extraction: TextExtractionConfig,
+    /// Permanent deletion of files left in the trash
+    #[serde(default)]
+    pub trash_retention: TrashRetentionConfig,
+    /// Remote SFTP directory polled for partner deliveries
+    pub sftp: Option<SftpIngestConfig>,
+    /// Importing files from remote URLs; disabled when the section is absent
+    pub url_import: Option<UrlImportConfig>,
+    /// External origin (CDN or reverse proxy) that generated file links point at,
+    /// e.g. `https://cdn.example.com`; links are relative to the server when unset
+    pub public_base_url: Option<String>,
+    /// Secret presigned upload URLs are signed with; `auth.jwt_secret` is used when unset.
+    /// Every instance must have the same one
+    pub presigned_upload_key: Option<String>,
+    /// Secret that credentials of user-registered buckets are encrypted with;
+    /// users can only register their own bucket when this is set
+    pub bucket_credentials_key: Option<String>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackendKind::default(),
+            path: default_storage_path(),
+            s3: None,
+            gcs: None,
+            retry: StorageRetryConfig::default(),
+            tenant_isolation: false,
+            two_phase_uploads: false,
+            strict_mime_types: false,
+            upload_policy: UploadPolicyConfig::default(),
+            file_ids: FileIdKind::default(),
+            nanoid_length: default_nanoid_length(),
+            pending_upload_grace_seconds: default_pending_upload_grace_seconds(),
+            watch: StorageWatchConfig::default(),
+            integrity_audit: IntegrityAuditConfig::default(),
+            download_limits: DownloadLimitsConfig::default(),
+            cache: BlobCacheConfig::default(),
+            text_extraction: TextExtractionConfig::default(),
+            trash_retention: TrashRetentionConfig::default(),
+            sftp: None,
+            url_import: None,
+            public_base_url: None,
+            presigned_upload_key: None,
+            bucket_credentials_key: None,
+        }
+    }
+}
+
+/// Upload validation rules; empty lists and an unset size impose no restriction
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct UploadPolicyConfig {
+    /// Largest upload in bytes
+    pub max_size_bytes: Option<u64>,
+    /// File name extensions that may be uploaded, e.g. `["png", "pdf"]`
+    #[serde(default)]
+    pub allowed_extensions: Vec<String>,
+    /// MIME types that may be uploaded; `image/*` allows a whole family
+    #[serde(default)]
+    pub allowed_mime_types: Vec<String>,
+    /// MIME types that are always rejected, whether declared or detected
+    #[serde(default)]
+    pub blocked_mime_types: Vec<String>,
+}
+
+/// Drop directory whose files are ingested into storage as they appear
+#[derive(Debug, Deserialize, Clone)]
+pub struct StorageWatchConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directory to watch; defaults to `storage.path`
+    pub path: Option<String>,
+    /// User that ingested files are registered under
+    pub owner_user_id: Option<i64>,
+    #[serde(default = "default_watch_settle_ms")]
+    pub settle_ms: u64,
+}
+
+impl Default for StorageWatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: None,
+            owner_user_id: None,
+            settle_ms: default_watch_settle_ms(),
+        }
+    }
+}
+
+/// Background audit that re-hashes stored files and flags those that no longer match
+#[derive(Debug, Deserialize, Clone)]
+pub struct IntegrityAuditConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Time between audits
+    #[serde(default = "default_integrity_audit_interval_seconds")]
+    pub interval_seconds: u64,
+    /// Files checked per audit, least recently checked first; every file when unset
+    pub sample_size: Option<u64>,
+}
+
+impl Default for IntegrityAuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_seconds: default_integrity_audit_interval_seconds(),
+            sample_size: None,
+        }
+    }
+}
+
+/// Background task that extracts the text of uploaded documents so search matches their content
+#[derive(Debug, Deserialize, Clone)]
+pub struct TextExtractionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Time between passes
+    #[serde(default = "default_text_extraction_interval_seconds")]
+    pub interval_seconds: u64,
+    /// Files extracted per pass
+    #[serde(default = "default_text_extraction_batch_size")]
+    pub batch_size: u64,
+    /// Largest file whose text is extracted
+    #[serde(default = "default_text_extraction_max_file_bytes")]
+    pub max_file_bytes: u64,
+}
+
+impl Default for TextExtractionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_seconds: default_text_extraction_interval_seconds(),
+            batch_size: default_text_extraction_batch_size(),
+            max_file_bytes: default_text_extraction_max_file_bytes(),
+        }
+    }
+}
+
+/// Background task that permanently deletes files once they have been in the trash too long
+#[derive(Debug, Deserialize, Clone)]
+pub struct TrashRetentionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Days a file stays in the trash before it is purged
+    #[serde(default = "default_trash_retention_days")]
+    pub retention_days: u64,
+    /// Time between passes
+    #[serde(default = "default_trash_retention_interval_seconds")]
+    pub interval_seconds: u64,
+    /// Files purged per pass
+    #[serde(default = "default_trash_retention_batch_size")]
+    pub batch_size: u64,
+}
+
+impl Default for TrashRetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retention_days: default_trash_retention_days(),
+            interval_seconds: default_trash_retention_interval_seconds(),
+            batch_size: default_trash_retention_batch_size(),
+        }
+    }
+}
+
+/// Download bandwidth caps, enforced by each instance; unset caps are unlimited
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct DownloadLimitsConfig {
+    /// Bytes per second one download may be sent at
+    pub connection_bytes_per_second: Option<u64>,
+    /// Bytes per second all of a user's downloads together may be sent at
+    pub user_bytes_per_second: Option<u64>,
+}
+
+/// Least-recently-used cache of small blobs, kept in memory by each instance
+#[derive(Debug, Deserialize, Clone)]
+pub struct BlobCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Total size of the cached blobs
+    #[serde(default = "default_cache_max_bytes")]
+    pub max_bytes: u64,
+    /// Largest blob that is cached
+    #[serde(default = "default_cache_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// How long a blob is served from memory before it is read again
+    #[serde(default = "default_cache_ttl_seconds")]
+    pub ttl_seconds: u64,
+}
+
+impl Default for BlobCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes: default_cache_max_bytes(),
+            max_file_bytes: default_cache_max_file_bytes(),
+            ttl_seconds: default_cache_ttl_seconds(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GcsConfig {
+    pub bucket: String,
+    /// Service-account JSON key file; Application Default Credentials are used when unset
+    pub credentials_path: Option<String>,
+    pub prefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SftpIngestConfig {
+    pub host: String,
+    #[serde(default = "default_sftp_port")]
+    pub port: u16,
+    pub username: String,
+    pub password: Option<String>,
+    pub private_key_path: Option<String>,
+    pub private_key_passphrase: Option<String>,
+    /// Hex-encoded SHA-256 fingerprint of the server's host key; required, ingestion doesn't start without it
+    pub host_fingerprint: Option<String>,
+    pub remote_dir: String,
+    /// Remote directory ingested files are moved to; they are deleted when unset
+    pub processed_dir: Option<String>,
+    /// User that ingested files are registered under
+    pub owner_user_id: i64,
+    #[serde(default = "default_sftp_poll_seconds")]
+    pub poll_seconds: u64,
+}
+
+/// Limits on files the server fetches for `POST /files/import`
+#[derive(Debug, Deserialize, Clone)]
+pub struct UrlImportConfig {
+    /// Largest file that may be imported
+    #[serde(default = "default_url_import_max_size_bytes")]
+    pub max_size_bytes: u64,
+    /// MIME types that may be imported; `image/*` allows a whole family, and any type when empty
+    #[serde(default)]
+    pub allowed_mime_types: Vec<String>,
+    /// Downloads taking longer fail
+    #[serde(default = "default_url_import_timeout_seconds")]
+    pub timeout_seconds: u64,
+    /// Redirects followed before giving up
+    #[serde(default = "default_url_import_max_redirects")]
+    pub max_redirects: u32,
+}
+
+/// Retry/backoff and circuit-breaker settings for remote storage backends
+#[derive(Debug, Deserialize, Clone)]
+pub struct StorageRetryConfig {
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub base_delay_ms: u64,
+    #[serde(default = "default_retry_max_delay_ms")]
+    pub max_delay_ms: u64,
+    #[serde(default = "default_circuit_failure_threshold")]
+    pub circuit_failure_threshold: u32,
+    #[serde(default = "default_circuit_reset_seconds")]
+    pub circuit_reset_seconds: u64,
+}
+
+impl Default for StorageRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_max_attempts(),
+            base_delay_ms: default_retry_base_delay_ms(),
+            max_delay_ms: default_retry_max_delay_ms(),
+            circuit_failure_threshold: default_circuit_failure_threshold(),
+            circuit_reset_seconds: default_circuit_reset_seconds(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct S3Config {
+    pub bucket: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    pub endpoint: Option<String>,
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    #[serde(default)]
+    pub path_style: bool,
+    pub prefix: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_sql_console_max_rows() -> usize {
+    1000
+}
+
+fn default_sql_console_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_db_encryption_readers() -> Vec<String> {
+    vec!["service".to_string()]
+}
+
+fn default_schema_path() -> String {
+    "schema.toml".to_string()
 }
 
 fn default_token_expiry() -> i64 {
     3600 // 1 hour
 }
 
+fn default_refresh_token_expiry() -> i64 {
+    30 * 24 * 3600 // 30 days
+}
+
+fn default_account_deletion_grace() -> i64 {
+    30 * 24 * 3600 // 30 days
+}
+
+fn default_key_rotation_window() -> i64 {
+    7 * 24 * 3600 // 7 days
+}
+
+fn default_login_ip_burst() -> u32 {
+    20
+}
+
+fn default_login_ip_per_minute() -> u32 {
+    10
+}
+
+fn default_login_email_burst() -> u32 {
+    5
+}
+
+fn default_login_email_per_minute() -> u32 {
+    2
+}
+
+fn default_login_account_burst() -> u32 {
+    50
+}
+
+fn default_login_account_per_minute() -> u32 {
+    10
+}
+
 fn default_host() -> String {
     "0.0.0.0".to_string()
 }
@@ -41,6 +948,138 @@ fn default_port() -> u16 {
     3000
 }
 
+fn default_trusted_proxy_hops() -> usize {
+    1
+}
+
+fn default_storage_path() -> String {
+    "./storage".to_string()
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_base_delay_ms() -> u64 {
+    100
+}
+
+fn default_retry_max_delay_ms() -> u64 {
+    2000
+}
+
+fn default_circuit_failure_threshold() -> u32 {
+    5
+}
+
+fn default_circuit_reset_seconds() -> u64 {
+    30
+}
+
+fn default_pending_upload_grace_seconds() -> u64 {
+    3600 // 1 hour
+}
+
+fn default_nanoid_length() -> usize {
+    21
+}
+
+fn default_watch_settle_ms() -> u64 {
+    1000
+}
+
+fn default_integrity_audit_interval_seconds() -> u64 {
+    24 * 3600
+}
+
+fn default_text_extraction_interval_seconds() -> u64 {
+    60
+}
+
+fn default_text_extraction_batch_size() -> u64 {
+    100
+}
+
+fn default_text_extraction_max_file_bytes() -> u64 {
+    20 * 1024 * 1024
+}
+
+fn default_trash_retention_days() -> u64 {
+    30
+}
+
+fn default_trash_retention_interval_seconds() -> u64 {
+    3600
+}
+
+fn default_trash_retention_batch_size() -> u64 {
+    500
+}
+
+fn default_cache_max_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_cache_max_file_bytes() -> u64 {
+    256 * 1024
+}
+
+fn default_cache_ttl_seconds() -> u64 {
+    300
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    5
+}
+
+fn default_webhook_retry_base_seconds() -> u64 {
+    30
+}
+
+fn default_webhook_timeout_seconds() -> u64 {
+    10
+}
+
+fn default_jobs_max_concurrency() -> usize {
+    4
+}
+
+fn default_jobs_retry_base_seconds() -> u64 {
+    30
+}
+
+fn default_cluster_redis_channel() -> String {
+    "projectkit:activity".to_string()
+}
+
+fn default_streaming_subject_prefix() -> String {
+    "projectkit".to_string()
+}
+
+fn default_sftp_port() -> u16 {
+    22
+}
+
+fn default_sftp_poll_seconds() -> u64 {
+    60
+}
+
+fn default_url_import_max_size_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_url_import_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_url_import_max_redirects() -> u32 {
+    5
+}
+
 impl AppConfig {
     /// Load configuration from a TOML file
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
@@ -81,6 +1120,8 @@ impl AppConfig {
             ("PROJECTKIT_AUTH_TOKEN_EXPIRY_SECONDS", "auth.token_expiry_seconds"),
             ("PROJECTKIT_SERVER_HOST", "server.host"),
             ("PROJECTKIT_SERVER_PORT", "server.port"),
+            ("PROJECTKIT_STORAGE_BACKEND", "storage.backend"),
+            ("PROJECTKIT_STORAGE_PATH", "storage.path"),
         ];
         
         for (env_var, config_key) in env_vars {
@@ -101,7 +1142,337 @@ mod tests {
     #[test]
     fn test_default_values() {
         assert_eq!(default_token_expiry(), 3600);
+        assert_eq!(default_key_rotation_window(), 7 * 24 * 3600);
         assert_eq!(default_host(), "0.0.0.0");
         assert_eq!(default_port(), 3000);
+        assert_eq!(default_storage_path(), "./storage");
+        assert_eq!(StorageConfig::default().backend, StorageBackendKind::Local);
+        assert!(!StorageConfig::default().two_phase_uploads);
+        assert_eq!(StorageConfig::default().pending_upload_grace_seconds, 3600);
+        assert_eq!(DbConfig::default().encryption.readers, ["service"]);
+        assert!(!StorageConfig::default().integrity_audit.enabled);
+        assert_eq!(StorageConfig::default().integrity_audit.interval_seconds, 86400);
+        assert_eq!(SignupConfig::default().mode, SignupMode::Open);
+    }
+
+    #[test]
+    fn test_signup_config() {
+        let config: SignupConfig = toml::from_str("mode = \"invite_only\"\nallowed_domains = [\"example.com\", \"@Example.org\"]").unwrap();
+        assert_eq!(config.mode, SignupMode::InviteOnly);
+        assert!(config.allows_domain("alice@EXAMPLE.com"));
+        assert!(config.allows_domain("bob@example.org"));
+        assert!(!config.allows_domain("mallory@example.com.evil.net"));
+        assert!(!config.allows_domain("mallory@sub.example.com"));
+        assert!(!config.allows_domain("no-domain"));
+        assert!(SignupConfig::default().allows_domain("anyone@anywhere.net"));
+        assert!(config.requires_verified_email());
+        assert!(!SignupConfig::default().requires_verified_email());
+    }
+
+    #[test]
+    fn test_s3_storage_config() {
+        let toml = r#"
+            backend = "s3"
+
+            [s3]
+            bucket = "uploads"
+            endpoint = "http://localhost:9000"
+            path_style = true
+        "#;
+
+        let config: StorageConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.backend, StorageBackendKind::S3);
+        assert_eq!(config.path, "./storage");
+
+        let s3 = config.s3.unwrap();
+        assert_eq!(s3.bucket, "uploads");
+        assert_eq!(s3.region, "us-east-1");
+        assert!(s3.path_style);
+    }
+
+    #[test]
+    fn test_file_id_config() {
+        let config: StorageConfig = toml::from_str("").unwrap();
+        assert_eq!(config.file_ids, FileIdKind::UuidV4);
+
+        let config: StorageConfig = toml::from_str("file_ids = \"nanoid\"\nnanoid_length = 12").unwrap();
+        assert_eq!(config.file_ids, FileIdKind::Nanoid);
+        assert_eq!(config.nanoid_length, 12);
+
+        let config: StorageConfig = toml::from_str("file_ids = \"uuid_v7\"").unwrap();
+        assert_eq!(config.file_ids, FileIdKind::UuidV7);
+    }
+
+    #[test]
+    fn test_features_config() {
+        let config: FeaturesConfig = toml::from_str("").unwrap();
+        assert!(config.disabled().is_empty());
+
+        let config: FeaturesConfig = toml::from_str("realtime = false\nadmin = false").unwrap();
+        assert!(!config.realtime);
+        assert!(config.shares);
+        assert_eq!(config.disabled(), vec!["admin", "realtime"]);
+    }
+
+    #[test]
+    fn test_gcs_storage_config() {
+        let toml = r#"
+            backend = "gcs"
+
+            [gcs]
+            bucket = "uploads"
+            credentials_path = "/etc/projectkit/gcs.json"
+        "#;
+
+        let config: StorageConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.backend, StorageBackendKind::Gcs);
+
+        let gcs = config.gcs.unwrap();
+        assert_eq!(gcs.bucket, "uploads");
+        assert_eq!(gcs.credentials_path.as_deref(), Some("/etc/projectkit/gcs.json"));
+        assert!(gcs.prefix.is_none());
+    }
+
+    #[test]
+    fn test_sftp_ingest_config() {
+        let toml = r#"
+            [sftp]
+            host = "sftp.partner.example"
+            username = "projectkit"
+            private_key_path = "/etc/projectkit/id_ed25519"
+            remote_dir = "/outbound"
+            owner_user_id = 7
+        "#;
+
+        let config: StorageConfig = toml::from_str(toml).unwrap();
+        let sftp = config.sftp.unwrap();
+        assert_eq!(sftp.port, 22);
+        assert_eq!(sftp.poll_seconds, 60);
+        assert_eq!(sftp.owner_user_id, 7);
+        assert!(sftp.password.is_none());
+        assert!(sftp.processed_dir.is_none());
+    }
+
+    #[test]
+    fn test_billing_config() {
+        let toml = r#"
+            stripe_webhook_secret = "whsec_test"
+            default_plan = "free"
+
+            [[plans]]
+            name = "free"
+            max_storage_bytes = 1073741824
+
+            [[plans]]
+            name = "pro"
+            stripe_price_id = "price_pro"
+            requests_per_minute = 600
+        "#;
+
+        let config: BillingConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.plans.len(), 2);
+        assert_eq!(config.plans[0].max_storage_bytes, Some(1073741824));
+        assert!(config.plans[0].stripe_price_id.is_none());
+        assert_eq!(config.plans[1].stripe_price_id.as_deref(), Some("price_pro"));
+        assert!(config.plans[1].max_files.is_none());
+        assert_eq!(config.plans[1].requests_per_minute, Some(600));
+        assert!(config.plans[1].requests_per_month.is_none());
+    }
+
+    #[test]
+    fn test_deprecations_config() {
+        let toml = r#"
+            [[deprecations]]
+            route = "/files/{id}/copy"
+            method = "POST"
+            since = "2024-11-01T00:00:00Z"
+            sunset = "2025-05-01T00:00:00Z"
+
+            [[deprecations]]
+            route = "/files/stats"
+            field = "total_size"
+            since = "2024-11-01T00:00:00Z"
+        "#;
+
+        #[derive(Deserialize)]
+        struct Deprecations {
+            deprecations: Vec<DeprecationConfig>,
+        }
+        let config: Deprecations = toml::from_str(toml).unwrap();
+        assert_eq!(config.deprecations.len(), 2);
+        assert_eq!(config.deprecations[0].method.as_deref(), Some("POST"));
+        assert!(config.deprecations[0].field.is_none());
+        assert_eq!(config.deprecations[1].field.as_deref(), Some("total_size"));
+        assert!(config.deprecations[1].sunset.is_none());
+    }
+
+    #[test]
+    fn test_webhooks_config() {
+        let toml = r#"
+            retry_base_seconds = 60
+
+            [[endpoints]]
+            url = "https://hooks.example.com/files"
+            secret = "whsec_test"
+            events = ["file.created"]
+
+            [[endpoints]]
+            url = "https://audit.example.com"
+            secret = "whsec_audit"
+        "#;
+
+        let config: WebhooksConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.max_attempts, 5);
+        assert_eq!(config.retry_base_seconds, 60);
+        assert_eq!(config.timeout_seconds, 10);
+        assert_eq!(config.endpoints[0].events, vec!["file.created"]);
+        assert!(config.endpoints[1].events.is_empty());
+    }
+
+    #[test]
+    fn test_url_import_config() {
+        let config: UrlImportConfig = toml::from_str(r#"allowed_mime_types = ["image/*"]"#).unwrap();
+        assert_eq!(config.max_size_bytes, 100 * 1024 * 1024);
+        assert_eq!(config.allowed_mime_types, vec!["image/*"]);
+        assert_eq!(config.timeout_seconds, 30);
+        assert_eq!(config.max_redirects, 5);
+    }
+
+    #[test]
+    fn test_jobs_config() {
+        let toml = r#"
+            max_concurrency = 2
+
+            [types.integrity_audit]
+            priority = -5
+            timeout_seconds = 7200
+        "#;
+
+        let config: JobsConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.max_concurrency, 2);
+        assert_eq!(config.retry_base_seconds, 30);
+        let audit = &config.types["integrity_audit"];
+        assert_eq!(audit.priority, Some(-5));
+        assert_eq!(audit.timeout_seconds, Some(7200));
+        assert!(audit.concurrency.is_none());
+        assert!(audit.max_attempts.is_none());
+    }
+
+    #[test]
+    fn test_cluster_config() {
+        let config: ClusterConfig = toml::from_str(r#"redis_url = "redis://127.0.0.1:6379""#).unwrap();
+        assert_eq!(config.redis_url.as_deref(), Some("redis://127.0.0.1:6379"));
+        assert_eq!(config.redis_channel, "projectkit:activity");
+        assert!(!config.shared_local_storage);
+        assert!(ClusterConfig::default().redis_url.is_none());
+    }
+
+    #[test]
+    fn test_mail_config() {
+        let config: MailConfig = toml::from_str(
+            r#"
+            from = "ProjectKit <noreply@example.com>"
+            smtp_host = "smtp.example.com"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.smtp_host.as_deref(), Some("smtp.example.com"));
+        assert_eq!(config.smtp_port, 465);
+        assert!(config.smtp_username.is_none());
+    }
+
+    #[test]
+    fn test_streaming_config() {
+        let toml = r#"
+            events = ["file.uploaded"]
+
+            [[publishers]]
+            kind = "nats"
+            url = "nats://127.0.0.1:4222"
+
+            [[publishers]]
+            kind = "rabbitmq"
+            url = "amqp://127.0.0.1:5672/%2f"
+            exchange = "changes"
+        "#;
+
+        let config: StreamingConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.subject_prefix, "projectkit");
+        assert_eq!(config.events, vec!["file.uploaded"]);
+        assert_eq!(config.publishers[0].kind, StreamPublisherKind::Nats);
+        assert_eq!(config.publishers[0].exchange, "projectkit");
+        assert_eq!(config.publishers[1].kind, StreamPublisherKind::Rabbitmq);
+        assert_eq!(config.publishers[1].exchange, "changes");
+    }
+
+    #[test]
+    fn test_db_config() {
+        let config: DbConfig = toml::from_str(r#"server_managed_columns = ["user_id", "notes.pinned"]"#).unwrap();
+        assert_eq!(config.server_managed_columns, vec!["user_id", "notes.pinned"]);
+        assert!(DbConfig::default().server_managed_columns.is_empty());
+
+        let config: DbConfig = toml::from_str(
+            r#"
+            [reads]
+            max_limit = 500
+
+            [tables.events]
+            order_by = "created_at desc"
+            default_limit = 20
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.reads.max_limit, Some(500));
+        assert_eq!(config.reads.order_by, None);
+        assert_eq!(config.tables["events"].order_by.as_deref(), Some("created_at desc"));
+        assert_eq!(config.tables["events"].default_limit, Some(20));
+    }
+
+    #[test]
+    fn test_public_base_url_config() {
+        let config: StorageConfig = toml::from_str(r#"public_base_url = "https://cdn.example.com""#).unwrap();
+        assert_eq!(config.public_base_url.as_deref(), Some("https://cdn.example.com"));
+        assert!(StorageConfig::default().public_base_url.is_none());
+    }
+
+    #[test]
+    fn test_download_limits_config() {
+        let toml = r#"
+            [download_limits]
+            user_bytes_per_second = 10485760
+        "#;
+
+        let config: StorageConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.download_limits.user_bytes_per_second, Some(10485760));
+        assert!(config.download_limits.connection_bytes_per_second.is_none());
+    }
+
+    #[test]
+    fn test_blob_cache_config() {
+        let toml = r#"
+            [cache]
+            enabled = true
+            max_bytes = 1048576
+        "#;
+
+        let config: StorageConfig = toml::from_str(toml).unwrap();
+        assert!(config.cache.enabled);
+        assert_eq!(config.cache.max_bytes, 1048576);
+        assert_eq!(config.cache.max_file_bytes, 256 * 1024);
+        assert!(!StorageConfig::default().cache.enabled);
+    }
+
+    #[test]
+    fn test_text_extraction_config() {
+        let toml = r#"
+            [text_extraction]
+            enabled = true
+            batch_size = 10
+        "#;
+
+        let config: StorageConfig = toml::from_str(toml).unwrap();
+        assert!(config.text_extraction.enabled);
+        assert_eq!(config.text_extraction.batch_size, 10);
+        assert_eq!(config.text_extraction.interval_seconds, 60);
     }
 }
@@ -7,7 +7,7 @@ pub use orm::schema::{Column, Table};
 pub use orm::transaction::Transaction;
 
 pub mod config;
-pub use config::{AppConfig, AuthConfig, DatabaseConfig, ServerConfig};
+pub use config::{AppConfig, AuthConfig, BillingConfig, BlobCacheConfig, ClusterConfig, DatabaseConfig, DbConfig, DbEncryptionConfig, DbPolicyConfig, DbReadConfig, DeprecationConfig, DownloadLimitsConfig, FeaturesConfig, FileIdKind, GcsConfig, GeoIpConfig, IntegrityAuditConfig, JobTypeConfig, JobsConfig, LoginRateLimitConfig, MailConfig, S3Config, ServerConfig, SftpIngestConfig, SignupConfig, SignupMode, PlanConfig, SqlConsoleConfig, StorageBackendKind, StorageConfig, StorageRetryConfig, StorageWatchConfig, StreamPublisherConfig, StreamPublisherKind, StreamingConfig, TextExtractionConfig, TrashRetentionConfig, UploadPolicyConfig, UrlImportConfig, WebhookEndpointConfig, WebhooksConfig};
 
 pub mod orm_utils {
     pub use orm::utils::{mysql_row_to_json, sqlite_row_to_json};
@@ -0,0 +1,389 @@
+//! `server doctor`: a PASS/FAIL report on everything the server needs to start
+//!
+//! Each check runs even when an earlier one fails, unless it depends on it (no migration
+//! check without a database), so a first run shows every problem at once. The same cheap
+//! checks are repeated as warnings on every normal start.
+
+use api::db_handlers::{EncryptedColumns, ReadPolicies, ServerManagedColumns};
+use api::row_policies::RowPolicies;
+use api::deprecation::Deprecations;
+use auth::Role;
+use chrono::{DateTime, NaiveDate, Utc};
+use projectkit_core::{AppConfig, Database, StreamPublisherKind};
+use server::migrations;
+use std::fmt;
+
+/// The secret shipped in the sample `projectkit.toml`
+const SAMPLE_JWT_SECRET: &str = "super-secret-key-change-in-production";
+
+/// Shortest JWT secret that isn't reported as weak
+const MIN_JWT_SECRET_LENGTH: usize = 32;
+
+/// How far the database clock may drift from ours before it is reported
+const MAX_CLOCK_SKEW_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+    Skip,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+            Status::Skip => "SKIP",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self { name, status, detail: detail.into() }
+    }
+}
+
+/// Run every check and print the report
+/// Returns false if any check failed
+pub async fn run() -> bool {
+    println!("🩺 Checking projectkit setup");
+
+    let checks = checks().await;
+    for check in &checks {
+        println!("  {}  {:<14} {}", check.status, check.name, check.detail);
+    }
+    println!("{}", summary(&checks));
+
+    !checks.iter().any(|check| check.status == Status::Fail)
+}
+
+async fn checks() -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let config = match AppConfig::load_with_env() {
+        Ok((config, overrides)) => {
+            checks.push(check_config(&config, overrides.len()));
+            config
+        }
+        Err(e) => {
+            checks.push(Check::new("Configuration", Status::Fail, format!("failed to load: {}", e)));
+            for name in ["Database", "Migrations", "Storage", "JWT signing", "SMTP", "Clock"] {
+                checks.push(Check::new(name, Status::Skip, "needs a valid configuration"));
+            }
+            return checks;
+        }
+    };
+
+    let db = match Database::connect(&config.database.url).await {
+        Ok(db) => {
+            checks.push(Check::new("Database", Status::Pass, format!("connected to {}", database_kind(&config.database.url))));
+            Some(db)
+        }
+        Err(e) => {
+            checks.push(Check::new("Database", Status::Fail, format!("failed to connect: {}", e)));
+            None
+        }
+    };
+
+    checks.push(match &db {
+        Some(db) => check_migrations(db, &config.database.url).await,
+        None => Check::new("Migrations", Status::Skip, "needs a database connection"),
+    });
+    checks.push(check_storage(&config).await);
+    checks.push(check_jwt(&config.auth.jwt_secret));
+    checks.push(Check::new("SMTP", Status::Skip, "no mail transport is configured"));
+    checks.push(check_clock(db.as_ref(), &config.database.url, Utc::now()).await);
+    checks
+}
+
+/// Sections that parse but would stop the server from starting
+fn check_config(config: &AppConfig, overrides: usize) -> Check {
+    let mut problems = Vec::new();
+    if let Err(e) = Deprecations::from_config(&config.deprecations) {
+        problems.push(format!("[[deprecations]]: {}", e));
+    }
+    if let Some(webhooks) = &config.webhooks {
+        for endpoint in &webhooks.endpoints {
+            for event in &endpoint.events {
+                if storage::FileEvent::from_str(event).is_none() {
+                    problems.push(format!("[webhooks]: unknown event {:?} for {}", event, endpoint.url));
+                }
+            }
+        }
+    }
+    if let Err(e) = ServerManagedColumns::from_config(&config.db.server_managed_columns) {
+        problems.push(format!("[db]: {}", e));
+    }
+    if let Err(e) = ReadPolicies::from_config(&config.db) {
+        problems.push(e);
+    }
+    if let Err(e) = EncryptedColumns::from_config(&config.db.encryption) {
+        problems.push(format!("[db.encryption]: {}", e));
+    }
+    if let Err(e) = RowPolicies::from_config(&config.db) {
+        problems.push(e);
+    }
+    if config.auth.key_rotation_window_seconds < 0 {
+        problems.push("[auth]: key_rotation_window_seconds can't be negative".to_string());
+    }
+    for domain in &config.auth.signup.allowed_domains {
+        let domain = domain.trim().trim_start_matches('@');
+        if domain.is_empty() || domain.contains('@') || !domain.contains('.') {
+            problems.push(format!("[auth.signup]: {:?} is not an email domain", domain));
+        }
+    }
+    if config.mail.as_ref().is_some_and(|mail| mail.smtp_host.is_some()) && !cfg!(feature = "smtp") {
+        problems.push("[mail] smtp_host is configured but the server was built without the `smtp` feature".to_string());
+    }
+    if config.geoip.is_some() && !cfg!(feature = "geoip") {
+        problems.push("[geoip] is configured but the server was built without the `geoip` feature".to_string());
+    }
+    if let Some(stream_config) = &config.streaming {
+        for event in &stream_config.events {
+            if streaming::ChangeKind::from_str(event).is_none() {
+                problems.push(format!("[streaming]: unknown event {:?}", event));
+            }
+        }
+        for publisher in &stream_config.publishers {
+            let (feature, built) = match publisher.kind {
+                StreamPublisherKind::Nats => ("nats", cfg!(feature = "nats")),
+                StreamPublisherKind::Kafka => ("kafka", cfg!(feature = "kafka")),
+                StreamPublisherKind::Rabbitmq => ("rabbitmq", cfg!(feature = "rabbitmq")),
+            };
+            if !built {
+                problems.push(format!("[streaming]: a {} publisher is configured but the server was built without the `{}` feature", feature, feature));
+            }
+        }
+    }
+    if config.cluster.redis_url.is_some() && !cfg!(feature = "redis") {
+        problems.push("cluster.redis_url is set but the server was built without the `redis` feature".to_string());
+    }
+
+    if !problems.is_empty() {
+        return Check::new("Configuration", Status::Fail, problems.join("; "));
+    }
+    let detail = match overrides {
+        0 => "loaded projectkit.toml".to_string(),
+        n => format!("loaded projectkit.toml with {} environment override(s)", n),
+    };
+    Check::new("Configuration", Status::Pass, detail)
+}
+
+fn database_kind(url: &str) -> &'static str {
+    if url.starts_with("sqlite") {
+        "SQLite"
+    } else if url.starts_with("postgres") {
+        "PostgreSQL"
+    } else {
+        "MySQL"
+    }
+}
+
+fn dialect(url: &str) -> orm::query::builder::Dialect {
+    if url.starts_with("sqlite") {
+        orm::query::builder::Dialect::SQLite
+    } else {
+        orm::query::builder::Dialect::MySQL
+    }
+}
+
+async fn check_migrations(db: &Database, url: &str) -> Check {
+    match migrations::inspect(db.backend(), &dialect(url)).await {
+        Ok(progress) => migration_status(&progress),
+        Err(e) => Check::new("Migrations", Status::Fail, format!("failed to inspect: {}", e)),
+    }
+}
+
+/// Interrupted migrations block startup; pending ones just run on the next start
+fn migration_status(progress: &[migrations::MigrationProgress]) -> Check {
+    if let Some(partial) = progress.iter().find(|p| p.is_partial()) {
+        return Check::new(
+            "Migrations",
+            Status::Fail,
+            format!("{} ({}) was interrupted; run `server migrate repair`", partial.version, partial.name),
+        );
+    }
+    match progress.iter().filter(|p| !p.is_complete()).count() {
+        0 => Check::new("Migrations", Status::Pass, format!("all {} applied", progress.len())),
+        pending => Check::new("Migrations", Status::Warn, format!("{} pending, they run on the next start", pending)),
+    }
+}
+
+/// Store, read back, and delete a small file
+async fn check_storage(config: &AppConfig) -> Check {
+    let storage = match crate::init_storage(&config.storage).await {
+        Ok(storage) => storage,
+        Err(e) => return Check::new("Storage", Status::Fail, format!("failed to initialize: {}", e)),
+    };
+
+    let content = format!("projectkit doctor {}", Utc::now().to_rfc3339());
+    let metadata = match storage.store(content.as_bytes(), "doctor.txt", Some("text/plain".to_string())).await {
+        Ok(metadata) => metadata,
+        Err(e) => return Check::new("Storage", Status::Fail, format!("write to {} failed: {}", storage.location(), e)),
+    };
+    let read = storage.retrieve(&metadata.stored_name).await;
+    let deleted = storage.delete(&metadata.stored_name).await;
+
+    match (read, deleted) {
+        (Err(e), _) => Check::new("Storage", Status::Fail, format!("read from {} failed: {}", storage.location(), e)),
+        (Ok(data), _) if data != content.as_bytes() => {
+            Check::new("Storage", Status::Fail, format!("{} returned different content", storage.location()))
+        }
+        (Ok(_), Err(e)) => Check::new(
+            "Storage",
+            Status::Warn,
+            format!("roundtrip passed but cleanup of {} failed: {}", metadata.id, e),
+        ),
+        (Ok(_), Ok(())) => Check::new("Storage", Status::Pass, format!("write/read roundtrip on {}", storage.location())),
+    }
+}
+
+/// Sign and validate a short-lived token with the configured secret
+fn check_jwt(secret: &str) -> Check {
+    let roundtrip = auth::generate_token("doctor", Role::User, secret, 60)
+        .and_then(|token| auth::validate_token(&token, secret));
+    match roundtrip {
+        Ok(claims) if claims.sub == "doctor" => match jwt_secret_warning(secret) {
+            Some(warning) => Check::new("JWT signing", Status::Warn, warning),
+            None => Check::new("JWT signing", Status::Pass, "signed and validated a token"),
+        },
+        Ok(_) => Check::new("JWT signing", Status::Fail, "validated token has the wrong subject"),
+        Err(e) => Check::new("JWT signing", Status::Fail, format!("roundtrip failed: {}", e)),
+    }
+}
+
+fn jwt_secret_warning(secret: &str) -> Option<String> {
+    if secret == SAMPLE_JWT_SECRET {
+        Some("jwt_secret is still the sample value from projectkit.toml".to_string())
+    } else if secret.len() < MIN_JWT_SECRET_LENGTH {
+        Some(format!("jwt_secret is only {} characters; use at least {}", secret.len(), MIN_JWT_SECRET_LENGTH))
+    } else {
+        None
+    }
+}
+
+/// The clock can't be earlier than the newest migration, and should agree with the database's
+async fn check_clock(db: Option<&Database>, url: &str, now: DateTime<Utc>) -> Check {
+    if let Some(problem) = clock_before_migrations(now) {
+        return Check::new("Clock", Status::Fail, problem);
+    }
+    let Some(db) = db else {
+        return Check::new("Clock", Status::Pass, format!("{} (database clock not checked)", now.to_rfc3339()));
+    };
+
+    let sql = if url.starts_with("sqlite") {
+        "SELECT strftime('%Y-%m-%dT%H:%M:%SZ', 'now') AS now"
+    } else {
+        "SELECT DATE_FORMAT(UTC_TIMESTAMP(), '%Y-%m-%dT%H:%i:%sZ') AS now"
+    };
+    let db_now = db.backend().fetch_one_params(sql, &[]).await.ok().flatten()
+        .and_then(|row| row.get("now").and_then(|v| v.as_str()).map(str::to_string))
+        .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+        .map(|dt| dt.with_timezone(&Utc));
+    match db_now {
+        Some(db_now) => clock_skew_status(now, db_now),
+        None => Check::new("Clock", Status::Warn, "couldn't read the database clock"),
+    }
+}
+
+fn clock_before_migrations(now: DateTime<Utc>) -> Option<String> {
+    let date = migration_date(migrations::latest_version())?;
+    (now.date_naive() < date).then(|| {
+        format!("system date {} is before the newest migration ({})", now.format("%Y-%m-%d"), date)
+    })
+}
+
+/// Migration versions start with their date, e.g. `20241018_000003`
+fn migration_date(version: i64) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(&(version / 1_000_000).to_string(), "%Y%m%d").ok()
+}
+
+fn clock_skew_status(now: DateTime<Utc>, db_now: DateTime<Utc>) -> Check {
+    let skew = (now - db_now).num_seconds().abs();
+    if skew > MAX_CLOCK_SKEW_SECONDS {
+        Check::new("Clock", Status::Warn, format!("{}s apart from the database clock", skew))
+    } else {
+        Check::new("Clock", Status::Pass, format!("within {}s of the database clock", skew))
+    }
+}
+
+/// Problems cheap enough to look for on every start
+pub fn startup_warnings(config: &AppConfig) -> Vec<String> {
+    jwt_secret_warning(&config.auth.jwt_secret)
+        .into_iter()
+        .chain(clock_before_migrations(Utc::now()))
+        .collect()
+}
+
+fn summary(checks: &[Check]) -> String {
+    let count = |status: Status| checks.iter().filter(|check| check.status == status).count();
+    let (failed, warned) = (count(Status::Fail), count(Status::Warn));
+    let icon = if failed > 0 { "❌" } else if warned > 0 { "⚠️ " } else { "✅" };
+    format!(
+        "{} {} passed, {} warning(s), {} failed, {} skipped",
+        icon,
+        count(Status::Pass),
+        warned,
+        failed,
+        count(Status::Skip)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(timestamp).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_jwt_secret_warning() {
+        assert!(jwt_secret_warning(SAMPLE_JWT_SECRET).unwrap().contains("sample"));
+        assert!(jwt_secret_warning("short").unwrap().contains("5 characters"));
+        assert!(jwt_secret_warning(&"x".repeat(MIN_JWT_SECRET_LENGTH)).is_none());
+
+        assert_eq!(check_jwt(&"x".repeat(48)).status, Status::Pass);
+        assert_eq!(check_jwt("short").status, Status::Warn);
+    }
+
+    #[test]
+    fn test_migration_date() {
+        assert_eq!(migration_date(20241018_000003), NaiveDate::from_ymd_opt(2024, 10, 18));
+        assert_eq!(migration_date(42), None);
+
+        assert!(clock_before_migrations(at("2000-01-01T00:00:00Z")).is_some());
+        assert!(clock_before_migrations(at("2100-01-01T00:00:00Z")).is_none());
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let now = at("2025-01-01T12:00:00Z");
+        assert_eq!(clock_skew_status(now, at("2025-01-01T12:00:30Z")).status, Status::Pass);
+        assert_eq!(clock_skew_status(now, at("2025-01-01T11:58:00Z")).status, Status::Warn);
+    }
+
+    #[test]
+    fn test_summary() {
+        let checks = [
+            Check::new("Database", Status::Pass, ""),
+            Check::new("Clock", Status::Warn, ""),
+            Check::new("SMTP", Status::Skip, ""),
+        ];
+        assert_eq!(summary(&checks), "⚠️  1 passed, 1 warning(s), 0 failed, 1 skipped");
+
+        let checks = [Check::new("Storage", Status::Fail, "")];
+        assert!(summary(&checks).starts_with("❌"));
+    }
+}
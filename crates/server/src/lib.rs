@@ -0,0 +1,7 @@
+//! Parts of the server that other crates build on
+//!
+//! The schema migrations live here so test support can apply the real schema, next to the
+//! declarative schema push used for prototyping.
+
+pub mod migrations;
+pub mod push;
@@ -1,14 +1,27 @@
-use api::{router, AppState};
-use auth::AuthService;
-use projectkit_core::{AppConfig, Database};
-use storage::{StorageService, TransactionalStorageService};
+use activity::{ActivityService, EventFanout};
+use announcements::AnnouncementService;
+use api::{cluster::Deployment, db_handlers::{EncryptedColumns, ReadPolicies, ServerManagedColumns}, deprecation::Deprecations, jobs::{JobLimits, JobScheduler}, router, row_policies::RowPolicies, AppState};
+use auth::{AuthService, GeoLookup, Mailer};
+use billing::{ApiLimits, BillingService, Plan};
+use comments::CommentService;
+use projectkit_core::{AppConfig, BillingConfig, ClusterConfig, Database, FileIdKind, GeoIpConfig, IntegrityAuditConfig, MailConfig, StorageBackendKind, StorageConfig, StreamPublisherConfig, StreamPublisherKind, StreamingConfig, TextExtractionConfig, TrashRetentionConfig, UploadPolicyConfig, UrlImportConfig, WebhooksConfig};
+use storage::{FileIdStrategy, StorageEvent, StorageMigrator, StorageService, TransactionalStorageService, UserBuckets, Webhooks};
+use server::{migrations, push};
+use streaming::{EventStream, Publisher};
 use std::sync::Arc;
+use tokio::sync::broadcast;
 
-mod migrations;
+mod doctor;
 mod seed;
 
 #[tokio::main]
 async fn main() {
+    // `server doctor` reports on the setup instead of starting, so it runs before anything can panic
+    if std::env::args().skip(1).eq(["doctor"]) {
+        let healthy = doctor::run().await;
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
+    
     // Load configuration from projectkit.toml with environment variable overrides
     let (config, overrides) = AppConfig::load_with_env()
         .expect("Failed to load configuration. Make sure projectkit.toml exists or set PROJECTKIT_* environment variables.");
@@ -21,6 +34,15 @@ async fn main() {
         }
     }
     
+    let disabled_features = config.features.disabled();
+    if !disabled_features.is_empty() {
+        println!("🚧 Disabled features: {}", disabled_features.join(", "));
+    }
+    
+    for warning in doctor::startup_warnings(&config) {
+        eprintln!("⚠️  {}; run `server doctor` for a full check", warning);
+    }
+    
     // Connect to database
     let db = Database::connect(&config.database.url)
         .await
@@ -33,6 +55,97 @@ async fn main() {
         orm::query::builder::Dialect::MySQL
     };
     
+    // Maintenance commands run instead of starting the server:
+    // `server migrate repair` resumes an interrupted migration, `server db push` applies schema.toml
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [] => {}
+        ["migrate", "repair"] => {
+            let resumed = migrations::repair(db.backend(), dialect)
+                .await
+                .expect("Failed to repair migrations");
+            for migration in &resumed {
+                let undone: Vec<String> = migration.applied.iter().map(|step| step.to_string()).collect();
+                println!("🔧 Resumed migration {} ({}), redoing: {}", migration.version, migration.name, undone.join(", "));
+            }
+            println!("✅ Migrations are up to date");
+            return;
+        }
+        ["db", "push", rest @ ..] if rest.is_empty() || rest == ["--apply"] => {
+            let apply = !rest.is_empty();
+            let schema_path = &config.database.schema_path;
+            let schema = push::DesiredSchema::load(schema_path)
+                .unwrap_or_else(|e| panic!("Failed to load {}: {}", schema_path, e));
+            let plan = push::plan(db.backend(), &dialect, &schema)
+                .await
+                .expect("Failed to compare schema");
+
+            println!("📐 Pushing {}", schema_path);
+            for change in &plan.changes {
+                println!("  + {}", change.description);
+            }
+            for warning in &plan.warnings {
+                println!("  ⚠️  {}", warning);
+            }
+
+            if plan.changes.is_empty() {
+                println!("✅ Database already has every declared table and column");
+            } else if apply {
+                push::apply(db.backend(), &plan)
+                    .await
+                    .expect("Failed to apply schema changes");
+                println!("✅ Applied {} change(s)", plan.changes.len());
+            } else {
+                println!("Run `server db push --apply` to make these changes");
+            }
+            return;
+        }
+        ["auth", "rotate-secret", rest @ ..] => {
+            let window_seconds = match rest {
+                [] => config.auth.key_rotation_window_seconds,
+                ["--window", seconds] => seconds.parse()
+                    .unwrap_or_else(|_| panic!("Invalid --window '{}': expected a number of seconds", seconds)),
+                _ => {
+                    eprintln!("Usage: server auth rotate-secret [--window <seconds>]");
+                    std::process::exit(2);
+                }
+            };
+            migrations::run_migrations(db.backend(), dialect)
+                .await
+                .expect("Failed to run migrations");
+            rotate_secret(&config, window_seconds).await;
+            return;
+        }
+        ["storage", "migrate", "--to", backend, rest @ ..] => {
+            let to = match *backend {
+                "local" => StorageBackendKind::Local,
+                "s3" => StorageBackendKind::S3,
+                "gcs" => StorageBackendKind::Gcs,
+                _ => {
+                    eprintln!("Unknown storage backend '{}': expected local, s3 or gcs", backend);
+                    std::process::exit(2);
+                }
+            };
+            let path = match rest {
+                [] => None,
+                ["--path", path] => Some(path.to_string()),
+                _ => {
+                    eprintln!("Usage: server storage migrate --to <local|s3|gcs> [--path <dir>]");
+                    std::process::exit(2);
+                }
+            };
+            migrations::run_migrations(db.backend(), dialect)
+                .await
+                .expect("Failed to run migrations");
+            let migrated = migrate_storage(&config, to, path).await;
+            std::process::exit(if migrated { 0 } else { 1 });
+        }
+        _ => {
+            eprintln!("Usage: server [doctor | migrate repair | db push [--apply] | auth rotate-secret [--window <seconds>] | storage migrate --to <local|s3|gcs> [--path <dir>]]");
+            std::process::exit(2);
+        }
+    }
+    
     let _ = migrations::run_migrations(db.backend(), dialect)
         .await
         .expect("Failed to run migrations");
@@ -43,11 +156,24 @@ async fn main() {
         .expect("Failed to connect to database for auth");
     
     // Initialize auth service
-    let auth_service = AuthService::new(
+    let mut auth_service = AuthService::new(
         db_for_auth,
         config.auth.jwt_secret.clone(),
         config.auth.token_expiry_seconds
-    );
+    )
+    .with_refresh_token_expiry_seconds(config.auth.refresh_token_expiry_seconds)
+    .with_deletion_grace_seconds(config.auth.account_deletion_grace_seconds)
+    .with_verified_email_required(config.auth.signup.requires_verified_email());
+    if let Some(key) = &config.auth.signing_key_encryption_key {
+        auth_service = auth_service.with_signing_key_encryption_key(key);
+    }
+    auth_service.reload_signing_keys()
+        .await
+        .expect("Failed to load signing keys");
+    if let Some(geoip) = &config.geoip {
+        let lookup = init_geoip(geoip).expect("Failed to load GeoIP database");
+        auth_service = auth_service.with_geo_lookup(lookup);
+    }
     
     // Seed database with initial data (creates default service account if needed)
     let _ = seed::seed_database(&auth_service)
@@ -55,24 +181,192 @@ async fn main() {
         .expect("Failed to seed database");
     
     // Initialize storage service
-    let storage_base_path = std::env::var("PROJECTKIT_STORAGE_PATH")
-        .unwrap_or_else(|_| "./storage".to_string());
-    
-    let storage = StorageService::new(&storage_base_path)
+    let storage = init_storage(&config.storage)
         .await
         .expect("Failed to initialize storage service");
     
-    println!("💾 Storage initialized at: {}", storage_base_path);
+    println!("💾 Storage initialized at: {}", storage.location());
     
     // Connect third database instance for storage service
     let db_for_storage = Database::connect(&config.database.url)
         .await
         .expect("Failed to connect to database for storage");
     
-    let storage_service = TransactionalStorageService::new(storage, db_for_storage);
+    let billing_service = match &config.billing {
+        Some(billing) => Some(Arc::new(init_billing(&config.database.url, billing).await)),
+        None => None,
+    };
+    
+    let mut storage_service = TransactionalStorageService::new(storage.clone(), db_for_storage)
+        .with_tenant_isolation(config.storage.tenant_isolation)
+        .with_two_phase_uploads(config.storage.two_phase_uploads)
+        .with_strict_mime_types(config.storage.strict_mime_types)
+        .with_upload_policy(upload_policy(&config.storage.upload_policy))
+        .with_public_base_url(config.storage.public_base_url.clone())
+        .with_upload_signing_key(Some(
+            config.storage.presigned_upload_key.clone().unwrap_or_else(|| config.auth.jwt_secret.clone()),
+        ));
+    if let Some(billing_service) = &billing_service {
+        storage_service = storage_service.with_limits(billing_service.clone());
+    }
+    
+    let user_buckets = match &config.storage.bucket_credentials_key {
+        Some(key) => {
+            let db_for_buckets = Database::connect(&config.database.url)
+                .await
+                .expect("Failed to connect to database for user buckets");
+            Some(Arc::new(UserBuckets::new(db_for_buckets, key)))
+        }
+        None => None,
+    };
+    if let Some(user_buckets) = &user_buckets {
+        storage_service = storage_service.with_buckets(user_buckets.clone());
+    }
+    
+    let webhooks = match &config.webhooks {
+        Some(webhooks) => Some(init_webhooks(&config.database.url, webhooks)
+            .await
+            .expect("Failed to initialize webhooks")),
+        None => None,
+    };
+    if let Some(webhooks) = &webhooks {
+        storage_service = storage_service.with_webhooks(webhooks.clone());
+    }
+    
+    // Keep the watcher alive for the lifetime of the server
+    // Files they ingest are announced on the main service's event channel
+    let _watcher = init_watcher(&config, storage.clone(), webhooks.clone(), storage_service.event_sender())
+        .await
+        .expect("Failed to start storage watcher");
+    let _sftp_ingestor = init_sftp_ingestor(&config, storage, webhooks.clone(), storage_service.event_sender())
+        .await
+        .expect("Failed to start SFTP ingestion");
+    
+    // Connect database instance for announcements
+    let db_for_announcements = Database::connect(&config.database.url)
+        .await
+        .expect("Failed to connect to database for announcements");
+    let announcement_service = AnnouncementService::new(db_for_announcements);
+    
+    // Connect database instance for comments
+    let db_for_comments = Database::connect(&config.database.url)
+        .await
+        .expect("Failed to connect to database for comments");
+    let comment_service = CommentService::new(db_for_comments);
+    
+    // Connect database instance for the activity feed
+    let db_for_activity = Database::connect(&config.database.url)
+        .await
+        .expect("Failed to connect to database for activity feed");
+    let mut activity_service = ActivityService::new(db_for_activity);
+    if let Some(redis_url) = &config.cluster.redis_url {
+        let fanout = init_fanout(redis_url, &config.cluster)
+            .await
+            .expect("Failed to connect to Redis");
+        activity_service = activity_service.with_fanout(fanout);
+    }
     
     // Create app state
-    let state = Arc::new(AppState::new(db, auth_service, storage_service));
+    let mut app_state = AppState::new(
+        db,
+        auth_service,
+        storage_service,
+        announcement_service,
+        comment_service,
+        activity_service,
+    );
+    if let Some(billing_service) = billing_service {
+        app_state = app_state.with_billing(billing_service);
+    }
+    if let Some(user_buckets) = user_buckets {
+        app_state = app_state.with_user_buckets(user_buckets);
+    }
+    if let Some(webhooks) = webhooks {
+        app_state = app_state.with_webhooks(webhooks);
+    }
+    if let Some(url_import) = &config.storage.url_import {
+        let url_importer = init_url_importer(url_import).expect("Failed to set up URL imports");
+        app_state = app_state.with_url_importer(url_importer);
+    }
+    if let Some(streaming) = &config.streaming {
+        let event_stream = init_event_stream(streaming)
+            .await
+            .expect("Failed to connect to message queues");
+        // Storage changes from every service, the watcher's and SFTP's included, arrive on this channel
+        event_stream.relay_storage_events(app_state.storage_service.subscribe());
+        app_state = app_state.with_event_stream(event_stream);
+    }
+    app_state = app_state
+        .with_metrics_token(config.server.metrics_token.clone())
+        .with_features(config.features.clone())
+        .with_sql_console(config.sql_console.clone())
+        .with_signup(config.auth.signup.clone())
+        .with_trusted_proxy_hops(config.server.forwarded_for_hops())
+        .with_download_limits(&config.storage.download_limits)
+        .with_login_limits(&config.auth.login_rate_limit)
+        .with_jobs(&config.jobs)
+        .with_deployment(Deployment {
+            database: config.database.url.split(':').next().unwrap_or_default().to_string(),
+            storage_backend: config.storage.backend,
+            shared_local_storage: config.cluster.shared_local_storage,
+            drop_directory_watcher: config.storage.watch.enabled,
+            sftp_ingestion: config.storage.sftp.is_some(),
+            blob_cache: config.storage.cache.enabled,
+        });
+    let server_managed_columns = ServerManagedColumns::from_config(&config.db.server_managed_columns)
+        .expect("Invalid [db] server_managed_columns");
+    if !server_managed_columns.is_empty() {
+        app_state = app_state.with_server_managed_columns(server_managed_columns);
+    }
+    let read_policies = ReadPolicies::from_config(&config.db).expect("Invalid [db] read settings");
+    app_state = app_state.with_read_policies(read_policies);
+    let encrypted_columns = EncryptedColumns::from_config(&config.db.encryption).expect("Invalid [db.encryption]");
+    if !encrypted_columns.is_empty() {
+        app_state = app_state.with_encrypted_columns(encrypted_columns);
+    }
+    let row_policies = RowPolicies::from_config(&config.db).expect("Invalid [db.policies]");
+    if !row_policies.is_empty() {
+        app_state = app_state.with_row_policies(row_policies);
+    }
+    if let Some(mail) = &config.mail {
+        let mailer = init_mailer(mail).expect("Failed to initialize mailer");
+        app_state = app_state.with_mailer(mailer);
+    }
+    let deprecations = Deprecations::from_config(&config.deprecations)
+        .expect("Invalid [[deprecations]] configuration");
+    if !deprecations.is_empty() {
+        println!("🌅 {} deprecated route(s) or field(s)", deprecations.len());
+        app_state = app_state.with_deprecations(deprecations);
+    }
+    let state = Arc::new(app_state);
+    
+    schedule_signing_key_reload(state.clone());
+    schedule_upload_journal_recovery(state.clone(), config.storage.pending_upload_grace_seconds);
+    if config.storage.integrity_audit.enabled {
+        schedule_integrity_audit(state.clone(), &config.storage.integrity_audit);
+    }
+    if config.storage.text_extraction.enabled {
+        schedule_text_extraction(state.clone(), &config.storage.text_extraction);
+    }
+    if config.storage.trash_retention.enabled {
+        schedule_trash_purge(state.clone(), &config.storage.trash_retention);
+    }
+    if config.storage.two_phase_uploads {
+        schedule_pending_upload_gc(state.clone(), config.storage.pending_upload_grace_seconds);
+    }
+    
+    schedule_usage_report_aggregation(state.clone());
+    schedule_deleted_account_purge(state.clone());
+    if let Some(user_buckets) = state.user_buckets.clone() {
+        schedule_bucket_health_checks(state.clone(), user_buckets);
+    }
+    if let Some(webhooks) = state.webhooks.clone() {
+        schedule_webhook_delivery(state.clone(), webhooks);
+    }
+    for name in state.jobs.unknown_overrides() {
+        eprintln!("⚠️  [jobs.types.{}] matches no job running on this instance", name);
+    }
+    state.jobs.start();
     
     // Create router with state
     let app = router::router(state);
@@ -87,5 +381,781 @@ async fn main() {
     println!("🚀 Running on http://{}", addr);
     println!();
     
-    axum::serve(listener, app).await.unwrap();
-}
\ No newline at end of file
+    // Client addresses are needed to locate logins
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .await
+        .unwrap();
+}
+
+/// Add a JWT signing key and explain how the old one is phased out
+async fn rotate_secret(config: &AppConfig, window_seconds: i64) {
+    let db = Database::connect(&config.database.url)
+        .await
+        .expect("Failed to connect to database");
+    let mut auth_service = AuthService::new(db, config.auth.jwt_secret.clone(), config.auth.token_expiry_seconds);
+    if let Some(key) = &config.auth.signing_key_encryption_key {
+        auth_service = auth_service.with_signing_key_encryption_key(key);
+    }
+
+    if window_seconds < config.auth.token_expiry_seconds {
+        eprintln!(
+            "⚠️  The {}s window is shorter than token_expiry_seconds ({}s): sessions signed by the old key may end early",
+            window_seconds, config.auth.token_expiry_seconds
+        );
+    }
+
+    let rotation = auth_service
+        .rotate_signing_key(
+            chrono::Duration::seconds(auth::KEY_ACTIVATION_DELAY_SECONDS),
+            chrono::Duration::seconds(window_seconds),
+        )
+        .await
+        .expect("Failed to rotate signing key");
+
+    println!("🔑 Added signing key {}; it signs new tokens from {}", rotation.key.kid, rotation.key.activates_at.to_rfc3339());
+    for key in &rotation.retiring {
+        let source = if key.configured { " (jwt_secret)" } else { "" };
+        println!("⏳ Key {}{} retires at {}", key.kid, source, key.retires_at.map(|t| t.to_rfc3339()).unwrap_or_default());
+    }
+    println!();
+    println!("Running servers load the new key within {} seconds; nothing needs restarting.", auth::KEY_RELOAD_INTERVAL_SECONDS);
+    println!("Until the old key retires, a request with a token it signed gets a re-signed token in the");
+    println!("X-Renewed-Token response header. Watch projectkit_auth_token_validations_total{{key=\"retiring\"}}");
+    println!("on /metrics to see how much traffic still uses the old key; after it retires, its tokens are");
+    println!("rejected and it is deleted. A retired jwt_secret stays rejected, so it can be left in place.");
+}
+
+/// Copy every blob to another backend and point the files table at it
+/// The destination is configured like the current backend, from the same `[storage]` section
+async fn migrate_storage(config: &AppConfig, to: StorageBackendKind, path: Option<String>) -> bool {
+    let mut destination_config = config.storage.clone();
+    destination_config.backend = to;
+    if let Some(path) = path {
+        destination_config.path = path;
+    }
+
+    let source = init_storage(&config.storage)
+        .await
+        .expect("Failed to initialize source storage");
+    let destination = init_storage(&destination_config)
+        .await
+        .expect("Failed to initialize destination storage");
+    let db = Database::connect(&config.database.url)
+        .await
+        .expect("Failed to connect to database");
+
+    println!("🚚 Migrating blobs from {} to {}", source.location(), destination.location());
+    let migrator = StorageMigrator::new(source, destination, db).with_progress(|progress| {
+        if progress.done() % 100 == 0 || progress.done() == progress.total {
+            println!("  {}/{} blobs ({} copied, {} already there, {} failed, {} bytes)",
+                progress.done(), progress.total, progress.copied, progress.skipped, progress.failed, progress.bytes);
+        }
+    });
+    let report = migrator.run().await.expect("Failed to migrate storage");
+
+    if !report.failures.is_empty() {
+        for failure in &report.failures {
+            eprintln!("  ❌ {}: {}", failure.key, failure.error);
+        }
+        eprintln!("⚠️  {} blob(s) could not be migrated; files still point at the old storage. Run the migration again to retry them.", report.failures.len());
+        return false;
+    }
+
+    println!("✅ Every blob is in the new storage; {} file(s) now point at it", report.switched_files);
+    println!("Set `backend` under [storage] in projectkit.toml to match and restart the server.");
+    println!("The old blobs are left in place and can be removed once the server runs on the new storage.");
+    true
+}
+
+/// Queue a run of `job` every `period`, starting now
+/// A run still queued when the next one is due absorbs it, so slow jobs don't pile up.
+fn enqueue_every(jobs: Arc<JobScheduler>, job: &'static str, period: std::time::Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = jobs.enqueue(job) {
+                eprintln!("⚠️  Failed to queue {}: {}", job, e);
+            }
+        }
+    });
+}
+
+/// Pick up signing keys added by rotations on other instances, and delete retired ones
+fn schedule_signing_key_reload(state: Arc<AppState>) {
+    let jobs = state.jobs.clone();
+    // Instances must pick up new keys before tokens signed with them arrive, however busy the pool is
+    let limits = JobLimits::new(100, std::time::Duration::from_secs(60)).with_reserved_slot();
+    jobs.register("signing_key_reload", limits, move || {
+        let state = state.clone();
+        async move {
+            let result = async {
+                state.auth_service.prune_signing_keys().await?;
+                state.auth_service.reload_signing_keys().await
+            }.await;
+            result.map_err(|e| {
+                eprintln!("⚠️  Failed to reload signing keys: {}", e);
+                e.to_string()
+            })
+        }
+    });
+    enqueue_every(jobs, "signing_key_reload", std::time::Duration::from_secs(auth::KEY_RELOAD_INTERVAL_SECONDS as u64));
+}
+
+/// Settle uploads cut off between writing the blob and recording its metadata
+/// The first pass runs at startup, and picks up whatever a crash of the previous run left behind
+fn schedule_upload_journal_recovery(state: Arc<AppState>, grace_seconds: u64) {
+    let grace = std::time::Duration::from_secs(grace_seconds.max(1));
+    let jobs = state.jobs.clone();
+    jobs.register("upload_journal_recovery", JobLimits::new(50, std::time::Duration::from_secs(600)), move || {
+        let state = state.clone();
+        async move {
+            match state.storage_service.recover_upload_journal(grace).await {
+                Ok(recovery) => {
+                    state.metrics.set_job_backlog("upload_journal_recovery", recovery.unresolved);
+                    if recovery.completed + recovery.rolled_back > 0 {
+                        println!(
+                            "🧾 Upload journal: {} completed, {} rolled back",
+                            recovery.completed, recovery.rolled_back
+                        );
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Upload journal recovery failed: {}", e);
+                    Err(e.to_string())
+                }
+            }
+        }
+    });
+    enqueue_every(jobs, "upload_journal_recovery", grace);
+}
+
+/// Re-hash stored files on a schedule and flag those that no longer match their checksum
+fn schedule_integrity_audit(state: Arc<AppState>, config: &IntegrityAuditConfig) {
+    let period = std::time::Duration::from_secs(config.interval_seconds.max(1));
+    let sample = config.sample_size;
+    let jobs = state.jobs.clone();
+    jobs.register("integrity_audit", JobLimits::new(-10, std::time::Duration::from_secs(6 * 3600)), move || {
+        let state = state.clone();
+        async move {
+            match state.storage_service.audit_integrity(sample).await {
+                Ok(audit) => {
+                    let damaged = (audit.corrupted.len() + audit.missing.len()) as u64;
+                    state.metrics.set_job_backlog("integrity_audit", damaged);
+                    if damaged > 0 {
+                        eprintln!(
+                            "⚠️  Integrity audit: {} corrupted and {} missing of {} file(s) checked",
+                            audit.corrupted.len(), audit.missing.len(), audit.checked
+                        );
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Integrity audit failed: {}", e);
+                    Err(e.to_string())
+                }
+            }
+        }
+    });
+    enqueue_every(jobs, "integrity_audit", period);
+}
+
+/// Extract the text of new and changed documents, a batch at a time, so search matches their content
+fn schedule_text_extraction(state: Arc<AppState>, config: &TextExtractionConfig) {
+    let period = std::time::Duration::from_secs(config.interval_seconds.max(1));
+    let (batch, max_file_size) = (config.batch_size.max(1), config.max_file_bytes);
+    let jobs = state.jobs.clone();
+    jobs.register("text_extraction", JobLimits::new(-10, std::time::Duration::from_secs(1800)), move || {
+        let state = state.clone();
+        async move {
+            match state.storage_service.extract_texts(batch, max_file_size).await {
+                Ok(extraction) => {
+                    state.metrics.set_job_backlog("text_extraction", extraction.errors);
+                    if extraction.errors > 0 {
+                        eprintln!(
+                            "⚠️  Text extraction: {} file(s) could not be read; {} extracted",
+                            extraction.errors, extraction.extracted
+                        );
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Text extraction failed: {}", e);
+                    Err(e.to_string())
+                }
+            }
+        }
+    });
+    enqueue_every(jobs, "text_extraction", period);
+}
+
+/// Periodically purge files that have been in the trash for longer than the retention window
+fn schedule_trash_purge(state: Arc<AppState>, config: &TrashRetentionConfig) {
+    let period = std::time::Duration::from_secs(config.interval_seconds.max(1));
+    let retention = chrono::Duration::days(config.retention_days.min(i32::MAX as u64) as i64);
+    let batch = config.batch_size.max(1);
+    let jobs = state.jobs.clone();
+    jobs.register("trash_purge", JobLimits::new(0, std::time::Duration::from_secs(1800)), move || {
+        let state = state.clone();
+        async move {
+            match state.storage_service.purge_expired_trash(retention, batch).await {
+                Ok(purge) => {
+                    state.metrics.set_job_backlog("trash_purge", purge.errors);
+                    if purge.purged > 0 {
+                        println!("🧹 Purged {} file(s) from the trash", purge.purged);
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Trash purge failed: {}", e);
+                    Err(e.to_string())
+                }
+            }
+        }
+    });
+    enqueue_every(jobs, "trash_purge", period);
+}
+
+/// Periodically commit or remove pending blobs left behind by interrupted two-phase uploads
+fn schedule_pending_upload_gc(state: Arc<AppState>, grace_seconds: u64) {
+    let grace = std::time::Duration::from_secs(grace_seconds.max(1));
+    let jobs = state.jobs.clone();
+    jobs.register("pending_upload_gc", JobLimits::new(0, std::time::Duration::from_secs(600)), move || {
+        let state = state.clone();
+        async move {
+            match state.storage_service.collect_pending_uploads(grace).await {
+                Ok(stats) => {
+                    state.metrics.set_job_backlog("pending_upload_gc", stats.committed + stats.removed);
+                    if stats.committed + stats.removed > 0 {
+                        println!("🧹 Pending uploads: {} committed, {} removed", stats.committed, stats.removed);
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Pending upload GC failed: {}", e);
+                    Err(e.to_string())
+                }
+            }
+        }
+    });
+    enqueue_every(jobs, "pending_upload_gc", grace);
+}
+
+/// Refresh the daily and monthly storage usage reports every hour
+fn schedule_usage_report_aggregation(state: Arc<AppState>) {
+    let jobs = state.jobs.clone();
+    jobs.register("usage_report_aggregation", JobLimits::new(-10, std::time::Duration::from_secs(1800)), move || {
+        let state = state.clone();
+        async move {
+            state.storage_service.refresh_usage_reports().await.map(|_| ()).map_err(|e| {
+                eprintln!("⚠️  Usage report aggregation failed: {}", e);
+                e.to_string()
+            })
+        }
+    });
+    enqueue_every(jobs, "usage_report_aggregation", std::time::Duration::from_secs(3600));
+}
+
+/// Purge deleted accounts whose restore window is over, every hour
+fn schedule_deleted_account_purge(state: Arc<AppState>) {
+    let jobs = state.jobs.clone();
+    jobs.register("deleted_account_purge", JobLimits::new(0, std::time::Duration::from_secs(3600)), move || {
+        let state = state.clone();
+        async move {
+            match purge_deleted_accounts(&state).await {
+                Ok(purged) => {
+                    state.metrics.set_job_backlog("deleted_account_purge", purged);
+                    if purged > 0 {
+                        println!("🧹 Purged {} deleted account(s)", purged);
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Deleted account purge failed: {}", e);
+                    Err(e)
+                }
+            }
+        }
+    });
+    enqueue_every(jobs, "deleted_account_purge", std::time::Duration::from_secs(3600));
+}
+
+/// Health-check every user-registered bucket every 15 minutes
+fn schedule_bucket_health_checks(state: Arc<AppState>, user_buckets: Arc<UserBuckets>) {
+    let jobs = state.jobs.clone();
+    jobs.register("bucket_health_check", JobLimits::new(0, std::time::Duration::from_secs(300)), move || {
+        let state = state.clone();
+        let user_buckets = user_buckets.clone();
+        async move {
+            match user_buckets.check_all().await {
+                Ok(unhealthy) => {
+                    state.metrics.set_job_backlog("bucket_health_check", unhealthy.len() as u64);
+                    for bucket in unhealthy {
+                        eprintln!(
+                            "⚠️  Bucket {} of user {} is unhealthy: {}",
+                            bucket.bucket,
+                            bucket.user_id,
+                            bucket.last_error.unwrap_or_default()
+                        );
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Bucket health check failed: {}", e);
+                    Err(e.to_string())
+                }
+            }
+        }
+    });
+    enqueue_every(jobs, "bucket_health_check", std::time::Duration::from_secs(900));
+}
+
+/// Retry webhook deliveries whose backoff has passed
+/// New deliveries are sent straight away; this picks up the ones that failed
+fn schedule_webhook_delivery(state: Arc<AppState>, webhooks: Arc<Webhooks>) {
+    let jobs = state.jobs.clone();
+    jobs.register("webhook_delivery", JobLimits::new(50, std::time::Duration::from_secs(300)), move || {
+        let state = state.clone();
+        let webhooks = webhooks.clone();
+        async move {
+            let result = webhooks.deliver_due().await;
+            if let Ok(pending) = webhooks.pending_count().await {
+                state.metrics.set_job_backlog("webhook_delivery", pending);
+            }
+            result.map(|_| ()).map_err(|e| {
+                eprintln!("⚠️  Webhook delivery failed: {}", e);
+                e.to_string()
+            })
+        }
+    });
+    enqueue_every(jobs, "webhook_delivery", std::time::Duration::from_secs(15));
+}
+
+/// Permanently erase each expired account's files, then the account and its rows
+/// Accounts owning anything under a legal hold are kept until the hold is released
+async fn purge_deleted_accounts(state: &AppState) -> Result<u64, String> {
+    let user_ids = state.auth_service.users_to_purge().await.map_err(|e| e.to_string())?;
+
+    let mut purged = 0;
+    for &user_id in &user_ids {
+        if state.storage_service.user_has_legal_holds(user_id).await.map_err(|e| e.to_string())? {
+            continue;
+        }
+        // Blobs aren't removed by the cascade, so purge them while the metadata still exists
+        state.storage_service.purge_user_files(user_id).await.map_err(|e| e.to_string())?;
+        state.auth_service.delete_user(user_id).await.map_err(|e| e.to_string())?;
+        purged += 1;
+    }
+
+    Ok(purged)
+}
+
+/// Build the billing service from `[billing]`
+async fn init_billing(database_url: &str, config: &BillingConfig) -> BillingService {
+    // Connect a dedicated database instance for billing
+    let db_for_billing = Database::connect(database_url)
+        .await
+        .expect("Failed to connect to database for billing");
+
+    let plans = config.plans.iter().map(|plan| {
+        let limits = storage::StorageLimits {
+            max_file_size: plan.max_file_size_bytes,
+            max_total_size: plan.max_storage_bytes,
+            max_files: plan.max_files,
+        };
+        let api = ApiLimits {
+            requests_per_minute: plan.requests_per_minute,
+            requests_per_month: plan.requests_per_month,
+        };
+        let plan_with_limits = Plan::new(&plan.name, limits).with_api_limits(api);
+        match &plan.stripe_price_id {
+            Some(price_id) => plan_with_limits.with_stripe_price(price_id),
+            None => plan_with_limits,
+        }
+    }).collect();
+
+    let billing = BillingService::new(
+        db_for_billing,
+        plans,
+        config.default_plan.clone(),
+        config.stripe_webhook_secret.clone(),
+    ).expect("Invalid [billing] configuration");
+
+    println!("💳 Billing enabled with {} plans", config.plans.len());
+    billing
+}
+
+/// Load the MaxMind database that `[geoip]` points at
+#[cfg(feature = "geoip")]
+fn init_geoip(config: &GeoIpConfig) -> auth::Result<Arc<dyn GeoLookup>> {
+    let lookup = auth::MaxMindLookup::open(&config.database_path)?;
+    println!("🌍 Locating logins with {}", config.database_path);
+    Ok(Arc::new(lookup))
+}
+
+#[cfg(not(feature = "geoip"))]
+fn init_geoip(_config: &GeoIpConfig) -> auth::Result<Arc<dyn GeoLookup>> {
+    Err(auth::AuthError::GeoIpError(
+        "[geoip] is configured but the server was built without the `geoip` feature".to_string(),
+    ))
+}
+
+/// Send email through the SMTP relay that `[mail]` names, or log it when there is none
+#[cfg(feature = "smtp")]
+fn init_mailer(config: &MailConfig) -> auth::Result<Arc<dyn Mailer>> {
+    let Some(host) = &config.smtp_host else {
+        return Ok(Arc::new(auth::LogMailer));
+    };
+    let credentials = config.smtp_username.clone().zip(config.smtp_password.clone());
+    let mailer = auth::SmtpMailer::new(host, config.smtp_port, credentials, &config.from)?;
+    println!("📧 Sending email through {}:{} as {}", host, config.smtp_port, config.from);
+    Ok(Arc::new(mailer))
+}
+
+#[cfg(not(feature = "smtp"))]
+fn init_mailer(config: &MailConfig) -> auth::Result<Arc<dyn Mailer>> {
+    if config.smtp_host.is_none() {
+        return Ok(Arc::new(auth::LogMailer));
+    }
+    Err(auth::AuthError::MailError(
+        "[mail] smtp_host is configured but the server was built without the `smtp` feature".to_string(),
+    ))
+}
+
+/// Fan realtime activity events out through Redis, so clients of every instance receive them
+#[cfg(feature = "redis")]
+async fn init_fanout(redis_url: &str, config: &ClusterConfig) -> activity::Result<Arc<dyn EventFanout>> {
+    let fanout = activity::RedisFanout::connect(redis_url, &config.redis_channel).await?;
+    println!("📣 Fanning out activity events through Redis channel {}", config.redis_channel);
+    Ok(Arc::new(fanout))
+}
+
+#[cfg(not(feature = "redis"))]
+async fn init_fanout(_redis_url: &str, _config: &ClusterConfig) -> activity::Result<Arc<dyn EventFanout>> {
+    Err(activity::ActivityError::FanoutError(
+        "cluster.redis_url is set but the server was built without the `redis` feature".to_string(),
+    ))
+}
+
+/// Connect the message queue publishers under `[streaming]`
+async fn init_event_stream(config: &StreamingConfig) -> streaming::Result<Arc<EventStream>> {
+    let kinds = config.events.iter()
+        .map(|event| streaming::ChangeKind::from_str(event).ok_or_else(|| {
+            streaming::StreamingError::InvalidConfig(format!("unknown streaming event {:?}", event))
+        }))
+        .collect::<streaming::Result<Vec<_>>>()?;
+
+    let mut publishers = Vec::new();
+    for publisher in &config.publishers {
+        publishers.push(match publisher.kind {
+            StreamPublisherKind::Nats => init_nats_publisher(publisher).await?,
+            StreamPublisherKind::Kafka => init_kafka_publisher(publisher)?,
+            StreamPublisherKind::Rabbitmq => init_rabbitmq_publisher(publisher).await?,
+        });
+    }
+
+    let event_stream = EventStream::new(publishers)
+        .with_subject_prefix(&config.subject_prefix)
+        .with_kinds(kinds);
+    println!("📨 Streaming change events to {}", event_stream.publisher_names().join(", "));
+    Ok(Arc::new(event_stream))
+}
+
+#[cfg(feature = "nats")]
+async fn init_nats_publisher(config: &StreamPublisherConfig) -> streaming::Result<Arc<dyn Publisher>> {
+    Ok(Arc::new(streaming::NatsPublisher::connect(&config.url).await?))
+}
+
+#[cfg(not(feature = "nats"))]
+async fn init_nats_publisher(_config: &StreamPublisherConfig) -> streaming::Result<Arc<dyn Publisher>> {
+    Err(streaming::StreamingError::InvalidConfig(
+        "a nats publisher is configured but the server was built without the `nats` feature".to_string(),
+    ))
+}
+
+#[cfg(feature = "kafka")]
+fn init_kafka_publisher(config: &StreamPublisherConfig) -> streaming::Result<Arc<dyn Publisher>> {
+    Ok(Arc::new(streaming::KafkaPublisher::connect(&config.url)?))
+}
+
+#[cfg(not(feature = "kafka"))]
+fn init_kafka_publisher(_config: &StreamPublisherConfig) -> streaming::Result<Arc<dyn Publisher>> {
+    Err(streaming::StreamingError::InvalidConfig(
+        "a kafka publisher is configured but the server was built without the `kafka` feature".to_string(),
+    ))
+}
+
+#[cfg(feature = "rabbitmq")]
+async fn init_rabbitmq_publisher(config: &StreamPublisherConfig) -> streaming::Result<Arc<dyn Publisher>> {
+    Ok(Arc::new(streaming::RabbitMqPublisher::connect(&config.url, &config.exchange).await?))
+}
+
+#[cfg(not(feature = "rabbitmq"))]
+async fn init_rabbitmq_publisher(_config: &StreamPublisherConfig) -> streaming::Result<Arc<dyn Publisher>> {
+    Err(streaming::StreamingError::InvalidConfig(
+        "a rabbitmq publisher is configured but the server was built without the `rabbitmq` feature".to_string(),
+    ))
+}
+
+/// Set up delivery of webhooks to the endpoints under `[webhooks]`
+#[cfg(feature = "webhooks")]
+async fn init_webhooks(database_url: &str, config: &WebhooksConfig) -> storage::Result<Arc<Webhooks>> {
+    let mut endpoints = Vec::new();
+    for endpoint in &config.endpoints {
+        let events = endpoint.events.iter()
+            .map(|event| storage::FileEvent::from_str(event).ok_or_else(|| {
+                storage::StorageError::InvalidInput(format!("unknown webhook event {:?} for {}", event, endpoint.url))
+            }))
+            .collect::<storage::Result<Vec<_>>>()?;
+        endpoints.push(storage::WebhookEndpoint {
+            url: endpoint.url.clone(),
+            secret: endpoint.secret.clone(),
+            events,
+        });
+    }
+
+    let transport = storage::HttpTransport::new(std::time::Duration::from_secs(config.timeout_seconds))?;
+    let db_for_webhooks = Database::connect(database_url)
+        .await
+        .map_err(|e| storage::StorageError::StorageError(format!("Database error: {}", e)))?;
+
+    println!("🪝 Sending webhooks to {} endpoint(s)", endpoints.len());
+    Ok(Arc::new(
+        Webhooks::new(db_for_webhooks, endpoints, Arc::new(transport))
+            .with_retries(config.max_attempts, std::time::Duration::from_secs(config.retry_base_seconds)),
+    ))
+}
+
+#[cfg(not(feature = "webhooks"))]
+async fn init_webhooks(_database_url: &str, _config: &WebhooksConfig) -> storage::Result<Arc<Webhooks>> {
+    Err(storage::StorageError::StorageError(
+        "[webhooks] is configured but the server was built without the `webhooks` feature".to_string(),
+    ))
+}
+
+/// Set up `POST /files/import` with the limits under `[storage.url_import]`
+#[cfg(feature = "import")]
+fn init_url_importer(config: &UrlImportConfig) -> storage::Result<Arc<storage::UrlImporter>> {
+    let fetcher = storage::HttpFetcher::new(std::time::Duration::from_secs(config.timeout_seconds), config.max_redirects);
+    println!("🌐 Importing files from URLs of up to {} bytes", config.max_size_bytes);
+    Ok(Arc::new(
+        storage::UrlImporter::new(Arc::new(fetcher), config.max_size_bytes)
+            .with_allowed_mime_types(config.allowed_mime_types.clone()),
+    ))
+}
+
+#[cfg(not(feature = "import"))]
+fn init_url_importer(_config: &UrlImportConfig) -> storage::Result<Arc<storage::UrlImporter>> {
+    Err(storage::StorageError::StorageError(
+        "[storage.url_import] is configured but the server was built without the `import` feature".to_string(),
+    ))
+}
+
+/// Build the storage service for the configured backend
+async fn init_storage(config: &StorageConfig) -> storage::Result<StorageService> {
+    let mut storage = match config.backend {
+        StorageBackendKind::Local => StorageService::new(&config.path).await,
+        StorageBackendKind::S3 => init_s3_storage(config),
+        StorageBackendKind::Gcs => init_gcs_storage(config).await,
+    }?;
+    if config.cache.enabled {
+        storage = storage.with_cache(storage::CachePolicy {
+            max_bytes: config.cache.max_bytes,
+            max_entry_bytes: config.cache.max_file_bytes,
+            ttl: std::time::Duration::from_secs(config.cache.ttl_seconds),
+        });
+    }
+
+    let id_strategy = match config.file_ids {
+        FileIdKind::UuidV4 => FileIdStrategy::UuidV4,
+        FileIdKind::UuidV7 => FileIdStrategy::UuidV7,
+        FileIdKind::Ulid => FileIdStrategy::Ulid,
+        FileIdKind::Nanoid => FileIdStrategy::nanoid(config.nanoid_length)?,
+    };
+    Ok(storage.with_id_strategy(id_strategy))
+}
+
+/// Start the drop-directory watcher when `[storage.watch]` is enabled
+#[cfg(feature = "watch")]
+async fn init_watcher(config: &AppConfig, storage: StorageService, webhooks: Option<Arc<Webhooks>>, events: broadcast::Sender<StorageEvent>) -> storage::Result<Option<storage::StorageWatcher>> {
+    let watch = &config.storage.watch;
+    if !watch.enabled {
+        return Ok(None);
+    }
+
+    let owner_user_id = watch.owner_user_id.ok_or_else(|| {
+        storage::StorageError::StorageError("[storage.watch] is enabled but owner_user_id is missing".to_string())
+    })?;
+    let path = watch.path.clone().unwrap_or_else(|| config.storage.path.clone());
+
+    // Connect a dedicated database instance for the watcher
+    let db_for_watcher = Database::connect(&config.database.url)
+        .await
+        .map_err(|e| storage::StorageError::StorageError(format!("Database error: {}", e)))?;
+
+    let mut service = TransactionalStorageService::new(storage, db_for_watcher)
+        .with_tenant_isolation(config.storage.tenant_isolation)
+        .with_two_phase_uploads(config.storage.two_phase_uploads)
+        .with_event_sender(events);
+    if let Some(webhooks) = webhooks {
+        service = service.with_webhooks(webhooks);
+    }
+
+    let watcher = storage::StorageWatcher::start(Arc::new(service), storage::WatchConfig {
+        path: path.clone().into(),
+        owner_user_id,
+        settle_delay: std::time::Duration::from_millis(watch.settle_ms),
+    }).await?;
+
+    println!("👀 Watching {} for new files", path);
+    Ok(Some(watcher))
+}
+
+#[cfg(not(feature = "watch"))]
+async fn init_watcher(config: &AppConfig, _storage: StorageService, _webhooks: Option<Arc<Webhooks>>, _events: broadcast::Sender<StorageEvent>) -> storage::Result<Option<()>> {
+    if config.storage.watch.enabled {
+        return Err(storage::StorageError::StorageError(
+            "[storage.watch] is enabled but the server was built without the `watch` feature".to_string(),
+        ));
+    }
+    Ok(None)
+}
+
+/// Start polling the partner SFTP directory when `[storage.sftp]` is configured
+#[cfg(feature = "sftp")]
+async fn init_sftp_ingestor(config: &AppConfig, storage: StorageService, webhooks: Option<Arc<Webhooks>>, events: broadcast::Sender<StorageEvent>) -> storage::Result<Option<storage::SftpIngestor>> {
+    let Some(sftp) = &config.storage.sftp else {
+        return Ok(None);
+    };
+
+    let auth = match (&sftp.private_key_path, &sftp.password) {
+        (Some(path), _) => storage::SftpAuth::PrivateKey {
+            path: path.into(),
+            passphrase: sftp.private_key_passphrase.clone(),
+        },
+        (None, Some(password)) => storage::SftpAuth::Password(password.clone()),
+        (None, None) => {
+            return Err(storage::StorageError::StorageError(
+                "[storage.sftp] needs either private_key_path or password".to_string(),
+            ));
+        }
+    };
+
+    // Without a pinned host key, anyone who can intercept the connection would get the credentials
+    let Some(host_fingerprint) = sftp.host_fingerprint.clone() else {
+        return Err(storage::StorageError::StorageError(
+            "[storage.sftp] needs host_fingerprint, the hex SHA-256 of the server's host key".to_string(),
+        ));
+    };
+
+    // Connect a dedicated database instance for SFTP ingestion
+    let db_for_sftp = Database::connect(&config.database.url)
+        .await
+        .map_err(|e| storage::StorageError::StorageError(format!("Database error: {}", e)))?;
+
+    let mut service = TransactionalStorageService::new(storage, db_for_sftp)
+        .with_tenant_isolation(config.storage.tenant_isolation)
+        .with_two_phase_uploads(config.storage.two_phase_uploads)
+        .with_event_sender(events);
+    if let Some(webhooks) = webhooks {
+        service = service.with_webhooks(webhooks);
+    }
+
+    let ingestor = storage::SftpIngestor::start(Arc::new(service), storage::SftpSourceConfig {
+        host: sftp.host.clone(),
+        port: sftp.port,
+        username: sftp.username.clone(),
+        auth,
+        host_fingerprint,
+        remote_dir: sftp.remote_dir.clone(),
+        processed_dir: sftp.processed_dir.clone(),
+        owner_user_id: sftp.owner_user_id,
+        poll_interval: std::time::Duration::from_secs(sftp.poll_seconds),
+    });
+
+    println!("📡 Polling sftp://{}:{}{} every {}s", sftp.host, sftp.port, sftp.remote_dir, sftp.poll_seconds);
+    Ok(Some(ingestor))
+}
+
+#[cfg(not(feature = "sftp"))]
+async fn init_sftp_ingestor(config: &AppConfig, _storage: StorageService, _webhooks: Option<Arc<Webhooks>>, _events: broadcast::Sender<StorageEvent>) -> storage::Result<Option<()>> {
+    if config.storage.sftp.is_some() {
+        return Err(storage::StorageError::StorageError(
+            "[storage.sftp] is configured but the server was built without the `sftp` feature".to_string(),
+        ));
+    }
+    Ok(None)
+}
+
+/// Upload validation rules from `[storage.upload_policy]`
+fn upload_policy(config: &UploadPolicyConfig) -> storage::UploadPolicy {
+    storage::UploadPolicy {
+        max_size: config.max_size_bytes,
+        allowed_extensions: config.allowed_extensions.clone(),
+        allowed_mime_types: config.allowed_mime_types.clone(),
+        blocked_mime_types: config.blocked_mime_types.clone(),
+    }
+}
+
+/// Retry policy applied to remote storage backends
+#[cfg(any(feature = "s3", feature = "gcs"))]
+fn retry_policy(config: &StorageConfig) -> storage::RetryPolicy {
+    let retry = &config.retry;
+    storage::RetryPolicy {
+        max_attempts: retry.max_attempts.max(1),
+        base_delay: std::time::Duration::from_millis(retry.base_delay_ms),
+        max_delay: std::time::Duration::from_millis(retry.max_delay_ms),
+        failure_threshold: retry.circuit_failure_threshold.max(1),
+        reset_timeout: std::time::Duration::from_secs(retry.circuit_reset_seconds),
+    }
+}
+
+#[cfg(feature = "s3")]
+fn init_s3_storage(config: &StorageConfig) -> storage::Result<StorageService> {
+    let s3 = config.s3.clone().ok_or_else(|| {
+        storage::StorageError::StorageError("storage.backend is \"s3\" but [storage.s3] is missing".to_string())
+    })?;
+
+    let backend = storage::S3Backend::new(storage::S3BackendConfig {
+        bucket: s3.bucket,
+        region: s3.region,
+        endpoint: s3.endpoint,
+        access_key: s3.access_key,
+        secret_key: s3.secret_key,
+        path_style: s3.path_style,
+        prefix: s3.prefix,
+    })?;
+
+    Ok(StorageService::with_backend(storage::RetryingBackend::new(backend, retry_policy(config))))
+}
+
+#[cfg(not(feature = "s3"))]
+fn init_s3_storage(_config: &StorageConfig) -> storage::Result<StorageService> {
+    Err(storage::StorageError::StorageError(
+        "storage.backend is \"s3\" but the server was built without the `s3` feature".to_string(),
+    ))
+}
+
+#[cfg(feature = "gcs")]
+async fn init_gcs_storage(config: &StorageConfig) -> storage::Result<StorageService> {
+    let gcs = config.gcs.clone().ok_or_else(|| {
+        storage::StorageError::StorageError("storage.backend is \"gcs\" but [storage.gcs] is missing".to_string())
+    })?;
+
+    let backend = storage::GcsBackend::new(storage::GcsBackendConfig {
+        bucket: gcs.bucket,
+        credentials_path: gcs.credentials_path,
+        prefix: gcs.prefix,
+    })
+    .await?;
+
+    Ok(StorageService::with_backend(storage::RetryingBackend::new(backend, retry_policy(config))))
+}
+
+#[cfg(not(feature = "gcs"))]
+async fn init_gcs_storage(_config: &StorageConfig) -> storage::Result<StorageService> {
+    Err(storage::StorageError::StorageError(
+        "storage.backend is \"gcs\" but the server was built without the `gcs` feature".to_string(),
+    ))
+}
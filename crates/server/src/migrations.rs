@@ -2,8 +2,10 @@ use orm::migration::{Migration, MigrationRunner, Schema};
 use orm::query::builder::Dialect;
 use orm::schema::{ForeignKey, ForeignKeyAction};
 use orm::backend::Backend;
-use orm::error::Result;
+use orm::error::{Error, Result};
+use orm::query::QueryValue;
 use async_trait::async_trait;
+use std::collections::HashSet;
 
 /// Migration to create users table
 struct CreateUsersTable;
@@ -163,19 +165,2300 @@ impl Migration for CreateFilesTable {
     }
 }
 
+/// Migration to create file favorites and access log tables
+struct CreateFileFavoritesAndAccessLog;
+
+#[async_trait]
+impl Migration for CreateFileFavoritesAndAccessLog {
+    fn name(&self) -> &str {
+        "create_file_favorites_and_access_log"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000005
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("file_favorites", |table| {
+            table.id("id");
+            table.string("file_id", 36);
+            table.big_integer("user_id");
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "file_id".to_string(),
+                references_table: "files".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_file_favorites_user_file", vec!["user_id".to_string(), "file_id".to_string()], true);
+        });
+
+        schema.create_table("file_access_log", |table| {
+            table.id("id");
+            table.string("file_id", 36);
+            table.big_integer("user_id");
+            table.string("accessed_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "file_id".to_string(),
+                references_table: "files".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_file_access_log_user_id", vec!["user_id".to_string()], false);
+            table.index("idx_file_access_log_file_id", vec!["file_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("file_access_log");
+        schema.drop_table("file_favorites");
+        Ok(())
+    }
+}
+
+/// Migration to record a content checksum for each file
+struct AddFileChecksum;
+
+#[async_trait]
+impl Migration for AddFileChecksum {
+    fn name(&self) -> &str {
+        "add_file_checksum"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000006
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: files stored before this migration have no checksum and are served unverified
+        schema.alter_table("files", |table| {
+            table.string("checksum", 64);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("files", |table| {
+            table.drop_column("checksum");
+        });
+        Ok(())
+    }
+}
+
+/// Migration to create the transfer log and aggregated usage report tables
+struct CreateStorageUsageReports;
+
+#[async_trait]
+impl Migration for CreateStorageUsageReports {
+    fn name(&self) -> &str {
+        "create_storage_usage_reports"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000007
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("storage_transfer_log", |table| {
+            table.id("id");
+            table.big_integer("user_id");
+            table.string("direction", 10);
+            table.big_integer("bytes");
+            table.string("occurred_at", 50);
+
+            table.index("idx_storage_transfer_log_occurred_at", vec!["occurred_at".to_string()], false);
+        });
+
+        schema.create_table("storage_usage_reports", |table| {
+            table.id("id");
+            table.big_integer("user_id");
+            table.string("period", 10);
+            table.string("period_start", 50);
+            table.big_integer("upload_bytes");
+            table.big_integer("download_bytes");
+            table.big_integer("stored_bytes");
+            table.big_integer("file_count");
+
+            table.index(
+                "idx_storage_usage_reports_period",
+                vec!["period".to_string(), "period_start".to_string(), "user_id".to_string()],
+                true,
+            );
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("storage_usage_reports");
+        schema.drop_table("storage_transfer_log");
+        Ok(())
+    }
+}
+
+/// Migration to let files be moved to the trash instead of deleted outright
+struct AddFileDeletedAt;
+
+#[async_trait]
+impl Migration for AddFileDeletedAt {
+    fn name(&self) -> &str {
+        "add_file_deleted_at"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000008
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("files", |table| {
+            table.string("deleted_at", 50);
+            table.index("idx_files_deleted_at", vec!["deleted_at".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("files", |table| {
+            table.drop_column("deleted_at");
+        });
+        Ok(())
+    }
+}
+
+/// Migration to create the billing subscriptions table
+struct CreateBillingSubscriptions;
+
+#[async_trait]
+impl Migration for CreateBillingSubscriptions {
+    fn name(&self) -> &str {
+        "create_billing_subscriptions"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000009
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("billing_subscriptions", |table| {
+            table.id("id");
+            table.big_integer("user_id");
+            table.string("stripe_customer_id", 255);
+            table.string("stripe_subscription_id", 255);
+            table.string("plan", 100);
+            table.string("status", 30);
+            table.string("current_period_end", 50);
+            table.string("updated_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_billing_subscriptions_user_id", vec!["user_id".to_string()], true);
+            table.index("idx_billing_subscriptions_customer", vec!["stripe_customer_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("billing_subscriptions");
+        Ok(())
+    }
+}
+
+struct CreateAnnouncementsTables;
+
+#[async_trait]
+impl Migration for CreateAnnouncementsTables {
+    fn name(&self) -> &str {
+        "create_announcements_tables"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000010
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("announcements", |table| {
+            table.id("id");
+            table.string("title", 255);
+            table.text("body");
+            table.string("level", 20);
+            table.string("starts_at", 50);
+            table.string("ends_at", 50);
+            table.big_integer("created_by");
+            table.string("created_at", 50);
+
+            table.index("idx_announcements_starts_at", vec!["starts_at".to_string()], false);
+        });
+
+        schema.create_table("announcement_dismissals", |table| {
+            table.id("id");
+            table.big_integer("announcement_id");
+            table.big_integer("user_id");
+            table.string("dismissed_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "announcement_id".to_string(),
+                references_table: "announcements".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index(
+                "idx_announcement_dismissals_user",
+                vec!["user_id".to_string(), "announcement_id".to_string()],
+                true,
+            );
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("announcement_dismissals");
+        schema.drop_table("announcements");
+        Ok(())
+    }
+}
+
+struct CreateCommentsTable;
+
+#[async_trait]
+impl Migration for CreateCommentsTable {
+    fn name(&self) -> &str {
+        "create_comments_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000011
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("comments", |table| {
+            table.id("id");
+            table.string("table_name", 64);
+            table.string("row_id", 255);
+            table.big_integer("user_id");
+            table.text("body");
+            table.string("created_at", 50);
+            table.string("updated_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index(
+                "idx_comments_record",
+                vec!["table_name".to_string(), "row_id".to_string()],
+                false,
+            );
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("comments");
+        Ok(())
+    }
+}
+
+struct CreateFileSharesTable;
+
+#[async_trait]
+impl Migration for CreateFileSharesTable {
+    fn name(&self) -> &str {
+        "create_file_shares_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000012
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("file_shares", |table| {
+            table.id("id");
+            table.string("token", 64);
+            table.string("file_id", 36);
+            table.big_integer("user_id");
+            table.string("password_hash", 255);
+            table.string("expires_at", 50);
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "file_id".to_string(),
+                references_table: "files".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_file_shares_token", vec!["token".to_string()], true);
+            table.index("idx_file_shares_file_id", vec!["file_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("file_shares");
+        Ok(())
+    }
+}
+
+struct CreateActivityEventsTable;
+
+#[async_trait]
+impl Migration for CreateActivityEventsTable {
+    fn name(&self) -> &str {
+        "create_activity_events_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000013
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("activity_events", |table| {
+            table.id("id");
+            table.big_integer("user_id");
+            table.big_integer("actor_id");
+            table.string("kind", 50);
+            table.string("subject_table", 64);
+            table.string("subject_id", 255);
+            table.text("data");
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_activity_events_user_id", vec!["user_id".to_string(), "id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("activity_events");
+        Ok(())
+    }
+}
+
+struct CreateDownloadTokensTable;
+
+#[async_trait]
+impl Migration for CreateDownloadTokensTable {
+    fn name(&self) -> &str {
+        "create_download_tokens_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000014
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("download_tokens", |table| {
+            table.id("id");
+            table.string("token", 64);
+            table.string("file_id", 36);
+            table.big_integer("issued_by");
+            table.string("expires_at", 50);
+            table.string("used_at", 50);
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "file_id".to_string(),
+                references_table: "files".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_download_tokens_token", vec!["token".to_string()], true);
+            table.index("idx_download_tokens_expires_at", vec!["expires_at".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("download_tokens");
+        Ok(())
+    }
+}
+
+/// Migration to record the content type detected from each file's bytes
+struct AddFileDetectedMimeType;
+
+#[async_trait]
+impl Migration for AddFileDetectedMimeType {
+    fn name(&self) -> &str {
+        "add_file_detected_mime_type"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000015
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: unrecognized content and files stored before detection have no detected type
+        schema.alter_table("files", |table| {
+            table.string("detected_mime_type", 255);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("files", |table| {
+            table.drop_column("detected_mime_type");
+        });
+        Ok(())
+    }
+}
+
+/// Migration to let files be filed into folders
+struct AddFileFolder;
+
+#[async_trait]
+impl Migration for AddFileFolder {
+    fn name(&self) -> &str {
+        "add_file_folder"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000016
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: files at the root have no folder
+        schema.alter_table("files", |table| {
+            table.string("folder", 500);
+            table.index("idx_files_folder", vec!["user_id".to_string(), "folder".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("files", |table| {
+            table.drop_column("folder");
+        });
+        Ok(())
+    }
+}
+
+/// Migration to store each user's notification preferences
+struct CreateNotificationPreferencesTable;
+
+#[async_trait]
+impl Migration for CreateNotificationPreferencesTable {
+    fn name(&self) -> &str {
+        "create_notification_preferences_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000017
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("notification_preferences", |table| {
+            table.id("id");
+            table.big_integer("user_id");
+            // JSON document, so new notification kinds don't need a migration
+            table.text("preferences");
+            table.string("updated_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_notification_preferences_user_id", vec!["user_id".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("notification_preferences");
+        Ok(())
+    }
+}
+
+/// Migration to let deleted accounts wait out a restore window before they are purged
+struct AddUserDeletedAt;
+
+#[async_trait]
+impl Migration for AddUserDeletedAt {
+    fn name(&self) -> &str {
+        "add_user_deleted_at"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000018
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: only deleted accounts have a deletion time
+        schema.alter_table("users", |table| {
+            table.string("deleted_at", 50);
+            table.index("idx_users_deleted_at", vec!["deleted_at".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("users", |table| {
+            table.drop_column("deleted_at");
+        });
+        Ok(())
+    }
+}
+
+/// Migration to create the user_buckets table (buckets users registered for their own files)
+struct CreateUserBucketsTable;
+
+#[async_trait]
+impl Migration for CreateUserBucketsTable {
+    fn name(&self) -> &str {
+        "create_user_buckets_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000019
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("user_buckets", |table| {
+            table.id("id");
+            table.big_integer("user_id");
+            table.string("bucket", 255);
+            table.string("region", 100);
+            table.string("endpoint", 500);
+            table.big_integer("path_style");
+            table.string("prefix", 500);
+            // Access and secret key, encrypted with the server's bucket credentials key
+            table.text("credentials");
+            table.string("health", 20);
+            table.text("last_error");
+            table.string("checked_at", 50);
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_user_buckets_user_id", vec!["user_id".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("user_buckets");
+        Ok(())
+    }
+}
+
+/// Migration to create the legal_holds table (holds blocking deletion of files and records)
+struct CreateLegalHoldsTable;
+
+#[async_trait]
+impl Migration for CreateLegalHoldsTable {
+    fn name(&self) -> &str {
+        "create_legal_holds_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000020
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // No foreign keys: holds name rows of any table, and outlive the users who placed them
+        schema.create_table("legal_holds", |table| {
+            table.id("id");
+            table.string("table_name", 64);
+            table.string("record_id", 255);
+            table.text("reason");
+            table.big_integer("placed_by");
+            table.string("placed_at", 50);
+            table.big_integer("released_by");
+            table.string("released_at", 50);
+
+            table.index("idx_legal_holds_record", vec!["table_name".to_string(), "record_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("legal_holds");
+        Ok(())
+    }
+}
+
+/// Migration to record where each session was opened from
+struct AddSessionLocation;
+
+#[async_trait]
+impl Migration for AddSessionLocation {
+    fn name(&self) -> &str {
+        "add_session_location"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000021
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: the address is not always known, and is only located with a GeoIP database
+        schema.alter_table("sessions", |table| {
+            table.string("ip_address", 45);
+            table.string("country", 2);
+            table.string("city", 255);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("sessions", |table| {
+            table.drop_column("ip_address");
+            table.drop_column("country");
+            table.drop_column("city");
+        });
+        Ok(())
+    }
+}
+
+/// Migration to create the login_history table (every successful login and where it came from)
+struct CreateLoginHistoryTable;
+
+#[async_trait]
+impl Migration for CreateLoginHistoryTable {
+    fn name(&self) -> &str {
+        "create_login_history_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000022
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("login_history", |table| {
+            table.id("id");
+            table.big_integer("user_id");
+            table.string("ip_address", 45);
+            table.string("country", 2);
+            table.string("city", 255);
+            table.big_integer("new_country");
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_login_history_user_id", vec!["user_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("login_history");
+        Ok(())
+    }
+}
+
+/// Migration to create the webhook_deliveries table (log of webhooks sent on file events)
+struct CreateWebhookDeliveriesTable;
+
+#[async_trait]
+impl Migration for CreateWebhookDeliveriesTable {
+    fn name(&self) -> &str {
+        "create_webhook_deliveries_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000023
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // No foreign keys: the log outlives the files it reports on
+        schema.create_table("webhook_deliveries", |table| {
+            table.id("id");
+            table.string("event_id", 36);
+            table.string("event", 50);
+            table.string("url", 2048);
+            table.text("payload");
+            table.string("status", 20);
+            table.big_integer("attempts");
+            table.big_integer("response_status");
+            table.text("last_error");
+            table.string("next_attempt_at", 50);
+            table.string("created_at", 50);
+            table.string("delivered_at", 50);
+
+            table.index("idx_webhook_deliveries_status", vec!["status".to_string(), "next_attempt_at".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("webhook_deliveries");
+        Ok(())
+    }
+}
+
+/// Migration to count downloads of each file and record when it was last downloaded
+struct AddFileDownloadStats;
+
+#[async_trait]
+impl Migration for AddFileDownloadStats {
+    fn name(&self) -> &str {
+        "add_file_download_stats"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000024
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: existing files have no count, which reads as zero downloads
+        schema.alter_table("files", |table| {
+            table.big_integer("download_count");
+            table.string("last_accessed_at", 50);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("files", |table| {
+            table.drop_column("download_count");
+            table.drop_column("last_accessed_at");
+        });
+        Ok(())
+    }
+}
+
+/// Migration to create the upload_sessions table (resumable uploads in progress)
+struct CreateUploadSessionsTable;
+
+#[async_trait]
+impl Migration for CreateUploadSessionsTable {
+    fn name(&self) -> &str {
+        "create_upload_sessions_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000025
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("upload_sessions", |table| {
+            table.id("id");
+            table.string("upload_id", 36);
+            table.big_integer("user_id");
+            table.string("original_name", 255);
+            table.string("mime_type", 255);
+            table.big_integer("total_size");
+            table.big_integer("received_bytes");
+            table.string("checksum", 64);
+            table.string("stored_name", 255);
+            table.string("blob_created_at", 50);
+            table.string("created_at", 50);
+            table.string("updated_at", 50);
+            table.string("expires_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_upload_sessions_upload_id", vec!["upload_id".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("upload_sessions");
+        Ok(())
+    }
+}
+
+/// Migration: Create share_invitations table
+/// Files shared with someone by email; pending until the recipient has an account
+struct CreateShareInvitationsTable;
+
+#[async_trait]
+impl Migration for CreateShareInvitationsTable {
+    fn name(&self) -> &str {
+        "create_share_invitations_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000026
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("share_invitations", |table| {
+            table.id("id");
+            table.string("file_id", 36);
+            table.big_integer("owner_id");
+            table.string("email", 255);
+            table.big_integer("recipient_id");
+            table.string("created_at", 50);
+            table.string("accepted_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "file_id".to_string(),
+                references_table: "files".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+            table.foreign_key(ForeignKey {
+                column: "owner_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+            table.foreign_key(ForeignKey {
+                column: "recipient_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_share_invitations_file_email", vec!["file_id".to_string(), "email".to_string()], true);
+            table.index("idx_share_invitations_email", vec!["email".to_string()], false);
+            table.index("idx_share_invitations_recipient_id", vec!["recipient_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("share_invitations");
+        Ok(())
+    }
+}
+
+/// Migration: Create upload_journal table
+/// Write-ahead journal of uploads whose blob and metadata may not have converged yet
+struct CreateUploadJournalTable;
+
+#[async_trait]
+impl Migration for CreateUploadJournalTable {
+    fn name(&self) -> &str {
+        "create_upload_journal_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000027
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // No foreign key to users: an entry must outlive its owner until the blob is settled
+        schema.create_table("upload_journal", |table| {
+            table.id("id");
+            table.string("stored_name", 255);
+            table.big_integer("user_id");
+            table.string("storage_path", 500);
+            table.string("pending_since", 50);
+            table.string("created_at", 50);
+
+            table.index("idx_upload_journal_stored_name", vec!["stored_name".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("upload_journal");
+        Ok(())
+    }
+}
+
+/// Migration: Create signing_keys table
+/// JWT signing keys added by rotations, and when replaced keys retire
+struct CreateSigningKeysTable;
+
+#[async_trait]
+impl Migration for CreateSigningKeysTable {
+    fn name(&self) -> &str {
+        "create_signing_keys_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000028
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("signing_keys", |table| {
+            table.id("id");
+            table.string("kid", 16);
+            // Empty for the configured secret, which is only recorded to retire it
+            table.string("secret", 64);
+            table.string("activates_at", 50);
+            table.string("retires_at", 50);
+            table.string("created_at", 50);
+
+            table.index("idx_signing_keys_kid", vec!["kid".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("signing_keys");
+        Ok(())
+    }
+}
+
+/// Migration to record the outcome of integrity audits on each file
+struct AddFileIntegrityStatus;
+
+#[async_trait]
+impl Migration for AddFileIntegrityStatus {
+    fn name(&self) -> &str {
+        "add_file_integrity_status"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000029
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: existing files haven't been audited yet
+        schema.alter_table("files", |table| {
+            table.string("integrity_status", 20);
+            table.string("integrity_checked_at", 50);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("files", |table| {
+            table.drop_column("integrity_status");
+            table.drop_column("integrity_checked_at");
+        });
+        Ok(())
+    }
+}
+
+/// Migration: Create file_permissions table
+/// Access to a file granted by its owner to another user
+struct CreateFilePermissionsTable;
+
+#[async_trait]
+impl Migration for CreateFilePermissionsTable {
+    fn name(&self) -> &str {
+        "create_file_permissions_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000030
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("file_permissions", |table| {
+            table.id("id");
+            table.string("file_id", 36);
+            table.big_integer("owner_id");
+            table.big_integer("user_id");
+            table.string("access", 20);
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "file_id".to_string(),
+                references_table: "files".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+            table.foreign_key(ForeignKey {
+                column: "owner_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_file_permissions_file_user", vec!["file_id".to_string(), "owner_id".to_string(), "user_id".to_string()], true);
+            table.index("idx_file_permissions_user_id", vec!["user_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("file_permissions");
+        Ok(())
+    }
+}
+
+/// Migration: Create file_transfers table
+/// Offers of a file's ownership waiting for their recipient
+struct CreateFileTransfersTable;
+
+#[async_trait]
+impl Migration for CreateFileTransfersTable {
+    fn name(&self) -> &str {
+        "create_file_transfers_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000031
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("file_transfers", |table| {
+            table.id("id");
+            table.string("file_id", 36);
+            table.big_integer("from_user_id");
+            table.big_integer("to_user_id");
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "file_id".to_string(),
+                references_table: "files".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+            table.foreign_key(ForeignKey {
+                column: "from_user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+            table.foreign_key(ForeignKey {
+                column: "to_user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            // One offer per file at a time
+            table.index("idx_file_transfers_file_id", vec!["file_id".to_string()], true);
+            table.index("idx_file_transfers_to_user_id", vec!["to_user_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("file_transfers");
+        Ok(())
+    }
+}
+
+/// Migration: Create organizations table
+/// Teams of users that own files together
+struct CreateOrganizationsTable;
+
+#[async_trait]
+impl Migration for CreateOrganizationsTable {
+    fn name(&self) -> &str {
+        "create_organizations_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000032
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("organizations", |table| {
+            table.id("id");
+            table.string("name", 100);
+            table.big_integer("owner_id");
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "owner_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_organizations_owner_id", vec!["owner_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("organizations");
+        Ok(())
+    }
+}
+
+/// Migration: Create memberships table
+/// The users in each organization and their roles
+struct CreateMembershipsTable;
+
+#[async_trait]
+impl Migration for CreateMembershipsTable {
+    fn name(&self) -> &str {
+        "create_memberships_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000033
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("memberships", |table| {
+            table.id("id");
+            table.big_integer("organization_id");
+            table.big_integer("user_id");
+            table.string("role", 20);
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "organization_id".to_string(),
+                references_table: "organizations".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            // A user is in an organization once, with one role
+            table.index(
+                "idx_memberships_organization_user",
+                vec!["organization_id".to_string(), "user_id".to_string()],
+                true,
+            );
+            table.index("idx_memberships_user_id", vec!["user_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("memberships");
+        Ok(())
+    }
+}
+
+/// Migration to let organizations own files
+struct AddFileOrganization;
+
+#[async_trait]
+impl Migration for AddFileOrganization {
+    fn name(&self) -> &str {
+        "add_file_organization"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000034
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: files of a single user belong to no organization
+        schema.alter_table("files", |table| {
+            table.big_integer("organization_id");
+            table.index("idx_files_organization_id", vec!["organization_id".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("files", |table| {
+            table.drop_column("organization_id");
+        });
+        Ok(())
+    }
+}
+
+/// Migration: Create table_versions table
+/// A counter per `/db` table, bumped by every write through the API, that list reads use as their ETag
+struct CreateTableVersionsTable;
+
+#[async_trait]
+impl Migration for CreateTableVersionsTable {
+    fn name(&self) -> &str {
+        "create_table_versions_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000035
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("table_versions", |table| {
+            table.id("id");
+            table.string("table_name", 64);
+            table.big_integer("version");
+            table.string("updated_at", 50);
+
+            table.index("idx_table_versions_table_name", vec!["table_name".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("table_versions");
+        Ok(())
+    }
+}
+
+/// Migration: Add plan to organizations
+/// Organizations on a plan of their own get its limits instead of their owner's
+struct AddOrganizationPlan;
+
+#[async_trait]
+impl Migration for AddOrganizationPlan {
+    fn name(&self) -> &str {
+        "add_organization_plan"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000036
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: organizations without a plan use their owner's
+        schema.alter_table("organizations", |table| {
+            table.string("plan", 100);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("organizations", |table| {
+            table.drop_column("plan");
+        });
+        Ok(())
+    }
+}
+
+/// Migration: Create api_usage table
+/// Requests made by each user or organization per calendar month, counted against plan caps
+struct CreateApiUsageTable;
+
+#[async_trait]
+impl Migration for CreateApiUsageTable {
+    fn name(&self) -> &str {
+        "create_api_usage_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000037
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("api_usage", |table| {
+            table.id("id");
+            table.string("tenant", 64);
+            table.string("period", 7);
+            table.big_integer("requests");
+            table.string("updated_at", 50);
+
+            table.index("idx_api_usage_tenant_period", vec!["tenant".to_string(), "period".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("api_usage");
+        Ok(())
+    }
+}
+
+/// Migration: Add checksum_state to files
+/// Saved SHA-256 progress, so appending to a file only hashes the new bytes
+struct AddFileChecksumState;
+
+#[async_trait]
+impl Migration for AddFileChecksumState {
+    fn name(&self) -> &str {
+        "add_file_checksum_state"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000038
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: the state is saved on the first append and cleared by other writes
+        schema.alter_table("files", |table| {
+            table.string("checksum_state", 64);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("files", |table| {
+            table.drop_column("checksum_state");
+        });
+        Ok(())
+    }
+}
+
+/// Migration: Create file_texts table
+/// Text extracted from each file's content, so file search can match it
+struct CreateFileTextsTable;
+
+#[async_trait]
+impl Migration for CreateFileTextsTable {
+    fn name(&self) -> &str {
+        "create_file_texts_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000039
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("file_texts", |table| {
+            table.string("file_id", 36);
+            // Checksum of the content the text was extracted from
+            table.string("checksum", 64);
+            table.string("status", 20);
+            table.text("content");
+            table.string("extracted_at", 50);
+
+            table.index("idx_file_texts_file_id", vec!["file_id".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("file_texts");
+        Ok(())
+    }
+}
+
+/// Migration: Add width, height, and duration_ms to files
+/// Dimensions and durations read from images, video, and audio when they are uploaded
+struct AddFileMediaInfo;
+
+#[async_trait]
+impl Migration for AddFileMediaInfo {
+    fn name(&self) -> &str {
+        "add_file_media_info"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000040
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: only set for media files whose headers could be read
+        schema.alter_table("files", |table| {
+            table.big_integer("width");
+            table.big_integer("height");
+            table.big_integer("duration_ms");
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("files", |table| {
+            table.drop_column("width");
+            table.drop_column("height");
+            table.drop_column("duration_ms");
+        });
+        Ok(())
+    }
+}
+
+/// Migration: Create presigned_uploads table
+/// Uploads allowed through a signed URL, claimed by the request that sends the file
+struct CreatePresignedUploadsTable;
+
+#[async_trait]
+impl Migration for CreatePresignedUploadsTable {
+    fn name(&self) -> &str {
+        "create_presigned_uploads_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000041
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("presigned_uploads", |table| {
+            table.id("id");
+            table.string("upload_id", 64);
+            table.big_integer("user_id");
+            table.string("original_name", 255);
+            table.string("mime_type", 255);
+            table.big_integer("max_size");
+            table.string("expires_at", 50);
+            // Set while a request is storing the file
+            table.string("used_at", 50);
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_presigned_uploads_upload_id", vec!["upload_id".to_string()], true);
+            table.index("idx_presigned_uploads_expires_at", vec!["expires_at".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("presigned_uploads");
+        Ok(())
+    }
+}
+
+/// Migration: Create refresh_tokens table
+/// Hashes of issued refresh tokens, grouped into a family per login
+struct CreateRefreshTokensTable;
+
+#[async_trait]
+impl Migration for CreateRefreshTokensTable {
+    fn name(&self) -> &str {
+        "create_refresh_tokens_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000042
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("refresh_tokens", |table| {
+            table.id("id");
+            table.string("token_hash", 64);
+            table.string("family", 64);
+            table.big_integer("user_id");
+            table.string("expires_at", 50);
+            // Set once the token has been traded for a new one
+            table.string("used_at", 50);
+            table.string("revoked_at", 50);
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_refresh_tokens_token_hash", vec!["token_hash".to_string()], true);
+            table.index("idx_refresh_tokens_family", vec!["family".to_string()], false);
+            table.index("idx_refresh_tokens_expires_at", vec!["expires_at".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("refresh_tokens");
+        Ok(())
+    }
+}
+
+/// Migration: Create email_tokens table
+/// Hashes of the single-use tokens in password reset, verification, and magic link emails
+struct CreateEmailTokensTable;
+
+#[async_trait]
+impl Migration for CreateEmailTokensTable {
+    fn name(&self) -> &str {
+        "create_email_tokens_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000043
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("email_tokens", |table| {
+            table.id("id");
+            table.string("token_hash", 64);
+            table.big_integer("user_id");
+            table.string("purpose", 32);
+            // Address the token was sent to
+            table.string("email", 255);
+            table.string("expires_at", 50);
+            table.string("used_at", 50);
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_email_tokens_token_hash", vec!["token_hash".to_string()], true);
+            table.index("idx_email_tokens_user_purpose", vec!["user_id".to_string(), "purpose".to_string()], false);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("email_tokens");
+        Ok(())
+    }
+}
+
+/// Migration to record when users verify their email address
+struct AddUserEmailVerifiedAt;
+
+#[async_trait]
+impl Migration for AddUserEmailVerifiedAt {
+    fn name(&self) -> &str {
+        "add_user_email_verified_at"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000044
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: unverified addresses have no verification time
+        schema.alter_table("users", |table| {
+            table.string("email_verified_at", 50);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("users", |table| {
+            table.drop_column("email_verified_at");
+        });
+        Ok(())
+    }
+}
+
+/// Migration: Create roles table
+/// Roles an app defines on top of the built-in user and service roles
+struct CreateRolesTable;
+
+#[async_trait]
+impl Migration for CreateRolesTable {
+    fn name(&self) -> &str {
+        "create_roles_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000045
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("roles", |table| {
+            table.id("id");
+            table.string("name", 64);
+            table.text("description");
+            table.string("created_at", 50);
+
+            table.index("idx_roles_name", vec!["name".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("roles");
+        Ok(())
+    }
+}
+
+/// Migration: Create permissions table
+/// The permissions each role grants, such as `files:delete`
+struct CreatePermissionsTable;
+
+#[async_trait]
+impl Migration for CreatePermissionsTable {
+    fn name(&self) -> &str {
+        "create_permissions_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000046
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("permissions", |table| {
+            table.id("id");
+            table.big_integer("role_id");
+            table.string("permission", 255);
+
+            table.foreign_key(ForeignKey {
+                column: "role_id".to_string(),
+                references_table: "roles".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_permissions_role_permission", vec!["role_id".to_string(), "permission".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("permissions");
+        Ok(())
+    }
+}
+
+/// Migration: Create user_roles table
+/// The custom roles each user holds
+struct CreateUserRolesTable;
+
+#[async_trait]
+impl Migration for CreateUserRolesTable {
+    fn name(&self) -> &str {
+        "create_user_roles_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000047
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("user_roles", |table| {
+            table.id("id");
+            table.big_integer("user_id");
+            table.big_integer("role_id");
+            table.string("created_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+            table.foreign_key(ForeignKey {
+                column: "role_id".to_string(),
+                references_table: "roles".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_user_roles_user_role", vec!["user_id".to_string(), "role_id".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("user_roles");
+        Ok(())
+    }
+}
+
+/// Migration to record when users last changed their password
+struct AddUserPasswordChangedAt;
+
+#[async_trait]
+impl Migration for AddUserPasswordChangedAt {
+    fn name(&self) -> &str {
+        "add_user_password_changed_at"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000048
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: passwords never changed since signup have no change time
+        schema.alter_table("users", |table| {
+            table.string("password_changed_at", 50);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("users", |table| {
+            table.drop_column("password_changed_at");
+        });
+        Ok(())
+    }
+}
+
+/// Migration to record when every session of a user was last ended
+/// Tokens issued before then are refused
+struct AddUserSessionsRevokedAt;
+
+#[async_trait]
+impl Migration for AddUserSessionsRevokedAt {
+    fn name(&self) -> &str {
+        "add_user_sessions_revoked_at"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000049
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        // Nullable: accounts whose sessions were never ended have no revocation time
+        schema.alter_table("users", |table| {
+            table.string("sessions_revoked_at", 50);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.alter_table("users", |table| {
+            table.drop_column("sessions_revoked_at");
+        });
+        Ok(())
+    }
+}
+
+/// Migration to create the profiles table
+struct CreateProfilesTable;
+
+#[async_trait]
+impl Migration for CreateProfilesTable {
+    fn name(&self) -> &str {
+        "create_profiles_table"
+    }
+
+    fn version(&self) -> i64 {
+        20241018_000050
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        schema.create_table("profiles", |table| {
+            table.id("id");
+            table.big_integer("user_id");
+            table.string("display_name", 64);
+            // Not a foreign key: the API hides an avatar that was trashed or purged
+            table.string("avatar_file_id", 36);
+            table.text("bio");
+            table.string("locale", 35);
+            table.string("updated_at", 50);
+
+            table.foreign_key(ForeignKey {
+                column: "user_id".to_string(),
+                references_table: "users".to_string(),
+                references_column: "id".to_string(),
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: None,
+            });
+
+            table.index("idx_profiles_user_id", vec!["user_id".to_string()], true);
+        });
+        Ok(())
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        schema.drop_table("profiles");
+        Ok(())
+    }
+}
+
+/// Table recording the migration steps confirmed to be in the schema
+const STEP_JOURNAL_TABLE: &str = "schema_migration_steps";
+
+/// Table recording the steps of migrations about to run, until they are confirmed
+/// A step found in the schema without a row here or in the journal wasn't made by the runner,
+/// so [`repair`] never undoes it.
+const STEP_STARTED_TABLE: &str = "schema_migration_started";
+
+/// One schema change made by a migration
+/// Whether a step is applied is read from the live schema, so an interrupted migration can be
+/// told apart from one that never started. Indexes are created with their table and are not
+/// separate steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// A table, with the columns the migration creates it with
+    CreateTable(&'static str, &'static [&'static str]),
+    AddColumn(&'static str, &'static str),
+}
+
+impl Step {
+    /// Whether this change is present in the database
+    /// A table that exists without the columns the migration gives it was made by something
+    /// else, and is an error rather than a step to skip or undo
+    async fn is_applied(&self, backend: &dyn Backend, dialect: &Dialect) -> Result<bool> {
+        match self {
+            Step::CreateTable(table, columns) => {
+                let existing = table_columns(backend, dialect, table).await?;
+                if existing.is_empty() {
+                    return Ok(false);
+                }
+                let missing: Vec<&str> = columns
+                    .iter()
+                    .filter(|column| !existing.iter().any(|c| c.eq_ignore_ascii_case(column)))
+                    .copied()
+                    .collect();
+                if !missing.is_empty() {
+                    return Err(Error::QueryError(format!(
+                        "Table {} exists but lacks the columns its migration creates ({}); rename or drop it before migrating",
+                        table,
+                        missing.join(", ")
+                    )));
+                }
+                Ok(true)
+            }
+            Step::AddColumn(table, column) => {
+                let existing = table_columns(backend, dialect, table).await?;
+                Ok(existing.iter().any(|c| c.eq_ignore_ascii_case(column)))
+            }
+        }
+    }
+
+    /// SQL that takes this change back out of the schema
+    fn undo_sql(&self) -> String {
+        match self {
+            Step::CreateTable(table, _) => format!("DROP TABLE {}", table),
+            Step::AddColumn(table, column) => format!("ALTER TABLE {} DROP COLUMN {}", table, column),
+        }
+    }
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::CreateTable(table, _) => write!(f, "create table {}", table),
+            Step::AddColumn(table, column) => write!(f, "add column {}.{}", table, column),
+        }
+    }
+}
+
+/// Names of a table's columns, empty if the table doesn't exist
+async fn table_columns(backend: &dyn Backend, dialect: &Dialect, table: &str) -> Result<Vec<String>> {
+    let sql = match dialect {
+        Dialect::SQLite => "SELECT name FROM pragma_table_info(?1)",
+        _ => "SELECT column_name AS name FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = ?1",
+    };
+    Ok(backend.fetch_all_params(sql, &[QueryValue::String(table.to_string())]).await?
+        .iter()
+        .filter_map(|row| row.get("name").and_then(|v| v.as_str()).map(str::to_string))
+        .collect())
+}
+
+/// Pair a migration with the steps it makes
+fn migration<M: Migration + 'static>(migration: M, steps: &'static [Step]) -> (Box<dyn Migration>, &'static [Step]) {
+    (Box::new(migration), steps)
+}
+
+/// Every migration in order, with the schema changes it makes
+fn migrations() -> Vec<(Box<dyn Migration>, &'static [Step])> {
+    vec![
+        migration(CreateUsersTable, &[Step::CreateTable("users", &["id", "email", "password_hash", "role"])]),
+        migration(CreateSessionsTable, &[
+            Step::CreateTable("sessions", &["id", "user_id", "token", "expires_at", "created_at"]),
+        ]),
+        migration(CreatePostsTable, &[Step::CreateTable("posts", &["id", "title", "content", "user_id"])]),
+        migration(CreateFilesTable, &[
+            Step::CreateTable("files", &["id", "user_id", "original_name", "stored_name", "size", "mime_type", "storage_path", "created_at"]),
+        ]),
+        migration(CreateFileFavoritesAndAccessLog, &[
+            Step::CreateTable("file_favorites", &["id", "file_id", "user_id", "created_at"]),
+            Step::CreateTable("file_access_log", &["id", "file_id", "user_id", "accessed_at"]),
+        ]),
+        migration(AddFileChecksum, &[Step::AddColumn("files", "checksum")]),
+        migration(CreateStorageUsageReports, &[
+            Step::CreateTable("storage_transfer_log", &["id", "user_id", "direction", "bytes", "occurred_at"]),
+            Step::CreateTable("storage_usage_reports", &["id", "user_id", "period", "period_start", "upload_bytes", "download_bytes", "stored_bytes", "file_count"]),
+        ]),
+        migration(AddFileDeletedAt, &[Step::AddColumn("files", "deleted_at")]),
+        migration(CreateBillingSubscriptions, &[
+            Step::CreateTable("billing_subscriptions", &["id", "user_id", "stripe_customer_id", "stripe_subscription_id", "plan", "status", "current_period_end", "updated_at"]),
+        ]),
+        migration(CreateAnnouncementsTables, &[
+            Step::CreateTable("announcements", &["id", "title", "body", "level", "starts_at", "ends_at", "created_by", "created_at"]),
+            Step::CreateTable("announcement_dismissals", &["id", "announcement_id", "user_id", "dismissed_at"]),
+        ]),
+        migration(CreateCommentsTable, &[
+            Step::CreateTable("comments", &["id", "table_name", "row_id", "user_id", "body", "created_at", "updated_at"]),
+        ]),
+        migration(CreateFileSharesTable, &[
+            Step::CreateTable("file_shares", &["id", "token", "file_id", "user_id", "password_hash", "expires_at", "created_at"]),
+        ]),
+        migration(CreateActivityEventsTable, &[
+            Step::CreateTable("activity_events", &["id", "user_id", "actor_id", "kind", "subject_table", "subject_id", "data", "created_at"]),
+        ]),
+        migration(CreateDownloadTokensTable, &[
+            Step::CreateTable("download_tokens", &["id", "token", "file_id", "issued_by", "expires_at", "used_at", "created_at"]),
+        ]),
+        migration(AddFileDetectedMimeType, &[Step::AddColumn("files", "detected_mime_type")]),
+        migration(AddFileFolder, &[Step::AddColumn("files", "folder")]),
+        migration(CreateNotificationPreferencesTable, &[
+            Step::CreateTable("notification_preferences", &["id", "user_id", "preferences", "updated_at"]),
+        ]),
+        migration(AddUserDeletedAt, &[Step::AddColumn("users", "deleted_at")]),
+        migration(CreateUserBucketsTable, &[
+            Step::CreateTable("user_buckets", &["id", "user_id", "bucket", "region", "endpoint", "path_style", "prefix", "credentials", "health", "last_error", "checked_at", "created_at"]),
+        ]),
+        migration(CreateLegalHoldsTable, &[
+            Step::CreateTable("legal_holds", &["id", "table_name", "record_id", "reason", "placed_by", "placed_at", "released_by", "released_at"]),
+        ]),
+        migration(AddSessionLocation, &[
+            Step::AddColumn("sessions", "ip_address"),
+            Step::AddColumn("sessions", "country"),
+            Step::AddColumn("sessions", "city"),
+        ]),
+        migration(CreateLoginHistoryTable, &[
+            Step::CreateTable("login_history", &["id", "user_id", "ip_address", "country", "city", "new_country", "created_at"]),
+        ]),
+        migration(CreateWebhookDeliveriesTable, &[
+            Step::CreateTable("webhook_deliveries", &["id", "event_id", "event", "url", "payload", "status", "attempts", "response_status", "last_error", "next_attempt_at", "created_at", "delivered_at"]),
+        ]),
+        migration(AddFileDownloadStats, &[
+            Step::AddColumn("files", "download_count"),
+            Step::AddColumn("files", "last_accessed_at"),
+        ]),
+        migration(CreateUploadSessionsTable, &[
+            Step::CreateTable("upload_sessions", &["id", "upload_id", "user_id", "original_name", "mime_type", "total_size", "received_bytes", "checksum", "stored_name", "blob_created_at", "created_at", "updated_at", "expires_at"]),
+        ]),
+        migration(CreateShareInvitationsTable, &[
+            Step::CreateTable("share_invitations", &["id", "file_id", "owner_id", "email", "recipient_id", "created_at", "accepted_at"]),
+        ]),
+        migration(CreateUploadJournalTable, &[
+            Step::CreateTable("upload_journal", &["id", "stored_name", "user_id", "storage_path", "pending_since", "created_at"]),
+        ]),
+        migration(CreateSigningKeysTable, &[
+            Step::CreateTable("signing_keys", &["id", "kid", "secret", "activates_at", "retires_at", "created_at"]),
+        ]),
+        migration(AddFileIntegrityStatus, &[
+            Step::AddColumn("files", "integrity_status"),
+            Step::AddColumn("files", "integrity_checked_at"),
+        ]),
+        migration(CreateFilePermissionsTable, &[
+            Step::CreateTable("file_permissions", &["id", "file_id", "owner_id", "user_id", "access", "created_at"]),
+        ]),
+        migration(CreateFileTransfersTable, &[
+            Step::CreateTable("file_transfers", &["id", "file_id", "from_user_id", "to_user_id", "created_at"]),
+        ]),
+        migration(CreateOrganizationsTable, &[
+            Step::CreateTable("organizations", &["id", "name", "owner_id", "created_at"]),
+        ]),
+        migration(CreateMembershipsTable, &[
+            Step::CreateTable("memberships", &["id", "organization_id", "user_id", "role", "created_at"]),
+        ]),
+        migration(AddFileOrganization, &[Step::AddColumn("files", "organization_id")]),
+        migration(CreateTableVersionsTable, &[
+            Step::CreateTable("table_versions", &["id", "table_name", "version", "updated_at"]),
+        ]),
+        migration(AddOrganizationPlan, &[Step::AddColumn("organizations", "plan")]),
+        migration(CreateApiUsageTable, &[
+            Step::CreateTable("api_usage", &["id", "tenant", "period", "requests", "updated_at"]),
+        ]),
+        migration(AddFileChecksumState, &[Step::AddColumn("files", "checksum_state")]),
+        migration(CreateFileTextsTable, &[
+            Step::CreateTable("file_texts", &["file_id", "checksum", "status", "content", "extracted_at"]),
+        ]),
+        migration(AddFileMediaInfo, &[
+            Step::AddColumn("files", "width"),
+            Step::AddColumn("files", "height"),
+            Step::AddColumn("files", "duration_ms"),
+        ]),
+        migration(CreatePresignedUploadsTable, &[
+            Step::CreateTable("presigned_uploads", &["id", "upload_id", "user_id", "original_name", "mime_type", "max_size", "expires_at", "used_at", "created_at"]),
+        ]),
+        migration(CreateRefreshTokensTable, &[
+            Step::CreateTable("refresh_tokens", &["id", "token_hash", "family", "user_id", "expires_at", "used_at", "revoked_at", "created_at"]),
+        ]),
+        migration(CreateEmailTokensTable, &[
+            Step::CreateTable("email_tokens", &["id", "token_hash", "user_id", "purpose", "email", "expires_at", "used_at", "created_at"]),
+        ]),
+        migration(AddUserEmailVerifiedAt, &[Step::AddColumn("users", "email_verified_at")]),
+        migration(CreateRolesTable, &[Step::CreateTable("roles", &["id", "name", "description", "created_at"])]),
+        migration(CreatePermissionsTable, &[Step::CreateTable("permissions", &["id", "role_id", "permission"])]),
+        migration(CreateUserRolesTable, &[
+            Step::CreateTable("user_roles", &["id", "user_id", "role_id", "created_at"]),
+        ]),
+        migration(AddUserPasswordChangedAt, &[Step::AddColumn("users", "password_changed_at")]),
+        migration(AddUserSessionsRevokedAt, &[Step::AddColumn("users", "sessions_revoked_at")]),
+        migration(CreateProfilesTable, &[
+            Step::CreateTable("profiles", &["id", "user_id", "display_name", "avatar_file_id", "bio", "locale", "updated_at"]),
+        ]),
+    ]
+}
+
+/// Tables owned by the migrations, including the runner's own bookkeeping
+pub fn managed_tables() -> Vec<&'static str> {
+    let mut tables = vec!["migrations", STEP_JOURNAL_TABLE, STEP_STARTED_TABLE];
+    for (_, steps) in migrations() {
+        for step in steps {
+            if let Step::CreateTable(table, _) = step {
+                tables.push(table);
+            }
+        }
+    }
+    tables
+}
+
+/// Version of the newest migration
+pub fn latest_version() -> i64 {
+    migrations().iter().map(|(migration, _)| migration.version()).max().unwrap_or(0)
+}
+
+/// Which steps of a migration are in the schema
+#[derive(Debug, Clone)]
+pub struct MigrationProgress {
+    pub version: i64,
+    pub name: String,
+    pub steps: &'static [Step],
+    pub applied: Vec<Step>,
+}
+
+impl MigrationProgress {
+    /// Some but not all of the migration's steps are applied
+    pub fn is_partial(&self) -> bool {
+        !self.applied.is_empty() && self.applied.len() < self.steps.len()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.applied.len() == self.steps.len()
+    }
+}
+
+/// A migration whose changes are all in the schema is recorded without running it again,
+/// so a crash between applying a migration and recording it doesn't block startup
+struct Resumable {
+    migration: Box<dyn Migration>,
+    complete: bool,
+}
+
+#[async_trait]
+impl Migration for Resumable {
+    fn name(&self) -> &str {
+        self.migration.name()
+    }
+
+    fn version(&self) -> i64 {
+        self.migration.version()
+    }
+
+    async fn up(&self, schema: &mut Schema) -> Result<()> {
+        if self.complete {
+            return Ok(());
+        }
+        self.migration.up(schema).await
+    }
+
+    async fn down(&self, schema: &mut Schema) -> Result<()> {
+        self.migration.down(schema).await
+    }
+}
+
+/// Work out how far each migration got
+/// Steps confirmed by an earlier run come from the journal; the rest are probed in the schema
+pub async fn inspect(backend: &dyn Backend, dialect: &Dialect) -> Result<Vec<MigrationProgress>> {
+    let sql = format!(
+        "CREATE TABLE IF NOT EXISTS {} (version BIGINT NOT NULL, step VARCHAR(255) NOT NULL, applied_at VARCHAR(50) NOT NULL)",
+        STEP_JOURNAL_TABLE
+    );
+    backend.execute(&sql, &[]).await?;
+    let sql = format!(
+        "CREATE TABLE IF NOT EXISTS {} (version BIGINT NOT NULL, step VARCHAR(255) NOT NULL, started_at VARCHAR(50) NOT NULL)",
+        STEP_STARTED_TABLE
+    );
+    backend.execute(&sql, &[]).await?;
+
+    let journal = read_steps(backend, STEP_JOURNAL_TABLE).await?;
+
+    let mut progress = Vec::new();
+    for (migration, steps) in migrations() {
+        let version = migration.version();
+        let mut applied = Vec::new();
+        for step in steps {
+            if journal.contains(&(version, step.to_string())) || step.is_applied(backend, dialect).await? {
+                applied.push(*step);
+            }
+        }
+        progress.push(MigrationProgress {
+            version,
+            name: migration.name().to_string(),
+            steps,
+            applied,
+        });
+    }
+    Ok(progress)
+}
+
 /// Run all migrations silently
 /// Returns true if any migrations were run
+/// 
+/// Fails without touching the schema if a migration was interrupted part way through;
+/// [`repair`] resumes it.
 pub async fn run_migrations(backend: &dyn Backend, dialect: Dialect) -> Result<bool> {
+    let progress = inspect(backend, &dialect).await?;
+    if let Some(partial) = progress.iter().find(|p| p.is_partial()) {
+        let applied: Vec<String> = partial.applied.iter().map(Step::to_string).collect();
+        return Err(Error::QueryError(format!(
+            "Migration {} ({}) was interrupted after: {}. Run `server migrate repair` to resume it",
+            partial.version,
+            partial.name,
+            applied.join(", ")
+        )));
+    }
+
+    run(backend, dialect, &progress).await
+}
+
+/// Resume migrations that were interrupted part way through, then run everything pending
+/// 
+/// The steps an interrupted migration did apply are undone first and the migration is then run
+/// in full, so it ends up exactly as if it had never been interrupted. Only steps the runner
+/// recorded starting are undone: if an interrupted migration's table or column was made by
+/// something else, nothing is changed and the error names it.
+/// 
+/// # Returns
+/// The migrations that were resumed
+pub async fn repair(backend: &dyn Backend, dialect: Dialect) -> Result<Vec<MigrationProgress>> {
+    let mut progress = inspect(backend, &dialect).await?;
+    let started = read_steps(backend, STEP_STARTED_TABLE).await?;
+
+    for migration in progress.iter().filter(|p| p.is_partial()) {
+        let foreign: Vec<String> = migration.applied
+            .iter()
+            .map(Step::to_string)
+            .filter(|step| !started.contains(&(migration.version, step.clone())))
+            .collect();
+        if !foreign.is_empty() {
+            return Err(Error::QueryError(format!(
+                "Migration {} ({}) was interrupted, but the runner didn't make: {}. Check and remove them by hand, then run repair again",
+                migration.version,
+                migration.name,
+                foreign.join(", ")
+            )));
+        }
+    }
+
+    let mut resumed = Vec::new();
+    for migration in progress.iter_mut().filter(|p| p.is_partial()) {
+        for step in migration.applied.iter().rev() {
+            backend.execute(&step.undo_sql(), &[]).await?;
+        }
+
+        for table in [STEP_JOURNAL_TABLE, STEP_STARTED_TABLE] {
+            let sql = format!("DELETE FROM {} WHERE version = ?1", table);
+            backend.execute(&sql, &[QueryValue::I64(migration.version)]).await?;
+        }
+
+        resumed.push(migration.clone());
+        migration.applied.clear();
+    }
+
+    run(backend, dialect, &progress).await?;
+    Ok(resumed)
+}
+
+/// Steps recorded in a journal table, as (version, step) pairs
+async fn read_steps(backend: &dyn Backend, table: &str) -> Result<HashSet<(i64, String)>> {
+    let sql = format!("SELECT version, step FROM {}", table);
+    Ok(backend.fetch_all_params(&sql, &[]).await?
+        .iter()
+        .filter_map(|row| Some((
+            row.get("version")?.as_i64()?,
+            row.get("step")?.as_str()?.to_string(),
+        )))
+        .collect())
+}
+
+/// Run pending migrations and journal every step as applied
+async fn run(backend: &dyn Backend, dialect: Dialect, progress: &[MigrationProgress]) -> Result<bool> {
     let mut runner = MigrationRunner::new(backend, dialect);
     
     // Add migrations in order
-    runner.add_migration(Box::new(CreateUsersTable));
-    runner.add_migration(Box::new(CreateSessionsTable));
-    runner.add_migration(Box::new(CreatePostsTable));
-    runner.add_migration(Box::new(CreateFilesTable));
+    for ((migration, _), progress) in migrations().into_iter().zip(progress) {
+        runner.add_migration(Box::new(Resumable {
+            migration,
+            complete: progress.is_complete(),
+        }));
+    }
     
+    // Note the steps about to be made, so an interrupted run can be undone by `repair`
+    let sql = format!("INSERT INTO {} (version, step, started_at) VALUES (?1, ?2, ?3)", STEP_STARTED_TABLE);
+    let started_at = chrono::Utc::now().to_rfc3339();
+    for migration in progress {
+        for step in migration.steps.iter().filter(|step| !migration.applied.contains(step)) {
+            backend.execute(&sql, &[
+                QueryValue::I64(migration.version),
+                QueryValue::String(step.to_string()),
+                QueryValue::String(started_at.clone()),
+            ]).await?;
+        }
+    }
+
     // Run pending migrations - this will print output only if migrations are executed
     runner.run_pending(backend).await?;
+
+    // Every step is in the schema now; note the ones the journal doesn't have yet
+    let journal = read_steps(backend, STEP_JOURNAL_TABLE).await?;
+    let sql = format!("INSERT INTO {} (version, step, applied_at) VALUES (?1, ?2, ?3)", STEP_JOURNAL_TABLE);
+    let applied_at = chrono::Utc::now().to_rfc3339();
+    for migration in progress {
+        for step in migration.steps {
+            if !journal.contains(&(migration.version, step.to_string())) {
+                backend.execute(&sql, &[
+                    QueryValue::I64(migration.version),
+                    QueryValue::String(step.to_string()),
+                    QueryValue::String(applied_at.clone()),
+                ]).await?;
+            }
+        }
+    }
+    let sql = format!("DELETE FROM {}", STEP_STARTED_TABLE);
+    backend.execute(&sql, &[]).await?;
     
     // We can't easily detect if migrations ran without modifying the ORM,
     // so we'll just return false for now (migrations print their own output)
@@ -0,0 +1,443 @@
+//! Declarative schema push (`server db push`)
+//!
+//! For prototyping before writing migrations: declare the tables the app needs and [`plan`]
+//! works out the statements that bring the live database in line, like `prisma db push`.
+//! Pushes are additive. Missing tables and columns are created; columns that are not declared
+//! or whose type differs are reported but never dropped or altered, so a push can't lose data.
+//! Tables owned by the migrations can't be declared.
+//!
+//! The schema is usually declared in `schema.toml`, one table per section. Every table also
+//! gets an auto-incrementing `id` primary key, and a trailing `?` makes a column nullable:
+//!
+//! ```toml
+//! [tables.notes]
+//! title = "string"
+//! body = "text?"
+//! pinned = "boolean"
+//! ```
+//!
+//! The same schema can be built in Rust:
+//!
+//! ```
+//! use server::push::{ColumnType, DesiredSchema};
+//!
+//! let schema = DesiredSchema::new().table("notes", |table| {
+//!     table
+//!         .column("title", ColumnType::String)
+//!         .nullable_column("body", ColumnType::Text)
+//!         .column("pinned", ColumnType::Boolean)
+//! });
+//! ```
+
+use orm::backend::Backend;
+use orm::query::builder::Dialect;
+use orm::query::QueryValue;
+use std::path::Path;
+
+use crate::migrations::managed_tables;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PushError {
+    #[error("Invalid schema: {0}")]
+    InvalidSchema(String),
+    #[error("Failed to read schema file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    Database(#[from] orm::error::Error),
+}
+
+pub type Result<T> = std::result::Result<T, PushError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    String,
+    Text,
+    Integer,
+    BigInteger,
+    Float,
+    Boolean,
+}
+
+impl ColumnType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "string" => Some(ColumnType::String),
+            "text" => Some(ColumnType::Text),
+            "integer" => Some(ColumnType::Integer),
+            "big_integer" => Some(ColumnType::BigInteger),
+            "float" => Some(ColumnType::Float),
+            "boolean" => Some(ColumnType::Boolean),
+            _ => None,
+        }
+    }
+
+    fn sql_type(&self, dialect: &Dialect) -> &'static str {
+        match (self, dialect) {
+            (ColumnType::String | ColumnType::Text, Dialect::SQLite) => "TEXT",
+            (ColumnType::Integer | ColumnType::BigInteger | ColumnType::Boolean, Dialect::SQLite) => "INTEGER",
+            (ColumnType::Float, Dialect::SQLite) => "REAL",
+            (ColumnType::String, _) => "VARCHAR(255)",
+            (ColumnType::Text, _) => "TEXT",
+            (ColumnType::Integer, _) => "INT",
+            (ColumnType::BigInteger, _) => "BIGINT",
+            (ColumnType::Float, _) => "DOUBLE",
+            (ColumnType::Boolean, _) => "BOOLEAN",
+        }
+    }
+
+    /// Value given to existing rows when a non-nullable column is added
+    fn zero_value(&self) -> &'static str {
+        match self {
+            ColumnType::String | ColumnType::Text => "''",
+            ColumnType::Integer | ColumnType::BigInteger | ColumnType::Boolean => "0",
+            ColumnType::Float => "0.0",
+        }
+    }
+
+    /// Storage class a column of this type ends up with, in either dialect
+    fn affinity(&self) -> Affinity {
+        match self {
+            ColumnType::String | ColumnType::Text => Affinity::Text,
+            ColumnType::Integer | ColumnType::BigInteger | ColumnType::Boolean => Affinity::Integer,
+            ColumnType::Float => Affinity::Real,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Affinity {
+    Text,
+    Integer,
+    Real,
+    Other,
+}
+
+impl Affinity {
+    /// Classify a column type as reported by the database (`VARCHAR(200)`, `bigint`, ...)
+    fn of(sql_type: &str) -> Self {
+        let sql_type = sql_type.to_ascii_lowercase();
+        if sql_type.contains("int") {
+            Affinity::Integer
+        } else if ["char", "text", "clob"].iter().any(|t| sql_type.contains(t)) {
+            Affinity::Text
+        } else if ["real", "floa", "doub", "dec"].iter().any(|t| sql_type.contains(t)) {
+            Affinity::Real
+        } else {
+            Affinity::Other
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub kind: ColumnType,
+    pub nullable: bool,
+}
+
+impl ColumnDef {
+    fn sql(&self, dialect: &Dialect, adding: bool) -> String {
+        let mut sql = format!("{} {}", self.name, self.kind.sql_type(dialect));
+        if !self.nullable {
+            sql.push_str(" NOT NULL");
+            // Rows that already exist need a value
+            if adding {
+                sql.push_str(&format!(" DEFAULT {}", self.kind.zero_value()));
+            }
+        }
+        sql
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableDef {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+}
+
+impl TableDef {
+    /// Add a required column
+    pub fn column(self, name: &str, kind: ColumnType) -> Self {
+        self.with_column(name, kind, false)
+    }
+
+    /// Add a nullable column
+    pub fn nullable_column(self, name: &str, kind: ColumnType) -> Self {
+        self.with_column(name, kind, true)
+    }
+
+    fn with_column(mut self, name: &str, kind: ColumnType, nullable: bool) -> Self {
+        self.columns.push(ColumnDef {
+            name: name.to_string(),
+            kind,
+            nullable,
+        });
+        self
+    }
+}
+
+/// The tables the app expects, in declaration order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DesiredSchema {
+    pub tables: Vec<TableDef>,
+}
+
+impl DesiredSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare a table; it gets an `id` primary key in addition to the columns `build` adds
+    pub fn table<F>(mut self, name: &str, build: F) -> Self
+    where
+        F: FnOnce(TableDef) -> TableDef,
+    {
+        self.tables.push(build(TableDef {
+            name: name.to_string(),
+            columns: Vec::new(),
+        }));
+        self
+    }
+
+    /// Read a schema declared in TOML
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse a schema declared in TOML
+    pub fn from_toml(source: &str) -> Result<Self> {
+        let document: toml::Table = source.parse()
+            .map_err(|e| PushError::InvalidSchema(format!("{}", e)))?;
+
+        let mut schema = Self::new();
+        let Some(tables) = document.get("tables") else {
+            return Ok(schema);
+        };
+        let tables = tables.as_table()
+            .ok_or_else(|| PushError::InvalidSchema("`tables` must be a table".to_string()))?;
+
+        for (table_name, columns) in tables {
+            let columns = columns.as_table().ok_or_else(|| {
+                PushError::InvalidSchema(format!("`tables.{}` must be a table of columns", table_name))
+            })?;
+
+            let mut table = TableDef {
+                name: table_name.clone(),
+                columns: Vec::new(),
+            };
+            for (column_name, spec) in columns {
+                let spec = spec.as_str().ok_or_else(|| {
+                    PushError::InvalidSchema(format!("Type of column {}.{} must be a string", table_name, column_name))
+                })?;
+                let (kind, nullable) = match spec.strip_suffix('?') {
+                    Some(kind) => (kind, true),
+                    None => (spec, false),
+                };
+                let kind = ColumnType::parse(kind).ok_or_else(|| {
+                    PushError::InvalidSchema(format!(
+                        "Unknown type '{}' for column {}.{}; expected string, text, integer, big_integer, float, or boolean",
+                        kind, table_name, column_name
+                    ))
+                })?;
+                table = table.with_column(column_name, kind, nullable);
+            }
+            schema.tables.push(table);
+        }
+
+        Ok(schema)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let managed = managed_tables();
+        for table in &self.tables {
+            if !is_identifier(&table.name) {
+                return Err(PushError::InvalidSchema(format!("Invalid table name: '{}'", table.name)));
+            }
+            if managed.contains(&table.name.as_str()) {
+                return Err(PushError::InvalidSchema(format!(
+                    "Table '{}' is managed by the migrations and can't be pushed",
+                    table.name
+                )));
+            }
+            for column in &table.columns {
+                if !is_identifier(&column.name) || column.name == "id" {
+                    return Err(PushError::InvalidSchema(format!(
+                        "Invalid column name: '{}.{}'",
+                        table.name, column.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Names are interpolated into DDL unquoted, so only plain identifiers are allowed
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A statement that brings the database closer to the declared schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub description: String,
+    pub sql: String,
+}
+
+/// What a push would do
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PushPlan {
+    pub changes: Vec<Change>,
+    /// Differences a push leaves alone, such as type changes and undeclared columns
+    pub warnings: Vec<String>,
+}
+
+impl PushPlan {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.warnings.is_empty()
+    }
+}
+
+/// A column as it exists in the database
+struct LiveColumn {
+    name: String,
+    sql_type: String,
+    nullable: bool,
+}
+
+/// Columns of `table`; empty when the table doesn't exist
+async fn live_columns(backend: &dyn Backend, dialect: &Dialect, table: &str) -> Result<Vec<LiveColumn>> {
+    let sql = match dialect {
+        Dialect::SQLite => "SELECT name, type, \"notnull\" = 0 AS nullable FROM pragma_table_info(?1)",
+        _ => "SELECT column_name AS name, data_type AS type, is_nullable = 'YES' AS nullable FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = ?1 ORDER BY ordinal_position",
+    };
+
+    let rows = backend.fetch_all_params(sql, &[QueryValue::String(table.to_string())]).await?;
+    Ok(rows.iter()
+        .filter_map(|row| Some(LiveColumn {
+            name: row.get("name")?.as_str()?.to_string(),
+            sql_type: row.get("type").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            nullable: row.get("nullable").and_then(|v| v.as_i64()).unwrap_or(1) != 0,
+        }))
+        .collect())
+}
+
+/// Compare the declared schema with the live database
+pub async fn plan(backend: &dyn Backend, dialect: &Dialect, schema: &DesiredSchema) -> Result<PushPlan> {
+    schema.validate()?;
+
+    let mut plan = PushPlan::default();
+    for table in &schema.tables {
+        let live = live_columns(backend, dialect, &table.name).await?;
+
+        if live.is_empty() {
+            let id = match dialect {
+                Dialect::SQLite => "id INTEGER PRIMARY KEY AUTOINCREMENT",
+                _ => "id BIGINT AUTO_INCREMENT PRIMARY KEY",
+            };
+            let columns: Vec<String> = std::iter::once(id.to_string())
+                .chain(table.columns.iter().map(|column| column.sql(dialect, false)))
+                .collect();
+            let names: Vec<&str> = table.columns.iter().map(|column| column.name.as_str()).collect();
+
+            plan.changes.push(Change {
+                description: format!("create table {} ({})", table.name, names.join(", ")),
+                sql: format!("CREATE TABLE {} ({})", table.name, columns.join(", ")),
+            });
+            continue;
+        }
+
+        for column in &table.columns {
+            match live.iter().find(|live| live.name == column.name) {
+                None => plan.changes.push(Change {
+                    description: format!("add column {}.{}", table.name, column.name),
+                    sql: format!("ALTER TABLE {} ADD COLUMN {}", table.name, column.sql(dialect, true)),
+                }),
+                Some(existing) => {
+                    if Affinity::of(&existing.sql_type) != column.kind.affinity() {
+                        plan.warnings.push(format!(
+                            "column {}.{} is {} in the database but declared {:?}; write a migration to change it",
+                            table.name, column.name, existing.sql_type, column.kind
+                        ));
+                    }
+                    if existing.nullable != column.nullable {
+                        plan.warnings.push(format!(
+                            "column {}.{} is {} in the database but declared {}",
+                            table.name,
+                            column.name,
+                            if existing.nullable { "nullable" } else { "required" },
+                            if column.nullable { "nullable" } else { "required" },
+                        ));
+                    }
+                }
+            }
+        }
+
+        for existing in &live {
+            if existing.name != "id" && !table.columns.iter().any(|column| column.name == existing.name) {
+                plan.warnings.push(format!(
+                    "column {}.{} is not declared and was left in place",
+                    table.name, existing.name
+                ));
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Run the changes of a plan in order
+pub async fn apply(backend: &dyn Backend, plan: &PushPlan) -> Result<()> {
+    for change in &plan.changes {
+        backend.execute(&change.sql, &[]).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_toml() {
+        let schema = DesiredSchema::from_toml(
+            r#"
+[tables.notes]
+title = "string"
+body = "text?"
+"#,
+        )
+        .unwrap();
+
+        let expected = DesiredSchema::new().table("notes", |table| {
+            table
+                .column("title", ColumnType::String)
+                .nullable_column("body", ColumnType::Text)
+        });
+        assert_eq!(schema, expected);
+
+        assert!(DesiredSchema::from_toml("[tables.notes]\ntitle = \"varchar\"").is_err());
+        assert!(DesiredSchema::from_toml("[tables.notes]\ntitle = 3").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let managed = DesiredSchema::new().table("users", |table| table.column("nickname", ColumnType::String));
+        assert!(managed.validate().is_err());
+
+        let injected = DesiredSchema::new().table("notes; DROP TABLE users", |table| table);
+        assert!(injected.validate().is_err());
+
+        let id = DesiredSchema::new().table("notes", |table| table.column("id", ColumnType::Integer));
+        assert!(id.validate().is_err());
+    }
+
+    #[test]
+    fn test_affinity() {
+        assert_eq!(Affinity::of("VARCHAR(200)"), ColumnType::String.affinity());
+        assert_eq!(Affinity::of("bigint"), ColumnType::Boolean.affinity());
+        assert_eq!(Affinity::of("REAL"), ColumnType::Float.affinity());
+        assert_eq!(Affinity::of("BLOB"), Affinity::Other);
+    }
+}
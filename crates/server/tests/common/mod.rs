@@ -0,0 +1,154 @@
+//! Boots the real server binary against a throwaway SQLite database and storage directory
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use projectkit_core::Database;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+/// How long the server gets to migrate, seed, and start listening
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A server process listening on a random local port
+/// The process is killed and its files removed when this is dropped
+pub struct TestServer {
+    pub base_url: String,
+    pub client: reqwest::Client,
+    child: Child,
+    _dir: TempDir,
+}
+
+impl TestServer {
+    /// Start a server with the default test configuration
+    pub async fn start() -> Self {
+        Self::start_with("").await
+    }
+
+    /// Start a server with `extra_config` appended to its `projectkit.toml`
+    pub async fn start_with(extra_config: &str) -> Self {
+        let dir = tempfile::tempdir().expect("failed to create temp dir");
+        let database_url = format!("sqlite:{}", dir.path().join("projectkit.db").display());
+        let storage_path = dir.path().join("storage");
+
+        let config = format!(
+            r#"
+[database]
+url = '{database_url}'
+
+[auth]
+jwt_secret = "integration-test-secret"
+
+[server]
+host = "127.0.0.1"
+port = 0
+
+[storage]
+path = '{storage_path}'
+
+{extra_config}
+"#,
+            storage_path = storage_path.display(),
+        );
+        std::fs::write(dir.path().join("projectkit.toml"), config).expect("failed to write config");
+
+        // A user table for the generic /db endpoints; the server's migrations only create system tables
+        let db = Database::connect(&database_url).await.expect("failed to create test database");
+        db.execute(
+            "CREATE TABLE notes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                title TEXT NOT NULL,
+                body TEXT
+            )",
+        )
+        .await
+        .expect("failed to create notes table");
+        drop(db);
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_server"))
+            .current_dir(dir.path())
+            .env_remove("PROJECTKIT_DATABASE_URL")
+            .env_remove("PROJECTKIT_SERVER_PORT")
+            .env_remove("PROJECTKIT_STORAGE_PATH")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .expect("failed to start server");
+
+        // Wait for the listening address, then keep draining stdout so the server never blocks on it
+        let stdout = child.stdout.take().unwrap();
+        let (address_tx, address_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if let Some(address) = line.split("Running on ").nth(1) {
+                    let _ = address_tx.send(address.trim().to_string());
+                }
+            }
+        });
+
+        let base_url = match address_rx.recv_timeout(STARTUP_TIMEOUT) {
+            Ok(address) => address,
+            Err(e) => {
+                let _ = child.kill();
+                panic!("server did not start: {}", e);
+            }
+        };
+
+        Self {
+            base_url,
+            client: reqwest::Client::new(),
+            child,
+            _dir: dir,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Sign up a new user and return their bearer token
+    pub async fn signup(&self, email: &str, password: &str) -> String {
+        let response = self
+            .client
+            .post(self.url("/auth/signup"))
+            .json(&json!({ "email": email, "password": password }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201, "signup failed");
+
+        let body: Value = response.json().await.unwrap();
+        body["token"].as_str().unwrap().to_string()
+    }
+
+    /// Upload `data` as `name` and return the created file's ID
+    pub async fn upload(&self, token: &str, name: &str, mime_type: &str, data: &[u8]) -> String {
+        let part = reqwest::multipart::Part::bytes(data.to_vec())
+            .file_name(name.to_string())
+            .mime_str(mime_type)
+            .unwrap();
+        let form = reqwest::multipart::Form::new().part("file", part);
+
+        let response = self
+            .client
+            .post(self.url("/files/upload"))
+            .bearer_auth(token)
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201, "upload failed");
+
+        let body: Value = response.json().await.unwrap();
+        body["file"]["id"].as_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
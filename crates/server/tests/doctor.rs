@@ -0,0 +1,56 @@
+//! `server doctor` against a fresh setup and a broken one
+
+use std::process::{Command, Output};
+
+fn doctor(dir: &std::path::Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("doctor")
+        .current_dir(dir)
+        .env_remove("PROJECTKIT_DATABASE_URL")
+        .env_remove("PROJECTKIT_SERVER_PORT")
+        .env_remove("PROJECTKIT_STORAGE_PATH")
+        .output()
+        .expect("failed to run server doctor")
+}
+
+#[test]
+fn test_doctor_passes_on_fresh_setup() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let config = format!(
+        r#"
+[database]
+url = 'sqlite:{database}'
+
+[auth]
+jwt_secret = "a-doctor-test-secret-that-is-long-enough"
+
+[storage]
+path = '{storage}'
+"#,
+        database = dir.path().join("projectkit.db").display(),
+        storage = dir.path().join("storage").display(),
+    );
+    std::fs::write(dir.path().join("projectkit.toml"), config).expect("failed to write config");
+
+    let output = doctor(dir.path());
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", report);
+    assert!(report.contains("PASS  Configuration"));
+    assert!(report.contains("PASS  Database"));
+    assert!(report.contains("PASS  Storage"));
+    assert!(report.contains("PASS  JWT signing"));
+    // Nothing has started the server yet
+    assert!(report.contains("WARN  Migrations"));
+    assert!(report.contains("SKIP  SMTP"));
+}
+
+#[test]
+fn test_doctor_fails_without_config() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+
+    let output = doctor(dir.path());
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", report);
+    assert!(report.contains("FAIL  Configuration"));
+    assert!(report.contains("SKIP  Database"));
+}
@@ -0,0 +1,283 @@
+//! End-to-end flows over HTTP against a freshly booted server
+
+mod common;
+
+use common::TestServer;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_user_file_and_record_flow() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    // Signup, then log in again with the same credentials
+    server.signup("alice@example.com", "correct-horse-battery").await;
+    let response = client
+        .post(server.url("/auth/login"))
+        .json(&json!({ "email": "alice@example.com", "password": "correct-horse-battery" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let token = body["token"].as_str().unwrap().to_string();
+
+    let response = client.get(server.url("/files")).send().await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    // Upload and list
+    let file_id = server.upload(&token, "hello.txt", "text/plain", b"hello, world").await;
+
+    let response = client.get(server.url("/files")).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let files = body["data"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["id"], file_id.as_str());
+    assert_eq!(files[0]["original_name"], "hello.txt");
+    assert_eq!(files[0]["size"], 12);
+
+    // Generic record CRUD
+    let response = client
+        .post(server.url("/db/notes"))
+        .bearer_auth(&token)
+        .json(&json!({ "title": "First note", "body": "Written over HTTP" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client.get(server.url("/db/notes")).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["page"]["total"], 1);
+    assert_eq!(body["data"][0]["title"], "First note");
+
+    let response = client.get(server.url("/db/users")).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(response.status(), 403);
+
+    // Download returns the uploaded bytes
+    let response = client
+        .get(server.url(&format!("/files/{}", file_id)))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.bytes().await.unwrap().as_ref(), b"hello, world");
+
+    // Delete moves the file to the trash
+    let response = client
+        .delete(server.url(&format!("/files/{}", file_id)))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .get(server.url(&format!("/files/{}", file_id)))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let response = client.get(server.url("/files/trash")).bearer_auth(&token).send().await.unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"][0]["id"], file_id.as_str());
+}
+
+#[tokio::test]
+async fn test_db_list_etags() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+    let token = server.signup("alice@example.com", "correct-horse-battery").await;
+
+    let response = client.get(server.url("/db/notes")).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    assert!(etag.starts_with("W/"));
+    assert_eq!(response.headers()["cache-control"], "private, no-cache");
+    assert_eq!(response.headers()["vary"], "Authorization");
+
+    let response = client
+        .get(server.url("/db/notes"))
+        .bearer_auth(&token)
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 304);
+
+    // Another caller's ETag never validates the rows this caller is sent
+    let bob = server.signup("bob@example.com", "correct-horse-battery").await;
+    let response = client
+        .get(server.url("/db/notes"))
+        .bearer_auth(&bob)
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // A write through the API changes the version
+    let response = client
+        .post(server.url("/db/notes"))
+        .bearer_auth(&token)
+        .json(&json!({ "title": "Fresh" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .get(server.url("/db/notes"))
+        .bearer_auth(&token)
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_ne!(response.headers()["etag"].to_str().unwrap(), etag);
+}
+
+#[tokio::test]
+async fn test_files_are_private_to_their_owner() {
+    let server = TestServer::start().await;
+
+    let alice = server.signup("alice@example.com", "correct-horse-battery").await;
+    let bob = server.signup("bob@example.com", "battery-staple-horse").await;
+    let file_id = server.upload(&alice, "secret.txt", "text/plain", b"for alice only").await;
+
+    let response = server
+        .client
+        .get(server.url(&format!("/files/{}", file_id)))
+        .bearer_auth(&bob)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    let response = server.client.get(server.url("/files")).bearer_auth(&bob).send().await.unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert!(body["data"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_range_downloads() {
+    let server = TestServer::start().await;
+
+    let alice = server.signup("alice@example.com", "correct-horse-battery").await;
+    let bob = server.signup("bob@example.com", "battery-staple-horse").await;
+    let file_id = server.upload(&alice, "digits.txt", "text/plain", b"0123456789").await;
+    let download = |token: &str, range: &str| {
+        server
+            .client
+            .get(server.url(&format!("/files/{}", file_id)))
+            .bearer_auth(token)
+            .header("Range", range)
+            .send()
+    };
+
+    let response = download(&alice, "bytes=2-4").await.unwrap();
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.headers()["content-range"], "bytes 2-4/10");
+    assert_eq!(response.bytes().await.unwrap().as_ref(), b"234");
+
+    let response = download(&alice, "bytes=-3").await.unwrap();
+    assert_eq!(response.headers()["content-range"], "bytes 7-9/10");
+    assert_eq!(response.bytes().await.unwrap().as_ref(), b"789");
+
+    let response = download(&alice, "bytes=10-").await.unwrap();
+    assert_eq!(response.status(), 416);
+    assert_eq!(response.headers()["content-range"], "bytes */10");
+
+    // Multiple ranges are ignored and the whole file is sent
+    let response = download(&alice, "bytes=0-1,4-5").await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["accept-ranges"], "bytes");
+    assert_eq!(response.bytes().await.unwrap().as_ref(), b"0123456789");
+
+    let response = download(&bob, "bytes=10-").await.unwrap();
+    assert_eq!(response.status(), 403);
+}
+
+#[tokio::test]
+async fn test_download_etags() {
+    let server = TestServer::start().await;
+
+    let alice = server.signup("alice@example.com", "correct-horse-battery").await;
+    let file_id = server.upload(&alice, "logo.svg", "image/svg+xml", b"<svg/>").await;
+    let url = server.url(&format!("/files/{}", file_id));
+
+    let response = server.client.get(&url).bearer_auth(&alice).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+
+    let response = server.client.get(&url).bearer_auth(&alice).header("If-None-Match", &etag).send().await.unwrap();
+    assert_eq!(response.status(), 304);
+    assert_eq!(response.headers()["etag"], etag.as_str());
+    assert!(response.bytes().await.unwrap().is_empty());
+
+    // Changing the file changes its ETag, so the cached copy is stale
+    let response = server
+        .client
+        .post(server.url(&format!("/files/{}/append", file_id)))
+        .bearer_auth(&alice)
+        .body("\n")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = server.client.get(&url).bearer_auth(&alice).header("If-None-Match", &etag).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_ne!(response.headers()["etag"], etag.as_str());
+    assert_eq!(response.bytes().await.unwrap().as_ref(), b"<svg/>\n");
+}
+
+#[tokio::test]
+async fn test_disabled_features_are_not_mounted() {
+    let server = TestServer::start_with("[features]\ndatabase = false\nmetrics = false").await;
+    let token = server.signup("alice@example.com", "correct-horse-battery").await;
+
+    let response = server.client.get(server.url("/db/notes")).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    let response = server.client.get(server.url("/metrics")).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    let response = server.client.get(server.url("/files")).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_batch_upload_is_all_or_nothing() {
+    let server = TestServer::start_with("[storage.upload_policy]\nallowed_extensions = [\"txt\"]").await;
+    let token = server.signup("alice@example.com", "correct-horse-battery").await;
+
+    let part = |name: &str| {
+        reqwest::multipart::Part::bytes(b"batch".to_vec())
+            .file_name(name.to_string())
+            .mime_str("text/plain")
+            .unwrap()
+    };
+
+    let form = reqwest::multipart::Form::new().part("file", part("one.txt")).part("file", part("two.txt"));
+    let response = server.client.post(server.url("/files/upload")).bearer_auth(&token).multipart(form).send().await.unwrap();
+    assert_eq!(response.status(), 201);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["files"].as_array().unwrap().len(), 2);
+
+    // The second part breaks the policy, so the first is rolled back
+    let form = reqwest::multipart::Form::new().part("file", part("three.txt")).part("file", part("four.exe"));
+    let response = server.client.post(server.url("/files/upload")).bearer_auth(&token).multipart(form).send().await.unwrap();
+    assert_eq!(response.status(), 422);
+
+    let response = server.client.get(server.url("/files")).bearer_auth(&token).send().await.unwrap();
+    let body: Value = response.json().await.unwrap();
+    let names: Vec<&str> = body["data"].as_array().unwrap().iter().map(|f| f["original_name"].as_str().unwrap()).collect();
+    assert_eq!(names.len(), 2);
+    assert!(!names.contains(&"three.txt"));
+}
@@ -0,0 +1,94 @@
+//! Recovery from migrations interrupted part way through
+
+use orm::query::builder::Dialect;
+use projectkit_core::Database;
+use server::migrations::{self, Step};
+
+async fn database() -> (Database, tempfile::TempDir) {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let url = format!("sqlite:{}", dir.path().join("projectkit.db").display());
+    let db = Database::connect(&url).await.expect("failed to create test database");
+    (db, dir)
+}
+
+#[tokio::test]
+async fn test_fresh_database_is_fully_migrated() {
+    let (db, _dir) = database().await;
+    migrations::run_migrations(db.backend(), Dialect::SQLite).await.unwrap();
+
+    let progress = migrations::inspect(db.backend(), &Dialect::SQLite).await.unwrap();
+    assert!(progress.iter().all(|migration| migration.is_complete()));
+
+    // Running again is a no-op
+    migrations::run_migrations(db.backend(), Dialect::SQLite).await.unwrap();
+}
+
+/// Version of the migration creating `file_favorites` and `file_access_log`
+async fn favorites_version(db: &Database) -> i64 {
+    migrations::inspect(db.backend(), &Dialect::SQLite).await.unwrap()
+        .iter()
+        .find(|migration| migration.name == "create_file_favorites_and_access_log")
+        .unwrap()
+        .version
+}
+
+#[tokio::test]
+async fn test_interrupted_migration_blocks_until_repaired() {
+    let (db, _dir) = database().await;
+
+    // The runner noted both tables of the favorites migration, then crashed after creating the first
+    let version = favorites_version(&db).await;
+    for table in ["file_favorites", "file_access_log"] {
+        db.execute(&format!(
+            "INSERT INTO schema_migration_started (version, step, started_at) VALUES ({}, 'create table {}', '2024-10-18T00:00:00Z')",
+            version, table
+        ))
+            .await
+            .unwrap();
+    }
+    db.execute("CREATE TABLE file_favorites (id INTEGER PRIMARY KEY AUTOINCREMENT, file_id TEXT, user_id INTEGER, created_at TEXT)")
+        .await
+        .unwrap();
+
+    let error = migrations::run_migrations(db.backend(), Dialect::SQLite).await.unwrap_err();
+    assert!(error.to_string().contains("migrate repair"));
+
+    let resumed = migrations::repair(db.backend(), Dialect::SQLite).await.unwrap();
+    assert_eq!(resumed.len(), 1);
+    assert_eq!(resumed[0].name, "create_file_favorites_and_access_log");
+    let applied: Vec<String> = resumed[0].applied.iter().map(Step::to_string).collect();
+    assert_eq!(applied, vec!["create table file_favorites".to_string()]);
+
+    let progress = migrations::inspect(db.backend(), &Dialect::SQLite).await.unwrap();
+    assert!(progress.iter().all(|migration| migration.is_complete()));
+    migrations::run_migrations(db.backend(), Dialect::SQLite).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_repair_keeps_tables_it_did_not_create() {
+    let (db, _dir) = database().await;
+
+    // An app table that happens to look like the favorites table, with data in it
+    db.execute("CREATE TABLE file_favorites (id INTEGER PRIMARY KEY AUTOINCREMENT, file_id TEXT, user_id INTEGER, created_at TEXT)")
+        .await
+        .unwrap();
+    db.execute("INSERT INTO file_favorites (file_id, user_id, created_at) VALUES ('a', 1, '2024-10-18T00:00:00Z')")
+        .await
+        .unwrap();
+
+    let error = migrations::repair(db.backend(), Dialect::SQLite).await.unwrap_err();
+    assert!(error.to_string().contains("create table file_favorites"));
+    let rows = db.backend().fetch_all_params("SELECT * FROM file_favorites", &[]).await.unwrap();
+    assert_eq!(rows.len(), 1);
+}
+
+#[tokio::test]
+async fn test_foreign_table_with_other_columns_is_not_migrated() {
+    let (db, _dir) = database().await;
+    db.execute("CREATE TABLE profiles (id INTEGER PRIMARY KEY AUTOINCREMENT, nickname TEXT)")
+        .await
+        .unwrap();
+
+    let error = migrations::run_migrations(db.backend(), Dialect::SQLite).await.unwrap_err();
+    assert!(error.to_string().contains("Table profiles exists"));
+}
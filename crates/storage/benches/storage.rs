@@ -0,0 +1,57 @@
+//! Blob store/retrieve throughput on the in-memory and local filesystem backends
+//!
+//! Run with `cargo bench -p storage`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+use storage::StorageService;
+use tokio::runtime::Runtime;
+
+const SIZES: [(&str, usize); 4] = [
+    ("1KiB", 1024),
+    ("64KiB", 64 * 1024),
+    ("1MiB", 1024 * 1024),
+    ("16MiB", 16 * 1024 * 1024),
+];
+
+fn bench_backend(c: &mut Criterion, name: &str, rt: &Runtime, storage: &StorageService) {
+    let mut group = c.benchmark_group(format!("storage/{}", name));
+
+    for (label, size) in SIZES {
+        let data = vec![0xA5u8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        if size >= 1024 * 1024 {
+            group.sample_size(10);
+        }
+
+        group.bench_with_input(BenchmarkId::new("store", label), &data, |b, data| {
+            b.to_async(rt).iter(|| async {
+                let file = storage.store(black_box(data), "bench.bin", None).await.unwrap();
+                storage.delete(&file.stored_name).await.unwrap();
+            })
+        });
+
+        let file = rt.block_on(storage.store(&data, "bench.bin", None)).unwrap();
+        group.bench_function(BenchmarkId::new("retrieve", label), |b| {
+            b.to_async(rt).iter(|| async { storage.retrieve(black_box(&file.stored_name)).await.unwrap() })
+        });
+        rt.block_on(storage.delete(&file.stored_name)).unwrap();
+    }
+
+    group.finish();
+}
+
+fn memory(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    bench_backend(c, "memory", &rt, &StorageService::in_memory());
+}
+
+fn local(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let storage = rt.block_on(StorageService::new(dir.path())).unwrap();
+    bench_backend(c, "local", &rt, &storage);
+}
+
+criterion_group!(benches, memory, local);
+criterion_main!(benches);
@@ -0,0 +1,295 @@
+//! Zip archives of several files
+//!
+//! Archives are written entry by entry as the blobs are read, so a download of many files
+//! never holds a whole file in memory and can start before the last one is fetched.
+//! Uploaded archives can be unpacked into individual files the same way, one entry at a time.
+
+use crate::{File, MetadataUpdate, Result, StorageError, TransactionalStorageService};
+use async_zip::base::read::mem::ZipFileReader;
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipDateTime, ZipEntryBuilder};
+use std::collections::HashSet;
+use tokio::io::{AsyncReadExt, AsyncWrite};
+use tokio_util::compat::{FuturesAsyncReadCompatExt, FuturesAsyncWriteCompatExt};
+
+/// Most files one archive may hold
+pub const MAX_ARCHIVE_FILES: usize = 1000;
+
+/// Most bytes an uploaded archive may unpack to, whatever its compressed size
+pub const MAX_EXTRACTED_SIZE: u64 = 1 << 30;
+
+/// A file and the path it gets inside the archive
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub file: File,
+}
+
+/// Give every file a unique path inside an archive
+///
+/// Files keep their folder structure below `base_folder` (the whole path when `None`). Names
+/// that would collide get a counter, e.g. `report (1).pdf`.
+pub fn entry_paths(files: Vec<File>, base_folder: Option<&str>) -> Vec<ArchiveEntry> {
+    let mut taken = HashSet::new();
+    files
+        .into_iter()
+        .map(|file| {
+            let folder = match (file.folder.as_deref(), base_folder) {
+                (Some(folder), Some(base)) if folder == base => None,
+                (Some(folder), Some(base)) => folder.strip_prefix(base).and_then(|rest| rest.strip_prefix('/')),
+                (folder, None) => folder,
+                (None, Some(_)) => None,
+            };
+            let name = file.original_name.replace(['/', '\\'], "_");
+            let path = match folder {
+                Some(folder) => format!("{}/{}", folder, name),
+                None => name,
+            };
+
+            let mut unique = path.clone();
+            let mut counter = 1;
+            while !taken.insert(unique.clone()) {
+                unique = numbered(&path, counter);
+                counter += 1;
+            }
+            ArchiveEntry { path: unique, file }
+        })
+        .collect()
+}
+
+/// `a/report.pdf` becomes `a/report (n).pdf`
+fn numbered(path: &str, n: usize) -> String {
+    let name_start = path.rfind('/').map(|i| i + 1).unwrap_or(0);
+    match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = name_start + dot;
+            format!("{} ({}){}", &path[..dot], n, &path[dot..])
+        }
+        _ => format!("{} ({})", path, n),
+    }
+}
+
+/// Content that is already compressed is stored as is instead of being deflated again
+fn compression_for(file: &File) -> Compression {
+    let mime = file.content_type().unwrap_or_default();
+    let compressed = mime.starts_with("image/")
+        || mime.starts_with("video/")
+        || mime.starts_with("audio/")
+        || matches!(mime, "application/zip" | "application/gzip" | "application/x-7z-compressed" | "application/pdf");
+    if compressed { Compression::Stored } else { Compression::Deflate }
+}
+
+/// Write a zip of `entries` to `writer`, reading each file with the owner's permissions
+/// Each file counts as a download, exactly as if it had been fetched on its own
+pub async fn write_zip<W>(
+    service: &TransactionalStorageService,
+    user_id: i64,
+    entries: &[ArchiveEntry],
+    writer: W,
+) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let zip_error = |e: async_zip::error::ZipError| StorageError::StorageError(format!("Failed to write archive: {}", e));
+
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    for entry in entries {
+        let file_id = entry.file.id.as_deref().unwrap_or_default();
+        let (_, mut reader) = service.retrieve_with_metadata(file_id, user_id).await?;
+        let builder = ZipEntryBuilder::new(entry.path.clone().into(), compression_for(&entry.file))
+            .last_modification_date(ZipDateTime::from_chrono(&entry.file.created_at));
+
+        // Copied through as it is read; the sizes and CRC follow the data in a descriptor
+        let mut entry_writer = zip.write_entry_stream(builder).await.map_err(zip_error)?.compat_write();
+        tokio::io::copy(&mut reader, &mut entry_writer).await?;
+        entry_writer.into_inner().close().await.map_err(zip_error)?;
+    }
+    zip.close().await.map_err(zip_error)?;
+    Ok(())
+}
+
+/// Whether an upload with this name or declared type should be treated as a zip archive
+pub fn is_zip(original_name: &str, mime_type: Option<&str>) -> bool {
+    original_name.to_lowercase().ends_with(".zip")
+        || matches!(mime_type, Some("application/zip" | "application/x-zip-compressed"))
+}
+
+/// Split an entry path into its folder and file name, skipping directories and the metadata
+/// archivers add (`__MACOSX/`, `.DS_Store`)
+fn entry_location(path: &str) -> Option<(Option<&str>, &str)> {
+    let path = path.trim_matches('/');
+    let (folder, name) = match path.rsplit_once('/') {
+        Some((folder, name)) => (Some(folder), name),
+        None => (None, path),
+    };
+    let skipped = name.is_empty()
+        || name == ".DS_Store"
+        || path.split('/').next() == Some("__MACOSX");
+    (!skipped).then_some((folder, name))
+}
+
+/// Unpack a zip into individual files owned by `user_id`
+///
+/// Each entry goes through the same quota and upload policy checks as a regular upload, and
+/// lands in the folder it has inside the archive. All or nothing: if any entry is rejected,
+/// the files already unpacked are removed again.
+pub async fn extract_zip(service: &TransactionalStorageService, user_id: i64, data: Vec<u8>) -> Result<Vec<File>> {
+    let zip_error = |e: async_zip::error::ZipError| StorageError::InvalidInput(format!("Invalid zip archive: {}", e));
+
+    let zip = ZipFileReader::new(data).await.map_err(zip_error)?;
+    let entries = zip.file().entries();
+
+    // Refuse oversized archives before unpacking anything, going by the sizes they declare
+    let mut declared = 0u64;
+    let mut count = 0usize;
+    for entry in entries {
+        if entry.dir().map_err(zip_error)? {
+            continue;
+        }
+        count += 1;
+        declared = declared.saturating_add(entry.uncompressed_size());
+    }
+    if count > MAX_ARCHIVE_FILES {
+        return Err(StorageError::InvalidInput(format!("An archive holds at most {} files", MAX_ARCHIVE_FILES)));
+    }
+    if declared > MAX_EXTRACTED_SIZE {
+        return Err(StorageError::InvalidInput(format!("Archive unpacks to more than {} bytes", MAX_EXTRACTED_SIZE)));
+    }
+
+    let mut stored = Vec::new();
+    let mut result = Ok(());
+    for index in 0..entries.len() {
+        result = extract_entry(service, user_id, &zip, index).await.map(|file| {
+            if let Some(file) = file {
+                stored.push(file);
+            }
+        });
+        if result.is_err() {
+            break;
+        }
+    }
+
+    if let Err(e) = result {
+        for file in &stored {
+            if let Some(file_id) = &file.id {
+                if let Err(e) = service.force_purge_file(file_id).await {
+                    eprintln!("⚠️  Failed to roll back extracted file {}: {}", file_id, e);
+                }
+            }
+        }
+        return Err(e);
+    }
+    Ok(stored)
+}
+
+/// Store one entry of an archive; `None` for directories and skipped entries
+async fn extract_entry(
+    service: &TransactionalStorageService,
+    user_id: i64,
+    zip: &ZipFileReader,
+    index: usize,
+) -> Result<Option<File>> {
+    let zip_error = |e: async_zip::error::ZipError| StorageError::InvalidInput(format!("Invalid zip archive: {}", e));
+
+    let entry = &zip.file().entries()[index];
+    if entry.dir().map_err(zip_error)? {
+        return Ok(None);
+    }
+    let path = entry.filename().as_str().map_err(zip_error)?;
+    let Some((folder, name)) = entry_location(path) else {
+        return Ok(None);
+    };
+    let declared_size = entry.uncompressed_size();
+
+    // Never inflate past the declared size, so a lying header can't unpack a zip bomb
+    let reader = zip.reader_without_entry(index).await.map_err(zip_error)?;
+    let reader = reader.compat().take(declared_size + 1);
+    // Archives carry no content types; the one detected from the bytes is recorded
+    let file = service.store_stream_with_metadata(reader, name, user_id, None).await?;
+
+    let file_id = file.id.clone().unwrap_or_default();
+    if file.size as u64 != declared_size {
+        let _ = service.force_purge_file(&file_id).await;
+        return Err(StorageError::InvalidInput(format!("Archive entry '{}' is corrupt", path)));
+    }
+
+    match folder {
+        Some(folder) => {
+            let update = MetadataUpdate { folder: Some(folder.to_string()), ..Default::default() };
+            match service.update_metadata(&file_id, user_id, update, None).await {
+                Ok(file) => Ok(Some(file)),
+                Err(e) => {
+                    let _ = service.force_purge_file(&file_id).await;
+                    Err(e)
+                }
+            }
+        }
+        None => Ok(Some(file)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, folder: Option<&str>) -> File {
+        let mut file = File::new(
+            name.to_string(),
+            1,
+            name.to_string(),
+            name.to_string(),
+            1,
+            None,
+            "./storage".to_string(),
+            None,
+        );
+        file.folder = folder.map(str::to_string);
+        file
+    }
+
+    fn paths(entries: &[ArchiveEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_entry_paths_keep_folders() {
+        let files = vec![
+            file("a.txt", None),
+            file("b.txt", Some("reports")),
+            file("c.txt", Some("reports/2025")),
+        ];
+        let entries = entry_paths(files.clone(), None);
+        assert_eq!(paths(&entries), ["a.txt", "reports/b.txt", "reports/2025/c.txt"]);
+
+        // Paths are relative to an archived folder
+        let entries = entry_paths(files[1..].to_vec(), Some("reports"));
+        assert_eq!(paths(&entries), ["b.txt", "2025/c.txt"]);
+    }
+
+    #[test]
+    fn test_entry_location() {
+        assert_eq!(entry_location("photo.jpg"), Some((None, "photo.jpg")));
+        assert_eq!(entry_location("album/2025/photo.jpg"), Some((Some("album/2025"), "photo.jpg")));
+        assert_eq!(entry_location("album/"), None);
+        assert_eq!(entry_location("album/.DS_Store"), None);
+        assert_eq!(entry_location("__MACOSX/album/._photo.jpg"), None);
+    }
+
+    #[test]
+    fn test_entry_paths_are_unique() {
+        let files = vec![
+            file("report.pdf", None),
+            file("report.pdf", None),
+            file("report.pdf", None),
+            file("notes", None),
+            file("notes", None),
+            file(".env", None),
+            file(".env", None),
+        ];
+        let entries = entry_paths(files, None);
+        assert_eq!(
+            paths(&entries),
+            ["report.pdf", "report (1).pdf", "report (2).pdf", "notes", "notes (1)", ".env", ".env (1)"]
+        );
+    }
+}
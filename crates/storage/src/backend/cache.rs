@@ -0,0 +1,399 @@
+use super::{check_read_offset, BlobReader, StorageBackend};
+use crate::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Size limits and freshness of cached blobs
+#[derive(Debug, Clone)]
+pub struct CachePolicy {
+    /// Total size of the cached blobs; the least recently read are evicted past it
+    pub max_bytes: u64,
+    /// Largest blob that is cached
+    pub max_entry_bytes: u64,
+    /// How long a cached blob is served before it is read from the backend again
+    pub ttl: Duration,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024 * 1024,
+            max_entry_bytes: 256 * 1024,
+            ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Wraps a backend, keeping small blobs that are read in memory
+///
+/// Blobs are cached by key as they are read, and dropped as soon as they are written, moved, or
+/// deleted through this backend. Writes made by other processes sharing the backend aren't seen
+/// until the cached copy is older than the policy's `ttl`.
+pub struct CachingBackend {
+    inner: Arc<dyn StorageBackend>,
+    cache: Arc<Mutex<Lru>>,
+}
+
+impl CachingBackend {
+    pub fn new(inner: Arc<dyn StorageBackend>, policy: CachePolicy) -> Self {
+        Self {
+            inner,
+            cache: Arc::new(Mutex::new(Lru::new(policy))),
+        }
+    }
+
+    fn cached(&self, key: &str) -> Option<Arc<[u8]>> {
+        self.cache.lock().unwrap().get(key, Instant::now())
+    }
+
+    fn invalidate(&self, key: &str) {
+        self.cache.lock().unwrap().invalidate(key);
+    }
+
+    /// Run a write to `keys`, dropping their cached copies before and after it so a read that
+    /// overlaps the write can't cache what was there before
+    async fn write<T>(&self, keys: &[&str], write: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+        for key in keys {
+            self.invalidate(key);
+        }
+        let result = write.await;
+        for key in keys {
+            self.invalidate(key);
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl StorageBackend for CachingBackend {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.write(&[key], self.inner.put(key, data)).await
+    }
+
+    async fn put_stream(&self, key: &str, reader: &mut (dyn AsyncRead + Unpin + Send)) -> Result<u64> {
+        self.write(&[key], self.inner.put_stream(key, reader)).await
+    }
+
+    async fn append(&self, key: &str, data: &[u8]) -> Result<u64> {
+        self.write(&[key], self.inner.append(key, data)).await
+    }
+
+    async fn write_at(&self, key: &str, offset: u64, data: &[u8]) -> Result<u64> {
+        self.write(&[key], self.inner.write_at(key, offset, data)).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        self.write(&[from, to], self.inner.rename(from, to)).await
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        self.write(&[to], self.inner.copy(from, to)).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        if let Some(data) = self.cached(key) {
+            return Ok(data.to_vec());
+        }
+        let generation = self.cache.lock().unwrap().generation;
+        let data = self.inner.get(key).await?;
+        self.cache.lock().unwrap().insert(key, &data, generation);
+        Ok(data)
+    }
+
+    /// Served from memory when the blob is cached; partial reads don't cache it
+    async fn get_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let Some(data) = self.cached(key) else {
+            return self.inner.get_range(key, offset, len).await;
+        };
+        check_read_offset(key, offset, data.len() as u64)?;
+        let start = offset as usize;
+        let end = offset.saturating_add(len).min(data.len() as u64) as usize;
+        Ok(data[start..end].to_vec())
+    }
+
+    /// A blob that isn't cached is streamed from the backend and cached once it has been read to
+    /// the end, if it turned out small enough
+    async fn get_stream(&self, key: &str) -> Result<BlobReader> {
+        if let Some(data) = self.cached(key) {
+            return Ok(Box::new(std::io::Cursor::new(data)));
+        }
+        let (generation, max_entry_bytes) = {
+            let cache = self.cache.lock().unwrap();
+            (cache.generation, cache.policy.max_entry_bytes)
+        };
+        let reader = self.inner.get_stream(key).await?;
+        Ok(Box::new(CachingReader {
+            inner: reader,
+            key: key.to_string(),
+            buffer: Some(Vec::new()),
+            max_entry_bytes,
+            generation,
+            cache: Arc::clone(&self.cache),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.write(&[key], self.inner.delete(key)).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        self.inner.exists(key).await
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        self.inner.list().await
+    }
+
+    async fn size(&self, key: &str) -> Result<u64> {
+        match self.cached(key) {
+            Some(data) => Ok(data.len() as u64),
+            None => self.inner.size(key).await,
+        }
+    }
+
+    async fn modified(&self, key: &str) -> Result<Option<DateTime<Utc>>> {
+        self.inner.modified(key).await
+    }
+
+    fn location(&self) -> String {
+        self.inner.location()
+    }
+}
+
+/// Least-recently-used blobs, by key
+struct Lru {
+    policy: CachePolicy,
+    entries: HashMap<String, Entry>,
+    /// Keys by when they were last read, oldest first
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: u64,
+    /// Bumped on every write, so reads that started before one don't cache what they read
+    generation: u64,
+}
+
+struct Entry {
+    data: Arc<[u8]>,
+    cached_at: Instant,
+    used: u64,
+}
+
+impl Lru {
+    fn new(policy: CachePolicy) -> Self {
+        Self {
+            policy,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            generation: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str, now: Instant) -> Option<Arc<[u8]>> {
+        let entry = self.entries.get(key)?;
+        if now.saturating_duration_since(entry.cached_at) >= self.policy.ttl {
+            self.remove(key);
+            return None;
+        }
+
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.used);
+        entry.used = self.tick;
+        self.order.insert(self.tick, key.to_string());
+        Some(Arc::clone(&entry.data))
+    }
+
+    /// Cache a blob read while the cache was at `generation`, unless it was written since
+    fn insert(&mut self, key: &str, data: &[u8], generation: u64) {
+        let size = data.len() as u64;
+        if generation != self.generation || size > self.policy.max_entry_bytes || size > self.policy.max_bytes {
+            return;
+        }
+
+        self.remove(key);
+        while self.bytes + size > self.policy.max_bytes {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.bytes -= entry.data.len() as u64;
+            }
+        }
+
+        self.tick += 1;
+        self.order.insert(self.tick, key.to_string());
+        self.entries.insert(key.to_string(), Entry { data: data.into(), cached_at: Instant::now(), used: self.tick });
+        self.bytes += size;
+    }
+
+    fn invalidate(&mut self, key: &str) {
+        self.generation += 1;
+        self.remove(key);
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.used);
+            self.bytes -= entry.data.len() as u64;
+        }
+    }
+}
+
+/// Reader adapter that keeps what is read through it, and caches it when the end is reached
+/// Stops keeping bytes once there are more than the cache takes.
+struct CachingReader {
+    inner: BlobReader,
+    key: String,
+    buffer: Option<Vec<u8>>,
+    max_entry_bytes: u64,
+    generation: u64,
+    cache: Arc<Mutex<Lru>>,
+}
+
+impl AsyncRead for CachingReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let already_filled = buf.filled().len();
+        let wanted_more = buf.remaining() > 0;
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &poll {
+            let this = &mut *self;
+            let new_bytes = &buf.filled()[already_filled..];
+            if !new_bytes.is_empty() {
+                if let Some(buffer) = &mut this.buffer {
+                    if (buffer.len() + new_bytes.len()) as u64 > this.max_entry_bytes {
+                        this.buffer = None;
+                    } else {
+                        buffer.extend_from_slice(new_bytes);
+                    }
+                }
+            } else if wanted_more {
+                // End of stream: the whole blob has been read
+                if let Some(buffer) = this.buffer.take() {
+                    this.cache.lock().unwrap().insert(&this.key, &buffer, this.generation);
+                }
+            }
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryBackend;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::io::AsyncReadExt;
+
+    /// Memory backend that counts reads reaching it
+    struct CountingBackend {
+        inner: MemoryBackend,
+        reads: AtomicU32,
+    }
+
+    #[async_trait]
+    impl StorageBackend for CountingBackend {
+        async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+            self.inner.put(key, data).await
+        }
+
+        async fn get(&self, key: &str) -> Result<Vec<u8>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.get(key).await
+        }
+
+        async fn get_stream(&self, key: &str) -> Result<BlobReader> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.get_stream(key).await
+        }
+
+        async fn delete(&self, key: &str) -> Result<()> {
+            self.inner.delete(key).await
+        }
+
+        async fn exists(&self, key: &str) -> Result<bool> {
+            self.inner.exists(key).await
+        }
+
+        async fn list(&self) -> Result<Vec<String>> {
+            self.inner.list().await
+        }
+
+        fn location(&self) -> String {
+            self.inner.location()
+        }
+    }
+
+    async fn read_all(mut reader: BlobReader) -> Vec<u8> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await.unwrap();
+        data
+    }
+
+    fn caching(policy: CachePolicy) -> (Arc<CountingBackend>, CachingBackend) {
+        let counting = Arc::new(CountingBackend { inner: MemoryBackend::new(), reads: AtomicU32::new(0) });
+        let backend = CachingBackend::new(counting.clone(), policy);
+        (counting, backend)
+    }
+
+    #[tokio::test]
+    async fn test_small_blobs_are_served_from_memory() {
+        let (counting, backend) = caching(CachePolicy::default());
+        backend.put("icon.png", b"icon").await.unwrap();
+
+        assert_eq!(read_all(backend.get_stream("icon.png").await.unwrap()).await, b"icon");
+        assert_eq!(read_all(backend.get_stream("icon.png").await.unwrap()).await, b"icon");
+        assert_eq!(backend.get("icon.png").await.unwrap(), b"icon");
+        assert_eq!(backend.get_range("icon.png", 1, 2).await.unwrap(), b"co");
+        assert_eq!(counting.reads.load(Ordering::SeqCst), 1);
+
+        // Writes drop the cached copy
+        backend.put("icon.png", b"new icon").await.unwrap();
+        assert_eq!(backend.get("icon.png").await.unwrap(), b"new icon");
+        assert_eq!(counting.reads.load(Ordering::SeqCst), 2);
+
+        backend.delete("icon.png").await.unwrap();
+        assert!(backend.get("icon.png").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_large_blobs_are_not_cached() {
+        let (counting, backend) = caching(CachePolicy { max_entry_bytes: 4, ..CachePolicy::default() });
+        backend.put("video.mp4", b"too large").await.unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(read_all(backend.get_stream("video.mp4").await.unwrap()).await, b"too large");
+        }
+        assert_eq!(counting.reads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_least_recently_used_are_evicted() {
+        let mut lru = Lru::new(CachePolicy { max_bytes: 8, ..CachePolicy::default() });
+        let now = Instant::now();
+        lru.insert("a", b"aaaa", 0);
+        lru.insert("b", b"bbbb", 0);
+        assert!(lru.get("a", now).is_some());
+
+        // "b" was read least recently
+        lru.insert("c", b"cccc", 0);
+        assert!(lru.get("b", now).is_none());
+        assert!(lru.get("a", now).is_some());
+        assert_eq!(lru.bytes, 8);
+
+        // Reads that started before a write aren't cached
+        lru.invalidate("a");
+        lru.insert("a", b"old!", 0);
+        assert!(lru.get("a", now).is_none());
+
+        // Expired blobs are read again
+        assert!(lru.get("c", now + Duration::from_secs(301)).is_none());
+    }
+}
@@ -0,0 +1,269 @@
+use super::{http_status_error, StorageBackend};
+use crate::{Result, StorageError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use gcp_auth::{CustomServiceAccount, TokenProvider};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// OAuth scope granting read/write access to objects
+const GCS_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+
+const GCS_API_BASE: &str = "https://storage.googleapis.com";
+
+/// Connection settings for a Google Cloud Storage bucket
+#[derive(Debug, Clone)]
+pub struct GcsBackendConfig {
+    /// Bucket name
+    pub bucket: String,
+    /// Path to a service-account JSON key file
+    /// When unset, Application Default Credentials are used (GOOGLE_APPLICATION_CREDENTIALS, metadata server, gcloud)
+    pub credentials_path: Option<String>,
+    /// Optional object name prefix so several deployments can share a bucket
+    pub prefix: Option<String>,
+}
+
+/// Backend storing blobs as objects in a Google Cloud Storage bucket, via the JSON API
+pub struct GcsBackend {
+    client: Client,
+    auth: Arc<dyn TokenProvider>,
+    bucket: String,
+    prefix: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListResponse {
+    #[serde(default)]
+    items: Vec<ObjectResource>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectResource {
+    name: String,
+    /// Object size in bytes (the JSON API encodes it as a string)
+    #[serde(default)]
+    size: Option<String>,
+    /// When the object was last modified, as RFC 3339
+    #[serde(default)]
+    updated: Option<String>,
+}
+
+impl GcsBackend {
+    /// Create a new GCS backend, loading service-account credentials
+    pub async fn new(config: GcsBackendConfig) -> Result<Self> {
+        let auth: Arc<dyn TokenProvider> = match &config.credentials_path {
+            Some(path) => Arc::new(
+                CustomServiceAccount::from_file(path)
+                    .map_err(|e| StorageError::StorageError(format!("Invalid GCS credentials '{}': {}", path, e)))?,
+            ),
+            None => gcp_auth::provider()
+                .await
+                .map_err(|e| StorageError::StorageError(format!("No GCS credentials found: {}", e)))?,
+        };
+
+        let prefix = config.prefix
+            .map(|p| p.trim_matches('/').to_string())
+            .unwrap_or_default();
+
+        Ok(Self {
+            client: Client::new(),
+            auth,
+            bucket: config.bucket,
+            prefix,
+        })
+    }
+
+    /// Full object name for a stored blob, including the configured prefix
+    fn object_name(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.prefix, key)
+        }
+    }
+
+    /// URL of an object resource: `/storage/v1/b/{bucket}/o/{name}`
+    fn object_url(&self, key: &str) -> Url {
+        let mut url = Url::parse(GCS_API_BASE).expect("valid GCS base URL");
+        url.path_segments_mut()
+            .expect("GCS base URL can have path segments")
+            .extend(["storage", "v1", "b", self.bucket.as_str(), "o", self.object_name(key).as_str()]);
+        url
+    }
+
+    /// URL of the bucket's object collection: `/storage/v1/b/{bucket}/o`
+    fn objects_url(&self) -> Url {
+        let mut url = Url::parse(GCS_API_BASE).expect("valid GCS base URL");
+        url.path_segments_mut()
+            .expect("GCS base URL can have path segments")
+            .extend(["storage", "v1", "b", self.bucket.as_str(), "o"]);
+        url
+    }
+
+    async fn access_token(&self) -> Result<String> {
+        let token = self.auth.token(&[GCS_SCOPE]).await
+            .map_err(|e| StorageError::Transient(format!("Failed to obtain GCS access token: {}", e)))?;
+        Ok(token.as_str().to_string())
+    }
+
+    /// Send a request with a bearer token, classifying transport and status failures
+    async fn send(&self, operation: &str, key: &str, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let token = self.access_token().await?;
+        let response = request
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|e| StorageError::Transient(format!("GCS {} failed for '{}': {}", operation, key, e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            Err(http_status_error("GCS", operation, key, status.as_u16()))
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for GcsBackend {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let mut url = Url::parse(GCS_API_BASE).expect("valid GCS base URL");
+        url.path_segments_mut()
+            .expect("GCS base URL can have path segments")
+            .extend(["upload", "storage", "v1", "b", self.bucket.as_str(), "o"]);
+        url.query_pairs_mut()
+            .append_pair("uploadType", "media")
+            .append_pair("name", &self.object_name(key));
+
+        let request = self.client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+            .body(data.to_vec());
+
+        self.send("put", key, request).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let mut url = self.object_url(key);
+        url.query_pairs_mut().append_pair("alt", "media");
+
+        let response = self.send("get", key, self.client.get(url)).await?;
+        let bytes = response.bytes().await
+            .map_err(|e| StorageError::Transient(format!("GCS get failed for '{}': {}", key, e)))?;
+
+        Ok(bytes.to_vec())
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let mut url = self.object_url(key);
+        url.query_pairs_mut().append_pair("alt", "media");
+
+        let range = format!("bytes={}-{}", offset, offset.saturating_add(len - 1));
+        let response = self.send("get", key, self.client.get(url).header(reqwest::header::RANGE, range)).await?;
+        let bytes = response.bytes().await
+            .map_err(|e| StorageError::Transient(format!("GCS get failed for '{}': {}", key, e)))?;
+
+        Ok(bytes.to_vec())
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        // copyTo copies server-side: /storage/v1/b/{bucket}/o/{from}/copyTo/b/{bucket}/o/{to}
+        let mut url = self.object_url(from);
+        url.path_segments_mut()
+            .expect("GCS base URL can have path segments")
+            .extend(["copyTo", "b", self.bucket.as_str(), "o", self.object_name(to).as_str()]);
+
+        self.send("copy", from, self.client.post(url).body(Vec::new())).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.send("delete", key, self.client.delete(self.object_url(key))).await?;
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match self.send("head", key, self.client.get(self.object_url(key))).await {
+            Ok(_) => Ok(true),
+            Err(StorageError::FileNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn size(&self, key: &str) -> Result<u64> {
+        let response = self.send("head", key, self.client.get(self.object_url(key))).await?;
+        let object: ObjectResource = response.json().await
+            .map_err(|e| StorageError::StorageError(format!("Invalid GCS object metadata: {}", e)))?;
+
+        object.size
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| StorageError::StorageError(format!("GCS object '{}' has no size", key)))
+    }
+
+    async fn modified(&self, key: &str) -> Result<Option<DateTime<Utc>>> {
+        let response = self.send("head", key, self.client.get(self.object_url(key))).await?;
+        let object: ObjectResource = response.json().await
+            .map_err(|e| StorageError::StorageError(format!("Invalid GCS object metadata: {}", e)))?;
+
+        Ok(object.updated
+            .and_then(|updated| DateTime::parse_from_rfc3339(&updated).ok())
+            .map(|updated| updated.with_timezone(&Utc)))
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let list_prefix = if self.prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", self.prefix)
+        };
+
+        let mut names = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut url = self.objects_url();
+            {
+                let mut query = url.query_pairs_mut();
+                query.append_pair("fields", "items(name),nextPageToken");
+                if !list_prefix.is_empty() {
+                    query.append_pair("prefix", &list_prefix);
+                }
+                if let Some(token) = &page_token {
+                    query.append_pair("pageToken", token);
+                }
+            }
+
+            let response = self.send("list", &list_prefix, self.client.get(url)).await?;
+            let page: ListResponse = response.json().await
+                .map_err(|e| StorageError::StorageError(format!("Invalid GCS list response: {}", e)))?;
+
+            names.extend(
+                page.items
+                    .into_iter()
+                    .map(|object| object.name.trim_start_matches(&list_prefix).to_string()),
+            );
+
+            match page.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn location(&self) -> String {
+        if self.prefix.is_empty() {
+            format!("gs://{}", self.bucket)
+        } else {
+            format!("gs://{}/{}", self.bucket, self.prefix)
+        }
+    }
+}
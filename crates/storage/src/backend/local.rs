@@ -0,0 +1,225 @@
+use super::{check_read_offset, check_write_offset, validate_blob_key, BlobReader, StorageBackend};
+use crate::{Result, StorageError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use std::io::SeekFrom;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+/// Backend storing blobs as files under a base directory on the local filesystem
+pub struct LocalBackend {
+    base_path: PathBuf,
+}
+
+impl LocalBackend {
+    /// Create a new local backend, creating the base directory if it doesn't exist
+    pub async fn new<P: AsRef<Path>>(base_path: P) -> Result<Self> {
+        let base_path = base_path.as_ref().to_path_buf();
+
+        if !base_path.exists() {
+            fs::create_dir_all(&base_path).await?;
+        }
+
+        Ok(Self { base_path })
+    }
+
+    /// Get the base storage path
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
+    }
+
+    /// Path of a blob on disk, rejecting keys that could escape the base directory
+    fn path_for(&self, key: &str) -> Result<PathBuf> {
+        validate_blob_key(key)?;
+        Ok(self.base_path.join(key))
+    }
+
+    /// Path of a blob about to be written, creating its tenant directory if needed
+    async fn prepare_path(&self, key: &str) -> Result<PathBuf> {
+        let file_path = self.path_for(key)?;
+
+        // Tenant-scoped keys live in a subdirectory per tenant
+        if let Some(parent) = file_path.parent() {
+            if !parent.exists() {
+                fs::create_dir_all(parent).await?;
+            }
+        }
+
+        Ok(file_path)
+    }
+
+    /// Resolve a key to the path of an existing blob; keys must match the stored name exactly
+    fn resolve(&self, key: &str) -> Result<Option<PathBuf>> {
+        let file_path = self.path_for(key)?;
+        Ok(file_path.is_file().then_some(file_path))
+    }
+
+    /// Get filesystem metadata for a stored blob
+    pub async fn metadata(&self, key: &str) -> Result<std::fs::Metadata> {
+        let file_path = self.path_for(key)?;
+
+        if !file_path.exists() {
+            return Err(StorageError::FileNotFound(key.to_string()));
+        }
+
+        Ok(fs::metadata(&file_path).await?)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalBackend {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let mut file = fs::File::create(self.prepare_path(key).await?).await?;
+        file.write_all(data).await?;
+        file.flush().await?;
+
+        Ok(())
+    }
+
+    async fn put_stream(&self, key: &str, reader: &mut (dyn AsyncRead + Unpin + Send)) -> Result<u64> {
+        let file_path = self.prepare_path(key).await?;
+        let mut file = fs::File::create(&file_path).await?;
+
+        let written = async {
+            let written = tokio::io::copy(reader, &mut file).await?;
+            file.flush().await?;
+            Ok::<_, std::io::Error>(written)
+        }.await;
+
+        match written {
+            Ok(written) => Ok(written),
+            Err(e) => {
+                // Don't leave a truncated blob behind
+                drop(file);
+                let _ = fs::remove_file(&file_path).await;
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn append(&self, key: &str, data: &[u8]) -> Result<u64> {
+        let file_path = self.resolve(key)?
+            .ok_or_else(|| StorageError::FileNotFound(key.to_string()))?;
+
+        let mut file = fs::OpenOptions::new().append(true).open(&file_path).await?;
+        file.write_all(data).await?;
+        file.flush().await?;
+
+        Ok(file.metadata().await?.len())
+    }
+
+    async fn write_at(&self, key: &str, offset: u64, data: &[u8]) -> Result<u64> {
+        let file_path = self.resolve(key)?
+            .ok_or_else(|| StorageError::FileNotFound(key.to_string()))?;
+
+        let mut file = fs::OpenOptions::new().write(true).open(&file_path).await?;
+        check_write_offset(key, offset, file.metadata().await?.len())?;
+
+        file.seek(SeekFrom::Start(offset)).await?;
+        file.write_all(data).await?;
+        file.flush().await?;
+
+        Ok(file.metadata().await?.len())
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let from_path = self.resolve(from)?
+            .ok_or_else(|| StorageError::FileNotFound(from.to_string()))?;
+
+        fs::rename(&from_path, self.prepare_path(to).await?).await?;
+        Ok(())
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        let from_path = self.resolve(from)?
+            .ok_or_else(|| StorageError::FileNotFound(from.to_string()))?;
+
+        fs::copy(&from_path, self.prepare_path(to).await?).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let file_path = self.resolve(key)?
+            .ok_or_else(|| StorageError::FileNotFound(key.to_string()))?;
+
+        let mut file = fs::File::open(&file_path).await?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await?;
+
+        Ok(data)
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let file_path = self.resolve(key)?
+            .ok_or_else(|| StorageError::FileNotFound(key.to_string()))?;
+
+        let mut file = fs::File::open(&file_path).await?;
+        check_read_offset(key, offset, file.metadata().await?.len())?;
+
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut data = Vec::new();
+        file.take(len).read_to_end(&mut data).await?;
+
+        Ok(data)
+    }
+
+    async fn get_stream(&self, key: &str) -> Result<BlobReader> {
+        let file_path = self.resolve(key)?
+            .ok_or_else(|| StorageError::FileNotFound(key.to_string()))?;
+
+        Ok(Box::new(fs::File::open(&file_path).await?))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let file_path = self.resolve(key)?
+            .ok_or_else(|| StorageError::FileNotFound(key.to_string()))?;
+
+        fs::remove_file(&file_path).await?;
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.resolve(key)?.is_some())
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+        let mut entries = fs::read_dir(&self.base_path).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+
+            if file_type.is_file() {
+                files.push(name);
+            } else if file_type.is_dir() {
+                // Tenant directory: list its blobs as `tenant/stored_name`
+                let mut tenant_entries = fs::read_dir(entry.path()).await?;
+                while let Some(tenant_entry) = tenant_entries.next_entry().await? {
+                    if tenant_entry.file_type().await?.is_file() {
+                        if let Some(stored_name) = tenant_entry.file_name().to_str() {
+                            files.push(format!("{}/{}", name, stored_name));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    async fn size(&self, key: &str) -> Result<u64> {
+        Ok(self.metadata(key).await?.len())
+    }
+
+    async fn modified(&self, key: &str) -> Result<Option<DateTime<Utc>>> {
+        Ok(self.metadata(key).await?.modified().ok().map(DateTime::from))
+    }
+
+    fn location(&self) -> String {
+        self.base_path.to_string_lossy().to_string()
+    }
+}
@@ -0,0 +1,185 @@
+use super::{check_read_offset, validate_blob_key, StorageBackend};
+use crate::{Result, StorageError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+#[derive(Debug)]
+struct Blob {
+    data: Vec<u8>,
+    modified: DateTime<Utc>,
+}
+
+impl Blob {
+    fn new(data: Vec<u8>) -> Self {
+        Self { data, modified: Utc::now() }
+    }
+}
+
+/// Backend keeping blobs in a HashMap, for tests that shouldn't touch disk
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    blobs: RwLock<HashMap<String, Blob>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of blobs currently stored
+    pub fn len(&self) -> usize {
+        self.blobs.read().unwrap().len()
+    }
+
+    /// Whether no blobs are stored
+    pub fn is_empty(&self) -> bool {
+        self.blobs.read().unwrap().is_empty()
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryBackend {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        validate_blob_key(key)?;
+        self.blobs.write().unwrap().insert(key.to_string(), Blob::new(data.to_vec()));
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        validate_blob_key(key)?;
+        self.blobs.read().unwrap()
+            .get(key)
+            .map(|blob| blob.data.clone())
+            .ok_or_else(|| StorageError::FileNotFound(key.to_string()))
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        validate_blob_key(key)?;
+        let blobs = self.blobs.read().unwrap();
+        let blob = blobs.get(key)
+            .ok_or_else(|| StorageError::FileNotFound(key.to_string()))?;
+        check_read_offset(key, offset, blob.data.len() as u64)?;
+
+        let end = offset.saturating_add(len).min(blob.data.len() as u64) as usize;
+        Ok(blob.data[offset as usize..end].to_vec())
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        validate_blob_key(from)?;
+        validate_blob_key(to)?;
+        let mut blobs = self.blobs.write().unwrap();
+        let blob = blobs.remove(from)
+            .ok_or_else(|| StorageError::FileNotFound(from.to_string()))?;
+        blobs.insert(to.to_string(), blob);
+        Ok(())
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        validate_blob_key(from)?;
+        validate_blob_key(to)?;
+        let mut blobs = self.blobs.write().unwrap();
+        let data = blobs.get(from)
+            .map(|blob| blob.data.clone())
+            .ok_or_else(|| StorageError::FileNotFound(from.to_string()))?;
+        blobs.insert(to.to_string(), Blob::new(data));
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        validate_blob_key(key)?;
+        self.blobs.write().unwrap()
+            .remove(key)
+            .map(|_| ())
+            .ok_or_else(|| StorageError::FileNotFound(key.to_string()))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        validate_blob_key(key)?;
+        Ok(self.blobs.read().unwrap().contains_key(key))
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        Ok(self.blobs.read().unwrap().keys().cloned().collect())
+    }
+
+    async fn modified(&self, key: &str) -> Result<Option<DateTime<Utc>>> {
+        validate_blob_key(key)?;
+        self.blobs.read().unwrap()
+            .get(key)
+            .map(|blob| Some(blob.modified))
+            .ok_or_else(|| StorageError::FileNotFound(key.to_string()))
+    }
+
+    fn location(&self) -> String {
+        "memory://".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StorageService;
+
+    #[tokio::test]
+    async fn test_store_retrieve_delete() {
+        let storage = StorageService::in_memory();
+
+        let metadata = storage.store(b"Hello, memory!", "hello.txt", None).await.unwrap();
+        assert_eq!(storage.retrieve(&metadata.stored_name).await.unwrap(), b"Hello, memory!");
+        assert_eq!(storage.list_files().await.unwrap(), vec![metadata.stored_name.clone()]);
+
+        storage.delete(&metadata.stored_name).await.unwrap();
+        assert!(!storage.exists(&metadata.stored_name).await);
+        assert!(matches!(
+            storage.retrieve(&metadata.stored_name).await,
+            Err(StorageError::FileNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_append_and_write_at() {
+        let backend = MemoryBackend::new();
+        backend.put("log.txt", b"abc").await.unwrap();
+
+        assert_eq!(backend.append("log.txt", b"def").await.unwrap(), 6);
+        assert_eq!(backend.write_at("log.txt", 4, b"XYZ").await.unwrap(), 7);
+        assert_eq!(backend.get("log.txt").await.unwrap(), b"abcdXYZ");
+
+        assert!(matches!(backend.write_at("log.txt", 8, b"!").await, Err(StorageError::InvalidInput(_))));
+        assert!(matches!(backend.append("missing.txt", b"!").await, Err(StorageError::FileNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_range() {
+        let backend = MemoryBackend::new();
+        backend.put("log.txt", b"abcdef").await.unwrap();
+
+        assert_eq!(backend.get_range("log.txt", 1, 2).await.unwrap(), b"bc");
+        assert_eq!(backend.get_range("log.txt", 4, u64::MAX).await.unwrap(), b"ef");
+        assert!(matches!(backend.get_range("log.txt", 7, 1).await, Err(StorageError::InvalidInput(_))));
+        assert!(matches!(backend.get_range("missing.txt", 0, 1).await, Err(StorageError::FileNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_copy() {
+        let backend = MemoryBackend::new();
+        backend.put("original.txt", b"abc").await.unwrap();
+
+        backend.copy("original.txt", "copy.txt").await.unwrap();
+        backend.append("copy.txt", b"def").await.unwrap();
+        assert_eq!(backend.get("original.txt").await.unwrap(), b"abc");
+        assert_eq!(backend.get("copy.txt").await.unwrap(), b"abcdef");
+
+        assert!(matches!(backend.copy("missing.txt", "other.txt").await, Err(StorageError::FileNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_keys() {
+        let backend = MemoryBackend::new();
+
+        assert!(matches!(backend.put("../escape", b"x").await, Err(StorageError::InvalidPath(_))));
+        assert!(backend.is_empty());
+    }
+}
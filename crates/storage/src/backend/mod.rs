@@ -0,0 +1,217 @@
+//! Pluggable blob storage backends
+//!
+//! `StorageService` delegates all blob I/O to a `StorageBackend`, so files can live on
+//! local disk, in remote object storage, or in memory (for tests) while metadata stays
+//! in the database.
+
+mod cache;
+#[cfg(feature = "gcs")]
+mod gcs;
+mod local;
+mod memory;
+mod retry;
+#[cfg(feature = "s3")]
+mod s3;
+
+pub use self::cache::{CachePolicy, CachingBackend};
+#[cfg(feature = "gcs")]
+pub use self::gcs::{GcsBackend, GcsBackendConfig};
+pub use self::local::LocalBackend;
+pub use self::memory::MemoryBackend;
+pub use self::retry::{RetryPolicy, RetryingBackend};
+#[cfg(feature = "s3")]
+pub use self::s3::{S3Backend, S3BackendConfig};
+
+use crate::{Result, StorageError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// A blob opened for reading
+pub type BlobReader = Box<dyn AsyncRead + Unpin + Send>;
+
+/// A place where file blobs are stored, addressed by key (the stored file name)
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Write a blob, replacing any existing blob with the same key
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()>;
+
+    /// Write a blob from a reader, returning the number of bytes written
+    /// The default implementation buffers the whole stream and calls `put`; backends that can
+    /// write incrementally should override it
+    async fn put_stream(&self, key: &str, reader: &mut (dyn AsyncRead + Unpin + Send)) -> Result<u64> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        self.put(key, &data).await?;
+        Ok(data.len() as u64)
+    }
+
+    /// Append data to the end of a blob, returning the blob's new size
+    async fn append(&self, key: &str, data: &[u8]) -> Result<u64> {
+        let mut blob = self.get(key).await?;
+        blob.extend_from_slice(data);
+        self.put(key, &blob).await?;
+        Ok(blob.len() as u64)
+    }
+
+    /// Overwrite part of a blob starting at `offset`, returning the blob's new size
+    /// Writes may run past the end of the blob, but may not start past it
+    async fn write_at(&self, key: &str, offset: u64, data: &[u8]) -> Result<u64> {
+        let mut blob = self.get(key).await?;
+        check_write_offset(key, offset, blob.len() as u64)?;
+
+        let start = offset as usize;
+        let end = start + data.len();
+        if end > blob.len() {
+            blob.resize(end, 0);
+        }
+        blob[start..end].copy_from_slice(data);
+
+        self.put(key, &blob).await?;
+        Ok(blob.len() as u64)
+    }
+
+    /// Move a blob to a new key, replacing any blob already there
+    /// The default implementation copies and then deletes; backends with a native move should override it
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let data = self.get(from).await?;
+        self.put(to, &data).await?;
+        self.delete(from).await
+    }
+
+    /// Copy a blob to a new key, replacing any blob already there
+    /// The default implementation reads the whole blob; backends that can copy server-side or
+    /// on disk should override it
+    async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        let data = self.get(from).await?;
+        self.put(to, &data).await
+    }
+
+    /// Read a blob
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Read up to `len` bytes of a blob starting at `offset`
+    /// Reads running past the end of the blob are cut short, and reads starting past it fail
+    /// with `InvalidInput`. The default implementation reads the whole blob; backends that can
+    /// read part of a blob should override it
+    async fn get_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let blob = self.get(key).await?;
+        check_read_offset(key, offset, blob.len() as u64)?;
+
+        let start = offset as usize;
+        let end = offset.saturating_add(len).min(blob.len() as u64) as usize;
+        Ok(blob[start..end].to_vec())
+    }
+
+    /// Open a blob for reading as a stream
+    /// The default implementation reads the whole blob into memory; backends that can read
+    /// incrementally should override it
+    async fn get_stream(&self, key: &str) -> Result<BlobReader> {
+        Ok(Box::new(std::io::Cursor::new(self.get(key).await?)))
+    }
+
+    /// Delete a blob
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Check whether a blob exists
+    async fn exists(&self, key: &str) -> Result<bool>;
+
+    /// List the keys of all stored blobs
+    async fn list(&self) -> Result<Vec<String>>;
+
+    /// Size of a blob in bytes
+    /// The default implementation reads the whole blob; backends should override it when
+    /// they can get the size from metadata
+    async fn size(&self, key: &str) -> Result<u64> {
+        Ok(self.get(key).await?.len() as u64)
+    }
+
+    /// When a blob was last written, if the backend records it
+    /// The default implementation reports `None`; garbage collection never removes blobs of unknown age
+    async fn modified(&self, _key: &str) -> Result<Option<DateTime<Utc>>> {
+        Ok(None)
+    }
+
+    /// Human-readable location of this backend (recorded as the file's `storage_path`)
+    fn location(&self) -> String;
+}
+
+/// Convert a non-success HTTP status from a remote object store into a storage error
+/// Throttling, timeouts, and server errors are transient; other client errors are permanent
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn http_status_error(provider: &str, operation: &str, key: &str, status: u16) -> StorageError {
+    match status {
+        404 => StorageError::FileNotFound(key.to_string()),
+        416 => StorageError::InvalidInput(format!("range read of '{}' starts past its end", key)),
+        408 | 429 | 500..=599 => StorageError::Transient(format!(
+            "{} {} failed for '{}': HTTP {}",
+            provider, operation, key, status
+        )),
+        _ => StorageError::StorageError(format!(
+            "{} {} failed for '{}': HTTP {}",
+            provider, operation, key, status
+        )),
+    }
+}
+
+/// Reject ranged writes that would leave a hole in the blob
+pub(crate) fn check_write_offset(key: &str, offset: u64, size: u64) -> Result<()> {
+    if offset > size {
+        return Err(StorageError::InvalidInput(format!(
+            "write offset {} is past the end of '{}' ({} bytes)",
+            offset, key, size
+        )));
+    }
+    Ok(())
+}
+
+/// Reject ranged reads that start past the end of the blob
+pub(crate) fn check_read_offset(key: &str, offset: u64, size: u64) -> Result<()> {
+    if offset > size {
+        return Err(StorageError::InvalidInput(format!(
+            "read offset {} is past the end of '{}' ({} bytes)",
+            offset, key, size
+        )));
+    }
+    Ok(())
+}
+
+/// Maximum length of a blob key
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Validate a backend key: a stored name, optionally namespaced as `tenant/stored_name`
+pub(crate) fn validate_blob_key(key: &str) -> Result<()> {
+    match key.split_once('/') {
+        Some((tenant, stored_name)) => {
+            validate_key(tenant)?;
+            validate_key(stored_name)
+        }
+        None => validate_key(key),
+    }
+}
+
+/// Validate a blob key before it is used to address storage
+///
+/// Keys are flat names (a file id, optionally with an extension), so anything that could
+/// name another location — separators, `..`, hidden files, control characters — is rejected.
+pub fn validate_key(key: &str) -> Result<()> {
+    let invalid = |reason: &str| Err(StorageError::InvalidPath(format!("'{}' {}", key.escape_debug(), reason)));
+
+    if key.is_empty() {
+        return invalid("is empty");
+    }
+    if key.len() > MAX_KEY_LENGTH {
+        return invalid("is too long");
+    }
+    if key.starts_with('.') {
+        return invalid("must not start with '.'");
+    }
+    if key.contains("..") {
+        return invalid("must not contain '..'");
+    }
+    if !key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        return invalid("may only contain letters, digits, '-', '_' and '.'");
+    }
+
+    Ok(())
+}
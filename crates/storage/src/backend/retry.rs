@@ -0,0 +1,296 @@
+use super::{BlobReader, StorageBackend};
+use crate::{Result, StorageError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Retry and circuit-breaker settings for remote backends
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts per operation, including the first one
+    pub max_attempts: u32,
+    /// Base delay for exponential backoff
+    pub base_delay: Duration,
+    /// Upper bound on any single backoff delay
+    pub max_delay: Duration,
+    /// Consecutive transient failures after which the circuit opens
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a trial request is let through
+    pub reset_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            failure_threshold: 5,
+            reset_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff before the given retry (1-based), using "full jitter":
+    /// a random delay between zero and the capped exponential backoff
+    fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(retry - 1));
+        let cap = exponential.min(self.max_delay);
+        let millis = cap.as_millis() as u64;
+        if millis == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+/// Wraps a backend, retrying transient failures and failing fast while the backend is down
+pub struct RetryingBackend<B> {
+    inner: B,
+    policy: RetryPolicy,
+    circuit: Mutex<CircuitState>,
+}
+
+impl<B: StorageBackend> RetryingBackend<B> {
+    pub fn new(inner: B, policy: RetryPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            circuit: Mutex::new(CircuitState::default()),
+        }
+    }
+
+    /// Fail fast if the circuit is open; once the reset timeout passes, allow a trial request
+    fn check_circuit(&self) -> Result<()> {
+        let circuit = self.circuit.lock().unwrap();
+        match circuit.opened_at {
+            Some(opened_at) if opened_at.elapsed() < self.policy.reset_timeout => {
+                Err(StorageError::CircuitOpen(format!(
+                    "storage backend {} is unavailable after {} consecutive failures",
+                    self.inner.location(),
+                    circuit.consecutive_failures
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn record_success(&self) {
+        let mut circuit = self.circuit.lock().unwrap();
+        circuit.consecutive_failures = 0;
+        circuit.opened_at = None;
+    }
+
+    fn record_failure(&self) {
+        let mut circuit = self.circuit.lock().unwrap();
+        circuit.consecutive_failures += 1;
+        if circuit.consecutive_failures >= self.policy.failure_threshold {
+            circuit.opened_at = Some(Instant::now());
+        }
+    }
+
+    /// Run an operation, retrying transient errors with jittered backoff
+    async fn call<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T>> + Send,
+        T: Send,
+    {
+        self.check_circuit()?;
+
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Ok(value) => {
+                    self.record_success();
+                    return Ok(value);
+                }
+                Err(e) if e.is_transient() => {
+                    self.record_failure();
+                    if attempt >= self.policy.max_attempts {
+                        return Err(e);
+                    }
+                    tokio::time::sleep(self.policy.backoff(attempt)).await;
+                    self.check_circuit()?;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<B: StorageBackend> StorageBackend for RetryingBackend<B> {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.call(|| self.inner.put(key, data)).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        self.call(|| self.inner.get(key)).await
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.call(|| self.inner.get_range(key, offset, len)).await
+    }
+
+    /// Only opening the stream is retried; a failure while reading it is the caller's to handle
+    async fn get_stream(&self, key: &str) -> Result<BlobReader> {
+        self.call(|| self.inner.get_stream(key)).await
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        self.call(|| self.inner.copy(from, to)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.call(|| self.inner.delete(key)).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        self.call(|| self.inner.exists(key)).await
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        self.call(|| self.inner.list()).await
+    }
+
+    async fn size(&self, key: &str) -> Result<u64> {
+        self.call(|| self.inner.size(key)).await
+    }
+
+    async fn modified(&self, key: &str) -> Result<Option<DateTime<Utc>>> {
+        self.call(|| self.inner.modified(key)).await
+    }
+
+    fn location(&self) -> String {
+        self.inner.location()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Backend that fails with the given error a fixed number of times before succeeding
+    struct FlakyBackend {
+        failures_left: AtomicU32,
+        transient: bool,
+        calls: AtomicU32,
+    }
+
+    impl FlakyBackend {
+        fn new(failures: u32, transient: bool) -> Self {
+            Self {
+                failures_left: AtomicU32::new(failures),
+                transient,
+                calls: AtomicU32::new(0),
+            }
+        }
+
+        fn attempt(&self) -> Result<()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.failures_left.load(Ordering::SeqCst) == 0 {
+                return Ok(());
+            }
+            self.failures_left.fetch_sub(1, Ordering::SeqCst);
+            if self.transient {
+                Err(StorageError::Transient("HTTP 503".to_string()))
+            } else {
+                Err(StorageError::StorageError("HTTP 403".to_string()))
+            }
+        }
+    }
+
+    #[async_trait]
+    impl StorageBackend for FlakyBackend {
+        async fn put(&self, _key: &str, _data: &[u8]) -> Result<()> {
+            self.attempt()
+        }
+
+        async fn get(&self, _key: &str) -> Result<Vec<u8>> {
+            self.attempt().map(|_| b"data".to_vec())
+        }
+
+        async fn delete(&self, _key: &str) -> Result<()> {
+            self.attempt()
+        }
+
+        async fn exists(&self, _key: &str) -> Result<bool> {
+            self.attempt().map(|_| true)
+        }
+
+        async fn list(&self) -> Result<Vec<String>> {
+            self.attempt().map(|_| Vec::new())
+        }
+
+        fn location(&self) -> String {
+            "flaky://test".to_string()
+        }
+    }
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            failure_threshold: 10,
+            reset_timeout: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let backend = RetryingBackend::new(FlakyBackend::new(2, true), fast_policy());
+
+        assert_eq!(backend.get("key").await.unwrap(), b"data");
+        assert_eq!(backend.inner.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_permanent_failures() {
+        let backend = RetryingBackend::new(FlakyBackend::new(1, false), fast_policy());
+
+        assert!(backend.put("key", b"data").await.is_err());
+        assert_eq!(backend.inner.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_after_threshold() {
+        let policy = RetryPolicy {
+            max_attempts: 1,
+            failure_threshold: 2,
+            ..fast_policy()
+        };
+        let backend = RetryingBackend::new(FlakyBackend::new(5, true), policy);
+
+        assert!(matches!(backend.delete("key").await, Err(StorageError::Transient(_))));
+        assert!(matches!(backend.delete("key").await, Err(StorageError::Transient(_))));
+        assert!(matches!(backend.delete("key").await, Err(StorageError::CircuitOpen(_))));
+        assert_eq!(backend.inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(250),
+            ..RetryPolicy::default()
+        };
+
+        for retry in 1..8 {
+            assert!(policy.backoff(retry) <= Duration::from_millis(250));
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use super::{http_status_error, StorageBackend};
+use crate::{Result, StorageError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ::s3::creds::Credentials;
+use ::s3::{Bucket, Region};
+
+/// Connection settings for an S3-compatible bucket (AWS S3, MinIO, Cloudflare R2, ...)
+#[derive(Debug, Clone)]
+pub struct S3BackendConfig {
+    /// Bucket name
+    pub bucket: String,
+    /// Region name (e.g. "us-east-1", or "auto" for R2)
+    pub region: String,
+    /// Custom endpoint URL for non-AWS providers (e.g. "http://localhost:9000" for MinIO)
+    pub endpoint: Option<String>,
+    /// Access key ID (falls back to the standard AWS environment/profile chain when unset)
+    pub access_key: Option<String>,
+    /// Secret access key
+    pub secret_key: Option<String>,
+    /// Use path-style addressing (`endpoint/bucket/key`), required by MinIO
+    pub path_style: bool,
+    /// Optional key prefix so several deployments can share a bucket
+    pub prefix: Option<String>,
+}
+
+/// Backend storing blobs as objects in an S3-compatible bucket
+pub struct S3Backend {
+    bucket: Box<Bucket>,
+    prefix: String,
+}
+
+impl S3Backend {
+    /// Create a new S3 backend from connection settings
+    pub fn new(config: S3BackendConfig) -> Result<Self> {
+        let region = match config.endpoint {
+            Some(endpoint) => Region::Custom {
+                region: config.region.clone(),
+                endpoint,
+            },
+            None => config.region.parse()
+                .map_err(|e| StorageError::StorageError(format!("Invalid S3 region '{}': {}", config.region, e)))?,
+        };
+
+        let credentials = Credentials::new(
+            config.access_key.as_deref(),
+            config.secret_key.as_deref(),
+            None,
+            None,
+            None,
+        )
+        .map_err(|e| StorageError::StorageError(format!("Invalid S3 credentials: {}", e)))?;
+
+        let mut bucket = Bucket::new(&config.bucket, region, credentials)
+            .map_err(|e| StorageError::StorageError(format!("Failed to configure S3 bucket: {}", e)))?;
+
+        if config.path_style {
+            bucket = bucket.with_path_style();
+        }
+
+        let prefix = config.prefix
+            .map(|p| p.trim_matches('/').to_string())
+            .unwrap_or_default();
+
+        Ok(Self { bucket, prefix })
+    }
+
+    /// Full object key for a stored blob, including the configured prefix
+    fn object_key(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.prefix, key)
+        }
+    }
+}
+
+/// Convert an S3 client error into a storage error
+/// Client errors without a status are connection-level failures, which are worth retrying
+fn s3_error(operation: &str, key: &str, e: ::s3::error::S3Error) -> StorageError {
+    match e {
+        ::s3::error::S3Error::HttpFailWithBody(status, _) => status_error(operation, key, status),
+        ::s3::error::S3Error::Credentials(_) | ::s3::error::S3Error::Region(_) => {
+            StorageError::StorageError(format!("S3 {} failed for '{}': {}", operation, key, e))
+        }
+        _ => StorageError::Transient(format!("S3 {} failed for '{}': {}", operation, key, e)),
+    }
+}
+
+/// Convert a non-success S3 status code into a storage error
+fn status_error(operation: &str, key: &str, status: u16) -> StorageError {
+    http_status_error("S3", operation, key, status)
+}
+
+#[async_trait]
+impl StorageBackend for S3Backend {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let response = self.bucket.put_object(self.object_key(key), data).await
+            .map_err(|e| s3_error("put", key, e))?;
+
+        match response.status_code() {
+            200..=299 => Ok(()),
+            status => Err(status_error("put", key, status)),
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let response = self.bucket.get_object(self.object_key(key)).await
+            .map_err(|e| s3_error("get", key, e))?;
+
+        match response.status_code() {
+            200..=299 => Ok(response.bytes().to_vec()),
+            status => Err(status_error("get", key, status)),
+        }
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let end = offset.saturating_add(len - 1);
+        let response = self.bucket.get_object_range(self.object_key(key), offset, Some(end)).await
+            .map_err(|e| s3_error("get", key, e))?;
+
+        match response.status_code() {
+            200..=299 => Ok(response.bytes().to_vec()),
+            status => Err(status_error("get", key, status)),
+        }
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        // CopyObject copies server-side, so the blob never passes through this process
+        let status = self.bucket.copy_object_internal(self.object_key(from), self.object_key(to)).await
+            .map_err(|e| s3_error("copy", from, e))?;
+
+        match status {
+            200..=299 => Ok(()),
+            status => Err(status_error("copy", from, status)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let response = self.bucket.delete_object(self.object_key(key)).await
+            .map_err(|e| s3_error("delete", key, e))?;
+
+        match response.status_code() {
+            200..=299 => Ok(()),
+            status => Err(status_error("delete", key, status)),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        let (_, status) = self.bucket.head_object(self.object_key(key)).await
+            .map_err(|e| s3_error("head", key, e))?;
+
+        match status {
+            200..=299 => Ok(true),
+            404 => Ok(false),
+            status => Err(status_error("head", key, status)),
+        }
+    }
+
+    async fn size(&self, key: &str) -> Result<u64> {
+        let (head, status) = self.bucket.head_object(self.object_key(key)).await
+            .map_err(|e| s3_error("head", key, e))?;
+
+        match status {
+            200..=299 => Ok(head.content_length.unwrap_or(0).max(0) as u64),
+            status => Err(status_error("head", key, status)),
+        }
+    }
+
+    async fn modified(&self, key: &str) -> Result<Option<DateTime<Utc>>> {
+        let (head, status) = self.bucket.head_object(self.object_key(key)).await
+            .map_err(|e| s3_error("head", key, e))?;
+
+        match status {
+            // S3 reports Last-Modified as an HTTP date
+            200..=299 => Ok(head.last_modified
+                .and_then(|modified| DateTime::parse_from_rfc2822(&modified).ok())
+                .map(|modified| modified.with_timezone(&Utc))),
+            status => Err(status_error("head", key, status)),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let list_prefix = if self.prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", self.prefix)
+        };
+
+        let pages = self.bucket.list(list_prefix.clone(), None).await
+            .map_err(|e| s3_error("list", &list_prefix, e))?;
+
+        Ok(pages
+            .into_iter()
+            .flat_map(|page| page.contents)
+            .map(|object| object.key.trim_start_matches(&list_prefix).to_string())
+            .collect())
+    }
+
+    fn location(&self) -> String {
+        if self.prefix.is_empty() {
+            format!("s3://{}", self.bucket.name())
+        } else {
+            format!("s3://{}/{}", self.bucket.name(), self.prefix)
+        }
+    }
+}
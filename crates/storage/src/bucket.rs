@@ -0,0 +1,531 @@
+//! Buckets users bring themselves
+//!
+//! A user may register their own S3-compatible bucket, so their files are written to storage
+//! they control rather than the server's. Credentials are kept encrypted with a key derived from
+//! the server's configured secret, and each bucket is health-checked when it is registered and
+//! periodically afterwards.
+//!
+//! All of a user's files live in one place: a bucket can only be registered or removed while the
+//! user has no files, so no file is ever left behind in storage it is no longer routed to.
+//!
+//! The server connects to the endpoint a user gives, so endpoints must be public IP addresses.
+//! Host names are refused: the S3 client resolves them again on every request, so a name that
+//! was public when checked could later point into the server's own network.
+//! Errors from the bucket are logged, and its owner only sees what kind of failure it was.
+
+use crate::{File, Result, StorageError, StorageService};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use orm::prelude::*;
+use orm::query::QueryValue;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// Table of user-registered buckets, one per user
+pub const USER_BUCKETS_TABLE: &str = "user_buckets";
+
+/// Bytes in an AES-GCM nonce
+const NONCE_LENGTH: usize = 12;
+
+/// Name of the blob written and removed again by a health check
+const HEALTH_CHECK_NAME: &str = "projectkit-health-check.txt";
+
+/// Source of storage for owners whose blobs live outside the server's own storage
+#[async_trait]
+pub trait BucketResolver: Send + Sync {
+    /// Storage holding a user's blobs, or `None` to keep them in the server's storage
+    async fn storage_for_owner(&self, user_id: i64) -> Result<Option<StorageService>>;
+}
+
+/// Connection settings of a bucket, as submitted by its owner
+#[derive(Clone, Deserialize)]
+pub struct BucketCredentials {
+    pub bucket: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// Endpoint of S3-compatible providers (R2, MinIO, ...); AWS when absent
+    pub endpoint: Option<String>,
+    pub access_key: String,
+    pub secret_key: String,
+    #[serde(default)]
+    pub path_style: bool,
+    /// Key prefix inside the bucket
+    pub prefix: Option<String>,
+}
+
+impl std::fmt::Debug for BucketCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BucketCredentials")
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("endpoint", &self.endpoint)
+            .field("path_style", &self.path_style)
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+/// Secret part of the credentials, stored encrypted
+#[derive(Serialize, Deserialize)]
+struct BucketSecret {
+    access_key: String,
+    secret_key: String,
+}
+
+/// Outcome of the latest health check of a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BucketHealth {
+    Healthy,
+    Unhealthy,
+}
+
+impl BucketHealth {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BucketHealth::Healthy => "healthy",
+            BucketHealth::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+/// A registered bucket, without its credentials
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserBucket {
+    pub user_id: i64,
+    pub bucket: String,
+    pub region: String,
+    pub endpoint: Option<String>,
+    pub path_style: bool,
+    pub prefix: Option<String>,
+    pub health: BucketHealth,
+    /// Why the latest health check failed
+    pub last_error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl UserBucket {
+    fn from_json(row: &serde_json::Value) -> Option<Self> {
+        let string = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+        let timestamp = |column: &str| {
+            row.get(column)
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+
+        Some(Self {
+            user_id: row.get("user_id").and_then(|v| v.as_i64())?,
+            bucket: string("bucket")?,
+            region: string("region")?,
+            endpoint: string("endpoint"),
+            path_style: row.get("path_style").and_then(|v| v.as_i64()).unwrap_or(0) != 0,
+            prefix: string("prefix"),
+            health: match string("health").as_deref() {
+                Some("healthy") => BucketHealth::Healthy,
+                _ => BucketHealth::Unhealthy,
+            },
+            last_error: string("last_error"),
+            checked_at: timestamp("checked_at").unwrap_or_else(Utc::now),
+            created_at: timestamp("created_at").unwrap_or_else(Utc::now),
+        })
+    }
+}
+
+/// Opens storage on a bucket from its credentials
+pub type BucketConnector = Arc<dyn Fn(&BucketCredentials) -> Result<StorageService> + Send + Sync>;
+
+/// Encrypts bucket credentials at rest with AES-256-GCM
+#[derive(Clone)]
+pub struct CredentialCipher {
+    cipher: Aes256Gcm,
+}
+
+impl CredentialCipher {
+    /// Derive the encryption key from a secret
+    /// Changing the secret makes every stored credential unreadable
+    pub fn new(secret: &str) -> Self {
+        let key = Sha256::digest(secret.as_bytes());
+        Self {
+            cipher: Aes256Gcm::new_from_slice(&key).expect("SHA-256 output is a valid AES-256 key"),
+        }
+    }
+
+    /// Encrypt under a fresh random nonce, returning hex of the nonce followed by the ciphertext
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self.cipher.encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| StorageError::StorageError("Failed to encrypt bucket credentials".to_string()))?;
+
+        Ok(nonce.iter().chain(&ciphertext).map(|b| format!("{:02x}", b)).collect())
+    }
+
+    /// Decrypt the output of [`CredentialCipher::encrypt`]
+    pub fn decrypt(&self, encrypted: &str) -> Result<Vec<u8>> {
+        let unreadable = || StorageError::StorageError("Stored bucket credentials cannot be decrypted".to_string());
+        let bytes = decode_hex(encrypted).ok_or_else(unreadable)?;
+        if bytes.len() < NONCE_LENGTH {
+            return Err(unreadable());
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+        self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| unreadable())
+    }
+}
+
+/// Registry of user buckets, routing each owner's blobs to their bucket
+pub struct UserBuckets {
+    db: Database,
+    cipher: CredentialCipher,
+    connector: BucketConnector,
+    /// Open buckets by owner, with the stored credentials they were opened with
+    connected: Mutex<HashMap<i64, (String, StorageService)>>,
+}
+
+impl UserBuckets {
+    /// Create a registry encrypting credentials with a key derived from `secret`
+    /// Buckets are opened as S3 buckets
+    pub fn new(db: Database, secret: &str) -> Self {
+        Self::with_connector(db, secret, Arc::new(connect_s3))
+    }
+
+    /// Create a registry that opens buckets with `connector` (useful for tests)
+    pub fn with_connector(db: Database, secret: &str, connector: BucketConnector) -> Self {
+        Self {
+            db,
+            cipher: CredentialCipher::new(secret),
+            connector,
+            connected: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The bucket registered by a user, if any
+    pub async fn get(&self, user_id: i64) -> Result<Option<UserBucket>> {
+        Ok(self.fetch_row(user_id).await?.as_ref().and_then(UserBucket::from_json))
+    }
+
+    /// Register a bucket for a user, replacing any previous one
+    ///
+    /// The bucket must pass a health check, and the user must have no files, since files
+    /// stored before would stay behind in storage that is no longer routed to.
+    pub async fn configure(&self, user_id: i64, credentials: BucketCredentials) -> Result<UserBucket> {
+        if credentials.bucket.trim().is_empty() {
+            return Err(StorageError::InvalidInput("bucket must not be empty".to_string()));
+        }
+        if credentials.access_key.is_empty() || credentials.secret_key.is_empty() {
+            return Err(StorageError::InvalidInput("access_key and secret_key are required".to_string()));
+        }
+        self.ensure_no_files(user_id).await?;
+        if let Some(endpoint) = &credentials.endpoint {
+            check_endpoint(endpoint)?;
+        }
+
+        let checked = match (self.connector)(&credentials) {
+            Ok(storage) => check_storage(&storage).await,
+            Err(e @ StorageError::InvalidInput(_)) => return Err(e),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = checked {
+            eprintln!("⚠️  Bucket {} of user {} failed its health check: {}", credentials.bucket, user_id, e);
+            return Err(StorageError::InvalidInput(format!("bucket failed its health check: {}", health_error(&e))));
+        }
+
+        let secret = serde_json::to_vec(&BucketSecret {
+            access_key: credentials.access_key.clone(),
+            secret_key: credentials.secret_key.clone(),
+        }).map_err(|e| StorageError::StorageError(format!("Failed to encode bucket credentials: {}", e)))?;
+        let encrypted = self.cipher.encrypt(&secret)?;
+        let now = Utc::now().to_rfc3339();
+
+        let backend = self.db.backend();
+        let sql = format!("DELETE FROM {} WHERE user_id = ?1", USER_BUCKETS_TABLE);
+        backend.execute(&sql, &[QueryValue::I64(user_id)]).await.map_err(db_error)?;
+
+        let sql = format!(
+            "INSERT INTO {} (user_id, bucket, region, endpoint, path_style, prefix, credentials, health, last_error, checked_at, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, NULL, ?9, ?9)",
+            USER_BUCKETS_TABLE
+        );
+        backend.execute(&sql, &[
+            QueryValue::I64(user_id),
+            QueryValue::String(credentials.bucket.clone()),
+            QueryValue::String(credentials.region.clone()),
+            credentials.endpoint.clone().map(QueryValue::String).unwrap_or(QueryValue::Null),
+            QueryValue::I64(credentials.path_style as i64),
+            credentials.prefix.clone().map(QueryValue::String).unwrap_or(QueryValue::Null),
+            QueryValue::String(encrypted),
+            QueryValue::String(BucketHealth::Healthy.as_str().to_string()),
+            QueryValue::String(now),
+        ]).await.map_err(db_error)?;
+        self.forget(user_id);
+
+        self.get(user_id).await?
+            .ok_or_else(|| StorageError::StorageError("Registered bucket was not found".to_string()))
+    }
+
+    /// Stop routing a user's files to their bucket
+    /// The user must have no files left, which stay in their bucket otherwise
+    ///
+    /// # Returns
+    /// Whether the user had a bucket registered
+    pub async fn remove(&self, user_id: i64) -> Result<bool> {
+        if self.fetch_row(user_id).await?.is_none() {
+            return Ok(false);
+        }
+        self.ensure_no_files(user_id).await?;
+
+        let sql = format!("DELETE FROM {} WHERE user_id = ?1", USER_BUCKETS_TABLE);
+        let removed = self.db.backend().execute(&sql, &[QueryValue::I64(user_id)]).await.map_err(db_error)?;
+        self.forget(user_id);
+        Ok(removed > 0)
+    }
+
+    /// Check that a user's bucket accepts writes, reads and deletes, and record the outcome
+    pub async fn check_health(&self, user_id: i64) -> Result<Option<UserBucket>> {
+        let result = match self.storage_for_owner(user_id).await? {
+            Some(storage) => check_storage(&storage).await,
+            None => return Ok(None),
+        };
+        let (health, last_error) = match result {
+            Ok(()) => (BucketHealth::Healthy, QueryValue::Null),
+            Err(e) => {
+                eprintln!("⚠️  Bucket of user {} failed its health check: {}", user_id, e);
+                (BucketHealth::Unhealthy, QueryValue::String(health_error(&e).to_string()))
+            }
+        };
+
+        let sql = format!(
+            "UPDATE {} SET health = ?1, last_error = ?2, checked_at = ?3 WHERE user_id = ?4",
+            USER_BUCKETS_TABLE
+        );
+        self.db.backend().execute(&sql, &[
+            QueryValue::String(health.as_str().to_string()),
+            last_error,
+            QueryValue::String(Utc::now().to_rfc3339()),
+            QueryValue::I64(user_id),
+        ]).await.map_err(db_error)?;
+
+        self.get(user_id).await
+    }
+
+    /// Health-check every registered bucket
+    ///
+    /// # Returns
+    /// The buckets found unhealthy
+    pub async fn check_all(&self) -> Result<Vec<UserBucket>> {
+        let sql = format!("SELECT user_id FROM {} ORDER BY user_id", USER_BUCKETS_TABLE);
+        let rows = self.db.backend().fetch_all_params(&sql, &[]).await.map_err(db_error)?;
+
+        let mut unhealthy = Vec::new();
+        for user_id in rows.iter().filter_map(|row| row.get("user_id").and_then(|v| v.as_i64())) {
+            if let Some(bucket) = self.check_health(user_id).await? {
+                if bucket.health == BucketHealth::Unhealthy {
+                    unhealthy.push(bucket);
+                }
+            }
+        }
+        Ok(unhealthy)
+    }
+
+    /// Drop the open connection to a user's bucket, after it was replaced or removed
+    fn forget(&self, user_id: i64) {
+        self.connected.lock().unwrap_or_else(|e| e.into_inner()).remove(&user_id);
+    }
+
+    async fn fetch_row(&self, user_id: i64) -> Result<Option<serde_json::Value>> {
+        let sql = format!("SELECT * FROM {} WHERE user_id = ?1", USER_BUCKETS_TABLE);
+        self.db.backend().fetch_one_params(&sql, &[QueryValue::I64(user_id)]).await.map_err(db_error)
+    }
+
+    /// Fail with `PreconditionFailed` if the user has any files, trashed ones included
+    async fn ensure_no_files(&self, user_id: i64) -> Result<()> {
+        let sql = format!("SELECT COUNT(*) as count FROM {} WHERE user_id = ?1", File::table_name());
+        let row = self.db.backend().fetch_one_params(&sql, &[QueryValue::I64(user_id)]).await.map_err(db_error)?;
+        let count = row.and_then(|row| row.get("count").and_then(|v| v.as_i64())).unwrap_or(0);
+        if count > 0 {
+            return Err(StorageError::PreconditionFailed(format!(
+                "{} file(s) would be left behind; delete them and empty the trash first",
+                count
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BucketResolver for UserBuckets {
+    async fn storage_for_owner(&self, user_id: i64) -> Result<Option<StorageService>> {
+        let Some(row) = self.fetch_row(user_id).await? else {
+            return Ok(None);
+        };
+        let string = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+
+        // Another instance may have replaced the bucket, so the cached connection is only
+        // reused while the stored credentials (encrypted under a fresh nonce each time) match
+        let encrypted = string("credentials").unwrap_or_default();
+        let cached = self.connected.lock().unwrap_or_else(|e| e.into_inner()).get(&user_id).cloned();
+        if let Some((_, storage)) = cached.filter(|(opened_with, _)| *opened_with == encrypted) {
+            return Ok(Some(storage));
+        }
+
+        let secret: BucketSecret = serde_json::from_slice(&self.cipher.decrypt(&encrypted)?)
+            .map_err(|e| StorageError::StorageError(format!("Invalid stored bucket credentials: {}", e)))?;
+        let credentials = BucketCredentials {
+            bucket: string("bucket").unwrap_or_default(),
+            region: string("region").unwrap_or_else(default_region),
+            endpoint: string("endpoint"),
+            access_key: secret.access_key,
+            secret_key: secret.secret_key,
+            path_style: row.get("path_style").and_then(|v| v.as_i64()).unwrap_or(0) != 0,
+            prefix: string("prefix"),
+        };
+
+        if let Some(endpoint) = &credentials.endpoint {
+            check_endpoint(endpoint)?;
+        }
+        let storage = (self.connector)(&credentials)?;
+        self.connected.lock().unwrap_or_else(|e| e.into_inner()).insert(user_id, (encrypted, storage.clone()));
+        Ok(Some(storage))
+    }
+}
+
+/// Fail unless `endpoint` is an `http(s)` URL whose host is a public IP address
+fn check_endpoint(endpoint: &str) -> Result<()> {
+    let (scheme, rest) = endpoint.split_once("://")
+        .ok_or_else(|| StorageError::InvalidInput(format!("endpoint {} must be an http or https URL", endpoint)))?;
+    if !matches!(scheme.to_ascii_lowercase().as_str(), "https" | "http") {
+        return Err(StorageError::InvalidInput(format!("endpoint {} must be an http or https URL", endpoint)));
+    }
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, after) = bracketed.split_once(']')
+                .ok_or_else(|| StorageError::InvalidInput(format!("endpoint {} has an invalid host", endpoint)))?;
+            (host, after.strip_prefix(':'))
+        }
+        None => match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    if host.is_empty() {
+        return Err(StorageError::InvalidInput(format!("endpoint {} has no host", endpoint)));
+    }
+    if let Some(port) = port {
+        port.parse::<u16>()
+            .map_err(|_| StorageError::InvalidInput(format!("endpoint {} has an invalid port", endpoint)))?;
+    }
+
+    let ip = host.parse::<IpAddr>().map_err(|_| StorageError::InvalidInput(format!(
+        "endpoint host {} must be an IP address; leave the endpoint out for AWS",
+        host
+    )))?;
+    if !crate::is_public_address(ip) {
+        return Err(StorageError::PermissionDenied(format!("endpoint host {} is not a public address", host)));
+    }
+    Ok(())
+}
+
+/// What went wrong with a bucket, without the bucket's own words
+fn health_error(error: &StorageError) -> &'static str {
+    match error {
+        StorageError::Transient(_) | StorageError::CircuitOpen(_) | StorageError::IoError(_) => "the bucket could not be reached",
+        StorageError::PermissionDenied(_) => "the bucket refused the credentials",
+        StorageError::FileNotFound(_) => "the bucket lost a blob it had just stored",
+        _ => "the bucket did not work as expected",
+    }
+}
+
+/// Write, read back and delete a small blob
+async fn check_storage(storage: &StorageService) -> Result<()> {
+    let probe = b"projectkit health check";
+    let metadata = storage.store(probe, HEALTH_CHECK_NAME, None).await?;
+    let read = storage.retrieve(&metadata.stored_name).await;
+    storage.delete(&metadata.stored_name).await?;
+
+    if read? != probe {
+        return Err(StorageError::StorageError("read back different bytes than were written".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "s3")]
+fn connect_s3(credentials: &BucketCredentials) -> Result<StorageService> {
+    let backend = crate::S3Backend::new(crate::S3BackendConfig {
+        bucket: credentials.bucket.clone(),
+        region: credentials.region.clone(),
+        endpoint: credentials.endpoint.clone(),
+        access_key: Some(credentials.access_key.clone()),
+        secret_key: Some(credentials.secret_key.clone()),
+        path_style: credentials.path_style,
+        prefix: credentials.prefix.clone(),
+    })?;
+    Ok(StorageService::with_backend(backend))
+}
+
+#[cfg(not(feature = "s3"))]
+fn connect_s3(_credentials: &BucketCredentials) -> Result<StorageService> {
+    Err(StorageError::InvalidInput(
+        "user buckets require the server to be built with the `s3` feature".to_string(),
+    ))
+}
+
+fn db_error(e: impl std::fmt::Display) -> StorageError {
+    StorageError::StorageError(format!("Database error: {}", e))
+}
+
+/// Decode a hex string, returning `None` if it isn't valid hex
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credential_cipher_round_trip() {
+        let cipher = CredentialCipher::new("server-secret");
+        let encrypted = cipher.encrypt(b"access:secret").unwrap();
+
+        assert!(!encrypted.contains("secret"));
+        assert_ne!(encrypted, cipher.encrypt(b"access:secret").unwrap());
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), b"access:secret");
+    }
+
+    #[test]
+    fn test_credential_cipher_rejects_other_keys_and_tampering() {
+        let encrypted = CredentialCipher::new("server-secret").encrypt(b"access:secret").unwrap();
+
+        assert!(CredentialCipher::new("other-secret").decrypt(&encrypted).is_err());
+        let mut tampered = encrypted.clone();
+        tampered.replace_range(tampered.len() - 2.., if encrypted.ends_with("00") { "01" } else { "00" });
+        assert!(CredentialCipher::new("server-secret").decrypt(&tampered).is_err());
+        assert!(CredentialCipher::new("server-secret").decrypt("not hex").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_storage() {
+        let storage = StorageService::in_memory();
+        check_storage(&storage).await.unwrap();
+        assert!(storage.list_files().await.unwrap().is_empty());
+    }
+}
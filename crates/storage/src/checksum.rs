@@ -0,0 +1,255 @@
+//! SHA-256 content checksums
+
+use sha2::digest::generic_array::GenericArray;
+use sha2::{Digest, Sha256};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::mime::SNIFF_LENGTH;
+
+/// Hex-encoded SHA-256 checksum of some bytes
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Reader adapter that hashes everything read through it
+/// Also keeps the first bytes, so the content type can be detected without a second read
+pub(crate) struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    head: Vec<u8>,
+}
+
+impl<R> HashingReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            head: Vec::new(),
+        }
+    }
+
+    /// Up to the first `SNIFF_LENGTH` bytes read so far
+    pub(crate) fn head(&self) -> &[u8] {
+        &self.head
+    }
+
+    /// Hex-encoded checksum of the bytes read so far
+    pub(crate) fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let already_filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &poll {
+            let new_bytes = &buf.filled()[already_filled..];
+            self.hasher.update(new_bytes);
+            let wanted = SNIFF_LENGTH.saturating_sub(self.head.len()).min(new_bytes.len());
+            self.head.extend_from_slice(&new_bytes[..wanted]);
+        }
+        poll
+    }
+}
+
+/// Reader adapter that checks the bytes read through it against an expected checksum
+/// A mismatch surfaces as an `InvalidData` error once the end of the stream is reached, so a
+/// response streamed from it is cut off instead of completing with corrupted content.
+pub(crate) struct VerifyingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    expected: String,
+    finished: bool,
+}
+
+impl<R> VerifyingReader<R> {
+    pub(crate) fn new(inner: R, expected: &str) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            expected: expected.to_ascii_lowercase(),
+            finished: false,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for VerifyingReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let already_filled = buf.filled().len();
+        let wanted_more = buf.remaining() > 0;
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &poll {
+            let new_bytes = &buf.filled()[already_filled..];
+            if !new_bytes.is_empty() {
+                self.hasher.update(new_bytes);
+            } else if wanted_more && !self.finished {
+                // End of stream: the content is complete, so check it
+                self.finished = true;
+                let actual = format!("{:x}", self.hasher.finalize_reset());
+                if actual != self.expected {
+                    return Poll::Ready(Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("checksum mismatch: content has {} but {} was recorded", actual, self.expected),
+                    )));
+                }
+            }
+        }
+        poll
+    }
+}
+
+/// Size of a SHA-256 block in bytes
+const BLOCK_SIZE: usize = 64;
+
+/// SHA-256 state before any input
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// SHA-256 whose progress can be saved and resumed
+/// Appending to a file then only hashes the new bytes and the partial block before them, not the
+/// whole file again. A saved state covers the whole 64-byte blocks hashed so far.
+#[derive(Debug, Clone)]
+pub(crate) struct ResumableSha256 {
+    state: [u32; 8],
+    /// Bytes hashed into `state`, always a whole number of blocks
+    absorbed: u64,
+    /// Bytes after the last whole block
+    pending: Vec<u8>,
+}
+
+impl ResumableSha256 {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: INITIAL_STATE,
+            absorbed: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Resume from a state saved by [`Self::save`] once the first `absorbed` bytes were hashed
+    pub(crate) fn resume(saved: &str, absorbed: u64) -> Option<Self> {
+        if saved.len() != 64 || absorbed % BLOCK_SIZE as u64 != 0 {
+            return None;
+        }
+        let mut state = [0u32; 8];
+        for (i, word) in state.iter_mut().enumerate() {
+            *word = u32::from_str_radix(saved.get(i * 8..i * 8 + 8)?, 16).ok()?;
+        }
+        Some(Self { state, absorbed, pending: Vec::new() })
+    }
+
+    /// Bytes covered by the saved state
+    pub(crate) fn absorbed(&self) -> u64 {
+        self.absorbed
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        if !self.pending.is_empty() {
+            let wanted = (BLOCK_SIZE - self.pending.len()).min(data.len());
+            self.pending.extend_from_slice(&data[..wanted]);
+            data = &data[wanted..];
+            if self.pending.len() < BLOCK_SIZE {
+                return;
+            }
+            let block = std::mem::take(&mut self.pending);
+            self.absorb(&block);
+        }
+        let whole = data.len() - data.len() % BLOCK_SIZE;
+        self.absorb(&data[..whole]);
+        self.pending.extend_from_slice(&data[whole..]);
+    }
+
+    /// Hash whole blocks into the state
+    fn absorb(&mut self, blocks: &[u8]) {
+        for block in blocks.chunks_exact(BLOCK_SIZE) {
+            sha2::compress256(&mut self.state, std::slice::from_ref(GenericArray::from_slice(block)));
+        }
+        self.absorbed += blocks.len() as u64;
+    }
+
+    /// State to save, covering the first [`Self::absorbed`] bytes
+    pub(crate) fn save(&self) -> String {
+        self.state.iter().map(|word| format!("{:08x}", word)).collect()
+    }
+
+    /// Hex-encoded checksum of the bytes hashed so far
+    pub(crate) fn finish(&self) -> String {
+        let length_bits = (self.absorbed + self.pending.len() as u64) * 8;
+        let mut tail = self.pending.clone();
+        tail.push(0x80);
+        while tail.len() % BLOCK_SIZE != BLOCK_SIZE - 8 {
+            tail.push(0);
+        }
+        tail.extend_from_slice(&length_bits.to_be_bytes());
+
+        let mut state = self.state;
+        for block in tail.chunks_exact(BLOCK_SIZE) {
+            sha2::compress256(&mut state, std::slice::from_ref(GenericArray::from_slice(block)));
+        }
+        state.iter().map(|word| format!("{:08x}", word)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"hello"),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+
+    #[test]
+    fn test_resumable_sha256() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+
+        for split in [0, 1, 63, 64, 65, 200, 1000] {
+            let mut hasher = ResumableSha256::new();
+            hasher.update(&data[..split]);
+            assert_eq!(hasher.finish(), sha256_hex(&data[..split]));
+
+            // Resuming re-reads the bytes after the saved whole blocks
+            let mut resumed = ResumableSha256::resume(&hasher.save(), hasher.absorbed()).unwrap();
+            resumed.update(&data[hasher.absorbed() as usize..]);
+            assert_eq!(resumed.finish(), sha256_hex(&data), "split at {}", split);
+        }
+
+        assert!(ResumableSha256::resume("not a state", 0).is_none());
+        assert!(ResumableSha256::resume(&ResumableSha256::new().save(), 10).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_hashing_reader_matches_sha256_hex() {
+        let data = vec![42u8; 100_000];
+        let mut reader = HashingReader::new(&data[..]);
+
+        let mut copied = Vec::new();
+        reader.read_to_end(&mut copied).await.unwrap();
+
+        assert_eq!(copied, data);
+        assert_eq!(reader.head(), &data[..SNIFF_LENGTH]);
+        assert_eq!(reader.finish(), sha256_hex(&data));
+    }
+
+    #[tokio::test]
+    async fn test_verifying_reader() {
+        let data = vec![7u8; 100_000];
+
+        let mut copied = Vec::new();
+        let mut reader = VerifyingReader::new(&data[..], &sha256_hex(&data).to_uppercase());
+        reader.read_to_end(&mut copied).await.unwrap();
+        assert_eq!(copied, data);
+
+        let mut reader = VerifyingReader::new(&data[..], &sha256_hex(b"other"));
+        let result = reader.read_to_end(&mut Vec::new()).await;
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,49 @@
+//! Single-use download tokens for service integrations
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::share::generate_share_token;
+
+/// Lifetime of a download token when the caller doesn't choose one
+pub const DEFAULT_DOWNLOAD_TOKEN_TTL_SECONDS: i64 = 300;
+
+/// Longest lifetime a download token may have
+pub const MAX_DOWNLOAD_TOKEN_TTL_SECONDS: i64 = 86_400;
+
+/// A token that lets its bearer download one file exactly once
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DownloadToken {
+    pub token: String,
+    pub file_id: String,
+    /// Service account that issued the token
+    pub issued_by: i64,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl DownloadToken {
+    /// Issue a token for a file, valid for `ttl`
+    pub fn new(file_id: String, issued_by: i64, ttl: Duration) -> Self {
+        let created_at = Utc::now();
+        Self {
+            token: generate_share_token(),
+            file_id,
+            issued_by,
+            expires_at: created_at + ttl,
+            created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_download_token_expiry() {
+        let token = DownloadToken::new("file-1".to_string(), 1, Duration::seconds(DEFAULT_DOWNLOAD_TOKEN_TTL_SECONDS));
+        assert_eq!(token.expires_at - token.created_at, Duration::seconds(300));
+        assert_ne!(token.token, DownloadToken::new("file-1".to_string(), 1, Duration::seconds(1)).token);
+    }
+}
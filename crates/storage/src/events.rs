@@ -0,0 +1,39 @@
+//! In-process storage events
+//!
+//! `TransactionalStorageService` broadcasts a [`StorageEvent`] whenever a file is stored or
+//! deleted, so server-side code (thumbnailers, indexers, notification senders) can react
+//! without polling the files table. Delivery is best effort: events are not persisted, and a
+//! subscriber that falls more than [`EVENT_CHANNEL_CAPACITY`] events behind misses the oldest
+//! ones and receives `RecvError::Lagged`.
+
+use crate::File;
+use serde::Serialize;
+
+/// Events buffered for slow subscribers before they start missing items
+pub const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Something that happened to a file
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StorageEvent {
+    /// A file was stored, by an upload, a copy, or an ingested drop
+    Uploaded {
+        file: File,
+        /// Source of a copy
+        copied_from: Option<String>,
+    },
+    /// A file was moved to the trash or, when `permanent`, removed with its blob
+    Deleted {
+        file: File,
+        permanent: bool,
+    },
+}
+
+impl StorageEvent {
+    /// The file the event is about
+    pub fn file(&self) -> &File {
+        match self {
+            StorageEvent::Uploaded { file, .. } | StorageEvent::Deleted { file, .. } => file,
+        }
+    }
+}
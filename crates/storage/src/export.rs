@@ -0,0 +1,176 @@
+//! Exports of a user's file inventory
+//!
+//! An export lists every file a user owns, trashed ones included, with the metadata needed to
+//! check a backup against it: names, sizes, checksums, folders, favorites and share links.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::File;
+
+/// Files read from the database per query while an export is written
+pub const EXPORT_BATCH_SIZE: i64 = 500;
+
+/// Format an export is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// A JSON array with one object per file
+    Json,
+}
+
+impl ExportFormat {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Json => "application/json",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// A share link of an exported file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExportedShare {
+    pub url: String,
+    pub has_password: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// One file of an export
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedFile {
+    pub id: String,
+    pub original_name: String,
+    pub folder: Option<String>,
+    pub size: i64,
+    pub mime_type: Option<String>,
+    pub detected_mime_type: Option<String>,
+    pub checksum: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// When the file was moved to the trash
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub download_count: i64,
+    pub last_accessed_at: Option<DateTime<Utc>>,
+    pub favorite: bool,
+    pub share_links: Vec<ExportedShare>,
+}
+
+/// Columns of a CSV export, in order
+const CSV_COLUMNS: &[&str] = &[
+    "id",
+    "original_name",
+    "folder",
+    "size",
+    "mime_type",
+    "detected_mime_type",
+    "checksum",
+    "created_at",
+    "deleted_at",
+    "download_count",
+    "last_accessed_at",
+    "favorite",
+    "share_links",
+];
+
+impl ExportedFile {
+    pub(crate) fn new(file: File, favorite: bool, share_links: Vec<ExportedShare>) -> Self {
+        Self {
+            id: file.id.unwrap_or_default(),
+            original_name: file.original_name,
+            folder: file.folder,
+            size: file.size,
+            mime_type: file.mime_type,
+            detected_mime_type: file.detected_mime_type,
+            checksum: file.checksum,
+            created_at: file.created_at,
+            deleted_at: file.deleted_at,
+            download_count: file.download_count,
+            last_accessed_at: file.last_accessed_at,
+            favorite,
+            share_links,
+        }
+    }
+
+    /// The header line of a CSV export
+    pub fn csv_header() -> String {
+        format!("{}\r\n", CSV_COLUMNS.join(","))
+    }
+
+    /// The file as a CSV line; share links are separated by spaces
+    pub fn csv_row(&self) -> String {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+        let timestamp = |value: &Option<DateTime<Utc>>| value.map(|t| t.to_rfc3339()).unwrap_or_default();
+        let share_links: Vec<&str> = self.share_links.iter().map(|share| share.url.as_str()).collect();
+
+        let fields = [
+            self.id.clone(),
+            self.original_name.clone(),
+            optional(&self.folder),
+            self.size.to_string(),
+            optional(&self.mime_type),
+            optional(&self.detected_mime_type),
+            optional(&self.checksum),
+            self.created_at.to_rfc3339(),
+            timestamp(&self.deleted_at),
+            self.download_count.to_string(),
+            timestamp(&self.last_accessed_at),
+            self.favorite.to_string(),
+            share_links.join(" "),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        format!("{}\r\n", fields.join(","))
+    }
+}
+
+/// Quote a CSV field when it needs it
+/// Fields starting with a formula character are prefixed with `'`, so spreadsheets don't evaluate
+/// file names that users chose
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_fields() {
+        assert_eq!(csv_field("report.pdf"), "report.pdf");
+        assert_eq!(csv_field("a, b.txt"), "\"a, b.txt\"");
+        assert_eq!(csv_field("say \"hi\".txt"), "\"say \"\"hi\"\".txt\"");
+        assert_eq!(csv_field("=SUM(A1).csv"), "'=SUM(A1).csv");
+        assert_eq!(ExportedFile::csv_header().split(',').count(), CSV_COLUMNS.len());
+    }
+
+    #[test]
+    fn test_export_formats() {
+        assert_eq!(ExportFormat::from_str("CSV"), Some(ExportFormat::Csv));
+        assert_eq!(ExportFormat::from_str("json"), Some(ExportFormat::Json));
+        assert_eq!(ExportFormat::from_str("xml"), None);
+    }
+}
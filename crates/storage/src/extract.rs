@@ -0,0 +1,290 @@
+//! Plain text of documents, for searching files by content
+//!
+//! Text files are taken as they are, Office and OpenDocument files are unzipped and their XML
+//! stripped of markup, and PDFs are parsed when the crate is built with the `pdf` feature.
+//! The text is kept in its own table next to the checksum of the content it came from, so a
+//! file is extracted again once its content changes.
+
+use crate::{File, Result, StorageError};
+use async_zip::base::read::mem::ZipFileReader;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+/// Table of the text extracted from each file
+pub const FILE_TEXTS_TABLE: &str = "file_texts";
+
+/// Most bytes of text kept per file; the rest isn't searchable
+pub const MAX_TEXT_LENGTH: usize = 1024 * 1024;
+
+/// Most bytes read from one document part inside an Office or OpenDocument file
+const MAX_PART_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Outcome of extracting a file's text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtractionStatus {
+    /// The text was extracted
+    Extracted,
+    /// Not a kind of file text is extracted from, or too large to extract
+    Unsupported,
+    /// The file looked like a document but couldn't be read as one
+    Failed,
+}
+
+impl ExtractionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExtractionStatus::Extracted => "extracted",
+            ExtractionStatus::Unsupported => "unsupported",
+            ExtractionStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Outcome of one extraction pass
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TextExtraction {
+    pub extracted: u64,
+    pub unsupported: u64,
+    pub failed: u64,
+    /// Files that could not be read or recorded, such as when their storage was unreachable;
+    /// files that couldn't be read are counted as failed too
+    pub errors: u64,
+}
+
+/// Kinds of documents text is extracted from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DocumentKind {
+    Text,
+    /// Word, Excel, and PowerPoint files, and OpenDocument text, spreadsheets, and presentations
+    OfficeXml,
+    Pdf,
+}
+
+impl DocumentKind {
+    /// Kind of a file by its content type, falling back to its extension for generic types
+    pub(crate) fn of(file: &File) -> Option<Self> {
+        let content_type = file.content_type().unwrap_or_default().to_ascii_lowercase();
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        let extension = file.original_name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+
+        match essence {
+            "application/pdf" => return Some(DocumentKind::Pdf),
+            "application/json" | "application/xml" | "application/x-yaml" | "application/yaml" => {
+                return Some(DocumentKind::Text)
+            }
+            essence if essence.starts_with("text/") => return Some(DocumentKind::Text),
+            essence if essence.starts_with("application/vnd.openxmlformats-officedocument.")
+                || essence.starts_with("application/vnd.oasis.opendocument.") =>
+            {
+                return Some(DocumentKind::OfficeXml)
+            }
+            "" | "application/octet-stream" | "application/zip" => {}
+            _ => return None,
+        }
+
+        match extension.as_deref()? {
+            "txt" | "md" | "csv" | "tsv" | "json" | "xml" | "yaml" | "yml" | "log" | "html" | "htm" => {
+                Some(DocumentKind::Text)
+            }
+            "docx" | "xlsx" | "pptx" | "odt" | "ods" | "odp" => Some(DocumentKind::OfficeXml),
+            "pdf" => Some(DocumentKind::Pdf),
+            _ => None,
+        }
+    }
+}
+
+/// Plain text of a document's content, cut to [`MAX_TEXT_LENGTH`]
+pub(crate) async fn extract_text(kind: DocumentKind, data: Vec<u8>) -> Result<String> {
+    let text = match kind {
+        DocumentKind::Text => String::from_utf8_lossy(&data).into_owned(),
+        DocumentKind::OfficeXml => office_text(data).await?,
+        DocumentKind::Pdf => pdf_text(data).await?,
+    };
+    Ok(truncate(collapse_whitespace(&text), MAX_TEXT_LENGTH))
+}
+
+/// Text of the document parts of an Office Open XML or OpenDocument file
+async fn office_text(data: Vec<u8>) -> Result<String> {
+    let zip_error = |e: async_zip::error::ZipError| StorageError::InvalidInput(format!("Invalid document: {}", e));
+
+    let zip = ZipFileReader::new(data).await.map_err(zip_error)?;
+    let mut parts = Vec::new();
+    for (index, entry) in zip.file().entries().iter().enumerate() {
+        let name = entry.filename().as_str().map_err(zip_error)?;
+        if is_text_part(name) {
+            parts.push((name.to_string(), index));
+        }
+    }
+    // Slides and sheets in their order, not the archive's
+    parts.sort_by(|(a, _), (b, _)| natural_key(a).cmp(&natural_key(b)));
+
+    let mut text = String::new();
+    for (_, index) in parts {
+        let reader = zip.reader_without_entry(index).await.map_err(zip_error)?;
+        let mut xml = String::new();
+        reader.compat().take(MAX_PART_SIZE).read_to_string(&mut xml).await
+            .map_err(|e| StorageError::InvalidInput(format!("Invalid document: {}", e)))?;
+        text.push_str(&xml_text(&xml));
+        text.push('\n');
+        if text.len() > MAX_TEXT_LENGTH {
+            break;
+        }
+    }
+    Ok(text)
+}
+
+/// Whether a part of an Office or OpenDocument file holds its visible text
+fn is_text_part(name: &str) -> bool {
+    name == "word/document.xml"
+        || name == "xl/sharedStrings.xml"
+        || (name.starts_with("ppt/slides/slide") && name.ends_with(".xml"))
+        || name == "content.xml"
+}
+
+/// Sort key that puts `slide2.xml` before `slide10.xml`
+fn natural_key(name: &str) -> (String, u64) {
+    let stem = name.trim_end_matches(".xml");
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (prefix, number) = stem.split_at(stem.len() - digits);
+    (prefix.to_string(), number.parse().unwrap_or(0))
+}
+
+/// Character data of an XML document, with paragraphs, breaks, and tabs turned into whitespace
+fn xml_text(xml: &str) -> String {
+    let mut text = String::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..start]));
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        let name = tag.trim_start_matches('/').split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default();
+        let local_name = name.rsplit(':').next().unwrap_or_default();
+        match local_name {
+            "p" | "h" | "si" | "br" | "cr" | "line-break" => text.push('\n'),
+            "tab" | "s" => text.push(' '),
+            _ => {}
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text
+}
+
+/// Replace the predefined XML entities and numeric character references
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find(';') else {
+            decoded.push_str(&rest[start..]);
+            return decoded;
+        };
+        let entity = &rest[start + 1..start + end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match character {
+            Some(character) => decoded.push(character),
+            None => decoded.push_str(&rest[start..start + end + 1]),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(feature = "pdf")]
+async fn pdf_text(data: Vec<u8>) -> Result<String> {
+    // Parsing is CPU-bound, so it is kept off the async workers
+    tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&data))
+        .await
+        .map_err(|e| StorageError::StorageError(format!("PDF extraction panicked: {}", e)))?
+        .map_err(|e| StorageError::InvalidInput(format!("Invalid PDF: {}", e)))
+}
+
+#[cfg(not(feature = "pdf"))]
+async fn pdf_text(_data: Vec<u8>) -> Result<String> {
+    Err(StorageError::InvalidInput(
+        "PDF text extraction requires building with the `pdf` feature".to_string(),
+    ))
+}
+
+/// Whether text can be extracted from documents of `kind` in this build
+pub(crate) fn is_supported(kind: DocumentKind) -> bool {
+    kind != DocumentKind::Pdf || cfg!(feature = "pdf")
+}
+
+/// Lines of text with runs of spaces and blank lines squeezed out
+fn collapse_whitespace(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Cut a string to at most `max` bytes, on a character boundary
+fn truncate(mut text: String, max: usize) -> String {
+    if text.len() > max {
+        let mut end = max;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, mime_type: Option<&str>) -> File {
+        File::new("f1".to_string(), 1, name.to_string(), name.to_string(), 0, mime_type.map(str::to_string), String::new(), None)
+    }
+
+    #[test]
+    fn test_document_kinds() {
+        assert_eq!(DocumentKind::of(&file("notes.txt", Some("text/plain"))), Some(DocumentKind::Text));
+        assert_eq!(DocumentKind::of(&file("report.pdf", Some("application/pdf"))), Some(DocumentKind::Pdf));
+        assert_eq!(DocumentKind::of(&file("letter.docx", Some("application/zip"))), Some(DocumentKind::OfficeXml));
+        assert_eq!(DocumentKind::of(&file("data.csv", None)), Some(DocumentKind::Text));
+        assert_eq!(DocumentKind::of(&file("photo.txt", Some("image/png"))), None);
+        assert_eq!(DocumentKind::of(&file("archive.zip", Some("application/zip"))), None);
+    }
+
+    #[test]
+    fn test_xml_text() {
+        let xml = r#"<w:document><w:body><w:p><w:r><w:t>Quarterly</w:t></w:r><w:r><w:t xml:space="preserve"> report</w:t></w:r></w:p><w:p><w:r><w:t>Fish &amp; chips&#33;</w:t><w:tab/><w:t>done</w:t></w:r></w:p></w:body></w:document>"#;
+        assert_eq!(collapse_whitespace(&xml_text(xml)), "Quarterly report\nFish & chips! done");
+    }
+
+    #[test]
+    fn test_part_order() {
+        let mut parts = vec!["ppt/slides/slide10.xml", "ppt/slides/slide2.xml", "ppt/slides/slide1.xml"];
+        parts.sort_by_key(|name| natural_key(name));
+        assert_eq!(parts, ["ppt/slides/slide1.xml", "ppt/slides/slide2.xml", "ppt/slides/slide10.xml"]);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("héllo".to_string(), 2), "h");
+        assert_eq!(truncate("hello".to_string(), 10), "hello");
+    }
+}
@@ -0,0 +1,56 @@
+//! Legal holds on files and records
+//!
+//! A hold names a row by table and primary key; files are held as rows of the `files` table.
+//! While a hold is active the row can't be deleted, by its owner, an admin, or the purge of
+//! deleted accounts. Released holds are kept, so the table doubles as the hold history.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Table of placed and released holds
+pub const LEGAL_HOLDS_TABLE: &str = "legal_holds";
+
+/// A hold on one row of a table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LegalHold {
+    pub id: i64,
+    /// Table of the held row, e.g. `files`
+    pub table_name: String,
+    /// Primary key of the held row
+    pub record_id: String,
+    /// Why the row is held, such as a case reference
+    pub reason: String,
+    pub placed_by: i64,
+    pub placed_at: DateTime<Utc>,
+    pub released_by: Option<i64>,
+    pub released_at: Option<DateTime<Utc>>,
+}
+
+impl LegalHold {
+    /// Whether the hold still blocks deletion
+    pub fn is_active(&self) -> bool {
+        self.released_at.is_none()
+    }
+
+    /// Read a hold from a database row
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        let string = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+        let timestamp = |column: &str| {
+            row.get(column)
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+
+        Some(Self {
+            id: row.get("id").and_then(|v| v.as_i64())?,
+            table_name: string("table_name")?,
+            record_id: string("record_id")?,
+            reason: string("reason").unwrap_or_default(),
+            placed_by: row.get("placed_by").and_then(|v| v.as_i64())?,
+            placed_at: timestamp("placed_at").unwrap_or_else(Utc::now),
+            released_by: row.get("released_by").and_then(|v| v.as_i64()),
+            released_at: timestamp("released_at"),
+        })
+    }
+}
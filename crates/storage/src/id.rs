@@ -0,0 +1,110 @@
+//! File ID generation strategies
+
+use crate::{Result, StorageError};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Longest ID the files table can hold
+pub const MAX_FILE_ID_LENGTH: usize = 36;
+
+/// Shortest nanoid accepted, to keep collisions out of reach
+pub const MIN_NANOID_LENGTH: usize = 12;
+
+/// Length of a nanoid when none is configured
+pub const DEFAULT_NANOID_LENGTH: usize = 21;
+
+/// URL- and filename-safe alphabet nanoids are drawn from
+const NANOID_ALPHABET: &[u8] = b"_-0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// How new file IDs are generated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileIdStrategy {
+    /// Random UUID, e.g. `550e8400-e29b-41d4-a716-446655440000`
+    #[default]
+    UuidV4,
+    /// Time-ordered UUID, so IDs sort by creation time
+    UuidV7,
+    /// 26-character time-ordered ID, e.g. `01JAB3Q9W8X6E4YQ9R7KZ2M5VD`
+    Ulid,
+    /// Random URL-safe ID of the given length
+    Nanoid { length: usize },
+}
+
+impl FileIdStrategy {
+    /// A nanoid strategy, checking that IDs of `length` fit the files table and won't collide
+    pub fn nanoid(length: usize) -> Result<Self> {
+        if !(MIN_NANOID_LENGTH..=MAX_FILE_ID_LENGTH).contains(&length) {
+            return Err(StorageError::InvalidInput(format!(
+                "nanoid length must be between {} and {}, got {}",
+                MIN_NANOID_LENGTH, MAX_FILE_ID_LENGTH, length
+            )));
+        }
+        Ok(Self::Nanoid { length })
+    }
+
+    /// Generate a new file ID
+    pub fn generate(&self) -> String {
+        match self {
+            Self::UuidV4 => Uuid::new_v4().to_string(),
+            Self::UuidV7 => Uuid::now_v7().to_string(),
+            Self::Ulid => ulid::Ulid::new().to_string(),
+            Self::Nanoid { length } => {
+                let mut rng = rand::thread_rng();
+                (0..*length)
+                    .map(|_| NANOID_ALPHABET[rng.gen_range(0..NANOID_ALPHABET.len())] as char)
+                    .collect()
+            }
+        }
+    }
+
+    /// Whether `id` has the shape of IDs this strategy generates
+    pub fn is_generated_id(&self, id: &str) -> bool {
+        match self {
+            Self::UuidV4 | Self::UuidV7 => Uuid::parse_str(id).is_ok(),
+            Self::Ulid => id.len() == ulid::ULID_LEN && ulid::Ulid::from_string(id).is_ok(),
+            Self::Nanoid { length } => id.len() == *length && id.bytes().all(|b| NANOID_ALPHABET.contains(&b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        for strategy in [
+            FileIdStrategy::UuidV4,
+            FileIdStrategy::UuidV7,
+            FileIdStrategy::Ulid,
+            FileIdStrategy::nanoid(16).unwrap(),
+        ] {
+            let id = strategy.generate();
+            assert!(id.len() <= MAX_FILE_ID_LENGTH);
+            assert!(strategy.is_generated_id(&id), "{:?} generated {}", strategy, id);
+            assert!(crate::validate_key(&id).is_ok());
+            assert_ne!(id, strategy.generate());
+        }
+
+        assert_eq!(FileIdStrategy::Ulid.generate().len(), 26);
+        assert_eq!(FileIdStrategy::nanoid(16).unwrap().generate().len(), 16);
+    }
+
+    #[test]
+    fn test_time_ordered_ids_sort() {
+        for strategy in [FileIdStrategy::UuidV7, FileIdStrategy::Ulid] {
+            let first = strategy.generate();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            assert!(first < strategy.generate());
+        }
+    }
+
+    #[test]
+    fn test_nanoid_length_limits() {
+        assert!(FileIdStrategy::nanoid(MIN_NANOID_LENGTH - 1).is_err());
+        assert!(FileIdStrategy::nanoid(MAX_FILE_ID_LENGTH + 1).is_err());
+        assert!(FileIdStrategy::nanoid(DEFAULT_NANOID_LENGTH).is_ok());
+    }
+}
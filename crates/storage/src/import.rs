@@ -0,0 +1,314 @@
+//! Importing files from remote URLs
+//!
+//! The server fetches the URL itself, so without care a request could reach services on its
+//! own network. Only `http` and `https` URLs are fetched, every address their host resolves to
+//! must be public, and the connection is pinned to the addresses that were checked so the name
+//! can't be re-resolved somewhere else. Redirects are followed one at a time and each hop is
+//! checked the same way.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::mime::{detect_mime_type, SNIFF_LENGTH};
+use crate::policy::{matches_any, PolicyRule, PolicyViolation};
+use crate::Result;
+
+/// A file downloaded from a remote URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteFile {
+    pub data: Vec<u8>,
+    /// Last segment of the URL's path, if it has one
+    pub file_name: Option<String>,
+    /// Content type the remote server declared
+    pub mime_type: Option<String>,
+}
+
+/// Downloads remote files
+#[async_trait]
+pub trait RemoteFetcher: Send + Sync {
+    /// Download `url`, giving up with a [`PolicyViolation`] once more than `max_size` bytes arrive
+    async fn fetch(&self, url: &str, max_size: u64) -> Result<RemoteFile>;
+}
+
+/// Fetches files for `POST /files/import` and checks them against the import limits
+pub struct UrlImporter {
+    fetcher: Arc<dyn RemoteFetcher>,
+    max_size: u64,
+    allowed_mime_types: Vec<String>,
+}
+
+impl UrlImporter {
+    pub fn new(fetcher: Arc<dyn RemoteFetcher>, max_size: u64) -> Self {
+        Self {
+            fetcher,
+            max_size,
+            allowed_mime_types: Vec::new(),
+        }
+    }
+
+    /// Only import files of these MIME types; `type/*` matches a whole family
+    pub fn with_allowed_mime_types(mut self, allowed_mime_types: Vec<String>) -> Self {
+        self.allowed_mime_types = allowed_mime_types;
+        self
+    }
+
+    /// Download `url` within the import limits
+    /// The type detected from the content is checked when it has a signature, and the type the
+    /// remote server declared otherwise.
+    pub async fn fetch(&self, url: &str) -> Result<RemoteFile> {
+        let remote = self.fetcher.fetch(url, self.max_size).await?;
+        if remote.data.len() as u64 > self.max_size {
+            return Err(too_large(self.max_size).into());
+        }
+
+        if !self.allowed_mime_types.is_empty() {
+            let head = &remote.data[..remote.data.len().min(SNIFF_LENGTH)];
+            let content_type = detect_mime_type(head).or(remote.mime_type.as_deref());
+            let allowed = content_type.is_some_and(|mime_type| matches_any(&self.allowed_mime_types, mime_type));
+            if !allowed {
+                return Err(PolicyViolation::new(
+                    PolicyRule::MimeType,
+                    format!(
+                        "content type {} can't be imported; allowed types: {}",
+                        content_type.unwrap_or("unknown"),
+                        self.allowed_mime_types.join(", ")
+                    ),
+                ).into());
+            }
+        }
+        Ok(remote)
+    }
+}
+
+fn too_large(max_size: u64) -> PolicyViolation {
+    PolicyViolation::new(
+        PolicyRule::MaxSize,
+        format!("file is larger than {} bytes, the most an import may be", max_size),
+    )
+}
+
+/// Whether an address is on the public internet
+/// Private, loopback, link-local, shared (carrier-grade NAT), documentation, benchmarking,
+/// multicast and reserved ranges are not. IPv6 addresses that carry an IPv4 address (mapped,
+/// NAT64 and 6to4) are judged by the IPv4 address they lead to.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (b & 0xc0) == 64)
+                || (a == 192 && b == 0 && ip.octets()[2] == 0)
+                || (a == 198 && (b & 0xfe) == 18))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(ip));
+            }
+            let segments = ip.segments();
+            let first = segments[0];
+            let embedded = |high: u16, low: u16| IpAddr::V4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)));
+            // Well-known NAT64 (64:ff9b::/96) keeps the address in the last 32 bits
+            if first == 0x0064 && segments[1] == 0xff9b && segments[2..6] == [0; 4] {
+                return is_public_address(embedded(segments[6], segments[7]));
+            }
+            // 6to4 (2002::/16) keeps it right after the prefix
+            if first == 0x2002 {
+                return is_public_address(embedded(segments[1], segments[2]));
+            }
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x2001 && segments[1] == 0x0db8)
+                // Local-use NAT64 (64:ff9b:1::/48) translates however its operator set it up
+                || (first == 0x0064 && segments[1] == 0xff9b && segments[2] == 0x0001))
+        }
+    }
+}
+
+/// Last segment of a URL's path, ignoring its query and fragment
+pub fn file_name_from_url(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let (_, path) = rest.split_once('/')?;
+    path.rsplit('/').next().filter(|name| !name.is_empty()).map(str::to_string)
+}
+
+/// Fetches files over HTTP(S), refusing anything that resolves to a non-public address
+#[cfg(feature = "import")]
+pub struct HttpFetcher {
+    timeout: std::time::Duration,
+    max_redirects: u32,
+}
+
+#[cfg(feature = "import")]
+impl HttpFetcher {
+    /// Downloads taking longer than `timeout` fail
+    pub fn new(timeout: std::time::Duration, max_redirects: u32) -> Self {
+        Self { timeout, max_redirects }
+    }
+
+    /// Resolve the host of `url`, failing unless it is fetched over HTTP(S) from public addresses
+    async fn check_url(url: &reqwest::Url) -> Result<Vec<std::net::SocketAddr>> {
+        use crate::StorageError;
+
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(StorageError::InvalidInput(format!("only http and https URLs can be imported, not {}", url.scheme())));
+        }
+        let host = url.host_str()
+            .ok_or_else(|| StorageError::InvalidInput(format!("{} has no host", url)))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        let addrs: Vec<_> = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            Ok(ip) => vec![std::net::SocketAddr::new(ip, port)],
+            Err(_) => tokio::net::lookup_host((host, port)).await
+                .map_err(|e| StorageError::InvalidInput(format!("failed to resolve {}: {}", host, e)))?
+                .collect(),
+        };
+        if addrs.is_empty() {
+            return Err(StorageError::InvalidInput(format!("{} has no addresses", host)));
+        }
+        if addrs.iter().any(|addr| !is_public_address(addr.ip())) {
+            return Err(StorageError::PermissionDenied(format!("{} resolves to a non-public address", host)));
+        }
+        Ok(addrs)
+    }
+}
+
+#[cfg(feature = "import")]
+#[async_trait]
+impl RemoteFetcher for HttpFetcher {
+    async fn fetch(&self, url: &str, max_size: u64) -> Result<RemoteFile> {
+        use crate::StorageError;
+
+        let mut url = reqwest::Url::parse(url)
+            .map_err(|e| StorageError::InvalidInput(format!("invalid URL: {}", e)))?;
+        let mut redirects = 0;
+
+        let mut response = loop {
+            let addrs = Self::check_url(&url).await?;
+
+            // Connect only to the addresses just checked, and never through a proxy
+            let mut builder = reqwest::Client::builder()
+                .timeout(self.timeout)
+                .redirect(reqwest::redirect::Policy::none())
+                .no_proxy();
+            if let Some(domain) = url.domain() {
+                builder = builder.resolve_to_addrs(domain, &addrs);
+            }
+            let client = builder.build()
+                .map_err(|e| StorageError::StorageError(format!("Failed to build HTTP client: {}", e)))?;
+
+            let response = client.get(url.clone()).send().await
+                .map_err(|e| StorageError::InvalidInput(format!("failed to fetch {}: {}", url, e)))?;
+            if !response.status().is_redirection() {
+                break response;
+            }
+
+            redirects += 1;
+            if redirects > self.max_redirects {
+                return Err(StorageError::InvalidInput(format!("{} redirected more than {} times", url, self.max_redirects)));
+            }
+            let location = response.headers().get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| StorageError::InvalidInput(format!("{} redirected without a location", url)))?;
+            url = url.join(location)
+                .map_err(|e| StorageError::InvalidInput(format!("invalid redirect from {}: {}", url, e)))?;
+        };
+
+        if !response.status().is_success() {
+            return Err(StorageError::InvalidInput(format!("{} answered {}", url, response.status())));
+        }
+        if response.content_length().is_some_and(|length| length > max_size) {
+            return Err(too_large(max_size).into());
+        }
+
+        let mime_type = response.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .map(str::to_string);
+        let file_name = file_name_from_url(url.as_str());
+
+        // The declared length can't be trusted, so the limit is enforced while reading
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await
+            .map_err(|e| StorageError::InvalidInput(format!("failed to read {}: {}", url, e)))?
+        {
+            if (data.len() + chunk.len()) as u64 > max_size {
+                return Err(too_large(max_size).into());
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(RemoteFile { data, file_name, mime_type })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StorageError;
+
+    struct FakeFetcher(RemoteFile);
+
+    #[async_trait]
+    impl RemoteFetcher for FakeFetcher {
+        async fn fetch(&self, _url: &str, _max_size: u64) -> Result<RemoteFile> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_is_public_address() {
+        for ip in ["10.0.0.1", "127.0.0.1", "169.254.169.254", "172.16.0.1", "192.168.1.1", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "64:ff9b::7f00:1", "64:ff9b::a9fe:a9fe", "64:ff9b:1::a00:1", "2002:a00:1::1", "2002:c0a8:101::"] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{} is not public", ip);
+        }
+        for ip in ["93.184.216.34", "8.8.8.8", "2606:4700::1111", "64:ff9b::808:808", "2002:808:808::1"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{} is public", ip);
+        }
+    }
+
+    #[test]
+    fn test_file_name_from_url() {
+        assert_eq!(file_name_from_url("https://example.com/reports/q3.pdf?download=1").as_deref(), Some("q3.pdf"));
+        assert_eq!(file_name_from_url("https://example.com/reports/"), None);
+        assert_eq!(file_name_from_url("https://example.com"), None);
+    }
+
+    #[tokio::test]
+    async fn test_import_limits() {
+        let png = RemoteFile {
+            data: b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec(),
+            file_name: Some("logo.png".to_string()),
+            mime_type: Some("text/plain".to_string()),
+        };
+
+        // The detected type wins over the declared one
+        let importer = UrlImporter::new(Arc::new(FakeFetcher(png.clone())), 1024)
+            .with_allowed_mime_types(vec!["image/*".to_string()]);
+        assert_eq!(importer.fetch("https://example.com/logo.png").await.unwrap(), png);
+
+        let importer = UrlImporter::new(Arc::new(FakeFetcher(png.clone())), 1024)
+            .with_allowed_mime_types(vec!["application/pdf".to_string()]);
+        assert!(matches!(
+            importer.fetch("https://example.com/logo.png").await,
+            Err(StorageError::PolicyViolation(PolicyViolation { rule: PolicyRule::MimeType, .. }))
+        ));
+
+        let importer = UrlImporter::new(Arc::new(FakeFetcher(png)), 4);
+        assert!(matches!(
+            importer.fetch("https://example.com/logo.png").await,
+            Err(StorageError::PolicyViolation(PolicyViolation { rule: PolicyRule::MaxSize, .. }))
+        ));
+    }
+}
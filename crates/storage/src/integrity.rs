@@ -0,0 +1,65 @@
+//! Integrity audits of stored files
+//!
+//! An audit re-reads stored blobs, hashes them and compares the result with the checksum
+//! recorded when they were written. The outcome is kept on each file as its `integrity_status`,
+//! so corruption found by one audit stays flagged until the file is rewritten or checked again.
+
+use serde::{Deserialize, Serialize};
+
+use crate::File;
+
+/// Outcome of the latest integrity check of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityStatus {
+    /// The blob matches its recorded checksum
+    Ok,
+    /// The blob no longer matches its recorded checksum
+    Corrupted,
+    /// The blob is gone from storage
+    Missing,
+}
+
+impl IntegrityStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntegrityStatus::Ok => "ok",
+            IntegrityStatus::Corrupted => "corrupted",
+            IntegrityStatus::Missing => "missing",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "ok" => Some(IntegrityStatus::Ok),
+            "corrupted" => Some(IntegrityStatus::Corrupted),
+            "missing" => Some(IntegrityStatus::Missing),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of one audit pass
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IntegrityAudit {
+    /// Files whose blob was hashed
+    pub checked: u64,
+    /// IDs of files whose blob no longer matches its checksum
+    pub corrupted: Vec<String>,
+    /// IDs of files whose blob is missing
+    pub missing: Vec<String>,
+    /// Files that could not be checked this time, such as when their storage was unreachable
+    pub errors: u64,
+}
+
+/// Integrity of every audited file, as recorded by past audits
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IntegrityReport {
+    pub ok: u64,
+    pub corrupted: u64,
+    pub missing: u64,
+    /// Files with a checksum that no audit has checked since they were last written
+    pub unchecked: u64,
+    /// Files whose latest check found them corrupted or missing
+    pub flagged: Vec<File>,
+}
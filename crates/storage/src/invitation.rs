@@ -0,0 +1,108 @@
+//! Files shared with a person by email
+//!
+//! An invitation gives one recipient read access to one file. It is addressed by email, so a
+//! file can be shared with someone who has no account yet: the invitation stays pending until
+//! an account is registered under that address, and is accepted automatically then.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Table of invitations, pending and accepted
+pub const SHARE_INVITATIONS_TABLE: &str = "share_invitations";
+
+/// A file shared with one recipient
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ShareInvitation {
+    pub id: i64,
+    pub file_id: String,
+    /// Owner of the file, who sent the invitation
+    pub owner_id: i64,
+    /// Address the invitation was sent to, lowercased
+    pub email: String,
+    /// Account of the recipient, once they have one
+    pub recipient_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+}
+
+impl ShareInvitation {
+    /// Whether the recipient still has to sign up before they can open the file
+    pub fn is_pending(&self) -> bool {
+        self.recipient_id.is_none()
+    }
+
+    /// Read an invitation from a database row
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        let string = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+        let timestamp = |column: &str| {
+            row.get(column)
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+
+        Some(Self {
+            id: row.get("id").and_then(|v| v.as_i64())?,
+            file_id: string("file_id")?,
+            owner_id: row.get("owner_id").and_then(|v| v.as_i64())?,
+            email: string("email")?,
+            recipient_id: row.get("recipient_id").and_then(|v| v.as_i64()),
+            created_at: timestamp("created_at").unwrap_or_else(Utc::now),
+            accepted_at: timestamp("accepted_at"),
+        })
+    }
+}
+
+/// Email addresses are matched case-insensitively, so invitations store them lowercased
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Whether an address is plausible enough to send an invitation to
+pub(crate) fn is_valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+                && !domain.contains('@')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invitation_emails() {
+        assert_eq!(normalize_email("  Bob@Example.COM "), "bob@example.com");
+        assert!(is_valid_email("bob@example.com"));
+        assert!(!is_valid_email("bob"));
+        assert!(!is_valid_email("@example.com"));
+        assert!(!is_valid_email("bob@localhost"));
+        assert!(!is_valid_email("bob@example.com@evil.com"));
+        assert!(!is_valid_email("bob smith@example.com"));
+    }
+
+    #[test]
+    fn test_invitation_from_json() {
+        let row = serde_json::json!({
+            "id": 3,
+            "file_id": "file-1",
+            "owner_id": 7,
+            "email": "bob@example.com",
+            "recipient_id": null,
+            "created_at": "2025-10-18T00:00:00+00:00",
+            "accepted_at": null
+        });
+
+        let invitation = ShareInvitation::from_json(&row).unwrap();
+        assert_eq!(invitation.owner_id, 7);
+        assert!(invitation.is_pending());
+        assert!(ShareInvitation::from_json(&serde_json::json!({"id": 3})).is_none());
+    }
+}
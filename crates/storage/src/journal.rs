@@ -0,0 +1,95 @@
+//! Write-ahead journal of uploads in progress
+//!
+//! Storing a file takes two writes that can't share a transaction: the blob goes to the storage
+//! backend and the metadata row to the database. Before the blob is written, an entry naming it
+//! is added to the journal, and the entry is removed once both writes have settled. An entry
+//! that is still there after a crash marks a write that may have been cut off halfway; recovery
+//! keeps the blob if its metadata was recorded and removes it otherwise.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::PendingBlob;
+
+/// Table of journaled uploads
+pub const UPLOAD_JOURNAL_TABLE: &str = "upload_journal";
+
+/// An upload whose blob and metadata may not have converged yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JournalEntry {
+    /// Stored name of the blob being written
+    pub stored_name: String,
+    pub user_id: i64,
+    /// Location of the storage the blob is written to, as [`crate::StorageService::location`]
+    pub storage_path: String,
+    /// Set when the blob is written as a pending blob, to the time its pending name carries
+    pub pending_since: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl JournalEntry {
+    /// The pending blob the entry refers to, in `tenant`'s scope, if it is written as one
+    pub fn pending_blob(&self, tenant: Option<&str>) -> Option<PendingBlob> {
+        self.pending_since.map(|created_at| PendingBlob {
+            tenant: tenant.map(str::to_string),
+            stored_name: self.stored_name.clone(),
+            created_at,
+        })
+    }
+
+    /// Read an entry from a database row
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        let timestamp = |column: &str| {
+            row.get(column)
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+
+        Some(Self {
+            stored_name: row.get("stored_name").and_then(|v| v.as_str())?.to_string(),
+            user_id: row.get("user_id").and_then(|v| v.as_i64())?,
+            storage_path: row.get("storage_path").and_then(|v| v.as_str())?.to_string(),
+            pending_since: timestamp("pending_since"),
+            created_at: timestamp("created_at").unwrap_or_else(Utc::now),
+        })
+    }
+}
+
+/// Outcome of recovering journaled uploads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct JournalRecovery {
+    /// Uploads whose metadata was recorded; their blob was kept, and committed if pending
+    pub completed: u64,
+    /// Uploads without metadata; their blob was removed
+    pub rolled_back: u64,
+    /// Entries left for a later pass, because their storage could not be reached as journaled
+    /// or settling them failed
+    pub unresolved: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_entry_from_json() {
+        let row = serde_json::json!({
+            "id": 1,
+            "stored_name": "abc.txt",
+            "user_id": 7,
+            "storage_path": "./storage",
+            "pending_since": "2025-10-18T00:00:00.123+00:00",
+            "created_at": "2025-10-18T00:00:00+00:00"
+        });
+
+        let entry = JournalEntry::from_json(&row).unwrap();
+        let pending = entry.pending_blob(Some("tenant-7")).unwrap();
+        assert_eq!(pending.stored_name, "abc.txt");
+        assert_eq!(pending.created_at.timestamp_subsec_millis(), 123);
+
+        let row = serde_json::json!({"stored_name": "abc.txt", "user_id": 7, "storage_path": "./storage", "pending_since": null});
+        assert!(JournalEntry::from_json(&row).unwrap().pending_blob(None).is_none());
+        assert!(JournalEntry::from_json(&serde_json::json!({"stored_name": "abc.txt"})).is_none());
+    }
+}
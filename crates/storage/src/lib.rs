@@ -1,25 +1,106 @@
 //! Storage module for file management
 //! 
 //! Provides functionality for:
-//! - Uploading files to local filesystem
-//! - Downloading files
+//! - Uploading files to local filesystem, S3-compatible object storage, or Google Cloud Storage
+//! - Routing a user's files to a bucket they registered themselves
+//! - Resumable uploads in chunks, tracked in the database
+//! - Presigned URLs that upload one file without the uploader's credentials
+//! - Importing files from remote URLs
+//! - Downloading files, one at a time or as a zip archive
+//! - Sharing files by public link, with other users for reading, writing or co-owning, or with
+//!   people by email before they have an account
+//! - Transferring a file's ownership to another user
+//! - Files owned by a team through organizations
 //! - Deleting files
 //! - Listing files
 //! - Metadata tracking with database persistence
+//! - Image dimensions and video and audio durations read at upload time
+//! - Webhooks on file events
+//! - In-process events for subscribers to uploads and deletes
 
+pub mod archive;
+pub mod backend;
+pub mod bucket;
+pub mod checksum;
+pub mod download_token;
+pub mod events;
+pub mod export;
+pub mod extract;
+pub mod hold;
+pub mod id;
+pub mod import;
+pub mod integrity;
+pub mod invitation;
+pub mod journal;
+pub mod limits;
+pub mod media;
+pub mod migrate;
+pub mod mime;
 pub mod model;
+pub mod organization;
+pub mod permission;
+pub mod policy;
+pub mod presign;
+pub mod report;
 pub mod service;
+pub mod share;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+pub mod transfer;
+pub mod upload;
+#[cfg(feature = "watch")]
+pub mod watcher;
+pub mod webhook;
 
+pub use archive::{ArchiveEntry, MAX_ARCHIVE_FILES, MAX_EXTRACTED_SIZE};
+pub use backend::{validate_key, BlobReader, CachePolicy, CachingBackend, LocalBackend, MemoryBackend, RetryPolicy, RetryingBackend, StorageBackend};
+#[cfg(feature = "gcs")]
+pub use backend::{GcsBackend, GcsBackendConfig};
+#[cfg(feature = "s3")]
+pub use backend::{S3Backend, S3BackendConfig};
+pub use bucket::{BucketConnector, BucketCredentials, BucketHealth, BucketResolver, CredentialCipher, UserBucket, UserBuckets};
+pub use checksum::sha256_hex;
+pub use download_token::{DownloadToken, DEFAULT_DOWNLOAD_TOKEN_TTL_SECONDS, MAX_DOWNLOAD_TOKEN_TTL_SECONDS};
+pub use events::{StorageEvent, EVENT_CHANNEL_CAPACITY};
+pub use export::{ExportFormat, ExportedFile, ExportedShare, EXPORT_BATCH_SIZE};
+pub use extract::{ExtractionStatus, TextExtraction, FILE_TEXTS_TABLE, MAX_TEXT_LENGTH};
+pub use hold::{LegalHold, LEGAL_HOLDS_TABLE};
+pub use id::{FileIdStrategy, DEFAULT_NANOID_LENGTH};
+#[cfg(feature = "import")]
+pub use import::HttpFetcher;
+pub use import::{file_name_from_url, is_public_address, RemoteFetcher, RemoteFile, UrlImporter};
+pub use integrity::{IntegrityAudit, IntegrityReport, IntegrityStatus};
+pub use invitation::{normalize_email, ShareInvitation, SHARE_INVITATIONS_TABLE};
+pub use journal::{JournalEntry, JournalRecovery, UPLOAD_JOURNAL_TABLE};
+pub use limits::{LimitsProvider, StorageLimits};
+pub use media::MediaInfo;
+pub use migrate::{MigrationFailure, MigrationProgress, MigrationReport, StorageMigrator};
+pub use mime::{detect_mime_type, mime_types_match};
 pub use model::File;
-pub use service::{TransactionalStorageService, UserStorageStats};
+pub use organization::{Membership, Organization, OrganizationRole, MAX_ORGANIZATION_NAME_LENGTH, MEMBERSHIPS_TABLE, ORGANIZATIONS_TABLE};
+pub use permission::{FileAccess, FilePermission, FILE_PERMISSIONS_TABLE};
+pub use policy::{PolicyRule, PolicyViolation, UploadPolicy};
+pub use presign::{PresignedUpload, DEFAULT_PRESIGNED_UPLOAD_TTL_SECONDS, MAX_PRESIGNED_UPLOAD_TTL_SECONDS, PRESIGNED_UPLOADS_TABLE};
+pub use report::{ReportPeriod, UsageReport};
+pub use service::{ConsistencyReport, FileListOptions, FileSearchResults, FileSort, GcReport, MetadataUpdate, MimeTypeUsage, SizeMismatch, PendingUploadStats, DEFAULT_GC_GRACE_SECONDS, TransactionalStorageService, TrashPurge, UserStorageStats};
+pub use share::FileShare;
+pub use transfer::{FileTransfer, FILE_TRANSFERS_TABLE};
+pub use upload::{UploadSession, UPLOAD_SESSION_TTL_SECONDS};
+#[cfg(feature = "sftp")]
+pub use sftp::{SftpAuth, SftpIngestor, SftpSourceConfig};
+#[cfg(feature = "watch")]
+pub use watcher::{StorageWatcher, WatchConfig};
+#[cfg(feature = "webhooks")]
+pub use webhook::HttpTransport;
+pub use webhook::{sign_payload, DeliveryStatus, FileEvent, WebhookDelivery, WebhookEndpoint, WebhookTransport, Webhooks, WEBHOOK_DELIVERIES_TABLE};
 
-use chrono::{DateTime, Utc};
+use checksum::{HashingReader, VerifyingReader};
+use chrono::{DateTime, SubsecRound, Utc};
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::path::Path;
+use std::sync::Arc;
 use thiserror::Error;
-use tokio::fs;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use uuid::Uuid;
+use tokio::io::AsyncRead;
 
 #[derive(Debug, Error)]
 pub enum StorageError {
@@ -32,10 +113,53 @@ pub enum StorageError {
     #[error("Invalid path: {0}")]
     InvalidPath(String),
     
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+    
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+    
+    /// The file or record is under a legal hold and can't be deleted until it is released
+    #[error("Legal hold: {0}")]
+    LegalHold(String),
+    
+    /// The declared content type contradicts the type detected from the content
+    #[error("Content type mismatch: {0}")]
+    MimeTypeMismatch(String),
+    
+    /// The upload breaks a rule of the configured upload policy
+    #[error("Upload policy violation: {0}")]
+    PolicyViolation(PolicyViolation),
+    
+    /// Stored bytes no longer match the checksum recorded when they were written
+    #[error("Checksum mismatch: {0}")]
+    ChecksumMismatch(String),
+    
+    /// A remote backend failure that may succeed if retried (timeouts, throttling, 5xx)
+    #[error("Transient storage error: {0}")]
+    Transient(String),
+    
+    /// A remote backend has failed repeatedly and calls are being short-circuited
+    #[error("Storage backend unavailable: {0}")]
+    CircuitOpen(String),
+    
     #[error("Storage error: {0}")]
     StorageError(String),
 }
 
+impl StorageError {
+    /// Whether retrying the operation later may succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(self, StorageError::Transient(_) | StorageError::CircuitOpen(_))
+    }
+}
+
 pub type Result<T> = std::result::Result<T, StorageError>;
 
 /// Metadata for a stored file
@@ -45,29 +169,165 @@ pub struct FileMetadata {
     pub original_name: String,
     pub stored_name: String,
     pub size: u64,
+    /// Content type declared by the client
     pub mime_type: Option<String>,
+    /// Content type detected from the stored bytes, when they have a recognizable signature
+    pub detected_mime_type: Option<String>,
+    /// Hex-encoded SHA-256 of the stored bytes
+    pub checksum: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Stored name prefix of blobs written by a two-phase upload but not yet committed
+const PENDING_PREFIX: &str = "pending-";
+
+/// A blob written by a two-phase upload that has not been committed yet
+/// 
+/// Pending blobs are invisible to listings and usage. Once the file's metadata is recorded the
+/// blob is committed (renamed to its stored name); blobs that are never committed are garbage collected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingBlob {
+    /// Tenant the blob belongs to, if any
+    pub tenant: Option<String>,
+    /// Name the blob is stored under once committed
+    pub stored_name: String,
+    /// When the blob was written
+    pub created_at: DateTime<Utc>,
+}
+
+impl PendingBlob {
+    /// Name the blob is held under until it is committed
+    fn pending_name(&self) -> String {
+        format!("{}{}-{}", PENDING_PREFIX, self.created_at.timestamp_millis(), self.stored_name)
+    }
+
+    /// Parse a pending name back into its write time and final stored name
+    fn parse(tenant: Option<&str>, name: &str) -> Option<Self> {
+        let (millis, stored_name) = name.strip_prefix(PENDING_PREFIX)?.split_once('-')?;
+        Some(Self {
+            tenant: tenant.map(str::to_string),
+            stored_name: stored_name.to_string(),
+            created_at: DateTime::from_timestamp_millis(millis.parse().ok()?)?,
+        })
+    }
+}
+
+/// A file ID and stored name chosen before the file's blob is written
+/// Lets a write be journaled under the name it will have before any bytes reach the backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReservedName {
+    pub id: String,
+    pub stored_name: String,
+    /// When the name was reserved; a pending blob written under it carries this time
     pub created_at: DateTime<Utc>,
 }
 
+/// A committed blob and the tenant it is stored under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredBlob {
+    pub tenant: Option<String>,
+    pub stored_name: String,
+}
+
+impl StoredBlob {
+    /// Backend key of the blob, including the tenant prefix
+    pub fn key(&self) -> String {
+        match &self.tenant {
+            Some(tenant) => format!("{}/{}", tenant, self.stored_name),
+            None => self.stored_name.clone(),
+        }
+    }
+}
+
 /// Storage service for managing files
+/// 
+/// A service can be scoped to a tenant with [`StorageService::tenant`], which namespaces every
+/// key under that tenant's prefix so blobs of different tenants can never be addressed by each other.
+#[derive(Clone)]
 pub struct StorageService {
-    base_path: PathBuf,
+    backend: Arc<dyn StorageBackend>,
+    tenant: Option<String>,
+    id_strategy: FileIdStrategy,
+}
+
+/// Blob count and total size for a storage scope
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageUsage {
+    pub file_count: u64,
+    pub total_size: u64,
 }
 
 impl StorageService {
-    /// Create a new storage service
+    /// Create a new storage service backed by the local filesystem
     /// 
     /// # Arguments
     /// * `base_path` - Base directory for storing files
     pub async fn new<P: AsRef<Path>>(base_path: P) -> Result<Self> {
-        let base_path = base_path.as_ref().to_path_buf();
-        
-        // Create base directory if it doesn't exist
-        if !base_path.exists() {
-            fs::create_dir_all(&base_path).await?;
+        let backend = LocalBackend::new(base_path).await?;
+        Ok(Self::with_backend(backend))
+    }
+
+    /// Create a new storage service that keeps files in memory (useful for tests)
+    pub fn in_memory() -> Self {
+        Self::with_backend(MemoryBackend::new())
+    }
+
+    /// Create a new storage service on top of any storage backend
+    pub fn with_backend<B: StorageBackend + 'static>(backend: B) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            tenant: None,
+            id_strategy: FileIdStrategy::default(),
         }
-        
-        Ok(Self { base_path })
+    }
+
+    /// Keep small blobs that are read in memory, by the limits of `policy`
+    /// The cache is shared by every tenant view taken afterwards.
+    pub fn with_cache(mut self, policy: CachePolicy) -> Self {
+        self.backend = Arc::new(CachingBackend::new(self.backend, policy));
+        self
+    }
+
+    /// Generate IDs of newly stored files with `strategy` instead of random UUIDs
+    /// Existing files keep their IDs, so strategies can be changed at any time
+    pub fn with_id_strategy(mut self, strategy: FileIdStrategy) -> Self {
+        self.id_strategy = strategy;
+        self
+    }
+
+    /// How IDs of newly stored files are generated
+    pub fn id_strategy(&self) -> FileIdStrategy {
+        self.id_strategy
+    }
+
+    /// Get a view of this storage scoped to a tenant
+    /// 
+    /// All keys are stored under the tenant's prefix (a subdirectory or bucket prefix),
+    /// and keys of other tenants are unreachable through the returned service.
+    pub fn tenant(&self, tenant: &str) -> Result<Self> {
+        validate_key(tenant)?;
+        Ok(Self {
+            backend: Arc::clone(&self.backend),
+            tenant: Some(tenant.to_string()),
+            id_strategy: self.id_strategy,
+        })
+    }
+
+    /// The tenant this service is scoped to, if any
+    pub fn tenant_id(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    /// Backend key for a stored name, including the tenant prefix
+    fn blob_key(&self, stored_name: &str) -> Result<String> {
+        tenant_key(self.tenant.as_deref(), stored_name)
+    }
+
+    /// Generate a new file ID and the stored name derived from it
+    fn new_stored_name(&self, original_name: &str) -> (String, String) {
+        let id = self.id_strategy.generate();
+        let stored_name = stored_name_for(&id, original_name);
+        (id, stored_name)
     }
     
     /// Store a file with optional metadata
@@ -80,167 +340,447 @@ impl StorageService {
     /// # Returns
     /// FileMetadata with generated ID and storage information
     pub async fn store(&self, data: &[u8], original_name: &str, mime_type: Option<String>) -> Result<FileMetadata> {
-        let id = Uuid::new_v4().to_string();
-        let extension = Path::new(original_name)
-            .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("");
-        
-        let stored_name = if extension.is_empty() {
-            id.clone()
-        } else {
-            format!("{}.{}", id, extension)
+        let (id, stored_name) = self.new_stored_name(original_name);
+        
+        self.backend.put(&self.blob_key(&stored_name)?, data).await?;
+        
+        let metadata = FileMetadata {
+            id,
+            original_name: original_name.to_string(),
+            stored_name,
+            size: data.len() as u64,
+            mime_type,
+            detected_mime_type: detect_mime_type(data).map(str::to_string),
+            checksum: sha256_hex(data),
+            created_at: Utc::now(),
         };
         
-        let file_path = self.base_path.join(&stored_name);
+        Ok(metadata)
+    }
+    
+    /// Store a file from a reader without buffering it in memory
+    /// 
+    /// # Arguments
+    /// * `reader` - Source of the file data, consumed to the end
+    /// * `original_name` - Original filename
+    /// * `mime_type` - Optional MIME type
+    /// 
+    /// # Returns
+    /// FileMetadata with generated ID, storage information, and the number of bytes written
+    pub async fn store_stream<R>(&self, reader: R, original_name: &str, mime_type: Option<String>) -> Result<FileMetadata>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let reserved = self.reserve_name(original_name);
+        self.store_stream_reserved(&reserved, reader, original_name, mime_type).await
+    }
+    
+    /// Choose the ID and stored name of a file before writing it
+    pub fn reserve_name(&self, original_name: &str) -> ReservedName {
+        let (id, stored_name) = self.new_stored_name(original_name);
+        ReservedName {
+            id,
+            stored_name,
+            // Pending names carry the write time in milliseconds, so keep no finer precision
+            created_at: Utc::now().trunc_subsecs(3),
+        }
+    }
+    
+    /// Store a file from a reader under a name reserved with [`StorageService::reserve_name`]
+    pub async fn store_stream_reserved<R>(
+        &self,
+        reserved: &ReservedName,
+        reader: R,
+        original_name: &str,
+        mime_type: Option<String>,
+    ) -> Result<FileMetadata>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let ReservedName { id, stored_name, .. } = reserved.clone();
+        
+        let mut reader = HashingReader::new(reader);
+        let size = self.backend.put_stream(&self.blob_key(&stored_name)?, &mut reader).await?;
+        
+        let metadata = FileMetadata {
+            id,
+            original_name: original_name.to_string(),
+            stored_name,
+            size,
+            mime_type,
+            detected_mime_type: detect_mime_type(reader.head()).map(str::to_string),
+            checksum: reader.finish(),
+            created_at: Utc::now(),
+        };
+        
+        Ok(metadata)
+    }
+    
+    /// Store a file as a pending blob, the first phase of a two-phase upload
+    /// The blob stays out of listings until [`StorageService::commit_pending`] is called for it
+    /// 
+    /// # Returns
+    /// FileMetadata for the file as it will be once committed
+    pub async fn store_pending<R>(&self, reader: R, original_name: &str, mime_type: Option<String>) -> Result<FileMetadata>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let reserved = self.reserve_name(original_name);
+        self.store_pending_reserved(&reserved, reader, original_name, mime_type).await
+    }
+    
+    /// Store a file as a pending blob under a name reserved with [`StorageService::reserve_name`]
+    pub async fn store_pending_reserved<R>(
+        &self,
+        reserved: &ReservedName,
+        reader: R,
+        original_name: &str,
+        mime_type: Option<String>,
+    ) -> Result<FileMetadata>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let ReservedName { id, stored_name, created_at } = reserved.clone();
+        let pending = PendingBlob {
+            tenant: self.tenant.clone(),
+            stored_name: stored_name.clone(),
+            created_at,
+        };
+        
+        let mut reader = HashingReader::new(reader);
+        let size = self.backend.put_stream(&self.blob_key(&pending.pending_name())?, &mut reader).await?;
+        
+        Ok(FileMetadata {
+            id,
+            original_name: original_name.to_string(),
+            stored_name,
+            size,
+            mime_type,
+            detected_mime_type: detect_mime_type(reader.head()).map(str::to_string),
+            checksum: reader.finish(),
+            created_at,
+        })
+    }
+    
+    /// The pending blob written by [`StorageService::store_pending`] for a file
+    pub fn pending_for(&self, metadata: &FileMetadata) -> PendingBlob {
+        PendingBlob {
+            tenant: self.tenant.clone(),
+            stored_name: metadata.stored_name.clone(),
+            created_at: metadata.created_at,
+        }
+    }
+    
+    /// Commit a pending blob, making it retrievable under its stored name
+    pub async fn commit_pending(&self, pending: &PendingBlob) -> Result<()> {
+        let tenant = pending.tenant.as_deref();
+        self.backend.rename(
+            &tenant_key(tenant, &pending.pending_name())?,
+            &tenant_key(tenant, &pending.stored_name)?,
+        ).await
+    }
+    
+    /// Write part of a pending blob starting at `offset`, creating it when `offset` is 0
+    /// Used by resumable uploads, whose bytes stay pending until the last chunk arrives
+    /// 
+    /// # Returns
+    /// The blob's new size in bytes
+    pub async fn write_pending_at(&self, pending: &PendingBlob, offset: u64, data: &[u8]) -> Result<u64> {
+        let key = tenant_key(pending.tenant.as_deref(), &pending.pending_name())?;
+        if offset == 0 && !self.backend.exists(&key).await? {
+            self.backend.put(&key, data).await?;
+            return Ok(data.len() as u64);
+        }
+        self.backend.write_at(&key, offset, data).await
+    }
+    
+    /// Open a pending blob for reading as a stream, without loading it into memory
+    pub async fn retrieve_pending_stream(&self, pending: &PendingBlob) -> Result<BlobReader> {
+        self.backend.get_stream(&tenant_key(pending.tenant.as_deref(), &pending.pending_name())?).await
+    }
+    
+    /// Delete a pending blob that will never be committed
+    pub async fn discard_pending(&self, pending: &PendingBlob) -> Result<()> {
+        self.backend.delete(&tenant_key(pending.tenant.as_deref(), &pending.pending_name())?).await
+    }
+    
+    /// List uncommitted blobs in this scope
+    /// An unscoped service lists the pending blobs of every tenant
+    pub async fn list_pending(&self) -> Result<Vec<PendingBlob>> {
+        let keys = self.backend.list().await?;
         
-        // Write file
-        let mut file = fs::File::create(&file_path).await?;
-        file.write_all(data).await?;
-        file.flush().await?;
+        Ok(keys.iter()
+            .filter_map(|key| match key.split_once('/') {
+                Some((tenant, name)) => PendingBlob::parse(Some(tenant), name),
+                None => PendingBlob::parse(None, key),
+            })
+            .filter(|pending| self.tenant.is_none() || pending.tenant == self.tenant)
+            .collect())
+    }
+    
+    /// Store a file under the SHA-256 hash of its content
+    /// Storing the same bytes twice is a no-op, so the returned stored name is a stable,
+    /// immutable identifier for the content
+    /// 
+    /// # Returns
+    /// FileMetadata whose ID is the hex content hash
+    pub async fn store_content_addressed(&self, data: &[u8], original_name: &str, mime_type: Option<String>) -> Result<FileMetadata> {
+        let id = sha256_hex(data);
+        let stored_name = stored_name_for(&id, original_name);
+        
+        let key = self.blob_key(&stored_name)?;
+        if !self.backend.exists(&key).await? {
+            self.backend.put(&key, data).await?;
+        }
         
         let metadata = FileMetadata {
+            checksum: id.clone(),
             id,
             original_name: original_name.to_string(),
             stored_name,
             size: data.len() as u64,
             mime_type,
+            detected_mime_type: detect_mime_type(data).map(str::to_string),
             created_at: Utc::now(),
         };
         
         Ok(metadata)
     }
     
-    /// Retrieve a file by its ID
+    /// Retrieve a file by its stored name
     /// 
     /// # Arguments
-    /// * `file_id` - The file ID or stored name
+    /// * `stored_name` - The stored name recorded for the file, extension included
     /// 
     /// # Returns
     /// File data as bytes
-    pub async fn retrieve(&self, file_id: &str) -> Result<Vec<u8>> {
-        // Try direct lookup first
-        let mut file_path = self.base_path.join(file_id);
-        
-        // If not found, try with common extensions
-        if !file_path.exists() {
-            let extensions = ["", ".jpg", ".png", ".pdf", ".txt", ".json"];
-            let mut found = false;
-            
-            for ext in extensions {
-                let test_path = self.base_path.join(format!("{}{}", file_id, ext));
-                if test_path.exists() {
-                    file_path = test_path;
-                    found = true;
-                    break;
-                }
-            }
-            
-            if !found {
-                return Err(StorageError::FileNotFound(file_id.to_string()));
-            }
-        }
+    pub async fn retrieve(&self, stored_name: &str) -> Result<Vec<u8>> {
+        self.backend.get(&self.blob_key(stored_name)?).await
+    }
+    
+    /// Read part of a file without loading the rest of it
+    /// Ranges running past the end of the file are cut short, and ranges starting past it fail
+    /// with `StorageError::InvalidInput`. Partial reads can't be checked against the file's checksum.
+    /// 
+    /// # Arguments
+    /// * `file_id` - The stored name recorded for the file, extension included
+    /// * `offset` - Position of the first byte to read
+    /// * `len` - Most bytes to read
+    pub async fn retrieve_range(&self, file_id: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.backend.get_range(&self.blob_key(file_id)?, offset, len).await
+    }
+    
+    /// Retrieve a file and verify it against the checksum recorded when it was stored
+    /// 
+    /// # Returns
+    /// File data as bytes, or `StorageError::ChecksumMismatch` if the stored bytes were corrupted
+    pub async fn retrieve_verified(&self, file_id: &str, expected_checksum: &str) -> Result<Vec<u8>> {
+        let data = self.retrieve(file_id).await?;
         
-        let mut file = fs::File::open(&file_path).await?;
-        let mut data = Vec::new();
-        file.read_to_end(&mut data).await?;
+        let actual = sha256_hex(&data);
+        if !actual.eq_ignore_ascii_case(expected_checksum) {
+            return Err(StorageError::ChecksumMismatch(format!(
+                "{} has checksum {} but {} was recorded",
+                file_id, actual, expected_checksum
+            )));
+        }
         
         Ok(data)
     }
     
-    /// Delete a file by its ID
+    /// Open a file for reading as a stream, without loading it into memory
     /// 
     /// # Arguments
-    /// * `file_id` - The file ID or stored name
-    pub async fn delete(&self, file_id: &str) -> Result<()> {
-        let mut file_path = self.base_path.join(file_id);
-        
-        // If not found, try with common extensions
-        if !file_path.exists() {
-            let extensions = ["", ".jpg", ".png", ".pdf", ".txt", ".json"];
-            let mut found = false;
-            
-            for ext in extensions {
-                let test_path = self.base_path.join(format!("{}{}", file_id, ext));
-                if test_path.exists() {
-                    file_path = test_path;
-                    found = true;
-                    break;
-                }
-            }
-            
-            if !found {
-                return Err(StorageError::FileNotFound(file_id.to_string()));
-            }
-        }
-        
-        fs::remove_file(&file_path).await?;
-        Ok(())
+    /// * `stored_name` - The stored name recorded for the file, extension included
+    pub async fn retrieve_stream(&self, stored_name: &str) -> Result<BlobReader> {
+        self.backend.get_stream(&self.blob_key(stored_name)?).await
+    }
+    
+    /// Open a file for reading as a stream that is verified against its recorded checksum
+    /// Corruption is only detected at the end, where the stream fails with an `InvalidData` error
+    pub async fn retrieve_stream_verified(&self, stored_name: &str, expected_checksum: &str) -> Result<BlobReader> {
+        let reader = self.retrieve_stream(stored_name).await?;
+        Ok(Box::new(VerifyingReader::new(reader, expected_checksum)))
+    }
+    
+    /// Copy a stored file to a new ID without reading it into memory
+    /// 
+    /// # Returns
+    /// The source's metadata with the copy's ID and stored name
+    pub async fn copy(&self, source: &FileMetadata) -> Result<FileMetadata> {
+        let (id, stored_name) = self.new_stored_name(&source.original_name);
+
+        self.backend.copy(&self.blob_key(&source.stored_name)?, &self.blob_key(&stored_name)?).await?;
+
+        Ok(FileMetadata {
+            id,
+            stored_name,
+            created_at: Utc::now(),
+            ..source.clone()
+        })
+    }
+    
+    /// Append data to the end of a stored file
+    /// 
+    /// # Returns
+    /// The file's new size in bytes
+    pub async fn append(&self, file_id: &str, data: &[u8]) -> Result<u64> {
+        self.backend.append(&self.blob_key(file_id)?, data).await
+    }
+    
+    /// Overwrite part of a stored file starting at `offset`
+    /// The write may extend the file, but `offset` must not be past its end
+    /// 
+    /// # Returns
+    /// The file's new size in bytes
+    pub async fn write_at(&self, file_id: &str, offset: u64, data: &[u8]) -> Result<u64> {
+        self.backend.write_at(&self.blob_key(file_id)?, offset, data).await
+    }
+    
+    /// Delete a file by its stored name
+    /// 
+    /// # Arguments
+    /// * `stored_name` - The stored name recorded for the file, extension included
+    pub async fn delete(&self, stored_name: &str) -> Result<()> {
+        self.backend.delete(&self.blob_key(stored_name)?).await
     }
     
     /// Check if a file exists
     /// 
     /// # Arguments
-    /// * `file_id` - The file ID or stored name
-    pub async fn exists(&self, file_id: &str) -> bool {
-        let file_path = self.base_path.join(file_id);
-        
-        if file_path.exists() {
-            return true;
+    /// * `stored_name` - The stored name recorded for the file, extension included
+    pub async fn exists(&self, stored_name: &str) -> bool {
+        match self.blob_key(stored_name) {
+            Ok(key) => self.backend.exists(&key).await.unwrap_or(false),
+            Err(_) => false,
         }
+    }
+    
+    /// Find the blob of a file by its ID and original name
+    /// 
+    /// Lookups go by the stored name recorded in the files table; this is the legacy fallback
+    /// for blobs whose recorded name doesn't match what is in storage. It checks the names the
+    /// blob could have been stored under directly: the ID with the original name's extension,
+    /// as is and lowercased, and the bare ID.
+    /// 
+    /// # Returns
+    /// The stored name of the blob, or `None` if there is none for the ID
+    pub async fn find_legacy_blob(&self, file_id: &str, original_name: &str) -> Result<Option<String>> {
+        validate_key(file_id)?;
+        let mut candidates = vec![stored_name_for(file_id, original_name)];
+        let extension = safe_extension(original_name).to_ascii_lowercase();
+        if !extension.is_empty() {
+            candidates.push(format!("{}.{}", file_id, extension));
+        }
+        candidates.push(file_id.to_string());
+        candidates.dedup();
         
-        // Try with common extensions
-        let extensions = ["", ".jpg", ".png", ".pdf", ".txt", ".json"];
-        for ext in extensions {
-            let test_path = self.base_path.join(format!("{}{}", file_id, ext));
-            if test_path.exists() {
-                return true;
+        for candidate in candidates {
+            if self.exists(&candidate).await {
+                return Ok(Some(candidate));
             }
         }
-        
-        false
+        Ok(None)
     }
     
-    /// List all files in storage
+    /// List all files in storage (or in this tenant's scope)
+    /// Pending blobs of uncommitted uploads are not included
     /// 
     /// # Returns
-    /// Vector of file paths relative to base_path
+    /// Vector of stored file names
     pub async fn list_files(&self) -> Result<Vec<String>> {
-        let mut files = Vec::new();
-        let mut entries = fs::read_dir(&self.base_path).await?;
-        
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_file() {
-                if let Some(name) = entry.file_name().to_str() {
-                    files.push(name.to_string());
-                }
+        let keys = self.backend.list().await?;
+        
+        let files: Vec<String> = match &self.tenant {
+            Some(tenant) => {
+                let prefix = format!("{}/", tenant);
+                keys.into_iter()
+                    .filter_map(|key| key.strip_prefix(&prefix).map(str::to_string))
+                    .collect()
             }
-        }
+            None => keys.into_iter().filter(|key| !key.contains('/')).collect(),
+        };
         
-        Ok(files)
+        Ok(files.into_iter().filter(|name| !name.starts_with(PENDING_PREFIX)).collect())
     }
     
-    /// Get file metadata
-    /// 
-    /// # Arguments
-    /// * `file_id` - The file ID or stored name
-    pub async fn get_metadata(&self, file_id: &str) -> Result<std::fs::Metadata> {
-        let file_path = self.base_path.join(file_id);
+    /// List committed blobs with their tenants
+    /// An unscoped service lists the blobs of every tenant; pending blobs are not included
+    pub async fn list_blobs(&self) -> Result<Vec<StoredBlob>> {
+        let keys = self.backend.list().await?;
         
-        if !file_path.exists() {
-            return Err(StorageError::FileNotFound(file_id.to_string()));
+        Ok(keys.into_iter()
+            .map(|key| match key.split_once('/') {
+                Some((tenant, name)) => StoredBlob { tenant: Some(tenant.to_string()), stored_name: name.to_string() },
+                None => StoredBlob { tenant: None, stored_name: key },
+            })
+            .filter(|blob| !blob.stored_name.starts_with(PENDING_PREFIX))
+            .filter(|blob| self.tenant.is_none() || blob.tenant == self.tenant)
+            .collect())
+    }
+    
+    /// Size of a stored file in bytes
+    pub async fn size(&self, file_id: &str) -> Result<u64> {
+        self.backend.size(&self.blob_key(file_id)?).await
+    }
+    
+    /// When a file was last written, if the backend records it
+    pub async fn modified(&self, file_id: &str) -> Result<Option<DateTime<Utc>>> {
+        self.backend.modified(&self.blob_key(file_id)?).await
+    }
+    
+    /// Count the blobs and bytes stored in this scope
+    pub async fn usage(&self) -> Result<StorageUsage> {
+        let mut usage = StorageUsage::default();
+        for stored_name in self.list_files().await? {
+            usage.file_count += 1;
+            usage.total_size += self.backend.size(&self.blob_key(&stored_name)?).await?;
+        }
+        Ok(usage)
+    }
+    
+    /// Get the location files are stored at (a directory path or bucket URL)
+    pub fn location(&self) -> String {
+        match &self.tenant {
+            Some(tenant) => format!("{}/{}", self.backend.location(), tenant),
+            None => self.backend.location(),
         }
-        
-        let metadata = fs::metadata(&file_path).await?;
-        Ok(metadata)
     }
+}
+
+/// Backend key for a stored name within an optional tenant
+fn tenant_key(tenant: Option<&str>, stored_name: &str) -> Result<String> {
+    validate_key(stored_name)?;
+    Ok(match tenant {
+        Some(tenant) => format!("{}/{}", tenant, stored_name),
+        None => stored_name.to_string(),
+    })
+}
+
+/// Stored name for a file ID, keeping the original name's extension when it is safe
+fn stored_name_for(id: &str, original_name: &str) -> String {
+    let extension = safe_extension(original_name);
     
-    /// Get the base storage path
-    pub fn base_path(&self) -> &Path {
-        &self.base_path
+    if extension.is_empty() {
+        id.to_string()
+    } else {
+        format!("{}.{}", id, extension)
     }
 }
 
+/// Maximum length of a file extension carried over to the stored name
+const MAX_EXTENSION_LENGTH: usize = 16;
+
+/// Extension of an uploaded file's name, if it is safe to use in a stored name
+/// Extensions with anything but ASCII letters and digits are dropped rather than sanitized
+fn safe_extension(original_name: &str) -> &str {
+    Path::new(original_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .filter(|e| e.len() <= MAX_EXTENSION_LENGTH && e.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -256,9 +796,182 @@ mod tests {
         
         assert_eq!(metadata.original_name, "test.txt");
         assert_eq!(metadata.size, data.len() as u64);
+        assert_eq!(metadata.detected_mime_type, None);
         
-        let retrieved = storage.retrieve(&metadata.id).await.unwrap();
+        let retrieved = storage.retrieve(&metadata.stored_name).await.unwrap();
         assert_eq!(retrieved, data);
+        
+        // Blobs are addressed by their exact stored name, not by guessing an extension
+        assert!(matches!(storage.retrieve(&metadata.id).await, Err(StorageError::FileNotFound(_))));
+    }
+    
+    #[tokio::test]
+    async fn test_retrieve_stream() {
+        use tokio::io::AsyncReadExt;
+        
+        let temp_dir = TempDir::new().unwrap();
+        let storage = StorageService::new(temp_dir.path()).await.unwrap();
+        
+        let data = vec![3u8; 64 * 1024];
+        let metadata = storage.store(&data, "big.bin", None).await.unwrap();
+        
+        let mut streamed = Vec::new();
+        let mut reader = storage.retrieve_stream_verified(&metadata.stored_name, &metadata.checksum).await.unwrap();
+        reader.read_to_end(&mut streamed).await.unwrap();
+        assert_eq!(streamed, data);
+        
+        // Corruption is reported when the stream ends
+        std::fs::write(temp_dir.path().join(&metadata.stored_name), b"tampered").unwrap();
+        let mut reader = storage.retrieve_stream_verified(&metadata.stored_name, &metadata.checksum).await.unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+        
+        assert!(matches!(storage.retrieve_stream("missing.bin").await, Err(StorageError::FileNotFound(_))));
+    }
+    
+    #[tokio::test]
+    async fn test_find_legacy_blob() {
+        let storage = StorageService::in_memory().tenant("user-1").unwrap();
+        
+        let metadata = storage.store(b"archive", "backup.tar.zst", None).await.unwrap();
+        let bare = storage.store(b"notes", "README", None).await.unwrap();
+        storage.store(b"other", "other.txt", None).await.unwrap();
+        
+        // Any extension is found, not just a list of common ones
+        assert_eq!(storage.find_legacy_blob(&metadata.id, "backup.tar.zst").await.unwrap(), Some(metadata.stored_name.clone()));
+        assert_eq!(storage.find_legacy_blob(&metadata.id, "BACKUP.TAR.ZST").await.unwrap(), Some(metadata.stored_name));
+        assert_eq!(storage.find_legacy_blob(&bare.id, "README").await.unwrap(), Some(bare.id.clone()));
+        // A blob stored without its extension is found under the bare ID
+        assert_eq!(storage.find_legacy_blob(&bare.id, "README.md").await.unwrap(), Some(bare.id));
+        assert_eq!(storage.find_legacy_blob("missing", "missing.txt").await.unwrap(), None);
+    }
+    
+    #[tokio::test]
+    async fn test_store_stream() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = StorageService::new(temp_dir.path()).await.unwrap();
+        
+        let data = vec![7u8; 256 * 1024];
+        let metadata = storage.store_stream(&data[..], "big.bin", None).await.unwrap();
+        
+        assert_eq!(metadata.size, data.len() as u64);
+        assert!(metadata.stored_name.ends_with(".bin"));
+        assert_eq!(storage.retrieve(&metadata.stored_name).await.unwrap(), data);
+    }
+    
+    #[tokio::test]
+    async fn test_append_and_write_at() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = StorageService::new(temp_dir.path()).await.unwrap();
+        
+        let metadata = storage.store(b"line one\n", "app.log", None).await.unwrap();
+        
+        let size = storage.append(&metadata.stored_name, b"line two\n").await.unwrap();
+        assert_eq!(size, 18);
+        
+        let size = storage.write_at(&metadata.stored_name, 5, b"ONE").await.unwrap();
+        assert_eq!(size, 18);
+        
+        let size = storage.write_at(&metadata.stored_name, 14, b"2 and 3\n").await.unwrap();
+        assert_eq!(size, 22);
+        
+        let retrieved = storage.retrieve(&metadata.stored_name).await.unwrap();
+        assert_eq!(retrieved, b"line ONE\nline 2 and 3\n");
+        
+        let result = storage.write_at(&metadata.stored_name, 100, b"x").await;
+        assert!(matches!(result, Err(StorageError::InvalidInput(_))));
+    }
+    
+    #[tokio::test]
+    async fn test_retrieve_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = StorageService::new(temp_dir.path()).await.unwrap();
+        
+        let metadata = storage.store(b"0123456789", "digits.txt", None).await.unwrap();
+        
+        assert_eq!(storage.retrieve_range(&metadata.stored_name, 2, 3).await.unwrap(), b"234");
+        assert_eq!(storage.retrieve_range(&metadata.stored_name, 7, 100).await.unwrap(), b"789");
+        assert!(storage.retrieve_range(&metadata.stored_name, 10, 1).await.unwrap().is_empty());
+        
+        let result = storage.retrieve_range(&metadata.stored_name, 11, 1).await;
+        assert!(matches!(result, Err(StorageError::InvalidInput(_))));
+    }
+    
+    #[tokio::test]
+    async fn test_id_strategy() {
+        let storage = StorageService::in_memory().with_id_strategy(FileIdStrategy::Ulid);
+        
+        let metadata = storage.store(b"data", "notes.txt", None).await.unwrap();
+        assert_eq!(metadata.id.len(), 26);
+        assert_eq!(metadata.stored_name, format!("{}.txt", metadata.id));
+        
+        // Tenant views generate IDs the same way
+        let tenant = storage.tenant("user-1").unwrap();
+        let metadata = tenant.store_stream(&b"data"[..], "notes.txt", None).await.unwrap();
+        assert!(FileIdStrategy::Ulid.is_generated_id(&metadata.id));
+    }
+    
+    #[tokio::test]
+    async fn test_store_content_addressed() {
+        let storage = StorageService::in_memory().tenant("assets").unwrap();
+        
+        let first = storage.store_content_addressed(b"body { margin: 0 }", "app.css", None).await.unwrap();
+        let second = storage.store_content_addressed(b"body { margin: 0 }", "site.css", None).await.unwrap();
+        
+        assert_eq!(first.id.len(), 64);
+        assert_eq!(first.stored_name, format!("{}.css", first.id));
+        assert_eq!(first.stored_name, second.stored_name);
+        assert_eq!(storage.list_files().await.unwrap().len(), 1);
+        assert_eq!(storage.retrieve(&first.stored_name).await.unwrap(), b"body { margin: 0 }");
+    }
+    
+    #[tokio::test]
+    async fn test_checksum_verification() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = StorageService::new(temp_dir.path()).await.unwrap();
+        
+        let metadata = storage.store(b"original bytes", "data.bin", None).await.unwrap();
+        assert_eq!(metadata.checksum, sha256_hex(b"original bytes"));
+        
+        let streamed = storage.store_stream(&b"original bytes"[..], "data.bin", None).await.unwrap();
+        assert_eq!(streamed.checksum, metadata.checksum);
+        
+        let pdf = storage.store_stream(&b"%PDF-1.7\n"[..], "report.txt", Some("text/plain".to_string())).await.unwrap();
+        assert_eq!(pdf.mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(pdf.detected_mime_type.as_deref(), Some("application/pdf"));
+        
+        let data = storage.retrieve_verified(&metadata.stored_name, &metadata.checksum).await.unwrap();
+        assert_eq!(data, b"original bytes");
+        
+        // Corrupt the blob on disk
+        std::fs::write(temp_dir.path().join(&metadata.stored_name), b"tampered bytes").unwrap();
+        let result = storage.retrieve_verified(&metadata.stored_name, &metadata.checksum).await;
+        assert!(matches!(result, Err(StorageError::ChecksumMismatch(_))));
+    }
+    
+    #[tokio::test]
+    async fn test_two_phase_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = StorageService::new(temp_dir.path()).await.unwrap();
+        let storage = root.tenant("user-1").unwrap();
+        
+        let committed = storage.store_pending(&b"committed"[..], "a.txt", None).await.unwrap();
+        let abandoned = storage.store_pending(&b"abandoned"[..], "b.txt", None).await.unwrap();
+        assert_eq!(committed.checksum, sha256_hex(b"committed"));
+        
+        // Pending blobs are neither listed nor retrievable
+        assert!(storage.list_files().await.unwrap().is_empty());
+        assert!(!storage.exists(&committed.stored_name).await);
+        assert_eq!(root.list_pending().await.unwrap().len(), 2);
+        
+        storage.commit_pending(&storage.pending_for(&committed)).await.unwrap();
+        assert_eq!(storage.retrieve(&committed.stored_name).await.unwrap(), b"committed");
+        
+        let pending = root.list_pending().await.unwrap();
+        assert_eq!(pending, vec![storage.pending_for(&abandoned)]);
+        
+        root.discard_pending(&pending[0]).await.unwrap();
+        assert!(root.list_pending().await.unwrap().is_empty());
+        assert_eq!(storage.list_files().await.unwrap(), vec![committed.stored_name]);
     }
     
     #[tokio::test]
@@ -269,11 +982,11 @@ mod tests {
         let data = b"Test data";
         let metadata = storage.store(data, "test.txt", None).await.unwrap();
         
-        assert!(storage.exists(&metadata.id).await);
+        assert!(storage.exists(&metadata.stored_name).await);
         
-        storage.delete(&metadata.id).await.unwrap();
+        storage.delete(&metadata.stored_name).await.unwrap();
         
-        assert!(!storage.exists(&metadata.id).await);
+        assert!(!storage.exists(&metadata.stored_name).await);
     }
     
     #[tokio::test]
@@ -287,4 +1000,75 @@ mod tests {
         let files = storage.list_files().await.unwrap();
         assert_eq!(files.len(), 2);
     }
+    
+    #[tokio::test]
+    async fn test_rejects_path_traversal() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage_root = temp_dir.path().join("storage");
+        let storage = StorageService::new(&storage_root).await.unwrap();
+        
+        // A file outside the storage root that must never be reachable
+        std::fs::write(temp_dir.path().join("secret.txt"), b"secret").unwrap();
+        
+        for id in ["../secret.txt", "../secret", "..", "sub/../../secret.txt", "/etc/passwd", "..\\secret.txt", ".hidden", ""] {
+            assert!(
+                matches!(storage.retrieve(id).await, Err(StorageError::InvalidPath(_))),
+                "retrieve should reject {:?}",
+                id
+            );
+            assert!(
+                matches!(storage.delete(id).await, Err(StorageError::InvalidPath(_))),
+                "delete should reject {:?}",
+                id
+            );
+            assert!(!storage.exists(id).await, "exists should reject {:?}", id);
+        }
+        
+        assert!(temp_dir.path().join("secret.txt").exists());
+    }
+    
+    #[tokio::test]
+    async fn test_unsafe_extension_is_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = StorageService::new(temp_dir.path()).await.unwrap();
+        
+        let metadata = storage.store(b"data", "evil.txt/../../x", None).await.unwrap();
+        assert_eq!(metadata.stored_name, metadata.id);
+        
+        let metadata = storage.store(b"data", "photo.JPG", None).await.unwrap();
+        assert_eq!(metadata.stored_name, format!("{}.JPG", metadata.id));
+        assert!(storage.exists(&metadata.stored_name).await);
+    }
+    
+    #[test]
+    fn test_validate_key() {
+        assert!(validate_key("550e8400-e29b-41d4-a716-446655440000").is_ok());
+        assert!(validate_key("550e8400-e29b-41d4-a716-446655440000.pdf").is_ok());
+        assert!(validate_key("a/b").is_err());
+        assert!(validate_key("a\0b").is_err());
+        assert!(validate_key(&"a".repeat(256)).is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_tenant_isolation() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = StorageService::new(temp_dir.path()).await.unwrap();
+        let alice = storage.tenant("user-1").unwrap();
+        let bob = storage.tenant("user-2").unwrap();
+        
+        let metadata = alice.store(b"alice's data", "notes.txt", None).await.unwrap();
+        bob.store(b"bob", "b.txt", None).await.unwrap();
+        
+        assert_eq!(alice.retrieve(&metadata.stored_name).await.unwrap(), b"alice's data");
+        assert!(matches!(bob.retrieve(&metadata.stored_name).await, Err(StorageError::FileNotFound(_))));
+        assert!(matches!(storage.retrieve(&metadata.stored_name).await, Err(StorageError::FileNotFound(_))));
+        assert!(!bob.exists(&metadata.stored_name).await);
+        
+        assert_eq!(alice.list_files().await.unwrap(), vec![metadata.stored_name.clone()]);
+        assert_eq!(alice.usage().await.unwrap(), StorageUsage { file_count: 1, total_size: 12 });
+        assert_eq!(bob.usage().await.unwrap(), StorageUsage { file_count: 1, total_size: 3 });
+        assert!(storage.list_files().await.unwrap().is_empty());
+        
+        assert!(matches!(storage.tenant("../user-1"), Err(StorageError::InvalidPath(_))));
+    }
 }
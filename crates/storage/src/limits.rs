@@ -0,0 +1,79 @@
+//! Per-user storage limits enforced by `TransactionalStorageService`
+
+use crate::{Organization, Result, StorageError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// Storage limits for one user; `None` means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageLimits {
+    /// Largest single file in bytes
+    pub max_file_size: Option<u64>,
+    /// Total bytes across all of the user's files
+    pub max_total_size: Option<u64>,
+    /// Number of files the user may keep
+    pub max_files: Option<u64>,
+}
+
+impl StorageLimits {
+    /// Check that a user storing `file_size` bytes (`new_files` of them new files) stays within
+    /// these limits, given their current file count and total size
+    pub fn check(&self, file_count: u64, total_size: u64, new_files: u64, file_size: u64, added_bytes: u64) -> Result<()> {
+        if let Some(max) = self.max_file_size {
+            if file_size > max {
+                return Err(StorageError::QuotaExceeded(format!(
+                    "file is {} bytes, the limit is {} bytes",
+                    file_size, max
+                )));
+            }
+        }
+        if let Some(max) = self.max_files {
+            if new_files > 0 && file_count + new_files > max {
+                return Err(StorageError::QuotaExceeded(format!("file limit of {} reached", max)));
+            }
+        }
+        if let Some(max) = self.max_total_size {
+            if total_size + added_bytes > max {
+                return Err(StorageError::QuotaExceeded(format!(
+                    "storage limit of {} bytes reached ({} bytes used)",
+                    max, total_size
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Source of per-user storage limits, such as a billing plan
+#[async_trait]
+pub trait LimitsProvider: Send + Sync {
+    async fn limits_for(&self, user_id: i64) -> Result<StorageLimits>;
+
+    /// Limits of an organization's pooled files; those of its owner unless overridden
+    async fn organization_limits(&self, organization: &Organization) -> Result<StorageLimits> {
+        self.limits_for(organization.owner_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_limits() {
+        let limits = StorageLimits {
+            max_file_size: Some(100),
+            max_total_size: Some(1000),
+            max_files: Some(3),
+        };
+
+        assert!(limits.check(0, 0, 1, 100, 100).is_ok());
+        assert!(matches!(limits.check(0, 0, 1, 101, 101), Err(StorageError::QuotaExceeded(_))));
+        assert!(matches!(limits.check(3, 300, 1, 10, 10), Err(StorageError::QuotaExceeded(_))));
+        assert!(matches!(limits.check(1, 950, 1, 60, 60), Err(StorageError::QuotaExceeded(_))));
+
+        // Growing an existing file doesn't count against the file limit
+        assert!(limits.check(3, 300, 0, 50, 10).is_ok());
+        assert!(StorageLimits::default().check(u64::MAX / 2, 0, 1, u64::MAX / 2, 1).is_ok());
+    }
+}
@@ -0,0 +1,492 @@
+//! Dimensions and durations of images, video, and audio
+//!
+//! Files are recognized by their leading bytes and only the headers needed are read, in ranges,
+//! so probing a large video doesn't read the whole blob. Images report their size in pixels
+//! (PNG, JPEG, GIF, WebP, BMP), video its resolution and duration (MP4, QuickTime, AVI), and
+//! audio its duration (MP3, WAV, FLAC, Ogg Vorbis and Opus, M4A). Anything else reports nothing.
+
+use crate::StorageService;
+use serde::{Deserialize, Serialize};
+
+/// Bytes read up front; enough for the headers of every format but JPEGs with large metadata
+const HEAD_LENGTH: u64 = 64 * 1024;
+
+/// Bytes of a JPEG searched for its frame header before giving up
+const MAX_JPEG_SCAN: u64 = 1024 * 1024;
+
+/// Largest MP4 `moov` box that is read
+const MAX_MOOV_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Top-level MP4 boxes walked looking for `moov`
+const MAX_TOP_LEVEL_BOXES: usize = 64;
+
+/// Bytes at the end of an Ogg stream searched for its last page
+const OGG_TAIL_LENGTH: u64 = 64 * 1024;
+
+/// What could be read of a media file's dimensions and duration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaInfo {
+    /// Width in pixels of an image or video
+    pub width: Option<u32>,
+    /// Height in pixels of an image or video
+    pub height: Option<u32>,
+    /// Playing time of a video or audio file in milliseconds
+    pub duration_ms: Option<u64>,
+}
+
+impl MediaInfo {
+    fn dimensions((width, height): (u32, u32)) -> Self {
+        Self { width: Some(width), height: Some(height), duration_ms: None }
+    }
+
+    fn duration(duration_ms: u64) -> Self {
+        Self { duration_ms: Some(duration_ms), ..Self::default() }
+    }
+}
+
+/// Probe a stored blob of `size` bytes whose content type is `content_type`
+/// Only images, video, and audio are read. Unreadable or unrecognized files report nothing,
+/// so probing never fails an upload.
+pub(crate) async fn probe(storage: &StorageService, stored_name: &str, content_type: Option<&str>, size: u64) -> MediaInfo {
+    let content_type = content_type.unwrap_or_default();
+    if !["image/", "video/", "audio/"].iter().any(|prefix| content_type.starts_with(prefix)) || size == 0 {
+        return MediaInfo::default();
+    }
+    let read = |offset: u64, len: u64| storage.retrieve_range(stored_name, offset, len);
+    let Ok(head) = read(0, HEAD_LENGTH.min(size)).await else {
+        return MediaInfo::default();
+    };
+
+    if head.get(4..8) == Some(&b"ftyp"[..]) {
+        return mp4_info(&read, size).await.unwrap_or_default();
+    }
+    if head.starts_with(b"OggS") {
+        let tail_offset = size.saturating_sub(OGG_TAIL_LENGTH);
+        let Ok(tail) = read(tail_offset, size - tail_offset).await else {
+            return MediaInfo::default();
+        };
+        return ogg_duration(&head, &tail).map(MediaInfo::duration).unwrap_or_default();
+    }
+    if let Some(audio_start) = id3_length(&head) {
+        let Ok(frame) = read(audio_start, 4096.min(size.saturating_sub(audio_start))).await else {
+            return MediaInfo::default();
+        };
+        return mp3_duration(&frame, audio_start, size).map(MediaInfo::duration).unwrap_or_default();
+    }
+    if head.starts_with(&[0xff, 0xd8]) {
+        if let Some(dimensions) = jpeg_dimensions(&head) {
+            return MediaInfo::dimensions(dimensions);
+        }
+        if size <= head.len() as u64 {
+            return MediaInfo::default();
+        }
+        let Ok(more) = read(0, MAX_JPEG_SCAN.min(size)).await else {
+            return MediaInfo::default();
+        };
+        return jpeg_dimensions(&more).map(MediaInfo::dimensions).unwrap_or_default();
+    }
+
+    image_dimensions(&head)
+        .map(MediaInfo::dimensions)
+        .or_else(|| avi_info(&head))
+        .or_else(|| wav_duration(&head, size).map(MediaInfo::duration))
+        .or_else(|| flac_duration(&head).map(MediaInfo::duration))
+        .or_else(|| mp3_duration(&head, 0, size).map(MediaInfo::duration))
+        .unwrap_or_default()
+}
+
+fn u16_be(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u16_le(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_be(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u32_le(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_be(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+fn u64_le(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// Milliseconds in `units` of a clock ticking `rate` times a second
+fn millis(units: u64, rate: u64) -> Option<u64> {
+    (rate > 0).then(|| (units as u128 * 1000 / rate as u128).min(u64::MAX as u128) as u64)
+}
+
+/// Width and height of a PNG, GIF, WebP, or BMP image
+fn image_dimensions(head: &[u8]) -> Option<(u32, u32)> {
+    if head.starts_with(b"\x89PNG\r\n\x1a\n") && head.get(12..16) == Some(&b"IHDR"[..]) {
+        return Some((u32_be(head, 16)?, u32_be(head, 20)?));
+    }
+    if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        return Some((u16_le(head, 6)? as u32, u16_le(head, 8)? as u32));
+    }
+    if head.starts_with(b"RIFF") && head.get(8..12) == Some(&b"WEBP"[..]) {
+        return match head.get(12..16)? {
+            b"VP8 " => Some((u16_le(head, 26)? as u32 & 0x3fff, u16_le(head, 28)? as u32 & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32_le(head, 21)?;
+                Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+            }
+            b"VP8X" => {
+                let width = u32_le(head, 24)? & 0xff_ffff;
+                let height = u32_le(head, 27)? & 0xff_ffff;
+                Some((width + 1, height + 1))
+            }
+            _ => None,
+        };
+    }
+    if head.starts_with(b"BM") {
+        let width = u32_le(head, 18)? as i32;
+        let height = u32_le(head, 22)? as i32;
+        // Bottom-up bitmaps have a negative height
+        return Some((width.unsigned_abs(), height.unsigned_abs()));
+    }
+    None
+}
+
+/// Width and height from the frame header of a JPEG, if it is within `data`
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut at = 2;
+    loop {
+        // Markers may be padded with any number of 0xff bytes
+        while *data.get(at)? == 0xff && *data.get(at + 1)? == 0xff {
+            at += 1;
+        }
+        if *data.get(at)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(at + 1)?;
+        match marker {
+            // Start of frame, except the DHT, JPG, and DAC markers that share the range
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                return Some((u16_be(data, at + 7)? as u32, u16_be(data, at + 5)? as u32));
+            }
+            // Markers without a length
+            0xd0..=0xd9 | 0x01 => at += 2,
+            _ => at += 2 + u16_be(data, at + 2)? as usize,
+        }
+    }
+}
+
+/// Resolution and duration of an AVI video, from its main header
+fn avi_info(head: &[u8]) -> Option<MediaInfo> {
+    if !head.starts_with(b"RIFF") || head.get(8..12) != Some(&b"AVI "[..]) || head.get(24..28) != Some(&b"avih"[..]) {
+        return None;
+    }
+    let micros_per_frame = u32_le(head, 32)? as u64;
+    let frames = u32_le(head, 48)? as u64;
+    Some(MediaInfo {
+        width: Some(u32_le(head, 64)?),
+        height: Some(u32_le(head, 68)?),
+        duration_ms: Some(frames * micros_per_frame / 1000),
+    })
+}
+
+/// Duration of a WAV file, from its byte rate and the length of its data
+fn wav_duration(head: &[u8], size: u64) -> Option<u64> {
+    if !head.starts_with(b"RIFF") || head.get(8..12) != Some(&b"WAVE"[..]) {
+        return None;
+    }
+    let mut byte_rate = None;
+    let mut at = 12;
+    loop {
+        let id = head.get(at..at + 4)?;
+        let length = u32_le(head, at + 4)? as u64;
+        let data_start = at as u64 + 8;
+        match id {
+            b"fmt " => byte_rate = Some(u32_le(head, at + 16)? as u64),
+            // Streamed WAVs may not have the final length in the header, so trust the file size
+            b"data" => return millis(length.min(size.saturating_sub(data_start)), byte_rate?),
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        at = (data_start + length + (length & 1)) as usize;
+    }
+}
+
+/// Duration of a FLAC file, from its stream info block
+fn flac_duration(head: &[u8]) -> Option<u64> {
+    if !head.starts_with(b"fLaC") || head.get(4)? & 0x7f != 0 {
+        return None;
+    }
+    let info = head.get(8..26)?;
+    let sample_rate = (info[10] as u64) << 12 | (info[11] as u64) << 4 | (info[12] as u64) >> 4;
+    let samples = ((info[13] & 0x0f) as u64) << 32 | u32_be(info, 14)? as u64;
+    // Zero samples means the encoder didn't know the length
+    if samples == 0 {
+        return None;
+    }
+    millis(samples, sample_rate)
+}
+
+/// Length of the ID3v2 tag at the start of an MP3 file, which may be large enough to hold album art
+fn id3_length(head: &[u8]) -> Option<u64> {
+    if !head.starts_with(b"ID3") {
+        return None;
+    }
+    let tag = head.get(6..10)?.iter().fold(0u64, |length, byte| length << 7 | (*byte & 0x7f) as u64);
+    let footer = if head.get(5)? & 0x10 != 0 { 10 } else { 0 };
+    Some(10 + tag + footer)
+}
+
+/// Duration of an MP3 file whose first frame is at the start of `frame`, `audio_start` bytes
+/// into the file, from the frame count of its Xing header, or else from its bit rate
+fn mp3_duration(frame: &[u8], audio_start: u64, size: u64) -> Option<u64> {
+    let header = frame.get(0..4)?;
+    if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+        return None;
+    }
+    let version = (header[1] >> 3) & 0x03;
+    let layer = (header[1] >> 1) & 0x03;
+    if layer != 0x01 || version == 0x01 {
+        // Only Layer III; version 1 is reserved
+        return None;
+    }
+    let mpeg1 = version == 0x03;
+
+    const MPEG1_BIT_RATES: [u64; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+    const MPEG2_BIT_RATES: [u64; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    let bit_rates = if mpeg1 { &MPEG1_BIT_RATES } else { &MPEG2_BIT_RATES };
+    let bit_rate = *bit_rates.get((header[2] >> 4) as usize)?;
+    let sample_rate = match ((header[2] >> 2) & 0x03, version) {
+        (index @ 0..=2, 0x03) => [44100, 48000, 32000][index as usize],
+        (index @ 0..=2, 0x02) => [22050, 24000, 16000][index as usize],
+        (index @ 0..=2, _) => [11025, 12000, 8000][index as usize],
+        _ => return None,
+    };
+    let samples_per_frame = if mpeg1 { 1152 } else { 576 };
+
+    // The Xing (VBR) or Info (CBR) header sits after the side information of the first frame
+    let mono = header[3] >> 6 == 0x03;
+    let side_info = match (mpeg1, mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let xing = 4 + side_info;
+    if matches!(frame.get(xing..xing + 4), Some(b"Xing") | Some(b"Info")) && u32_be(frame, xing + 4)? & 0x01 != 0 {
+        let frames = u32_be(frame, xing + 8)? as u64;
+        return millis(frames * samples_per_frame, sample_rate);
+    }
+
+    if bit_rate == 0 {
+        return None;
+    }
+    Some(size.saturating_sub(audio_start) * 8 / bit_rate)
+}
+
+/// Duration of an Ogg Vorbis or Opus stream, from the position of its last page
+fn ogg_duration(head: &[u8], tail: &[u8]) -> Option<u64> {
+    let segments = *head.get(26)? as usize;
+    let packet = head.get(27 + segments..)?;
+    let (rate, pre_skip) = if packet.starts_with(b"\x01vorbis") {
+        (u32_le(packet, 12)? as u64, 0)
+    } else if packet.starts_with(b"OpusHead") {
+        // Opus positions always count at 48 kHz
+        (48_000, u16_le(packet, 10)? as u64)
+    } else {
+        return None;
+    };
+
+    let last_page = tail.windows(4).rposition(|window| window == b"OggS")?;
+    let position = u64_le(tail, last_page + 6)?;
+    millis(position.saturating_sub(pre_skip), rate)
+}
+
+/// Resolution and duration of an MP4 or QuickTime file, from its `moov` box
+/// Top-level boxes are skipped over by their headers, so `moov` is found wherever it is.
+async fn mp4_info<F, Fut>(read: &F, size: u64) -> Option<MediaInfo>
+where
+    F: Fn(u64, u64) -> Fut,
+    Fut: std::future::Future<Output = crate::Result<Vec<u8>>>,
+{
+    let mut offset: u64 = 0;
+    for _ in 0..MAX_TOP_LEVEL_BOXES {
+        if offset.checked_add(8)? > size {
+            return None;
+        }
+        let header = read(offset, 16.min(size - offset)).await.ok()?;
+        let (box_size, header_size) = match u32_be(&header, 0)? {
+            0 => (size - offset, 8),
+            1 => (u64_be(&header, 8)?, 16),
+            box_size => (box_size as u64, 8),
+        };
+        if box_size < header_size {
+            return None;
+        }
+        if header.get(4..8)? == b"moov" {
+            let length = (box_size - header_size).min(MAX_MOOV_SIZE);
+            let moov = read(offset + header_size, length).await.ok()?;
+            return Some(moov_info(&moov));
+        }
+        // A corrupt 64-bit size can't wrap around to an earlier box
+        offset = offset.checked_add(box_size)?;
+    }
+    None
+}
+
+/// Child boxes of an MP4 box's content, as type and content
+fn mp4_boxes(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut at = 0;
+    std::iter::from_fn(move || {
+        let box_size = u32_be(data, at)? as usize;
+        let kind = data.get(at + 4..at + 8)?;
+        let (content_start, end) = match box_size {
+            0 => (at + 8, data.len()),
+            1 => (at + 16, at.checked_add(usize::try_from(u64_be(data, at + 8)?).ok()?)?),
+            box_size => (at + 8, at.checked_add(box_size)?),
+        };
+        if end < content_start {
+            return None;
+        }
+        let content = data.get(content_start..end.min(data.len()))?;
+        at = end;
+        Some((kind, content))
+    })
+}
+
+fn moov_info(moov: &[u8]) -> MediaInfo {
+    let mut info = MediaInfo::default();
+    for (kind, content) in mp4_boxes(moov) {
+        match kind {
+            b"mvhd" => {
+                let (timescale, duration) = match content.first() {
+                    Some(1) => (u32_be(content, 20), u64_be(content, 24)),
+                    _ => (u32_be(content, 12), u32_be(content, 16).map(u64::from)),
+                };
+                if let (Some(timescale), Some(duration)) = (timescale, duration) {
+                    info.duration_ms = millis(duration, timescale as u64).filter(|ms| *ms > 0);
+                }
+            }
+            b"trak" if info.width.is_none() => {
+                let Some((_, tkhd)) = mp4_boxes(content).find(|(kind, _)| *kind == b"tkhd") else {
+                    continue;
+                };
+                let at = if tkhd.first() == Some(&1) { 88 } else { 76 };
+                // Sizes are 16.16 fixed point; audio tracks have none
+                if let (Some(width), Some(height)) = (u32_be(tkhd, at), u32_be(tkhd, at + 4)) {
+                    if width >> 16 > 0 && height >> 16 > 0 {
+                        info.width = Some(width >> 16);
+                        info.height = Some(height >> 16);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(kind: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut data = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(content);
+        data
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        assert_eq!(image_dimensions(&png), Some((640, 480)));
+
+        let gif = b"GIF89a\x40\x01\xf0\x00";
+        assert_eq!(image_dimensions(gif), Some((320, 240)));
+
+        // APP0 segment, then a baseline frame header
+        let jpeg = b"\xff\xd8\xff\xe0\x00\x04ab\xff\xc0\x00\x11\x08\x01\xe0\x02\x80\x03";
+        assert_eq!(jpeg_dimensions(jpeg), Some((640, 480)));
+        assert_eq!(jpeg_dimensions(&jpeg[..10]), None);
+    }
+
+    #[test]
+    fn test_audio_durations() {
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0".to_vec();
+        wav.extend_from_slice(&[1, 0, 2, 0]);
+        wav.extend_from_slice(&44100u32.to_le_bytes());
+        wav.extend_from_slice(&176400u32.to_le_bytes());
+        wav.extend_from_slice(&[4, 0, 16, 0]);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&352800u32.to_le_bytes());
+        assert_eq!(wav_duration(&wav, wav.len() as u64 + 352800), Some(2000));
+
+        // 44.1 kHz, 441000 samples
+        let mut flac = b"fLaC\0\0\0\x22".to_vec();
+        flac.extend_from_slice(&[0; 10]);
+        flac.extend_from_slice(&[0x0a, 0xc4, 0x40, 0x00, 0x00, 0x06, 0xba, 0xa8]);
+        assert_eq!(flac_duration(&flac), Some(10_000));
+
+        // MPEG-1 Layer III at 128 kbit/s: a million bytes play for 62.5 seconds
+        let mp3 = [0xff, 0xfb, 0x90, 0x00];
+        assert_eq!(mp3_duration(&mp3, 0, 1_000_000), Some(62_500));
+        assert_eq!(id3_length(b"ID3\x04\0\0\0\0\x02\x01"), Some(10 + 257));
+    }
+
+    #[test]
+    fn test_mp4_info() {
+        let mut mvhd = vec![0; 20];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&90_500u32.to_be_bytes());
+        let mut tkhd = vec![0; 84];
+        tkhd[76..80].copy_from_slice(&(1920u32 << 16).to_be_bytes());
+        tkhd[80..84].copy_from_slice(&(1080u32 << 16).to_be_bytes());
+
+        let mut moov = mp4_box(b"mvhd", &mvhd);
+        moov.extend(mp4_box(b"trak", &mp4_box(b"tkhd", &tkhd)));
+        assert_eq!(
+            moov_info(&moov),
+            MediaInfo { width: Some(1920), height: Some(1080), duration_ms: Some(90_500) }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe_finds_moov_at_the_end() {
+        let mut mvhd = vec![0; 20];
+        mvhd[12..16].copy_from_slice(&600u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&1200u32.to_be_bytes());
+
+        let mut video = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        video.extend(mp4_box(b"mdat", &vec![0; 100_000]));
+        video.extend(mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd)));
+
+        let storage = StorageService::in_memory();
+        let stored = storage.store(&video, "clip.mp4", None).await.unwrap();
+        let info = probe(&storage, &stored.stored_name, Some("video/mp4"), video.len() as u64).await;
+        assert_eq!(info.duration_ms, Some(2000));
+
+        let info = probe(&storage, &stored.stored_name, Some("application/octet-stream"), video.len() as u64).await;
+        assert_eq!(info, MediaInfo::default());
+    }
+
+    #[tokio::test]
+    async fn test_mp4_box_size_overflow() {
+        // A 64-bit box size that would wrap the offset back to the start of the file
+        let mut video = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        video.extend_from_slice(&1u32.to_be_bytes());
+        video.extend_from_slice(b"mdat");
+        video.extend_from_slice(&(u64::MAX - 15).to_be_bytes());
+        video.extend(mp4_box(b"moov", &[]));
+
+        let storage = StorageService::in_memory();
+        let stored = storage.store(&video, "clip.mp4", None).await.unwrap();
+        let read = |offset: u64, len: u64| storage.retrieve_range(&stored.stored_name, offset, len);
+        assert_eq!(mp4_info(&read, video.len() as u64).await, None);
+    }
+}
@@ -0,0 +1,228 @@
+//! Moving every blob from one storage backend to another
+//!
+//! The migrator copies each committed blob to the same key in the destination, checks the copy
+//! against the checksum of the source bytes (and the checksum recorded for the file, when there
+//! is one), and only once every blob is in place rewrites the `storage_path` recorded for the
+//! files in a single statement. A failed or interrupted migration leaves the files table
+//! pointing at the source; running it again skips the blobs that were already copied.
+
+use chrono::Utc;
+use orm::prelude::*;
+use orm::query::QueryValue;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::checksum::HashingReader;
+use crate::{File, ReservedName, Result, StorageError, StorageService, StoredBlob};
+
+/// How far a migration has got, reported after each blob
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MigrationProgress {
+    /// Blobs in the source
+    pub total: u64,
+    /// Blobs copied and verified so far
+    pub copied: u64,
+    /// Blobs already in the destination with the right content
+    pub skipped: u64,
+    /// Blobs that could not be copied
+    pub failed: u64,
+    /// Bytes copied so far
+    pub bytes: u64,
+}
+
+impl MigrationProgress {
+    /// Blobs dealt with so far, whatever the outcome
+    pub fn done(&self) -> u64 {
+        self.copied + self.skipped + self.failed
+    }
+}
+
+/// A blob that could not be migrated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationFailure {
+    /// Backend key of the blob, including its tenant prefix
+    pub key: String,
+    pub error: String,
+}
+
+/// Outcome of a migration
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MigrationReport {
+    pub progress: MigrationProgress,
+    pub failures: Vec<MigrationFailure>,
+    /// Files whose `storage_path` now points at the destination; zero unless every blob migrated
+    pub switched_files: u64,
+}
+
+type ProgressCallback = Arc<dyn Fn(&MigrationProgress) + Send + Sync>;
+
+/// Copies the blobs of one storage backend to another and switches the files table over
+///
+/// Both services should be unscoped, so the blobs of every tenant are migrated. Pending blobs of
+/// uploads in flight are not copied, so uploads should be stopped while a migration runs.
+/// Blobs in users' own buckets are never touched.
+pub struct StorageMigrator {
+    source: StorageService,
+    destination: StorageService,
+    db: Database,
+    progress: Option<ProgressCallback>,
+}
+
+impl StorageMigrator {
+    pub fn new(source: StorageService, destination: StorageService, db: Database) -> Self {
+        Self {
+            source,
+            destination,
+            db,
+            progress: None,
+        }
+    }
+
+    /// Call `callback` after each blob is dealt with
+    pub fn with_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&MigrationProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Copy and verify every blob, then switch the files over if they all made it
+    pub async fn run(&self) -> Result<MigrationReport> {
+        if self.source.location() == self.destination.location() {
+            return Err(StorageError::InvalidInput("source and destination are the same storage".to_string()));
+        }
+
+        let blobs = self.source.list_blobs().await?;
+        let recorded = self.recorded_checksums().await?;
+
+        let mut report = MigrationReport::default();
+        report.progress.total = blobs.len() as u64;
+
+        for blob in &blobs {
+            let expected = recorded.get(&blob.stored_name).map(String::as_str);
+            match self.migrate_blob(blob, expected).await {
+                Ok(Some(size)) => {
+                    report.progress.copied += 1;
+                    report.progress.bytes += size;
+                }
+                Ok(None) => report.progress.skipped += 1,
+                Err(e) => {
+                    report.progress.failed += 1;
+                    report.failures.push(MigrationFailure { key: blob.key(), error: e.to_string() });
+                }
+            }
+            if let Some(callback) = &self.progress {
+                callback(&report.progress);
+            }
+        }
+
+        if report.failures.is_empty() {
+            report.switched_files = self.switch_storage_paths().await?;
+        }
+        Ok(report)
+    }
+
+    /// Copy one blob, returning its size, or `None` if the destination already holds it
+    async fn migrate_blob(&self, blob: &StoredBlob, expected: Option<&str>) -> Result<Option<u64>> {
+        let (source, destination) = match &blob.tenant {
+            Some(tenant) => (self.source.tenant(tenant)?, self.destination.tenant(tenant)?),
+            None => (self.source.clone(), self.destination.clone()),
+        };
+
+        if let Some(expected) = expected {
+            if destination.exists(&blob.stored_name).await && checksum_of(&destination, &blob.stored_name).await? == expected {
+                return Ok(None);
+            }
+        }
+
+        let reserved = ReservedName {
+            id: blob.stored_name.clone(),
+            stored_name: blob.stored_name.clone(),
+            created_at: Utc::now(),
+        };
+        let reader = source.retrieve_stream(&blob.stored_name).await?;
+        let copied = destination.store_stream_reserved(&reserved, reader, &blob.stored_name, None).await?;
+
+        let mismatch = if expected.is_some_and(|expected| !expected.eq_ignore_ascii_case(&copied.checksum)) {
+            Some("source blob does not match the checksum recorded for its file")
+        } else if checksum_of(&destination, &blob.stored_name).await? != copied.checksum {
+            Some("copy does not match the source blob")
+        } else {
+            None
+        };
+
+        if let Some(mismatch) = mismatch {
+            let _ = destination.delete(&blob.stored_name).await;
+            return Err(StorageError::ChecksumMismatch(format!("{}: {}", blob.key(), mismatch)));
+        }
+        Ok(Some(copied.size))
+    }
+
+    /// Checksums recorded for files, by stored name
+    async fn recorded_checksums(&self) -> Result<HashMap<String, String>> {
+        let sql = format!("SELECT stored_name, checksum FROM {} WHERE checksum IS NOT NULL", File::table_name());
+        let rows = self.db.backend().fetch_all_params(&sql, &[]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok(rows.iter()
+            .filter_map(|row| Some((
+                row.get("stored_name")?.as_str()?.to_string(),
+                row.get("checksum")?.as_str()?.to_ascii_lowercase(),
+            )))
+            .collect())
+    }
+
+    /// Point every file stored in the source at the destination, in one statement
+    async fn switch_storage_paths(&self) -> Result<u64> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT DISTINCT storage_path FROM {}", File::table_name());
+        let rows = backend.fetch_all_params(&sql, &[]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        let source_location = self.source.location();
+        let mut switches = Vec::new();
+        for path in rows.iter().filter_map(|row| row.get("storage_path").and_then(|v| v.as_str())) {
+            let destination = if path == source_location {
+                self.destination.location()
+            } else {
+                match path.strip_prefix(&source_location).and_then(|rest| rest.strip_prefix('/')) {
+                    Some(tenant) => self.destination.tenant(tenant)?.location(),
+                    None => continue,
+                }
+            };
+            switches.push((path.to_string(), destination));
+        }
+
+        if switches.is_empty() {
+            return Ok(0);
+        }
+
+        let mut params = Vec::new();
+        let mut cases = String::new();
+        let mut matched = Vec::new();
+        for (from, to) in switches {
+            cases.push_str(&format!(" WHEN ?{} THEN ?{}", params.len() + 1, params.len() + 2));
+            matched.push(format!("?{}", params.len() + 1));
+            params.push(QueryValue::String(from));
+            params.push(QueryValue::String(to));
+        }
+        let sql = format!(
+            "UPDATE {} SET storage_path = CASE storage_path{} END WHERE storage_path IN ({})",
+            File::table_name(),
+            cases,
+            matched.join(", ")
+        );
+
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))
+    }
+}
+
+/// Checksum of a stored blob, read as a stream
+async fn checksum_of(storage: &StorageService, stored_name: &str) -> Result<String> {
+    let mut reader = HashingReader::new(storage.retrieve_stream(stored_name).await?);
+    tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+    Ok(reader.finish())
+}
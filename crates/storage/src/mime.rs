@@ -0,0 +1,69 @@
+//! Server-side MIME type detection from file content
+//!
+//! Client-declared content types are only hints; the type detected from a file's magic bytes
+//! is recorded next to it, so a mislabeled upload can be spotted or rejected.
+
+/// Number of leading bytes inspected to detect a file's type
+pub const SNIFF_LENGTH: usize = 8192;
+
+/// Generic type that declares nothing about the content
+const GENERIC_MIME_TYPE: &str = "application/octet-stream";
+
+/// Detect a MIME type from the first bytes of a file
+/// Returns `None` for content without a recognizable signature, such as plain text
+pub fn detect_mime_type(head: &[u8]) -> Option<&'static str> {
+    infer::get(head).map(|kind| kind.mime_type())
+}
+
+/// Whether a client-declared MIME type is consistent with the detected one
+/// Nothing can be checked when either side is unknown or the declared type is generic
+pub fn mime_types_match(declared: Option<&str>, detected: Option<&str>) -> bool {
+    let (Some(declared), Some(detected)) = (declared, detected) else {
+        return true;
+    };
+
+    let declared = essence(declared);
+    declared == GENERIC_MIME_TYPE || canonical(&declared) == canonical(&essence(detected))
+}
+
+/// The `type/subtype` part of a MIME type, without parameters
+fn essence(mime_type: &str) -> String {
+    mime_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// Map common non-standard aliases to the type the detector reports
+fn canonical(mime_type: &str) -> &str {
+    match mime_type {
+        "image/jpg" | "image/pjpeg" => "image/jpeg",
+        "audio/mp3" => "audio/mpeg",
+        "application/x-zip-compressed" => "application/zip",
+        "application/x-pdf" => "application/pdf",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_HEADER: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D, b'I', b'H', b'D', b'R'];
+
+    #[test]
+    fn test_detect_mime_type() {
+        assert_eq!(detect_mime_type(PNG_HEADER), Some("image/png"));
+        assert_eq!(detect_mime_type(b"%PDF-1.7\n"), Some("application/pdf"));
+        assert_eq!(detect_mime_type(b"just some text"), None);
+    }
+
+    #[test]
+    fn test_mime_types_match() {
+        assert!(mime_types_match(Some("image/png"), Some("image/png")));
+        assert!(mime_types_match(Some("Image/JPG; q=1"), Some("image/jpeg")));
+        assert!(mime_types_match(Some("application/octet-stream"), Some("image/png")));
+        assert!(mime_types_match(Some("text/plain"), None));
+        assert!(mime_types_match(None, Some("image/png")));
+
+        assert!(!mime_types_match(Some("image/png"), Some("application/x-msdownload")));
+        assert!(!mime_types_match(Some("text/html"), Some("application/pdf")));
+    }
+}
@@ -2,8 +2,11 @@ use chrono::{DateTime, Utc};
 use orm::prelude::*;
 use orm::model::Row;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 
+use crate::{IntegrityStatus, MediaInfo};
+
 /// File model for database persistence
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct File {
@@ -12,9 +15,35 @@ pub struct File {
     pub original_name: String,
     pub stored_name: String,
     pub size: i64,
+    /// Content type declared by the uploader
     pub mime_type: Option<String>,
+    /// Content type detected from the file's bytes (missing when unrecognized or stored before detection)
+    pub detected_mime_type: Option<String>,
     pub storage_path: String,
+    /// Hex-encoded SHA-256 of the stored bytes (missing for files stored before checksums were tracked)
+    pub checksum: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// When the file was moved to the trash; `None` for live files
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Folder the file is filed under, as a `/`-separated path; `None` at the root
+    pub folder: Option<String>,
+    /// Times the file was downloaded, by its owner, through a share link, or with a download token
+    pub download_count: i64,
+    /// When the file was last downloaded; `None` if it never was
+    pub last_accessed_at: Option<DateTime<Utc>>,
+    /// Outcome of the latest integrity audit; `None` until one checks the file's current content
+    pub integrity_status: Option<IntegrityStatus>,
+    /// When an integrity audit last checked the file
+    pub integrity_checked_at: Option<DateTime<Utc>>,
+    /// Organization the file belongs to; its members can open it, and it counts toward the
+    /// organization's storage instead of `user_id`'s
+    pub organization_id: Option<i64>,
+    /// Width in pixels of an image or video (missing for other files, or when it couldn't be read)
+    pub width: Option<i64>,
+    /// Height in pixels of an image or video
+    pub height: Option<i64>,
+    /// Playing time in milliseconds of a video or audio file
+    pub duration_ms: Option<i64>,
 }
 
 impl File {
@@ -27,6 +56,7 @@ impl File {
         size: i64,
         mime_type: Option<String>,
         storage_path: String,
+        checksum: Option<String>,
     ) -> Self {
         Self {
             id: Some(id),
@@ -35,10 +65,78 @@ impl File {
             stored_name,
             size,
             mime_type,
+            detected_mime_type: None,
             storage_path,
+            checksum,
             created_at: Utc::now(),
+            deleted_at: None,
+            folder: None,
+            download_count: 0,
+            last_accessed_at: None,
+            integrity_status: None,
+            integrity_checked_at: None,
+            organization_id: None,
+            width: None,
+            height: None,
+            duration_ms: None,
         }
     }
+
+    /// Content type to serve the file with
+    /// The detected type wins, so a mislabeled upload is never served as what it claims to be
+    pub fn content_type(&self) -> Option<&str> {
+        self.detected_mime_type.as_deref().or(self.mime_type.as_deref())
+    }
+
+    /// Record what probing the file's content found of its dimensions and duration
+    pub(crate) fn set_media(&mut self, media: MediaInfo) {
+        self.width = media.width.map(i64::from);
+        self.height = media.height.map(i64::from);
+        self.duration_ms = media.duration_ms.map(|ms| ms.min(i64::MAX as u64) as i64);
+    }
+
+    /// Whether the file is in the trash
+    pub fn is_trashed(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    /// Entity tag identifying the current version of this file's metadata
+    /// Changes whenever the name, folder, stored blob, size, MIME type, or content checksum changes
+    pub fn etag(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.id.as_deref().unwrap_or_default().as_bytes());
+        hasher.update([0]);
+        hasher.update(self.original_name.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.stored_name.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.size.to_le_bytes());
+        hasher.update(self.mime_type.as_deref().unwrap_or_default().as_bytes());
+        hasher.update([0]);
+        hasher.update(self.checksum.as_deref().unwrap_or_default().as_bytes());
+        // Only filed files hash a folder, so tags of files at the root are unchanged
+        if let Some(folder) = &self.folder {
+            hasher.update([0]);
+            hasher.update(folder.as_bytes());
+        }
+        let digest = format!("{:x}", hasher.finalize());
+        format!("\"{}\"", &digest[..32])
+    }
+
+    /// Check an `If-Match` header value against this file's ETag
+    /// Accepts `*`, a single tag, or a comma-separated list (weak tags compare by value)
+    pub fn matches_if_match(&self, if_match: &str) -> bool {
+        let etag = self.etag();
+        if_match.split(',').map(str::trim).any(|candidate| {
+            candidate == "*" || candidate.trim_start_matches("W/") == etag
+        })
+    }
+
+    /// Check an `If-None-Match` header value against this file's ETag
+    /// Compared the same way as `If-Match`; a match means the client's cached copy is current
+    pub fn matches_if_none_match(&self, if_none_match: &str) -> bool {
+        self.matches_if_match(if_none_match)
+    }
 }
 
 impl Model for File {
@@ -66,13 +164,47 @@ impl Model for File {
         if let Some(mime_type) = &self.mime_type {
             map.insert("mime_type".to_string(), Value::String(mime_type.clone()));
         }
+        if let Some(detected_mime_type) = &self.detected_mime_type {
+            map.insert("detected_mime_type".to_string(), Value::String(detected_mime_type.clone()));
+        }
         map.insert("storage_path".to_string(), Value::String(self.storage_path.clone()));
+        if let Some(checksum) = &self.checksum {
+            map.insert("checksum".to_string(), Value::String(checksum.clone()));
+        }
         map.insert("created_at".to_string(), Value::String(self.created_at.to_rfc3339()));
+        if let Some(deleted_at) = &self.deleted_at {
+            map.insert("deleted_at".to_string(), Value::String(deleted_at.to_rfc3339()));
+        }
+        if let Some(folder) = &self.folder {
+            map.insert("folder".to_string(), Value::String(folder.clone()));
+        }
+        map.insert("download_count".to_string(), Value::I64(self.download_count));
+        if let Some(last_accessed_at) = &self.last_accessed_at {
+            map.insert("last_accessed_at".to_string(), Value::String(last_accessed_at.to_rfc3339()));
+        }
+        if let Some(integrity_status) = &self.integrity_status {
+            map.insert("integrity_status".to_string(), Value::String(integrity_status.as_str().to_string()));
+        }
+        if let Some(integrity_checked_at) = &self.integrity_checked_at {
+            map.insert("integrity_checked_at".to_string(), Value::String(integrity_checked_at.to_rfc3339()));
+        }
+        if let Some(organization_id) = self.organization_id {
+            map.insert("organization_id".to_string(), Value::I64(organization_id));
+        }
+        if let Some(width) = self.width {
+            map.insert("width".to_string(), Value::I64(width));
+        }
+        if let Some(height) = self.height {
+            map.insert("height".to_string(), Value::I64(height));
+        }
+        if let Some(duration_ms) = self.duration_ms {
+            map.insert("duration_ms".to_string(), Value::I64(duration_ms));
+        }
         map
     }
 
     fn columns() -> Vec<&'static str> {
-        vec!["user_id", "original_name", "stored_name", "size", "mime_type", "storage_path", "created_at"]
+        vec!["user_id", "original_name", "stored_name", "size", "mime_type", "detected_mime_type", "storage_path", "checksum", "created_at", "deleted_at", "folder", "download_count", "last_accessed_at", "integrity_status", "integrity_checked_at", "organization_id", "width", "height", "duration_ms"]
     }
 }
 
@@ -120,6 +252,12 @@ impl FromRow for File {
                 _ => None,
             });
 
+        let detected_mime_type = row.get("detected_mime_type")
+            .and_then(|v| match v {
+                Value::String(s) => Some(s.clone()),
+                _ => None,
+            });
+
         let storage_path = row.get("storage_path")
             .and_then(|v| match v {
                 Value::String(s) => Some(s.clone()),
@@ -127,6 +265,12 @@ impl FromRow for File {
             })
             .ok_or_else(|| Error::SerializationError("Missing storage_path".to_string()))?;
 
+        let checksum = row.get("checksum")
+            .and_then(|v| match v {
+                Value::String(s) => Some(s.clone()),
+                _ => None,
+            });
+
         let created_at = row.get("created_at")
             .and_then(|v| match v {
                 Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
@@ -134,6 +278,65 @@ impl FromRow for File {
             })
             .unwrap_or_else(Utc::now);
 
+        let deleted_at = row.get("deleted_at")
+            .and_then(|v| match v {
+                Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                _ => None,
+            });
+
+        let folder = row.get("folder")
+            .and_then(|v| match v {
+                Value::String(s) => Some(s.clone()),
+                _ => None,
+            });
+
+        // Missing on files stored before downloads were counted
+        let download_count = row.get("download_count")
+            .and_then(|v| match v {
+                Value::I64(i) => Some(*i),
+                Value::I32(i) => Some(*i as i64),
+                _ => None,
+            })
+            .unwrap_or(0);
+
+        let last_accessed_at = row.get("last_accessed_at")
+            .and_then(|v| match v {
+                Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                _ => None,
+            });
+
+        // Missing on files stored before integrity audits
+        let integrity_status = row.get("integrity_status")
+            .and_then(|v| match v {
+                Value::String(s) => IntegrityStatus::from_str(s),
+                _ => None,
+            });
+
+        let integrity_checked_at = row.get("integrity_checked_at")
+            .and_then(|v| match v {
+                Value::String(s) => DateTime::parse_from_rfc3339(s.as_str()).ok().map(|dt| dt.with_timezone(&Utc)),
+                _ => None,
+            });
+
+        // Missing on files stored before organizations
+        let organization_id = row.get("organization_id")
+            .and_then(|v| match v {
+                Value::I64(i) => Some(*i),
+                Value::I32(i) => Some(*i as i64),
+                _ => None,
+            });
+
+        // Missing on files stored before media probing
+        let media_field = |name: &str| row.get(name)
+            .and_then(|v| match v {
+                Value::I64(i) => Some(*i),
+                Value::I32(i) => Some(*i as i64),
+                _ => None,
+            });
+        let width = media_field("width");
+        let height = media_field("height");
+        let duration_ms = media_field("duration_ms");
+
         Ok(File {
             id,
             user_id,
@@ -141,8 +344,91 @@ impl FromRow for File {
             stored_name,
             size,
             mime_type,
+            detected_mime_type,
             storage_path,
+            checksum,
             created_at,
+            deleted_at,
+            folder,
+            download_count,
+            last_accessed_at,
+            integrity_status,
+            integrity_checked_at,
+            organization_id,
+            width,
+            height,
+            duration_ms,
         })
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_file() -> File {
+        File::new(
+            "550e8400-e29b-41d4-a716-446655440000".to_string(),
+            1,
+            "report.pdf".to_string(),
+            "550e8400-e29b-41d4-a716-446655440000.pdf".to_string(),
+            1024,
+            Some("application/pdf".to_string()),
+            "./storage".to_string(),
+            Some("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_string()),
+        )
+    }
+
+    #[test]
+    fn test_etag_changes_with_metadata() {
+        let file = sample_file();
+        let mut renamed = file.clone();
+        renamed.original_name = "renamed.pdf".to_string();
+
+        let mut rewritten = file.clone();
+        rewritten.checksum = Some("0".repeat(64));
+
+        let mut moved = file.clone();
+        moved.folder = Some("reports/2024".to_string());
+
+        assert_eq!(file.etag(), sample_file().etag());
+        assert_ne!(file.etag(), renamed.etag());
+        assert_ne!(file.etag(), rewritten.etag());
+        assert_ne!(file.etag(), moved.etag());
+    }
+
+    #[test]
+    fn test_content_type_prefers_detected() {
+        let mut file = sample_file();
+        assert_eq!(file.content_type(), Some("application/pdf"));
+
+        file.mime_type = Some("text/html".to_string());
+        file.detected_mime_type = Some("image/png".to_string());
+        assert_eq!(file.content_type(), Some("image/png"));
+    }
+
+    #[test]
+    fn test_is_trashed() {
+        let mut file = sample_file();
+        assert!(!file.is_trashed());
+        assert!(!file.to_values().contains_key("deleted_at"));
+
+        file.deleted_at = Some(Utc::now());
+        assert!(file.is_trashed());
+        assert!(file.to_values().contains_key("deleted_at"));
+    }
+
+    #[test]
+    fn test_matches_if_match() {
+        let file = sample_file();
+        let etag = file.etag();
+
+        assert!(file.matches_if_match(&etag));
+        assert!(file.matches_if_match("*"));
+        assert!(file.matches_if_match(&format!("\"other\", W/{}", etag)));
+        assert!(!file.matches_if_match("\"stale\""));
+        assert!(file.matches_if_none_match(&format!("W/{}", etag)));
+        assert!(!file.matches_if_none_match("\"stale\""));
+    }
+}
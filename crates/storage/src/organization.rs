@@ -0,0 +1,133 @@
+//! Organizations: files owned by a team
+//!
+//! An organization groups users. A file moved into one stays recorded under the user who
+//! uploaded it, but every member can open and change it, and admins can delete it and share it
+//! like its owner. Organization files are pooled for storage limits: together they count
+//! against the limits of the organization's plan, and no longer against their uploader's.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::FileAccess;
+
+/// Table of organizations
+pub const ORGANIZATIONS_TABLE: &str = "organizations";
+
+/// Table of the users in each organization
+pub const MEMBERSHIPS_TABLE: &str = "memberships";
+
+/// Longest organization name, in bytes; matches the `name` column
+pub const MAX_ORGANIZATION_NAME_LENGTH: usize = 100;
+
+/// A team of users that can own files together
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Organization {
+    pub id: i64,
+    pub name: String,
+    /// User whose storage limits apply to the organization's files unless it has a plan of its
+    /// own; always an admin
+    pub owner_id: i64,
+    /// Billing plan assigned to the organization; the owner's plan applies when unset
+    pub plan: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Organization {
+    /// Read an organization from a database row
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        Some(Self {
+            id: row.get("id").and_then(|v| v.as_i64())?,
+            name: row.get("name").and_then(|v| v.as_str())?.to_string(),
+            owner_id: row.get("owner_id").and_then(|v| v.as_i64())?,
+            plan: row.get("plan").and_then(|v| v.as_str()).map(str::to_string),
+            created_at: timestamp(row, "created_at").unwrap_or_else(Utc::now),
+        })
+    }
+}
+
+/// What a member may do in an organization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrganizationRole {
+    /// Manage members, and delete and share the organization's files
+    Admin,
+    /// Open and change the organization's files
+    Member,
+}
+
+impl OrganizationRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrganizationRole::Admin => "admin",
+            OrganizationRole::Member => "member",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "admin" => Some(OrganizationRole::Admin),
+            "member" => Some(OrganizationRole::Member),
+            _ => None,
+        }
+    }
+
+    /// Access the role gives to the organization's files
+    pub fn file_access(&self) -> FileAccess {
+        match self {
+            OrganizationRole::Admin => FileAccess::Owner,
+            OrganizationRole::Member => FileAccess::ReadWrite,
+        }
+    }
+}
+
+/// A user's place in an organization
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Membership {
+    pub organization_id: i64,
+    pub user_id: i64,
+    pub role: OrganizationRole,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Membership {
+    /// Read a membership from a database row
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        Some(Self {
+            organization_id: row.get("organization_id").and_then(|v| v.as_i64())?,
+            user_id: row.get("user_id").and_then(|v| v.as_i64())?,
+            role: row.get("role").and_then(|v| v.as_str()).and_then(OrganizationRole::from_str)?,
+            created_at: timestamp(row, "created_at").unwrap_or_else(Utc::now),
+        })
+    }
+}
+
+fn timestamp(row: &serde_json::Value, column: &str) -> Option<DateTime<Utc>> {
+    row.get(column)
+        .and_then(|v| v.as_str())
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_membership_from_json() {
+        let row = serde_json::json!({
+            "id": 1,
+            "organization_id": 3,
+            "user_id": 7,
+            "role": "admin",
+            "created_at": "2025-10-18T00:00:00+00:00"
+        });
+
+        let membership = Membership::from_json(&row).unwrap();
+        assert_eq!(membership.role, OrganizationRole::Admin);
+        assert_eq!(membership.role.file_access(), FileAccess::Owner);
+        assert_eq!(OrganizationRole::Member.file_access(), FileAccess::ReadWrite);
+
+        let row = serde_json::json!({"organization_id": 3, "user_id": 7, "role": "guest"});
+        assert!(Membership::from_json(&row).is_none());
+    }
+}
@@ -0,0 +1,114 @@
+//! Files shared with other users by account
+//!
+//! A permission lets one user read, or read and write, one file of another user. Unlike an
+//! invitation it names an existing account rather than an address, and it can allow writes.
+//! Permissions are recorded with the owner who granted them, so they lapse if the file changes
+//! hands. `owner` access makes a user a co-owner, who can also delete the file and share it;
+//! the file keeps counting toward the storage limits of the owner it is recorded under.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Table of permissions granted on files
+pub const FILE_PERMISSIONS_TABLE: &str = "file_permissions";
+
+/// What a user may do with a file, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileAccess {
+    /// Download the file
+    Read,
+    /// Download the file, change its content and rename or move it
+    ReadWrite,
+    /// Everything the owner can do, except granting or revoking `owner` access and transferring
+    /// the file
+    Owner,
+}
+
+impl FileAccess {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileAccess::Read => "read",
+            FileAccess::ReadWrite => "read_write",
+            FileAccess::Owner => "owner",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "read" => Some(FileAccess::Read),
+            "read_write" => Some(FileAccess::ReadWrite),
+            "owner" => Some(FileAccess::Owner),
+            _ => None,
+        }
+    }
+
+    pub fn can_write(&self) -> bool {
+        matches!(self, FileAccess::ReadWrite | FileAccess::Owner)
+    }
+
+    /// Whether the user may delete the file and manage who it is shared with
+    pub fn is_owner(&self) -> bool {
+        *self == FileAccess::Owner
+    }
+}
+
+/// Access to one file granted to one user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FilePermission {
+    pub id: i64,
+    pub file_id: String,
+    /// Owner of the file, who granted the permission
+    pub owner_id: i64,
+    /// User the file is shared with
+    pub user_id: i64,
+    pub access: FileAccess,
+    pub created_at: DateTime<Utc>,
+}
+
+impl FilePermission {
+    /// Read a permission from a database row
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        Some(Self {
+            id: row.get("id").and_then(|v| v.as_i64())?,
+            file_id: row.get("file_id").and_then(|v| v.as_str())?.to_string(),
+            owner_id: row.get("owner_id").and_then(|v| v.as_i64())?,
+            user_id: row.get("user_id").and_then(|v| v.as_i64())?,
+            access: row.get("access").and_then(|v| v.as_str()).and_then(FileAccess::from_str)?,
+            created_at: row.get("created_at")
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(Utc::now),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permission_from_json() {
+        let row = serde_json::json!({
+            "id": 4,
+            "file_id": "file-1",
+            "owner_id": 7,
+            "user_id": 9,
+            "access": "read_write",
+            "created_at": "2025-10-18T00:00:00+00:00"
+        });
+
+        let permission = FilePermission::from_json(&row).unwrap();
+        assert_eq!(permission.user_id, 9);
+        assert!(permission.access.can_write());
+        assert!(!FileAccess::Read.can_write());
+        assert!(FileAccess::Owner.can_write());
+        assert!(!permission.access.is_owner());
+        assert_eq!(FileAccess::from_str("owner"), Some(FileAccess::Owner));
+        assert!(FileAccess::Read < FileAccess::ReadWrite && FileAccess::ReadWrite < FileAccess::Owner);
+
+        let row = serde_json::json!({"id": 4, "file_id": "file-1", "owner_id": 7, "user_id": 9, "access": "admin"});
+        assert!(FilePermission::from_json(&row).is_none());
+    }
+}
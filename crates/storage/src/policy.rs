@@ -0,0 +1,312 @@
+//! Upload validation rules enforced by `TransactionalStorageService`
+
+use crate::{FileMetadata, StorageError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Which upload rule a file broke
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyRule {
+    MaxSize,
+    Extension,
+    MimeType,
+}
+
+/// A file rejected by an [`UploadPolicy`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyViolation {
+    pub rule: PolicyRule,
+    pub message: String,
+}
+
+impl PolicyViolation {
+    pub(crate) fn new(rule: PolicyRule, message: String) -> Self {
+        Self { rule, message }
+    }
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+impl From<PolicyViolation> for StorageError {
+    fn from(violation: PolicyViolation) -> Self {
+        StorageError::PolicyViolation(violation)
+    }
+}
+
+/// Rules every uploaded file must satisfy
+/// Empty lists and `None` impose no restriction
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadPolicy {
+    /// Largest file in bytes
+    pub max_size: Option<u64>,
+    /// File name extensions that may be uploaded, without the dot (case-insensitive)
+    pub allowed_extensions: Vec<String>,
+    /// MIME types that may be uploaded; `type/*` matches a whole family
+    pub allowed_mime_types: Vec<String>,
+    /// MIME types that are always rejected; `type/*` matches a whole family
+    pub blocked_mime_types: Vec<String>,
+}
+
+impl UploadPolicy {
+    /// Whether the policy allows everything
+    pub fn is_unrestricted(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check what is known before an upload is read: its name, declared type, and declared size
+    /// Lets a request be refused early; [`UploadPolicy::check_stored`] is still authoritative
+    pub fn check_declared(
+        &self,
+        original_name: &str,
+        mime_type: Option<&str>,
+        size: Option<u64>,
+    ) -> Result<(), PolicyViolation> {
+        if let Some(size) = size {
+            self.check_size(size)?;
+        }
+        self.check_extension(original_name)?;
+        if let Some(mime_type) = mime_type {
+            self.check_blocked(mime_type)?;
+        }
+        Ok(())
+    }
+
+    /// Check a stored file, including its actual size and the type detected from its bytes
+    pub fn check_stored(&self, metadata: &FileMetadata) -> Result<(), PolicyViolation> {
+        self.check_size(metadata.size)?;
+        self.check_extension(&metadata.original_name)?;
+
+        // A file can't dodge a block by mislabeling itself, so both types are checked
+        for mime_type in [metadata.mime_type.as_deref(), metadata.detected_mime_type.as_deref()].into_iter().flatten() {
+            self.check_blocked(mime_type)?;
+        }
+
+        if !self.allowed_mime_types.is_empty() {
+            let content_type = metadata.detected_mime_type.as_deref().or(metadata.mime_type.as_deref());
+            let allowed = content_type.is_some_and(|mime_type| matches_any(&self.allowed_mime_types, mime_type));
+            if !allowed {
+                return Err(PolicyViolation::new(
+                    PolicyRule::MimeType,
+                    format!(
+                        "content type {} is not allowed; allowed types: {}",
+                        content_type.unwrap_or("unknown"),
+                        self.allowed_mime_types.join(", ")
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Limit a streamed upload to the policy's maximum size
+    /// Reading fails as soon as the stream runs past it, so an oversized upload is cut off
+    /// instead of being written in full and rejected afterwards.
+    pub(crate) fn limit_reader<R>(&self, reader: R) -> SizeLimitedReader<R> {
+        SizeLimitedReader::new(reader, self.max_size)
+    }
+
+    fn check_size(&self, size: u64) -> Result<(), PolicyViolation> {
+        match self.max_size {
+            Some(max) if size > max => Err(PolicyViolation::new(
+                PolicyRule::MaxSize,
+                format!("file is {} bytes, uploads may be at most {} bytes", size, max),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_extension(&self, original_name: &str) -> Result<(), PolicyViolation> {
+        if self.allowed_extensions.is_empty() {
+            return Ok(());
+        }
+
+        let extension = std::path::Path::new(original_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        let allowed = self.allowed_extensions.iter()
+            .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(extension));
+        if allowed {
+            return Ok(());
+        }
+
+        Err(PolicyViolation::new(
+            PolicyRule::Extension,
+            format!(
+                "file extension '{}' is not allowed; allowed extensions: {}",
+                extension,
+                self.allowed_extensions.join(", ")
+            ),
+        ))
+    }
+
+    fn check_blocked(&self, mime_type: &str) -> Result<(), PolicyViolation> {
+        if matches_any(&self.blocked_mime_types, mime_type) {
+            return Err(PolicyViolation::new(
+                PolicyRule::MimeType,
+                format!("content type {} is blocked", essence(mime_type)),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Reader adapter that fails once more than a maximum size has been read through it
+/// The error carries the [`PolicyViolation`]; [`surface_violation`] recovers it.
+pub(crate) struct SizeLimitedReader<R> {
+    inner: R,
+    max_size: Option<u64>,
+    read: u64,
+}
+
+impl<R> SizeLimitedReader<R> {
+    pub(crate) fn new(inner: R, max_size: Option<u64>) -> Self {
+        Self { inner, max_size, read: 0 }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for SizeLimitedReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let already_filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &poll {
+            self.read += (buf.filled().len() - already_filled) as u64;
+            let read = self.read;
+            if let Some(max) = self.max_size.filter(|max| read > *max) {
+                let violation = PolicyViolation::new(
+                    PolicyRule::MaxSize,
+                    format!("file is larger than {} bytes, the most an upload may be", max),
+                );
+                return Poll::Ready(Err(std::io::Error::other(violation)));
+            }
+        }
+        poll
+    }
+}
+
+/// Turn the read error of a [`SizeLimitedReader`] back into the policy violation it carries
+/// Any other error is returned unchanged.
+pub(crate) fn surface_violation(e: StorageError) -> StorageError {
+    let violation = match &e {
+        StorageError::IoError(io) => io.get_ref().and_then(|inner| inner.downcast_ref::<PolicyViolation>()).cloned(),
+        _ => None,
+    };
+    violation.map_or(e, StorageError::PolicyViolation)
+}
+
+/// Whether a MIME type matches any pattern (`type/subtype` or `type/*`)
+pub(crate) fn matches_any(patterns: &[String], mime_type: &str) -> bool {
+    let mime_type = essence(mime_type);
+    patterns.iter().any(|pattern| {
+        let pattern = essence(pattern);
+        match pattern.strip_suffix("/*") {
+            Some(family) => mime_type.split('/').next() == Some(family),
+            None => pattern == mime_type,
+        }
+    })
+}
+
+/// The `type/subtype` part of a MIME type, without parameters
+fn essence(mime_type: &str) -> String {
+    mime_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn metadata(name: &str, size: u64, declared: Option<&str>, detected: Option<&str>) -> FileMetadata {
+        FileMetadata {
+            id: "id".to_string(),
+            original_name: name.to_string(),
+            stored_name: "id".to_string(),
+            size,
+            mime_type: declared.map(str::to_string),
+            detected_mime_type: detected.map(str::to_string),
+            checksum: String::new(),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn rule(result: Result<(), PolicyViolation>) -> Option<PolicyRule> {
+        result.err().map(|violation| violation.rule)
+    }
+
+    #[test]
+    fn test_check_stored() {
+        let policy = UploadPolicy {
+            max_size: Some(1000),
+            allowed_extensions: vec!["png".to_string(), ".PDF".to_string()],
+            allowed_mime_types: vec!["image/*".to_string(), "application/pdf".to_string()],
+            blocked_mime_types: vec!["image/svg+xml".to_string()],
+        };
+
+        assert_eq!(rule(policy.check_stored(&metadata("a.png", 10, Some("image/png"), Some("image/png")))), None);
+        assert_eq!(rule(policy.check_stored(&metadata("a.pdf", 10, None, Some("application/pdf")))), None);
+        assert_eq!(rule(policy.check_stored(&metadata("a.png", 1001, None, Some("image/png")))), Some(PolicyRule::MaxSize));
+        assert_eq!(rule(policy.check_stored(&metadata("a.exe", 10, None, None))), Some(PolicyRule::Extension));
+        assert_eq!(rule(policy.check_stored(&metadata("a", 10, None, None))), Some(PolicyRule::Extension));
+
+        // The detected type decides what the file is
+        assert_eq!(
+            rule(policy.check_stored(&metadata("a.png", 10, Some("image/png"), Some("application/x-msdownload")))),
+            Some(PolicyRule::MimeType)
+        );
+        assert_eq!(rule(policy.check_stored(&metadata("a.png", 10, None, None))), Some(PolicyRule::MimeType));
+        assert_eq!(
+            rule(policy.check_stored(&metadata("a.png", 10, Some("image/svg+xml; charset=utf-8"), None))),
+            Some(PolicyRule::MimeType)
+        );
+    }
+
+    #[test]
+    fn test_check_declared() {
+        let policy = UploadPolicy {
+            max_size: Some(1000),
+            blocked_mime_types: vec!["text/html".to_string()],
+            ..UploadPolicy::default()
+        };
+
+        assert!(policy.check_declared("page.txt", Some("text/plain"), None).is_ok());
+        assert_eq!(rule(policy.check_declared("page.html", Some("text/html"), None)), Some(PolicyRule::MimeType));
+        assert_eq!(rule(policy.check_declared("big.bin", None, Some(5000))), Some(PolicyRule::MaxSize));
+        assert!(UploadPolicy::default().is_unrestricted());
+    }
+
+    #[tokio::test]
+    async fn test_size_limited_reader() {
+        use tokio::io::AsyncReadExt;
+
+        let policy = UploadPolicy {
+            max_size: Some(1000),
+            ..UploadPolicy::default()
+        };
+        let data = vec![1u8; 1000];
+        let mut copied = Vec::new();
+        policy.limit_reader(&data[..]).read_to_end(&mut copied).await.unwrap();
+        assert_eq!(copied.len(), 1000);
+
+        let data = vec![1u8; 1001];
+        let e = policy.limit_reader(&data[..]).read_to_end(&mut Vec::new()).await.unwrap_err();
+        match surface_violation(StorageError::IoError(e)) {
+            StorageError::PolicyViolation(violation) => assert_eq!(violation.rule, PolicyRule::MaxSize),
+            other => panic!("expected a policy violation, got {:?}", other),
+        }
+        assert!(matches!(
+            surface_violation(StorageError::IoError(std::io::Error::other("disk full"))),
+            StorageError::IoError(_)
+        ));
+    }
+}
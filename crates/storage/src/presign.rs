@@ -0,0 +1,140 @@
+//! Presigned upload URLs, which let a client send one file straight to the server
+//!
+//! The URL carries the upload's ID, expiry, and size limit, signed with HMAC-SHA256, so a forged
+//! or altered URL is refused before the database is consulted. The upload itself is recorded in
+//! its own table and is claimed by the first request that sends it, so each URL stores one file.
+
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::share::generate_share_token;
+
+/// Table of issued presigned uploads
+pub const PRESIGNED_UPLOADS_TABLE: &str = "presigned_uploads";
+
+/// Lifetime of a presigned upload URL when the caller doesn't choose one
+pub const DEFAULT_PRESIGNED_UPLOAD_TTL_SECONDS: i64 = 900;
+
+/// Longest lifetime a presigned upload URL may have
+pub const MAX_PRESIGNED_UPLOAD_TTL_SECONDS: i64 = 86_400;
+
+/// A file a user has allowed to be uploaded, once, without their credentials
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PresignedUpload {
+    pub id: String,
+    /// User the uploaded file will belong to
+    pub user_id: i64,
+    pub original_name: String,
+    pub mime_type: Option<String>,
+    /// Most bytes the upload may have
+    pub max_size: u64,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PresignedUpload {
+    /// Allow one upload of at most `max_size` bytes, valid for `ttl`
+    pub fn new(user_id: i64, original_name: String, mime_type: Option<String>, max_size: u64, ttl: Duration) -> Self {
+        // Whole seconds, so the expiry in the URL is exactly the one recorded
+        let created_at = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap_or_else(Utc::now);
+        Self {
+            id: generate_share_token(),
+            user_id,
+            original_name,
+            mime_type,
+            max_size,
+            expires_at: created_at + ttl,
+            created_at,
+        }
+    }
+
+    /// Server path the file is sent to with `PUT`, signed with `key`
+    pub fn signed_path(&self, key: &str) -> String {
+        let expires = self.expires_at.timestamp();
+        format!(
+            "/uploads/{}?expires={}&max_size={}&signature={}",
+            self.id,
+            expires,
+            self.max_size,
+            sign(key, &self.id, expires, self.max_size)
+        )
+    }
+
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        let timestamp = |name: &str| {
+            row.get(name)
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+        Some(Self {
+            id: row.get("upload_id")?.as_str()?.to_string(),
+            user_id: row.get("user_id")?.as_i64()?,
+            original_name: row.get("original_name")?.as_str()?.to_string(),
+            mime_type: row.get("mime_type").and_then(|v| v.as_str()).map(str::to_string),
+            max_size: row.get("max_size")?.as_i64()?.try_into().ok()?,
+            expires_at: timestamp("expires_at")?,
+            created_at: timestamp("created_at")?,
+        })
+    }
+}
+
+fn mac(key: &str, id: &str, expires: i64, max_size: u64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    // Prefixed so the signature can't be mistaken for one made with the same key elsewhere
+    mac.update(format!("presigned-upload.{}.{}.{}", id, expires, max_size).as_bytes());
+    mac
+}
+
+/// Hex signature of a presigned upload's ID, expiry (Unix seconds), and size limit
+pub fn sign(key: &str, id: &str, expires: i64, max_size: u64) -> String {
+    mac(key, id, expires, max_size).finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Check a signature made by [`sign`], in constant time
+pub fn verify(key: &str, id: &str, expires: i64, max_size: u64, signature: &str) -> bool {
+    let Some(signature) = decode_hex(signature) else {
+        return false;
+    };
+    mac(key, id, expires, max_size).verify_slice(&signature).is_ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let signature = sign("secret", "upload-1", 1_700_000_000, 1024);
+        assert!(verify("secret", "upload-1", 1_700_000_000, 1024, &signature));
+        assert!(!verify("other", "upload-1", 1_700_000_000, 1024, &signature));
+        assert!(!verify("secret", "upload-2", 1_700_000_000, 1024, &signature));
+        assert!(!verify("secret", "upload-1", 1_700_000_001, 1024, &signature));
+        assert!(!verify("secret", "upload-1", 1_700_000_000, 1025, &signature));
+        assert!(!verify("secret", "upload-1", 1_700_000_000, 1024, "not hex"));
+    }
+
+    #[test]
+    fn test_signed_path() {
+        let upload = PresignedUpload::new(1, "photo.png".to_string(), None, 2048, Duration::seconds(60));
+        assert_eq!(upload.expires_at - upload.created_at, Duration::seconds(60));
+
+        let path = upload.signed_path("secret");
+        let expected = format!("/uploads/{}?expires={}&max_size=2048&signature=", upload.id, upload.expires_at.timestamp());
+        assert!(path.starts_with(&expected));
+        assert!(verify("secret", &upload.id, upload.expires_at.timestamp(), 2048, &path[expected.len()..]));
+    }
+}
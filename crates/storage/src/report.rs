@@ -0,0 +1,92 @@
+//! Periodic bandwidth and storage usage reports
+
+use chrono::{DateTime, Datelike, Months, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Granularity of a usage report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportPeriod {
+    Daily,
+    Monthly,
+}
+
+impl ReportPeriod {
+    /// Convert the period to string for database storage
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportPeriod::Daily => "daily",
+            ReportPeriod::Monthly => "monthly",
+        }
+    }
+
+    /// Parse a period from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "daily" => Some(ReportPeriod::Daily),
+            "monthly" => Some(ReportPeriod::Monthly),
+            _ => None,
+        }
+    }
+
+    /// Start of the period containing `at`
+    pub fn start_of(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let date = match self {
+            ReportPeriod::Daily => at.date_naive(),
+            ReportPeriod::Monthly => at.date_naive().with_day(1).unwrap_or(at.date_naive()),
+        };
+        midnight(date)
+    }
+
+    /// Start of the period after the one starting at `start`
+    pub fn next(&self, start: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            ReportPeriod::Daily => start + chrono::Duration::days(1),
+            ReportPeriod::Monthly => start + Months::new(1),
+        }
+    }
+}
+
+/// Bytes transferred and stored by one user during one report period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub user_id: i64,
+    pub period: ReportPeriod,
+    pub period_start: DateTime<Utc>,
+    pub upload_bytes: i64,
+    pub download_bytes: i64,
+    /// Bytes stored when the period was last aggregated
+    pub stored_bytes: i64,
+    /// Files stored when the period was last aggregated
+    pub file_count: i64,
+}
+
+/// Midnight UTC at the start of a date
+pub(crate) fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_boundaries() {
+        let at = Utc.with_ymd_and_hms(2025, 1, 31, 17, 45, 0).unwrap();
+
+        let day = ReportPeriod::Daily.start_of(at);
+        assert_eq!(day, Utc.with_ymd_and_hms(2025, 1, 31, 0, 0, 0).unwrap());
+        assert_eq!(ReportPeriod::Daily.next(day), Utc.with_ymd_and_hms(2025, 2, 1, 0, 0, 0).unwrap());
+
+        let month = ReportPeriod::Monthly.start_of(at);
+        assert_eq!(month, Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(ReportPeriod::Monthly.next(month), Utc.with_ymd_and_hms(2025, 2, 1, 0, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_period_from_str() {
+        assert_eq!(ReportPeriod::from_str("Monthly"), Some(ReportPeriod::Monthly));
+        assert_eq!(ReportPeriod::from_str(ReportPeriod::Daily.as_str()), Some(ReportPeriod::Daily));
+        assert_eq!(ReportPeriod::from_str("weekly"), None);
+    }
+}
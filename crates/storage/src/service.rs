@@ -1,21 +1,302 @@
-use crate::{File, StorageService, StorageError, Result};
+use crate::archive::{self, ArchiveEntry, MAX_ARCHIVE_FILES};
+use crate::checksum::{HashingReader, ResumableSha256};
+use crate::extract::{self, DocumentKind, ExtractionStatus, TextExtraction, FILE_TEXTS_TABLE};
+use crate::export::{ExportFormat, ExportedFile, ExportedShare, EXPORT_BATCH_SIZE};
+use crate::invitation::{is_valid_email, normalize_email, ShareInvitation, SHARE_INVITATIONS_TABLE};
+use crate::journal::{JournalEntry, JournalRecovery, UPLOAD_JOURNAL_TABLE};
+use crate::media;
+use crate::organization::{Membership, Organization, OrganizationRole, MAX_ORGANIZATION_NAME_LENGTH, MEMBERSHIPS_TABLE, ORGANIZATIONS_TABLE};
+use crate::permission::{FileAccess, FilePermission, FILE_PERMISSIONS_TABLE};
+use crate::presign::{self, PresignedUpload, PRESIGNED_UPLOADS_TABLE};
+use crate::transfer::{FileTransfer, FILE_TRANSFERS_TABLE};
+use crate::policy::{surface_violation, SizeLimitedReader};
+use crate::upload::UploadSession;
+use crate::{mime_types_match, BlobReader, BucketResolver, DownloadToken, FileEvent, StorageEvent, EVENT_CHANNEL_CAPACITY, LegalHold, LEGAL_HOLDS_TABLE, UploadPolicy, File, FileMetadata, FileShare, IntegrityAudit, IntegrityReport, IntegrityStatus, LimitsProvider, StorageLimits, PendingBlob, ReportPeriod, ReservedName, StorageService, StorageError, StorageUsage, StoredBlob, UsageReport, Result, Webhooks};
+use chrono::{DateTime, Duration, Utc};
 use orm::prelude::*;
 use orm::query::QueryValue;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::broadcast;
+
+/// Table tracking resumable uploads in progress
+const UPLOAD_SESSIONS_TABLE: &str = "upload_sessions";
+
+/// Table recording which files each user has marked as favorite
+const FAVORITES_TABLE: &str = "file_favorites";
+
+/// Table of public share links
+const SHARES_TABLE: &str = "file_shares";
+
+/// Table of single-use download tokens
+const DOWNLOAD_TOKENS_TABLE: &str = "download_tokens";
+
+/// Table recording every successful file retrieval
+const ACCESS_LOG_TABLE: &str = "file_access_log";
+
+/// Table recording every upload and download with its size in bytes
+const TRANSFER_LOG_TABLE: &str = "storage_transfer_log";
+
+/// Table of per-user usage aggregated by day and by month
+const USAGE_REPORTS_TABLE: &str = "storage_usage_reports";
+
+/// Longest file name a rename accepts, in bytes
+const MAX_FILE_NAME_LENGTH: usize = 255;
+
+/// Longest folder path, in bytes; matches the `folder` column
+const MAX_FOLDER_LENGTH: usize = 500;
+
+/// Default minimum age of an unreferenced blob before storage GC removes it
+pub const DEFAULT_GC_GRACE_SECONDS: u64 = 3600;
+
+/// Files read per page by a full integrity audit
+const AUDIT_PAGE_SIZE: i64 = 500;
+
+/// Largest download verified in full before any of it is sent
+/// Larger downloads are verified as they stream, and a mismatch cuts the response off at the end
+const VERIFY_BEFORE_SENDING_SIZE: i64 = 8 * 1024 * 1024;
+
+/// Storage namespace for content-addressed public assets
+/// Never collides with per-user tenants, which are named `user-<id>`
+const ASSETS_NAMESPACE: &str = "assets";
 
 /// Transactional storage service that integrates filesystem storage with database persistence
 pub struct TransactionalStorageService {
     storage: StorageService,
     db: Database,
+    tenant_isolation: bool,
+    two_phase_uploads: bool,
+    strict_mime_types: bool,
+    upload_policy: UploadPolicy,
+    public_base_url: Option<String>,
+    upload_signing_key: Option<String>,
+    limits: Option<Arc<dyn LimitsProvider>>,
+    buckets: Option<Arc<dyn BucketResolver>>,
+    webhooks: Option<Arc<Webhooks>>,
+    events: broadcast::Sender<StorageEvent>,
 }
 
 impl TransactionalStorageService {
     /// Create a new transactional storage service
     pub fn new(storage: StorageService, db: Database) -> Self {
-        Self { storage, db }
+        Self {
+            storage,
+            db,
+            tenant_isolation: false,
+            two_phase_uploads: false,
+            strict_mime_types: false,
+            upload_policy: UploadPolicy::default(),
+            public_base_url: None,
+            upload_signing_key: None,
+            limits: None,
+            buckets: None,
+            webhooks: None,
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+        }
+    }
+
+    /// Store each owner's blobs under their own tenant prefix
+    /// 
+    /// Blob keys are then derived from the caller's identity rather than only from the files
+    /// table, so a faulty metadata lookup can never serve another owner's blob.
+    /// Enable this on fresh deployments: blobs stored before it was enabled are not moved.
+    pub fn with_tenant_isolation(mut self, enabled: bool) -> Self {
+        self.tenant_isolation = enabled;
+        self
+    }
+
+    /// Write uploads as pending blobs and commit them only once their metadata is recorded
+    /// 
+    /// Without this, a blob is stored under its final name before its metadata exists, and is
+    /// deleted again if the metadata insert fails. With it, blobs stay out of listings and usage
+    /// until they are committed, and ones never committed are removed by
+    /// [`TransactionalStorageService::collect_pending_uploads`]. Either way, writes cut off by a
+    /// crash are settled by [`TransactionalStorageService::recover_upload_journal`].
+    pub fn with_two_phase_uploads(mut self, enabled: bool) -> Self {
+        self.two_phase_uploads = enabled;
+        self
+    }
+
+    /// Reject uploads whose declared content type contradicts the type detected from their bytes
+    /// Writes that fail the check return `MimeTypeMismatch` and leave nothing behind
+    pub fn with_strict_mime_types(mut self, enabled: bool) -> Self {
+        self.strict_mime_types = enabled;
+        self
+    }
+
+    /// Validate every stored upload against `policy`
+    /// Uploads that break a rule fail with `PolicyViolation` and leave nothing behind
+    pub fn with_upload_policy(mut self, policy: UploadPolicy) -> Self {
+        self.upload_policy = policy;
+        self
+    }
+
+    /// Rules uploads are validated against
+    pub fn upload_policy(&self) -> &UploadPolicy {
+        &self.upload_policy
+    }
+
+    /// Enforce per-user storage limits (file size, total size, file count) on every write
+    /// Writes that would exceed a limit fail with `QuotaExceeded`
+    pub fn with_limits(mut self, limits: Arc<dyn LimitsProvider>) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Write the blobs of owners who registered their own bucket to that bucket
+    /// Their blobs are not tenant-prefixed, and are skipped by garbage collection and consistency checks
+    pub fn with_buckets(mut self, buckets: Arc<dyn BucketResolver>) -> Self {
+        self.buckets = Some(buckets);
+        self
+    }
+
+    /// Send webhooks when files are created, deleted, or downloaded
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Broadcast events on another service's channel, so one subscription sees the files
+    /// stored or deleted through either service
+    pub fn with_event_sender(mut self, sender: broadcast::Sender<StorageEvent>) -> Self {
+        self.events = sender;
+        self
+    }
+
+    /// Receive every file stored or deleted through this service from now on
+    /// Subscribers must filter by owner; the channel carries all users' files
+    pub fn subscribe(&self) -> broadcast::Receiver<StorageEvent> {
+        self.events.subscribe()
+    }
+
+    /// The channel events are broadcast on, for sharing with [`Self::with_event_sender`]
+    pub fn event_sender(&self) -> broadcast::Sender<StorageEvent> {
+        self.events.clone()
+    }
+
+    /// Generate file links against an external origin (a CDN or reverse proxy)
+    /// instead of leaving them relative to the server's bind address
+    pub fn with_public_base_url(mut self, base_url: Option<String>) -> Self {
+        self.public_base_url = base_url.map(|url| url.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Sign presigned upload URLs with `key`
+    /// Every instance behind the same URL must share the key; without one, uploads can't be presigned
+    pub fn with_upload_signing_key(mut self, key: Option<String>) -> Self {
+        self.upload_signing_key = key;
+        self
+    }
+
+    /// How IDs of newly stored files are generated
+    pub fn id_strategy(&self) -> crate::FileIdStrategy {
+        self.storage.id_strategy()
+    }
+
+    /// Public URL for a server path such as `/assets/<name>`
+    /// Anything that signs a link must sign this URL, since it is what clients will request
+    pub fn public_url(&self, path: &str) -> String {
+        match &self.public_base_url {
+            Some(base_url) => format!("{}{}", base_url, path),
+            None => path.to_string(),
+        }
+    }
+
+    /// Storage scope for blobs owned by a user, routed to their own bucket if they have one
+    async fn storage_for(&self, user_id: i64) -> Result<StorageService> {
+        if let Some(storage) = self.external_storage_for(user_id).await? {
+            return Ok(storage.with_id_strategy(self.storage.id_strategy()));
+        }
+        self.managed_storage_for(user_id)
+    }
+
+    /// Read a file's blob by the stored name recorded for it
+    /// Files with a recorded checksum are verified
+    async fn read_blob(&self, storage: &StorageService, file: &File) -> Result<Vec<u8>> {
+        let read = |stored_name: String| async move {
+            match &file.checksum {
+                Some(checksum) => storage.retrieve_verified(&stored_name, checksum).await,
+                None => storage.retrieve(&stored_name).await,
+            }
+        };
+
+        match read(file.stored_name.clone()).await {
+            Err(StorageError::FileNotFound(missing)) => match legacy_stored_name(storage, file).await? {
+                Some(stored_name) => read(stored_name).await,
+                None => Err(StorageError::FileNotFound(missing)),
+            },
+            result => result,
+        }
+    }
+
+    /// Open a file's blob as a stream, like [`Self::read_blob`] without loading it into memory
+    /// Verification happens as the stream is read, so corruption fails its last read
+    async fn open_blob(&self, storage: &StorageService, file: &File) -> Result<BlobReader> {
+        let open = |stored_name: String| async move {
+            match &file.checksum {
+                Some(checksum) => storage.retrieve_stream_verified(&stored_name, checksum).await,
+                None => storage.retrieve_stream(&stored_name).await,
+            }
+        };
+
+        match open(file.stored_name.clone()).await {
+            Err(StorageError::FileNotFound(missing)) => match legacy_stored_name(storage, file).await? {
+                Some(stored_name) => open(stored_name).await,
+                None => Err(StorageError::FileNotFound(missing)),
+            },
+            result => result,
+        }
+    }
+
+    /// Open a file's blob to send to a client
+    /// Small files are verified against their checksum before anything is sent, and a corrupted
+    /// one fails with `StorageError::ChecksumMismatch` and is recorded as corrupted; larger ones
+    /// are verified as the stream is read.
+    async fn open_download(&self, storage: &StorageService, file: &File) -> Result<BlobReader> {
+        if file.checksum.is_none() || file.size > VERIFY_BEFORE_SENDING_SIZE {
+            return self.open_blob(storage, file).await;
+        }
+        match self.read_blob(storage, file).await {
+            Ok(data) => Ok(Box::new(std::io::Cursor::new(data))),
+            Err(StorageError::ChecksumMismatch(mismatch)) => {
+                if let Some(file_id) = &file.id {
+                    self.record_corrupted(file_id).await;
+                }
+                Err(StorageError::ChecksumMismatch(mismatch))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The user's own bucket, if they registered one
+    async fn external_storage_for(&self, user_id: i64) -> Result<Option<StorageService>> {
+        match &self.buckets {
+            Some(buckets) => buckets.storage_for_owner(user_id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Storage scope for a user's blobs within the server's own storage
+    fn managed_storage_for(&self, user_id: i64) -> Result<StorageService> {
+        if self.tenant_isolation {
+            self.storage.tenant(&tenant_for_user(user_id))
+        } else {
+            Ok(self.storage.clone())
+        }
+    }
+
+    /// Blob count and bytes actually held in storage for a user's tenant
+    pub async fn get_tenant_usage(&self, user_id: i64) -> Result<StorageUsage> {
+        if !self.tenant_isolation {
+            return Err(StorageError::InvalidInput("tenant isolation is not enabled".to_string()));
+        }
+        self.storage_for(user_id).await?.usage().await
     }
 
     /// Store a file with database metadata tracking
-    /// Uses compensating transaction pattern: write file first, then DB, cleanup on failure
+    /// The write is journaled first, so a blob and its metadata converge even if the process
+    /// dies in between; see [`TransactionalStorageService::recover_upload_journal`]
     pub async fn store_with_metadata(
         &self,
         data: &[u8],
@@ -23,20 +304,238 @@ impl TransactionalStorageService {
         user_id: i64,
         mime_type: Option<String>,
     ) -> Result<File> {
-        // Step 1: Write file to disk
-        let file_metadata = self.storage.store(data, original_name, mime_type.clone()).await?;
+        let storage = self.storage_for(user_id).await?;
+        self.store_journaled(&storage, data, original_name, user_id, mime_type).await
+    }
+
+    /// Store a file from a reader and record its metadata (transactional)
+    /// The file is written in chunks, so uploads never have to fit in memory
+    pub async fn store_stream_with_metadata<R>(
+        &self,
+        reader: R,
+        original_name: &str,
+        user_id: i64,
+        mime_type: Option<String>,
+    ) -> Result<File>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let storage = self.storage_for(user_id).await?;
+        // Stop reading as soon as the upload outgrows the policy; the backend drops what it wrote
+        let reader = self.upload_policy.limit_reader(reader);
+        self.store_journaled(&storage, reader, original_name, user_id, mime_type).await
+    }
+
+    /// Journal a write, write the blob, and record its metadata
+    async fn store_journaled<R>(
+        &self,
+        storage: &StorageService,
+        reader: R,
+        original_name: &str,
+        user_id: i64,
+        mime_type: Option<String>,
+    ) -> Result<File>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        // Step 1: Journal the blob under the name it will get, before any of it is written
+        let reserved = storage.reserve_name(original_name);
+        self.journal_upload(storage, &reserved, user_id).await?;
+
+        // Step 2: Write the blob; a failed write removes what it wrote, so the entry can go
+        let stored = if self.two_phase_uploads {
+            storage.store_pending_reserved(&reserved, reader, original_name, mime_type).await
+        } else {
+            storage.store_stream_reserved(&reserved, reader, original_name, mime_type).await
+        };
+        let file_metadata = match stored {
+            Ok(file_metadata) => file_metadata,
+            Err(e) => {
+                self.clear_journal_entry(&reserved.stored_name).await;
+                return Err(surface_violation(e));
+            }
+        };
+
+        // Step 3: Insert metadata into database
+        self.record_stored_file(storage, file_metadata, user_id).await
+    }
+
+    /// Add a journal entry for a blob about to be written under `reserved`
+    async fn journal_upload(&self, storage: &StorageService, reserved: &ReservedName, user_id: i64) -> Result<()> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "INSERT INTO {} (stored_name, user_id, storage_path, pending_since, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            UPLOAD_JOURNAL_TABLE
+        );
+        let pending_since = if self.two_phase_uploads {
+            QueryValue::String(reserved.created_at.to_rfc3339())
+        } else {
+            QueryValue::Null
+        };
+        let params = [
+            QueryValue::String(reserved.stored_name.clone()),
+            QueryValue::I64(user_id),
+            QueryValue::String(storage.location()),
+            pending_since,
+            QueryValue::String(Utc::now().to_rfc3339()),
+        ];
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+        Ok(())
+    }
 
-        // Step 2: Insert metadata into database
-        let file = File::new(
+    /// Remove the journal entry of a write that has settled
+    /// Failing to remove it is harmless: recovery finds the write settled and drops the entry
+    async fn clear_journal_entry(&self, stored_name: &str) {
+        let backend = self.db.backend();
+        let sql = format!("DELETE FROM {} WHERE stored_name = ?1", UPLOAD_JOURNAL_TABLE);
+        if let Err(e) = backend.execute(&sql, &[QueryValue::String(stored_name.to_string())]).await {
+            eprintln!("⚠️  Failed to clear upload journal entry for {}: {}", stored_name, e);
+        }
+    }
+
+    /// Settle journaled uploads older than `grace` that never finished, such as those cut off
+    /// by a crash. A blob whose metadata was recorded is kept (and committed, if pending); any
+    /// other blob is removed. Run at startup and periodically; `grace` keeps uploads still in
+    /// flight on other instances out of reach.
+    pub async fn recover_upload_journal(&self, grace: std::time::Duration) -> Result<JournalRecovery> {
+        let grace = Duration::from_std(grace)
+            .map_err(|e| StorageError::InvalidInput(format!("Invalid grace period: {}", e)))?;
+        let cutoff = Utc::now() - grace;
+        let backend = self.db.backend();
+
+        let sql = format!("SELECT * FROM {}", UPLOAD_JOURNAL_TABLE);
+        let entries: Vec<JournalEntry> = backend.fetch_all_params(&sql, &[]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .iter()
+            .filter_map(JournalEntry::from_json)
+            .filter(|entry| entry.created_at <= cutoff)
+            .collect();
+
+        let mut recovery = JournalRecovery::default();
+        for entry in entries {
+            // One entry failing must not keep the rest from being settled; it is retried next pass
+            match self.settle_journal_entry(&entry).await {
+                Ok(Some(true)) => recovery.completed += 1,
+                Ok(Some(false)) => recovery.rolled_back += 1,
+                Ok(None) => recovery.unresolved += 1,
+                Err(e) => {
+                    eprintln!("⚠️  Failed to recover journaled upload {}: {}", entry.stored_name, e);
+                    recovery.unresolved += 1;
+                }
+            }
+        }
+
+        Ok(recovery)
+    }
+
+    /// Keep or remove the blob of one journaled upload and clear its entry
+    ///
+    /// # Returns
+    /// Whether the upload's metadata was recorded, or `None` when the entry was left in place
+    async fn settle_journal_entry(&self, entry: &JournalEntry) -> Result<Option<bool>> {
+        // The owner's storage may have moved to another bucket since; leave the entry for an operator
+        let storage = self.storage_for(entry.user_id).await?;
+        if storage.location() != entry.storage_path {
+            eprintln!(
+                "⚠️  Journaled upload {} was written to {}, but user {} now stores in {}",
+                entry.stored_name, entry.storage_path, entry.user_id, storage.location()
+            );
+            return Ok(None);
+        }
+
+        let recorded_sql = format!("SELECT COUNT(*) as count FROM {} WHERE stored_name = ?1", File::table_name());
+        let recorded = self.db.backend().fetch_one_params(&recorded_sql, &[QueryValue::String(entry.stored_name.clone())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| json.get("count").and_then(|v| v.as_i64()))
+            .unwrap_or(0) > 0;
+
+        // A blob that is already gone was committed, removed, or never written
+        let settled = match (entry.pending_blob(storage.tenant_id()), recorded) {
+            (Some(pending), true) => storage.commit_pending(&pending).await,
+            (Some(pending), false) => storage.discard_pending(&pending).await,
+            (None, true) => Ok(()),
+            (None, false) => storage.delete(&entry.stored_name).await,
+        };
+        match settled {
+            Ok(()) | Err(StorageError::FileNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        self.clear_journal_entry(&entry.stored_name).await;
+        Ok(Some(recorded))
+    }
+
+    /// Insert metadata for a freshly stored blob, deleting the blob if the insert fails
+    /// In two-phase mode the blob is pending until the insert succeeds, and is committed afterwards
+    async fn record_stored_file(&self, storage: &StorageService, file_metadata: FileMetadata, user_id: i64) -> Result<File> {
+        let pending = self.two_phase_uploads.then(|| storage.pending_for(&file_metadata));
+
+        // The size and content of a streamed upload are only known once it is written
+        let checked = match self.upload_policy.check_stored(&file_metadata) {
+            Ok(()) => match self.check_mime_type(&file_metadata) {
+                Ok(()) => self.check_limits(user_id, 1, file_metadata.size, file_metadata.size).await,
+                Err(e) => Err(e),
+            },
+            Err(violation) => Err(violation.into()),
+        };
+        if let Err(e) = checked {
+            self.discard_stored_file(storage, &file_metadata.stored_name, pending.as_ref()).await;
+            return Err(e);
+        }
+
+        // The blob is still under its pending name when uploads are two-phase
+        let blob_name = pending.as_ref().map(|p| p.pending_name()).unwrap_or_else(|| file_metadata.stored_name.clone());
+        let content_type = file_metadata.detected_mime_type.as_deref().or(file_metadata.mime_type.as_deref());
+        let media = media::probe(storage, &blob_name, content_type, file_metadata.size).await;
+
+        let mut file = File::new(
             file_metadata.id.clone(),
             user_id,
             file_metadata.original_name,
             file_metadata.stored_name.clone(),
             file_metadata.size as i64,
-            mime_type,
-            self.storage.base_path().to_string_lossy().to_string(),
+            file_metadata.mime_type,
+            storage.location(),
+            Some(file_metadata.checksum),
         );
+        file.detected_mime_type = file_metadata.detected_mime_type;
+        file.set_media(media);
+
+        // Execute insert with compensating action on failure
+        match self.insert_file(&file).await {
+            Ok(()) => {
+                if let Some(pending) = &pending {
+                    // If this fails the row already exists, so journal recovery commits the blob
+                    storage.commit_pending(pending).await?;
+                }
+                self.clear_journal_entry(&file.stored_name).await;
+                self.record_transfer(user_id, TransferDirection::Upload, file.size).await;
+                self.notify(FileEvent::Created, &file, serde_json::json!({})).await;
+                self.publish(StorageEvent::Uploaded { file: file.clone(), copied_from: None });
+                Ok(file)
+            }
+            Err(e) => {
+                self.discard_stored_file(storage, &file_metadata.stored_name, pending.as_ref()).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Compensating action: delete a blob whose metadata won't be recorded
+    /// If the delete fails, the journal entry stays so that recovery removes the blob later
+    async fn discard_stored_file(&self, storage: &StorageService, stored_name: &str, pending: Option<&PendingBlob>) {
+        let removed = match pending {
+            Some(pending) => storage.discard_pending(pending).await,
+            None => storage.delete(stored_name).await,
+        };
+        if removed.is_ok() {
+            self.clear_journal_entry(stored_name).await;
+        }
+    }
 
+    /// Insert a file's metadata row
+    async fn insert_file(&self, file: &File) -> Result<()> {
         let backend = self.db.backend();
         let mut query_builder = backend.query_builder();
 
@@ -50,56 +549,2937 @@ impl TransactionalStorageService {
         let sql = query_builder.build()
             .map_err(|e| StorageError::StorageError(format!("Query build error: {}", e)))?;
 
-        // Execute insert with compensating action on failure
-        match backend.execute(&sql, query_builder.params()).await {
-            Ok(_) => Ok(file),
+        backend.execute(&sql, query_builder.params()).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// In strict mode, check the declared content type against the detected one
+    fn check_mime_type(&self, file_metadata: &FileMetadata) -> Result<()> {
+        let declared = file_metadata.mime_type.as_deref();
+        let detected = file_metadata.detected_mime_type.as_deref();
+        if !self.strict_mime_types || mime_types_match(declared, detected) {
+            return Ok(());
+        }
+
+        Err(StorageError::MimeTypeMismatch(format!(
+            "declared as {} but the content is {}",
+            declared.unwrap_or_default(),
+            detected.unwrap_or_default()
+        )))
+    }
+
+    /// Check that a write stays within the user's storage limits, if any are configured
+    /// `new_files` is 1 for a new file and 0 when an existing file grows to `file_size`.
+    /// Files of organizations with a plan of their own don't count; see
+    /// [`Self::check_organization_limits`].
+    async fn check_limits(&self, user_id: i64, new_files: u64, file_size: u64, added_bytes: u64) -> Result<()> {
+        let Some(limits) = &self.limits else {
+            return Ok(());
+        };
+        let limits = limits.limits_for(user_id).await?;
+        self.check_usage(&limits, &owner_pool_filter(), user_id, new_files, file_size, added_bytes).await
+    }
+
+    /// Check that a write stays within an organization's storage limits
+    /// The files of an organization with its own plan are pooled under that plan. Without one,
+    /// they share its owner's quota with the owner's files, so creating organizations doesn't
+    /// add storage.
+    async fn check_organization_limits(&self, organization_id: i64, new_files: u64, file_size: u64, added_bytes: u64) -> Result<()> {
+        let Some(limits) = &self.limits else {
+            return Ok(());
+        };
+        let organization = self.find_organization(organization_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(format!("organization {}", organization_id)))?;
+        if organization.plan.is_none() {
+            return self.check_limits(organization.owner_id, new_files, file_size, added_bytes).await;
+        }
+        let limits = limits.organization_limits(&organization).await?;
+        self.check_usage(&limits, "organization_id = ?1", organization_id, new_files, file_size, added_bytes).await
+    }
+
+    /// Whose quota a file of `user_id` in `organization_id` counts toward
+    async fn quota_owner(&self, user_id: i64, organization_id: Option<i64>) -> Result<QuotaOwner> {
+        let Some(organization_id) = organization_id else {
+            return Ok(QuotaOwner::User(user_id));
+        };
+        let organization = self.find_organization(organization_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(format!("organization {}", organization_id)))?;
+        Ok(match organization.plan {
+            Some(_) => QuotaOwner::Organization(organization_id),
+            None => QuotaOwner::User(organization.owner_id),
+        })
+    }
+
+    /// Check that a write to an existing file stays within the limits its storage counts toward
+    async fn check_file_limits(&self, file: &File, file_size: u64, added_bytes: u64) -> Result<()> {
+        match file.organization_id {
+            Some(organization_id) => self.check_organization_limits(organization_id, 0, file_size, added_bytes).await,
+            None => self.check_limits(file.user_id, 0, file_size, added_bytes).await,
+        }
+    }
+
+    /// Check the files matching `filter` against `limits`
+    async fn check_usage(
+        &self,
+        limits: &StorageLimits,
+        filter: &str,
+        filter_value: i64,
+        new_files: u64,
+        file_size: u64,
+        added_bytes: u64,
+    ) -> Result<()> {
+        // Files in the trash still occupy storage, so they count too
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT COUNT(*) as file_count, COALESCE(SUM(size), 0) as total_size FROM {} WHERE {}",
+            File::table_name(),
+            filter
+        );
+        let row = backend.fetch_one_params(&sql, &[QueryValue::I64(filter_value)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        let (file_count, total_size) = row
+            .map(|json| (json_i64(&json, "file_count"), json_i64(&json, "total_size")))
+            .unwrap_or_default();
+
+        // Totals come from recorded metadata, so `added_bytes` accounts for the write in progress
+        limits.check(file_count as u64, total_size as u64, new_files, file_size, added_bytes)
+    }
+
+    /// Resolve pending blobs older than `grace` left behind by interrupted two-phase uploads
+    /// Blobs whose metadata was recorded are committed; all others are deleted. Blobs of
+    /// resumable uploads in progress are kept, and expired uploads are abandoned.
+    pub async fn collect_pending_uploads(&self, grace: std::time::Duration) -> Result<PendingUploadStats> {
+        let grace = Duration::from_std(grace)
+            .map_err(|e| StorageError::InvalidInput(format!("Invalid grace period: {}", e)))?;
+        let cutoff = Utc::now() - grace;
+        let backend = self.db.backend();
+        let sql = format!("SELECT COUNT(*) as count FROM {} WHERE stored_name = ?1", File::table_name());
+
+        // Resumable uploads keep their bytes pending until they complete or expire
+        let now = Utc::now();
+        let sessions_sql = format!("SELECT * FROM {}", UPLOAD_SESSIONS_TABLE);
+        let sessions: Vec<UploadSession> = backend.fetch_all_params(&sessions_sql, &[]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .iter()
+            .filter_map(UploadSession::from_json)
+            .collect();
+        let mut open_uploads = HashSet::new();
+        for session in &sessions {
+            if session.is_expired(now) {
+                self.delete_upload_session(&session.upload_id).await?;
+            } else {
+                open_uploads.insert(session.stored_name.as_str());
+            }
+        }
+
+        let mut stats = PendingUploadStats::default();
+        for pending in self.storage.list_pending().await? {
+            if pending.created_at > cutoff || open_uploads.contains(pending.stored_name.as_str()) {
+                continue;
+            }
+
+            let recorded = backend.fetch_one_params(&sql, &[QueryValue::String(pending.stored_name.clone())]).await
+                .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+                .and_then(|json| json.get("count").and_then(|v| v.as_i64()))
+                .unwrap_or(0) > 0;
+
+            if recorded {
+                self.storage.commit_pending(&pending).await?;
+                stats.committed += 1;
+            } else {
+                self.storage.discard_pending(&pending).await?;
+                stats.removed += 1;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Reconcile blobs in storage with the files table
+    /// 
+    /// Blobs no file refers to (left behind when a compensating delete failed, for example) are
+    /// deleted once they are older than `grace`, so uploads still in flight are never touched.
+    /// Files whose blob is missing are only reported; their metadata is left for an operator.
+    /// Pending blobs, public assets and files in users' own buckets are not considered.
+    pub async fn gc(&self, grace: std::time::Duration, dry_run: bool) -> Result<GcReport> {
+        let grace = Duration::from_std(grace)
+            .map_err(|e| StorageError::InvalidInput(format!("Invalid grace period: {}", e)))?;
+        let cutoff = Utc::now() - grace;
+
+        // List blobs before reading metadata, so a blob whose row appears in between is recent
+        let blobs = self.storage.list_blobs().await?;
+
+        let backend = self.db.backend();
+        let sql = format!("SELECT id, user_id, stored_name, created_at FROM {}", File::table_name());
+        let rows = backend.fetch_all_params(&sql, &[]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        let recorded: HashSet<&str> = rows.iter()
+            .filter_map(|row| row.get("stored_name").and_then(|v| v.as_str()))
+            .collect();
+        let present: HashSet<String> = blobs.iter().map(StoredBlob::key).collect();
+
+        let mut report = GcReport::default();
+        for blob in &blobs {
+            if blob.tenant.as_deref() == Some(ASSETS_NAMESPACE) || recorded.contains(blob.stored_name.as_str()) {
+                continue;
+            }
+
+            let storage = match &blob.tenant {
+                Some(tenant) => self.storage.tenant(tenant)?,
+                None => self.storage.clone(),
+            };
+
+            // Blobs of unknown age are kept
+            let expired = storage.modified(&blob.stored_name).await?
+                .is_some_and(|modified| modified <= cutoff);
+            if !expired {
+                report.recent_blobs += 1;
+                continue;
+            }
+
+            report.orphaned_blobs.push(blob.key());
+            if !dry_run {
+                storage.delete(&blob.stored_name).await?;
+                report.removed_blobs += 1;
+            }
+        }
+
+        for row in &rows {
+            let (Some(id), Some(stored_name)) = (
+                row.get("id").and_then(|v| v.as_str()),
+                row.get("stored_name").and_then(|v| v.as_str()),
+            ) else {
+                continue;
+            };
+
+            // A two-phase upload commits its blob just after recording the row
+            let created_at = row.get("created_at")
+                .and_then(|v| v.as_str())
+                .and_then(|v| DateTime::parse_from_rfc3339(v).ok());
+            if created_at.is_none_or(|created_at| created_at > cutoff) {
+                continue;
+            }
+
+            // Blobs in an owner's own bucket are theirs to manage
+            let user_id = json_i64(row, "user_id");
+            if self.external_storage_for(user_id).await?.is_some() {
+                continue;
+            }
+            let storage = self.managed_storage_for(user_id)?;
+            let expected = StoredBlob {
+                tenant: storage.tenant_id().map(str::to_string),
+                stored_name: stored_name.to_string(),
+            };
+            if !present.contains(&expected.key()) && !storage.exists(stored_name).await {
+                report.missing_blobs.push(id.to_string());
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Re-hash stored files and compare them with their recorded checksums
+    ///
+    /// Checks `sample` files, or every file when `None`, starting with those never audited and
+    /// then those audited longest ago, so repeated sampled audits cover every file in turn. A full
+    /// audit reads the files a page at a time in ID order. Each checked file's `integrity_status`
+    /// is updated, unless the file was rewritten while it was being hashed; files without a
+    /// checksum or in the trash are skipped, and files that can't be read for another reason keep
+    /// their status.
+    pub async fn audit_integrity(&self, sample: Option<u64>) -> Result<IntegrityAudit> {
+        let backend = self.db.backend();
+        let mut audit = IntegrityAudit::default();
+
+        let Some(sample) = sample else {
+            let sql = format!(
+                "SELECT * FROM {} WHERE checksum IS NOT NULL AND deleted_at IS NULL AND id > ?1 \
+                 ORDER BY id LIMIT ?2",
+                File::table_name()
+            );
+            let mut after = String::new();
+            loop {
+                let json_rows = backend.fetch_all_params(&sql, &[
+                    QueryValue::String(after.clone()),
+                    QueryValue::I64(AUDIT_PAGE_SIZE),
+                ]).await
+                    .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+                let files = files_from_rows(&json_rows)?;
+                let Some(last) = files.last().and_then(|file| file.id.clone()) else {
+                    return Ok(audit);
+                };
+                for file in &files {
+                    self.audit_file(file, &mut audit).await?;
+                }
+                if (files.len() as i64) < AUDIT_PAGE_SIZE {
+                    return Ok(audit);
+                }
+                after = last;
+            }
+        };
+
+        let sql = format!(
+            "SELECT * FROM {} WHERE checksum IS NOT NULL AND deleted_at IS NULL \
+             ORDER BY CASE WHEN integrity_checked_at IS NULL THEN 0 ELSE 1 END, integrity_checked_at, id \
+             LIMIT ?1",
+            File::table_name()
+        );
+        let limit = sample.min(i64::MAX as u64) as i64;
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(limit)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        for file in &files_from_rows(&json_rows)? {
+            self.audit_file(file, &mut audit).await?;
+        }
+        Ok(audit)
+    }
+
+    /// Hash one file for an integrity audit and record what was found
+    async fn audit_file(&self, file: &File, audit: &mut IntegrityAudit) -> Result<()> {
+        let (Some(id), Some(checksum)) = (file.id.clone(), file.checksum.clone()) else {
+            return Ok(());
+        };
+
+        let status = match self.hash_blob(file).await {
+            Ok(actual) if actual.eq_ignore_ascii_case(&checksum) => IntegrityStatus::Ok,
+            Ok(_) => IntegrityStatus::Corrupted,
+            Err(StorageError::FileNotFound(_)) => IntegrityStatus::Missing,
             Err(e) => {
-                // Compensating action: delete the file we just wrote
-                let _ = self.storage.delete(&file_metadata.stored_name).await;
-                Err(StorageError::StorageError(format!("Database insert failed: {}", e)))
+                eprintln!("⚠️  Integrity audit could not read file {}: {}", id, e);
+                audit.errors += 1;
+                return Ok(());
+            }
+        };
+
+        // A file rewritten while it was hashed has a new checksum, and what was found is stale
+        let update_sql = format!(
+            "UPDATE {} SET integrity_status = ?1, integrity_checked_at = ?2 WHERE id = ?3 AND checksum = ?4",
+            File::table_name()
+        );
+        let updated = self.db.backend().execute(&update_sql, &[
+            QueryValue::String(status.as_str().to_string()),
+            QueryValue::String(Utc::now().to_rfc3339()),
+            QueryValue::String(id.clone()),
+            QueryValue::String(checksum),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+        if updated == 0 {
+            return Ok(());
+        }
+
+        audit.checked += 1;
+        match status {
+            IntegrityStatus::Ok => {}
+            IntegrityStatus::Corrupted => audit.corrupted.push(id),
+            IntegrityStatus::Missing => audit.missing.push(id),
+        }
+        Ok(())
+    }
+
+    /// Record that a file's content no longer matches its checksum, as an integrity audit would
+    /// Called when a download finds the corruption; failures are only logged
+    pub async fn record_corrupted(&self, file_id: &str) {
+        let sql = format!(
+            "UPDATE {} SET integrity_status = ?1, integrity_checked_at = ?2 WHERE id = ?3",
+            File::table_name()
+        );
+        let result = self.db.backend().execute(&sql, &[
+            QueryValue::String(IntegrityStatus::Corrupted.as_str().to_string()),
+            QueryValue::String(Utc::now().to_rfc3339()),
+            QueryValue::String(file_id.to_string()),
+        ]).await;
+        if let Err(e) = result {
+            eprintln!("⚠️  Failed to record file {} as corrupted: {}", file_id, e);
+        }
+    }
+
+    /// Hash a file's blob as it is stored, without checking it against anything
+    async fn hash_blob(&self, file: &File) -> Result<String> {
+        let storage = self.storage_for(file.user_id).await?;
+        let reader = match storage.retrieve_stream(&file.stored_name).await {
+            Err(StorageError::FileNotFound(missing)) => match legacy_stored_name(&storage, file).await? {
+                Some(stored_name) => storage.retrieve_stream(&stored_name).await?,
+                None => return Err(StorageError::FileNotFound(missing)),
+            },
+            result => result?,
+        };
+
+        let mut reader = HashingReader::new(reader);
+        tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+        Ok(reader.finish())
+    }
+
+    /// Integrity of every file as recorded by past audits, with the files found damaged
+    pub async fn integrity_report(&self) -> Result<IntegrityReport> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT integrity_status, COUNT(*) as count FROM {} \
+             WHERE checksum IS NOT NULL AND deleted_at IS NULL GROUP BY integrity_status",
+            File::table_name()
+        );
+        let rows = backend.fetch_all_params(&sql, &[]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        let mut report = IntegrityReport::default();
+        for row in &rows {
+            let count = row.get("count").and_then(|v| v.as_i64()).unwrap_or(0) as u64;
+            match row.get("integrity_status").and_then(|v| v.as_str()).and_then(IntegrityStatus::from_str) {
+                Some(IntegrityStatus::Ok) => report.ok += count,
+                Some(IntegrityStatus::Corrupted) => report.corrupted += count,
+                Some(IntegrityStatus::Missing) => report.missing += count,
+                None => report.unchecked += count,
+            }
+        }
+
+        let sql = format!(
+            "SELECT * FROM {} WHERE integrity_status IN (?1, ?2) AND deleted_at IS NULL ORDER BY integrity_checked_at DESC",
+            File::table_name()
+        );
+        let params = [
+            QueryValue::String(IntegrityStatus::Corrupted.as_str().to_string()),
+            QueryValue::String(IntegrityStatus::Missing.as_str().to_string()),
+        ];
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        report.flagged = files_from_rows(&json_rows)?;
+
+        Ok(report)
+    }
+
+    /// Extract the text of up to `batch` files that have none yet, or whose content changed
+    /// since it was extracted, so searches match their content
+    /// Files larger than `max_file_size` are recorded as unsupported without being read. Files
+    /// that can't be read are recorded as failed, so they don't hold up the files after them,
+    /// and are tried again once their content changes.
+    pub async fn extract_texts(&self, batch: u64, max_file_size: u64) -> Result<TextExtraction> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT f.* FROM {} f LEFT JOIN {} t ON t.file_id = f.id \
+             WHERE f.checksum IS NOT NULL AND f.deleted_at IS NULL \
+             AND (t.file_id IS NULL OR t.checksum <> f.checksum) \
+             ORDER BY f.created_at LIMIT ?1",
+            File::table_name(),
+            FILE_TEXTS_TABLE
+        );
+        let limit = batch.min(i64::MAX as u64) as i64;
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(limit)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        let mut extraction = TextExtraction::default();
+        for file in files_from_rows(&json_rows)? {
+            let (Some(id), Some(checksum)) = (file.id.clone(), file.checksum.clone()) else {
+                continue;
+            };
+
+            let kind = DocumentKind::of(&file)
+                .filter(|kind| extract::is_supported(*kind) && file.size as u64 <= max_file_size);
+            let (status, content) = match kind {
+                None => (ExtractionStatus::Unsupported, String::new()),
+                Some(kind) => {
+                    let data = match self.read_file_blob(&file).await {
+                        Ok(data) => Some(data),
+                        Err(e) => {
+                            eprintln!("⚠️  Text extraction could not read file {}: {}", id, e);
+                            extraction.errors += 1;
+                            None
+                        }
+                    };
+                    match data {
+                        Some(data) => match extract::extract_text(kind, data).await {
+                            Ok(content) => (ExtractionStatus::Extracted, content),
+                            Err(_) => (ExtractionStatus::Failed, String::new()),
+                        },
+                        None => (ExtractionStatus::Failed, String::new()),
+                    }
+                }
+            };
+
+            if let Err(e) = self.record_text(&id, &checksum, status, &content).await {
+                eprintln!("⚠️  Text extraction could not record file {}: {}", id, e);
+                extraction.errors += 1;
+                continue;
+            }
+            match status {
+                ExtractionStatus::Extracted => extraction.extracted += 1,
+                ExtractionStatus::Unsupported => extraction.unsupported += 1,
+                ExtractionStatus::Failed => extraction.failed += 1,
+            }
+        }
+
+        Ok(extraction)
+    }
+
+    /// Read a file's blob from the storage it is kept in
+    async fn read_file_blob(&self, file: &File) -> Result<Vec<u8>> {
+        let storage = self.storage_for(file.user_id).await?;
+        self.read_blob(&storage, file).await
+    }
+
+    /// Replace the text recorded for a file
+    async fn record_text(&self, file_id: &str, checksum: &str, status: ExtractionStatus, content: &str) -> Result<()> {
+        let backend = self.db.backend();
+        let delete_sql = format!("DELETE FROM {} WHERE file_id = ?1", FILE_TEXTS_TABLE);
+        backend.execute(&delete_sql, &[QueryValue::String(file_id.to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+
+        let insert_sql = format!(
+            "INSERT INTO {} (file_id, checksum, status, content, extracted_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            FILE_TEXTS_TABLE
+        );
+        backend.execute(&insert_sql, &[
+            QueryValue::String(file_id.to_string()),
+            QueryValue::String(checksum.to_string()),
+            QueryValue::String(status.as_str().to_string()),
+            QueryValue::String(content.to_string()),
+            QueryValue::String(Utc::now().to_rfc3339()),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+        Ok(())
+    }
+
+    /// Check that the files table and storage agree
+    /// 
+    /// Reports files whose blob is missing, files whose blob is not the size recorded for them,
+    /// and blobs no file refers to. With `repair`, files with a missing blob are deleted,
+    /// recorded sizes are corrected to the blob's size, and unknown blobs are removed; files and
+    /// blobs newer than the default GC grace period are only reported, so uploads in flight are
+    /// never touched. Pending blobs, public assets and files in users' own buckets are not checked.
+    pub async fn verify_consistency(&self, repair: bool) -> Result<ConsistencyReport> {
+        let cutoff = Utc::now() - Duration::seconds(DEFAULT_GC_GRACE_SECONDS as i64);
+
+        // List blobs before reading metadata, so a blob whose row appears in between is recent
+        let blobs = self.storage.list_blobs().await?;
+
+        let backend = self.db.backend();
+        let sql = format!("SELECT id, user_id, stored_name, size, created_at FROM {}", File::table_name());
+        let rows = backend.fetch_all_params(&sql, &[]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        let recorded: HashSet<&str> = rows.iter()
+            .filter_map(|row| row.get("stored_name").and_then(|v| v.as_str()))
+            .collect();
+        let present: HashSet<String> = blobs.iter().map(StoredBlob::key).collect();
+
+        let mut report = ConsistencyReport::default();
+        for row in &rows {
+            let (Some(id), Some(stored_name)) = (
+                row.get("id").and_then(|v| v.as_str()),
+                row.get("stored_name").and_then(|v| v.as_str()),
+            ) else {
+                continue;
+            };
+            let expired = row.get("created_at")
+                .and_then(|v| v.as_str())
+                .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+                .is_some_and(|created_at| created_at <= cutoff);
+
+            // Blobs in an owner's own bucket are theirs to manage
+            let user_id = json_i64(row, "user_id");
+            if self.external_storage_for(user_id).await?.is_some() {
+                continue;
+            }
+            let storage = self.managed_storage_for(user_id)?;
+            let expected = StoredBlob {
+                tenant: storage.tenant_id().map(str::to_string),
+                stored_name: stored_name.to_string(),
+            };
+            if !present.contains(&expected.key()) && !storage.exists(stored_name).await {
+                report.missing_blobs.push(id.to_string());
+                if repair && expired && !self.is_on_legal_hold(File::table_name(), id).await? {
+                    let sql = format!("DELETE FROM {} WHERE id = ?1", File::table_name());
+                    backend.execute(&sql, &[QueryValue::String(id.to_string())]).await
+                        .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+                    report.repaired += 1;
+                }
+                continue;
+            }
+
+            let recorded_size = json_i64(row, "size");
+            let actual_size = storage.size(stored_name).await? as i64;
+            if actual_size != recorded_size {
+                report.size_mismatches.push(SizeMismatch {
+                    file_id: id.to_string(),
+                    recorded_size,
+                    actual_size,
+                });
+                if repair && expired {
+                    let sql = format!("UPDATE {} SET size = ?1 WHERE id = ?2", File::table_name());
+                    backend.execute(&sql, &[QueryValue::I64(actual_size), QueryValue::String(id.to_string())]).await
+                        .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+                    report.repaired += 1;
+                }
+            }
+        }
+
+        for blob in &blobs {
+            if blob.tenant.as_deref() == Some(ASSETS_NAMESPACE) || recorded.contains(blob.stored_name.as_str()) {
+                continue;
+            }
+            report.unknown_blobs.push(blob.key());
+            if !repair {
+                continue;
+            }
+
+            let storage = match &blob.tenant {
+                Some(tenant) => self.storage.tenant(tenant)?,
+                None => self.storage.clone(),
+            };
+            let expired = storage.modified(&blob.stored_name).await?
+                .is_some_and(|modified| modified <= cutoff);
+            if expired {
+                storage.delete(&blob.stored_name).await?;
+                report.repaired += 1;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Store a public asset under its content hash
+    /// Assets are immutable and not tracked in the database; the stored name is the asset's URL key
+    pub async fn store_asset(&self, data: &[u8], original_name: &str) -> Result<FileMetadata> {
+        self.storage.tenant(ASSETS_NAMESPACE)?
+            .store_content_addressed(data, original_name, None)
+            .await
+    }
+
+    /// Retrieve a public asset by its stored name
+    pub async fn retrieve_asset(&self, stored_name: &str) -> Result<Vec<u8>> {
+        self.storage.tenant(ASSETS_NAMESPACE)?.retrieve(stored_name).await
+    }
+
+    /// Move a file to the trash
+    /// The blob is kept until the file is purged, so the file can be restored
+    pub async fn delete_with_metadata(&self, file_id: &str, user_id: i64) -> Result<()> {
+        self.delete_with_metadata_if_match(file_id, user_id, None).await
+    }
+
+    /// Move a file to the trash, only if its ETag matches `if_match` (when given)
+    /// Returns `PreconditionFailed` if another client modified the file since it was read
+    pub async fn delete_with_metadata_if_match(
+        &self,
+        file_id: &str,
+        user_id: i64,
+        if_match: Option<&str>,
+    ) -> Result<()> {
+        // Verify ownership and that the client is acting on the current version
+        let mut file = self.file_for_write(file_id, user_id, if_match).await?;
+        if !self.access_for(&file, user_id).await?.is_some_and(|access| access.is_owner()) {
+            return Err(StorageError::PermissionDenied("only owners can delete a file".to_string()));
+        }
+        let deleted_at = Utc::now();
+        self.set_deleted_at(file_id, Some(deleted_at)).await?;
+
+        file.deleted_at = Some(deleted_at);
+        self.notify(FileEvent::Deleted, &file, serde_json::json!({ "permanent": false })).await;
+        self.publish(StorageEvent::Deleted { file, permanent: false });
+        Ok(())
+    }
+
+    /// List the files in the user's trash, most recently deleted first
+    pub async fn list_trashed_files(&self, user_id: i64) -> Result<Vec<File>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE user_id = ?1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+            File::table_name()
+        );
+
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        files_from_rows(&json_rows)
+    }
+
+    /// List a page of the files in the user's trash, most recently deleted first
+    pub async fn list_trashed_files_page(&self, user_id: i64, limit: i64, offset: i64) -> Result<FileSearchResults> {
+        let sql = format!(
+            "SELECT * FROM {} WHERE user_id = ?1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC, id DESC",
+            File::table_name()
+        );
+
+        let (json_rows, total) = self.fetch_page(&sql, vec![QueryValue::I64(user_id)], limit, offset).await?;
+        Ok(FileSearchResults {
+            files: files_from_rows(&json_rows)?,
+            total,
+        })
+    }
+
+    /// Move a file out of the trash
+    pub async fn restore_file(&self, file_id: &str, user_id: i64) -> Result<File> {
+        let mut file = self.trashed_file(file_id, user_id).await?;
+        self.set_deleted_at(file_id, None).await?;
+
+        file.deleted_at = None;
+        Ok(file)
+    }
+
+    /// Permanently delete a file in the trash, removing its blob and metadata
+    pub async fn purge_file(&self, file_id: &str, user_id: i64) -> Result<()> {
+        let file = self.trashed_file(file_id, user_id).await?;
+        if !self.remove_file(&file, None).await? {
+            return Err(StorageError::FileNotFound(file_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Permanently delete any file, whoever owns it and whether or not it is in the trash
+    /// For administrators; returns the deleted file's metadata
+    pub async fn force_purge_file(&self, file_id: &str) -> Result<File> {
+        let file = self.find_file(file_id, true).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+        if !self.remove_file(&file, None).await? {
+            return Err(StorageError::FileNotFound(file_id.to_string()));
+        }
+        Ok(file)
+    }
+
+    /// Permanently delete every file a user owns, including the trash
+    /// Fails with `LegalHold`, deleting nothing, if any of the files is held
+    /// 
+    /// # Returns
+    /// The number of files purged
+    pub async fn purge_user_files(&self, user_id: i64) -> Result<u64> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT * FROM {} WHERE user_id = ?1", File::table_name());
+
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        let files = files_from_rows(&json_rows)?;
+        for file_id in files.iter().filter_map(|file| file.id.as_deref()) {
+            self.ensure_not_held(File::table_name(), file_id).await?;
+        }
+        for file in &files {
+            self.remove_file(file, None).await?;
+        }
+        Ok(files.len() as u64)
+    }
+
+    /// Delete a file's metadata and blob, unless the file is held
+    /// With `trashed_by`, the file is only deleted if it is still in the trash and was trashed no
+    /// later than that. Returns false, leaving the blob alone, when no row was deleted, such as when
+    /// the file was restored or removed since it was read.
+    async fn remove_file(&self, file: &File, trashed_by: Option<DateTime<Utc>>) -> Result<bool> {
+        let file_id = file.id.as_deref()
+            .ok_or_else(|| StorageError::StorageError("File has no ID".to_string()))?;
+        self.ensure_not_held(File::table_name(), file_id).await?;
+
+        // Delete from database first (safer - if blob delete fails, the blob is only orphaned)
+        let backend = self.db.backend();
+        let mut params = vec![QueryValue::String(file_id.to_string())];
+        let sql = match trashed_by {
+            Some(cutoff) => {
+                params.push(QueryValue::String(cutoff.to_rfc3339()));
+                format!(
+                    "DELETE FROM {} WHERE id = ?1 AND deleted_at IS NOT NULL AND deleted_at <= ?2",
+                    File::table_name()
+                )
+            }
+            None => format!("DELETE FROM {} WHERE id = ?1", File::table_name()),
+        };
+        let deleted = backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+        if deleted == 0 {
+            return Ok(false);
+        }
+        let text_sql = format!("DELETE FROM {} WHERE file_id = ?1", FILE_TEXTS_TABLE);
+        backend.execute(&text_sql, &[QueryValue::String(file_id.to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+
+        self.notify(FileEvent::Deleted, file, serde_json::json!({ "permanent": true })).await;
+        self.publish(StorageEvent::Deleted { file: file.clone(), permanent: true });
+
+        match self.storage_for(file.user_id).await?.delete(&file.stored_name).await {
+            Ok(()) | Err(StorageError::FileNotFound(_)) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Permanently delete every file in the user's trash
+    /// Files under a legal hold stay in the trash
+    /// 
+    /// # Returns
+    /// The number of files purged
+    pub async fn empty_trash(&self, user_id: i64) -> Result<u64> {
+        let trashed = self.list_trashed_files(user_id).await?;
+        let mut purged = 0;
+        for file in &trashed {
+            if let Some(file_id) = &file.id {
+                if self.is_on_legal_hold(File::table_name(), file_id).await? {
+                    continue;
+                }
+                self.purge_file(file_id, user_id).await?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
+    /// Permanently delete files that have been in the trash for longer than `retention`
+    /// The longest-trashed files go first, at most `batch` of them per call. Files under a legal
+    /// hold stay in the trash, and a file that can't be removed is retried on the next call.
+    /// Files restored while the batch runs are left alone.
+    pub async fn purge_expired_trash(&self, retention: Duration, batch: u64) -> Result<TrashPurge> {
+        let cutoff = Utc::now() - retention;
+        let sql = format!(
+            "SELECT * FROM {files} WHERE deleted_at IS NOT NULL AND deleted_at <= ?1 \
+             AND id NOT IN (SELECT record_id FROM {holds} WHERE table_name = ?2 AND released_at IS NULL) \
+             ORDER BY deleted_at LIMIT ?3",
+            files = File::table_name(),
+            holds = LEGAL_HOLDS_TABLE
+        );
+        let json_rows = self.db.backend().fetch_all_params(&sql, &[
+            QueryValue::String(cutoff.to_rfc3339()),
+            QueryValue::String(File::table_name().to_string()),
+            QueryValue::I64(batch as i64),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        let mut purge = TrashPurge::default();
+        for file in files_from_rows(&json_rows)? {
+            match self.remove_file(&file, Some(cutoff)).await {
+                Ok(true) => purge.purged += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    eprintln!("⚠️  Failed to purge trashed file {}: {}", file.id.as_deref().unwrap_or_default(), e);
+                    purge.errors += 1;
+                }
+            }
+        }
+        Ok(purge)
+    }
+
+    /// Fetch a file in the trash that the user owns or co-owns
+    async fn trashed_file(&self, file_id: &str, user_id: i64) -> Result<File> {
+        let file = self.find_file(file_id, true).await?
+            .filter(File::is_trashed)
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        if !self.access_for(&file, user_id).await?.is_some_and(|access| access.is_owner()) {
+            return Err(StorageError::PermissionDenied("file belongs to another user".to_string()));
+        }
+
+        Ok(file)
+    }
+
+    /// Move a file into (`Some`) or out of (`None`) the trash
+    async fn set_deleted_at(&self, file_id: &str, deleted_at: Option<DateTime<Utc>>) -> Result<()> {
+        let backend = self.db.backend();
+        let (sql, params) = match deleted_at {
+            Some(deleted_at) => (
+                format!("UPDATE {} SET deleted_at = ?1 WHERE id = ?2", File::table_name()),
+                vec![
+                    QueryValue::String(deleted_at.to_rfc3339()),
+                    QueryValue::String(file_id.to_string()),
+                ],
+            ),
+            None => (
+                format!("UPDATE {} SET deleted_at = NULL WHERE id = ?1", File::table_name()),
+                vec![QueryValue::String(file_id.to_string())],
+            ),
+        };
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Append data to a file and update its recorded size and checksum
+    /// The checksum is carried on from the hash state saved by the previous append, so only the
+    /// new bytes are hashed; the first append to a file hashes what it already held.
+    pub async fn append_with_metadata(
+        &self,
+        file_id: &str,
+        user_id: i64,
+        data: &[u8],
+        if_match: Option<&str>,
+    ) -> Result<File> {
+        let mut file = self.file_for_write(file_id, user_id, if_match).await?;
+        let old_size = file.size as u64;
+        let new_size = old_size + data.len() as u64;
+        // Writes by users the file is shared with count against the owner's limits
+        self.check_file_limits(&file, new_size, data.len() as u64).await?;
+
+        let storage = self.storage_for(file.user_id).await?;
+        let mut hasher = match self.resume_checksum(&storage, &file).await? {
+            Some(hasher) => hasher,
+            None => hash_blob(&storage, &file.stored_name).await?,
+        };
+        let size = storage.append(&file.stored_name, data).await?;
+        let (checksum, checksum_state) = if size == new_size {
+            hasher.update(data);
+            (hasher.finish(), Some(hasher.save()))
+        } else {
+            // The blob wasn't the size recorded for it, so the saved progress doesn't describe it
+            let hasher = hash_blob(&storage, &file.stored_name).await?;
+            (hasher.finish(), Some(hasher.save()))
+        };
+        file.size = size as i64;
+        file.checksum = Some(checksum);
+        file.set_media(media::probe(&storage, &file.stored_name, file.content_type(), size).await);
+        self.update_content(&file, checksum_state.as_deref()).await?;
+        self.record_transfer(file.user_id, TransferDirection::Upload, data.len() as i64).await;
+
+        Ok(file)
+    }
+
+    /// Overwrite a byte range of a file and update its recorded size
+    pub async fn write_range_with_metadata(
+        &self,
+        file_id: &str,
+        user_id: i64,
+        offset: u64,
+        data: &[u8],
+        if_match: Option<&str>,
+    ) -> Result<File> {
+        let mut file = self.file_for_write(file_id, user_id, if_match).await?;
+        let new_size = (file.size as u64).max(offset + data.len() as u64);
+        self.check_file_limits(&file, new_size, new_size - file.size as u64).await?;
+
+        let storage = self.storage_for(file.user_id).await?;
+        let size = storage.write_at(&file.stored_name, offset, data).await?;
+        // Hashed as a stream, so a small edit to a large file doesn't load all of it
+        let hasher = hash_blob(&storage, &file.stored_name).await?;
+        file.size = size as i64;
+        file.checksum = Some(hasher.finish());
+        file.set_media(media::probe(&storage, &file.stored_name, file.content_type(), size).await);
+        self.update_content(&file, Some(&hasher.save())).await?;
+        self.record_transfer(file.user_id, TransferDirection::Upload, data.len() as i64).await;
+
+        Ok(file)
+    }
+
+    /// Rename a file, change its declared MIME type, or move it to another folder
+    /// The blob is left untouched; renames and type changes are checked against the upload policy
+    pub async fn update_metadata(
+        &self,
+        file_id: &str,
+        user_id: i64,
+        update: MetadataUpdate,
+        if_match: Option<&str>,
+    ) -> Result<File> {
+        let mut file = self.file_for_write(file_id, user_id, if_match).await?;
+
+        if let Some(name) = update.original_name {
+            file.original_name = validate_file_name(&name)?;
+        }
+        if let Some(mime_type) = update.mime_type {
+            if self.strict_mime_types && !mime_types_match(Some(&mime_type), file.detected_mime_type.as_deref()) {
+                return Err(StorageError::MimeTypeMismatch(format!(
+                    "declared as {} but the content is {}",
+                    mime_type,
+                    file.detected_mime_type.as_deref().unwrap_or_default()
+                )));
+            }
+            file.mime_type = Some(mime_type);
+        }
+        if let Some(folder) = update.folder {
+            file.folder = normalize_folder(&folder)?;
+        }
+
+        self.upload_policy.check_declared(&file.original_name, file.mime_type.as_deref(), Some(file.size as u64))?;
+
+        let backend = self.db.backend();
+        let sql = format!(
+            "UPDATE {} SET original_name = ?1, mime_type = ?2, folder = ?3 WHERE id = ?4",
+            File::table_name()
+        );
+        backend.execute(&sql, &[
+            QueryValue::String(file.original_name.clone()),
+            file.mime_type.clone().map(QueryValue::String).unwrap_or(QueryValue::Null),
+            file.folder.clone().map(QueryValue::String).unwrap_or(QueryValue::Null),
+            QueryValue::String(file_id.to_string()),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+
+        Ok(file)
+    }
+
+    /// Copy a file, duplicating its blob and metadata
+    /// The blob is copied by the backend without being read into memory. The copy lands in `folder`
+    /// when given (an empty path is the root), and next to the original otherwise.
+    pub async fn copy_with_metadata(&self, file_id: &str, user_id: i64, folder: Option<&str>) -> Result<File> {
+        let source = self.get_file_by_id(file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        if source.user_id != user_id {
+            return Err(StorageError::PermissionDenied("file belongs to another user".to_string()));
+        }
+
+        let folder = match folder {
+            Some(folder) => normalize_folder(folder)?,
+            None => source.folder.clone(),
+        };
+        self.check_limits(user_id, 1, source.size as u64, source.size as u64).await?;
+
+        let storage = self.storage_for(user_id).await?;
+        let copied = storage.copy(&FileMetadata {
+            id: file_id.to_string(),
+            original_name: source.original_name.clone(),
+            stored_name: source.stored_name.clone(),
+            size: source.size as u64,
+            mime_type: source.mime_type.clone(),
+            detected_mime_type: source.detected_mime_type.clone(),
+            checksum: source.checksum.clone().unwrap_or_default(),
+            created_at: source.created_at,
+        }).await?;
+
+        let mut file = File::new(
+            copied.id,
+            user_id,
+            source.original_name,
+            copied.stored_name.clone(),
+            source.size,
+            source.mime_type,
+            storage.location(),
+            source.checksum,
+        );
+        file.detected_mime_type = source.detected_mime_type;
+        file.folder = folder;
+        file.width = source.width;
+        file.height = source.height;
+        file.duration_ms = source.duration_ms;
+
+        if let Err(e) = self.insert_file(&file).await {
+            // Compensating action: delete the copied blob
+            let _ = storage.delete(&copied.stored_name).await;
+            return Err(e);
+        }
+
+        self.notify(FileEvent::Created, &file, serde_json::json!({ "copied_from": file_id })).await;
+        self.publish(StorageEvent::Uploaded { file: file.clone(), copied_from: Some(file_id.to_string()) });
+        Ok(file)
+    }
+
+    /// Fetch a file the user is about to modify
+    /// Checks the user owns the file or was granted write access to it, that it isn't under a
+    /// legal hold, and, when given, that `If-Match` still matches the current version
+    async fn file_for_write(&self, file_id: &str, user_id: i64, if_match: Option<&str>) -> Result<File> {
+        let file = self.get_file_by_id(file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        if !self.access_for(&file, user_id).await?.is_some_and(|access| access.can_write()) {
+            return Err(StorageError::PermissionDenied("file belongs to another user".to_string()));
+        }
+        // Held content is evidence: appending, overwriting or renaming it would alter it
+        self.ensure_not_held(File::table_name(), file_id).await?;
+
+        if let Some(if_match) = if_match {
+            if !file.matches_if_match(if_match) {
+                return Err(StorageError::PreconditionFailed(format!(
+                    "File {} has been modified (current ETag: {})",
+                    file_id,
+                    file.etag()
+                )));
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Record a file's new size, checksum, and media details after its content changed
+    /// The new content hasn't been audited yet, so its integrity status is cleared
+    async fn update_content(&self, file: &File, checksum_state: Option<&str>) -> Result<()> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "UPDATE {} SET size = ?1, checksum = ?2, checksum_state = ?3, integrity_status = NULL, integrity_checked_at = NULL, width = ?4, height = ?5, duration_ms = ?6 WHERE id = ?7",
+            File::table_name()
+        );
+        let optional = |value: Option<i64>| value.map(QueryValue::I64).unwrap_or(QueryValue::Null);
+        backend.execute(&sql, &[
+            QueryValue::I64(file.size),
+            file.checksum.clone().map(QueryValue::String).unwrap_or(QueryValue::Null),
+            checksum_state.map(|state| QueryValue::String(state.to_string())).unwrap_or(QueryValue::Null),
+            optional(file.width),
+            optional(file.height),
+            optional(file.duration_ms),
+            QueryValue::String(file.id.clone().unwrap_or_default()),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Pick up hashing a file where its last append left off
+    /// `None` when no progress was saved, or when it no longer adds up to the recorded checksum
+    /// because the file was written some other way since.
+    async fn resume_checksum(&self, storage: &StorageService, file: &File) -> Result<Option<ResumableSha256>> {
+        let (Some(file_id), Some(checksum)) = (file.id.as_deref(), file.checksum.as_deref()) else {
+            return Ok(None);
+        };
+        let sql = format!("SELECT checksum_state FROM {} WHERE id = ?1", File::table_name());
+        let row = self.db.backend().fetch_one_params(&sql, &[QueryValue::String(file_id.to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        let saved = row.as_ref().and_then(|row| row.get("checksum_state")).and_then(|v| v.as_str());
+
+        let size = file.size as u64;
+        let Some(mut hasher) = saved.and_then(|saved| ResumableSha256::resume(saved, size - size % 64)) else {
+            return Ok(None);
+        };
+        let tail = storage.retrieve_range(&file.stored_name, hasher.absorbed(), size - hasher.absorbed()).await?;
+        hasher.update(&tail);
+        Ok(Some(hasher).filter(|hasher| hasher.finish().eq_ignore_ascii_case(checksum)))
+    }
+
+    /// Retrieve a file's data (with permission check)
+    /// The owner and users the file is shared with may read it. Files with a recorded checksum
+    /// are verified, so corrupted blobs fail with `ChecksumMismatch`.
+    pub async fn retrieve_with_permission(&self, file_id: &str, user_id: i64) -> Result<Vec<u8>> {
+        let file = self.get_file_by_id(file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        if self.access_for(&file, user_id).await?.is_none() {
+            return Err(StorageError::PermissionDenied("file belongs to another user".to_string()));
+        }
+
+        // Retrieve file data, verifying it when a checksum was recorded
+        let storage = self.storage_for(file.user_id).await?;
+        let data = self.read_blob(&storage, &file).await?;
+
+        // Record the access for the "recent files" view and usage reports (best effort)
+        let is_owner = file.user_id == user_id;
+        if is_owner {
+            self.record_access(file_id, user_id).await;
+        }
+        self.count_download(file_id).await;
+        self.record_transfer(file.user_id, TransferDirection::Download, data.len() as i64).await;
+        let via = if is_owner { "owner" } else { "recipient" };
+        self.notify(FileEvent::Downloaded, &file, serde_json::json!({ "via": via })).await;
+
+        Ok(data)
+    }
+
+    /// A file's metadata and a stream of its data (with permission check)
+    /// The owner and users the file is shared with may read the file. One metadata lookup
+    /// serves both the permission check and the caller's response headers, and the data is never
+    /// held in memory. Small files are verified against their checksum before they are returned,
+    /// and a corrupted one fails with `StorageError::ChecksumMismatch` and is recorded as corrupted;
+    /// larger ones are verified as the stream is read.
+    pub async fn retrieve_with_metadata(&self, file_id: &str, user_id: i64) -> Result<(File, BlobReader)> {
+        let file = self.get_file_with_permission(file_id, user_id).await?;
+        let is_owner = file.user_id == user_id;
+
+        let storage = self.storage_for(file.user_id).await?;
+        let reader = self.open_download(&storage, &file).await?;
+
+        if is_owner {
+            self.record_access(file_id, user_id).await;
+        }
+        self.count_download(file_id).await;
+        // Downloads by users the file is shared with count against the owner's bandwidth, like share links
+        self.record_transfer(file.user_id, TransferDirection::Download, file.size).await;
+        let via = if is_owner { "owner" } else { "recipient" };
+        self.notify(FileEvent::Downloaded, &file, serde_json::json!({ "via": via })).await;
+
+        Ok((file, reader))
+    }
+
+    /// A file's metadata, if the user may read the file
+    pub async fn get_file_with_permission(&self, file_id: &str, user_id: i64) -> Result<File> {
+        let file = self.get_file_by_id(file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        if self.access_for(&file, user_id).await?.is_none() {
+            return Err(StorageError::PermissionDenied("file belongs to another user".to_string()));
+        }
+        Ok(file)
+    }
+
+    /// Read part of a file (with permission check)
+    /// Reads at most `len` bytes from `offset`, cut short at the end of the file. Partial reads
+    /// can't be verified against the file's checksum. Every read counts toward the owner's
+    /// bandwidth, but only one from the start of the file counts as a download.
+    pub async fn retrieve_range_with_permission(&self, file_id: &str, user_id: i64, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let file = self.get_file_with_permission(file_id, user_id).await?;
+        let is_owner = file.user_id == user_id;
+
+        let storage = self.storage_for(file.user_id).await?;
+        let data = match storage.retrieve_range(&file.stored_name, offset, len).await {
+            Err(StorageError::FileNotFound(missing)) => match legacy_stored_name(&storage, &file).await? {
+                Some(stored_name) => storage.retrieve_range(&stored_name, offset, len).await?,
+                None => return Err(StorageError::FileNotFound(missing)),
+            },
+            result => result?,
+        };
+
+        if offset == 0 {
+            if is_owner {
+                self.record_access(file_id, user_id).await;
+            }
+            self.count_download(file_id).await;
+            let via = if is_owner { "owner" } else { "recipient" };
+            self.notify(FileEvent::Downloaded, &file, serde_json::json!({ "via": via })).await;
+        }
+        self.record_transfer(file.user_id, TransferDirection::Download, data.len() as u64).await;
+
+        Ok(data)
+    }
+
+    /// Record that a user accessed a file
+    async fn record_access(&self, file_id: &str, user_id: i64) {
+        let backend = self.db.backend();
+        let sql = format!(
+            "INSERT INTO {} (file_id, user_id, accessed_at) VALUES (?1, ?2, ?3)",
+            ACCESS_LOG_TABLE
+        );
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(user_id),
+            QueryValue::String(Utc::now().to_rfc3339()),
+        ];
+        let _ = backend.execute(&sql, &params).await;
+    }
+
+    /// Bump a file's download count and last access time (best effort)
+    async fn count_download(&self, file_id: &str) {
+        let backend = self.db.backend();
+        let sql = format!(
+            "UPDATE {} SET download_count = COALESCE(download_count, 0) + 1, last_accessed_at = ?2 WHERE id = ?1",
+            File::table_name()
+        );
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::String(Utc::now().to_rfc3339()),
+        ];
+        let _ = backend.execute(&sql, &params).await;
+    }
+
+    /// Queue webhooks for a file event and start delivering them in the background
+    /// Best effort: a failure to queue is logged and never fails the operation
+    async fn notify(&self, event: FileEvent, file: &File, details: serde_json::Value) {
+        let Some(webhooks) = &self.webhooks else {
+            return;
+        };
+        match webhooks.emit(event, file, details).await {
+            Ok(0) => {}
+            Ok(_) => {
+                let webhooks = webhooks.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = webhooks.deliver_due().await {
+                        eprintln!("✗ Webhook delivery failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("✗ Failed to queue {} webhooks: {}", event.as_str(), e),
+        }
+    }
+
+    /// Broadcast an event to in-process subscribers
+    /// Having no subscribers is not an error
+    fn publish(&self, event: StorageEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Record bytes moved in or out of storage for usage reports
+    async fn record_transfer(&self, user_id: i64, direction: TransferDirection, bytes: i64) {
+        let backend = self.db.backend();
+        let sql = format!(
+            "INSERT INTO {} (user_id, direction, bytes, occurred_at) VALUES (?1, ?2, ?3, ?4)",
+            TRANSFER_LOG_TABLE
+        );
+        let params = [
+            QueryValue::I64(user_id),
+            QueryValue::String(direction.as_str().to_string()),
+            QueryValue::I64(bytes),
+            QueryValue::String(Utc::now().to_rfc3339()),
+        ];
+        let _ = backend.execute(&sql, &params).await;
+    }
+
+    /// Aggregate every user's transfers and current storage footprint into the report
+    /// for the period containing `at`, replacing any earlier aggregation of that period
+    /// 
+    /// # Returns
+    /// The number of per-user reports written
+    pub async fn aggregate_usage(&self, period: ReportPeriod, at: DateTime<Utc>) -> Result<u64> {
+        let period_start = period.start_of(at);
+        let period_end = period.next(period_start);
+        let backend = self.db.backend();
+
+        let transfers_sql = format!(
+            "SELECT user_id, \
+                COALESCE(SUM(CASE WHEN direction = 'upload' THEN bytes ELSE 0 END), 0) as upload_bytes, \
+                COALESCE(SUM(CASE WHEN direction = 'download' THEN bytes ELSE 0 END), 0) as download_bytes \
+             FROM {} WHERE occurred_at >= ?1 AND occurred_at < ?2 GROUP BY user_id",
+            TRANSFER_LOG_TABLE
+        );
+        let range = [
+            QueryValue::String(period_start.to_rfc3339()),
+            QueryValue::String(period_end.to_rfc3339()),
+        ];
+        let transfer_rows = backend.fetch_all_params(&transfers_sql, &range).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        let footprint_sql = format!(
+            "SELECT user_id, COUNT(*) as file_count, COALESCE(SUM(size), 0) as stored_bytes FROM {} GROUP BY user_id",
+            File::table_name()
+        );
+        let footprint_rows = backend.fetch_all_params(&footprint_sql, &[]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        let mut reports: std::collections::BTreeMap<i64, UsageReport> = std::collections::BTreeMap::new();
+        let empty_report = |user_id| UsageReport {
+            user_id,
+            period,
+            period_start,
+            upload_bytes: 0,
+            download_bytes: 0,
+            stored_bytes: 0,
+            file_count: 0,
+        };
+        for row in &transfer_rows {
+            let user_id = json_i64(row, "user_id");
+            let report = reports.entry(user_id).or_insert_with(|| empty_report(user_id));
+            report.upload_bytes = json_i64(row, "upload_bytes");
+            report.download_bytes = json_i64(row, "download_bytes");
+        }
+        for row in &footprint_rows {
+            let user_id = json_i64(row, "user_id");
+            let report = reports.entry(user_id).or_insert_with(|| empty_report(user_id));
+            report.stored_bytes = json_i64(row, "stored_bytes");
+            report.file_count = json_i64(row, "file_count");
+        }
+
+        let delete_sql = format!(
+            "DELETE FROM {} WHERE period = ?1 AND period_start = ?2",
+            USAGE_REPORTS_TABLE
+        );
+        backend.execute(&delete_sql, &[
+            QueryValue::String(period.as_str().to_string()),
+            QueryValue::String(period_start.to_rfc3339()),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+
+        let insert_sql = format!(
+            "INSERT INTO {} (user_id, period, period_start, upload_bytes, download_bytes, stored_bytes, file_count) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            USAGE_REPORTS_TABLE
+        );
+        for report in reports.values() {
+            backend.execute(&insert_sql, &[
+                QueryValue::I64(report.user_id),
+                QueryValue::String(period.as_str().to_string()),
+                QueryValue::String(period_start.to_rfc3339()),
+                QueryValue::I64(report.upload_bytes),
+                QueryValue::I64(report.download_bytes),
+                QueryValue::I64(report.stored_bytes),
+                QueryValue::I64(report.file_count),
+            ]).await
+                .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+        }
+
+        Ok(reports.len() as u64)
+    }
+
+    /// Re-aggregate the current and previous day and month
+    /// Including the previous periods closes them out with any transfers logged just before they ended
+    pub async fn refresh_usage_reports(&self) -> Result<u64> {
+        let now = Utc::now();
+        let mut written = 0;
+        for period in [ReportPeriod::Daily, ReportPeriod::Monthly] {
+            let current = period.start_of(now);
+            written += self.aggregate_usage(period, current - Duration::seconds(1)).await?;
+            written += self.aggregate_usage(period, current).await?;
+        }
+        Ok(written)
+    }
+
+    /// Aggregated usage reports for periods starting in `[from, to)`, oldest first
+    /// Reports for all users are returned unless `user_id` is given
+    pub async fn list_usage_reports(
+        &self,
+        period: ReportPeriod,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        user_id: Option<i64>,
+    ) -> Result<Vec<UsageReport>> {
+        let backend = self.db.backend();
+        let mut sql = format!(
+            "SELECT * FROM {} WHERE period = ?1 AND period_start >= ?2 AND period_start < ?3",
+            USAGE_REPORTS_TABLE
+        );
+        let mut params = vec![
+            QueryValue::String(period.as_str().to_string()),
+            QueryValue::String(from.to_rfc3339()),
+            QueryValue::String(to.to_rfc3339()),
+        ];
+        if let Some(user_id) = user_id {
+            sql.push_str(" AND user_id = ?4");
+            params.push(QueryValue::I64(user_id));
+        }
+        sql.push_str(" ORDER BY period_start ASC, user_id ASC");
+
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        json_rows.iter()
+            .map(|row| {
+                let period_start = row.get("period_start")
+                    .and_then(|v| v.as_str())
+                    .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .ok_or_else(|| StorageError::StorageError("Deserialization error: invalid period_start".to_string()))?;
+
+                Ok(UsageReport {
+                    user_id: json_i64(row, "user_id"),
+                    period,
+                    period_start,
+                    upload_bytes: json_i64(row, "upload_bytes"),
+                    download_bytes: json_i64(row, "download_bytes"),
+                    stored_bytes: json_i64(row, "stored_bytes"),
+                    file_count: json_i64(row, "file_count"),
+                })
+            })
+            .collect()
+    }
+
+    /// Mark a file as a favorite of the user (idempotent)
+    pub async fn favorite_file(&self, file_id: &str, user_id: i64) -> Result<()> {
+        let file = self.get_file_by_id(file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        if file.user_id != user_id {
+            return Err(StorageError::PermissionDenied("file belongs to another user".to_string()));
+        }
+
+        let backend = self.db.backend();
+        let check_sql = format!(
+            "SELECT COUNT(*) as count FROM {} WHERE file_id = ?1 AND user_id = ?2",
+            FAVORITES_TABLE
+        );
+        let key_params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(user_id),
+        ];
+
+        let count = backend.fetch_one_params(&check_sql, &key_params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| json.get("count").and_then(|v| v.as_i64()))
+            .unwrap_or(0);
+
+        if count > 0 {
+            return Ok(());
+        }
+
+        let sql = format!(
+            "INSERT INTO {} (file_id, user_id, created_at) VALUES (?1, ?2, ?3)",
+            FAVORITES_TABLE
+        );
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(user_id),
+            QueryValue::String(Utc::now().to_rfc3339()),
+        ];
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Remove a file from the user's favorites (idempotent)
+    pub async fn unfavorite_file(&self, file_id: &str, user_id: i64) -> Result<()> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "DELETE FROM {} WHERE file_id = ?1 AND user_id = ?2",
+            FAVORITES_TABLE
+        );
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(user_id),
+        ];
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// List the user's favorite files, most recently favorited first
+    pub async fn list_favorite_files(&self, user_id: i64) -> Result<Vec<File>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT f.* FROM {files} f \
+             JOIN {favorites} fav ON fav.file_id = f.id \
+             WHERE fav.user_id = ?1 AND f.user_id = ?1 AND f.deleted_at IS NULL \
+             ORDER BY fav.created_at DESC",
+            files = File::table_name(),
+            favorites = FAVORITES_TABLE
+        );
+
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        files_from_rows(&json_rows)
+    }
+
+    /// List a page of the user's favorite files, most recently favorited first
+    pub async fn list_favorite_files_page(&self, user_id: i64, limit: i64, offset: i64) -> Result<FileSearchResults> {
+        let sql = format!(
+            "SELECT f.* FROM {files} f \
+             JOIN {favorites} fav ON fav.file_id = f.id \
+             WHERE fav.user_id = ?1 AND f.user_id = ?1 AND f.deleted_at IS NULL \
+             ORDER BY fav.created_at DESC, f.id DESC",
+            files = File::table_name(),
+            favorites = FAVORITES_TABLE
+        );
+
+        let (json_rows, total) = self.fetch_page(&sql, vec![QueryValue::I64(user_id)], limit, offset).await?;
+        Ok(FileSearchResults {
+            files: files_from_rows(&json_rows)?,
+            total,
+        })
+    }
+
+    /// List the files the user accessed most recently, newest first
+    pub async fn list_recent_files(&self, user_id: i64, limit: i64) -> Result<Vec<File>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT f.* FROM {files} f \
+             JOIN (SELECT file_id, MAX(accessed_at) as last_access FROM {log} \
+                   WHERE user_id = ?1 GROUP BY file_id) recent ON recent.file_id = f.id \
+             WHERE f.user_id = ?1 AND f.deleted_at IS NULL \
+             ORDER BY recent.last_access DESC \
+             LIMIT ?2",
+            files = File::table_name(),
+            log = ACCESS_LOG_TABLE
+        );
+        let params = [QueryValue::I64(user_id), QueryValue::I64(limit)];
+
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        files_from_rows(&json_rows)
+    }
+
+    /// Create a public share link for a file
+    /// 
+    /// # Arguments
+    /// * `file_id` - File to share
+    /// * `user_id` - Owner creating the link
+    /// * `password_hash` - Hash of the password required to open the link, if any
+    /// * `expires_at` - When the link stops working, if ever
+    pub async fn create_share(
+        &self,
+        file_id: &str,
+        user_id: i64,
+        password_hash: Option<String>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<FileShare> {
+        let file = self.get_file_by_id(file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        if file.user_id != user_id {
+            return Err(StorageError::PermissionDenied("file belongs to another user".to_string()));
+        }
+        if expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err(StorageError::InvalidInput("expires_at must be in the future".to_string()));
+        }
+
+        let share = FileShare::new(file_id.to_string(), user_id, password_hash, expires_at);
+
+        let backend = self.db.backend();
+        let sql = format!(
+            "INSERT INTO {} (token, file_id, user_id, password_hash, expires_at, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            SHARES_TABLE
+        );
+        let params = [
+            QueryValue::String(share.token.clone()),
+            QueryValue::String(share.file_id.clone()),
+            QueryValue::I64(user_id),
+            share.password_hash.clone().map(QueryValue::String).unwrap_or(QueryValue::Null),
+            share.expires_at.map(|at| QueryValue::String(at.to_rfc3339())).unwrap_or(QueryValue::Null),
+            QueryValue::String(share.created_at.to_rfc3339()),
+        ];
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+
+        Ok(share)
+    }
+
+    /// List the share links of one of the user's files, newest first
+    pub async fn list_shares(&self, file_id: &str, user_id: i64) -> Result<Vec<FileShare>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE file_id = ?1 AND user_id = ?2 ORDER BY created_at DESC",
+            SHARES_TABLE
+        );
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(user_id),
+        ];
+
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok(json_rows.iter().filter_map(FileShare::from_json).collect())
+    }
+
+    /// List a page of the share links of one of the user's files, newest first, along with
+    /// how many there are
+    pub async fn list_shares_page(&self, file_id: &str, user_id: i64, limit: i64, offset: i64) -> Result<(Vec<FileShare>, i64)> {
+        let sql = format!(
+            "SELECT * FROM {} WHERE file_id = ?1 AND user_id = ?2 ORDER BY created_at DESC, id DESC",
+            SHARES_TABLE
+        );
+        let params = vec![
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(user_id),
+        ];
+
+        let (json_rows, total) = self.fetch_page(&sql, params, limit, offset).await?;
+        Ok((json_rows.iter().filter_map(FileShare::from_json).collect(), total))
+    }
+
+    /// Revoke one of the user's share links
+    pub async fn revoke_share(&self, file_id: &str, token: &str, user_id: i64) -> Result<()> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "DELETE FROM {} WHERE token = ?1 AND file_id = ?2 AND user_id = ?3",
+            SHARES_TABLE
+        );
+        let params = [
+            QueryValue::String(token.to_string()),
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(user_id),
+        ];
+        let rows_affected = backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+
+        if rows_affected == 0 {
+            return Err(StorageError::FileNotFound(format!("share link {}", token)));
+        }
+        Ok(())
+    }
+
+    /// Look up an active share link and the file it points to
+    /// Expired links and links to trashed files are reported as not found
+    pub async fn resolve_share(&self, token: &str) -> Result<(FileShare, File)> {
+        let not_found = || StorageError::FileNotFound(format!("share link {}", token));
+
+        let backend = self.db.backend();
+        let sql = format!("SELECT * FROM {} WHERE token = ?1", SHARES_TABLE);
+        let share = backend.fetch_one_params(&sql, &[QueryValue::String(token.to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| FileShare::from_json(&json))
+            .filter(|share| !share.is_expired(Utc::now()))
+            .ok_or_else(not_found)?;
+
+        let file = self.get_file_by_id(&share.file_id).await?
+            .filter(|file| file.user_id == share.user_id)
+            .ok_or_else(not_found)?;
+        if !self.is_owner_active(file.user_id).await? {
+            return Err(not_found());
+        }
+
+        Ok((share, file))
+    }
+
+    /// Whether a user's account exists and isn't deleted
+    /// Links a deleted account handed out stop working while it waits to be purged, and work again
+    /// if it is restored
+    async fn is_owner_active(&self, user_id: i64) -> Result<bool> {
+        let sql = "SELECT deleted_at FROM users WHERE id = ?1";
+        let row = self.db.backend().fetch_one_params(sql, &[QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        Ok(row.is_some_and(|row| row.get("deleted_at").is_none_or(|v| v.is_null())))
+    }
+
+    /// A stream of the data of a file opened through a share link, never held in memory
+    /// The caller must have resolved the share and checked its password
+    pub async fn retrieve_shared(&self, share: &FileShare, file: &File) -> Result<BlobReader> {
+        let storage = self.storage_for(share.user_id).await?;
+        let reader = self.open_download(&storage, file).await?;
+
+        self.count_download(&share.file_id).await;
+        // Shared downloads count against the owner's bandwidth
+        self.record_transfer(share.user_id, TransferDirection::Download, file.size).await;
+        self.notify(FileEvent::Downloaded, file, serde_json::json!({ "via": "share" })).await;
+
+        Ok(reader)
+    }
+
+    /// Share one of the user's files with someone by email
+    ///
+    /// # Arguments
+    /// * `file_id` - File to share
+    /// * `owner_id` - Owner of the file
+    /// * `email` - Address of the recipient
+    /// * `recipient_id` - Account registered under `email`, if any; the invitation is accepted
+    ///   at once, otherwise it stays pending until the address signs up
+    ///
+    /// # Returns
+    /// The invitation; inviting the same address to the same file again returns the existing one
+    pub async fn invite_to_file(
+        &self,
+        file_id: &str,
+        owner_id: i64,
+        email: &str,
+        recipient_id: Option<i64>,
+    ) -> Result<ShareInvitation> {
+        let file = self.get_file_by_id(file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        if file.user_id != owner_id {
+            return Err(StorageError::PermissionDenied("file belongs to another user".to_string()));
+        }
+        let email = normalize_email(email);
+        if !is_valid_email(&email) {
+            return Err(StorageError::InvalidInput(format!("'{}' is not a valid email address", email)));
+        }
+        if recipient_id == Some(owner_id) {
+            return Err(StorageError::InvalidInput("a file can't be shared with its owner".to_string()));
+        }
+        if let Some(existing) = self.find_invitation(file_id, &email).await? {
+            return Ok(existing);
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let backend = self.db.backend();
+        let sql = format!(
+            "INSERT INTO {} (file_id, owner_id, email, recipient_id, created_at, accepted_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            SHARE_INVITATIONS_TABLE
+        );
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(owner_id),
+            QueryValue::String(email.clone()),
+            recipient_id.map(QueryValue::I64).unwrap_or(QueryValue::Null),
+            QueryValue::String(now.clone()),
+            recipient_id.map(|_| QueryValue::String(now)).unwrap_or(QueryValue::Null),
+        ];
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+
+        self.find_invitation(file_id, &email).await?
+            .ok_or_else(|| StorageError::StorageError("Invitation was not recorded".to_string()))
+    }
+
+    /// The invitation of one address to one file
+    async fn find_invitation(&self, file_id: &str, email: &str) -> Result<Option<ShareInvitation>> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT * FROM {} WHERE file_id = ?1 AND email = ?2", SHARE_INVITATIONS_TABLE);
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::String(email.to_string()),
+        ];
+
+        Ok(backend.fetch_one_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| ShareInvitation::from_json(&json)))
+    }
+
+    /// List the invitations to one of the user's files, newest first
+    pub async fn list_invitations(&self, file_id: &str, owner_id: i64) -> Result<Vec<ShareInvitation>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE file_id = ?1 AND owner_id = ?2 ORDER BY created_at DESC",
+            SHARE_INVITATIONS_TABLE
+        );
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(owner_id),
+        ];
+
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok(json_rows.iter().filter_map(ShareInvitation::from_json).collect())
+    }
+
+    /// List a page of the invitations to one of the user's files, newest first, along with
+    /// how many there are
+    pub async fn list_invitations_page(&self, file_id: &str, owner_id: i64, limit: i64, offset: i64) -> Result<(Vec<ShareInvitation>, i64)> {
+        let sql = format!(
+            "SELECT * FROM {} WHERE file_id = ?1 AND owner_id = ?2 ORDER BY created_at DESC, id DESC",
+            SHARE_INVITATIONS_TABLE
+        );
+        let params = vec![
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(owner_id),
+        ];
+
+        let (json_rows, total) = self.fetch_page(&sql, params, limit, offset).await?;
+        Ok((json_rows.iter().filter_map(ShareInvitation::from_json).collect(), total))
+    }
+
+    /// Revoke an invitation to one of the user's files, pending or accepted
+    pub async fn revoke_invitation(&self, file_id: &str, invitation_id: i64, owner_id: i64) -> Result<()> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "DELETE FROM {} WHERE id = ?1 AND file_id = ?2 AND owner_id = ?3",
+            SHARE_INVITATIONS_TABLE
+        );
+        let params = [
+            QueryValue::I64(invitation_id),
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(owner_id),
+        ];
+        let rows_affected = backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+
+        if rows_affected == 0 {
+            return Err(StorageError::FileNotFound(format!("invitation {}", invitation_id)));
+        }
+        Ok(())
+    }
+
+    /// Whether an address has an invitation waiting for it to sign up
+    pub async fn has_pending_invitation(&self, email: &str) -> Result<bool> {
+        let sql = format!(
+            "SELECT id FROM {} WHERE email = ?1 AND recipient_id IS NULL LIMIT 1",
+            SHARE_INVITATIONS_TABLE
+        );
+        let rows = self.db.backend().fetch_all_params(&sql, &[QueryValue::String(normalize_email(email))]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        Ok(!rows.is_empty())
+    }
+
+    /// Accept the pending invitations sent to an address, now that `user_id` is registered under it
+    /// Called when an account is created, so files shared before the recipient signed up show up
+    ///
+    /// # Returns
+    /// The invitations that were accepted
+    pub async fn accept_invitations(&self, email: &str, user_id: i64) -> Result<Vec<ShareInvitation>> {
+        let email = normalize_email(email);
+        let backend = self.db.backend();
+
+        let select_sql = format!(
+            "SELECT * FROM {} WHERE email = ?1 AND recipient_id IS NULL",
+            SHARE_INVITATIONS_TABLE
+        );
+        let json_rows = backend.fetch_all_params(&select_sql, &[QueryValue::String(email.clone())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        let pending: Vec<ShareInvitation> = json_rows.iter().filter_map(ShareInvitation::from_json).collect();
+        if pending.is_empty() {
+            return Ok(pending);
+        }
+
+        let accepted_at = Utc::now();
+        let update_sql = format!(
+            "UPDATE {} SET recipient_id = ?1, accepted_at = ?2 WHERE email = ?3 AND recipient_id IS NULL",
+            SHARE_INVITATIONS_TABLE
+        );
+        backend.execute(&update_sql, &[
+            QueryValue::I64(user_id),
+            QueryValue::String(accepted_at.to_rfc3339()),
+            QueryValue::String(email),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+
+        Ok(pending.into_iter()
+            .map(|invitation| ShareInvitation {
+                recipient_id: Some(user_id),
+                accepted_at: Some(accepted_at),
+                ..invitation
+            })
+            .collect())
+    }
+
+    /// List the files others shared with the user, by invitation or permission, most recently
+    /// shared first
+    pub async fn files_shared_with(&self, user_id: i64) -> Result<Vec<File>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT f.* FROM {files} f \
+             JOIN (SELECT file_id, owner_id, MAX(shared_at) AS shared_at FROM ( \
+                 SELECT file_id, owner_id, created_at AS shared_at FROM {invitations} WHERE recipient_id = ?1 \
+                 UNION ALL \
+                 SELECT file_id, owner_id, created_at AS shared_at FROM {permissions} WHERE user_id = ?1 \
+             ) grants GROUP BY file_id, owner_id) shared ON shared.file_id = f.id \
+             WHERE f.user_id = shared.owner_id AND f.deleted_at IS NULL \
+             ORDER BY shared.shared_at DESC",
+            files = File::table_name(),
+            invitations = SHARE_INVITATIONS_TABLE,
+            permissions = FILE_PERMISSIONS_TABLE
+        );
+
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        files_from_rows(&json_rows)
+    }
+
+    /// List a page of the files shared with a user, filtered and sorted by `options`
+    /// Most recently shared first unless a sort is given
+    pub async fn files_shared_with_page(&self, user_id: i64, options: &FileListOptions) -> Result<FileSearchResults> {
+        let mut params = vec![QueryValue::I64(user_id)];
+        let mut conditions = vec!["f.user_id = shared.owner_id".to_string(), "f.deleted_at IS NULL".to_string()];
+        conditions.extend(options.filters(&mut params)?);
+
+        let order = match options.sort {
+            Some(sort) => {
+                let direction = if options.is_descending(sort) { "DESC" } else { "ASC" };
+                format!("{} {}, f.id {}", sort.column(), direction, direction)
+            }
+            None => "shared.shared_at DESC, f.id DESC".to_string(),
+        };
+        let sql = format!(
+            "SELECT f.* FROM {files} f \
+             JOIN (SELECT file_id, owner_id, MAX(shared_at) AS shared_at FROM ( \
+                 SELECT file_id, owner_id, created_at AS shared_at FROM {invitations} WHERE recipient_id = ?1 \
+                 UNION ALL \
+                 SELECT file_id, owner_id, created_at AS shared_at FROM {permissions} WHERE user_id = ?1 \
+             ) grants GROUP BY file_id, owner_id) shared ON shared.file_id = f.id \
+             WHERE {conditions} \
+             ORDER BY {order}",
+            files = File::table_name(),
+            invitations = SHARE_INVITATIONS_TABLE,
+            permissions = FILE_PERMISSIONS_TABLE,
+            conditions = conditions.join(" AND "),
+            order = order
+        );
+
+        let (json_rows, total) = self.fetch_page(&sql, params, options.limit, options.offset).await?;
+        Ok(FileSearchResults {
+            files: files_from_rows(&json_rows)?,
+            total,
+        })
+    }
+
+    /// What the user may do with a file: everything as its owner, otherwise the most of what
+    /// their role allows in the organization the file belongs to and what a permission from the
+    /// owner allows, or read it through an accepted invitation
+    async fn access_for(&self, file: &File, user_id: i64) -> Result<Option<FileAccess>> {
+        if file.user_id == user_id {
+            return Ok(Some(FileAccess::Owner));
+        }
+        let organization_access = match file.organization_id {
+            Some(organization_id) => self.find_membership(organization_id, user_id).await?
+                .map(|membership| membership.role.file_access()),
+            None => None,
+        };
+        if organization_access == Some(FileAccess::Owner) {
+            return Ok(organization_access);
+        }
+        let file_id = file.id.clone().unwrap_or_default();
+        if let Some(permission) = self.find_permission(&file_id, file.user_id, user_id).await? {
+            return Ok(organization_access.max(Some(permission.access)));
+        }
+        if organization_access.is_some() {
+            return Ok(organization_access);
+        }
+
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT id FROM {} WHERE file_id = ?1 AND owner_id = ?2 AND recipient_id = ?3",
+            SHARE_INVITATIONS_TABLE
+        );
+        let params = [
+            QueryValue::String(file_id),
+            QueryValue::I64(file.user_id),
+            QueryValue::I64(user_id),
+        ];
+
+        let invited = backend.fetch_one_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .is_some();
+        Ok(invited.then_some(FileAccess::Read))
+    }
+
+    /// Let another user read, write, or co-own a file
+    /// Owners and co-owners grant access; only the owner makes or unmakes co-owners. Granting
+    /// access to a user who already has some replaces it.
+    pub async fn grant_permission(
+        &self,
+        file_id: &str,
+        granted_by: i64,
+        user_id: i64,
+        access: FileAccess,
+    ) -> Result<FilePermission> {
+        let file = self.owned_file(file_id, granted_by).await?;
+        let owner_id = file.user_id;
+        if user_id == owner_id {
+            return Err(StorageError::InvalidInput("a file can't be shared with its owner".to_string()));
+        }
+
+        let existing = self.find_permission(file_id, owner_id, user_id).await?;
+        let changes_ownership = access.is_owner() || existing.as_ref().is_some_and(|existing| existing.access.is_owner());
+        if changes_ownership && granted_by != owner_id {
+            return Err(StorageError::PermissionDenied("only the owner can change who co-owns a file".to_string()));
+        }
+
+        let backend = self.db.backend();
+        let (sql, params) = match existing {
+            Some(existing) => (
+                format!("UPDATE {} SET access = ?1 WHERE id = ?2", FILE_PERMISSIONS_TABLE),
+                vec![QueryValue::String(access.as_str().to_string()), QueryValue::I64(existing.id)],
+            ),
+            None => (
+                format!(
+                    "INSERT INTO {} (file_id, owner_id, user_id, access, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    FILE_PERMISSIONS_TABLE
+                ),
+                vec![
+                    QueryValue::String(file_id.to_string()),
+                    QueryValue::I64(owner_id),
+                    QueryValue::I64(user_id),
+                    QueryValue::String(access.as_str().to_string()),
+                    QueryValue::String(Utc::now().to_rfc3339()),
+                ],
+            ),
+        };
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database write failed: {}", e)))?;
+
+        self.find_permission(file_id, owner_id, user_id).await?
+            .ok_or_else(|| StorageError::StorageError("Permission was not recorded".to_string()))
+    }
+
+    /// The permission the owner of a file granted one user
+    async fn find_permission(&self, file_id: &str, owner_id: i64, user_id: i64) -> Result<Option<FilePermission>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE file_id = ?1 AND owner_id = ?2 AND user_id = ?3",
+            FILE_PERMISSIONS_TABLE
+        );
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(owner_id),
+            QueryValue::I64(user_id),
+        ];
+
+        Ok(backend.fetch_one_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| FilePermission::from_json(&json)))
+    }
+
+    /// Fetch a file the user owns or co-owns
+    async fn owned_file(&self, file_id: &str, user_id: i64) -> Result<File> {
+        let file = self.get_file_by_id(file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        if !self.access_for(&file, user_id).await?.is_some_and(|access| access.is_owner()) {
+            return Err(StorageError::PermissionDenied("file belongs to another user".to_string()));
+        }
+        Ok(file)
+    }
+
+    /// List the users a file is shared with, oldest grant first
+    /// Owners and co-owners may list them
+    pub async fn list_permissions(&self, file_id: &str, user_id: i64) -> Result<Vec<FilePermission>> {
+        let file = self.owned_file(file_id, user_id).await?;
+        let owner_id = file.user_id;
+
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE file_id = ?1 AND owner_id = ?2 ORDER BY created_at ASC, id ASC",
+            FILE_PERMISSIONS_TABLE
+        );
+        let params = [QueryValue::String(file_id.to_string()), QueryValue::I64(owner_id)];
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok(json_rows.iter().filter_map(FilePermission::from_json).collect())
+    }
+
+    /// List a page of the users a file is shared with, oldest grant first, along with how many
+    /// there are
+    /// Owners and co-owners may list them
+    pub async fn list_permissions_page(&self, file_id: &str, user_id: i64, limit: i64, offset: i64) -> Result<(Vec<FilePermission>, i64)> {
+        let file = self.owned_file(file_id, user_id).await?;
+
+        let sql = format!(
+            "SELECT * FROM {} WHERE file_id = ?1 AND owner_id = ?2 ORDER BY created_at ASC, id ASC",
+            FILE_PERMISSIONS_TABLE
+        );
+        let params = vec![QueryValue::String(file_id.to_string()), QueryValue::I64(file.user_id)];
+
+        let (json_rows, total) = self.fetch_page(&sql, params, limit, offset).await?;
+        Ok((json_rows.iter().filter_map(FilePermission::from_json).collect(), total))
+    }
+
+    /// Stop sharing a file with a user
+    /// Owners and co-owners revoke access; only the owner revokes co-ownership
+    pub async fn revoke_permission(&self, file_id: &str, revoked_by: i64, user_id: i64) -> Result<()> {
+        let file = self.owned_file(file_id, revoked_by).await?;
+        let owner_id = file.user_id;
+        let existing = self.find_permission(file_id, owner_id, user_id).await?;
+        if existing.is_some_and(|existing| existing.access.is_owner()) && revoked_by != owner_id {
+            return Err(StorageError::PermissionDenied("only the owner can change who co-owns a file".to_string()));
+        }
+
+        let backend = self.db.backend();
+        let sql = format!(
+            "DELETE FROM {} WHERE file_id = ?1 AND owner_id = ?2 AND user_id = ?3",
+            FILE_PERMISSIONS_TABLE
+        );
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(owner_id),
+            QueryValue::I64(user_id),
+        ];
+        let rows_affected = backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+
+        if rows_affected == 0 {
+            return Err(StorageError::FileNotFound(format!("permission of user {} on {}", user_id, file_id)));
+        }
+        Ok(())
+    }
+
+    /// Offer one of the owner's files to another user, who becomes its owner on accepting
+    /// A file has at most one transfer waiting; offering it again replaces the earlier offer
+    pub async fn request_transfer(&self, file_id: &str, owner_id: i64, to_user_id: i64) -> Result<FileTransfer> {
+        let file = self.get_file_by_id(file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        if file.user_id != owner_id {
+            return Err(StorageError::PermissionDenied("only the owner can transfer a file".to_string()));
+        }
+        if to_user_id == owner_id {
+            return Err(StorageError::InvalidInput("the file already belongs to this user".to_string()));
+        }
+        if file.organization_id.is_some() {
+            return Err(StorageError::InvalidInput("files of an organization can't be transferred".to_string()));
+        }
+        self.ensure_not_held(File::table_name(), file_id).await?;
+
+        self.clear_transfers(file_id).await?;
+
+        let backend = self.db.backend();
+        let insert_sql = format!(
+            "INSERT INTO {} (file_id, from_user_id, to_user_id, created_at) VALUES (?1, ?2, ?3, ?4)",
+            FILE_TRANSFERS_TABLE
+        );
+        let params = [
+            QueryValue::String(file_id.to_string()),
+            QueryValue::I64(owner_id),
+            QueryValue::I64(to_user_id),
+            QueryValue::String(Utc::now().to_rfc3339()),
+        ];
+        backend.execute(&insert_sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+
+        let select_sql = format!("SELECT * FROM {} WHERE file_id = ?1", FILE_TRANSFERS_TABLE);
+        backend.fetch_one_params(&select_sql, &[QueryValue::String(file_id.to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| FileTransfer::from_json(&json))
+            .ok_or_else(|| StorageError::StorageError("Transfer was not recorded".to_string()))
+    }
+
+    /// List the transfers the user offered or was offered, newest first
+    pub async fn list_transfers(&self, user_id: i64) -> Result<Vec<FileTransfer>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE from_user_id = ?1 OR to_user_id = ?1 ORDER BY created_at DESC, id DESC",
+            FILE_TRANSFERS_TABLE
+        );
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok(json_rows.iter().filter_map(FileTransfer::from_json).collect())
+    }
+
+    /// List a page of the transfers the user offered or was offered, newest first, along with
+    /// how many there are
+    pub async fn list_transfers_page(&self, user_id: i64, limit: i64, offset: i64) -> Result<(Vec<FileTransfer>, i64)> {
+        let sql = format!(
+            "SELECT * FROM {} WHERE from_user_id = ?1 OR to_user_id = ?1 ORDER BY created_at DESC, id DESC",
+            FILE_TRANSFERS_TABLE
+        );
+
+        let (json_rows, total) = self.fetch_page(&sql, vec![QueryValue::I64(user_id)], limit, offset).await?;
+        Ok((json_rows.iter().filter_map(FileTransfer::from_json).collect(), total))
+    }
+
+    /// Accept a transfer offered to the user, making them the owner of the file
+    ///
+    /// The file counts toward the new owner's storage limits, so the transfer fails with
+    /// `QuotaExceeded` if it doesn't fit. Its blob moves to the new owner's storage when that
+    /// differs. Share links, invitations and permissions granted by the previous owner are
+    /// removed, along with the previous owner's favorite.
+    pub async fn accept_transfer(&self, transfer_id: i64, user_id: i64) -> Result<File> {
+        let backend = self.db.backend();
+        let select_sql = format!("SELECT * FROM {} WHERE id = ?1 AND to_user_id = ?2", FILE_TRANSFERS_TABLE);
+        let transfer = backend.fetch_one_params(&select_sql, &[QueryValue::I64(transfer_id), QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| FileTransfer::from_json(&json))
+            .ok_or_else(|| StorageError::FileNotFound(format!("transfer {}", transfer_id)))?;
+
+        // The offer lapses if the file was deleted or changed hands since it was made
+        let file = self.get_file_by_id(&transfer.file_id).await?
+            .filter(|file| file.user_id == transfer.from_user_id);
+        let Some(mut file) = file else {
+            self.clear_transfers(&transfer.file_id).await?;
+            return Err(StorageError::FileNotFound(transfer.file_id));
+        };
+        self.ensure_not_held(File::table_name(), &transfer.file_id).await?;
+        self.check_limits(user_id, 1, file.size as u64, file.size as u64).await?;
+
+        let from_storage = self.storage_for(file.user_id).await?;
+        let to_storage = self.storage_for(user_id).await?;
+        let moves_blob = from_storage.location() != to_storage.location();
+        if moves_blob {
+            let reserved = ReservedName {
+                id: file.stored_name.clone(),
+                stored_name: file.stored_name.clone(),
+                created_at: Utc::now(),
+            };
+            let reader = self.open_blob(&from_storage, &file).await?;
+            to_storage.store_stream_reserved(&reserved, reader, &file.original_name, file.mime_type.clone()).await?;
+        }
+
+        // Only switch owners if nobody else did in the meantime
+        let update_sql = format!(
+            "UPDATE {} SET user_id = ?1, storage_path = ?2 WHERE id = ?3 AND user_id = ?4",
+            File::table_name()
+        );
+        let params = [
+            QueryValue::I64(user_id),
+            QueryValue::String(to_storage.location()),
+            QueryValue::String(transfer.file_id.clone()),
+            QueryValue::I64(transfer.from_user_id),
+        ];
+        let rows_affected = backend.execute(&update_sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+        if rows_affected == 0 {
+            if moves_blob {
+                let _ = to_storage.delete(&file.stored_name).await;
+            }
+            self.clear_transfers(&transfer.file_id).await?;
+            return Err(StorageError::FileNotFound(transfer.file_id));
+        }
+
+        for (table, owner_column) in [
+            (SHARES_TABLE, "user_id"),
+            (SHARE_INVITATIONS_TABLE, "owner_id"),
+            (FILE_PERMISSIONS_TABLE, "owner_id"),
+            (FAVORITES_TABLE, "user_id"),
+        ] {
+            let sql = format!("DELETE FROM {} WHERE file_id = ?1 AND {} = ?2", table, owner_column);
+            let params = [QueryValue::String(transfer.file_id.clone()), QueryValue::I64(transfer.from_user_id)];
+            if let Err(e) = backend.execute(&sql, &params).await {
+                eprintln!("⚠️  Failed to clear {} of transferred file {}: {}", table, transfer.file_id, e);
+            }
+        }
+        self.clear_transfers(&transfer.file_id).await?;
+
+        // The copy is in place and recorded, so the old blob is only garbage now
+        if moves_blob {
+            if let Err(e) = from_storage.delete(&file.stored_name).await {
+                eprintln!("⚠️  Failed to delete the previous blob of transferred file {}: {}", transfer.file_id, e);
+            }
+        }
+
+        file.user_id = user_id;
+        file.storage_path = to_storage.location();
+        Ok(file)
+    }
+
+    /// Withdraw a transfer the user offered, or decline one offered to them
+    pub async fn cancel_transfer(&self, transfer_id: i64, user_id: i64) -> Result<()> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "DELETE FROM {} WHERE id = ?1 AND (from_user_id = ?2 OR to_user_id = ?2)",
+            FILE_TRANSFERS_TABLE
+        );
+        let rows_affected = backend.execute(&sql, &[QueryValue::I64(transfer_id), QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+
+        if rows_affected == 0 {
+            return Err(StorageError::FileNotFound(format!("transfer {}", transfer_id)));
+        }
+        Ok(())
+    }
+
+    /// Remove every transfer of a file
+    async fn clear_transfers(&self, file_id: &str) -> Result<()> {
+        let sql = format!("DELETE FROM {} WHERE file_id = ?1", FILE_TRANSFERS_TABLE);
+        self.db.backend().execute(&sql, &[QueryValue::String(file_id.to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+        Ok(())
+    }
+
+    /// Create an organization, with the user as its owner and first admin
+    pub async fn create_organization(&self, name: &str, user_id: i64) -> Result<Organization> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(StorageError::InvalidInput("organization name must not be empty".to_string()));
+        }
+        if name.len() > MAX_ORGANIZATION_NAME_LENGTH {
+            return Err(StorageError::InvalidInput(format!(
+                "organization name is longer than {} bytes",
+                MAX_ORGANIZATION_NAME_LENGTH
+            )));
+        }
+
+        let backend = self.db.backend();
+        let created_at = Utc::now().to_rfc3339();
+        let insert_sql = format!(
+            "INSERT INTO {} (name, owner_id, created_at) VALUES (?1, ?2, ?3)",
+            ORGANIZATIONS_TABLE
+        );
+        let params = [
+            QueryValue::String(name.to_string()),
+            QueryValue::I64(user_id),
+            QueryValue::String(created_at.clone()),
+        ];
+        backend.execute(&insert_sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+
+        let select_sql = format!(
+            "SELECT * FROM {} WHERE owner_id = ?1 ORDER BY id DESC LIMIT 1",
+            ORGANIZATIONS_TABLE
+        );
+        let organization = backend.fetch_one_params(&select_sql, &[QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| Organization::from_json(&json))
+            .ok_or_else(|| StorageError::StorageError("Organization was not recorded".to_string()))?;
+
+        let membership_sql = format!(
+            "INSERT INTO {} (organization_id, user_id, role, created_at) VALUES (?1, ?2, ?3, ?4)",
+            MEMBERSHIPS_TABLE
+        );
+        let params = [
+            QueryValue::I64(organization.id),
+            QueryValue::I64(user_id),
+            QueryValue::String(OrganizationRole::Admin.as_str().to_string()),
+            QueryValue::String(created_at),
+        ];
+        backend.execute(&membership_sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+
+        Ok(organization)
+    }
+
+    /// Assign a billing plan to an organization, or clear it so its owner's plan applies
+    /// The plan name isn't checked here; callers validate it against the configured plans.
+    pub async fn set_organization_plan(&self, organization_id: i64, plan: Option<&str>) -> Result<Organization> {
+        let sql = format!("UPDATE {} SET plan = ?1 WHERE id = ?2", ORGANIZATIONS_TABLE);
+        let plan_value = plan.map(|plan| QueryValue::String(plan.to_string())).unwrap_or(QueryValue::Null);
+        let rows_affected = self.db.backend().execute(&sql, &[plan_value, QueryValue::I64(organization_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+        if rows_affected == 0 {
+            return Err(StorageError::FileNotFound(format!("organization {}", organization_id)));
+        }
+
+        self.find_organization(organization_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(format!("organization {}", organization_id)))
+    }
+
+    /// Fetch an organization the user is a member of
+    pub async fn get_organization(&self, organization_id: i64, user_id: i64) -> Result<Organization> {
+        self.member_of(organization_id, user_id).await?;
+        self.find_organization(organization_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(format!("organization {}", organization_id)))
+    }
+
+    /// List the organizations the user is a member of, oldest first
+    pub async fn organizations_for(&self, user_id: i64) -> Result<Vec<Organization>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT o.* FROM {} o JOIN {} m ON m.organization_id = o.id WHERE m.user_id = ?1 ORDER BY o.created_at ASC, o.id ASC",
+            ORGANIZATIONS_TABLE, MEMBERSHIPS_TABLE
+        );
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok(json_rows.iter().filter_map(Organization::from_json).collect())
+    }
+
+    /// List a page of the organizations a user is a member of, oldest first, along with how
+    /// many there are
+    pub async fn organizations_for_page(&self, user_id: i64, limit: i64, offset: i64) -> Result<(Vec<Organization>, i64)> {
+        let sql = format!(
+            "SELECT o.* FROM {} o JOIN {} m ON m.organization_id = o.id WHERE m.user_id = ?1 ORDER BY o.created_at ASC, o.id ASC",
+            ORGANIZATIONS_TABLE, MEMBERSHIPS_TABLE
+        );
+
+        let (json_rows, total) = self.fetch_page(&sql, vec![QueryValue::I64(user_id)], limit, offset).await?;
+        Ok((json_rows.iter().filter_map(Organization::from_json).collect(), total))
+    }
+
+    /// Add a user to an organization, or change their role if they are already in it
+    /// Only admins add members, and the organization's owner stays an admin
+    pub async fn add_member(
+        &self,
+        organization_id: i64,
+        added_by: i64,
+        user_id: i64,
+        role: OrganizationRole,
+    ) -> Result<Membership> {
+        let organization = self.administered_organization(organization_id, added_by).await?;
+        if user_id == organization.owner_id && role != OrganizationRole::Admin {
+            return Err(StorageError::InvalidInput("the owner of an organization must stay an admin".to_string()));
+        }
+
+        let backend = self.db.backend();
+        let (sql, params) = match self.find_membership(organization_id, user_id).await? {
+            Some(_) => (
+                format!("UPDATE {} SET role = ?1 WHERE organization_id = ?2 AND user_id = ?3", MEMBERSHIPS_TABLE),
+                vec![
+                    QueryValue::String(role.as_str().to_string()),
+                    QueryValue::I64(organization_id),
+                    QueryValue::I64(user_id),
+                ],
+            ),
+            None => (
+                format!(
+                    "INSERT INTO {} (organization_id, user_id, role, created_at) VALUES (?1, ?2, ?3, ?4)",
+                    MEMBERSHIPS_TABLE
+                ),
+                vec![
+                    QueryValue::I64(organization_id),
+                    QueryValue::I64(user_id),
+                    QueryValue::String(role.as_str().to_string()),
+                    QueryValue::String(Utc::now().to_rfc3339()),
+                ],
+            ),
+        };
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database write failed: {}", e)))?;
+
+        self.find_membership(organization_id, user_id).await?
+            .ok_or_else(|| StorageError::StorageError("Membership was not recorded".to_string()))
+    }
+
+    /// Remove a user from an organization
+    /// Admins remove anyone but the owner, and members may leave. Files the user moved into the
+    /// organization stay in it.
+    pub async fn remove_member(&self, organization_id: i64, removed_by: i64, user_id: i64) -> Result<()> {
+        let organization = if removed_by == user_id {
+            self.member_of(organization_id, user_id).await?;
+            self.find_organization(organization_id).await?
+                .ok_or_else(|| StorageError::FileNotFound(format!("organization {}", organization_id)))?
+        } else {
+            self.administered_organization(organization_id, removed_by).await?
+        };
+        if user_id == organization.owner_id {
+            return Err(StorageError::InvalidInput("the owner can't leave their organization".to_string()));
+        }
+
+        let backend = self.db.backend();
+        let sql = format!("DELETE FROM {} WHERE organization_id = ?1 AND user_id = ?2", MEMBERSHIPS_TABLE);
+        let rows_affected = backend.execute(&sql, &[QueryValue::I64(organization_id), QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+
+        if rows_affected == 0 {
+            return Err(StorageError::FileNotFound(format!("member {} of organization {}", user_id, organization_id)));
+        }
+        Ok(())
+    }
+
+    /// List the members of an organization the user belongs to, oldest first
+    pub async fn list_members(&self, organization_id: i64, user_id: i64) -> Result<Vec<Membership>> {
+        self.member_of(organization_id, user_id).await?;
+
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE organization_id = ?1 ORDER BY created_at ASC, id ASC",
+            MEMBERSHIPS_TABLE
+        );
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(organization_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok(json_rows.iter().filter_map(Membership::from_json).collect())
+    }
+
+    /// List a page of the members of an organization the user belongs to, oldest first, along
+    /// with how many there are
+    pub async fn list_members_page(&self, organization_id: i64, user_id: i64, limit: i64, offset: i64) -> Result<(Vec<Membership>, i64)> {
+        self.member_of(organization_id, user_id).await?;
+
+        let sql = format!(
+            "SELECT * FROM {} WHERE organization_id = ?1 ORDER BY created_at ASC, id ASC",
+            MEMBERSHIPS_TABLE
+        );
+
+        let (json_rows, total) = self.fetch_page(&sql, vec![QueryValue::I64(organization_id)], limit, offset).await?;
+        Ok((json_rows.iter().filter_map(Membership::from_json).collect(), total))
+    }
+
+    /// List the files of an organization the user belongs to, newest first
+    pub async fn list_organization_files(&self, organization_id: i64, user_id: i64) -> Result<Vec<File>> {
+        self.member_of(organization_id, user_id).await?;
+
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE organization_id = ?1 AND deleted_at IS NULL ORDER BY created_at DESC",
+            File::table_name()
+        );
+        let json_rows = backend.fetch_all_params(&sql, &[QueryValue::I64(organization_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        files_from_rows(&json_rows)
+    }
+
+    /// List a page of the files of an organization the user belongs to, newest first
+    pub async fn list_organization_files_page(&self, organization_id: i64, user_id: i64, limit: i64, offset: i64) -> Result<FileSearchResults> {
+        self.member_of(organization_id, user_id).await?;
+
+        let sql = format!(
+            "SELECT * FROM {} WHERE organization_id = ?1 AND deleted_at IS NULL ORDER BY created_at DESC, id DESC",
+            File::table_name()
+        );
+
+        let (json_rows, total) = self.fetch_page(&sql, vec![QueryValue::I64(organization_id)], limit, offset).await?;
+        Ok(FileSearchResults {
+            files: files_from_rows(&json_rows)?,
+            total,
+        })
+    }
+
+    /// Move a file into an organization, or back out of it with `None`
+    ///
+    /// The user must own or co-own the file and be a member of the organization it moves into.
+    /// Moving a file changes whose storage limits it counts toward, so it fails with
+    /// `QuotaExceeded` if it doesn't fit there. A pending transfer of the file is withdrawn.
+    pub async fn set_file_organization(&self, file_id: &str, user_id: i64, organization_id: Option<i64>) -> Result<File> {
+        let mut file = self.owned_file(file_id, user_id).await?;
+        if file.organization_id == organization_id {
+            return Ok(file);
+        }
+        self.ensure_not_held(File::table_name(), file_id).await?;
+
+        if let Some(organization_id) = organization_id {
+            self.member_of(organization_id, user_id).await?;
+        }
+        // Moving between an owner's files and their plan-less organizations stays in one quota
+        let from = self.quota_owner(file.user_id, file.organization_id).await?;
+        let to = self.quota_owner(file.user_id, organization_id).await?;
+        if from != to {
+            let size = file.size as u64;
+            match organization_id {
+                Some(organization_id) => self.check_organization_limits(organization_id, 1, size, size).await?,
+                None => self.check_limits(file.user_id, 1, size, size).await?,
+            }
+        }
+
+        let backend = self.db.backend();
+        let sql = format!("UPDATE {} SET organization_id = ?1 WHERE id = ?2", File::table_name());
+        let params = [
+            organization_id.map(QueryValue::I64).unwrap_or(QueryValue::Null),
+            QueryValue::String(file_id.to_string()),
+        ];
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+
+        if organization_id.is_some() {
+            self.clear_transfers(file_id).await?;
+        }
+
+        file.organization_id = organization_id;
+        Ok(file)
+    }
+
+    /// Fetch an organization whether or not the caller belongs to it
+    async fn find_organization(&self, organization_id: i64) -> Result<Option<Organization>> {
+        let sql = format!("SELECT * FROM {} WHERE id = ?1", ORGANIZATIONS_TABLE);
+        Ok(self.db.backend().fetch_one_params(&sql, &[QueryValue::I64(organization_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| Organization::from_json(&json)))
+    }
+
+    /// The user's membership of an organization
+    async fn find_membership(&self, organization_id: i64, user_id: i64) -> Result<Option<Membership>> {
+        let sql = format!("SELECT * FROM {} WHERE organization_id = ?1 AND user_id = ?2", MEMBERSHIPS_TABLE);
+        let params = [QueryValue::I64(organization_id), QueryValue::I64(user_id)];
+        Ok(self.db.backend().fetch_one_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| Membership::from_json(&json)))
+    }
+
+    /// The user's membership of an organization, or `FileNotFound` so outsiders can't tell
+    /// which organizations exist
+    async fn member_of(&self, organization_id: i64, user_id: i64) -> Result<Membership> {
+        self.find_membership(organization_id, user_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(format!("organization {}", organization_id)))
+    }
+
+    /// Fetch an organization the user is an admin of
+    async fn administered_organization(&self, organization_id: i64, user_id: i64) -> Result<Organization> {
+        let membership = self.member_of(organization_id, user_id).await?;
+        if membership.role != OrganizationRole::Admin {
+            return Err(StorageError::PermissionDenied("only admins can manage an organization's members".to_string()));
+        }
+        self.find_organization(organization_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(format!("organization {}", organization_id)))
+    }
+
+    /// Issue a single-use download token for a file
+    /// Tokens let third-party processors fetch one file without holding user credentials
+    /// 
+    /// # Arguments
+    /// * `file_id` - File the token grants access to
+    /// * `issued_by` - Service account issuing the token
+    /// * `ttl` - How long the token stays valid if unused
+    pub async fn issue_download_token(&self, file_id: &str, issued_by: i64, ttl: Duration) -> Result<DownloadToken> {
+        self.get_file_by_id(file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+
+        let token = DownloadToken::new(file_id.to_string(), issued_by, ttl);
+
+        let backend = self.db.backend();
+
+        // Expired tokens can never be redeemed; drop them while we're here
+        let cleanup_sql = format!("DELETE FROM {} WHERE expires_at <= ?1", DOWNLOAD_TOKENS_TABLE);
+        let _ = backend.execute(&cleanup_sql, &[QueryValue::String(Utc::now().to_rfc3339())]).await;
+
+        let sql = format!(
+            "INSERT INTO {} (token, file_id, issued_by, expires_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            DOWNLOAD_TOKENS_TABLE
+        );
+        let params = [
+            QueryValue::String(token.token.clone()),
+            QueryValue::String(token.file_id.clone()),
+            QueryValue::I64(issued_by),
+            QueryValue::String(token.expires_at.to_rfc3339()),
+            QueryValue::String(token.created_at.to_rfc3339()),
+        ];
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+
+        Ok(token)
+    }
+
+    /// Redeem a download token, returning the file and a stream of its data
+    /// A token works once: it is consumed before the data is read, so concurrent
+    /// redemptions cannot both succeed. Used, expired, and unknown tokens are not found.
+    pub async fn redeem_download_token(&self, token: &str) -> Result<(File, BlobReader)> {
+        let not_found = || StorageError::FileNotFound(format!("download token {}", token));
+        let now = Utc::now().to_rfc3339();
+
+        let backend = self.db.backend();
+        let sql = format!("SELECT file_id FROM {} WHERE token = ?1", DOWNLOAD_TOKENS_TABLE);
+        let file_id = backend.fetch_one_params(&sql, &[QueryValue::String(token.to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| json.get("file_id").and_then(|v| v.as_str()).map(str::to_string))
+            .ok_or_else(not_found)?;
+        let file = self.get_file_by_id(&file_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(file_id.clone()))?;
+        if !self.is_owner_active(file.user_id).await? {
+            return Err(not_found());
+        }
+
+        // Consume atomically; only one caller can flip used_at from NULL
+        let consume_sql = format!(
+            "UPDATE {} SET used_at = ?1 WHERE token = ?2 AND used_at IS NULL AND expires_at > ?3",
+            DOWNLOAD_TOKENS_TABLE
+        );
+        let consumed = backend.execute(&consume_sql, &[
+            QueryValue::String(now.clone()),
+            QueryValue::String(token.to_string()),
+            QueryValue::String(now),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+        if consumed == 0 {
+            return Err(not_found());
+        }
+
+        let storage = self.storage_for(file.user_id).await?;
+        let reader = self.open_download(&storage, &file).await?;
+
+        self.count_download(&file_id).await;
+        self.record_transfer(file.user_id, TransferDirection::Download, file.size).await;
+        self.notify(FileEvent::Downloaded, &file, serde_json::json!({ "via": "download_token" })).await;
+
+        Ok((file, reader))
+    }
+
+    /// Let a file of at most `max_size` bytes be uploaded to `user_id`'s files once, without
+    /// their credentials, for `ttl`
+    /// The name, declared type and size, and the user's quota are checked now, and again once
+    /// the file arrives.
+    ///
+    /// # Returns
+    /// The upload and the signed path to `PUT` the file to
+    pub async fn presign_upload(
+        &self,
+        user_id: i64,
+        original_name: &str,
+        mime_type: Option<String>,
+        max_size: u64,
+        ttl: Duration,
+    ) -> Result<(PresignedUpload, String)> {
+        let key = self.upload_signing_key.as_deref()
+            .ok_or_else(|| StorageError::InvalidInput("presigned uploads are not configured".to_string()))?;
+        let original_name = validate_file_name(original_name)?;
+        if max_size == 0 {
+            return Err(StorageError::InvalidInput("max_size must be at least 1 byte".to_string()));
+        }
+        self.upload_policy.check_declared(&original_name, mime_type.as_deref(), Some(max_size))?;
+        self.check_limits(user_id, 1, max_size, max_size).await?;
+
+        let upload = PresignedUpload::new(user_id, original_name, mime_type, max_size, ttl);
+
+        let backend = self.db.backend();
+
+        // Expired uploads can never be sent; drop them while we're here
+        let cleanup_sql = format!("DELETE FROM {} WHERE expires_at <= ?1", PRESIGNED_UPLOADS_TABLE);
+        let _ = backend.execute(&cleanup_sql, &[QueryValue::String(Utc::now().to_rfc3339())]).await;
+
+        let sql = format!(
+            "INSERT INTO {} (upload_id, user_id, original_name, mime_type, max_size, expires_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            PRESIGNED_UPLOADS_TABLE
+        );
+        let params = [
+            QueryValue::String(upload.id.clone()),
+            QueryValue::I64(user_id),
+            QueryValue::String(upload.original_name.clone()),
+            upload.mime_type.clone().map(QueryValue::String).unwrap_or(QueryValue::Null),
+            QueryValue::I64(max_size as i64),
+            QueryValue::String(upload.expires_at.to_rfc3339()),
+            QueryValue::String(upload.created_at.to_rfc3339()),
+        ];
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+
+        let path = self.public_url(&upload.signed_path(key));
+        Ok((upload, path))
+    }
+
+    /// Store the file sent to a presigned upload URL
+    /// The signature is checked first. The upload is then claimed, so concurrent requests can't
+    /// both store a file; if storing fails it is released again, and the client may retry until
+    /// the URL expires. Used, expired, and unknown uploads are not found.
+    pub async fn complete_presigned_upload<R>(
+        &self,
+        upload_id: &str,
+        expires: i64,
+        max_size: u64,
+        signature: &str,
+        reader: R,
+    ) -> Result<File>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let key = self.upload_signing_key.as_deref()
+            .ok_or_else(|| StorageError::InvalidInput("presigned uploads are not configured".to_string()))?;
+        if !presign::verify(key, upload_id, expires, max_size, signature) {
+            return Err(StorageError::PermissionDenied("invalid upload signature".to_string()));
+        }
+        let not_found = || StorageError::FileNotFound(format!("presigned upload {}", upload_id));
+        let now = Utc::now();
+        if expires <= now.timestamp() {
+            return Err(not_found());
+        }
+
+        let backend = self.db.backend();
+        let claim_sql = format!(
+            "UPDATE {} SET used_at = ?1 WHERE upload_id = ?2 AND used_at IS NULL AND expires_at > ?3",
+            PRESIGNED_UPLOADS_TABLE
+        );
+        let claimed = backend.execute(&claim_sql, &[
+            QueryValue::String(now.to_rfc3339()),
+            QueryValue::String(upload_id.to_string()),
+            QueryValue::String(now.to_rfc3339()),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+        if claimed == 0 {
+            return Err(not_found());
+        }
+
+        let sql = format!("SELECT * FROM {} WHERE upload_id = ?1", PRESIGNED_UPLOADS_TABLE);
+        let upload = backend.fetch_one_params(&sql, &[QueryValue::String(upload_id.to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|row| PresignedUpload::from_json(&row))
+            .ok_or_else(not_found)?;
+
+        let reader = SizeLimitedReader::new(reader, Some(upload.max_size));
+        match self.store_stream_with_metadata(reader, &upload.original_name, upload.user_id, upload.mime_type.clone()).await {
+            Ok(file) => {
+                let sql = format!("DELETE FROM {} WHERE upload_id = ?1", PRESIGNED_UPLOADS_TABLE);
+                let _ = backend.execute(&sql, &[QueryValue::String(upload_id.to_string())]).await;
+                Ok(file)
+            }
+            Err(e) => {
+                let sql = format!("UPDATE {} SET used_at = NULL WHERE upload_id = ?1", PRESIGNED_UPLOADS_TABLE);
+                let _ = backend.execute(&sql, &[QueryValue::String(upload_id.to_string())]).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Start a resumable upload of a `total_size`-byte file
+    /// The name, declared type and size, and the owner's quota are checked now, so an upload
+    /// that could never be accepted is refused before any bytes are sent
+    pub async fn create_upload_session(
+        &self,
+        user_id: i64,
+        original_name: &str,
+        mime_type: Option<String>,
+        total_size: u64,
+        checksum: Option<String>,
+    ) -> Result<UploadSession> {
+        let original_name = validate_file_name(original_name)?;
+        if let Some(checksum) = &checksum {
+            if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(StorageError::InvalidInput("checksum must be a hex SHA-256".to_string()));
             }
         }
-    }
+        self.upload_policy.check_declared(&original_name, mime_type.as_deref(), Some(total_size))?;
+        self.check_limits(user_id, 1, total_size, total_size).await?;
 
-    /// Delete a file and its metadata (transactional)
-    pub async fn delete_with_metadata(&self, file_id: &str, user_id: i64) -> Result<()> {
-        // Step 1: Fetch file metadata to verify ownership and get stored_name
-        let file = self.get_file_by_id(file_id).await?
-            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+        let storage = self.storage_for(user_id).await?;
+        let (upload_id, stored_name) = storage.new_stored_name(&original_name);
+        let session = UploadSession::new(
+            upload_id,
+            user_id,
+            original_name,
+            mime_type,
+            total_size as i64,
+            checksum.map(|checksum| checksum.to_lowercase()),
+            stored_name,
+        );
+        storage.write_pending_at(&session.pending_blob(storage.tenant_id()), 0, &[]).await?;
 
-        // Step 2: Verify ownership
-        if file.user_id != user_id {
-            return Err(StorageError::StorageError("Access denied: file belongs to another user".to_string()));
+        let backend = self.db.backend();
+        let sql = format!(
+            "INSERT INTO {} (upload_id, user_id, original_name, mime_type, total_size, received_bytes, checksum, stored_name, blob_created_at, created_at, updated_at, expires_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6, ?7, ?8, ?9, ?9, ?10)",
+            UPLOAD_SESSIONS_TABLE
+        );
+        let optional = |value: &Option<String>| value.clone().map(QueryValue::String).unwrap_or(QueryValue::Null);
+        let inserted = backend.execute(&sql, &[
+            QueryValue::String(session.upload_id.clone()),
+            QueryValue::I64(user_id),
+            QueryValue::String(session.original_name.clone()),
+            optional(&session.mime_type),
+            QueryValue::I64(session.total_size),
+            optional(&session.checksum),
+            QueryValue::String(session.stored_name.clone()),
+            QueryValue::String(session.blob_created_at.to_rfc3339()),
+            QueryValue::String(session.created_at.to_rfc3339()),
+            QueryValue::String(session.expires_at.to_rfc3339()),
+        ]).await;
+        if let Err(e) = inserted {
+            let _ = storage.discard_pending(&session.pending_blob(storage.tenant_id())).await;
+            return Err(StorageError::StorageError(format!("Database insert failed: {}", e)));
         }
 
-        // Step 3: Delete from database first (safer - if disk delete fails, we can retry)
+        Ok(session)
+    }
+
+    /// Get one of a user's uploads in progress
+    /// Uploads of other users and expired uploads are reported as missing
+    pub async fn get_upload_session(&self, upload_id: &str, user_id: i64) -> Result<UploadSession> {
         let backend = self.db.backend();
-        let sql = format!("DELETE FROM {} WHERE id = ?1", File::table_name());
-        backend.execute(&sql, &[QueryValue::String(file_id.to_string())]).await
-            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))?;
+        let sql = format!("SELECT * FROM {} WHERE upload_id = ?1", UPLOAD_SESSIONS_TABLE);
+        let row = backend.fetch_one_params(&sql, &[QueryValue::String(upload_id.to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        row.as_ref()
+            .and_then(UploadSession::from_json)
+            .filter(|session| session.user_id == user_id && !session.is_expired(Utc::now()))
+            .ok_or_else(|| StorageError::FileNotFound(format!("upload {}", upload_id)))
+    }
 
-        // Step 4: Delete file from disk
-        self.storage.delete(&file.stored_name).await?;
+    /// Write a chunk of a resumable upload at `offset`
+    /// Chunks may be resent, but must not start past the bytes received so far
+    pub async fn write_upload_chunk(&self, upload_id: &str, user_id: i64, offset: u64, data: &[u8]) -> Result<UploadSession> {
+        let mut session = self.get_upload_session(upload_id, user_id).await?;
+        if offset > session.received_bytes as u64 {
+            return Err(StorageError::PreconditionFailed(format!(
+                "upload {} has received {} bytes; send the chunk at that offset",
+                upload_id, session.received_bytes
+            )));
+        }
+        let end = offset + data.len() as u64;
+        if end > session.total_size as u64 {
+            return Err(StorageError::InvalidInput(format!(
+                "chunk ends at byte {} but the upload is {} bytes",
+                end, session.total_size
+            )));
+        }
 
-        Ok(())
+        let storage = self.storage_for(user_id).await?;
+        storage.write_pending_at(&session.pending_blob(storage.tenant_id()), offset, data).await?;
+
+        // Only ever move forward, so a resent chunk racing a later one can't lose progress
+        let now = Utc::now();
+        let backend = self.db.backend();
+        let sql = format!(
+            "UPDATE {} SET received_bytes = ?1, updated_at = ?2 WHERE upload_id = ?3 AND received_bytes < ?1",
+            UPLOAD_SESSIONS_TABLE
+        );
+        backend.execute(&sql, &[
+            QueryValue::I64(end as i64),
+            QueryValue::String(now.to_rfc3339()),
+            QueryValue::String(upload_id.to_string()),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+        self.record_transfer(user_id, TransferDirection::Upload, data.len() as i64).await;
+
+        session.received_bytes = session.received_bytes.max(end as i64);
+        session.updated_at = now;
+        Ok(session)
     }
 
-    /// Retrieve a file's data (with permission check)
-    pub async fn retrieve_with_permission(&self, file_id: &str, user_id: i64) -> Result<Vec<u8>> {
-        // Verify ownership
-        let file = self.get_file_by_id(file_id).await?
-            .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+    /// Turn a fully received upload into a file
+    /// The bytes are checked against the declared checksum, the upload policy, and the quota
+    /// like any other upload; a rejected upload is discarded
+    pub async fn complete_upload(&self, upload_id: &str, user_id: i64) -> Result<File> {
+        let session = self.get_upload_session(upload_id, user_id).await?;
+        if !session.is_complete() {
+            return Err(StorageError::PreconditionFailed(format!(
+                "upload {} has received {} of {} bytes",
+                upload_id, session.received_bytes, session.total_size
+            )));
+        }
 
-        if file.user_id != user_id {
-            return Err(StorageError::StorageError("Access denied: file belongs to another user".to_string()));
+        // Claim the session, so only one request turns it into a file
+        if self.delete_upload_session(upload_id).await? == 0 {
+            return Err(StorageError::FileNotFound(format!("upload {}", upload_id)));
+        }
+
+        let storage = self.storage_for(user_id).await?;
+        let pending = session.pending_blob(storage.tenant_id());
+        // Hashed as a stream, then moved into place by name, so large uploads are never held in memory
+        let mut reader = HashingReader::new(storage.retrieve_pending_stream(&pending).await?);
+        let size = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+        let detected_mime_type = crate::detect_mime_type(reader.head()).map(str::to_string);
+        let checksum = reader.finish();
+        if session.checksum.as_ref().is_some_and(|expected| *expected != checksum) {
+            let _ = storage.discard_pending(&pending).await;
+            return Err(StorageError::InvalidInput(format!(
+                "upload {} has checksum {} but {} was declared",
+                upload_id, checksum, session.checksum.unwrap_or_default()
+            )));
+        }
+
+        let file_metadata = FileMetadata {
+            id: session.upload_id,
+            original_name: session.original_name,
+            stored_name: session.stored_name,
+            size,
+            mime_type: session.mime_type,
+            detected_mime_type,
+            checksum,
+            created_at: session.blob_created_at,
+        };
+        // Without two-phase uploads, a recorded file's blob must be committed before its row exists
+        if !self.two_phase_uploads {
+            storage.commit_pending(&pending).await?;
         }
+        self.record_stored_file(&storage, file_metadata, user_id).await
+    }
 
-        // Retrieve file data
-        self.storage.retrieve(&file.stored_name).await
+    /// Abandon an upload and remove the bytes received so far
+    pub async fn cancel_upload(&self, upload_id: &str, user_id: i64) -> Result<()> {
+        let session = self.get_upload_session(upload_id, user_id).await?;
+        self.delete_upload_session(upload_id).await?;
+        let storage = self.storage_for(user_id).await?;
+        storage.discard_pending(&session.pending_blob(storage.tenant_id())).await
+    }
+
+    /// Delete an upload's row, returning how many rows were deleted
+    async fn delete_upload_session(&self, upload_id: &str) -> Result<u64> {
+        let backend = self.db.backend();
+        let sql = format!("DELETE FROM {} WHERE upload_id = ?1", UPLOAD_SESSIONS_TABLE);
+        backend.execute(&sql, &[QueryValue::String(upload_id.to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database delete failed: {}", e)))
     }
 
     /// Get file metadata by ID
+    /// Files in the trash are treated as missing
     pub async fn get_file_by_id(&self, file_id: &str) -> Result<Option<File>> {
+        self.find_file(file_id, false).await
+    }
+
+    /// Get file metadata by ID, optionally including files in the trash
+    async fn find_file(&self, file_id: &str, include_trashed: bool) -> Result<Option<File>> {
         let backend = self.db.backend();
         let mut query_builder = backend.query_builder();
 
@@ -121,10 +3501,10 @@ impl TransactionalStorageService {
         let file = File::from_json(&json_rows[0])
             .map_err(|e| StorageError::StorageError(format!("Deserialization error: {}", e)))?;
 
-        Ok(Some(file))
+        Ok(Some(file).filter(|file| include_trashed || !file.is_trashed()))
     }
 
-    /// List all files for a user
+    /// List all files for a user, excluding files in the trash
     pub async fn list_user_files(&self, user_id: i64) -> Result<Vec<File>> {
         let backend = self.db.backend();
         let mut query_builder = backend.query_builder();
@@ -140,12 +3520,270 @@ impl TransactionalStorageService {
         let json_rows = backend.fetch_all_params(&sql, query_builder.params()).await
             .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
 
-        let files: Result<Vec<File>> = json_rows.iter()
-            .map(|json| File::from_json(json)
-                .map_err(|e| StorageError::StorageError(format!("Deserialization error: {}", e))))
+        let files = files_from_rows(&json_rows)?;
+
+        Ok(files.into_iter().filter(|file| !file.is_trashed()).collect())
+    }
+
+    /// List a page of a user's files, excluding files in the trash, filtered and sorted by `options`
+    /// Newest first unless a sort is given; files that tie are ordered by ID, so pages don't overlap
+    pub async fn list_user_files_page(&self, user_id: i64, options: &FileListOptions) -> Result<FileSearchResults> {
+        let mut params = vec![QueryValue::I64(user_id)];
+        let mut conditions = vec!["user_id = ?1".to_string(), "deleted_at IS NULL".to_string()];
+        conditions.extend(options.filters(&mut params)?);
+
+        let sort = options.sort.unwrap_or(FileSort::CreatedAt);
+        let direction = if options.is_descending(sort) { "DESC" } else { "ASC" };
+        let sql = format!(
+            "SELECT * FROM {} WHERE {} ORDER BY {} {}, id {}",
+            File::table_name(),
+            conditions.join(" AND "),
+            sort.column(),
+            direction,
+            direction
+        );
+
+        let (json_rows, total) = self.fetch_page(&sql, params, options.limit, options.offset).await?;
+        Ok(FileSearchResults {
+            files: files_from_rows(&json_rows)?,
+            total,
+        })
+    }
+
+    /// Count the rows `sql` selects, and fetch `limit` of them after skipping `offset`
+    /// `sql` must order its rows completely, so pages don't overlap
+    async fn fetch_page(&self, sql: &str, mut params: Vec<QueryValue>, limit: i64, offset: i64) -> Result<(Vec<serde_json::Value>, i64)> {
+        let backend = self.db.backend();
+
+        let count_sql = format!("SELECT COUNT(*) as total FROM ({}) listing", sql);
+        let total = backend.fetch_one_params(&count_sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| json.get("total").and_then(|v| v.as_i64()))
+            .unwrap_or(0);
+
+        let page_sql = format!("{} LIMIT ?{} OFFSET ?{}", sql, params.len() + 1, params.len() + 2);
+        params.push(QueryValue::I64(limit));
+        params.push(QueryValue::I64(offset));
+        let json_rows = backend.fetch_all_params(&page_sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok((json_rows, total))
+    }
+
+    /// Write every file the user owns, trashed ones included, to `writer` as `format`
+    ///
+    /// Files are read in batches ordered by ID, so the whole inventory is never held in memory
+    /// and a large export starts arriving straight away. Returns the number of files written.
+    pub async fn export_files<W>(&self, user_id: i64, format: ExportFormat, mut writer: W) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE user_id = ?1 AND id > ?2 ORDER BY id LIMIT ?3",
+            File::table_name()
+        );
+
+        writer.write_all(match format {
+            ExportFormat::Csv => ExportedFile::csv_header(),
+            ExportFormat::Json => "[".to_string(),
+        }.as_bytes()).await?;
+
+        let mut exported = 0u64;
+        let mut after = String::new();
+        loop {
+            let params = [
+                QueryValue::I64(user_id),
+                QueryValue::String(after.clone()),
+                QueryValue::I64(EXPORT_BATCH_SIZE),
+            ];
+            let json_rows = backend.fetch_all_params(&sql, &params).await
+                .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+            let files = files_from_rows(&json_rows)?;
+            let Some(last) = files.last().and_then(|file| file.id.clone()) else {
+                break;
+            };
+
+            let ids: Vec<String> = files.iter().filter_map(|file| file.id.clone()).collect();
+            let (favorites, mut shares) = self.export_details(user_id, &ids).await?;
+            for file in files {
+                let id = file.id.clone().unwrap_or_default();
+                let record = ExportedFile::new(file, favorites.contains(&id), shares.remove(&id).unwrap_or_default());
+                let line = match format {
+                    ExportFormat::Csv => record.csv_row(),
+                    ExportFormat::Json => {
+                        let json = serde_json::to_string(&record)
+                            .map_err(|e| StorageError::StorageError(format!("Failed to encode export: {}", e)))?;
+                        if exported == 0 { json } else { format!(",{}", json) }
+                    }
+                };
+                writer.write_all(line.as_bytes()).await?;
+                exported += 1;
+            }
+
+            if (json_rows.len() as i64) < EXPORT_BATCH_SIZE {
+                break;
+            }
+            after = last;
+        }
+
+        if format == ExportFormat::Json {
+            writer.write_all(b"]").await?;
+        }
+        writer.flush().await?;
+        Ok(exported)
+    }
+
+    /// Which of a batch of exported files are favorites, and their share links by file ID
+    async fn export_details(
+        &self,
+        user_id: i64,
+        ids: &[String],
+    ) -> Result<(HashSet<String>, HashMap<String, Vec<ExportedShare>>)> {
+        let backend = self.db.backend();
+        let placeholders: Vec<String> = (2..ids.len() + 2).map(|i| format!("?{}", i)).collect();
+        let mut params = vec![QueryValue::I64(user_id)];
+        params.extend(ids.iter().cloned().map(QueryValue::String));
+
+        let sql = format!(
+            "SELECT file_id FROM {} WHERE user_id = ?1 AND file_id IN ({})",
+            FAVORITES_TABLE,
+            placeholders.join(", ")
+        );
+        let favorites = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .iter()
+            .filter_map(|row| row.get("file_id").and_then(|v| v.as_str()).map(str::to_string))
             .collect();
 
-        files
+        let sql = format!(
+            "SELECT * FROM {} WHERE user_id = ?1 AND file_id IN ({}) ORDER BY created_at",
+            SHARES_TABLE,
+            placeholders.join(", ")
+        );
+        let rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        let mut shares: HashMap<String, Vec<ExportedShare>> = HashMap::new();
+        for share in rows.iter().filter_map(FileShare::from_json) {
+            shares.entry(share.file_id.clone()).or_default().push(ExportedShare {
+                url: self.public_url(&format!("/share/{}", share.token)),
+                has_password: share.has_password(),
+                expires_at: share.expires_at,
+            });
+        }
+
+        Ok((favorites, shares))
+    }
+
+    /// Pick the files for a zip archive: the given files, or every file in `folder` and its
+    /// subfolders (all of the user's files when `folder` is the root)
+    /// Every file must belong to `user_id` and be out of the trash
+    pub async fn archive_entries(&self, user_id: i64, file_ids: &[String], folder: Option<&str>) -> Result<Vec<ArchiveEntry>> {
+        let (files, base_folder) = match folder {
+            Some(folder) => {
+                let folder = normalize_folder(folder)?;
+                let files = self.list_user_files(user_id).await?
+                    .into_iter()
+                    .filter(|file| match (&folder, &file.folder) {
+                        (None, _) => true,
+                        (Some(base), Some(folder)) => folder == base || folder.starts_with(&format!("{}/", base)),
+                        (Some(_), None) => false,
+                    })
+                    .collect::<Vec<_>>();
+                (files, folder)
+            }
+            None => {
+                if file_ids.len() > MAX_ARCHIVE_FILES {
+                    return Err(StorageError::InvalidInput(format!("An archive holds at most {} files", MAX_ARCHIVE_FILES)));
+                }
+                let mut files = Vec::with_capacity(file_ids.len());
+                let mut seen = HashSet::new();
+                for file_id in file_ids.iter().filter(|file_id| seen.insert(file_id.as_str())) {
+                    let file = self.get_file_by_id(file_id).await?
+                        .ok_or_else(|| StorageError::FileNotFound(file_id.to_string()))?;
+                    if file.user_id != user_id {
+                        return Err(StorageError::PermissionDenied("file belongs to another user".to_string()));
+                    }
+                    files.push(file);
+                }
+                (files, None)
+            }
+        };
+
+        if files.is_empty() {
+            return Err(StorageError::InvalidInput("No files to archive".to_string()));
+        }
+        if files.len() > MAX_ARCHIVE_FILES {
+            return Err(StorageError::InvalidInput(format!("An archive holds at most {} files", MAX_ARCHIVE_FILES)));
+        }
+        Ok(archive::entry_paths(files, base_folder.as_deref()))
+    }
+
+    /// Search a user's files by name, MIME type, and the text extracted from their content
+    /// Results are ranked: exact name matches first, then name prefix matches, then any other match
+    pub async fn search_user_files(
+        &self,
+        user_id: i64,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<FileSearchResults> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(FileSearchResults::default());
+        }
+
+        let backend = self.db.backend();
+        let escaped = escape_like(&query.to_lowercase());
+        let match_clause = format!(
+            "user_id = ?1 AND deleted_at IS NULL AND (LOWER(original_name) LIKE ?2 ESCAPE '!' OR LOWER(mime_type) LIKE ?2 ESCAPE '!' \
+             OR EXISTS (SELECT 1 FROM {} t WHERE t.file_id = {}.id AND LOWER(t.content) LIKE ?2 ESCAPE '!'))",
+            FILE_TEXTS_TABLE,
+            File::table_name()
+        );
+
+        let count_sql = format!(
+            "SELECT COUNT(*) as total FROM {} WHERE {}",
+            File::table_name(),
+            match_clause
+        );
+        let count_params = [
+            QueryValue::I64(user_id),
+            QueryValue::String(format!("%{}%", escaped)),
+        ];
+
+        let total = backend.fetch_one_params(&count_sql, &count_params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .and_then(|json| json.get("total").and_then(|v| v.as_i64()))
+            .unwrap_or(0);
+
+        let sql = format!(
+            "SELECT *, CASE \
+                WHEN LOWER(original_name) = ?3 THEN 3 \
+                WHEN LOWER(original_name) LIKE ?4 ESCAPE '!' THEN 2 \
+                ELSE 1 END as search_rank \
+             FROM {} WHERE {} \
+             ORDER BY search_rank DESC, created_at DESC \
+             LIMIT ?5 OFFSET ?6",
+            File::table_name(),
+            match_clause
+        );
+        let params = [
+            QueryValue::I64(user_id),
+            QueryValue::String(format!("%{}%", escaped)),
+            QueryValue::String(query.to_lowercase()),
+            QueryValue::String(format!("{}%", escaped)),
+            QueryValue::I64(limit),
+            QueryValue::I64(offset),
+        ];
+
+        let json_rows = backend.fetch_all_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok(FileSearchResults {
+            files: files_from_rows(&json_rows)?,
+            total,
+        })
     }
 
     /// Check if a file exists and belongs to the user
@@ -160,7 +3798,7 @@ impl TransactionalStorageService {
     pub async fn get_user_storage_stats(&self, user_id: i64) -> Result<UserStorageStats> {
         let backend = self.db.backend();
         let sql = format!(
-            "SELECT COUNT(*) as file_count, COALESCE(SUM(size), 0) as total_size FROM {} WHERE user_id = ?1",
+            "SELECT COUNT(*) as file_count, COALESCE(SUM(size), 0) as total_size FROM {} WHERE user_id = ?1 AND deleted_at IS NULL",
             File::table_name()
         );
 
@@ -179,10 +3817,204 @@ impl TransactionalStorageService {
             UserStorageStats {
                 file_count,
                 total_size,
+                by_mime_type: Vec::new(),
             }
         }).unwrap_or_default();
 
-        Ok(stats)
+        Ok(UserStorageStats {
+            by_mime_type: self.usage_by_mime_type(user_id).await?,
+            ..stats
+        })
+    }
+
+    /// Place a legal hold on a row, blocking its deletion until the hold is released
+    /// 
+    /// Files are held as rows of the `files` table. A row may carry several holds (one per case,
+    /// say), and stays protected until every one of them is released.
+    pub async fn place_legal_hold(&self, table: &str, record_id: &str, reason: &str, placed_by: i64) -> Result<LegalHold> {
+        validate_table_name(table)?;
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err(StorageError::InvalidInput("a hold needs a reason".to_string()));
+        }
+
+        let backend = self.db.backend();
+        let sql = format!("SELECT COUNT(*) as count FROM {} WHERE id = ?1", table);
+        let row = backend.fetch_one_params(&sql, &[QueryValue::String(record_id.to_string())]).await
+            .map_err(|e| StorageError::InvalidInput(format!("cannot look up records in '{}': {}", table, e)))?;
+        if row.and_then(|row| row.get("count").and_then(|v| v.as_i64())).unwrap_or(0) == 0 {
+            return Err(StorageError::FileNotFound(format!("{} {}", table, record_id)));
+        }
+
+        let placed_at = Utc::now().to_rfc3339();
+        let sql = format!(
+            "INSERT INTO {} (table_name, record_id, reason, placed_by, placed_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            LEGAL_HOLDS_TABLE
+        );
+        let params = [
+            QueryValue::String(table.to_string()),
+            QueryValue::String(record_id.to_string()),
+            QueryValue::String(reason.to_string()),
+            QueryValue::I64(placed_by),
+            QueryValue::String(placed_at.clone()),
+        ];
+        backend.execute(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+
+        let sql = format!(
+            "SELECT * FROM {} WHERE table_name = ?1 AND record_id = ?2 AND placed_by = ?3 AND placed_at = ?4 \
+             ORDER BY id DESC LIMIT 1",
+            LEGAL_HOLDS_TABLE
+        );
+        let row = backend.fetch_one_params(&sql, &[
+            QueryValue::String(table.to_string()),
+            QueryValue::String(record_id.to_string()),
+            QueryValue::I64(placed_by),
+            QueryValue::String(placed_at),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        row.as_ref().and_then(LegalHold::from_json)
+            .ok_or_else(|| StorageError::StorageError("Placed hold was not found".to_string()))
+    }
+
+    /// Release a legal hold; the hold is kept as history
+    /// Fails with `PreconditionFailed` if it was already released
+    pub async fn release_legal_hold(&self, hold_id: i64, released_by: i64) -> Result<LegalHold> {
+        let hold = self.get_legal_hold(hold_id).await?
+            .ok_or_else(|| StorageError::FileNotFound(format!("legal hold {}", hold_id)))?;
+        if !hold.is_active() {
+            return Err(StorageError::PreconditionFailed(format!("legal hold {} is already released", hold_id)));
+        }
+
+        let released_at = Utc::now();
+        let sql = format!(
+            "UPDATE {} SET released_by = ?1, released_at = ?2 WHERE id = ?3 AND released_at IS NULL",
+            LEGAL_HOLDS_TABLE
+        );
+        self.db.backend().execute(&sql, &[
+            QueryValue::I64(released_by),
+            QueryValue::String(released_at.to_rfc3339()),
+            QueryValue::I64(hold_id),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+
+        Ok(LegalHold {
+            released_by: Some(released_by),
+            released_at: Some(released_at),
+            ..hold
+        })
+    }
+
+    /// Fetch a legal hold, active or released
+    pub async fn get_legal_hold(&self, hold_id: i64) -> Result<Option<LegalHold>> {
+        let sql = format!("SELECT * FROM {} WHERE id = ?1", LEGAL_HOLDS_TABLE);
+        let row = self.db.backend().fetch_one_params(&sql, &[QueryValue::I64(hold_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        Ok(row.as_ref().and_then(LegalHold::from_json))
+    }
+
+    /// List legal holds, newest first, optionally only those on one table and only active ones
+    pub async fn list_legal_holds(&self, table: Option<&str>, active_only: bool) -> Result<Vec<LegalHold>> {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        if let Some(table) = table {
+            conditions.push("table_name = ?1");
+            params.push(QueryValue::String(table.to_string()));
+        }
+        if active_only {
+            conditions.push("released_at IS NULL");
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        };
+
+        let sql = format!("SELECT * FROM {}{} ORDER BY id DESC", LEGAL_HOLDS_TABLE, where_clause);
+        let rows = self.db.backend().fetch_all_params(&sql, &params).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        Ok(rows.iter().filter_map(LegalHold::from_json).collect())
+    }
+
+    /// Whether a row is under an active legal hold
+    pub async fn is_on_legal_hold(&self, table: &str, record_id: &str) -> Result<bool> {
+        let sql = format!(
+            "SELECT COUNT(*) as count FROM {} WHERE table_name = ?1 AND record_id = ?2 AND released_at IS NULL",
+            LEGAL_HOLDS_TABLE
+        );
+        let row = self.db.backend().fetch_one_params(&sql, &[
+            QueryValue::String(table.to_string()),
+            QueryValue::String(record_id.to_string()),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        Ok(row.and_then(|row| row.get("count").and_then(|v| v.as_i64())).unwrap_or(0) > 0)
+    }
+
+    /// Tables with at least one row under an active legal hold
+    pub async fn tables_on_legal_hold(&self) -> Result<Vec<String>> {
+        let sql = format!(
+            "SELECT DISTINCT table_name FROM {} WHERE released_at IS NULL ORDER BY table_name",
+            LEGAL_HOLDS_TABLE
+        );
+        let rows = self.db.backend().fetch_all_params(&sql, &[]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        Ok(rows.iter()
+            .filter_map(|row| row.get("table_name").and_then(|v| v.as_str()).map(str::to_string))
+            .collect())
+    }
+
+    /// Whether the user's account, or any row they own (by its `user_id` column), is under an
+    /// active legal hold
+    /// Erasing the user would delete those rows along with the account
+    pub async fn user_has_legal_holds(&self, user_id: i64) -> Result<bool> {
+        if self.is_on_legal_hold("users", &user_id.to_string()).await? {
+            return Ok(true);
+        }
+        let backend = self.db.backend();
+        for hold in self.list_legal_holds(None, true).await? {
+            if validate_table_name(&hold.table_name).is_err() {
+                continue;
+            }
+            // Tables without a user_id column have no owner to check
+            let sql = format!("SELECT user_id FROM {} WHERE id = ?1", hold.table_name);
+            let Ok(Some(row)) = backend.fetch_one_params(&sql, &[QueryValue::String(hold.record_id.clone())]).await else {
+                continue;
+            };
+            if row.get("user_id").and_then(|v| v.as_i64()) == Some(user_id) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Fail with `LegalHold` if a row is under an active legal hold
+    pub async fn ensure_not_held(&self, table: &str, record_id: &str) -> Result<()> {
+        if self.is_on_legal_hold(table, record_id).await? {
+            return Err(StorageError::LegalHold(format!("{} {} is under a legal hold", table, record_id)));
+        }
+        Ok(())
+    }
+
+    /// File count and bytes per content type, largest first
+    /// Files are grouped by their detected type, falling back to the declared one
+    async fn usage_by_mime_type(&self, user_id: i64) -> Result<Vec<MimeTypeUsage>> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT COALESCE(detected_mime_type, mime_type) as mime_type, COUNT(*) as file_count, \
+             COALESCE(SUM(size), 0) as total_size FROM {} WHERE user_id = ?1 AND deleted_at IS NULL \
+             GROUP BY COALESCE(detected_mime_type, mime_type) ORDER BY total_size DESC",
+            File::table_name()
+        );
+
+        let rows = backend.fetch_all_params(&sql, &[QueryValue::I64(user_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok(rows.iter().map(|row| MimeTypeUsage {
+            mime_type: row.get("mime_type").and_then(|v| v.as_str()).map(str::to_string),
+            file_count: row.get("file_count").and_then(|v| v.as_i64()).unwrap_or(0),
+            total_size: row.get("total_size").and_then(|v| v.as_i64()).unwrap_or(0),
+        }).collect())
     }
 }
 
@@ -191,6 +4023,8 @@ impl TransactionalStorageService {
 pub struct UserStorageStats {
     pub file_count: i64,
     pub total_size: i64,
+    /// Usage per content type, largest first
+    pub by_mime_type: Vec<MimeTypeUsage>,
 }
 
 impl Default for UserStorageStats {
@@ -198,6 +4032,320 @@ impl Default for UserStorageStats {
         Self {
             file_count: 0,
             total_size: 0,
+            by_mime_type: Vec::new(),
+        }
+    }
+}
+
+/// Files of one content type in a user's storage stats
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MimeTypeUsage {
+    /// Detected content type, or the declared one; `None` when neither is known
+    pub mime_type: Option<String>,
+    pub file_count: i64,
+    pub total_size: i64,
+}
+
+/// Outcome of a pending upload GC pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PendingUploadStats {
+    /// Blobs whose metadata was recorded before the upload was interrupted
+    pub committed: u64,
+    /// Blobs with no metadata that were deleted
+    pub removed: u64,
+}
+
+/// Outcome of a storage reconciliation pass
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct GcReport {
+    /// Keys of expired blobs that no file refers to
+    pub orphaned_blobs: Vec<String>,
+    /// Orphaned blobs that were deleted; none in a dry run
+    pub removed_blobs: u64,
+    /// Unreferenced blobs kept because they are newer than the grace period or of unknown age
+    pub recent_blobs: u64,
+    /// IDs of files whose blob is missing from storage
+    pub missing_blobs: Vec<String>,
+}
+
+/// Changes to a file's metadata; fields left out are unchanged
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct MetadataUpdate {
+    pub original_name: Option<String>,
+    pub mime_type: Option<String>,
+    /// Folder to move the file to, as a `/`-separated path; an empty path moves it to the root
+    pub folder: Option<String>,
+}
+
+/// Outcome of purging files whose time in the trash is over
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TrashPurge {
+    pub purged: u64,
+    /// Files that could not be removed this time, such as when their storage was unreachable
+    pub errors: u64,
+}
+
+/// Outcome of a storage consistency check
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ConsistencyReport {
+    /// IDs of files whose blob is missing from storage
+    pub missing_blobs: Vec<String>,
+    /// Files whose blob is not the size recorded for them
+    pub size_mismatches: Vec<SizeMismatch>,
+    /// Keys of blobs no file refers to
+    pub unknown_blobs: Vec<String>,
+    /// Problems fixed in repair mode; none otherwise
+    pub repaired: u64,
+}
+
+/// A file whose recorded size disagrees with its blob
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SizeMismatch {
+    pub file_id: String,
+    pub recorded_size: i64,
+    pub actual_size: i64,
+}
+
+/// Order of a file listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSort {
+    /// Newest first
+    CreatedAt,
+    /// By name, ignoring case, A to Z
+    Name,
+    /// Largest first
+    Size,
+}
+
+impl FileSort {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "created_at" => Some(Self::CreatedAt),
+            "name" => Some(Self::Name),
+            "size" => Some(Self::Size),
+            _ => None,
+        }
+    }
+
+    /// Whether files are listed from the highest value down unless the order is reversed
+    pub fn is_descending(&self) -> bool {
+        !matches!(self, Self::Name)
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            Self::CreatedAt => "created_at",
+            Self::Name => "LOWER(original_name)",
+            Self::Size => "size",
+        }
+    }
+}
+
+/// Which files of a listing to return, in what order
+#[derive(Debug, Clone)]
+pub struct FileListOptions {
+    /// Order of the files; a listing's own order when unset
+    pub sort: Option<FileSort>,
+    /// Direction of `sort`; the sort's natural direction when unset
+    pub descending: Option<bool>,
+    /// Only files served as this content type, or as any type of a family given as `image/*`
+    pub mime_type: Option<String>,
+    /// Only files directly in this folder; an empty path means the root
+    pub folder: Option<String>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl FileListOptions {
+    /// Every file, in the listing's own order, `limit` at a time
+    pub fn new(limit: i64, offset: i64) -> Self {
+        Self { sort: None, descending: None, mime_type: None, folder: None, limit, offset }
+    }
+
+    fn is_descending(&self, sort: FileSort) -> bool {
+        self.descending.unwrap_or_else(|| sort.is_descending())
+    }
+
+    /// SQL conditions for the filters, binding their values after `params`
+    fn filters(&self, params: &mut Vec<QueryValue>) -> Result<Vec<String>> {
+        let mut conditions = Vec::new();
+
+        if let Some(mime_type) = &self.mime_type {
+            // The type files are served as, without parameters such as `; charset=utf-8`
+            let content_type = "LOWER(COALESCE(detected_mime_type, mime_type))";
+            let mime_type = mime_type.trim().to_lowercase();
+            match mime_type.strip_suffix("/*") {
+                Some(family) => {
+                    params.push(QueryValue::String(format!("{}/%", escape_like(family))));
+                    conditions.push(format!("{} LIKE ?{} ESCAPE '!'", content_type, params.len()));
+                }
+                None => {
+                    params.push(QueryValue::String(format!("{};%", escape_like(&mime_type))));
+                    params.push(QueryValue::String(mime_type));
+                    conditions.push(format!(
+                        "({} LIKE ?{} ESCAPE '!' OR {} = ?{})",
+                        content_type, params.len() - 1, content_type, params.len()
+                    ));
+                }
+            }
+        }
+
+        if let Some(folder) = &self.folder {
+            match normalize_folder(folder)? {
+                Some(folder) => {
+                    params.push(QueryValue::String(folder));
+                    conditions.push(format!("folder = ?{}", params.len()));
+                }
+                None => conditions.push("folder IS NULL".to_string()),
+            }
+        }
+
+        Ok(conditions)
+    }
+}
+
+/// A page of file search results, or of a file listing
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct FileSearchResults {
+    pub files: Vec<File>,
+    /// Total number of matches across all pages
+    pub total: i64,
+}
+
+/// Direction of a transfer recorded for usage reports
+#[derive(Debug, Clone, Copy)]
+enum TransferDirection {
+    Upload,
+    Download,
+}
+
+impl TransferDirection {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TransferDirection::Upload => "upload",
+            TransferDirection::Download => "download",
+        }
+    }
+}
+
+/// Integer column of a JSON row, treating missing values as zero
+/// Who a file's storage is counted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuotaOwner {
+    User(i64),
+    /// An organization with a plan of its own
+    Organization(i64),
+}
+
+/// Filter for the files counted against a user's own quota, with the user's ID as `?1`: their
+/// personal files and the files of the organizations they own that have no plan of their own
+fn owner_pool_filter() -> String {
+    format!(
+        "((user_id = ?1 AND organization_id IS NULL) OR organization_id IN (SELECT id FROM {} WHERE owner_id = ?1 AND plan IS NULL))",
+        ORGANIZATIONS_TABLE
+    )
+}
+
+fn json_i64(row: &serde_json::Value, column: &str) -> i64 {
+    row.get(column).and_then(|v| v.as_i64()).unwrap_or(0)
+}
+
+/// Check that a table name is safe to interpolate into SQL
+fn validate_table_name(table: &str) -> Result<()> {
+    let valid = !table.is_empty()
+        && table.len() <= 64
+        && table.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(StorageError::InvalidInput(format!("'{}' is not a valid table name", table.escape_debug())));
+    }
+    Ok(())
+}
+
+/// Hash a stored blob from the start, read as a stream
+async fn hash_blob(storage: &StorageService, stored_name: &str) -> Result<ResumableSha256> {
+    let mut reader = storage.retrieve_stream(stored_name).await?;
+    let mut hasher = ResumableSha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(hasher);
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// Where a file's blob is when it is missing under its recorded stored name
+/// Looked up by ID as a legacy fallback, for files stored before names were recorded exactly
+async fn legacy_stored_name(storage: &StorageService, file: &File) -> Result<Option<String>> {
+    let Some(file_id) = file.id.as_deref() else {
+        return Ok(None);
+    };
+    match storage.find_legacy_blob(file_id, &file.original_name).await? {
+        Some(stored_name) if stored_name != file.stored_name => {
+            eprintln!("⚠️  File {} found under legacy name {} instead of {}", file_id, stored_name, file.stored_name);
+            Ok(Some(stored_name))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Tenant that a user's blobs are stored under when tenant isolation is enabled
+fn tenant_for_user(user_id: i64) -> String {
+    format!("user-{}", user_id)
+}
+
+/// Deserialize a set of JSON rows into `File` records
+fn files_from_rows(json_rows: &[serde_json::Value]) -> Result<Vec<File>> {
+    json_rows.iter()
+        .map(|json| File::from_json(json)
+            .map_err(|e| StorageError::StorageError(format!("Deserialization error: {}", e))))
+        .collect()
+}
+
+/// Trim a file name and check it can be stored
+fn validate_file_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    let invalid_char = name.chars().any(|c| c.is_control() || c == '/' || c == '\\');
+    if name.is_empty() || name.len() > MAX_FILE_NAME_LENGTH || invalid_char {
+        return Err(StorageError::InvalidInput(format!("Invalid file name: '{}'", name)));
+    }
+    Ok(name.to_string())
+}
+
+/// Normalize a folder path to `a/b/c`, dropping empty segments
+/// Returns `None` for the root
+fn normalize_folder(folder: &str) -> Result<Option<String>> {
+    let segments: Vec<&str> = folder.split('/').map(str::trim).filter(|s| !s.is_empty()).collect();
+    if segments.is_empty() {
+        return Ok(None);
+    }
+
+    for segment in &segments {
+        if *segment == "." || *segment == ".." || segment.chars().any(|c| c.is_control() || c == '\\') {
+            return Err(StorageError::InvalidInput(format!("Invalid folder name: '{}'", segment)));
+        }
+    }
+
+    let folder = segments.join("/");
+    if folder.len() > MAX_FOLDER_LENGTH {
+        return Err(StorageError::InvalidInput(format!(
+            "Folder path is longer than {} bytes",
+            MAX_FOLDER_LENGTH
+        )));
+    }
+    Ok(Some(folder))
+}
+
+/// Escape LIKE wildcards so user input is matched literally
+fn escape_like(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(c, '%' | '_' | '!') {
+            escaped.push('!');
         }
+        escaped.push(c);
     }
+    escaped
 }
@@ -0,0 +1,221 @@
+//! Polling ingestion from a remote SFTP directory
+//!
+//! For partners that can only deliver files over SFTP: the remote directory is polled on an
+//! interval, every complete file found there is streamed into `TransactionalStorageService`
+//! under a configured owner, and the remote copy is then moved to a processed directory or
+//! deleted.
+
+use crate::{Result, StorageError, TransactionalStorageService};
+use ssh2::{HashType, Session, Sftp};
+use std::io::Write;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::spawn_blocking;
+use tokio_util::io::SyncIoBridge;
+
+/// Buffer between the blocking SFTP download and the async storage write
+const TRANSFER_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How to authenticate against the SFTP server
+#[derive(Debug, Clone)]
+pub enum SftpAuth {
+    Password(String),
+    PrivateKey {
+        path: PathBuf,
+        passphrase: Option<String>,
+    },
+}
+
+/// Settings for polling a remote SFTP directory
+#[derive(Debug, Clone)]
+pub struct SftpSourceConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub auth: SftpAuth,
+    /// Hex-encoded SHA-256 fingerprint of the server's host key; connections to any other key are refused
+    pub host_fingerprint: String,
+    /// Remote directory to poll (non-recursively)
+    pub remote_dir: String,
+    /// Remote directory ingested files are moved to; they are deleted when unset
+    pub processed_dir: Option<String>,
+    /// User that ingested files are registered under
+    pub owner_user_id: i64,
+    pub poll_interval: Duration,
+}
+
+/// Running ingestion loop; stops polling when dropped
+pub struct SftpIngestor {
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl SftpIngestor {
+    /// Start polling the remote directory
+    pub fn start(service: Arc<TransactionalStorageService>, config: SftpSourceConfig) -> Self {
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config.poll_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+                if let Err(e) = poll_once(&service, &config).await {
+                    eprintln!("✗ SFTP ingestion from {} failed: {}", config.host, e);
+                }
+            }
+        });
+
+        Self { task }
+    }
+}
+
+impl Drop for SftpIngestor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Ingest every complete file currently in the remote directory
+async fn poll_once(service: &TransactionalStorageService, config: &SftpSourceConfig) -> Result<()> {
+    let connect_config = config.clone();
+    let sftp = Arc::new(blocking(move || connect(&connect_config)).await?);
+
+    let list_sftp = Arc::clone(&sftp);
+    let remote_dir = PathBuf::from(&config.remote_dir);
+    let entries = blocking(move || list_remote_files(&list_sftp, &remote_dir)).await?;
+
+    for (remote_path, name) in entries {
+        if let Err(e) = ingest_file(service, config, &sftp, &remote_path, &name).await {
+            eprintln!("✗ Failed to ingest {}: {}", remote_path.display(), e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Stream one remote file into storage, then move or delete the remote copy
+async fn ingest_file(
+    service: &TransactionalStorageService,
+    config: &SftpSourceConfig,
+    sftp: &Arc<Sftp>,
+    remote_path: &Path,
+    name: &str,
+) -> Result<()> {
+    let (reader, writer) = tokio::io::duplex(TRANSFER_BUFFER_SIZE);
+
+    let download_sftp = Arc::clone(sftp);
+    let download_path = remote_path.to_path_buf();
+    let download = spawn_blocking(move || -> std::io::Result<()> {
+        let mut remote = download_sftp.open(&download_path)?;
+        let mut bridge = SyncIoBridge::new(writer);
+        std::io::copy(&mut remote, &mut bridge)?;
+        bridge.flush()?;
+        bridge.shutdown()
+    });
+
+    let stored = service.store_stream_with_metadata(reader, name, config.owner_user_id, None).await;
+    let downloaded = download.await
+        .map_err(|e| StorageError::StorageError(format!("SFTP download task failed: {}", e)))?;
+
+    let file = stored?;
+    if let Err(e) = downloaded {
+        // The reader hit EOF early, so what was stored is truncated
+        let file_id = file.id.unwrap_or_default();
+        let _ = service.delete_with_metadata(&file_id, config.owner_user_id).await;
+        return Err(e.into());
+    }
+
+    let finish_sftp = Arc::clone(sftp);
+    let finish_path = remote_path.to_path_buf();
+    let processed_path = config.processed_dir.as_ref().map(|dir| Path::new(dir).join(name));
+    blocking(move || match processed_path {
+        Some(target) => finish_sftp.rename(&finish_path, &target, None).map_err(Into::into),
+        None => finish_sftp.unlink(&finish_path).map_err(Into::into),
+    }).await?;
+
+    println!("📥 Ingested {} from SFTP as {}", name, file.id.unwrap_or_default());
+    Ok(())
+}
+
+/// Open an authenticated SFTP session
+fn connect(config: &SftpSourceConfig) -> std::io::Result<Sftp> {
+    let tcp = TcpStream::connect((config.host.as_str(), config.port))?;
+
+    let mut session = Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.handshake()?;
+
+    // Credentials are only sent once the host key is the pinned one
+    let actual = session.host_key_hash(HashType::Sha256)
+        .map(hex_encode)
+        .unwrap_or_default();
+    if actual.is_empty() || !actual.eq_ignore_ascii_case(config.host_fingerprint.trim()) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("host key fingerprint {} does not match", actual),
+        ));
+    }
+
+    match &config.auth {
+        SftpAuth::Password(password) => session.userauth_password(&config.username, password)?,
+        SftpAuth::PrivateKey { path, passphrase } => {
+            session.userauth_pubkey_file(&config.username, None, path, passphrase.as_deref())?
+        }
+    }
+
+    Ok(session.sftp()?)
+}
+
+/// Regular files in the remote directory that look complete, as (path, file name)
+fn list_remote_files(sftp: &Sftp, remote_dir: &Path) -> std::io::Result<Vec<(PathBuf, String)>> {
+    Ok(sftp.readdir(remote_dir)?
+        .into_iter()
+        .filter(|(_, stat)| stat.is_file())
+        .filter_map(|(path, _)| {
+            let name = path.file_name()?.to_str()?.to_string();
+            is_complete_upload(&name).then_some((path, name))
+        })
+        .collect())
+}
+
+/// Whether a remote file name looks like a finished upload
+/// Hidden and temporary names are skipped; partners upload under those and rename when done
+fn is_complete_upload(name: &str) -> bool {
+    !(name.starts_with('.') || name.ends_with('~') || name.ends_with(".part") || name.ends_with(".tmp"))
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Run a blocking SFTP operation on the blocking thread pool
+async fn blocking<T, F>(operation: F) -> Result<T>
+where
+    F: FnOnce() -> std::io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_blocking(operation)
+        .await
+        .map_err(|e| StorageError::StorageError(format!("SFTP task failed: {}", e)))?
+        .map_err(|e| StorageError::Transient(format!("SFTP error: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_complete_upload() {
+        assert!(is_complete_upload("invoice-2024-10.csv"));
+        assert!(!is_complete_upload(".invoice.csv"));
+        assert!(!is_complete_upload("invoice.csv.part"));
+        assert!(!is_complete_upload("invoice.csv.tmp"));
+        assert!(!is_complete_upload("invoice.csv~"));
+    }
+
+    #[test]
+    fn test_hex_encode() {
+        assert_eq!(hex_encode(&[0x00, 0xab, 0xff]), "00abff");
+    }
+}
@@ -0,0 +1,119 @@
+//! Public share links for files
+
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::Serialize;
+
+/// Random bytes in a share token (hex-encoded, so tokens are twice as long)
+pub const SHARE_TOKEN_BYTES: usize = 24;
+
+/// A public link granting unauthenticated read access to one file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileShare {
+    pub token: String,
+    pub file_id: String,
+    /// Owner who created the link
+    pub user_id: i64,
+    /// Hash of the password required to open the link, if any
+    #[serde(skip)]
+    pub password_hash: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl FileShare {
+    /// Create a share link with a fresh random token
+    pub fn new(file_id: String, user_id: i64, password_hash: Option<String>, expires_at: Option<DateTime<Utc>>) -> Self {
+        Self {
+            token: generate_share_token(),
+            file_id,
+            user_id,
+            password_hash,
+            expires_at,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Whether opening the link requires a password
+    pub fn has_password(&self) -> bool {
+        self.password_hash.is_some()
+    }
+
+    /// Whether the link has expired at `at`
+    pub fn is_expired(&self, at: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= at)
+    }
+
+    /// Read a share from a database row
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        let string = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+        let timestamp = |column: &str| {
+            row.get(column)
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+
+        Some(Self {
+            token: string("token")?,
+            file_id: string("file_id")?,
+            user_id: row.get("user_id").and_then(|v| v.as_i64())?,
+            password_hash: string("password_hash"),
+            expires_at: timestamp("expires_at"),
+            created_at: timestamp("created_at").unwrap_or_else(Utc::now),
+        })
+    }
+}
+
+/// Generate an unguessable, URL-safe share token
+pub fn generate_share_token() -> String {
+    let mut bytes = [0u8; SHARE_TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_share_token() {
+        let token = generate_share_token();
+        assert_eq!(token.len(), SHARE_TOKEN_BYTES * 2);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, generate_share_token());
+    }
+
+    #[test]
+    fn test_share_expiry() {
+        let now = Utc::now();
+        let mut share = FileShare::new("file-1".to_string(), 1, None, None);
+        assert!(!share.is_expired(now));
+        assert!(!share.has_password());
+
+        share.expires_at = Some(now + Duration::hours(1));
+        assert!(!share.is_expired(now));
+        assert!(share.is_expired(now + Duration::hours(1)));
+    }
+
+    #[test]
+    fn test_share_from_json() {
+        let row = serde_json::json!({
+            "token": "abc123",
+            "file_id": "file-1",
+            "user_id": 7,
+            "password_hash": "$argon2id$v=19$...",
+            "expires_at": null,
+            "created_at": "2025-10-18T00:00:00+00:00"
+        });
+
+        let share = FileShare::from_json(&row).unwrap();
+        assert_eq!(share.token, "abc123");
+        assert_eq!(share.user_id, 7);
+        assert!(share.has_password());
+        assert!(share.expires_at.is_none());
+
+        assert!(FileShare::from_json(&serde_json::json!({"token": "abc123"})).is_none());
+    }
+}
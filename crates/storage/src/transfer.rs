@@ -0,0 +1,62 @@
+//! Handing files over to another user
+//!
+//! The owner of a file offers it to another user, who becomes its owner once they accept. The
+//! file then counts toward the new owner's storage limits and its blob moves to their storage
+//! if it differs. Share links, invitations and permissions were granted by the previous owner,
+//! so they lapse with the transfer.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Table of transfers waiting for their recipient
+pub const FILE_TRANSFERS_TABLE: &str = "file_transfers";
+
+/// An offer of a file's ownership to another user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileTransfer {
+    pub id: i64,
+    pub file_id: String,
+    /// Owner of the file when the transfer was offered
+    pub from_user_id: i64,
+    /// User who becomes the owner on accepting
+    pub to_user_id: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl FileTransfer {
+    /// Read a transfer from a database row
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        Some(Self {
+            id: row.get("id").and_then(|v| v.as_i64())?,
+            file_id: row.get("file_id").and_then(|v| v.as_str())?.to_string(),
+            from_user_id: row.get("from_user_id").and_then(|v| v.as_i64())?,
+            to_user_id: row.get("to_user_id").and_then(|v| v.as_i64())?,
+            created_at: row.get("created_at")
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(Utc::now),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_from_json() {
+        let row = serde_json::json!({
+            "id": 2,
+            "file_id": "file-1",
+            "from_user_id": 7,
+            "to_user_id": 9,
+            "created_at": "2025-10-18T00:00:00+00:00"
+        });
+
+        let transfer = FileTransfer::from_json(&row).unwrap();
+        assert_eq!(transfer.from_user_id, 7);
+        assert_eq!(transfer.to_user_id, 9);
+        assert!(FileTransfer::from_json(&serde_json::json!({"id": 2, "file_id": "file-1"})).is_none());
+    }
+}
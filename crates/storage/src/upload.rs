@@ -0,0 +1,154 @@
+//! Resumable uploads
+//!
+//! A large file can be sent in chunks over several requests. The session (how many bytes have
+//! arrived and which blob holds them) lives in the database and the bytes in a pending blob, so
+//! an upload survives restarts and can continue on any instance that shares the database and
+//! storage. Chunks are written in order; a chunk may be resent, but not skip ahead of the bytes
+//! already received.
+
+use chrono::{DateTime, Duration, SubsecRound, Utc};
+use serde::Serialize;
+
+use crate::PendingBlob;
+
+/// How long an unfinished upload may take before it is abandoned and its bytes removed
+pub const UPLOAD_SESSION_TTL_SECONDS: i64 = 86_400;
+
+/// An upload in progress
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UploadSession {
+    pub upload_id: String,
+    pub user_id: i64,
+    pub original_name: String,
+    pub mime_type: Option<String>,
+    /// Size of the complete file, declared when the upload starts
+    pub total_size: i64,
+    /// Bytes received so far; the next chunk starts here
+    pub received_bytes: i64,
+    /// Hex SHA-256 the complete file must have, if the client declared one
+    pub checksum: Option<String>,
+    /// Name the blob gets once the upload completes
+    #[serde(skip)]
+    pub stored_name: String,
+    /// When the pending blob holding the received bytes was created
+    #[serde(skip)]
+    pub blob_created_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl UploadSession {
+    /// Start an upload of `total_size` bytes into a blob named `stored_name`
+    pub fn new(
+        upload_id: String,
+        user_id: i64,
+        original_name: String,
+        mime_type: Option<String>,
+        total_size: i64,
+        checksum: Option<String>,
+        stored_name: String,
+    ) -> Self {
+        // Pending blob names carry their creation time in milliseconds
+        let created_at = Utc::now().trunc_subsecs(3);
+        Self {
+            upload_id,
+            user_id,
+            original_name,
+            mime_type,
+            total_size,
+            received_bytes: 0,
+            checksum,
+            stored_name,
+            blob_created_at: created_at,
+            created_at,
+            updated_at: created_at,
+            expires_at: created_at + Duration::seconds(UPLOAD_SESSION_TTL_SECONDS),
+        }
+    }
+
+    /// Every byte has arrived
+    pub fn is_complete(&self) -> bool {
+        self.received_bytes >= self.total_size
+    }
+
+    pub fn is_expired(&self, at: DateTime<Utc>) -> bool {
+        self.expires_at <= at
+    }
+
+    /// The pending blob holding the received bytes, in `tenant`'s scope
+    pub fn pending_blob(&self, tenant: Option<&str>) -> PendingBlob {
+        PendingBlob {
+            tenant: tenant.map(str::to_string),
+            stored_name: self.stored_name.clone(),
+            created_at: self.blob_created_at,
+        }
+    }
+
+    /// Read a session from a database row
+    pub(crate) fn from_json(row: &serde_json::Value) -> Option<Self> {
+        let string = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+        let timestamp = |column: &str| {
+            row.get(column)
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+
+        Some(Self {
+            upload_id: string("upload_id")?,
+            user_id: row.get("user_id").and_then(|v| v.as_i64())?,
+            original_name: string("original_name")?,
+            mime_type: string("mime_type"),
+            total_size: row.get("total_size").and_then(|v| v.as_i64())?,
+            received_bytes: row.get("received_bytes").and_then(|v| v.as_i64()).unwrap_or(0),
+            checksum: string("checksum"),
+            stored_name: string("stored_name")?,
+            blob_created_at: timestamp("blob_created_at")?,
+            created_at: timestamp("created_at")?,
+            updated_at: timestamp("updated_at")?,
+            expires_at: timestamp("expires_at")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_session_roundtrip() {
+        let session = UploadSession::new(
+            "upload-1".to_string(),
+            7,
+            "video.mp4".to_string(),
+            Some("video/mp4".to_string()),
+            1024,
+            None,
+            "abc.mp4".to_string(),
+        );
+        assert!(!session.is_complete());
+        assert_eq!(session.expires_at - session.created_at, Duration::days(1));
+
+        let row = serde_json::json!({
+            "upload_id": session.upload_id,
+            "user_id": 7,
+            "original_name": "video.mp4",
+            "mime_type": "video/mp4",
+            "total_size": 1024,
+            "received_bytes": 0,
+            "checksum": null,
+            "stored_name": "abc.mp4",
+            "blob_created_at": session.blob_created_at.to_rfc3339(),
+            "created_at": session.created_at.to_rfc3339(),
+            "updated_at": session.updated_at.to_rfc3339(),
+            "expires_at": session.expires_at.to_rfc3339(),
+        });
+        assert_eq!(UploadSession::from_json(&row), Some(session.clone()));
+
+        // The pending blob is found again from the row alone
+        let blob = session.pending_blob(Some("user-7"));
+        assert_eq!(blob.created_at, session.blob_created_at);
+        assert_eq!(blob.stored_name, "abc.mp4");
+    }
+}
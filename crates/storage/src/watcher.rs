@@ -0,0 +1,172 @@
+//! Filesystem watcher that ingests externally dropped files
+//!
+//! For deployments where files also arrive via rsync, SFTP, or other tools writing straight
+//! into a directory: every file that appears there is stored through
+//! `TransactionalStorageService` under a configured owner, then removed from the drop directory.
+
+use crate::{FileIdStrategy, Result, StorageError, TransactionalStorageService};
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::mpsc;
+
+/// Settings for the drop-directory watcher
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    /// Directory to watch (non-recursively)
+    pub path: PathBuf,
+    /// User that ingested files are registered under
+    pub owner_user_id: i64,
+    /// How long a file's size must stay unchanged before it is considered fully written
+    pub settle_delay: Duration,
+}
+
+/// Running watcher; stops watching when dropped
+pub struct StorageWatcher {
+    _watcher: RecommendedWatcher,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl StorageWatcher {
+    /// Start watching the configured directory
+    /// Files already present when the watcher starts are ingested too
+    pub async fn start(service: Arc<TransactionalStorageService>, config: WatchConfig) -> Result<Self> {
+        let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+
+        let event_tx = tx.clone();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            if let Ok(event) = res {
+                let relevant = matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)) | EventKind::Modify(ModifyKind::Data(_))
+                );
+                if relevant {
+                    for path in event.paths {
+                        let _ = event_tx.send(path);
+                    }
+                }
+            }
+        })
+        .map_err(|e| StorageError::StorageError(format!("Failed to create watcher: {}", e)))?;
+
+        watcher.watch(&config.path, RecursiveMode::NonRecursive)
+            .map_err(|e| StorageError::StorageError(format!("Failed to watch {}: {}", config.path.display(), e)))?;
+
+        // Pick up files dropped while the server was down
+        let mut entries = fs::read_dir(&config.path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let _ = tx.send(entry.path());
+        }
+
+        let id_strategy = service.id_strategy();
+        let task = tokio::spawn(async move {
+            let in_flight: Arc<Mutex<HashSet<PathBuf>>> = Arc::new(Mutex::new(HashSet::new()));
+
+            while let Some(path) = rx.recv().await {
+                if !is_external_drop(&path, id_strategy) || !in_flight.lock().unwrap().insert(path.clone()) {
+                    continue;
+                }
+
+                let service = Arc::clone(&service);
+                let in_flight = Arc::clone(&in_flight);
+                let config = config.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = ingest_when_settled(&service, &path, &config).await {
+                        eprintln!("✗ Failed to ingest {}: {}", path.display(), e);
+                    }
+                    in_flight.lock().unwrap().remove(&path);
+                });
+            }
+        });
+
+        Ok(Self {
+            _watcher: watcher,
+            task,
+        })
+    }
+}
+
+impl Drop for StorageWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Whether a path looks like a file dropped by an external process
+///
+/// Skips hidden and partial files (rsync/SFTP temp files) and blobs written by the storage
+/// service itself, whose names start with a generated file ID (or a UUID, for blobs stored
+/// before the ID strategy was changed).
+fn is_external_drop(path: &Path, id_strategy: FileIdStrategy) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+
+    if name.starts_with('.') || name.ends_with('~') || name.ends_with(".part") || name.ends_with(".tmp") {
+        return false;
+    }
+
+    let stem = name.split('.').next().unwrap_or(name);
+    !id_strategy.is_generated_id(stem) && !FileIdStrategy::UuidV4.is_generated_id(stem)
+}
+
+/// Wait until the file stops growing, then store it and remove the dropped copy
+async fn ingest_when_settled(service: &TransactionalStorageService, path: &Path, config: &WatchConfig) -> Result<()> {
+    let mut last_size = None;
+    loop {
+        let metadata = match fs::metadata(path).await {
+            Ok(metadata) => metadata,
+            // Moved away or already ingested
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if !metadata.is_file() {
+            return Ok(());
+        }
+        if last_size == Some(metadata.len()) {
+            break;
+        }
+        last_size = Some(metadata.len());
+        tokio::time::sleep(config.settle_delay).await;
+    }
+
+    let original_name = path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unnamed")
+        .to_string();
+    let dropped = fs::File::open(path).await?;
+
+    let file = service.store_stream_with_metadata(dropped, &original_name, config.owner_user_id, None).await?;
+    fs::remove_file(path).await?;
+
+    println!("📥 Ingested external file {} as {}", original_name, file.id.unwrap_or_default());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_external_drop() {
+        let uuid = FileIdStrategy::UuidV4;
+        assert!(is_external_drop(Path::new("/drop/report.pdf"), uuid));
+        assert!(is_external_drop(Path::new("/drop/README"), uuid));
+
+        assert!(!is_external_drop(Path::new("/drop/.report.pdf.Xy12ab"), uuid));
+        assert!(!is_external_drop(Path::new("/drop/report.pdf.part"), uuid));
+        assert!(!is_external_drop(Path::new("/drop/report.pdf~"), uuid));
+        assert!(!is_external_drop(Path::new("/drop/550e8400-e29b-41d4-a716-446655440000.pdf"), uuid));
+        assert!(!is_external_drop(Path::new("/drop/550e8400-e29b-41d4-a716-446655440000"), uuid));
+
+        // Blobs named by other strategies, and UUID-named blobs from before a switch
+        let ulid = FileIdStrategy::Ulid;
+        assert!(!is_external_drop(Path::new("/drop/01JAB3Q9W8X6E4YQ9R7KZ2M5VD.pdf"), ulid));
+        assert!(!is_external_drop(Path::new("/drop/550e8400-e29b-41d4-a716-446655440000.pdf"), ulid));
+        assert!(is_external_drop(Path::new("/drop/report.pdf"), ulid));
+    }
+}
@@ -0,0 +1,496 @@
+//! Webhooks on file events
+//!
+//! Every configured endpoint subscribed to an event gets a delivery, recorded in the delivery log
+//! before it is sent. Payloads are signed like Stripe's: the `X-Projectkit-Signature` header holds
+//! `t=<unix time>,v1=<hex HMAC-SHA256 of "<t>.<body>">` keyed with the endpoint's secret.
+//! Failed deliveries are retried with exponential backoff until they run out of attempts.
+
+use crate::{File, Result, StorageError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use orm::prelude::*;
+use orm::query::QueryValue;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+
+/// Table logging every webhook delivery and its attempts
+pub const WEBHOOK_DELIVERIES_TABLE: &str = "webhook_deliveries";
+
+/// Header carrying the payload signature
+pub const SIGNATURE_HEADER: &str = "X-Projectkit-Signature";
+
+/// Header naming the event
+pub const EVENT_HEADER: &str = "X-Projectkit-Event";
+
+/// Header carrying the event ID, the same for every retry of a delivery
+pub const DELIVERY_HEADER: &str = "X-Projectkit-Delivery";
+
+/// Default number of attempts before a delivery is given up
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Default delay before the first retry; doubled for each later one
+pub const DEFAULT_RETRY_BASE_SECONDS: u64 = 30;
+
+/// How long a delivery being sent is hidden from other workers
+const SENDING_LEASE_SECONDS: i64 = 300;
+
+/// Most deliveries sent by one pass
+const DELIVERY_BATCH: u64 = 100;
+
+/// Something that happened to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileEvent {
+    #[serde(rename = "file.created")]
+    Created,
+    #[serde(rename = "file.deleted")]
+    Deleted,
+    #[serde(rename = "file.downloaded")]
+    Downloaded,
+}
+
+impl FileEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileEvent::Created => "file.created",
+            FileEvent::Deleted => "file.deleted",
+            FileEvent::Downloaded => "file.downloaded",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "file.created" => Some(FileEvent::Created),
+            "file.deleted" => Some(FileEvent::Deleted),
+            "file.downloaded" => Some(FileEvent::Downloaded),
+            _ => None,
+        }
+    }
+}
+
+/// A URL receiving webhooks
+#[derive(Debug, Clone)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// Key signing the payloads sent to this endpoint
+    pub secret: String,
+    /// Events sent to the endpoint; every event when empty
+    pub events: Vec<FileEvent>,
+}
+
+impl WebhookEndpoint {
+    fn wants(&self, event: FileEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// State of a delivery
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Waiting for its first attempt or a retry
+    Pending,
+    /// The endpoint answered with a 2xx status
+    Delivered,
+    /// Every attempt failed
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "pending" => Some(DeliveryStatus::Pending),
+            "delivered" => Some(DeliveryStatus::Delivered),
+            "failed" => Some(DeliveryStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// One event sent to one endpoint, as kept in the delivery log
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    /// ID of the event, shared by its deliveries to every endpoint
+    pub event_id: String,
+    pub event: FileEvent,
+    pub url: String,
+    /// JSON body sent to the endpoint
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// HTTP status of the last attempt, if the endpoint answered
+    pub response_status: Option<u16>,
+    pub last_error: Option<String>,
+    /// When the next attempt is due, while pending
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl WebhookDelivery {
+    fn from_json(row: &serde_json::Value) -> Option<Self> {
+        let string = |column: &str| row.get(column).and_then(|v| v.as_str()).map(str::to_string);
+        let timestamp = |column: &str| {
+            string(column)
+                .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+
+        Some(Self {
+            id: row.get("id").and_then(|v| v.as_i64())?,
+            event_id: string("event_id")?,
+            event: FileEvent::from_str(&string("event")?)?,
+            url: string("url")?,
+            payload: string("payload").unwrap_or_default(),
+            status: DeliveryStatus::from_str(&string("status")?)?,
+            attempts: row.get("attempts").and_then(|v| v.as_i64()).unwrap_or(0) as u32,
+            response_status: row.get("response_status").and_then(|v| v.as_i64()).map(|s| s as u16),
+            last_error: string("last_error"),
+            next_attempt_at: timestamp("next_attempt_at"),
+            created_at: timestamp("created_at").unwrap_or_else(Utc::now),
+            delivered_at: timestamp("delivered_at"),
+        })
+    }
+}
+
+/// Sends webhook requests
+#[async_trait]
+pub trait WebhookTransport: Send + Sync {
+    /// POST `body` to `url`, returning the response status, or an error if there was no response
+    async fn post(&self, url: &str, headers: &[(&str, String)], body: &[u8]) -> std::result::Result<u16, String>;
+}
+
+/// Sends webhooks over HTTP(S)
+#[cfg(feature = "webhooks")]
+pub struct HttpTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "webhooks")]
+impl HttpTransport {
+    /// Requests taking longer than `timeout` count as failed attempts
+    pub fn new(timeout: std::time::Duration) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| StorageError::StorageError(format!("Failed to build HTTP client: {}", e)))?;
+        Ok(Self { client })
+    }
+}
+
+#[cfg(feature = "webhooks")]
+#[async_trait]
+impl WebhookTransport for HttpTransport {
+    async fn post(&self, url: &str, headers: &[(&str, String)], body: &[u8]) -> std::result::Result<u16, String> {
+        let mut request = self.client.post(url).body(body.to_vec());
+        for (name, value) in headers {
+            request = request.header(*name, value);
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        Ok(response.status().as_u16())
+    }
+}
+
+/// Signature header value for a payload sent at `timestamp`
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let signature: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("t={},v1={}", timestamp, signature)
+}
+
+/// Delay before retrying after `attempts` failed attempts
+fn retry_delay(base: std::time::Duration, attempts: u32) -> chrono::Duration {
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+    chrono::Duration::from_std(base.saturating_mul(factor)).unwrap_or(chrono::Duration::days(1))
+}
+
+/// Records and delivers webhooks to the configured endpoints
+pub struct Webhooks {
+    db: Database,
+    endpoints: Vec<WebhookEndpoint>,
+    transport: Arc<dyn WebhookTransport>,
+    max_attempts: u32,
+    retry_base: std::time::Duration,
+}
+
+impl Webhooks {
+    pub fn new(db: Database, endpoints: Vec<WebhookEndpoint>, transport: Arc<dyn WebhookTransport>) -> Self {
+        Self {
+            db,
+            endpoints,
+            transport,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retry_base: std::time::Duration::from_secs(DEFAULT_RETRY_BASE_SECONDS),
+        }
+    }
+
+    /// Give up on a delivery after `max_attempts`, waiting `base`, then twice as long, and so on between them
+    pub fn with_retries(mut self, max_attempts: u32, base: std::time::Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.retry_base = base;
+        self
+    }
+
+    pub fn endpoints(&self) -> &[WebhookEndpoint] {
+        &self.endpoints
+    }
+
+    /// Log a delivery of `event` to every endpoint subscribed to it
+    /// `details` is merged into the payload's `data` next to the file
+    ///
+    /// # Returns
+    /// The number of deliveries queued
+    pub async fn emit(&self, event: FileEvent, file: &File, details: serde_json::Value) -> Result<u64> {
+        let endpoints: Vec<_> = self.endpoints.iter().filter(|e| e.wants(event)).collect();
+        if endpoints.is_empty() {
+            return Ok(0);
+        }
+
+        let event_id = uuid::Uuid::new_v4().to_string();
+        let now = Utc::now();
+        let mut data = serde_json::json!({ "file": file });
+        if let (Some(data), Some(details)) = (data.as_object_mut(), details.as_object()) {
+            data.extend(details.clone());
+        }
+        let payload = serde_json::json!({
+            "id": event_id,
+            "type": event.as_str(),
+            "created_at": now.to_rfc3339(),
+            "data": data,
+        })
+        .to_string();
+
+        let backend = self.db.backend();
+        let sql = format!(
+            "INSERT INTO {} (event_id, event, url, payload, status, attempts, next_attempt_at, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6, ?6)",
+            WEBHOOK_DELIVERIES_TABLE
+        );
+        for endpoint in &endpoints {
+            backend.execute(&sql, &[
+                QueryValue::String(event_id.clone()),
+                QueryValue::String(event.as_str().to_string()),
+                QueryValue::String(endpoint.url.clone()),
+                QueryValue::String(payload.clone()),
+                QueryValue::String(DeliveryStatus::Pending.as_str().to_string()),
+                QueryValue::String(now.to_rfc3339()),
+            ]).await
+                .map_err(|e| StorageError::StorageError(format!("Database insert failed: {}", e)))?;
+        }
+
+        Ok(endpoints.len() as u64)
+    }
+
+    /// Attempt every pending delivery that is due
+    ///
+    /// # Returns
+    /// The number of deliveries that succeeded
+    pub async fn deliver_due(&self) -> Result<u64> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "SELECT * FROM {} WHERE status = ?1 AND next_attempt_at <= ?2 ORDER BY id LIMIT {}",
+            WEBHOOK_DELIVERIES_TABLE, DELIVERY_BATCH
+        );
+        let rows = backend.fetch_all_params(&sql, &[
+            QueryValue::String(DeliveryStatus::Pending.as_str().to_string()),
+            QueryValue::String(Utc::now().to_rfc3339()),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        let mut delivered = 0;
+        for delivery in rows.iter().filter_map(WebhookDelivery::from_json) {
+            if self.attempt(&delivery).await? {
+                delivered += 1;
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// Number of deliveries waiting for an attempt
+    pub async fn pending_count(&self) -> Result<u64> {
+        let backend = self.db.backend();
+        let sql = format!("SELECT COUNT(*) as count FROM {} WHERE status = ?1", WEBHOOK_DELIVERIES_TABLE);
+        let row = backend.fetch_one_params(&sql, &[QueryValue::String(DeliveryStatus::Pending.as_str().to_string())]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+        Ok(row.and_then(|row| row.get("count").and_then(|v| v.as_i64())).unwrap_or(0) as u64)
+    }
+
+    /// Send one delivery, recording the outcome
+    /// Returns whether the endpoint accepted it; a delivery claimed by another worker is skipped
+    async fn attempt(&self, delivery: &WebhookDelivery) -> Result<bool> {
+        let backend = self.db.backend();
+        let now = Utc::now();
+
+        // Claim the delivery: hide it from other workers until the attempt is recorded
+        let claim_sql = format!(
+            "UPDATE {} SET attempts = attempts + 1, next_attempt_at = ?1 \
+             WHERE id = ?2 AND status = ?3 AND attempts = ?4",
+            WEBHOOK_DELIVERIES_TABLE
+        );
+        let claimed = backend.execute(&claim_sql, &[
+            QueryValue::String((now + chrono::Duration::seconds(SENDING_LEASE_SECONDS)).to_rfc3339()),
+            QueryValue::I64(delivery.id),
+            QueryValue::String(DeliveryStatus::Pending.as_str().to_string()),
+            QueryValue::I64(delivery.attempts as i64),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+        if claimed == 0 {
+            return Ok(false);
+        }
+        let attempts = delivery.attempts + 1;
+
+        let outcome = match self.endpoints.iter().find(|e| e.url == delivery.url) {
+            Some(endpoint) => {
+                let body = delivery.payload.as_bytes();
+                let headers = [
+                    ("Content-Type", "application/json".to_string()),
+                    (EVENT_HEADER, delivery.event.as_str().to_string()),
+                    (DELIVERY_HEADER, delivery.event_id.clone()),
+                    (SIGNATURE_HEADER, sign_payload(&endpoint.secret, now.timestamp(), body)),
+                ];
+                self.transport.post(&delivery.url, &headers, body).await
+            }
+            None => Err("endpoint is no longer configured".to_string()),
+        };
+
+        let (accepted, response_status, error) = match outcome {
+            Ok(code) if (200..300).contains(&code) => (true, Some(code), None),
+            Ok(code) => (false, Some(code), Some(format!("endpoint answered {}", code))),
+            Err(e) => (false, None, Some(e)),
+        };
+        let (status, next_attempt_at) = if accepted {
+            (DeliveryStatus::Delivered, None)
+        } else if attempts >= self.max_attempts {
+            (DeliveryStatus::Failed, None)
+        } else {
+            (DeliveryStatus::Pending, Some(now + retry_delay(self.retry_base, attempts)))
+        };
+        let delivered_at = (status == DeliveryStatus::Delivered).then_some(now);
+
+        let optional = |value: Option<String>| value.map(QueryValue::String).unwrap_or(QueryValue::Null);
+        let sql = format!(
+            "UPDATE {} SET status = ?1, response_status = ?2, last_error = ?3, next_attempt_at = ?4, delivered_at = ?5 WHERE id = ?6",
+            WEBHOOK_DELIVERIES_TABLE
+        );
+        backend.execute(&sql, &[
+            QueryValue::String(status.as_str().to_string()),
+            response_status.map(|s| QueryValue::I64(s as i64)).unwrap_or(QueryValue::Null),
+            optional(error),
+            optional(next_attempt_at.map(|at| at.to_rfc3339())),
+            optional(delivered_at.map(|at| at.to_rfc3339())),
+            QueryValue::I64(delivery.id),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+
+        Ok(status == DeliveryStatus::Delivered)
+    }
+
+    /// Most recent deliveries first, optionally only those in one state
+    pub async fn list_deliveries(&self, status: Option<DeliveryStatus>, limit: u64) -> Result<Vec<WebhookDelivery>> {
+        let backend = self.db.backend();
+        let rows = match status {
+            Some(status) => {
+                let sql = format!(
+                    "SELECT * FROM {} WHERE status = ?1 ORDER BY id DESC LIMIT {}",
+                    WEBHOOK_DELIVERIES_TABLE, limit
+                );
+                backend.fetch_all_params(&sql, &[QueryValue::String(status.as_str().to_string())]).await
+            }
+            None => {
+                let sql = format!("SELECT * FROM {} ORDER BY id DESC LIMIT {}", WEBHOOK_DELIVERIES_TABLE, limit);
+                backend.fetch_all_params(&sql, &[]).await
+            }
+        }
+        .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?;
+
+        Ok(rows.iter().filter_map(WebhookDelivery::from_json).collect())
+    }
+
+    /// Queue a failed delivery to be sent again, with a fresh set of attempts
+    pub async fn redeliver(&self, delivery_id: i64) -> Result<WebhookDelivery> {
+        let backend = self.db.backend();
+        let sql = format!(
+            "UPDATE {} SET status = ?1, attempts = 0, next_attempt_at = ?2 WHERE id = ?3 AND status = ?4",
+            WEBHOOK_DELIVERIES_TABLE
+        );
+        let updated = backend.execute(&sql, &[
+            QueryValue::String(DeliveryStatus::Pending.as_str().to_string()),
+            QueryValue::String(Utc::now().to_rfc3339()),
+            QueryValue::I64(delivery_id),
+            QueryValue::String(DeliveryStatus::Failed.as_str().to_string()),
+        ]).await
+            .map_err(|e| StorageError::StorageError(format!("Database update failed: {}", e)))?;
+
+        let sql = format!("SELECT * FROM {} WHERE id = ?1", WEBHOOK_DELIVERIES_TABLE);
+        let delivery = backend.fetch_one_params(&sql, &[QueryValue::I64(delivery_id)]).await
+            .map_err(|e| StorageError::StorageError(format!("Database error: {}", e)))?
+            .as_ref()
+            .and_then(WebhookDelivery::from_json)
+            .ok_or_else(|| StorageError::FileNotFound(format!("webhook delivery {}", delivery_id)))?;
+
+        if updated == 0 {
+            return Err(StorageError::PreconditionFailed(format!(
+                "Delivery {} is {}; only failed deliveries can be redelivered",
+                delivery_id,
+                delivery.status.as_str()
+            )));
+        }
+        Ok(delivery)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_names() {
+        for event in [FileEvent::Created, FileEvent::Deleted, FileEvent::Downloaded] {
+            assert_eq!(FileEvent::from_str(event.as_str()), Some(event));
+            assert_eq!(serde_json::to_value(event).unwrap(), event.as_str());
+        }
+        assert_eq!(FileEvent::from_str("file.updated"), None);
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        let signature = sign_payload("whsec", 1700000000, b"{}");
+        let (timestamp, v1) = signature.split_once(',').unwrap();
+        assert_eq!(timestamp, "t=1700000000");
+        assert_eq!(v1.len(), "v1=".len() + 64);
+        assert_eq!(signature, sign_payload("whsec", 1700000000, b"{}"));
+        assert_ne!(signature, sign_payload("other", 1700000000, b"{}"));
+        assert_ne!(signature, sign_payload("whsec", 1700000001, b"{}"));
+    }
+
+    #[test]
+    fn test_retry_delay_doubles() {
+        let base = std::time::Duration::from_secs(30);
+        assert_eq!(retry_delay(base, 1), chrono::Duration::seconds(30));
+        assert_eq!(retry_delay(base, 2), chrono::Duration::seconds(60));
+        assert_eq!(retry_delay(base, 4), chrono::Duration::seconds(240));
+    }
+
+    #[test]
+    fn test_endpoint_subscriptions() {
+        let all = WebhookEndpoint { url: "https://a".to_string(), secret: "s".to_string(), events: vec![] };
+        let created = WebhookEndpoint { events: vec![FileEvent::Created], ..all.clone() };
+        assert!(all.wants(FileEvent::Downloaded));
+        assert!(created.wants(FileEvent::Created));
+        assert!(!created.wants(FileEvent::Deleted));
+    }
+}
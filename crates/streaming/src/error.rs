@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StreamingError {
+    /// The broker could not be reached or refused the connection
+    #[error("Connection error: {0}")]
+    ConnectionError(String),
+
+    /// The broker did not accept a message
+    #[error("Publish error: {0}")]
+    PublishError(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+}
+
+pub type Result<T> = std::result::Result<T, StreamingError>;
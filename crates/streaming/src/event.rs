@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use storage::StorageEvent;
+
+/// What changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ChangeKind {
+    /// A file was stored, by an upload, a copy, or ingestion
+    #[serde(rename = "file.uploaded")]
+    FileUploaded,
+    /// A file was trashed or permanently removed
+    #[serde(rename = "file.deleted")]
+    FileDeleted,
+    /// A record was inserted through `POST /db/{table}`
+    #[serde(rename = "record.created")]
+    RecordCreated,
+}
+
+impl ChangeKind {
+    pub const ALL: [ChangeKind; 3] = [ChangeKind::FileUploaded, ChangeKind::FileDeleted, ChangeKind::RecordCreated];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::FileUploaded => "file.uploaded",
+            ChangeKind::FileDeleted => "file.deleted",
+            ChangeKind::RecordCreated => "record.created",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == s)
+    }
+}
+
+/// A change published to the message queues, serialized as the message body
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    /// Unique per event, so consumers can drop redeliveries
+    pub id: String,
+    pub kind: ChangeKind,
+    pub occurred_at: DateTime<Utc>,
+    /// Event-specific details
+    pub data: serde_json::Value,
+}
+
+impl ChangeEvent {
+    pub fn new(kind: ChangeKind, data: serde_json::Value) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind,
+            occurred_at: Utc::now(),
+            data,
+        }
+    }
+
+    /// The change behind an in-process storage event
+    pub fn from_storage(event: &StorageEvent) -> Self {
+        match event {
+            StorageEvent::Uploaded { file, copied_from } => Self::new(
+                ChangeKind::FileUploaded,
+                serde_json::json!({ "file": file, "copied_from": copied_from }),
+            ),
+            StorageEvent::Deleted { file, permanent } => Self::new(
+                ChangeKind::FileDeleted,
+                serde_json::json!({ "file": file, "permanent": permanent }),
+            ),
+        }
+    }
+
+    /// A record inserted into `table` by `user_id`, as sent in the request
+    pub fn record_created(table: &str, user_id: i64, record: serde_json::Value) -> Self {
+        Self::new(
+            ChangeKind::RecordCreated,
+            serde_json::json!({ "table": table, "user_id": user_id, "record": record }),
+        )
+    }
+
+    /// Subject, topic, or routing key the event is published under, e.g. `projectkit.file.uploaded`
+    pub fn subject(&self, prefix: &str) -> String {
+        format!("{}.{}", prefix, self.kind.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_kind_roundtrip() {
+        for kind in ChangeKind::ALL {
+            assert_eq!(ChangeKind::from_str(kind.as_str()), Some(kind));
+            assert_eq!(serde_json::to_value(kind).unwrap(), kind.as_str());
+        }
+        assert_eq!(ChangeKind::from_str("file.downloaded"), None);
+    }
+
+    #[test]
+    fn test_from_storage() {
+        let file = storage::File::new(
+            "abc".to_string(),
+            7,
+            "report.pdf".to_string(),
+            "abc.pdf".to_string(),
+            1024,
+            Some("application/pdf".to_string()),
+            "./storage".to_string(),
+            None,
+        );
+
+        let event = ChangeEvent::from_storage(&StorageEvent::Deleted { file, permanent: true });
+        assert_eq!(event.kind, ChangeKind::FileDeleted);
+        assert_eq!(event.data["file"]["id"], "abc");
+        assert_eq!(event.data["permanent"], true);
+        assert_eq!(event.subject("projectkit"), "projectkit.file.deleted");
+
+        let event = ChangeEvent::record_created("notes", 7, serde_json::json!({ "title": "Hi" }));
+        assert_eq!(event.data["table"], "notes");
+        assert_eq!(event.data["record"]["title"], "Hi");
+    }
+}
@@ -0,0 +1,183 @@
+//! Change events for message queues
+//!
+//! Publishes storage and database changes to NATS, Kafka, or RabbitMQ (each behind a crate
+//! feature), so larger systems can consume them in their existing streaming infrastructure
+//! instead of polling webhooks. Publishing is best effort and at most once: events are not
+//! persisted, and an event a broker rejects is logged and dropped. Use webhooks when every
+//! event has to arrive.
+
+mod error;
+
+pub mod event;
+pub mod publisher;
+
+pub use error::{Result, StreamingError};
+pub use event::{ChangeEvent, ChangeKind};
+#[cfg(feature = "kafka")]
+pub use publisher::KafkaPublisher;
+#[cfg(feature = "nats")]
+pub use publisher::NatsPublisher;
+pub use publisher::Publisher;
+#[cfg(feature = "rabbitmq")]
+pub use publisher::RabbitMqPublisher;
+
+use std::sync::Arc;
+use storage::StorageEvent;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Default prefix of subjects, topics, and routing keys
+pub const DEFAULT_SUBJECT_PREFIX: &str = "projectkit";
+
+/// Sends change events to every configured publisher
+pub struct EventStream {
+    publishers: Vec<Arc<dyn Publisher>>,
+    subject_prefix: String,
+    /// Kinds that are published; every kind when empty
+    kinds: Vec<ChangeKind>,
+}
+
+impl EventStream {
+    pub fn new(publishers: Vec<Arc<dyn Publisher>>) -> Self {
+        Self {
+            publishers,
+            subject_prefix: DEFAULT_SUBJECT_PREFIX.to_string(),
+            kinds: Vec::new(),
+        }
+    }
+
+    /// Publish under `<prefix>.<kind>` instead of `projectkit.<kind>`
+    pub fn with_subject_prefix(mut self, prefix: &str) -> Self {
+        self.subject_prefix = prefix.to_string();
+        self
+    }
+
+    /// Only publish events of these kinds
+    pub fn with_kinds(mut self, kinds: Vec<ChangeKind>) -> Self {
+        self.kinds = kinds;
+        self
+    }
+
+    /// Names of the configured brokers
+    pub fn publisher_names(&self) -> Vec<&'static str> {
+        self.publishers.iter().map(|publisher| publisher.name()).collect()
+    }
+
+    fn wants(&self, kind: ChangeKind) -> bool {
+        self.kinds.is_empty() || self.kinds.contains(&kind)
+    }
+
+    /// Send an event to every publisher
+    /// Every publisher is tried; the first failure is returned
+    pub async fn publish(&self, event: &ChangeEvent) -> Result<()> {
+        if !self.wants(event.kind) {
+            return Ok(());
+        }
+
+        let payload = serde_json::to_vec(event)
+            .map_err(|e| StreamingError::PublishError(e.to_string()))?;
+        let subject = event.subject(&self.subject_prefix);
+
+        let mut result = Ok(());
+        for publisher in &self.publishers {
+            if let Err(e) = publisher.publish(&subject, &payload).await {
+                if result.is_ok() {
+                    result = Err(StreamingError::PublishError(format!("{}: {}", publisher.name(), e)));
+                }
+            }
+        }
+        result
+    }
+
+    /// Publish an event in the background, logging failures
+    /// Used from request paths, which must never wait on or fail because of a broker
+    pub fn publish_in_background(self: &Arc<Self>, event: ChangeEvent) {
+        let stream = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = stream.publish(&event).await {
+                eprintln!("⚠️  Failed to stream {} event: {}", event.kind.as_str(), e);
+            }
+        });
+    }
+
+    /// Publish every storage event received on `events` until the channel closes
+    pub fn relay_storage_events(self: &Arc<Self>, mut events: broadcast::Receiver<StorageEvent>) -> tokio::task::JoinHandle<()> {
+        let stream = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        let event = ChangeEvent::from_storage(&event);
+                        if let Err(e) = stream.publish(&event).await {
+                            eprintln!("⚠️  Failed to stream {} event: {}", event.kind.as_str(), e);
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        eprintln!("⚠️  Event streaming fell behind and dropped {} storage events", missed);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder {
+        sent: Mutex<Vec<(String, serde_json::Value)>>,
+    }
+
+    #[async_trait]
+    impl Publisher for Recorder {
+        async fn publish(&self, subject: &str, payload: &[u8]) -> Result<()> {
+            let body = serde_json::from_slice(payload).unwrap();
+            self.sent.lock().unwrap().push((subject.to_string(), body));
+            Ok(())
+        }
+
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+    }
+
+    struct Broken;
+
+    #[async_trait]
+    impl Publisher for Broken {
+        async fn publish(&self, _subject: &str, _payload: &[u8]) -> Result<()> {
+            Err(StreamingError::PublishError("broker down".to_string()))
+        }
+
+        fn name(&self) -> &'static str {
+            "broken"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish() {
+        let recorder = Arc::new(Recorder::default());
+        let stream = EventStream::new(vec![Arc::new(Broken), recorder.clone()])
+            .with_subject_prefix("acme")
+            .with_kinds(vec![ChangeKind::RecordCreated]);
+
+        // A failing broker doesn't keep the others from receiving the event
+        let event = ChangeEvent::record_created("notes", 1, serde_json::json!({ "title": "Hi" }));
+        assert!(stream.publish(&event).await.is_err());
+
+        let sent = recorder.sent.lock().unwrap().clone();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, "acme.record.created");
+        assert_eq!(sent[0].1["kind"], "record.created");
+        assert_eq!(sent[0].1["data"]["table"], "notes");
+
+        // Filtered kinds are skipped
+        let event = ChangeEvent::new(ChangeKind::FileDeleted, serde_json::json!({}));
+        stream.publish(&event).await.unwrap();
+        assert_eq!(recorder.sent.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use activity::ActivityService;
+use announcements::AnnouncementService;
+use api::{router::router, AppState};
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use comments::CommentService;
+use testing::TestDatabase;
+use tower::ServiceExt;
+
+/// The API router over `db`, with every feature enabled
+async fn app(db: &TestDatabase) -> Router {
+    let state = AppState::new(
+        db.connect().await,
+        db.auth_service().await,
+        db.storage_service().await,
+        AnnouncementService::new(db.connect().await),
+        CommentService::new(db.connect().await),
+        ActivityService::new(db.connect().await),
+    );
+    router(Arc::new(state))
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: &str) -> StatusCode {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn test_admin_route_permissions() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let app = app(&db).await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let (token, _) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+
+    assert_eq!(send(&app, "GET", "/admin/roles", &token).await, StatusCode::FORBIDDEN);
+
+    auth.put_role("role-admin", None, &["roles:*".to_string()]).await.unwrap();
+    auth.assign_role(alice, "role-admin").await.unwrap();
+    assert_eq!(send(&app, "GET", "/admin/roles", &token).await, StatusCode::OK);
+    // The role grants nothing outside `roles:`
+    assert_eq!(send(&app, "GET", "/admin/jobs", &token).await, StatusCode::FORBIDDEN);
+    assert_eq!(send(&app, "DELETE", "/admin/files/1", &token).await, StatusCode::FORBIDDEN);
+
+    auth.unassign_role(alice, "role-admin").await.unwrap();
+    assert_eq!(send(&app, "GET", "/admin/roles", &token).await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_purge_requires_sudo() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let app = app(&db).await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let (token, _) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+    auth.put_role("janitor", None, &["files:purge".to_string()]).await.unwrap();
+    auth.assign_role(alice, "janitor").await.unwrap();
+
+    // Holding the permission isn't enough without a sudo token
+    let request = Request::builder()
+        .method("DELETE")
+        .uri("/admin/files/1")
+        .header("Authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("X-Sudo-Token"));
+}
+
+#[tokio::test]
+async fn test_avatar_must_be_own_image() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let storage = db.storage_service().await;
+    let app = app(&db).await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let (token, _) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    let bobs = storage.store_with_metadata(png, "bob.png", bob, None).await.unwrap().id.unwrap();
+    let notes = storage.store_with_metadata(b"notes", "notes.txt", alice, None).await.unwrap().id.unwrap();
+    let own = storage.store_with_metadata(png, "alice.png", alice, None).await.unwrap().id.unwrap();
+
+    let set_avatar = |file_id: String| {
+        Request::builder()
+            .method("PATCH")
+            .uri("/auth/profile")
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::json!({ "avatar_file_id": file_id }).to_string()))
+            .unwrap()
+    };
+    // Another user's image, and a file that isn't an image, are refused
+    let response = app.clone().oneshot(set_avatar(bobs)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = app.clone().oneshot(set_avatar(notes)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(auth.profile(alice).await.unwrap().avatar_file_id, None);
+
+    let response = app.clone().oneshot(set_avatar(own.clone())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(auth.profile(alice).await.unwrap().avatar_file_id, Some(own));
+}
@@ -0,0 +1,1718 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use activity::{ActivityEvent, ActivityKind, ActivityService, EventFanout, LocalFanout};
+use billing::{ApiLimits, BillingService, Plan, Tenant};
+use auth::{AuthError, EmailTokenPurpose, GeoLocation, GeoLookup, NotificationKind, NotificationPreferences, NotificationPreferencesUpdate, Profile, ProfileUpdate};
+use orm::query::QueryValue;
+use storage::archive::{extract_zip, write_zip};
+use storage::{sha256_hex, BucketCredentials, BucketHealth, DeliveryStatus, ExportFormat, FileAccess, FileEvent, FileListOptions, FileSearchResults, FileSort, IntegrityStatus, JournalRecovery, LimitsProvider, MetadataUpdate, MimeTypeUsage, MigrationProgress, OrganizationRole, PolicyRule, SizeMismatch, StorageError, StorageEvent, StorageLimits, StorageMigrator, StorageService, TextExtraction, TransactionalStorageService, UploadPolicy, UserBuckets, WebhookEndpoint, WebhookTransport, Webhooks};
+use testing::TestDatabase;
+
+#[tokio::test]
+async fn test_auth_service() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+
+    let user = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap();
+    assert!(user.id.is_some());
+    assert!(auth.signup("alice@example.com", "another-password").await.is_err());
+
+    let (token, logged_in) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+    assert_eq!(logged_in.id, user.id);
+    assert_eq!(auth.validate(&token).await.unwrap().email, "alice@example.com");
+    assert!(auth.login("alice@example.com", "wrong-password").await.is_err());
+}
+
+#[tokio::test]
+async fn test_refresh_tokens() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    auth.signup("alice@example.com", "correct-horse-battery").await.unwrap();
+    let (_, user) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+    let first = auth.issue_refresh_token(&user).await.unwrap();
+    let other_login = auth.issue_refresh_token(&user).await.unwrap();
+
+    let (token, second, refreshed) = auth.refresh(&first).await.unwrap();
+    assert_eq!(refreshed.id, user.id);
+    assert_ne!(second, first);
+    assert_eq!(auth.validate(&token).await.unwrap().email, "alice@example.com");
+    assert!(matches!(auth.refresh("not-a-token").await, Err(AuthError::InvalidToken)));
+
+    // Replaying a used token revokes the tokens that replaced it, but not other logins
+    assert!(matches!(auth.refresh(&first).await, Err(AuthError::InvalidToken)));
+    assert!(matches!(auth.refresh(&second).await, Err(AuthError::InvalidToken)));
+    assert!(auth.refresh(&other_login).await.is_ok());
+
+    let expired = db.auth_service().await.with_refresh_token_expiry_seconds(0);
+    let token = expired.issue_refresh_token(&user).await.unwrap();
+    assert!(matches!(expired.refresh(&token).await, Err(AuthError::TokenExpired)));
+}
+
+#[tokio::test]
+async fn test_email_tokens() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let user = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap();
+    assert!(!user.is_email_verified());
+
+    // Only the most recent reset link works, and only once
+    let stale = auth.issue_email_token(&user, EmailTokenPurpose::PasswordReset).await.unwrap();
+    let reset = auth.issue_email_token(&user, EmailTokenPurpose::PasswordReset).await.unwrap();
+    assert!(matches!(auth.reset_password(&stale, "new-password").await, Err(AuthError::InvalidToken)));
+    auth.reset_password(&reset, "battery-staple-horse").await.unwrap();
+    assert!(matches!(auth.reset_password(&reset, "again").await, Err(AuthError::InvalidToken)));
+    assert!(auth.login("alice@example.com", "correct-horse-battery").await.is_err());
+    assert!(auth.login("alice@example.com", "battery-staple-horse").await.is_ok());
+
+    // Tokens only serve the purpose they were issued for
+    let verification = auth.issue_email_token(&user, EmailTokenPurpose::EmailVerification).await.unwrap();
+    assert!(auth.login_with_magic_link(&verification, None).await.is_err());
+    assert!(auth.verify_email(&verification).await.unwrap().is_email_verified());
+    assert!(auth.active_user_by_id(user.id.unwrap()).await.unwrap().unwrap().is_email_verified());
+
+    let magic_link = auth.issue_email_token(&user, EmailTokenPurpose::MagicLink).await.unwrap();
+    let (token, logged_in, _) = auth.login_with_magic_link(&magic_link, None).await.unwrap();
+    assert_eq!(logged_in.id, user.id);
+    assert_eq!(auth.validate(&token).await.unwrap().email, "alice@example.com");
+    assert!(auth.login_with_magic_link(&magic_link, None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_verified_email_required() {
+    let db = TestDatabase::new().await;
+    let open = db.auth_service().await;
+    let alice = open.signup("alice@example.com", "correct-horse-battery").await.unwrap();
+    let bob = open.signup("bob@example.com", "correct-horse-battery").await.unwrap();
+    let (token, _) = open.login("alice@example.com", "correct-horse-battery").await.unwrap();
+
+    let auth = db.auth_service().await.with_verified_email_required(true);
+    assert!(matches!(
+        auth.login("alice@example.com", "correct-horse-battery").await,
+        Err(AuthError::EmailNotVerified)
+    ));
+    // Tokens issued before verification was required stop working too
+    assert!(matches!(auth.validate(&token).await, Err(AuthError::EmailNotVerified)));
+    // A wrong password gives nothing away
+    assert!(matches!(auth.login("alice@example.com", "wrong-password").await, Err(AuthError::InvalidPassword)));
+
+    let verification = auth.issue_email_token(&alice, EmailTokenPurpose::EmailVerification).await.unwrap();
+    auth.verify_email(&verification).await.unwrap();
+    assert!(auth.login("alice@example.com", "correct-horse-battery").await.is_ok());
+    assert!(auth.validate(&token).await.is_ok());
+
+    // Opening a magic link proves the address as well
+    let magic_link = auth.issue_email_token(&bob, EmailTokenPurpose::MagicLink).await.unwrap();
+    let (_, logged_in, _) = auth.login_with_magic_link(&magic_link, None).await.unwrap();
+    assert!(logged_in.is_email_verified());
+    assert!(auth.login("bob@example.com", "correct-horse-battery").await.is_ok());
+}
+
+#[tokio::test]
+async fn test_change_password() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    auth.signup("alice@example.com", "correct-horse-battery").await.unwrap();
+    let (old_token, user) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+    let refresh_token = auth.issue_refresh_token(&user).await.unwrap();
+
+    assert!(matches!(
+        auth.change_password(&user, "wrong-password", "battery-staple-horse").await,
+        Err(AuthError::InvalidPassword)
+    ));
+
+    // Tokens are dated to the second, so the change has to come in a later one
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let (token, changed) = auth.change_password(&user, "correct-horse-battery", "battery-staple-horse").await.unwrap();
+    assert!(changed.password_changed_at.is_some());
+    assert!(auth.validate(&old_token).await.is_err());
+    assert!(matches!(auth.refresh(&refresh_token).await, Err(AuthError::InvalidToken)));
+    assert_eq!(auth.validate(&token).await.unwrap().email, "alice@example.com");
+    assert!(auth.login("alice@example.com", "correct-horse-battery").await.is_err());
+    assert!(auth.login("alice@example.com", "battery-staple-horse").await.is_ok());
+}
+
+#[tokio::test]
+async fn test_email_change() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    auth.signup("alice@example.com", "correct-horse-battery").await.unwrap();
+    auth.signup("bob@example.com", "correct-horse-battery").await.unwrap();
+    let (old_token, user) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+
+    assert!(matches!(
+        auth.request_email_change(&user, "wrong-password", "ada@example.com").await,
+        Err(AuthError::InvalidPassword)
+    ));
+    for invalid in ["Alice@example.com", "not-an-address"] {
+        assert!(matches!(
+            auth.request_email_change(&user, "correct-horse-battery", invalid).await,
+            Err(AuthError::InvalidInput(_))
+        ));
+    }
+    // Another account's address gets no token, and no error that would give the account away
+    assert_eq!(auth.request_email_change(&user, "correct-horse-battery", "bob@example.com").await.unwrap(), None);
+
+    let token = auth.request_email_change(&user, "correct-horse-battery", " ada@example.com").await.unwrap().unwrap();
+    // Nothing changes until the new address is confirmed
+    assert_eq!(auth.validate(&old_token).await.unwrap().email, "alice@example.com");
+    assert!(matches!(auth.verify_email(&token).await, Err(AuthError::InvalidToken)));
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let (changed, old_email) = auth.confirm_email_change(&token).await.unwrap();
+    assert_eq!(old_email, "alice@example.com");
+    assert_eq!(changed.email, "ada@example.com");
+    assert!(changed.is_email_verified());
+    assert!(auth.validate(&old_token).await.is_err());
+    assert!(matches!(auth.confirm_email_change(&token).await, Err(AuthError::InvalidToken)));
+    assert!(auth.login("alice@example.com", "correct-horse-battery").await.is_err());
+    let (token, _) = auth.login("ada@example.com", "correct-horse-battery").await.unwrap();
+    assert_eq!(auth.validate(&token).await.unwrap().email, "ada@example.com");
+}
+
+#[tokio::test]
+async fn test_custom_roles() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let (token, _) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+    assert!(!auth.validate(&token).await.unwrap().has_permission("files:delete"));
+
+    assert!(matches!(auth.put_role("service", None, &[]).await, Err(AuthError::InvalidInput(_))));
+    assert!(matches!(
+        auth.put_role("moderator", None, &["files".to_string()]).await,
+        Err(AuthError::InvalidInput(_))
+    ));
+    let permissions = vec!["files:*".to_string(), "comments:delete".to_string(), "files:*".to_string()];
+    let role = auth.put_role("moderator", Some("Cleans up after users"), &permissions).await.unwrap();
+    assert_eq!(role.permissions, vec!["comments:delete".to_string(), "files:*".to_string()]);
+
+    assert!(matches!(auth.assign_role(alice, "missing").await, Err(AuthError::RoleNotFound(_))));
+    auth.assign_role(alice, "moderator").await.unwrap();
+    auth.assign_role(alice, "moderator").await.unwrap();
+    assert_eq!(auth.roles_of(alice).await.unwrap(), vec!["moderator".to_string()]);
+    let user = auth.validate(&token).await.unwrap();
+    assert!(user.has_permission("files:delete"));
+    assert!(user.has_permission("comments:delete"));
+    assert!(!user.has_permission("comments:edit"));
+
+    // Changes apply to existing tokens straight away
+    auth.put_role("moderator", None, &["comments:delete".to_string()]).await.unwrap();
+    assert!(!auth.validate(&token).await.unwrap().has_permission("files:delete"));
+    auth.delete_role("moderator").await.unwrap();
+    assert!(auth.validate(&token).await.unwrap().permissions.is_empty());
+    assert!(auth.list_roles().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_signing_key_rotation() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let other_instance = db.auth_service().await;
+    auth.signup("alice@example.com", "correct-horse-battery").await.unwrap();
+    let (old_token, _) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+
+    let rotation = auth.rotate_signing_key(chrono::Duration::zero(), chrono::Duration::hours(1)).await.unwrap();
+    assert_eq!(rotation.retiring.len(), 1);
+    assert!(rotation.retiring[0].configured);
+
+    // Tokens signed by the old key keep working, and are re-signed with the new one
+    assert!(auth.validate(&old_token).await.is_ok());
+    let renewed = auth.renew_token(&old_token).await.unwrap();
+    assert_ne!(renewed, old_token);
+    assert!(auth.validate(&renewed).await.is_ok());
+    assert!(auth.renew_token(&renewed).await.is_none());
+
+    // Other instances accept the new key once they reload
+    assert!(other_instance.validate(&renewed).await.is_err());
+    other_instance.reload_signing_keys().await.unwrap();
+    assert!(other_instance.validate(&renewed).await.is_ok());
+
+    // The new key is stored encrypted
+    let stored = db.connect().await.backend()
+        .fetch_one_params("SELECT secret FROM signing_keys WHERE kid = ?1", &[QueryValue::String(rotation.key.kid.clone())])
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(stored["secret"].as_str().unwrap().starts_with("enc:"));
+
+    // Rotating again with no window retires the key straight away
+    auth.rotate_signing_key(chrono::Duration::zero(), chrono::Duration::zero()).await.unwrap();
+    assert!(auth.validate(&renewed).await.is_err());
+    assert!(auth.validate(&old_token).await.is_ok());
+    assert_eq!(auth.prune_signing_keys().await.unwrap(), 1);
+    let (token, _) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+    assert!(auth.validate(&token).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_storage_service() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let storage = db.storage_service().await;
+
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+
+    let file = storage
+        .store_with_metadata(b"hello, world", "hello.txt", alice, Some("text/plain".to_string()))
+        .await
+        .unwrap();
+    let file_id = file.id.clone().unwrap();
+    assert_eq!(file.size, 12);
+
+    assert_eq!(storage.retrieve_with_permission(&file_id, alice).await.unwrap(), b"hello, world");
+    assert!(storage.retrieve_with_permission(&file_id, bob).await.is_err());
+    assert_eq!(storage.list_user_files(alice).await.unwrap().len(), 1);
+    assert!(storage.list_user_files(bob).await.unwrap().is_empty());
+
+    storage.delete_with_metadata(&file_id, alice).await.unwrap();
+    assert!(storage.get_file_by_id(&file_id).await.unwrap().is_none());
+    assert_eq!(storage.list_trashed_files(alice).await.unwrap().len(), 1);
+
+    storage.purge_file(&file_id, alice).await.unwrap();
+    assert!(matches!(
+        storage.restore_file(&file_id, alice).await,
+        Err(StorageError::FileNotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_download_counts() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let storage = db.storage_service().await;
+
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+
+    let file = storage.store_with_metadata(b"hello", "hello.txt", alice, None).await.unwrap();
+    let file_id = file.id.clone().unwrap();
+    assert_eq!(file.download_count, 0);
+    assert!(file.last_accessed_at.is_none());
+
+    storage.retrieve_with_permission(&file_id, alice).await.unwrap();
+    storage.retrieve_with_permission(&file_id, alice).await.unwrap();
+    // Refused downloads don't count
+    assert!(storage.retrieve_with_permission(&file_id, bob).await.is_err());
+
+    let file = storage.get_file_by_id(&file_id).await.unwrap().unwrap();
+    assert_eq!(file.download_count, 2);
+    assert!(file.last_accessed_at.is_some());
+
+    let share = storage.create_share(&file_id, alice, None, None).await.unwrap();
+    let (share, shared) = storage.resolve_share(&share.token).await.unwrap();
+    storage.retrieve_shared(&share, &shared).await.unwrap();
+    assert_eq!(storage.get_file_by_id(&file_id).await.unwrap().unwrap().download_count, 3);
+
+    let copy = storage.copy_with_metadata(&file_id, alice, None).await.unwrap();
+    assert_eq!(copy.download_count, 0);
+}
+
+#[tokio::test]
+async fn test_retrieve_with_metadata() {
+    use tokio::io::AsyncReadExt;
+
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let storage = db.storage_service().await;
+
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+
+    let stored = storage.store_with_metadata(b"streamed bytes", "notes.txt", alice, None).await.unwrap();
+    let file_id = stored.id.clone().unwrap();
+
+    let (file, mut reader) = storage.retrieve_with_metadata(&file_id, alice).await.unwrap();
+    assert_eq!(file.original_name, "notes.txt");
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await.unwrap();
+    assert_eq!(data, b"streamed bytes");
+
+    assert!(matches!(storage.retrieve_with_metadata(&file_id, bob).await, Err(StorageError::PermissionDenied(_))));
+    assert!(matches!(storage.retrieve_with_metadata("missing", alice).await, Err(StorageError::FileNotFound(_))));
+    assert_eq!(storage.get_file_by_id(&file_id).await.unwrap().unwrap().download_count, 1);
+}
+
+#[tokio::test]
+async fn test_share_invitations() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let storage = db.storage_service().await;
+
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let file = storage.store_with_metadata(b"minutes", "minutes.txt", alice, None).await.unwrap();
+    let file_id = file.id.clone().unwrap();
+
+    // Bob has no account yet, so the invitation waits for him
+    let invitation = storage.invite_to_file(&file_id, alice, "Bob@Example.com", None).await.unwrap();
+    assert!(invitation.is_pending());
+    assert_eq!(invitation.email, "bob@example.com");
+    let again = storage.invite_to_file(&file_id, alice, "bob@example.com", None).await.unwrap();
+    assert_eq!(again.id, invitation.id);
+    assert!(matches!(storage.invite_to_file(&file_id, alice, "not an address", None).await, Err(StorageError::InvalidInput(_))));
+    assert!(storage.has_pending_invitation(" BOB@example.com").await.unwrap());
+    assert!(!storage.has_pending_invitation("carol@example.com").await.unwrap());
+
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+    assert!(storage.retrieve_with_metadata(&file_id, bob).await.is_err());
+
+    let accepted = storage.accept_invitations("bob@example.com", bob).await.unwrap();
+    assert_eq!(accepted.len(), 1);
+    assert_eq!(accepted[0].recipient_id, Some(bob));
+    assert!(storage.accept_invitations("bob@example.com", bob).await.unwrap().is_empty());
+    assert!(!storage.has_pending_invitation("bob@example.com").await.unwrap());
+
+    let shared = storage.files_shared_with(bob).await.unwrap();
+    assert_eq!(shared.len(), 1);
+    assert_eq!(shared[0].id.as_deref(), Some(file_id.as_str()));
+    assert!(storage.retrieve_with_metadata(&file_id, bob).await.is_ok());
+
+    // Only the owner manages invitations; revoking one takes the file away again
+    assert!(storage.list_invitations(&file_id, bob).await.unwrap().is_empty());
+    storage.revoke_invitation(&file_id, invitation.id, alice).await.unwrap();
+    assert!(storage.files_shared_with(bob).await.unwrap().is_empty());
+    assert!(matches!(storage.retrieve_with_metadata(&file_id, bob).await, Err(StorageError::PermissionDenied(_))));
+}
+
+#[tokio::test]
+async fn test_file_permissions() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let storage = db.storage_service().await;
+
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+    let file = storage.store_with_metadata(b"draft", "draft.txt", alice, None).await.unwrap();
+    let file_id = file.id.clone().unwrap();
+
+    assert!(matches!(storage.retrieve_with_permission(&file_id, bob).await, Err(StorageError::PermissionDenied(_))));
+    assert!(matches!(storage.grant_permission(&file_id, bob, bob, FileAccess::Read).await, Err(StorageError::PermissionDenied(_))));
+    assert!(matches!(storage.grant_permission(&file_id, alice, alice, FileAccess::Read).await, Err(StorageError::InvalidInput(_))));
+
+    // Read access lets Bob download the file and see it among his shared files, but not change it
+    storage.grant_permission(&file_id, alice, bob, FileAccess::Read).await.unwrap();
+    assert_eq!(storage.retrieve_with_permission(&file_id, bob).await.unwrap(), b"draft");
+    assert!(storage.retrieve_with_metadata(&file_id, bob).await.is_ok());
+    assert!(storage.list_user_files(bob).await.unwrap().is_empty());
+    let shared = storage.files_shared_with(bob).await.unwrap();
+    assert_eq!(shared.len(), 1);
+    assert_eq!(shared[0].id.as_deref(), Some(file_id.as_str()));
+    let page = storage.files_shared_with_page(bob, &FileListOptions::new(10, 0)).await.unwrap();
+    assert_eq!((page.files.len(), page.total), (1, 1));
+    let images = FileListOptions { mime_type: Some("image/*".to_string()), ..FileListOptions::new(10, 0) };
+    assert_eq!(storage.files_shared_with_page(bob, &images).await.unwrap().total, 0);
+    assert!(matches!(storage.append_with_metadata(&file_id, bob, b" v2", None).await, Err(StorageError::PermissionDenied(_))));
+
+    // Granting again replaces the access
+    let permission = storage.grant_permission(&file_id, alice, bob, FileAccess::ReadWrite).await.unwrap();
+    assert_eq!(permission.access, FileAccess::ReadWrite);
+    assert_eq!(storage.list_permissions(&file_id, alice).await.unwrap().len(), 1);
+    assert!(storage.list_permissions(&file_id, bob).await.is_err());
+
+    let updated = storage.append_with_metadata(&file_id, bob, b" v2", None).await.unwrap();
+    assert_eq!(updated.size, 8);
+    assert_eq!(storage.retrieve_with_permission(&file_id, alice).await.unwrap(), b"draft v2");
+    // Only the owner deletes
+    assert!(matches!(storage.delete_with_metadata(&file_id, bob).await, Err(StorageError::PermissionDenied(_))));
+
+    storage.revoke_permission(&file_id, alice, bob).await.unwrap();
+    assert!(storage.files_shared_with(bob).await.unwrap().is_empty());
+    assert!(matches!(storage.retrieve_with_permission(&file_id, bob).await, Err(StorageError::PermissionDenied(_))));
+    assert!(matches!(storage.revoke_permission(&file_id, alice, bob).await, Err(StorageError::FileNotFound(_))));
+}
+
+#[tokio::test]
+async fn test_co_owners() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let storage = db.storage_service().await;
+
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+    let carol = auth.signup("carol@example.com", "staple-battery-horse").await.unwrap().id.unwrap();
+    let file_id = storage.store_with_metadata(b"plan", "plan.txt", alice, None).await.unwrap().id.unwrap();
+
+    // Co-owners share the file and delete it, but only the owner makes co-owners
+    storage.grant_permission(&file_id, alice, bob, FileAccess::Owner).await.unwrap();
+    storage.grant_permission(&file_id, bob, carol, FileAccess::Read).await.unwrap();
+    assert_eq!(storage.list_permissions(&file_id, bob).await.unwrap().len(), 2);
+    assert!(matches!(storage.grant_permission(&file_id, bob, carol, FileAccess::Owner).await, Err(StorageError::PermissionDenied(_))));
+    assert!(matches!(storage.revoke_permission(&file_id, bob, bob).await, Err(StorageError::PermissionDenied(_))));
+    assert!(matches!(storage.delete_with_metadata(&file_id, carol).await, Err(StorageError::PermissionDenied(_))));
+
+    storage.delete_with_metadata(&file_id, bob).await.unwrap();
+    assert_eq!(storage.list_trashed_files(alice).await.unwrap().len(), 1);
+    storage.restore_file(&file_id, bob).await.unwrap();
+    // The file still counts toward the owner
+    assert_eq!(storage.list_user_files(alice).await.unwrap().len(), 1);
+    assert!(storage.list_user_files(bob).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_file_transfers() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+    let carol = auth.signup("carol@example.com", "staple-battery-horse").await.unwrap().id.unwrap();
+    let blobs = StorageService::in_memory();
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await).with_tenant_isolation(true);
+
+    let file = storage.store_with_metadata(b"contract", "contract.pdf", alice, None).await.unwrap();
+    let file_id = file.id.clone().unwrap();
+    storage.grant_permission(&file_id, alice, carol, FileAccess::Read).await.unwrap();
+    storage.create_share(&file_id, alice, None, None).await.unwrap();
+
+    assert!(matches!(storage.request_transfer(&file_id, bob, carol).await, Err(StorageError::PermissionDenied(_))));
+    assert!(matches!(storage.request_transfer(&file_id, alice, alice).await, Err(StorageError::InvalidInput(_))));
+
+    // Offering the file again replaces the earlier offer, which can no longer be accepted
+    let first = storage.request_transfer(&file_id, alice, carol).await.unwrap();
+    let transfer = storage.request_transfer(&file_id, alice, bob).await.unwrap();
+    assert!(matches!(storage.accept_transfer(first.id, carol).await, Err(StorageError::FileNotFound(_))));
+    assert_eq!(storage.list_transfers(bob).await.unwrap(), vec![transfer.clone()]);
+    assert!(matches!(storage.accept_transfer(transfer.id, alice).await, Err(StorageError::FileNotFound(_))));
+
+    let accepted = storage.accept_transfer(transfer.id, bob).await.unwrap();
+    assert_eq!(accepted.user_id, bob);
+    assert!(storage.list_transfers(bob).await.unwrap().is_empty());
+    assert!(storage.list_user_files(alice).await.unwrap().is_empty());
+    assert_eq!(storage.list_user_files(bob).await.unwrap().len(), 1);
+
+    // The blob moved to Bob's tenant, and whatever Alice granted lapsed
+    assert!(blobs.tenant(&format!("user-{}", bob)).unwrap().exists(&file.stored_name).await);
+    assert!(!blobs.tenant(&format!("user-{}", alice)).unwrap().exists(&file.stored_name).await);
+    assert_eq!(storage.retrieve_with_permission(&file_id, bob).await.unwrap(), b"contract");
+    assert!(storage.retrieve_with_permission(&file_id, alice).await.is_err());
+    assert!(storage.retrieve_with_permission(&file_id, carol).await.is_err());
+    assert!(storage.list_shares(&file_id, alice).await.unwrap().is_empty());
+
+    // Declining removes the offer
+    let back = storage.request_transfer(&file_id, bob, alice).await.unwrap();
+    storage.cancel_transfer(back.id, alice).await.unwrap();
+    assert!(matches!(storage.cancel_transfer(back.id, bob).await, Err(StorageError::FileNotFound(_))));
+}
+
+#[tokio::test]
+async fn test_organizations() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+    let carol = auth.signup("carol@example.com", "staple-battery-horse").await.unwrap().id.unwrap();
+    let limits = StorageLimits { max_files: Some(1), ..Default::default() };
+    let storage = db.storage_service().await.with_limits(Arc::new(FixedLimits(limits)));
+
+    assert!(matches!(storage.create_organization("  ", alice).await, Err(StorageError::InvalidInput(_))));
+    let org = storage.create_organization("Design", alice).await.unwrap();
+    storage.add_member(org.id, alice, bob, OrganizationRole::Member).await.unwrap();
+    assert!(matches!(storage.add_member(org.id, bob, carol, OrganizationRole::Member).await, Err(StorageError::PermissionDenied(_))));
+    assert!(matches!(storage.get_organization(org.id, carol).await, Err(StorageError::FileNotFound(_))));
+    assert_eq!(storage.organizations_for(bob).await.unwrap(), vec![org.clone()]);
+
+    // Members change the organization's files, admins also delete them
+    let file_id = storage.store_with_metadata(b"logo", "logo.svg", alice, None).await.unwrap().id.unwrap();
+    assert!(storage.retrieve_with_permission(&file_id, bob).await.is_err());
+    let moved = storage.set_file_organization(&file_id, alice, Some(org.id)).await.unwrap();
+    assert_eq!(moved.organization_id, Some(org.id));
+    assert_eq!(storage.retrieve_with_permission(&file_id, bob).await.unwrap(), b"logo");
+    storage.append_with_metadata(&file_id, bob, b"!", None).await.unwrap();
+    assert!(matches!(storage.delete_with_metadata(&file_id, bob).await, Err(StorageError::PermissionDenied(_))));
+    assert!(storage.retrieve_with_permission(&file_id, carol).await.is_err());
+    assert_eq!(storage.list_organization_files(org.id, bob).await.unwrap().len(), 1);
+    assert!(matches!(storage.request_transfer(&file_id, alice, bob).await, Err(StorageError::InvalidInput(_))));
+
+    // Without a plan of its own, the organization shares Alice's one-file quota with her own
+    // files, however many organizations she creates
+    assert!(matches!(storage.store_with_metadata(b"notes", "notes.txt", alice, None).await, Err(StorageError::QuotaExceeded(_))));
+    let other = storage.create_organization("Marketing", alice).await.unwrap();
+    storage.set_file_organization(&file_id, alice, Some(other.id)).await.unwrap();
+    storage.set_file_organization(&file_id, alice, Some(org.id)).await.unwrap();
+    assert!(storage.store_with_metadata(b"notes", "notes.txt", bob, None).await.is_ok());
+
+    // Members may leave, the owner may not, and leaving ends access
+    assert!(matches!(storage.remove_member(org.id, alice, alice).await, Err(StorageError::InvalidInput(_))));
+    storage.remove_member(org.id, bob, bob).await.unwrap();
+    assert!(storage.retrieve_with_permission(&file_id, bob).await.is_err());
+    assert_eq!(storage.list_members(org.id, alice).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_organization_plans() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let plans = vec![
+        Plan::new("free", StorageLimits { max_files: Some(1), ..Default::default() }),
+        Plan::new("team", StorageLimits { max_files: Some(3), ..Default::default() })
+            .with_api_limits(ApiLimits { requests_per_month: Some(1000), ..Default::default() }),
+    ];
+    let billing = Arc::new(BillingService::new(db.connect().await, plans, "free".to_string(), "whsec_test".to_string()).unwrap());
+    let storage = db.storage_service().await.with_limits(billing.clone());
+
+    // Without a plan of its own the organization has its owner's limits
+    let org = storage.create_organization("Design", alice).await.unwrap();
+    assert_eq!(billing.plan_for_organization(&org).await.unwrap().name, "free");
+    assert_eq!(billing.tenant_for_organization(&org), Tenant::User(alice));
+    let first = storage.store_with_metadata(b"a", "a.txt", alice, None).await.unwrap().id.unwrap();
+    storage.set_file_organization(&first, alice, Some(org.id)).await.unwrap();
+    // Its files count against Alice's quota
+    assert!(matches!(storage.store_with_metadata(b"b", "b.txt", alice, None).await, Err(StorageError::QuotaExceeded(_))));
+
+    let org = storage.set_organization_plan(org.id, Some("team")).await.unwrap();
+    assert_eq!(org.plan.as_deref(), Some("team"));
+    assert_eq!(billing.plan_for_organization(&org).await.unwrap().name, "team");
+    assert_eq!(billing.tenant_for_organization(&org), Tenant::Organization(org.id));
+    let second = storage.store_with_metadata(b"b", "b.txt", alice, None).await.unwrap().id.unwrap();
+    storage.set_file_organization(&second, alice, Some(org.id)).await.unwrap();
+    assert!(matches!(storage.set_organization_plan(org.id + 1, Some("team")).await, Err(StorageError::FileNotFound(_))));
+
+    // Requests are counted per tenant
+    let tenant = Tenant::Organization(org.id);
+    assert_eq!(billing.record_request(tenant).await.unwrap(), 1);
+    assert_eq!(billing.record_request(tenant).await.unwrap(), 2);
+    assert_eq!(billing.requests_this_month(tenant).await.unwrap(), 2);
+    assert_eq!(billing.requests_this_month(Tenant::User(alice)).await.unwrap(), 0);
+}
+
+/// Gives every user the same limits
+struct FixedLimits(StorageLimits);
+
+#[async_trait::async_trait]
+impl LimitsProvider for FixedLimits {
+    async fn limits_for(&self, _user_id: i64) -> storage::Result<StorageLimits> {
+        Ok(self.0)
+    }
+}
+
+#[tokio::test]
+async fn test_corrupted_download_is_refused_and_recorded() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let blobs = StorageService::in_memory();
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await);
+
+    let stored = storage.store_with_metadata(b"original", "notes.txt", alice, None).await.unwrap();
+    blobs.write_at(&stored.stored_name, 0, b"tampered").await.unwrap();
+
+    // Small files are verified before anything is sent
+    let result = storage.retrieve_with_metadata(stored.id.as_deref().unwrap(), alice).await;
+    assert!(matches!(result, Err(StorageError::ChecksumMismatch(_))));
+    let report = storage.integrity_report().await.unwrap();
+    assert_eq!(report.corrupted, 1);
+    assert_eq!(report.flagged[0].id, stored.id);
+}
+
+#[tokio::test]
+async fn test_archive() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let storage = db.storage_service().await;
+
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+
+    let mut ids = Vec::new();
+    for (name, folder) in [("a.txt", Some("reports")), ("a.txt", Some("reports")), ("b.txt", Some("reports/2025")), ("c.txt", None)] {
+        let file = storage.store_with_metadata(name.as_bytes(), name, alice, None).await.unwrap();
+        let id = file.id.unwrap();
+        if let Some(folder) = folder {
+            let update = MetadataUpdate { folder: Some(folder.to_string()), ..Default::default() };
+            storage.update_metadata(&id, alice, update, None).await.unwrap();
+        }
+        ids.push(id);
+    }
+
+    let entries = storage.archive_entries(alice, &[], Some("reports")).await.unwrap();
+    let mut paths: Vec<_> = entries.iter().map(|entry| entry.path.clone()).collect();
+    paths.sort();
+    assert_eq!(paths, ["2025/b.txt", "a (1).txt", "a.txt"]);
+
+    let mut zip = Vec::new();
+    write_zip(&storage, alice, &entries, &mut zip).await.unwrap();
+    let reader = async_zip::base::read::mem::ZipFileReader::new(zip).await.unwrap();
+    assert_eq!(reader.file().entries().len(), 3);
+    assert_eq!(storage.get_file_by_id(&ids[2]).await.unwrap().unwrap().download_count, 1);
+
+    // Selected files keep their full folder path; duplicates are archived once
+    let selection = [ids[3].clone(), ids[2].clone(), ids[3].clone()];
+    let entries = storage.archive_entries(alice, &selection, None).await.unwrap();
+    let paths: Vec<_> = entries.iter().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(paths, ["c.txt", "reports/2025/b.txt"]);
+
+    // Other users' files, unknown files, and empty folders are refused
+    assert!(matches!(storage.archive_entries(bob, &selection, None).await, Err(StorageError::PermissionDenied(_))));
+    assert!(matches!(storage.archive_entries(alice, &["missing".to_string()], None).await, Err(StorageError::FileNotFound(_))));
+    assert!(matches!(storage.archive_entries(alice, &[], Some("empty")).await, Err(StorageError::InvalidInput(_))));
+}
+
+#[tokio::test]
+async fn test_extract_archive() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let storage = db.storage_service().await;
+
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+
+    let mut ids = Vec::new();
+    for (name, folder) in [("logo.txt", Some("assets/brand")), ("readme.txt", None)] {
+        let id = storage.store_with_metadata(name.as_bytes(), name, alice, None).await.unwrap().id.unwrap();
+        if let Some(folder) = folder {
+            let update = MetadataUpdate { folder: Some(folder.to_string()), ..Default::default() };
+            storage.update_metadata(&id, alice, update, None).await.unwrap();
+        }
+        ids.push(id);
+    }
+    let entries = storage.archive_entries(alice, &ids, None).await.unwrap();
+    let mut zip = Vec::new();
+    write_zip(&storage, alice, &entries, &mut zip).await.unwrap();
+
+    let mut files = extract_zip(&storage, bob, zip.clone()).await.unwrap();
+    files.sort_by(|a, b| a.original_name.cmp(&b.original_name));
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].original_name, "logo.txt");
+    assert_eq!(files[0].folder.as_deref(), Some("assets/brand"));
+    assert_eq!(files[0].user_id, bob);
+    assert_eq!(files[1].folder, None);
+    let data = storage.retrieve_with_permission(files[1].id.as_deref().unwrap(), bob).await.unwrap();
+    assert_eq!(data, b"readme.txt");
+
+    assert!(matches!(extract_zip(&storage, bob, b"not a zip".to_vec()).await, Err(StorageError::InvalidInput(_))));
+
+    // A rejected entry leaves nothing behind
+    let strict = db.storage_service().await.with_upload_policy(UploadPolicy {
+        allowed_extensions: vec!["md".to_string()],
+        ..Default::default()
+    });
+    assert!(matches!(extract_zip(&strict, bob, zip).await, Err(StorageError::PolicyViolation(_))));
+    assert_eq!(storage.list_user_files(bob).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_streamed_upload_over_max_size() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let blobs = StorageService::in_memory();
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await).with_upload_policy(UploadPolicy {
+        max_size: Some(1024),
+        ..Default::default()
+    });
+
+    let data = vec![0u8; 1024];
+    storage.store_stream_with_metadata(&data[..], "fits.bin", alice, None).await.unwrap();
+
+    let data = vec![0u8; 64 * 1024];
+    let result = storage.store_stream_with_metadata(&data[..], "big.bin", alice, None).await;
+    assert!(matches!(result, Err(StorageError::PolicyViolation(violation)) if violation.rule == PolicyRule::MaxSize));
+
+    // The cut-off upload left neither a row nor a blob
+    assert_eq!(storage.list_user_files(alice).await.unwrap().len(), 1);
+    let report = storage.gc(Duration::ZERO, true).await.unwrap();
+    assert!(report.orphaned_blobs.is_empty());
+}
+
+#[tokio::test]
+async fn test_upload_journal_recovery() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let blobs = StorageService::in_memory();
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await);
+    let database = db.connect().await;
+    let journal = |stored_name: String, created_at: &str| {
+        let params = [
+            QueryValue::String(stored_name),
+            QueryValue::I64(alice),
+            QueryValue::String(blobs.location()),
+            QueryValue::String(created_at.to_string()),
+        ];
+        let backend = database.backend();
+        async move {
+            backend.execute(
+                "INSERT INTO upload_journal (stored_name, user_id, storage_path, created_at) VALUES (?1, ?2, ?3, ?4)",
+                &params,
+            ).await.unwrap();
+        }
+    };
+
+    // A settled upload leaves nothing in the journal
+    let kept = storage.store_with_metadata(b"kept", "kept.txt", alice, None).await.unwrap();
+    let report = storage.recover_upload_journal(Duration::ZERO).await.unwrap();
+    assert_eq!(report, JournalRecovery::default());
+
+    // Cut off after the metadata was recorded, and after only the blob was written
+    let crashed = blobs.store(b"crashed", "crashed.txt", None).await.unwrap();
+    journal(kept.stored_name.clone(), "2024-01-01T00:00:00+00:00").await;
+    journal(crashed.stored_name.clone(), "2024-01-01T00:00:00+00:00").await;
+    // Too recent to tell from an upload still in flight
+    let in_flight = blobs.store(b"in flight", "in-flight.txt", None).await.unwrap();
+    journal(in_flight.stored_name.clone(), "2999-01-01T00:00:00+00:00").await;
+
+    let report = storage.recover_upload_journal(Duration::from_secs(3600)).await.unwrap();
+    assert_eq!(report.completed, 1);
+    assert_eq!(report.rolled_back, 1);
+    assert!(blobs.exists(&kept.stored_name).await);
+    assert!(!blobs.exists(&crashed.stored_name).await);
+    assert!(blobs.exists(&in_flight.stored_name).await);
+    assert_eq!(storage.list_user_files(alice).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_storage_migration() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let blobs = StorageService::in_memory();
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await).with_tenant_isolation(true);
+    let first = storage.store_with_metadata(b"first", "first.txt", alice, None).await.unwrap();
+    let second = storage.store_with_metadata(b"second", "second.txt", alice, None).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let destination = StorageService::new(dir.path()).await.unwrap();
+    let tenant = format!("user-{}", alice);
+
+    // A blob that no longer matches its checksum holds the whole switch back
+    blobs.tenant(&tenant).unwrap().write_at(&second.stored_name, 0, b"SECOND").await.unwrap();
+    let report = StorageMigrator::new(blobs.clone(), destination.clone(), db.connect().await).run().await.unwrap();
+    assert_eq!(report.progress.copied, 1);
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.switched_files, 0);
+    assert!(!destination.tenant(&tenant).unwrap().exists(&second.stored_name).await);
+    assert!(storage.list_user_files(alice).await.unwrap().iter().all(|file| file.storage_path == blobs.tenant(&tenant).unwrap().location()));
+
+    // Running again skips what was copied and switches every file over
+    blobs.tenant(&tenant).unwrap().write_at(&second.stored_name, 0, b"second").await.unwrap();
+    let reports = Arc::new(std::sync::Mutex::new(Vec::<MigrationProgress>::new()));
+    let seen = reports.clone();
+    let report = StorageMigrator::new(blobs.clone(), destination.clone(), db.connect().await)
+        .with_progress(move |progress| seen.lock().unwrap().push(*progress))
+        .run()
+        .await
+        .unwrap();
+    assert_eq!((report.progress.copied, report.progress.skipped), (1, 1));
+    assert_eq!(report.progress.bytes, 6);
+    assert_eq!(report.switched_files, 2);
+    assert_eq!(reports.lock().unwrap().len(), 2);
+
+    let scoped = destination.tenant(&tenant).unwrap();
+    assert_eq!(scoped.retrieve(&first.stored_name).await.unwrap(), b"first");
+    assert_eq!(scoped.retrieve(&second.stored_name).await.unwrap(), b"second");
+    assert!(storage.list_user_files(alice).await.unwrap().iter().all(|file| file.storage_path == scoped.location()));
+}
+
+#[tokio::test]
+async fn test_file_export() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+
+    let report = storage.store_with_metadata(b"report", "q3, final.pdf", alice, None).await.unwrap();
+    let trashed = storage.store_with_metadata(b"old", "old.txt", alice, None).await.unwrap();
+    storage.store_with_metadata(b"bob's", "bob.txt", bob, None).await.unwrap();
+    storage.favorite_file(&report.id, alice).await.unwrap();
+    let share = storage.create_share(&report.id, alice, None, None).await.unwrap();
+    storage.delete_with_metadata(&trashed.id, alice).await.unwrap();
+
+    let mut csv = Vec::new();
+    assert_eq!(storage.export_files(alice, ExportFormat::Csv, &mut csv).await.unwrap(), 2);
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("id,original_name,"));
+    let report_line = lines.iter().find(|line| line.starts_with(&report.id)).unwrap();
+    assert!(report_line.contains("\"q3, final.pdf\""));
+    assert!(report_line.contains(&format!("/share/{}", share.token)));
+    assert!(!csv.contains("bob.txt"));
+
+    let mut json = Vec::new();
+    storage.export_files(alice, ExportFormat::Json, &mut json).await.unwrap();
+    let files: Vec<serde_json::Value> = serde_json::from_slice(&json).unwrap();
+    assert_eq!(files.len(), 2);
+    let exported = files.iter().find(|file| file["id"] == report.id.as_str()).unwrap();
+    assert_eq!(exported["checksum"], sha256_hex(b"report"));
+    assert_eq!(exported["favorite"], true);
+    assert_eq!(exported["share_links"].as_array().unwrap().len(), 1);
+    assert!(files.iter().any(|file| file["id"] == trashed.id.as_str() && !file["deleted_at"].is_null()));
+
+    let mut empty = Vec::new();
+    storage.export_files(bob + 1, ExportFormat::Json, &mut empty).await.unwrap();
+    assert_eq!(empty, b"[]");
+}
+
+#[tokio::test]
+async fn test_integrity_audit() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let blobs = StorageService::in_memory();
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await);
+
+    let intact = storage.store_with_metadata(b"intact", "intact.txt", alice, None).await.unwrap();
+    let corrupted = storage.store_with_metadata(b"corrupted", "corrupted.txt", alice, None).await.unwrap();
+    let missing = storage.store_with_metadata(b"missing", "missing.txt", alice, None).await.unwrap();
+    blobs.write_at(&corrupted.stored_name, 0, b"C").await.unwrap();
+    blobs.delete(&missing.stored_name).await.unwrap();
+
+    // A sample checks the files that have gone unchecked longest
+    let audit = storage.audit_integrity(Some(2)).await.unwrap();
+    assert_eq!(audit.checked, 2);
+    let report = storage.integrity_report().await.unwrap();
+    assert_eq!(report.unchecked, 1);
+
+    let audit = storage.audit_integrity(None).await.unwrap();
+    assert_eq!(audit.checked, 3);
+    assert_eq!(audit.corrupted, [corrupted.id.clone()]);
+    assert_eq!(audit.missing, [missing.id.clone()]);
+
+    let report = storage.integrity_report().await.unwrap();
+    assert_eq!((report.ok, report.corrupted, report.missing, report.unchecked), (1, 1, 1, 0));
+    assert_eq!(report.flagged.len(), 2);
+    let file = storage.get_file_by_id(&intact.id).await.unwrap().unwrap();
+    assert_eq!(file.integrity_status, Some(IntegrityStatus::Ok));
+    assert!(file.integrity_checked_at.is_some());
+
+    // Rewriting a file clears its status until the next audit
+    storage.write_range_with_metadata(&corrupted.id, alice, 0, b"c", None).await.unwrap();
+    let file = storage.get_file_by_id(&corrupted.id).await.unwrap().unwrap();
+    assert_eq!(file.integrity_status, None);
+    assert_eq!(storage.integrity_report().await.unwrap().unchecked, 1);
+}
+
+#[tokio::test]
+async fn test_databases_are_isolated() {
+    let first = TestDatabase::new().await;
+    let second = TestDatabase::new().await;
+
+    first.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap();
+    assert!(second.auth_service().await.login("alice@example.com", "correct-horse-battery").await.is_err());
+}
+
+#[tokio::test]
+async fn test_gc() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let blobs = StorageService::in_memory();
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await);
+
+    let kept = storage.store_with_metadata(b"kept", "kept.txt", alice, None).await.unwrap();
+    let lost = storage.store_with_metadata(b"lost", "lost.txt", alice, None).await.unwrap();
+    blobs.delete(&lost.stored_name).await.unwrap();
+    let orphan = blobs.store(b"orphan", "orphan.txt", None).await.unwrap();
+
+    // Nothing is older than the grace period yet
+    let report = storage.gc(Duration::from_secs(3600), false).await.unwrap();
+    assert_eq!(report.recent_blobs, 1);
+    assert!(report.orphaned_blobs.is_empty());
+    assert!(report.missing_blobs.is_empty());
+
+    let report = storage.gc(Duration::ZERO, true).await.unwrap();
+    assert_eq!(report.orphaned_blobs, vec![orphan.stored_name.clone()]);
+    assert_eq!(report.removed_blobs, 0);
+    assert_eq!(report.missing_blobs, vec![lost.id.clone().unwrap()]);
+    assert!(blobs.exists(&orphan.stored_name).await);
+
+    let report = storage.gc(Duration::ZERO, false).await.unwrap();
+    assert_eq!(report.removed_blobs, 1);
+    assert!(!blobs.exists(&orphan.stored_name).await);
+    assert!(blobs.exists(&kept.stored_name).await);
+}
+
+#[tokio::test]
+async fn test_resumable_upload() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = db.auth_service().await.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+    let blobs = StorageService::in_memory();
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await);
+
+    let data = b"hello resumable world";
+    let checksum = sha256_hex(data);
+    let session = storage.create_upload_session(alice, "hello.txt", Some("text/plain".to_string()), data.len() as u64, Some(checksum)).await.unwrap();
+    let upload_id = session.upload_id.clone();
+    assert_eq!(session.received_bytes, 0);
+
+    let session = storage.write_upload_chunk(&upload_id, alice, 0, &data[..5]).await.unwrap();
+    assert_eq!(session.received_bytes, 5);
+    // Skipping ahead is refused; resending a chunk is not
+    assert!(matches!(storage.write_upload_chunk(&upload_id, alice, 10, &data[10..]).await, Err(StorageError::PreconditionFailed(_))));
+    storage.write_upload_chunk(&upload_id, alice, 0, &data[..5]).await.unwrap();
+    assert!(matches!(storage.get_upload_session(&upload_id, bob).await, Err(StorageError::FileNotFound(_))));
+
+    // Pending uploads survive garbage collection of interrupted uploads
+    storage.collect_pending_uploads(Duration::ZERO).await.unwrap();
+
+    // A restarted server (or another instance) picks up where the upload left off
+    drop(storage);
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await);
+    let session = storage.get_upload_session(&upload_id, alice).await.unwrap();
+    assert_eq!(session.received_bytes, 5);
+    assert!(matches!(storage.complete_upload(&upload_id, alice).await, Err(StorageError::PreconditionFailed(_))));
+
+    let session = storage.write_upload_chunk(&upload_id, alice, 5, &data[5..]).await.unwrap();
+    assert!(session.is_complete());
+    let file = storage.complete_upload(&upload_id, alice).await.unwrap();
+    assert_eq!(file.id.as_deref(), Some(upload_id.as_str()));
+    assert_eq!(storage.retrieve_with_permission(&upload_id, alice).await.unwrap(), data);
+    assert!(matches!(storage.get_upload_session(&upload_id, alice).await, Err(StorageError::FileNotFound(_))));
+
+    // The declared checksum is enforced
+    let session = storage.create_upload_session(alice, "bad.txt", None, 3, Some("0".repeat(64))).await.unwrap();
+    storage.write_upload_chunk(&session.upload_id, alice, 0, b"abc").await.unwrap();
+    assert!(matches!(storage.complete_upload(&session.upload_id, alice).await, Err(StorageError::InvalidInput(_))));
+
+    // Cancelled uploads leave nothing behind
+    let session = storage.create_upload_session(alice, "gone.txt", None, 3, None).await.unwrap();
+    storage.write_upload_chunk(&session.upload_id, alice, 0, b"ab").await.unwrap();
+    storage.cancel_upload(&session.upload_id, alice).await.unwrap();
+    assert!(blobs.list_pending().await.unwrap().is_empty());
+    assert_eq!(storage.list_user_files(alice).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_verify_consistency() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let blobs = StorageService::in_memory();
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await);
+
+    let kept = storage.store_with_metadata(b"kept", "kept.txt", alice, None).await.unwrap();
+    let lost = storage.store_with_metadata(b"lost", "lost.txt", alice, None).await.unwrap();
+    let grown = storage.store_with_metadata(b"grown", "grown.txt", alice, None).await.unwrap();
+    blobs.delete(&lost.stored_name).await.unwrap();
+    blobs.append(&grown.stored_name, b"!!").await.unwrap();
+    let orphan = blobs.store(b"orphan", "orphan.txt", None).await.unwrap();
+
+    let report = storage.verify_consistency(false).await.unwrap();
+    assert_eq!(report.missing_blobs, vec![lost.id.clone().unwrap()]);
+    assert_eq!(report.size_mismatches, vec![SizeMismatch {
+        file_id: grown.id.clone().unwrap(),
+        recorded_size: 5,
+        actual_size: 7,
+    }]);
+    assert_eq!(report.unknown_blobs, vec![orphan.stored_name.clone()]);
+    assert_eq!(report.repaired, 0);
+
+    // Everything is newer than the grace period, so repair only reports
+    let report = storage.verify_consistency(true).await.unwrap();
+    assert_eq!(report.repaired, 0);
+    assert!(blobs.exists(&orphan.stored_name).await);
+    assert!(storage.get_file_by_id(lost.id.as_deref().unwrap()).await.unwrap().is_some());
+    assert!(blobs.exists(&kept.stored_name).await);
+}
+
+#[tokio::test]
+async fn test_update_metadata() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+
+    let file = storage.store_with_metadata(b"report", "draft.txt", alice, None).await.unwrap();
+    let file_id = file.id.clone().unwrap();
+
+    let update = MetadataUpdate {
+        original_name: Some("report.txt".to_string()),
+        folder: Some("/reports//2025/".to_string()),
+        ..Default::default()
+    };
+    let moved = storage.update_metadata(&file_id, alice, update, Some(&file.etag())).await.unwrap();
+    assert_eq!(moved.original_name, "report.txt");
+    assert_eq!(moved.folder.as_deref(), Some("reports/2025"));
+    assert_eq!(storage.get_file_by_id(&file_id).await.unwrap().unwrap().folder.as_deref(), Some("reports/2025"));
+
+    // The old ETag no longer matches
+    let update = MetadataUpdate { folder: Some(String::new()), ..Default::default() };
+    assert!(matches!(
+        storage.update_metadata(&file_id, alice, update.clone(), Some(&file.etag())).await,
+        Err(StorageError::PreconditionFailed(_))
+    ));
+    assert_eq!(storage.update_metadata(&file_id, alice, update, None).await.unwrap().folder, None);
+
+    let update = MetadataUpdate { folder: Some("reports/../secrets".to_string()), ..Default::default() };
+    assert!(matches!(
+        storage.update_metadata(&file_id, alice, update, None).await,
+        Err(StorageError::InvalidInput(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_append_checksums() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+
+    let file_id = storage.store_with_metadata(b"", "app.log", alice, None).await.unwrap().id.unwrap();
+    let mut expected = Vec::new();
+    for i in 0..50 {
+        let line = format!("{} request handled in {}ms\n", i, i * 7);
+        expected.extend_from_slice(line.as_bytes());
+        let file = storage.append_with_metadata(&file_id, alice, line.as_bytes(), None).await.unwrap();
+        assert_eq!(file.size as usize, expected.len());
+        assert_eq!(file.checksum.as_deref(), Some(sha256_hex(&expected).as_str()));
+    }
+
+    // Writing elsewhere in the file leaves the saved progress behind; the next append rehashes
+    storage.write_range_with_metadata(&file_id, alice, 0, b"X", None).await.unwrap();
+    expected[0] = b'X';
+    expected.extend_from_slice(b"done\n");
+    let file = storage.append_with_metadata(&file_id, alice, b"done\n", None).await.unwrap();
+    assert_eq!(file.checksum.as_deref(), Some(sha256_hex(&expected).as_str()));
+    assert_eq!(storage.retrieve_with_permission(&file_id, alice).await.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn test_text_extraction() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+
+    let notes = storage.store_with_metadata(b"Minutes of the budget meeting", "notes.txt", alice, None).await.unwrap().id.unwrap();
+    storage.store_with_metadata(b"\x89PNG\r\n\x1a\nbudget", "chart.png", alice, None).await.unwrap();
+    storage.store_with_metadata(&vec![b'a'; 2048], "large.txt", alice, None).await.unwrap();
+
+    let extraction = storage.extract_texts(100, 1024).await.unwrap();
+    assert_eq!((extraction.extracted, extraction.unsupported, extraction.failed), (1, 2, 0));
+    // Files are only extracted again once their content changes
+    assert_eq!(storage.extract_texts(100, 1024).await.unwrap(), TextExtraction::default());
+
+    let results = storage.search_user_files(alice, "BUDGET", 10, 0).await.unwrap();
+    assert_eq!(results.total, 1);
+    assert_eq!(results.files[0].id.as_deref(), Some(notes.as_str()));
+
+    storage.append_with_metadata(&notes, alice, b"; action items: hire", None).await.unwrap();
+    assert!(storage.search_user_files(alice, "action items", 10, 0).await.unwrap().files.is_empty());
+    assert_eq!(storage.extract_texts(100, 1024).await.unwrap().extracted, 1);
+    assert_eq!(storage.search_user_files(alice, "action items", 10, 0).await.unwrap().total, 1);
+}
+
+#[tokio::test]
+async fn test_unreadable_files_dont_block_text_extraction() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let blobs = StorageService::in_memory();
+    let storage = TransactionalStorageService::new(blobs.clone(), db.connect().await);
+
+    let lost = storage.store_with_metadata(b"lost minutes", "lost.txt", alice, None).await.unwrap();
+    storage.store_with_metadata(b"budget minutes", "notes.txt", alice, None).await.unwrap();
+    blobs.delete(&lost.stored_name).await.unwrap();
+
+    // The unreadable file is recorded as failed, and the next pass moves on to the file after it
+    let extraction = storage.extract_texts(1, 1024).await.unwrap();
+    assert_eq!((extraction.failed, extraction.errors), (1, 1));
+    assert_eq!(storage.extract_texts(1, 1024).await.unwrap().extracted, 1);
+    assert_eq!(storage.search_user_files(alice, "budget", 10, 0).await.unwrap().total, 1);
+}
+
+#[tokio::test]
+async fn test_media_info_recorded_at_upload() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    png.extend_from_slice(&640u32.to_be_bytes());
+    png.extend_from_slice(&480u32.to_be_bytes());
+    png.extend_from_slice(&[8, 6, 0, 0, 0]);
+    let image = storage.store_with_metadata(&png, "photo.png", alice, Some("image/png".to_string())).await.unwrap();
+    assert_eq!((image.width, image.height, image.duration_ms), (Some(640), Some(480), None));
+
+    let stored = storage.get_file_by_id(image.id.as_deref().unwrap()).await.unwrap().unwrap();
+    assert_eq!((stored.width, stored.height), (Some(640), Some(480)));
+
+    let notes = storage.store_with_metadata(b"not a picture", "notes.txt", alice, None).await.unwrap();
+    assert_eq!((notes.width, notes.height, notes.duration_ms), (None, None, None));
+}
+
+#[tokio::test]
+async fn test_presigned_upload() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await.with_upload_signing_key(Some("test-signing-key".to_string()));
+
+    let (upload, path) = storage.presign_upload(alice, "notes.txt", None, 16, chrono::Duration::seconds(60)).await.unwrap();
+    let query = path.split_once('?').unwrap().1;
+    let param = |name: &str| query.split('&').find_map(|pair| pair.strip_prefix(&format!("{}=", name))).unwrap().to_string();
+    let (expires, signature): (i64, _) = (param("expires").parse().unwrap(), param("signature"));
+
+    // The size limit is part of the signature
+    let forged = storage.complete_presigned_upload(&upload.id, expires, 1024, &signature, &b"hello"[..]).await;
+    assert!(matches!(forged, Err(StorageError::PermissionDenied(_))));
+
+    let oversized = storage.complete_presigned_upload(&upload.id, expires, 16, &signature, &[b'a'; 17][..]).await;
+    assert!(matches!(oversized, Err(StorageError::PolicyViolation(ref violation)) if violation.rule == PolicyRule::MaxSize));
+
+    // A failed upload leaves the URL usable
+    let file = storage.complete_presigned_upload(&upload.id, expires, 16, &signature, &b"hello"[..]).await.unwrap();
+    assert_eq!((file.user_id, file.original_name.as_str(), file.size), (alice, "notes.txt", 5));
+
+    let replayed = storage.complete_presigned_upload(&upload.id, expires, 16, &signature, &b"again"[..]).await;
+    assert!(matches!(replayed, Err(StorageError::FileNotFound(_))));
+}
+
+#[tokio::test]
+async fn test_list_user_files_page() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+
+    let files = [
+        ("b.png", &b"\x89PNG\r\n\x1a\nsmall"[..], Some("photos")),
+        ("A.txt", &b"a longer text file"[..], None),
+        ("c.txt", &b"text"[..], Some("photos")),
+    ];
+    for (name, data, folder) in files {
+        let id = storage.store_with_metadata(data, name, alice, None).await.unwrap().id.unwrap();
+        if let Some(folder) = folder {
+            let update = MetadataUpdate { folder: Some(folder.to_string()), ..Default::default() };
+            storage.update_metadata(&id, alice, update, None).await.unwrap();
+        }
+    }
+    let names = |results: FileSearchResults| results.files.into_iter().map(|file| file.original_name).collect::<Vec<_>>();
+
+    let by_name = FileListOptions { sort: Some(FileSort::Name), ..FileListOptions::new(2, 0) };
+    let first = storage.list_user_files_page(alice, &by_name).await.unwrap();
+    assert_eq!(first.total, 3);
+    assert_eq!(names(first), ["A.txt", "b.png"]);
+    let second = storage.list_user_files_page(alice, &FileListOptions { offset: 2, ..by_name.clone() }).await.unwrap();
+    assert_eq!(names(second), ["c.txt"]);
+
+    let largest = FileListOptions { sort: Some(FileSort::Size), ..FileListOptions::new(10, 0) };
+    assert_eq!(names(storage.list_user_files_page(alice, &largest).await.unwrap()), ["A.txt", "b.png", "c.txt"]);
+
+    let images = FileListOptions { mime_type: Some("image/*".to_string()), ..FileListOptions::new(10, 0) };
+    assert_eq!(names(storage.list_user_files_page(alice, &images).await.unwrap()), ["b.png"]);
+
+    let in_photos = FileListOptions { folder: Some("photos".to_string()), descending: Some(true), ..by_name.clone() };
+    let results = storage.list_user_files_page(alice, &in_photos).await.unwrap();
+    assert_eq!(results.total, 2);
+    assert_eq!(names(results), ["c.txt", "b.png"]);
+
+    let at_root = FileListOptions { folder: Some(String::new()), ..FileListOptions::new(10, 0) };
+    assert_eq!(names(storage.list_user_files_page(alice, &at_root).await.unwrap()), ["A.txt"]);
+}
+
+#[tokio::test]
+async fn test_copy_with_metadata() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+
+    let original = storage.store_with_metadata(b"quarterly", "report.txt", alice, None).await.unwrap();
+    let original_id = original.id.clone().unwrap();
+
+    let copy = storage.copy_with_metadata(&original_id, alice, Some("archive")).await.unwrap();
+    let copy_id = copy.id.clone().unwrap();
+    assert_ne!(copy_id, original_id);
+    assert_eq!(copy.original_name, "report.txt");
+    assert_eq!(copy.checksum, original.checksum);
+    assert_eq!(copy.folder.as_deref(), Some("archive"));
+
+    // The copy has its own blob
+    storage.delete_with_metadata(&original_id, alice).await.unwrap();
+    storage.purge_file(&original_id, alice).await.unwrap();
+    assert_eq!(storage.retrieve_with_permission(&copy_id, alice).await.unwrap(), b"quarterly");
+
+    assert!(matches!(
+        storage.copy_with_metadata(&copy_id, bob, None).await,
+        Err(StorageError::PermissionDenied(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_storage_events() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+    let mut events = storage.subscribe();
+
+    let file = storage.store_with_metadata(b"hello", "hello.txt", alice, None).await.unwrap();
+    let file_id = file.id.clone().unwrap();
+    match events.recv().await.unwrap() {
+        StorageEvent::Uploaded { file, copied_from } => {
+            assert_eq!(file.id.as_deref(), Some(file_id.as_str()));
+            assert_eq!(copied_from, None);
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+
+    let copy = storage.copy_with_metadata(&file_id, alice, None).await.unwrap();
+    match events.recv().await.unwrap() {
+        StorageEvent::Uploaded { file, copied_from } => {
+            assert_eq!(file.id, copy.id);
+            assert_eq!(copied_from.as_deref(), Some(file_id.as_str()));
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+
+    storage.delete_with_metadata(&file_id, alice).await.unwrap();
+    storage.purge_file(&file_id, alice).await.unwrap();
+    for permanent in [false, true] {
+        match events.recv().await.unwrap() {
+            StorageEvent::Deleted { file, permanent: was_permanent } => {
+                assert_eq!(file.id.as_deref(), Some(file_id.as_str()));
+                assert_eq!(was_permanent, permanent);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    // Another service can share the channel
+    let ingest = db.storage_service().await.with_event_sender(storage.event_sender());
+    let ingested = ingest.store_with_metadata(b"dropped", "dropped.txt", alice, None).await.unwrap();
+    assert_eq!(events.recv().await.unwrap().file().id, ingested.id);
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_notification_preferences() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+
+    assert_eq!(auth.notification_preferences(alice).await.unwrap(), NotificationPreferences::default());
+
+    let update = NotificationPreferencesUpdate { announcements: Some(false), ..Default::default() };
+    auth.update_notification_preferences(alice, &update).await.unwrap();
+    let update = NotificationPreferencesUpdate { login_alert: Some(false), ..Default::default() };
+    let preferences = auth.update_notification_preferences(alice, &update).await.unwrap();
+    assert!(!preferences.announcements);
+    assert!(!preferences.login_alert);
+
+    assert!(!auth.should_notify(alice, NotificationKind::Announcements).await.unwrap());
+    assert!(auth.should_notify(alice, NotificationKind::QuotaWarning).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_profiles() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = auth.signup("bob@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    assert_eq!(auth.profile(alice).await.unwrap(), Profile::default());
+
+    let update = ProfileUpdate {
+        display_name: Some("Alice".to_string()),
+        avatar_file_id: Some("550e8400-e29b-41d4-a716-446655440000".to_string()),
+        locale: Some("en-GB".to_string()),
+        ..Default::default()
+    };
+    auth.update_profile(alice, &update).await.unwrap();
+    let update = ProfileUpdate { bio: Some("Counts things".to_string()), locale: Some(String::new()), ..Default::default() };
+    let profile = auth.update_profile(alice, &update).await.unwrap();
+    assert_eq!(auth.profile(alice).await.unwrap(), profile);
+    assert_eq!(profile.display_name.as_deref(), Some("Alice"));
+    assert_eq!(profile.bio.as_deref(), Some("Counts things"));
+    assert_eq!(profile.locale, None);
+    assert!(profile.updated_at.is_some());
+
+    let update = ProfileUpdate { locale: Some("not a locale".to_string()), ..Default::default() };
+    assert!(matches!(auth.update_profile(alice, &update).await, Err(AuthError::InvalidInput(_))));
+    assert_eq!(auth.profile(alice).await.unwrap(), profile);
+    assert_eq!(auth.profile(bob).await.unwrap(), Profile::default());
+}
+
+/// Locates 203.0.113.0/24 in Berlin and 198.51.100.0/24 in Paris
+struct FakeGeo;
+
+impl GeoLookup for FakeGeo {
+    fn lookup(&self, ip: std::net::IpAddr) -> Option<GeoLocation> {
+        let (country, city) = match ip.to_string() {
+            ip if ip.starts_with("203.0.113.") => ("DE", "Berlin"),
+            ip if ip.starts_with("198.51.100.") => ("FR", "Paris"),
+            _ => return None,
+        };
+        Some(GeoLocation { country: Some(country.to_string()), city: Some(city.to_string()) })
+    }
+}
+
+#[tokio::test]
+async fn test_login_locations() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await.with_geo_lookup(Arc::new(FakeGeo));
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+
+    let login_from = |ip: &str| auth.login_from("alice@example.com", "correct-horse-battery", Some(ip.parse().unwrap()));
+    let (_, _, first) = login_from("203.0.113.9").await.unwrap();
+    assert_eq!(first.country.as_deref(), Some("DE"));
+    assert_eq!(first.city.as_deref(), Some("Berlin"));
+    // The first login has nothing to compare against
+    assert!(!first.new_country);
+
+    let (_, _, again) = login_from("203.0.113.20").await.unwrap();
+    assert!(!again.new_country);
+    let (_, _, abroad) = login_from("198.51.100.7").await.unwrap();
+    assert!(abroad.new_country);
+    let (_, _, unknown) = login_from("10.0.0.1").await.unwrap();
+    assert_eq!(unknown.country, None);
+    assert!(!unknown.new_country);
+
+    let history = auth.login_history(alice, 10).await.unwrap();
+    assert_eq!(history.len(), 4);
+    assert_eq!(history[0].ip_address.as_deref(), Some("10.0.0.1"));
+    assert_eq!(history[1].country.as_deref(), Some("FR"));
+    assert!(history[1].new_country);
+    assert_eq!(auth.login_history(alice, 2).await.unwrap().len(), 2);
+
+    let sessions = db.connect().await.backend()
+        .fetch_all_params("SELECT country, city FROM sessions ORDER BY id", &[])
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 4);
+    assert_eq!(sessions[0]["country"], "DE");
+    assert_eq!(sessions[2]["city"], "Paris");
+}
+
+#[tokio::test]
+async fn test_soft_deleted_user_restore() {
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let (token, _) = auth.login("alice@example.com", "correct-horse-battery").await.unwrap();
+
+    let recovery_token = auth.soft_delete_user(alice).await.unwrap();
+    assert!(matches!(auth.validate(&token).await, Err(AuthError::AccountDeleted)));
+    assert!(matches!(
+        auth.login("alice@example.com", "correct-horse-battery").await,
+        Err(AuthError::AccountDeleted)
+    ));
+    assert!(auth.validate(&recovery_token).await.is_err());
+    // Still inside the grace period
+    assert!(auth.users_to_purge().await.unwrap().is_empty());
+
+    let restored = auth.recover_account(&recovery_token).await.unwrap();
+    assert!(!restored.is_deleted());
+    assert!(auth.login("alice@example.com", "correct-horse-battery").await.is_ok());
+    assert!(auth.recover_account(&recovery_token).await.is_err());
+
+    let auth = auth.with_deletion_grace_seconds(0);
+    auth.soft_delete_user(alice).await.unwrap();
+    assert_eq!(auth.users_to_purge().await.unwrap(), vec![alice]);
+}
+
+#[tokio::test]
+async fn test_deleted_owner_links() {
+    use tokio::io::AsyncReadExt;
+
+    let db = TestDatabase::new().await;
+    let auth = db.auth_service().await;
+    let storage = db.storage_service().await;
+    let alice = auth.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let file = storage.store_with_metadata(b"report", "report.txt", alice, None).await.unwrap();
+    let file_id = file.id.as_deref().unwrap();
+    let share = storage.create_share(file_id, alice, None, None).await.unwrap();
+    let download = storage.issue_download_token(file_id, alice, chrono::Duration::minutes(5)).await.unwrap();
+
+    // Links stop working while the account waits to be purged
+    auth.soft_delete_user(alice).await.unwrap();
+    assert!(matches!(storage.resolve_share(&share.token).await, Err(StorageError::FileNotFound(_))));
+    assert!(matches!(storage.redeem_download_token(&download.token).await, Err(StorageError::FileNotFound(_))));
+
+    // and work again once it is restored; the download token wasn't used up
+    auth.restore_user(alice).await.unwrap();
+    assert!(storage.resolve_share(&share.token).await.is_ok());
+    let (_, mut reader) = storage.redeem_download_token(&download.token).await.unwrap();
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await.unwrap();
+    assert_eq!(data, b"report");
+}
+
+#[tokio::test]
+async fn test_storage_stats_by_mime_type() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+
+    storage.store_with_metadata(b"a", "a.txt", alice, Some("text/plain".to_string())).await.unwrap();
+    storage.store_with_metadata(b"bb", "b.txt", alice, Some("text/plain".to_string())).await.unwrap();
+    storage.store_with_metadata(b"{\"big\": true}", "c.json", alice, Some("application/json".to_string())).await.unwrap();
+    let trashed = storage.store_with_metadata(b"gone", "d.txt", alice, Some("text/plain".to_string())).await.unwrap();
+    storage.delete_with_metadata(trashed.id.as_deref().unwrap(), alice).await.unwrap();
+
+    let stats = storage.get_user_storage_stats(alice).await.unwrap();
+    assert_eq!(stats.file_count, 3);
+    assert_eq!(stats.by_mime_type, vec![
+        MimeTypeUsage { mime_type: Some("application/json".to_string()), file_count: 1, total_size: 13 },
+        MimeTypeUsage { mime_type: Some("text/plain".to_string()), file_count: 2, total_size: 3 },
+    ]);
+}
+
+#[tokio::test]
+async fn test_user_buckets() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let bob = db.auth_service().await.signup("bob@example.com", "battery-staple-horse").await.unwrap().id.unwrap();
+
+    let managed = StorageService::in_memory();
+    let alice_bucket = StorageService::in_memory();
+    let connector_bucket = alice_bucket.clone();
+    let buckets = Arc::new(UserBuckets::with_connector(
+        db.connect().await,
+        "bucket-secret",
+        Arc::new(move |credentials: &BucketCredentials| match credentials.bucket.as_str() {
+            "alice-files" => Ok(connector_bucket.clone()),
+            _ => Err(StorageError::Transient("bucket unreachable".to_string())),
+        }),
+    ));
+    let storage = TransactionalStorageService::new(managed.clone(), db.connect().await).with_buckets(buckets.clone());
+
+    let credentials = |bucket: &str| BucketCredentials {
+        bucket: bucket.to_string(),
+        region: "us-east-1".to_string(),
+        endpoint: None,
+        access_key: "AKIAALICE".to_string(),
+        secret_key: "alice-secret-key".to_string(),
+        path_style: false,
+        prefix: None,
+    };
+    // The owner learns what kind of failure it was, not what the bucket said
+    match buckets.configure(alice, credentials("missing")).await {
+        Err(StorageError::InvalidInput(message)) => assert!(!message.contains("unreachable"), "{}", message),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    // The server won't connect to its own network on a user's behalf
+    for endpoint in ["http://127.0.0.1:9000", "http://169.254.169.254/latest", "http://[::1]:9000", "http://10.0.0.5"] {
+        let mut internal = credentials("alice-files");
+        internal.endpoint = Some(endpoint.to_string());
+        assert!(
+            matches!(buckets.configure(alice, internal).await, Err(StorageError::PermissionDenied(_))),
+            "{} should be refused",
+            endpoint
+        );
+    }
+    // Host names could be pointed at the server's network after they were checked
+    for endpoint in ["file:///etc/passwd", "http://localhost:9000", "https://s3.example.com"] {
+        let mut unsupported = credentials("alice-files");
+        unsupported.endpoint = Some(endpoint.to_string());
+        assert!(
+            matches!(buckets.configure(alice, unsupported).await, Err(StorageError::InvalidInput(_))),
+            "{} should be refused",
+            endpoint
+        );
+    }
+
+    let bucket = buckets.configure(alice, credentials("alice-files")).await.unwrap();
+    assert_eq!(bucket.health, BucketHealth::Healthy);
+
+    // Credentials are stored encrypted
+    let row = db.connect().await.backend()
+        .fetch_one_params("SELECT credentials FROM user_buckets", &[])
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!row["credentials"].as_str().unwrap().contains("alice-secret-key"));
+
+    // Each owner's blobs go to their own storage
+    let alice_file = storage.store_with_metadata(b"alice", "a.txt", alice, None).await.unwrap();
+    let bob_file = storage.store_with_metadata(b"bob", "b.txt", bob, None).await.unwrap();
+    assert!(alice_bucket.exists(&alice_file.stored_name).await);
+    assert!(!managed.exists(&alice_file.stored_name).await);
+    assert!(managed.exists(&bob_file.stored_name).await);
+    let alice_id = alice_file.id.as_deref().unwrap();
+    assert_eq!(storage.retrieve_with_permission(alice_id, alice).await.unwrap(), b"alice");
+
+    // Files in the user's bucket are not the server's to garbage collect
+    assert!(storage.gc(Duration::ZERO, true).await.unwrap().missing_blobs.is_empty());
+
+    // The bucket can't be swapped out from under existing files
+    assert!(matches!(buckets.remove(alice).await, Err(StorageError::PreconditionFailed(_))));
+    storage.delete_with_metadata(alice_id, alice).await.unwrap();
+    storage.purge_file(alice_id, alice).await.unwrap();
+    assert!(!alice_bucket.exists(&alice_file.stored_name).await);
+    assert!(buckets.remove(alice).await.unwrap());
+    assert!(buckets.get(alice).await.unwrap().is_none());
+    assert!(!buckets.remove(alice).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_legal_holds() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+    let file = storage.store_with_metadata(b"evidence", "evidence.txt", alice, None).await.unwrap();
+    let file_id = file.id.as_deref().unwrap();
+
+    assert!(matches!(
+        storage.place_legal_hold("files", "missing", "Case 42", 1).await,
+        Err(StorageError::FileNotFound(_))
+    ));
+    assert!(matches!(
+        storage.place_legal_hold("files; --", file_id, "Case 42", 1).await,
+        Err(StorageError::InvalidInput(_))
+    ));
+    let hold = storage.place_legal_hold("files", file_id, "Case 42", 1).await.unwrap();
+    assert!(hold.is_active());
+    assert!(storage.user_has_legal_holds(alice).await.unwrap());
+    assert_eq!(storage.tables_on_legal_hold().await.unwrap(), vec!["files".to_string()]);
+
+    // Neither the owner, an admin, nor an account purge can delete it
+    assert!(matches!(storage.delete_with_metadata(file_id, alice).await, Err(StorageError::LegalHold(_))));
+    assert!(matches!(storage.force_purge_file(file_id).await, Err(StorageError::LegalHold(_))));
+    assert!(matches!(storage.purge_user_files(alice).await, Err(StorageError::LegalHold(_))));
+    assert_eq!(storage.list_user_files(alice).await.unwrap().len(), 1);
+
+    // Nor can its content or metadata be changed
+    assert!(matches!(storage.append_with_metadata(file_id, alice, b" more", None).await, Err(StorageError::LegalHold(_))));
+    assert!(matches!(storage.write_range_with_metadata(file_id, alice, 0, b"E", None).await, Err(StorageError::LegalHold(_))));
+    let rename = MetadataUpdate { original_name: Some("innocent.txt".to_string()), ..Default::default() };
+    assert!(matches!(storage.update_metadata(file_id, alice, rename, None).await, Err(StorageError::LegalHold(_))));
+    assert_eq!(storage.retrieve_with_permission(file_id, alice).await.unwrap(), b"evidence");
+
+    let released = storage.release_legal_hold(hold.id, 1).await.unwrap();
+    assert_eq!(released.released_by, Some(1));
+    assert!(matches!(storage.release_legal_hold(hold.id, 1).await, Err(StorageError::PreconditionFailed(_))));
+    assert!(storage.list_legal_holds(Some("files"), true).await.unwrap().is_empty());
+    assert_eq!(storage.list_legal_holds(Some("files"), false).await.unwrap(), vec![released]);
+
+    storage.delete_with_metadata(file_id, alice).await.unwrap();
+    assert_eq!(storage.purge_user_files(alice).await.unwrap(), 1);
+
+    // A hold on the account itself keeps it too
+    let account_hold = storage.place_legal_hold("users", &alice.to_string(), "Case 43", 1).await.unwrap();
+    assert!(storage.user_has_legal_holds(alice).await.unwrap());
+    storage.release_legal_hold(account_hold.id, 1).await.unwrap();
+    assert!(!storage.user_has_legal_holds(alice).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_purge_expired_trash() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let storage = db.storage_service().await;
+    let old = storage.store_with_metadata(b"old", "old.txt", alice, None).await.unwrap();
+    let held = storage.store_with_metadata(b"held", "held.txt", alice, None).await.unwrap();
+    let kept = storage.store_with_metadata(b"kept", "kept.txt", alice, None).await.unwrap();
+    let old_id = old.id.as_deref().unwrap();
+    let held_id = held.id.as_deref().unwrap();
+    storage.delete_with_metadata(old_id, alice).await.unwrap();
+    storage.delete_with_metadata(held_id, alice).await.unwrap();
+    storage.place_legal_hold("files", held_id, "Case 42", 1).await.unwrap();
+
+    // Nothing has been in the trash for a day yet
+    let purge = storage.purge_expired_trash(chrono::Duration::days(1), 100).await.unwrap();
+    assert_eq!(purge.purged, 0);
+    assert_eq!(storage.list_trashed_files(alice).await.unwrap().len(), 2);
+    let page = storage.list_trashed_files_page(alice, 1, 1).await.unwrap();
+    assert_eq!((page.files.len(), page.total), (1, 2));
+    assert!(storage.list_trashed_files_page(alice, 1, 2).await.unwrap().files.is_empty());
+
+    // The held file stays in the trash, and files outside the trash are never touched
+    let purge = storage.purge_expired_trash(chrono::Duration::zero(), 100).await.unwrap();
+    assert_eq!((purge.purged, purge.errors), (1, 0));
+    let trashed = storage.list_trashed_files(alice).await.unwrap();
+    assert_eq!(trashed.iter().map(|f| f.id.as_deref().unwrap()).collect::<Vec<_>>(), vec![held_id]);
+    assert_eq!(storage.list_user_files(alice).await.unwrap()[0].id, kept.id);
+}
+
+/// Records webhook requests, answering each with `status`
+struct FakeTransport {
+    status: u16,
+    requests: std::sync::Mutex<Vec<(String, String, Vec<u8>)>>,
+}
+
+#[async_trait::async_trait]
+impl WebhookTransport for FakeTransport {
+    async fn post(&self, url: &str, headers: &[(&str, String)], body: &[u8]) -> Result<u16, String> {
+        let signature = headers.iter().find(|(name, _)| *name == storage::webhook::SIGNATURE_HEADER).unwrap();
+        self.requests.lock().unwrap().push((url.to_string(), signature.1.clone(), body.to_vec()));
+        Ok(self.status)
+    }
+}
+
+fn endpoint(url: &str, events: Vec<FileEvent>) -> WebhookEndpoint {
+    WebhookEndpoint { url: url.to_string(), secret: "whsec_test".to_string(), events }
+}
+
+#[tokio::test]
+async fn test_file_webhooks() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+
+    let transport = Arc::new(FakeTransport { status: 204, requests: Default::default() });
+    let webhooks = Arc::new(Webhooks::new(
+        db.connect().await,
+        vec![endpoint("https://all.example", vec![]), endpoint("https://deletes.example", vec![FileEvent::Deleted])],
+        transport.clone(),
+    ));
+    let storage = db.storage_service().await.with_webhooks(webhooks.clone());
+
+    let file = storage.store_with_metadata(b"hello", "hello.txt", alice, None).await.unwrap();
+    let file_id = file.id.as_deref().unwrap();
+    storage.retrieve_with_permission(file_id, alice).await.unwrap();
+    storage.delete_with_metadata(file_id, alice).await.unwrap();
+
+    // Deliveries start in the background; finish any still in flight
+    while webhooks.pending_count().await.unwrap() > 0 {
+        webhooks.deliver_due().await.unwrap();
+        tokio::task::yield_now().await;
+    }
+
+    // Created and downloaded go to one endpoint, deleted to both
+    let deliveries = webhooks.list_deliveries(Some(DeliveryStatus::Delivered), 10).await.unwrap();
+    assert_eq!(deliveries.len(), 4);
+    assert_eq!(deliveries.iter().filter(|d| d.url == "https://deletes.example").count(), 1);
+    assert!(deliveries.iter().all(|d| d.attempts == 1 && d.response_status == Some(204)));
+
+    // Each request is signed over its timestamp and body
+    let requests = transport.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 4);
+    for (_, signature, body) in &requests {
+        let timestamp: i64 = signature.strip_prefix("t=").unwrap().split(',').next().unwrap().parse().unwrap();
+        assert_eq!(signature, &storage::sign_payload("whsec_test", timestamp, body));
+    }
+    assert!(requests.iter().any(|(_, _, body)| String::from_utf8_lossy(body).contains(r#""type":"file.downloaded""#)));
+}
+
+#[tokio::test]
+async fn test_webhook_retries() {
+    let db = TestDatabase::new().await;
+    let alice = db.auth_service().await.signup("alice@example.com", "correct-horse-battery").await.unwrap().id.unwrap();
+    let file = db.storage_service().await.store_with_metadata(b"hello", "hello.txt", alice, None).await.unwrap();
+
+    let transport = Arc::new(FakeTransport { status: 503, requests: Default::default() });
+    let webhooks = Webhooks::new(db.connect().await, vec![endpoint("https://down.example", vec![])], transport.clone())
+        .with_retries(3, Duration::ZERO);
+
+    assert_eq!(webhooks.emit(FileEvent::Created, &file, serde_json::json!({})).await.unwrap(), 1);
+    for _ in 0..5 {
+        assert_eq!(webhooks.deliver_due().await.unwrap(), 0);
+    }
+
+    // Gave up after three attempts
+    let delivery = webhooks.list_deliveries(None, 10).await.unwrap().remove(0);
+    assert_eq!(delivery.status, DeliveryStatus::Failed);
+    assert_eq!(delivery.attempts, 3);
+    assert_eq!(delivery.response_status, Some(503));
+    assert_eq!(transport.requests.lock().unwrap().len(), 3);
+    assert_eq!(webhooks.pending_count().await.unwrap(), 0);
+
+    let retried = webhooks.redeliver(delivery.id).await.unwrap();
+    assert_eq!(retried.status, DeliveryStatus::Pending);
+    assert!(matches!(webhooks.redeliver(delivery.id).await, Err(StorageError::PreconditionFailed(_))));
+    assert!(matches!(webhooks.redeliver(9999).await, Err(StorageError::FileNotFound(_))));
+}
+
+#[tokio::test]
+async fn test_shared_activity_fanout() {
+    let db = TestDatabase::new().await;
+
+    // Two instances handing events to the same fanout, as they would through Redis
+    let fanout: Arc<dyn EventFanout> = Arc::new(LocalFanout::new());
+    let first = ActivityService::new(db.connect().await).with_fanout(fanout.clone());
+    let second = ActivityService::new(db.connect().await).with_fanout(fanout);
+    let mut subscriber = second.subscribe();
+
+    let recorded = first.record(ActivityEvent::new(7, ActivityKind::FileUploaded, "files", "abc")).await.unwrap();
+    let received = subscriber.recv().await.unwrap();
+    assert_eq!(received.id, recorded.id);
+    assert_eq!(received.user_id, 7);
+
+    // Without a shared fanout each instance only hears its own events
+    let isolated = ActivityService::new(db.connect().await);
+    let mut isolated_subscriber = isolated.subscribe();
+    first.record(ActivityEvent::new(7, ActivityKind::FileUploaded, "files", "def")).await.unwrap();
+    assert!(isolated_subscriber.try_recv().is_err());
+    assert!(!isolated.fanout().is_shared());
+}
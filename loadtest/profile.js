@@ -0,0 +1,84 @@
+// Load-test profile for Project Kit
+//
+// Mixes the hot paths a typical client hits: token-authenticated listing, small uploads,
+// downloads, and /db reads and writes. Thresholds mirror the latency budget in the README.
+//
+// Usage:
+//   k6 run loadtest/profile.js
+//   BASE_URL=http://staging:3000 VUS=50 DURATION=5m k6 run loadtest/profile.js
+//
+// The /db scenario expects a `notes` table with `title` and `body` columns.
+
+import http from 'k6/http';
+import { check } from 'k6';
+
+const BASE_URL = __ENV.BASE_URL || 'http://localhost:3000';
+const VUS = parseInt(__ENV.VUS || '20');
+const DURATION = __ENV.DURATION || '1m';
+const PASSWORD = 'loadtest-password';
+
+export const options = {
+  scenarios: {
+    browse: { executor: 'constant-vus', exec: 'browse', vus: VUS, duration: DURATION },
+    files: { executor: 'constant-vus', exec: 'files', vus: Math.max(1, Math.floor(VUS / 4)), duration: DURATION },
+    records: { executor: 'constant-vus', exec: 'records', vus: Math.max(1, Math.floor(VUS / 4)), duration: DURATION },
+  },
+  thresholds: {
+    http_req_failed: ['rate<0.01'],
+    'http_req_duration{endpoint:list_files}': ['p(95)<50'],
+    'http_req_duration{endpoint:upload}': ['p(95)<200'],
+    'http_req_duration{endpoint:download}': ['p(95)<50'],
+    'http_req_duration{endpoint:db_read}': ['p(95)<50'],
+    'http_req_duration{endpoint:db_write}': ['p(95)<100'],
+  },
+};
+
+// One account per run; logins are expensive by design and are not part of the measured mix
+export function setup() {
+  const email = `loadtest-${Date.now()}@example.com`;
+  const res = http.post(`${BASE_URL}/auth/signup`, JSON.stringify({ email, password: PASSWORD }), {
+    headers: { 'Content-Type': 'application/json' },
+  });
+  check(res, { 'signup succeeded': (r) => r.status === 201 });
+  return { token: res.json('token') };
+}
+
+function auth(token, endpoint) {
+  return { headers: { Authorization: `Bearer ${token}` }, tags: { endpoint } };
+}
+
+export function browse(data) {
+  const res = http.get(`${BASE_URL}/files?limit=20`, auth(data.token, 'list_files'));
+  check(res, { 'list files 200': (r) => r.status === 200 });
+}
+
+export function files(data) {
+  const upload = http.post(
+    `${BASE_URL}/files/upload`,
+    { file: http.file('x'.repeat(16 * 1024), 'loadtest.txt', 'text/plain') },
+    auth(data.token, 'upload'),
+  );
+  if (!check(upload, { 'upload 201': (r) => r.status === 201 })) {
+    return;
+  }
+
+  const id = upload.json('file.id');
+  const download = http.get(`${BASE_URL}/files/${id}`, auth(data.token, 'download'));
+  check(download, { 'download 200': (r) => r.status === 200 });
+
+  http.del(`${BASE_URL}/files/${id}`, null, auth(data.token, 'delete'));
+}
+
+export function records(data) {
+  const params = auth(data.token, 'db_write');
+  params.headers['Content-Type'] = 'application/json';
+  const write = http.post(
+    `${BASE_URL}/db/notes`,
+    JSON.stringify({ title: 'load test', body: 'written by k6' }),
+    params,
+  );
+  check(write, { 'db write 201': (r) => r.status === 201 });
+
+  const read = http.get(`${BASE_URL}/db/notes?limit=20`, auth(data.token, 'db_read'));
+  check(read, { 'db read 200': (r) => r.status === 200 });
+}